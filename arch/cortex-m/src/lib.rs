@@ -7,6 +7,9 @@
 #![no_std]
 
 use core::fmt::Write;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use kernel::platform::chip::FaultRegisters;
 
 pub mod dcb;
 pub mod dma_fence;
@@ -102,6 +105,11 @@ pub trait CortexMVariant {
     ///
     /// This is generally used after a `panic!()` to aid debugging.
     unsafe fn print_cortexm_state(writer: &mut dyn Write);
+
+    /// Capture the fault registers for a crash record.
+    fn fault_registers() -> FaultRegisters {
+        fault_registers()
+    }
 }
 
 #[cfg(any(doc, all(target_arch = "arm", target_os = "none")))]
@@ -235,6 +243,46 @@ pub unsafe extern "C" fn initialize_ram_jump_to_main() {
     );
 }
 
+/// Stacked PC and LR, and the CFSR, HFSR, MMFAR and BFAR registers, of the
+/// last kernel hard fault. The PC is zero if there has not been one.
+static KERNEL_FAULT_REGISTERS: [AtomicU32; 6] = [const { AtomicU32::new(0) }; 6];
+
+/// Save the registers of a kernel hard fault so that they can be included in
+/// a crash record by the panic handler.
+///
+/// Cortex-M variants without fault status registers pass zero for them.
+pub fn save_kernel_fault_registers(pc: u32, lr: u32, status: [u32; 4]) {
+    let values = [pc, lr, status[0], status[1], status[2], status[3]];
+    for (register, value) in KERNEL_FAULT_REGISTERS.iter().zip(values) {
+        register.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Fault registers for a crash record.
+///
+/// After a kernel hard fault these are the registers saved by
+/// `save_kernel_fault_registers()`. Otherwise they are the fault status
+/// registers saved on the last process hard fault, without a PC or LR.
+pub fn fault_registers() -> FaultRegisters {
+    let [pc, lr, cfsr, hfsr, mmfar, bfar] = KERNEL_FAULT_REGISTERS
+        .each_ref()
+        .map(|r| r.load(Ordering::Relaxed) as usize);
+    if pc != 0 {
+        FaultRegisters {
+            pc,
+            lr,
+            status: [cfsr, hfsr, mmfar, bfar],
+        }
+    } else {
+        let (_ccr, cfsr, hfsr, mmfar, bfar) = crate::syscall::get_global_scb_registers();
+        FaultRegisters {
+            pc: 0,
+            lr: 0,
+            status: [cfsr as usize, hfsr as usize, mmfar as usize, bfar as usize],
+        }
+    }
+}
+
 pub fn print_cortexm_state(writer: &mut dyn Write) {
     let (_ccr, cfsr, hfsr, mmfar, bfar) = crate::syscall::get_global_scb_registers();
 
@@ -14,6 +14,7 @@ use core::mem::{self, size_of};
 use core::ops::Range;
 use core::ptr;
 use kernel::errorcode::ErrorCode;
+use kernel::platform::chip::FaultRegisters;
 
 use crate::CortexMVariant;
 
@@ -624,6 +625,39 @@ impl<A: CortexMVariant> kernel::syscall::UserspaceKernelBoundary for SysCall<A>
         ));
     }
 
+    unsafe fn fault_registers(
+        &self,
+        accessible_memory_start: *const u8,
+        app_brk: *const u8,
+        state: &CortexMStoredState,
+    ) -> FaultRegisters {
+        // The fault status registers are saved on every process hard fault.
+        let (_ccr, cfsr, hfsr, mmfar, bfar) = get_global_scb_registers();
+        let status = [cfsr as usize, hfsr as usize, mmfar as usize, bfar as usize];
+
+        // The PC and LR are in the exception frame on the process stack, if
+        // the stored stack pointer is valid.
+        if state.psp < accessible_memory_start as usize
+            || state.psp.saturating_add(SVC_FRAME_SIZE) > app_brk as usize
+        {
+            return FaultRegisters {
+                pc: 0,
+                lr: 0,
+                status,
+            };
+        }
+        let stack_pointer = state.psp as *const usize;
+        // SAFETY: We ensured the exception frame we read from is within
+        // process memory, as in `print_context()`.
+        let (lr, pc) = unsafe {
+            (
+                ptr::read(stack_pointer.add(5)),
+                ptr::read(stack_pointer.add(6)),
+            )
+        };
+        FaultRegisters { pc, lr, status }
+    }
+
     fn store_context(
         &self,
         state: &CortexMStoredState,
@@ -645,4 +679,9 @@ impl<A: CortexMVariant> kernel::syscall::UserspaceKernelBoundary for SysCall<A>
             Err(ErrorCode::SIZE)
         }
     }
+
+    fn load_context(&self, state: &mut CortexMStoredState, input: &[u8]) -> Result<(), ErrorCode> {
+        *state = CortexMStoredState::try_from(input)?;
+        Ok(())
+    }
 }
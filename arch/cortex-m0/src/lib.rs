@@ -49,6 +49,12 @@ unsafe extern "C" fn hard_fault_handler_kernel(faulting_stack: *mut u32) -> ! {
         }
     };
 
+    cortexm::save_kernel_fault_registers(
+        hardfault_stacked_registers.pc,
+        hardfault_stacked_registers.lr,
+        [0; 4],
+    );
+
     panic!(
         "Kernel HardFault.\r\n\
          \tr0  0x{:x}\r\n\
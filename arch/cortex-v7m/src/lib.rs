@@ -435,6 +435,8 @@ unsafe extern "C" fn hard_fault_handler_arm_v7m_kernel(
         let thumb_bit = ((stacked_xpsr >> 24) & 0x1) == 1;
         let exception_number = (stacked_xpsr & 0x1ff) as usize;
 
+        cortexm::save_kernel_fault_registers(stacked_pc, stacked_lr, [cfsr, hfsr, mmfar, bfar]);
+
         panic!(
             "{} HardFault.\r\n\
          \tr0  0x{:x}\r\n\
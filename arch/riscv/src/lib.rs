@@ -8,6 +8,7 @@
 
 use core::fmt::Write;
 
+use kernel::platform::chip::FaultRegisters;
 use kernel::utilities::registers::interfaces::{Readable, Writeable};
 
 pub mod clic;
@@ -531,6 +532,27 @@ pub unsafe fn print_mcause(mcval: csr::mcause::Trap, writer: &mut dyn Write) {
     let _ = writer.write_str(s);
 }
 
+/// Fault registers for the crash record of a kernel panic, taken from the
+/// most recent trap.
+///
+/// The PC is `mepc` and the status registers are `mcause`, `mtval` and
+/// `mstatus`. RISC-V has no dedicated link register that is saved on a trap,
+/// so `lr` is zero. These CSRs are overwritten by every trap, so process
+/// faults use the registers saved in the process's stored state instead (see
+/// `Process::fault_registers()`).
+pub fn fault_registers() -> FaultRegisters {
+    FaultRegisters {
+        pc: csr::CSR.mepc.get(),
+        lr: 0,
+        status: [
+            csr::CSR.mcause.get(),
+            csr::CSR.mtval.get(),
+            csr::CSR.mstatus.get(),
+            0,
+        ],
+    }
+}
+
 /// Prints out RISCV machine state, including basic system registers
 /// (mcause, mstatus, mtvec, mepc, mtval, interrupt status).
 pub unsafe fn print_riscv_state(writer: &mut dyn Write) {
@@ -10,6 +10,7 @@ use core::ops::Range;
 
 use crate::csr::mcause;
 use kernel::errorcode::ErrorCode;
+use kernel::platform::chip::FaultRegisters;
 use kernel::syscall::ContextSwitchReason;
 
 /// This holds all of the state that the kernel must keep for the process when
@@ -896,6 +897,21 @@ impl kernel::syscall::UserspaceKernelBoundary for SysCall {
         ));
     }
 
+    unsafe fn fault_registers(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        state: &RiscvStoredState,
+    ) -> FaultRegisters {
+        // `mcause` and `mtval` are saved per process by the trap handler.
+        // `mstatus` is not, so it is left as zero.
+        FaultRegisters {
+            pc: state.pc,
+            lr: state.regs[R_RA],
+            status: [state.mcause, state.mtval, 0, 0],
+        }
+    }
+
     fn store_context(&self, state: &RiscvStoredState, out: &mut [u8]) -> Result<usize, ErrorCode> {
         const U32_SZ: usize = size_of::<usize>();
         if out.len() >= STORED_STATE_SIZE + METADATA_LEN * U32_SZ {
@@ -914,4 +930,9 @@ impl kernel::syscall::UserspaceKernelBoundary for SysCall {
             Err(ErrorCode::SIZE)
         }
     }
+
+    fn load_context(&self, state: &mut RiscvStoredState, input: &[u8]) -> Result<(), ErrorCode> {
+        *state = RiscvStoredState::try_from(input)?;
+        Ok(())
+    }
 }
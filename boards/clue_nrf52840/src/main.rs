@@ -699,7 +699,7 @@ unsafe fn start() -> (
 
     let device_id_bottom_16 = u16::from_le_bytes([device_id[0], device_id[1]]);
 
-    let (ieee802154_radio, _mux_mac) = components::ieee802154::Ieee802154Component::new(
+    let (ieee802154_radio, _mux_mac, _) = components::ieee802154::Ieee802154Component::new(
         board_kernel,
         capsules_extra::ieee802154::DRIVER_NUM,
         &nrf52840_peripherals.ieee802154_radio,
@@ -0,0 +1,151 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for the BLE peripheral stack: link layer, L2CAP, GATT server
+//! and the userspace driver.
+//!
+//! The link layer takes over the radio, so this cannot be combined with the
+//! BLE advertising driver on the same radio.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ble_peripheral = components::ble_peripheral::BlePeripheralComponent::new(
+//!     board_kernel,
+//!     capsules_extra::ble_peripheral::DRIVER_NUM,
+//!     &base_peripherals.ble_radio,
+//!     mux_alarm,
+//!     [0xf0, 0x0f, 0x0f, 0x0f, 0x0f, 0xf0],
+//!     b"Tock",
+//!     mem_cap,
+//! )
+//! .finalize(components::ble_peripheral_component_static!(
+//!     nrf52832::rtc::Rtc,
+//!     nrf52832::ble_radio::Radio
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ble_peripheral::driver::BlePeripheralDriver;
+use capsules_extra::ble_peripheral::gatt::GattServer;
+use capsules_extra::ble_peripheral::l2cap::L2cap;
+use capsules_extra::ble_peripheral::link_layer::{
+    ADDRESS_LEN, LinkLayer, PeripheralLinkLayer, RADIO_BUF_LEN,
+};
+use core::mem::MaybeUninit;
+use kernel::capabilities::MemoryAllocationCapability;
+use kernel::component::Component;
+use kernel::hil::ble_connection::BleConnectionDriver;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! ble_peripheral_component_static {
+    ($A:ty, $R:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let link_layer = kernel::static_buf!(
+            capsules_extra::ble_peripheral::link_layer::PeripheralLinkLayer<
+                'static,
+                $R,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let l2cap = kernel::static_buf!(capsules_extra::ble_peripheral::l2cap::L2cap<'static>);
+        let gatt = kernel::static_buf!(capsules_extra::ble_peripheral::gatt::GattServer<'static>);
+        let driver = kernel::static_buf!(
+            capsules_extra::ble_peripheral::driver::BlePeripheralDriver<'static>
+        );
+        let buffer =
+            kernel::static_buf!([u8; capsules_extra::ble_peripheral::link_layer::RADIO_BUF_LEN]);
+        (alarm, link_layer, l2cap, gatt, driver, buffer)
+    }};
+}
+
+pub struct BlePeripheralComponent<
+    A: Alarm<'static> + 'static,
+    R: BleConnectionDriver<'static> + 'static,
+    CAP: MemoryAllocationCapability + 'static,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    radio: &'static R,
+    mux_alarm: &'static MuxAlarm<'static, A>,
+    address: [u8; ADDRESS_LEN],
+    device_name: &'static [u8],
+    mem_cap: CAP,
+}
+
+impl<
+    A: Alarm<'static> + 'static,
+    R: BleConnectionDriver<'static> + 'static,
+    CAP: MemoryAllocationCapability + 'static,
+> BlePeripheralComponent<A, R, CAP>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        radio: &'static R,
+        mux_alarm: &'static MuxAlarm<'static, A>,
+        address: [u8; ADDRESS_LEN],
+        device_name: &'static [u8],
+        mem_cap: CAP,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            radio,
+            mux_alarm,
+            address,
+            device_name,
+            mem_cap,
+        }
+    }
+}
+
+impl<
+    A: Alarm<'static> + 'static,
+    R: BleConnectionDriver<'static> + 'static,
+    CAP: MemoryAllocationCapability + 'static,
+> Component for BlePeripheralComponent<A, R, CAP>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<PeripheralLinkLayer<'static, R, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<L2cap<'static>>,
+        &'static mut MaybeUninit<GattServer<'static>>,
+        &'static mut MaybeUninit<BlePeripheralDriver<'static>>,
+        &'static mut MaybeUninit<[u8; RADIO_BUF_LEN]>,
+    );
+    type Output = &'static BlePeripheralDriver<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.mux_alarm));
+        alarm.setup();
+        let buffer = s.5.write([0; RADIO_BUF_LEN]);
+
+        let link_layer = s.1.write(PeripheralLinkLayer::new(
+            self.radio,
+            alarm,
+            buffer,
+            self.address,
+        ));
+        let l2cap = s.2.write(L2cap::new(link_layer));
+        let gatt = s.3.write(GattServer::new(l2cap, self.device_name));
+        let driver = s.4.write(BlePeripheralDriver::new(
+            link_layer,
+            gatt,
+            self.board_kernel
+                .create_grant(self.driver_num, &self.mem_cap),
+        ));
+
+        self.radio.set_connection_client(link_layer);
+        alarm.set_alarm_client(link_layer);
+        link_layer.set_client(l2cap);
+        l2cap.set_client(gatt);
+        gatt.set_client(driver);
+
+        driver
+    }
+}
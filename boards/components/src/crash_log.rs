@@ -0,0 +1,116 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for the persistent crash log.
+//!
+//! This provides one component, CrashLogComponent, which stores records of
+//! kernel panics and process faults in a `storage_volume!` region, one record
+//! per flash page, and provides a system call interface to read and clear
+//! them. Only the processes whose `ShortId`s are passed as readers can use
+//! the system call interface.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! storage_volume!(CRASH_LOG, 8);
+//!
+//! let crash_log = components::crash_log::CrashLogComponent::new(
+//!     &CRASH_LOG,
+//!     &base_peripherals.nvmc,
+//!     board_kernel,
+//!     capsules_extra::crash_log::DRIVER_NUM,
+//!     &[ShortId::Fixed(NonZeroU32::new(0x100).unwrap())],
+//!     create_capability!(capabilities::MemoryAllocationCapability),
+//! )
+//! .finalize(components::crash_log_component_static!(nrf52840::nvmc::Nvmc));
+//!
+//! PANIC_RESOURCES.get().map(|resources| {
+//!     resources.crash_recorder.put(crash_log);
+//! });
+//! process_console.set_crash_log(crash_log);
+//! ```
+
+use capsules_extra::crash_log::CrashLogDriver;
+use core::mem::MaybeUninit;
+use kernel::capabilities::MemoryAllocationCapability;
+use kernel::component::Component;
+use kernel::hil;
+use kernel::process::ShortId;
+
+#[macro_export]
+macro_rules! crash_log_component_static {
+    ($F:ty $(,)?) => {{
+        let page_buffer = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let crash_log = kernel::static_buf!(capsules_extra::crash_log::CrashLogDriver<'static, $F>);
+
+        (page_buffer, crash_log)
+    }};
+}
+
+pub type CrashLogComponentType<F> = CrashLogDriver<'static, F>;
+
+pub struct CrashLogComponent<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, CrashLogDriver<'static, F>>,
+    CAP: MemoryAllocationCapability + 'static,
+> {
+    volume: &'static [u8],
+    flash: &'static F,
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    readers: &'static [ShortId],
+    mem_cap: CAP,
+}
+
+impl<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, CrashLogDriver<'static, F>>,
+    CAP: MemoryAllocationCapability + 'static,
+> CrashLogComponent<F, CAP>
+{
+    pub fn new(
+        volume: &'static [u8],
+        flash: &'static F,
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        readers: &'static [ShortId],
+        mem_cap: CAP,
+    ) -> Self {
+        Self {
+            volume,
+            flash,
+            board_kernel,
+            driver_num,
+            readers,
+            mem_cap,
+        }
+    }
+}
+
+impl<
+    F: 'static + hil::flash::Flash + hil::flash::HasClient<'static, CrashLogDriver<'static, F>>,
+    CAP: MemoryAllocationCapability + 'static,
+> Component for CrashLogComponent<F, CAP>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<<F as hil::flash::Flash>::Page>,
+        &'static mut MaybeUninit<CrashLogDriver<'static, F>>,
+    );
+    type Output = &'static CrashLogDriver<'static, F>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let page_buffer = static_buffer
+            .0
+            .write(<F as hil::flash::Flash>::Page::default());
+
+        let crash_log = static_buffer.1.write(CrashLogDriver::new(
+            self.volume,
+            self.flash,
+            page_buffer,
+            self.readers,
+            self.board_kernel
+                .create_grant(self.driver_num, &self.mem_cap),
+        ));
+        hil::flash::HasClient::set_client(self.flash, crash_log);
+        crash_log
+    }
+}
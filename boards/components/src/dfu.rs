@@ -0,0 +1,154 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for USB Device Firmware Upgrade support.
+//!
+//! This lets `dfu-util` install applications through the dynamic binary
+//! storage, and optionally write a kernel image into a staging area.
+//!
+//! No board uses this component yet, and it has not been tested on hardware
+//! or with `dfu-util`: the DFU capsule has only been exercised by its unit
+//! tests, which issue the DFU requests directly on simulated app flash. The
+//! USB controller must not have another client, so a board using this
+//! component cannot also provide its console over CDC-ACM.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static STRINGS: &'static [&str; 5] = &[
+//!     "XYZ Corp.",      // Manufacturer
+//!     "The Zorpinator", // Product
+//!     "Serial No. 5",   // Serial number
+//!     "apps",           // Alternate setting 0
+//!     "kernel",         // Alternate setting 1
+//! ];
+//! let dfu = components::dfu::DfuComponent::new(
+//!     &nrf52840_peripherals.usbd,
+//!     capsules_extra::usb::usbc_client::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x1915,
+//!     0x521f,
+//!     STRINGS,
+//!     dynamic_binary_storage,
+//!     dynamic_binary_storage,
+//!     None, // No kernel staging area
+//! )
+//! .finalize(components::dfu_component_static!(nrf52840::usbd::Usbd));
+//!
+//! dfu.enable();
+//! dfu.attach();
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_extra::usb::dfu::{Dfu, TRANSFER_SIZE};
+use kernel::component::Component;
+use kernel::dynamic_binary_storage;
+use kernel::hil;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! dfu_component_static {
+    ($U:ty $(,)?) => {{
+        let dfu = kernel::static_buf!(capsules_extra::usb::dfu::Dfu<'static, $U>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::usb::dfu::TRANSFER_SIZE]);
+
+        (dfu, buffer)
+    }};
+}
+
+/// A staging area for kernel images: the storage device, the address of the
+/// area and its length.
+pub type StagingArea = (
+    &'static dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+    usize,
+    usize,
+);
+
+pub struct DfuComponent<
+    U: 'static + hil::usb::UsbController<'static>,
+    S: dynamic_binary_storage::DynamicBinaryStore + 'static,
+    L: dynamic_binary_storage::DynamicProcessLoad + 'static,
+> {
+    usb: &'static U,
+    max_ctrl_packet_size: u8,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 5],
+    storage_driver: &'static S,
+    load_driver: &'static L,
+    staging: Option<StagingArea>,
+}
+
+impl<
+    U: 'static + hil::usb::UsbController<'static>,
+    S: dynamic_binary_storage::DynamicBinaryStore + 'static,
+    L: dynamic_binary_storage::DynamicProcessLoad + 'static,
+> DfuComponent<U, S, L>
+{
+    pub fn new(
+        usb: &'static U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 5],
+        storage_driver: &'static S,
+        load_driver: &'static L,
+        staging: Option<StagingArea>,
+    ) -> Self {
+        Self {
+            usb,
+            max_ctrl_packet_size,
+            vendor_id,
+            product_id,
+            strings,
+            storage_driver,
+            load_driver,
+            staging,
+        }
+    }
+}
+
+impl<
+    U: 'static + hil::usb::UsbController<'static>,
+    S: dynamic_binary_storage::DynamicBinaryStore + 'static,
+    L: dynamic_binary_storage::DynamicProcessLoad + 'static,
+> Component for DfuComponent<U, S, L>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<Dfu<'static, U>>,
+        &'static mut MaybeUninit<[u8; TRANSFER_SIZE]>,
+    );
+    type Output = &'static Dfu<'static, U>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let buffer = s.1.write([0; TRANSFER_SIZE]);
+
+        let (staging, staging_address, staging_length) = match self.staging {
+            Some((storage, address, length)) => (Some(storage), address, length),
+            None => (None, 0, 0),
+        };
+
+        let dfu = s.0.write(Dfu::new(
+            self.usb,
+            self.max_ctrl_packet_size,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+            self.storage_driver,
+            self.load_driver,
+            staging,
+            staging_address,
+            staging_length,
+            buffer,
+        ));
+        dynamic_binary_storage::DynamicBinaryStore::set_storage_client(self.storage_driver, dfu);
+        dynamic_binary_storage::DynamicProcessLoad::set_load_client(self.load_driver, dfu);
+        if let Some(staging) = staging {
+            staging.set_client(dfu);
+        }
+        self.usb.set_client(dfu);
+
+        dfu
+    }
+}
@@ -0,0 +1,83 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for the FAT filesystem driver.
+//!
+//! This provides one component, FatFsComponent, which provides a system call
+//! interface to files on a FAT formatted nonvolatile storage device.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let fat_fs = components::fat_fs::FatFsComponent::new(
+//!     board_kernel,
+//!     capsules_extra::fat_fs::DRIVER_NUM,
+//!     sdcard_storage,
+//!     create_capability!(capabilities::MemoryAllocationCapability),
+//! )
+//! .finalize(components::fat_fs_component_static!());
+//! ```
+
+use capsules_extra::fat_fs::FatFs;
+use core::mem::MaybeUninit;
+use kernel::capabilities::MemoryAllocationCapability;
+use kernel::component::Component;
+use kernel::hil;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! fat_fs_component_static {
+    () => {{
+        let fat_fs = kernel::static_buf!(capsules_extra::fat_fs::FatFs<'static>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::fat_fs::BUF_LEN]);
+
+        (fat_fs, buffer)
+    }};
+}
+
+pub type FatFsComponentType = FatFs<'static>;
+
+pub struct FatFsComponent<CAP: MemoryAllocationCapability + 'static> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    storage: &'static dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+    mem_cap: CAP,
+}
+
+impl<CAP: MemoryAllocationCapability + 'static> FatFsComponent<CAP> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        storage: &'static dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+        mem_cap: CAP,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            storage,
+            mem_cap,
+        }
+    }
+}
+
+impl<CAP: MemoryAllocationCapability + 'static> Component for FatFsComponent<CAP> {
+    type StaticInput = (
+        &'static mut MaybeUninit<FatFs<'static>>,
+        &'static mut MaybeUninit<[u8; capsules_extra::fat_fs::BUF_LEN]>,
+    );
+    type Output = &'static FatFs<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let buffer = static_buffer.1.write([0; capsules_extra::fat_fs::BUF_LEN]);
+
+        let fat_fs = static_buffer.0.write(FatFs::new(
+            self.storage,
+            self.board_kernel
+                .create_grant(self.driver_num, &self.mem_cap),
+            buffer,
+        ));
+        self.storage.set_client(fat_fs);
+        fat_fs
+    }
+}
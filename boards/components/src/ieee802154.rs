@@ -18,7 +18,7 @@
 //!     nrf52840::aes::AesECB
 //! ));
 //!
-//! let (radio, mux_mac) = components::ieee802154::Ieee802154Component::new(
+//! let (radio, mux_mac, mac_device) = components::ieee802154::Ieee802154Component::new(
 //!     board_kernel,
 //!     capsules_extra::ieee802154::DRIVER_NUM,
 //!     &nrf52::ieee802154_radio::RADIO,
@@ -32,6 +32,20 @@
 //!     nrf52::aes::AesECB<'static>
 //! ));
 //! ```
+//!
+//! Boards with nonvolatile storage should persist the frame counter so that it
+//! is not reused after a reboot:
+//!
+//! ```rust
+//! mac_device.set_frame_counter_storage(
+//!     nonvolatile_storage,
+//!     FRAME_COUNTER_ADDRESS,
+//!     static_init!(
+//!         [u8; capsules_extra::ieee802154::framer::FRAME_COUNTER_STORAGE_LEN],
+//!         [0; capsules_extra::ieee802154::framer::FRAME_COUNTER_STORAGE_LEN]
+//!     ),
+//! );
+//! ```
 
 use capsules_core::virtualizers::virtual_aes_ccm::MuxAES128CCM;
 use capsules_extra::ieee802154::device::MacDevice;
@@ -286,6 +300,11 @@ impl<
                 capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
             >,
         >,
+        &'static capsules_extra::ieee802154::framer::Framer<
+            'static,
+            AwakeMac<'static, R>,
+            capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, A>,
+        >,
     );
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
@@ -354,7 +373,7 @@ impl<
         userspace_mac.set_address(self.short_addr);
         userspace_mac.set_address_long(self.long_addr);
 
-        (radio_driver, mux_mac)
+        (radio_driver, mux_mac, mac_device)
     }
 }
 
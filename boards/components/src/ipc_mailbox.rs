@@ -0,0 +1,67 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for message-queue IPC.
+//!
+//! This provides one component, IpcMailboxComponent, which gives each process
+//! a mailbox that holds up to `DEPTH` messages of at most `MESSAGE_LEN` bytes.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ipc_mailbox = components::ipc_mailbox::IpcMailboxComponent::new(
+//!     board_kernel,
+//!     capsules_extra::ipc_mailbox::DRIVER_NUM,
+//!     create_capability!(capabilities::MemoryAllocationCapability),
+//! )
+//! .finalize(components::ipc_mailbox_component_static!(4, 64));
+//! ```
+
+use capsules_extra::ipc_mailbox::IpcMailbox;
+use core::mem::MaybeUninit;
+use kernel::capabilities::MemoryAllocationCapability;
+use kernel::component::Component;
+
+#[macro_export]
+macro_rules! ipc_mailbox_component_static {
+    ($DEPTH:expr, $MESSAGE_LEN:expr $(,)?) => {{
+        kernel::static_buf!(capsules_extra::ipc_mailbox::IpcMailbox<$DEPTH, $MESSAGE_LEN>)
+    }};
+}
+
+pub struct IpcMailboxComponent<
+    const DEPTH: usize,
+    const MESSAGE_LEN: usize,
+    CAP: MemoryAllocationCapability + 'static,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    mem_cap: CAP,
+}
+
+impl<const DEPTH: usize, const MESSAGE_LEN: usize, CAP: MemoryAllocationCapability + 'static>
+    IpcMailboxComponent<DEPTH, MESSAGE_LEN, CAP>
+{
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize, mem_cap: CAP) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            mem_cap,
+        }
+    }
+}
+
+impl<const DEPTH: usize, const MESSAGE_LEN: usize, CAP: MemoryAllocationCapability + 'static>
+    Component for IpcMailboxComponent<DEPTH, MESSAGE_LEN, CAP>
+{
+    type StaticInput = &'static mut MaybeUninit<IpcMailbox<DEPTH, MESSAGE_LEN>>;
+    type Output = &'static IpcMailbox<DEPTH, MESSAGE_LEN>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        static_buffer.write(IpcMailbox::new(
+            self.board_kernel
+                .create_grant(self.driver_num, &self.mem_cap),
+        ))
+    }
+}
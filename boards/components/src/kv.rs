@@ -167,35 +167,53 @@ impl<V: hil::kv::KVPermissions<'static> + 'static> Component for VirtualKVPermis
 // KV Store Permissions
 /////////////////////
 
+/// Allocate the static buffers of a `KVStorePermissions`.
+///
+/// Without `$MAX_KEYS` and `$MAX_KEY_LENGTH`, no room is reserved for the
+/// per-writer key indexes, which is only suitable for boards that do not assign
+/// storage quotas. Otherwise they must be at least the largest `max_keys` of any
+/// quota and the longest key stored.
 #[macro_export]
 macro_rules! kv_store_permissions_component_static {
-    ($V:ty $(,)?) => {{
+    ($V:ty $(,)?) => {{ $crate::kv_store_permissions_component_static!($V, 0, 0) }};
+    ($V:ty, $MAX_KEYS:expr, $MAX_KEY_LENGTH:expr $(,)?) => {{
         let buffer = kernel::static_buf!([u8; capsules_extra::kv_store_permissions::HEADER_LENGTH]);
+        let index_key =
+            kernel::static_buf!([u8; capsules_extra::kv_store_permissions::INDEX_KEY_LENGTH]);
+        let index_value = kernel::static_buf!(
+            [u8; capsules_extra::kv_store_permissions::index_length($MAX_KEYS, $MAX_KEY_LENGTH)]
+        );
         let kv_store = kernel::static_buf!(
             capsules_extra::kv_store_permissions::KVStorePermissions<'static, $V>
         );
 
-        (kv_store, buffer)
+        (kv_store, buffer, index_key, index_value)
     }};
 }
 
 pub type KVStorePermissionsComponentType<V> =
     capsules_extra::kv_store_permissions::KVStorePermissions<'static, V>;
 
-pub struct KVStorePermissionsComponent<V: hil::kv::KV<'static> + 'static> {
+pub struct KVStorePermissionsComponent<V: hil::kv::KV<'static> + 'static, const INDEX_LEN: usize> {
     kv: &'static V,
 }
 
-impl<V: hil::kv::KV<'static> + 'static> KVStorePermissionsComponent<V> {
+impl<V: hil::kv::KV<'static> + 'static, const INDEX_LEN: usize>
+    KVStorePermissionsComponent<V, INDEX_LEN>
+{
     pub fn new(kv: &'static V) -> Self {
         Self { kv }
     }
 }
 
-impl<V: hil::kv::KV<'static> + 'static> Component for KVStorePermissionsComponent<V> {
+impl<V: hil::kv::KV<'static> + 'static, const INDEX_LEN: usize> Component
+    for KVStorePermissionsComponent<V, INDEX_LEN>
+{
     type StaticInput = (
         &'static mut MaybeUninit<KVStorePermissions<'static, V>>,
         &'static mut MaybeUninit<[u8; capsules_extra::kv_store_permissions::HEADER_LENGTH]>,
+        &'static mut MaybeUninit<[u8; capsules_extra::kv_store_permissions::INDEX_KEY_LENGTH]>,
+        &'static mut MaybeUninit<[u8; INDEX_LEN]>,
     );
     type Output = &'static KVStorePermissions<'static, V>;
 
@@ -203,10 +221,17 @@ impl<V: hil::kv::KV<'static> + 'static> Component for KVStorePermissionsComponen
         let buffer = static_buffer
             .1
             .write([0; capsules_extra::kv_store_permissions::HEADER_LENGTH]);
+        let index_key = static_buffer
+            .2
+            .write([0; capsules_extra::kv_store_permissions::INDEX_KEY_LENGTH]);
+        let index_value = static_buffer.3.write([0; INDEX_LEN]);
 
-        let kv_store_permissions = static_buffer
-            .0
-            .write(KVStorePermissions::new(self.kv, buffer));
+        let kv_store_permissions = static_buffer.0.write(KVStorePermissions::new(
+            self.kv,
+            buffer,
+            index_key,
+            index_value,
+        ));
 
         self.kv.set_client(kv_store_permissions);
 
@@ -17,6 +17,7 @@ pub mod app_loader;
 pub mod appid;
 pub mod atecc508a;
 pub mod ble;
+pub mod ble_peripheral;
 pub mod bme280;
 pub mod bmm150;
 pub mod bmp280;
@@ -29,6 +30,7 @@ pub mod ccs811;
 pub mod cdc;
 pub mod chirp_i2c_moisture;
 pub mod console;
+pub mod crash_log;
 pub mod crc;
 pub mod ctap;
 pub mod cyw4343;
@@ -36,8 +38,10 @@ pub mod dac;
 pub mod date_time;
 pub mod debug_writer;
 pub mod dfrobot_rainfall_sensor;
+pub mod dfu;
 pub mod dynamic_binary_storage;
 pub mod eui64;
+pub mod fat_fs;
 pub mod flash;
 pub mod fm25cl;
 pub mod ft6x06;
@@ -50,6 +54,7 @@ pub mod hts221;
 pub mod humidity;
 pub mod i2c;
 pub mod ieee802154;
+pub mod ipc_mailbox;
 pub mod isl29035;
 pub mod isolated_nonvolatile_storage;
 pub mod keyboard_hid;
@@ -68,13 +73,16 @@ pub mod lsm6dsox;
 pub mod ltc294x;
 pub mod mlx90614;
 pub mod moisture;
+pub mod msc;
 pub mod mx25r6435f;
 pub mod ninedof;
 pub mod nonvolatile_storage;
 pub mod nrf51822;
 pub mod panic_button;
 pub mod pressure;
+pub mod process_accounting;
 pub mod process_array;
+pub mod process_checkpoint;
 pub mod process_console;
 pub mod process_info_driver;
 pub mod process_printer;
@@ -102,6 +110,8 @@ pub mod spi;
 pub mod ssd1306;
 pub mod st77xx;
 pub mod storage_permissions;
+pub mod syscall_trace;
+pub mod tcp;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
@@ -0,0 +1,112 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for USB Mass Storage Class support.
+//!
+//! This exposes a region of a nonvolatile storage device to a USB host as a
+//! removable drive.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static STRINGS: &'static [&str; 3] = &[
+//!     "XYZ Corp.",      // Manufacturer
+//!     "The Zorpinator", // Product
+//!     "Serial No. 5",   // Serial number
+//! ];
+//! let msc = components::msc::MscComponent::new(
+//!     &nrf52840_peripherals.usbd,
+//!     capsules_extra::usb::usbc_client::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x1915,
+//!     0x503b,
+//!     STRINGS,
+//!     sdcard_storage,
+//!     0,
+//!     0x1000000,
+//! )
+//! .finalize(components::msc_component_static!(nrf52840::usbd::Usbd));
+//!
+//! msc.enable();
+//! msc.attach();
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_extra::usb::msc::{BLOCK_SIZE, MassStorage};
+use kernel::component::Component;
+use kernel::hil;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! msc_component_static {
+    ($U:ty $(,)?) => {{
+        let msc = kernel::static_buf!(capsules_extra::usb::msc::MassStorage<'static, $U>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::usb::msc::BLOCK_SIZE]);
+
+        (msc, buffer)
+    }};
+}
+
+pub struct MscComponent<U: 'static + hil::usb::UsbController<'static>> {
+    usb: &'static U,
+    max_ctrl_packet_size: u8,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 3],
+    storage: &'static dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+    start_address: usize,
+    length: usize,
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> MscComponent<U> {
+    pub fn new(
+        usb: &'static U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        storage: &'static dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+        start_address: usize,
+        length: usize,
+    ) -> Self {
+        Self {
+            usb,
+            max_ctrl_packet_size,
+            vendor_id,
+            product_id,
+            strings,
+            storage,
+            start_address,
+            length,
+        }
+    }
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> Component for MscComponent<U> {
+    type StaticInput = (
+        &'static mut MaybeUninit<MassStorage<'static, U>>,
+        &'static mut MaybeUninit<[u8; BLOCK_SIZE]>,
+    );
+    type Output = &'static MassStorage<'static, U>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let buffer = s.1.write([0; BLOCK_SIZE]);
+
+        let msc = s.0.write(MassStorage::new(
+            self.usb,
+            self.max_ctrl_packet_size,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+            self.storage,
+            self.start_address,
+            self.length,
+            buffer,
+        ));
+        self.storage.set_client(msc);
+        self.usb.set_client(msc);
+
+        msc
+    }
+}
@@ -0,0 +1,65 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for the process accounting capsule.
+
+use capsules_extra::process_accounting::{self, ProcessAccounting};
+use core::mem::MaybeUninit;
+use kernel::capabilities::MemoryAllocationCapability;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::component::Component;
+
+#[macro_export]
+macro_rules! process_accounting_component_static {
+    ($C:ty $(,)?) => {{
+        let process_accounting =
+            kernel::static_buf!(capsules_extra::process_accounting::ProcessAccounting<$C>);
+
+        process_accounting
+    }};
+}
+
+pub struct ProcessAccountingComponent<
+    C: ProcessManagementCapability,
+    CAP: MemoryAllocationCapability + 'static,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    capability: C,
+    mem_cap: CAP,
+}
+
+impl<C: ProcessManagementCapability, CAP: MemoryAllocationCapability + 'static>
+    ProcessAccountingComponent<C, CAP>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        capability: C,
+        mem_cap: CAP,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            capability,
+            mem_cap,
+        }
+    }
+}
+
+impl<C: ProcessManagementCapability + 'static, CAP: MemoryAllocationCapability + 'static> Component
+    for ProcessAccountingComponent<C, CAP>
+{
+    type StaticInput = &'static mut MaybeUninit<ProcessAccounting<C>>;
+    type Output = &'static process_accounting::ProcessAccounting<C>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        static_buffer.write(ProcessAccounting::new(
+            self.board_kernel,
+            self.board_kernel
+                .create_grant(self.driver_num, &self.mem_cap),
+            self.capability,
+        ))
+    }
+}
@@ -0,0 +1,102 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for checkpointing processes to flash.
+//!
+//! This provides one component, ProcessCheckpointComponent, which saves
+//! process snapshots into a `storage_volume!` region and restores processes
+//! from them on boot. `SLOT_SIZE` bytes of the volume are used per process,
+//! and must be large enough for the process's RAM plus a small header.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! storage_volume!(CHECKPOINTS, 32);
+//!
+//! kernel::create_typed_capability!(checkpoint_cap, CheckpointCap:
+//!     kernel::capabilities::ProcessManagementCapability
+//! );
+//! let process_checkpoint = components::process_checkpoint::ProcessCheckpointComponent::new(
+//!     board_kernel,
+//!     nv_to_page,
+//!     &CHECKPOINTS,
+//!     checkpoint_cap,
+//! )
+//! .finalize(components::process_checkpoint_component_static!(CheckpointCap, 8192));
+//!
+//! // After processes are loaded, before starting the kernel loop.
+//! process_checkpoint.restore();
+//! ```
+
+use capsules_system::process_checkpoint::ProcessCheckpoint;
+use core::mem::MaybeUninit;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::component::Component;
+use kernel::hil;
+
+#[macro_export]
+macro_rules! process_checkpoint_component_static {
+    ($C: ty, $SLOT_SIZE: expr $(,)?) => {{
+        let buffer = kernel::static_buf!([u8; $SLOT_SIZE]);
+        let process_checkpoint = kernel::static_buf!(
+            capsules_system::process_checkpoint::ProcessCheckpoint<'static, $C>
+        );
+
+        (buffer, process_checkpoint)
+    }};
+}
+
+pub type ProcessCheckpointComponentType<C> = ProcessCheckpoint<'static, C>;
+
+pub struct ProcessCheckpointComponent<
+    C: ProcessManagementCapability + 'static,
+    const SLOT_SIZE: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    storage: &'static dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+    volume: &'static [u8],
+    capability: C,
+}
+
+impl<C: ProcessManagementCapability + 'static, const SLOT_SIZE: usize>
+    ProcessCheckpointComponent<C, SLOT_SIZE>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        storage: &'static dyn hil::nonvolatile_storage::NonvolatileStorage<'static>,
+        volume: &'static [u8],
+        capability: C,
+    ) -> Self {
+        Self {
+            board_kernel,
+            storage,
+            volume,
+            capability,
+        }
+    }
+}
+
+impl<C: ProcessManagementCapability + 'static, const SLOT_SIZE: usize> Component
+    for ProcessCheckpointComponent<C, SLOT_SIZE>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; SLOT_SIZE]>,
+        &'static mut MaybeUninit<ProcessCheckpoint<'static, C>>,
+    );
+    type Output = &'static ProcessCheckpoint<'static, C>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let buffer = static_buffer.0.write([0; SLOT_SIZE]);
+
+        let process_checkpoint = static_buffer.1.write(ProcessCheckpoint::new(
+            self.board_kernel,
+            self.storage,
+            self.volume,
+            buffer,
+            self.capability,
+        ));
+        self.storage.set_client(process_checkpoint);
+        process_checkpoint
+    }
+}
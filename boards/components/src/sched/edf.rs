@@ -0,0 +1,119 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for an earliest deadline first scheduler with CPU reservations.
+//!
+//! This provides one Component, EdfComponent.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static RESERVATIONS: [Reservation; 1] = [Reservation {
+//!     process_name: "control_loop",
+//!     period_us: 10_000,
+//!     budget_us: 2_000,
+//! }];
+//!
+//! let scheduler = components::sched::edf::EdfComponent::new(
+//!     mux_alarm,
+//!     processes,
+//!     &RESERVATIONS,
+//! )
+//! .finalize(components::edf_component_static!(
+//!     nrf52832::rtc::Rtc,
+//!     NUM_PROCS
+//! ));
+//!
+//! // Show missed deadlines in the process console.
+//! process_console.set_scheduler_info(scheduler);
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_system::scheduler::edf::{EdfProcessNode, EdfSched, Reservation};
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+use kernel::process::ProcessArray;
+
+#[macro_export]
+macro_rules! edf_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let edf_sched = kernel::static_buf!(
+            capsules_system::scheduler::edf::EdfSched<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let edf_nodes = kernel::static_buf!(
+            [core::mem::MaybeUninit<
+                capsules_system::scheduler::edf::EdfProcessNode<
+                    'static,
+                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                >,
+            >; $N]
+        );
+
+        (alarm, edf_sched, edf_nodes)
+    }};
+}
+
+pub type EdfComponentType<A> =
+    capsules_system::scheduler::edf::EdfSched<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct EdfComponent<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    processes: &'static ProcessArray<NUM_PROCS>,
+    reservations: &'static [Reservation],
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> EdfComponent<A, NUM_PROCS> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        processes: &'static ProcessArray<NUM_PROCS>,
+        reservations: &'static [Reservation],
+    ) -> EdfComponent<A, NUM_PROCS> {
+        EdfComponent {
+            alarm_mux,
+            processes,
+            reservations,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> Component
+    for EdfComponent<A, NUM_PROCS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<EdfSched<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<
+            [MaybeUninit<EdfProcessNode<'static, VirtualMuxAlarm<'static, A>>>; NUM_PROCS],
+        >,
+    );
+    type Output = &'static EdfSched<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let scheduler_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        scheduler_alarm.setup();
+
+        let scheduler = static_buffer
+            .1
+            .write(EdfSched::new(scheduler_alarm, self.reservations));
+        scheduler_alarm.set_alarm_client(scheduler);
+
+        let nodes = static_buffer
+            .2
+            .write([const { MaybeUninit::uninit() }; NUM_PROCS]);
+
+        for (i, node) in nodes.iter_mut().enumerate() {
+            let init_node = node.write(EdfProcessNode::new(&self.processes[i]));
+            scheduler.processes.push_tail(init_node);
+        }
+        scheduler
+    }
+}
@@ -4,4 +4,5 @@
 
 pub mod individual;
 pub mod null;
+pub mod quota;
 pub mod tbf_header;
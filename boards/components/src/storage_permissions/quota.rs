@@ -0,0 +1,115 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for creating a storage permissions policy that adds storage quotas
+//! to the permissions of another policy.
+//!
+//! ```rust
+//! kernel::create_typed_capability!(app_store_cap, AppStoreCap: kernel::capabilities::ApplicationStorageCapability);
+//! let storage_permissions_policy =
+//!     components::storage_permissions::quota::StoragePermissionsQuotaComponent::new(
+//!         individual_storage_permissions_policy,
+//!         Some(kernel::storage_permissions::StorageQuota {
+//!             max_bytes: 2048,
+//!             max_keys: 16,
+//!         }),
+//!         &[],
+//!         app_store_cap,
+//!     )
+//!     .finalize(
+//!         components::storage_permissions_quota_component_static!(
+//!             nrf52840dk_lib::Chip,
+//!             kernel::process::ProcessStandardDebugFull,
+//!             IndividualStoragePermissionsPolicy,
+//!             AppStoreCap,
+//!         ),
+//!     );
+//! ```
+
+use core::mem::MaybeUninit;
+use kernel::capabilities::ApplicationStorageCapability;
+use kernel::component::Component;
+use kernel::platform::chip::Chip;
+use kernel::process::{ProcessStandardDebug, ProcessStandardStoragePermissionsPolicy};
+use kernel::storage_permissions::StorageQuota;
+
+#[macro_export]
+macro_rules! storage_permissions_quota_component_static {
+    ($C:ty, $D:ty, $P:ty, $CAP:ty $(,)?) => {{
+        kernel::static_buf!(
+            capsules_system::storage_permissions::quota::QuotaStoragePermissions<
+                $C,
+                $D,
+                $P,
+                $CAP,
+            >
+        )
+    }};
+}
+
+pub type StoragePermissionsQuotaComponentType<C, D, P, CAP> =
+    capsules_system::storage_permissions::quota::QuotaStoragePermissions<C, D, P, CAP>;
+
+pub struct StoragePermissionsQuotaComponent<
+    C: Chip,
+    D: ProcessStandardDebug,
+    P: ProcessStandardStoragePermissionsPolicy<C, D> + 'static,
+    CAP: ApplicationStorageCapability + 'static,
+> {
+    policy: &'static P,
+    default_quota: Option<StorageQuota>,
+    app_quotas: &'static [(u32, StorageQuota)],
+    cap: CAP,
+    _chip: core::marker::PhantomData<C>,
+    _debug: core::marker::PhantomData<D>,
+}
+
+impl<
+    C: Chip,
+    D: ProcessStandardDebug,
+    P: ProcessStandardStoragePermissionsPolicy<C, D>,
+    CAP: ApplicationStorageCapability,
+> StoragePermissionsQuotaComponent<C, D, P, CAP>
+{
+    pub fn new(
+        policy: &'static P,
+        default_quota: Option<StorageQuota>,
+        app_quotas: &'static [(u32, StorageQuota)],
+        cap: CAP,
+    ) -> Self {
+        Self {
+            policy,
+            default_quota,
+            app_quotas,
+            cap,
+            _chip: core::marker::PhantomData,
+            _debug: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<
+    C: Chip + 'static,
+    D: ProcessStandardDebug + 'static,
+    P: ProcessStandardStoragePermissionsPolicy<C, D> + 'static,
+    CAP: ApplicationStorageCapability + 'static,
+> Component for StoragePermissionsQuotaComponent<C, D, P, CAP>
+{
+    type StaticInput = &'static mut MaybeUninit<
+        capsules_system::storage_permissions::quota::QuotaStoragePermissions<C, D, P, CAP>,
+    >;
+    type Output =
+        &'static capsules_system::storage_permissions::quota::QuotaStoragePermissions<C, D, P, CAP>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        s.write(
+            capsules_system::storage_permissions::quota::QuotaStoragePermissions::new(
+                self.policy,
+                self.default_quota,
+                self.app_quotas,
+                self.cap,
+            ),
+        )
+    }
+}
@@ -0,0 +1,98 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component for the system call trace buffer.
+//!
+//! This provides one component, SyscallTraceComponent, which keeps the most
+//! recent `RECORDS` system calls of up to `NUM_PROCS` traced processes and sets
+//! itself as the kernel's syscall tracer. The kernel must be built with the
+//! `record_syscalls` feature for system calls to be recorded.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let syscall_trace = components::syscall_trace::SyscallTraceComponent::new(
+//!     board_kernel,
+//!     &base_peripherals.rtc,
+//!     create_capability!(capabilities::ProcessManagementCapability),
+//! )
+//! .finalize(components::syscall_trace_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     NUM_PROCS,
+//!     64
+//! ));
+//! process_console.set_syscall_trace(syscall_trace);
+//! ```
+
+use capsules_system::syscall_trace::SyscallTraceBuffer;
+use core::mem::MaybeUninit;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::component::Component;
+use kernel::hil::time::Time;
+use kernel::syscall_trace::SyscallTraceRecord;
+
+#[macro_export]
+macro_rules! syscall_trace_component_static {
+    ($T:ty, $NUM_PROCS:expr, $RECORDS:expr $(,)?) => {{
+        let records =
+            kernel::static_buf!([Option<kernel::syscall_trace::SyscallTraceRecord>; $RECORDS]);
+        let syscall_trace = kernel::static_buf!(
+            capsules_system::syscall_trace::SyscallTraceBuffer<'static, $T, $NUM_PROCS>
+        );
+
+        (records, syscall_trace)
+    }};
+}
+
+pub struct SyscallTraceComponent<
+    T: 'static + Time,
+    const NUM_PROCS: usize,
+    const RECORDS: usize,
+    C: ProcessManagementCapability,
+> {
+    board_kernel: &'static kernel::Kernel,
+    time: &'static T,
+    capability: C,
+}
+
+impl<
+    T: 'static + Time,
+    const NUM_PROCS: usize,
+    const RECORDS: usize,
+    C: ProcessManagementCapability,
+> SyscallTraceComponent<T, NUM_PROCS, RECORDS, C>
+{
+    pub fn new(board_kernel: &'static kernel::Kernel, time: &'static T, capability: C) -> Self {
+        Self {
+            board_kernel,
+            time,
+            capability,
+        }
+    }
+}
+
+impl<
+    T: 'static + Time,
+    const NUM_PROCS: usize,
+    const RECORDS: usize,
+    C: ProcessManagementCapability,
+> Component for SyscallTraceComponent<T, NUM_PROCS, RECORDS, C>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[Option<SyscallTraceRecord>; RECORDS]>,
+        &'static mut MaybeUninit<SyscallTraceBuffer<'static, T, NUM_PROCS>>,
+    );
+    type Output = &'static SyscallTraceBuffer<'static, T, NUM_PROCS>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let records = static_buffer.0.write([None; RECORDS]);
+        let syscall_trace: &'static _ = static_buffer
+            .1
+            .write(SyscallTraceBuffer::new(self.time, records));
+        self.board_kernel
+            .set_syscall_tracer(syscall_trace, &self.capability);
+
+        syscall_trace
+    }
+}
@@ -0,0 +1,333 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Component to initialize the TCP/6LoWPAN stack and the userland TCP driver.
+//!
+//! This provides one Component, TCPDriverComponent. Like the UDP stack, the
+//! TCP stack has its own MAC user, 6LoWPAN state and IP sender and receiver
+//! on top of a shared `MuxMac`. The component creates a `MuxTcp` with
+//! `NUM_TCP_SOCKETS` sockets and hands all of them to the userspace driver,
+//! which assigns one socket to each process that opens a connection.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let tcp_driver = TCPDriverComponent::new(
+//!     board_kernel,
+//!     capsules_extra::net::tcp::DRIVER_NUM,
+//!     mux_mac,
+//!     DEFAULT_CTX_PREFIX_LEN,
+//!     DEFAULT_CTX_PREFIX,
+//!     DST_MAC_ADDR,
+//!     src_mac_from_serial_num,
+//!     local_ip_ifaces,
+//!     mux_alarm,
+//!     create_cap,
+//!     mem_cap,
+//! )
+//! .finalize(components::tcp_driver_component_static!(AlarmType, MacType));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ieee802154::device::MacDevice;
+use capsules_extra::ieee802154::virtual_mac::{MacUser, MuxMac};
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_recv::{IP6Receiver, IP6RecvStruct};
+use capsules_extra::net::ipv6::ipv6_send::{IP6SendStruct, IP6Sender};
+use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
+use capsules_extra::net::network_capabilities::{
+    AddrRange, IpVisibilityCapability, NetworkCapability, PortRange, TcpVisibilityCapability,
+};
+use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+use capsules_extra::net::tcp::TCPHeader;
+use capsules_extra::net::tcp::driver::{TCPDriver, TCPDriverSocket};
+use capsules_extra::net::tcp::tcp_mux::{MuxTcp, TCPSocketStruct};
+use core::mem::MaybeUninit;
+use kernel::capabilities::{MemoryAllocationCapability, NetworkCapabilityCreationCapability};
+use kernel::component::Component;
+use kernel::hil::radio;
+use kernel::hil::time::Alarm;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+
+/// Number of TCP connections that can be open at the same time.
+pub const NUM_TCP_SOCKETS: usize = 2;
+/// Largest payload carried by a single TCP segment.
+pub const MAX_SEGMENT_LEN: usize = 200;
+/// Size of the per-socket buffer holding data until the peer acknowledges it.
+pub const SEND_BUF_LEN: usize = 512;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! tcp_driver_component_static {
+    ($A:ty, $M:ty $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+        use capsules_extra::net::tcp::tcp_mux::{MuxTcp, TCPSocketStruct};
+        use components::tcp::{MAX_SEGMENT_LEN, NUM_TCP_SOCKETS, SEND_BUF_LEN};
+
+        let ip_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let tcp_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static, $M>);
+        let sixlowpan = kernel::static_buf!(
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+                sixlowpan_compression::Context,
+            >
+        );
+        let rx_state = kernel::static_buf!(sixlowpan_state::RxState<'static>);
+        let ip6_send = kernel::static_buf!(
+            capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let ip6_receive =
+            kernel::static_buf!(capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct<'static>);
+        let mux_tcp = kernel::static_buf!(MuxTcp<'static, VirtualMuxAlarm<'static, $A>>);
+        let sockets = kernel::static_buf!(
+            [TCPSocketStruct<'static, VirtualMuxAlarm<'static, $A>>; NUM_TCP_SOCKETS]
+        );
+        let driver_sockets = kernel::static_buf!(
+            [capsules_extra::net::tcp::driver::TCPDriverSocket<'static>; NUM_TCP_SOCKETS]
+        );
+        let tcp_driver = kernel::static_buf!(capsules_extra::net::tcp::TCPDriver<'static>);
+
+        let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let sixlowpan_rx = kernel::static_buf!([u8; 1280]);
+        let ip6_payload = kernel::static_buf!([u8; MAX_SEGMENT_LEN]);
+        let segment_buf = kernel::static_buf!([u8; MAX_SEGMENT_LEN]);
+        let send_bufs = kernel::static_buf!([[u8; SEND_BUF_LEN]; NUM_TCP_SOCKETS]);
+
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let tcp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::TcpVisibilityCapability);
+        let ip_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
+
+        (
+            ip_alarm,
+            tcp_alarm,
+            mac_user,
+            sixlowpan,
+            rx_state,
+            ip6_send,
+            ip6_packet,
+            ip6_receive,
+            mux_tcp,
+            sockets,
+            driver_sockets,
+            tcp_driver,
+            radio_buf,
+            sixlowpan_rx,
+            ip6_payload,
+            segment_buf,
+            send_bufs,
+            net_cap,
+            tcp_vis_cap,
+            ip_vis_cap,
+        )
+    }};
+}
+
+pub type TCPDriverComponentType = TCPDriver<'static>;
+
+pub struct TCPDriverComponent<
+    A: Alarm<'static> + 'static,
+    M: MacDevice<'static> + 'static,
+    NET: NetworkCapabilityCreationCapability + 'static,
+    MEM: MemoryAllocationCapability + 'static,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    mux_mac: &'static MuxMac<'static, M>,
+    ctx_pfix_len: u8,
+    ctx_pfix: [u8; 16],
+    dst_mac_addr: MacAddress,
+    src_mac_addr: MacAddress,
+    interface_list: &'static [IPAddr],
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    create_cap: NET,
+    mem_cap: MEM,
+}
+
+impl<
+    A: Alarm<'static> + 'static,
+    M: MacDevice<'static>,
+    NET: NetworkCapabilityCreationCapability + 'static,
+    MEM: MemoryAllocationCapability + 'static,
+> TCPDriverComponent<A, M, NET, MEM>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        mux_mac: &'static MuxMac<'static, M>,
+        ctx_pfix_len: u8,
+        ctx_pfix: [u8; 16],
+        dst_mac_addr: MacAddress,
+        src_mac_addr: MacAddress,
+        interface_list: &'static [IPAddr],
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        create_cap: NET,
+        mem_cap: MEM,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            mux_mac,
+            ctx_pfix_len,
+            ctx_pfix,
+            dst_mac_addr,
+            src_mac_addr,
+            interface_list,
+            alarm_mux,
+            create_cap,
+            mem_cap,
+        }
+    }
+}
+
+impl<
+    A: Alarm<'static> + 'static,
+    M: MacDevice<'static>,
+    NET: NetworkCapabilityCreationCapability + 'static,
+    MEM: MemoryAllocationCapability + 'static,
+> Component for TCPDriverComponent<A, M, NET, MEM>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<MacUser<'static, M>>,
+        &'static mut MaybeUninit<
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, A>,
+                sixlowpan_compression::Context,
+            >,
+        >,
+        &'static mut MaybeUninit<sixlowpan_state::RxState<'static>>,
+        &'static mut MaybeUninit<IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<IP6RecvStruct<'static>>,
+        &'static mut MaybeUninit<MuxTcp<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<
+            [TCPSocketStruct<'static, VirtualMuxAlarm<'static, A>>; NUM_TCP_SOCKETS],
+        >,
+        &'static mut MaybeUninit<[TCPDriverSocket<'static>; NUM_TCP_SOCKETS]>,
+        &'static mut MaybeUninit<TCPDriver<'static>>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; 1280]>,
+        &'static mut MaybeUninit<[u8; MAX_SEGMENT_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_SEGMENT_LEN]>,
+        &'static mut MaybeUninit<[[u8; SEND_BUF_LEN]; NUM_TCP_SOCKETS]>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<TcpVisibilityCapability>,
+        &'static mut MaybeUninit<IpVisibilityCapability>,
+    );
+    type Output = &'static TCPDriver<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let ipsender_virtual_alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        ipsender_virtual_alarm.setup();
+        let tcp_virtual_alarm = s.1.write(VirtualMuxAlarm::new(self.alarm_mux));
+        tcp_virtual_alarm.setup();
+
+        let tcp_mac = s.2.write(MacUser::new(self.mux_mac));
+        self.mux_mac.add_user(tcp_mac);
+
+        let net_cap = s.17.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &self.create_cap,
+        ));
+        let tcp_vis = s.18.write(TcpVisibilityCapability::new(&self.create_cap));
+        let ip_vis = s.19.write(IpVisibilityCapability::new(&self.create_cap));
+
+        let sixlowpan = s.3.write(sixlowpan_state::Sixlowpan::new(
+            sixlowpan_compression::Context {
+                prefix: self.ctx_pfix,
+                prefix_len: self.ctx_pfix_len,
+                id: 0,
+                compress: false,
+            },
+            ipsender_virtual_alarm, // OK to reuse bc only used to get time, not set alarms
+        ));
+
+        let sixlowpan_rx_buffer = s.13.write([0; 1280]);
+        let sixlowpan_state = sixlowpan as &dyn sixlowpan_state::SixlowpanState;
+        let sixlowpan_tx = sixlowpan_state::TxState::new(sixlowpan_state);
+        let default_rx_state =
+            s.4.write(sixlowpan_state::RxState::new(sixlowpan_rx_buffer));
+        sixlowpan_state.add_rx_state(default_rx_state);
+        tcp_mac.set_receive_client(sixlowpan);
+
+        let ip6_payload = s.14.write([0; MAX_SEGMENT_LEN]);
+        let ip_pyld: IPPayload = IPPayload {
+            header: TransportHeader::TCP(TCPHeader::new()),
+            payload: ip6_payload,
+        };
+        let ip6_dg = s.6.write(IP6Packet::new(ip_pyld));
+
+        let radio_buf = s.12.write([0; radio::MAX_BUF_SIZE]);
+
+        // As with UDP, the IP sender holds the destination MAC address, so all
+        // segments are sent via a single gateway router.
+        let ip_send = s.5.write(IP6SendStruct::new(
+            ip6_dg,
+            ipsender_virtual_alarm,
+            radio_buf,
+            sixlowpan_tx,
+            tcp_mac,
+            self.dst_mac_addr,
+            self.src_mac_addr,
+            ip_vis,
+        ));
+        ipsender_virtual_alarm.set_alarm_client(ip_send);
+        ip_send.set_addr(self.interface_list[0]);
+        tcp_mac.set_transmit_client(ip_send);
+
+        let ip_receive = s.7.write(IP6RecvStruct::new());
+        sixlowpan_state.set_rx_client(ip_receive);
+
+        let segment_buf = s.15.write([0; MAX_SEGMENT_LEN]);
+        let mux_tcp = s.8.write(MuxTcp::new(
+            ip_send,
+            tcp_virtual_alarm,
+            SubSliceMut::new(segment_buf),
+            net_cap,
+            tcp_vis,
+        ));
+        ip_send.set_client(mux_tcp);
+        ip_receive.set_client(mux_tcp);
+        tcp_virtual_alarm.set_alarm_client(mux_tcp);
+
+        let sockets: &'static [TCPSocketStruct<'static, VirtualMuxAlarm<'static, A>>;
+                     NUM_TCP_SOCKETS] =
+            s.9.write(core::array::from_fn(|_| TCPSocketStruct::new(mux_tcp)));
+        for socket in sockets.iter() {
+            mux_tcp.add_socket(socket);
+        }
+
+        let send_bufs = s.16.write([[0; SEND_BUF_LEN]; NUM_TCP_SOCKETS]);
+        let mut send_bufs = send_bufs.iter_mut();
+        let driver_sockets = s.10.write(core::array::from_fn(|i| {
+            TCPDriverSocket::new(&sockets[i], send_bufs.next().unwrap())
+        }));
+
+        let tcp_driver = s.11.write(TCPDriver::new(
+            driver_sockets,
+            self.board_kernel
+                .create_grant(self.driver_num, &self.mem_cap),
+            net_cap,
+        ));
+        tcp_driver.init();
+
+        tcp_driver
+    }
+}
@@ -25,6 +25,24 @@
 //!         nrf52840::aes::AesECB<'static>
 //!         ));
 //! ```
+//!
+//! Incoming data frames are secured with the Thread MAC key, which the IEEE
+//! 802.15.4 driver must be able to look up:
+//!
+//! ```rust
+//!        radio_driver.set_key_procedure(thread_driver);
+//!        radio_driver.set_device_procedure(thread_driver);
+//! ```
+//!
+//! Optionally, the MLE frame counter can be persisted across reboots:
+//!
+//! ```rust
+//!        thread_driver.set_frame_counter_storage(
+//!             nonvolatile_storage,
+//!             FRAME_COUNTER_ADDRESS,
+//!             static_init!([u8; 4], [0; 4]),
+//!         );
+//! ```
 
 use capsules_core::virtualizers::virtual_aes_ccm::MuxAES128CCM;
 use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
@@ -73,7 +91,7 @@ macro_rules! thread_network_component_static {
         let thread_network_driver = kernel::static_buf!(
             capsules_extra::net::thread::driver::ThreadNetworkDriver<
                 'static,
-                VirtualMuxAlarm<'static, $A>,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
             >
         );
         let send_buffer = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
@@ -84,7 +102,9 @@ macro_rules! thread_network_component_static {
         let crypt = kernel::static_buf!(
             capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<'static, $B>,
         );
-        let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
         let driver_cap = kernel::static_buf!($C);
 
         (
@@ -239,6 +259,7 @@ impl<
         let thread_network_driver = s.3.write(
             capsules_extra::net::thread::driver::ThreadNetworkDriver::new(
                 udp_send,
+                self.udp_send_mux.ip_sender(),
                 aes_ccm,
                 thread_virtual_alarm,
                 self.board_kernel
@@ -516,6 +516,14 @@ pub unsafe fn main() {
         DynamicBinaryStorage<'static>,
     ));
 
+    // Let the process console compact the stored apps, and report when it is
+    // done.
+    pconsole.set_binary_store(dynamic_binary_storage);
+    kernel::dynamic_binary_storage::DynamicBinaryStore::set_compact_client(
+        dynamic_binary_storage,
+        pconsole,
+    );
+
     //--------------------------------------------------------------------------
     // PLATFORM SETUP, SCHEDULER, AND START KERNEL LOOP
     //--------------------------------------------------------------------------
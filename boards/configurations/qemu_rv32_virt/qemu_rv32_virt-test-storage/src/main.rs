@@ -0,0 +1,364 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Board file for qemu-system-riscv32 "virt" machine type, with apps, a
+//! key-value store and a crash log on the second parallel flash bank.
+
+#![no_std]
+#![no_main]
+
+use capsules_system::process_policies::CrashRecordFaultPolicy;
+use core::num::NonZeroU32;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::hasher::Hasher;
+use kernel::platform::KernelResources;
+use kernel::platform::SyscallDriverLookup;
+use kernel::process::ProcessLoadingAsync;
+use kernel::process::ShortId;
+use kernel::{create_capability, debug, static_init};
+use qemu_rv32_virt_chip::flash::PFLASH1_BASE;
+use qemu_virt_chip::cfi_flash::{CfiFlash, CfiFlashBank, CfiFlashPage, PAGE_SIZE};
+
+// How should the kernel respond when a process faults.
+const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
+    capsules_system::process_policies::PanicFaultPolicy {};
+
+/// Size of the region at the start of the flash bank holding apps.
+const APP_FLASH_SIZE: usize = 0x0100_0000;
+/// Size of the region after the apps holding the KV store.
+const KV_FLASH_SIZE: usize = 0x0004_0000;
+/// Size of the region after the KV store holding the crash log.
+const CRASH_LOG_FLASH_SIZE: usize = 0x0000_8000;
+/// ShortId of the app allowed to read and clear the crash log.
+const CRASH_LOG_READER: u32 = 0x100;
+
+type FlashUser = capsules_core::virtualizers::virtual_flash::FlashUser<'static, CfiFlash>;
+
+type DynamicBinaryStorage = kernel::dynamic_binary_storage::SequentialDynamicBinaryStorage<
+    'static,
+    'static,
+    qemu_rv32_virt_lib::ChipHw,
+    kernel::process::ProcessStandardDebugFull,
+    components::dynamic_binary_storage::NVPages<FlashUser>,
+>;
+type AppLoaderDriver = capsules_extra::app_loader::AppLoader<
+    DynamicBinaryStorage,
+    DynamicBinaryStorage,
+    DynamicBinaryStorage,
+>;
+
+// TicKV
+type Siphasher24 = components::siphash::Siphasher24ComponentType;
+type TicKV = capsules_extra::tickv::TicKVSystem<'static, FlashUser, Siphasher24, PAGE_SIZE>;
+type TicKVKVStore =
+    components::kv::TicKVKVStoreComponentType<TicKV, capsules_extra::tickv::TicKVKeyType>;
+type KVStorePermissions = components::kv::KVStorePermissionsComponentType<TicKVKVStore>;
+type VirtualKVPermissions = components::kv::VirtualKVPermissionsComponentType<KVStorePermissions>;
+type KVDriver = components::kv::KVDriverComponentType<VirtualKVPermissions>;
+
+type CrashLog = components::crash_log::CrashLogComponentType<FlashUser>;
+
+struct Platform {
+    base: qemu_rv32_virt_lib::QemuRv32VirtPlatform,
+    kv_driver: &'static KVDriver,
+    dynamic_app_loader: &'static AppLoaderDriver,
+    crash_log: &'static CrashLog,
+}
+
+impl SyscallDriverLookup for Platform {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&dyn kernel::syscall::SyscallDriver>) -> R,
+    {
+        match driver_num {
+            capsules_extra::kv_driver::DRIVER_NUM => f(Some(self.kv_driver)),
+            capsules_extra::app_loader::DRIVER_NUM => f(Some(self.dynamic_app_loader)),
+            capsules_extra::crash_log::DRIVER_NUM => f(Some(self.crash_log)),
+
+            _ => self.base.with_driver(driver_num, f),
+        }
+    }
+}
+
+impl KernelResources<qemu_rv32_virt_lib::ChipHw> for Platform {
+    type SyscallDriverLookup = Self;
+    type SyscallFilter = <qemu_rv32_virt_lib::QemuRv32VirtPlatform as KernelResources<
+        qemu_rv32_virt_lib::ChipHw,
+    >>::SyscallFilter;
+    type ProcessFault = <qemu_rv32_virt_lib::QemuRv32VirtPlatform as KernelResources<
+        qemu_rv32_virt_lib::ChipHw,
+    >>::ProcessFault;
+    type Scheduler = <qemu_rv32_virt_lib::QemuRv32VirtPlatform as KernelResources<
+        qemu_rv32_virt_lib::ChipHw,
+    >>::Scheduler;
+    type SchedulerTimer = <qemu_rv32_virt_lib::QemuRv32VirtPlatform as KernelResources<
+        qemu_rv32_virt_lib::ChipHw,
+    >>::SchedulerTimer;
+    type WatchDog = <qemu_rv32_virt_lib::QemuRv32VirtPlatform as KernelResources<
+        qemu_rv32_virt_lib::ChipHw,
+    >>::WatchDog;
+    type ContextSwitchCallback = <qemu_rv32_virt_lib::QemuRv32VirtPlatform as KernelResources<
+        qemu_rv32_virt_lib::ChipHw,
+    >>::ContextSwitchCallback;
+
+    fn syscall_driver_lookup(&self) -> &Self::SyscallDriverLookup {
+        self
+    }
+    fn syscall_filter(&self) -> &Self::SyscallFilter {
+        self.base.syscall_filter()
+    }
+    fn process_fault(&self) -> &Self::ProcessFault {
+        self.base.process_fault()
+    }
+    fn scheduler(&self) -> &Self::Scheduler {
+        self.base.scheduler()
+    }
+    fn scheduler_timer(&self) -> &Self::SchedulerTimer {
+        self.base.scheduler_timer()
+    }
+    fn watchdog(&self) -> &Self::WatchDog {
+        self.base.watchdog()
+    }
+    fn context_switch_callback(&self) -> &Self::ContextSwitchCallback {
+        self.base.context_switch_callback()
+    }
+}
+
+impl kernel::process::ProcessLoadingAsyncClient for Platform {
+    fn process_loaded(&self, result: Result<(), kernel::process::ProcessLoadError>) {
+        if let Err(err) = result {
+            debug!("Error loading process: {:?}", err);
+        }
+    }
+
+    fn process_loading_finished(&self) {
+        debug!("Finished loading processes.");
+    }
+}
+
+/// Main function called after RAM initialized.
+#[no_mangle]
+pub unsafe fn main() {
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+
+    let (board_kernel, base_platform, chip) = qemu_rv32_virt_lib::start();
+
+    //--------------------------------------------------------------------------
+    // FLASH
+    //--------------------------------------------------------------------------
+
+    let pflash = static_init!(CfiFlash, CfiFlash::new(PFLASH1_BASE));
+    pflash.register();
+
+    let pflash_start = core::ptr::from_ref::<CfiFlashBank>(&PFLASH1_BASE).cast::<u8>();
+
+    let mux_flash = components::flash::FlashMuxComponent::new(pflash)
+        .finalize(components::flash_mux_component_static!(CfiFlash));
+
+    //--------------------------------------------------------------------------
+    // TICKV
+    //--------------------------------------------------------------------------
+
+    // Static buffers to use when reading/writing flash for TicKV.
+    let tickv_read_buf = static_init!([u8; PAGE_SIZE], [0; PAGE_SIZE]);
+    let page_buffer = static_init!(CfiFlashPage, CfiFlashPage::default());
+
+    // SipHash for creating TicKV hashed keys.
+    let sip_hash = components::siphash::Siphasher24Component::new()
+        .finalize(components::siphasher24_component_static!());
+
+    // TicKV with Tock wrapper/interface, right after the apps.
+    let tickv = components::tickv::TicKVComponent::new(
+        sip_hash,
+        mux_flash,
+        (pflash_start.addr() + APP_FLASH_SIZE) / PAGE_SIZE,
+        KV_FLASH_SIZE,
+        tickv_read_buf,
+        page_buffer,
+    )
+    .finalize(components::tickv_component_static!(
+        CfiFlash,
+        Siphasher24,
+        PAGE_SIZE
+    ));
+    sip_hash.set_client(tickv);
+
+    // KVSystem interface to KV (built on TicKV).
+    let tickv_kv_store = components::kv::TicKVKVStoreComponent::new(tickv).finalize(
+        components::tickv_kv_store_component_static!(TicKV, capsules_extra::tickv::TicKVKeyType),
+    );
+
+    let kv_store_permissions = components::kv::KVStorePermissionsComponent::new(tickv_kv_store)
+        .finalize(components::kv_store_permissions_component_static!(
+            TicKVKVStore
+        ));
+
+    // Share the KV stack with a mux.
+    let mux_kv = components::kv::KVPermissionsMuxComponent::new(kv_store_permissions).finalize(
+        components::kv_permissions_mux_component_static!(KVStorePermissions),
+    );
+
+    // Create a virtual component for the userspace driver.
+    let virtual_kv_driver = components::kv::VirtualKVPermissionsComponent::new(mux_kv).finalize(
+        components::virtual_kv_permissions_component_static!(KVStorePermissions),
+    );
+
+    // Userspace driver for KV.
+    let kv_driver = components::kv::KVDriverComponent::new(
+        virtual_kv_driver,
+        board_kernel,
+        capsules_extra::kv_driver::DRIVER_NUM,
+        create_capability!(capabilities::MemoryAllocationCapability),
+    )
+    .finalize(components::kv_driver_component_static!(
+        VirtualKVPermissions
+    ));
+
+    //--------------------------------------------------------------------------
+    // CRASH LOG
+    //--------------------------------------------------------------------------
+
+    // Kernel panics and process faults are recorded right after the KV store.
+    let crash_log_flash = components::flash::FlashUserComponent::new(mux_flash)
+        .finalize(components::flash_user_component_static!(CfiFlash));
+    let crash_log_readers = static_init!(
+        [ShortId; 1],
+        [ShortId::Fixed(NonZeroU32::new(CRASH_LOG_READER).unwrap())]
+    );
+    let crash_log = components::crash_log::CrashLogComponent::new(
+        core::slice::from_raw_parts(
+            pflash_start.add(APP_FLASH_SIZE + KV_FLASH_SIZE),
+            CRASH_LOG_FLASH_SIZE,
+        ),
+        crash_log_flash,
+        board_kernel,
+        capsules_extra::crash_log::DRIVER_NUM,
+        crash_log_readers,
+        create_capability!(capabilities::MemoryAllocationCapability),
+    )
+    .finalize(components::crash_log_component_static!(FlashUser));
+    qemu_rv32_virt_lib::set_crash_recorder(crash_log);
+    base_platform.process_console_set_crash_log(crash_log);
+
+    let crash_printer = components::process_printer::ProcessPrinterTextComponent::new()
+        .finalize(components::process_printer_text_component_static!());
+    let fault_policy = static_init!(
+        CrashRecordFaultPolicy<'static>,
+        CrashRecordFaultPolicy::new(crash_log, crash_printer, &FAULT_RESPONSE)
+    );
+
+    //--------------------------------------------------------------------------
+    // PROCESS LOADING
+    //--------------------------------------------------------------------------
+
+    // Create the credential checker.
+    let checking_policy = components::appid::checker_null::AppCheckerNullComponent::new()
+        .finalize(components::app_checker_null_component_static!());
+
+    // Create the AppID assigner.
+    let assigner = components::appid::assigner_tbf::AppIdAssignerTbfHeaderComponent::new()
+        .finalize(components::appid_assigner_tbf_header_component_static!());
+
+    // Create the process checking machine.
+    let checker = components::appid::checker::ProcessCheckerMachineComponent::new(checking_policy)
+        .finalize(components::process_checker_machine_component_static!());
+
+    let storage_permissions_policy =
+        components::storage_permissions::null::StoragePermissionsNullComponent::new().finalize(
+            components::storage_permissions_null_component_static!(
+                qemu_rv32_virt_lib::ChipHw,
+                kernel::process::ProcessStandardDebugFull,
+            ),
+        );
+
+    // These symbols are defined in the linker script.
+    extern "C" {
+        /// Beginning of the RAM region for app memory.
+        static mut _sappmem: u8;
+        /// End of the RAM region for app memory.
+        static _eappmem: u8;
+    }
+
+    let app_flash = core::slice::from_raw_parts(pflash_start, APP_FLASH_SIZE);
+    let app_memory = core::slice::from_raw_parts_mut(
+        core::ptr::addr_of_mut!(_sappmem),
+        core::ptr::addr_of!(_eappmem) as usize - core::ptr::addr_of!(_sappmem) as usize,
+    );
+
+    // Create and start the asynchronous process loader.
+    let loader = components::loader::sequential::ProcessLoaderSequentialComponent::new(
+        checker,
+        board_kernel,
+        chip,
+        fault_policy,
+        assigner,
+        storage_permissions_policy,
+        app_flash,
+        app_memory,
+        create_capability!(capabilities::ProcessManagementCapability),
+    )
+    .finalize(components::process_loader_sequential_component_static!(
+        qemu_rv32_virt_lib::ChipHw,
+        kernel::process::ProcessStandardDebugFull,
+        qemu_rv32_virt_lib::NUM_PROCS
+    ));
+
+    //--------------------------------------------------------------------------
+    // DYNAMIC APP LOADING
+    //--------------------------------------------------------------------------
+
+    let app_flash_user = components::flash::FlashUserComponent::new(mux_flash)
+        .finalize(components::flash_user_component_static!(CfiFlash));
+
+    // Create the dynamic binary flasher.
+    let dynamic_binary_storage =
+        components::dynamic_binary_storage::SequentialBinaryStorageComponent::new(
+            board_kernel,
+            app_flash_user,
+            loader,
+        )
+        .finalize(components::sequential_binary_storage_component_static!(
+            FlashUser,
+            qemu_rv32_virt_lib::ChipHw,
+            kernel::process::ProcessStandardDebugFull,
+        ));
+
+    // Create the dynamic app loader capsule.
+    let dynamic_app_loader = components::app_loader::AppLoaderComponent::new(
+        board_kernel,
+        capsules_extra::app_loader::DRIVER_NUM,
+        dynamic_binary_storage,
+        dynamic_binary_storage,
+        dynamic_binary_storage,
+        create_capability!(capabilities::MemoryAllocationCapability),
+    )
+    .finalize(components::app_loader_component_static!(
+        DynamicBinaryStorage,
+        DynamicBinaryStorage,
+        DynamicBinaryStorage,
+    ));
+
+    let platform = static_init!(
+        Platform,
+        Platform {
+            base: base_platform,
+            kv_driver,
+            dynamic_app_loader,
+            crash_log,
+        }
+    );
+    loader.set_client(platform);
+
+    // Start the process console:
+    let _ = platform.base.process_console_start();
+
+    board_kernel.kernel_loop(
+        platform,
+        chip,
+        Some(&platform.base.ipc),
+        &main_loop_capability,
+    );
+}
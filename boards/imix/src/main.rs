@@ -167,6 +167,7 @@ struct Imix {
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
     ninedof: &'static capsules_extra::ninedof::NineDof<'static>,
     udp_driver: &'static capsules_extra::net::udp::UDPDriver<'static>,
+    tcp_driver: &'static capsules_extra::net::tcp::TCPDriver<'static>,
     crc: &'static capsules_extra::crc::CrcDriver<'static, sam4l::crccu::Crccu<'static>>,
     usb_driver: &'static capsules_extra::usb::usb_user::UsbSyscallDriver<
         'static,
@@ -200,6 +201,7 @@ impl SyscallDriverLookup for Imix {
             capsules_extra::crc::DRIVER_NUM => f(Some(self.crc)),
             capsules_extra::usb::usb_user::DRIVER_NUM => f(Some(self.usb_driver)),
             capsules_extra::net::udp::DRIVER_NUM => f(Some(self.udp_driver)),
+            capsules_extra::net::tcp::DRIVER_NUM => f(Some(self.tcp_driver)),
             capsules_extra::nrf51822_serialization::DRIVER_NUM => f(Some(self.nrf51822)),
             capsules_extra::nonvolatile_storage_driver::DRIVER_NUM => {
                 f(Some(self.nonvolatile_storage))
@@ -642,7 +644,7 @@ unsafe fn start() -> (
     aes_mux.register();
     peripherals.aes.set_client(aes_mux);
 
-    let (_, mux_mac) = components::ieee802154::Ieee802154Component::new(
+    let (_, mux_mac, mac_device) = components::ieee802154::Ieee802154Component::new(
         board_kernel,
         capsules_extra::ieee802154::DRIVER_NUM,
         rf233,
@@ -690,6 +692,18 @@ unsafe fn start() -> (
         sam4l::flashcalw::FLASHCALW
     ));
 
+    // Secured 802.15.4 frames are only sent once the frame counter has been
+    // restored, so that counter values are never reused after a reboot.
+    kernel::storage_volume!(FRAME_COUNTER_VOLUME, 1);
+    let _ = mac_device.set_frame_counter_storage(
+        nonvolatile_storage,
+        core::ptr::addr_of!(FRAME_COUNTER_VOLUME) as usize,
+        static_init!(
+            [u8; capsules_extra::ieee802154::framer::FRAME_COUNTER_STORAGE_LEN],
+            [0; capsules_extra::ieee802154::framer::FRAME_COUNTER_STORAGE_LEN]
+        ),
+    );
+
     let local_ip_ifaces = static_init!(
         [IPAddr; 3],
         [
@@ -740,6 +754,25 @@ unsafe fn start() -> (
         UdpDriverCap
     ));
 
+    // The TCP stack uses its own 6LoWPAN and IP layers on the same MAC.
+    let tcp_driver = components::tcp::TCPDriverComponent::new(
+        board_kernel,
+        capsules_extra::net::tcp::DRIVER_NUM,
+        mux_mac,
+        DEFAULT_CTX_PREFIX_LEN,
+        DEFAULT_CTX_PREFIX,
+        DST_MAC_ADDR,
+        src_mac_from_serial_num,
+        local_ip_ifaces,
+        mux_alarm,
+        create_capability!(capabilities::NetworkCapabilityCreationCapability),
+        create_capability!(capabilities::MemoryAllocationCapability),
+    )
+    .finalize(components::tcp_driver_component_static!(
+        sam4l::ast::Ast,
+        Ieee802154MacDevice
+    ));
+
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(processes)
         .finalize(components::round_robin_component_static!(NUM_PROCS));
 
@@ -837,6 +870,7 @@ unsafe fn start() -> (
         ipc: kernel::ipc::IPC::new(board_kernel, kernel::ipc::DRIVER_NUM, &grant_cap),
         ninedof,
         udp_driver,
+        tcp_driver,
         usb_driver,
         nrf51822: nrf_serialization,
         nonvolatile_storage,
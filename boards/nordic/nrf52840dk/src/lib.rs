@@ -189,7 +189,8 @@ kernel::define_capability_type!(ProcessConsoleCap:
     kernel::capabilities::ProcessManagementCapability,
     kernel::capabilities::ProcessStartCapability
 );
-type ProcessConsoleDriver =
+/// The process console.
+pub type ProcessConsoleDriver =
     components::process_console::ProcessConsoleComponentType<AlarmHw, ProcessConsoleCap>;
 type TemperatureDriver = components::temperature::TemperatureComponentType<TemperatureHw>;
 type IpcDriver = kernel::ipc::IPC<{ NUM_PROCS as u8 }>;
@@ -220,13 +221,20 @@ pub type Eui64Driver = components::eui64::Eui64ComponentType;
 /// Userspace UDP driver.
 pub type UdpDriver = components::udp_driver::UDPDriverComponentType;
 
+/// Userspace Thread driver.
+pub type ThreadDriver = capsules_extra::net::thread::driver::ThreadNetworkDriver<
+    'static,
+    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, AlarmHw>,
+>;
+
 type SchedulerInUse = components::sched::round_robin::RoundRobinComponentType;
 
 /// Supported drivers by the platform
 pub struct Platform {
     ble_radio: &'static BleDriver,
     button: &'static ButtonDriver,
-    pconsole: &'static ProcessConsoleDriver,
+    /// The process console, for boards to add commands to.
+    pub pconsole: &'static ProcessConsoleDriver,
     console: &'static capsules_core::console::Console<'static>,
     gpio: &'static GpioDriver,
     led: &'static LedDriver,
@@ -301,7 +309,7 @@ impl KernelResources<ChipHw> for Platform {
     }
 }
 
-/// Create the capsules needed for the in-kernel UDP and 15.4 stack.
+/// Create the capsules needed for the in-kernel UDP, Thread and 15.4 stack.
 pub unsafe fn ieee802154_udp(
     board_kernel: &'static kernel::Kernel,
     nrf52840_peripherals: &'static Nrf52840DefaultPeripherals<'static>,
@@ -310,6 +318,7 @@ pub unsafe fn ieee802154_udp(
     &'static Eui64Driver,
     &'static Ieee802154Driver,
     &'static UdpDriver,
+    &'static ThreadDriver,
 ) {
     //--------------------------------------------------------------------------
     // AES
@@ -331,7 +340,7 @@ pub unsafe fn ieee802154_udp(
     let eui64_driver = components::eui64::Eui64Component::new(u64::from_le_bytes(device_id))
         .finalize(components::eui64_component_static!());
 
-    let (ieee802154_driver, mux_mac) = components::ieee802154::Ieee802154Component::new(
+    let (ieee802154_driver, mux_mac, _) = components::ieee802154::Ieee802154Component::new(
         board_kernel,
         capsules_extra::ieee802154::DRIVER_NUM,
         &nrf52840_peripherals.ieee802154_radio,
@@ -395,7 +404,35 @@ pub unsafe fn ieee802154_udp(
         UdpDriverCap
     ));
 
-    (eui64_driver, ieee802154_driver, udp_driver)
+    //--------------------------------------------------------------------------
+    // THREAD
+    //--------------------------------------------------------------------------
+
+    kernel::create_typed_capability!(thread_driver_cap, ThreadDriverCap: kernel::capabilities::UdpDriverCapability);
+    let thread_driver = components::thread_network::ThreadNetworkComponent::new(
+        board_kernel,
+        capsules_extra::net::thread::driver::DRIVER_NUM,
+        udp_send_mux,
+        udp_recv_mux,
+        udp_port_table,
+        aes_mux,
+        device_id,
+        mux_alarm,
+        thread_driver_cap,
+        create_capability!(capabilities::MemoryAllocationCapability),
+        create_capability!(capabilities::NetworkCapabilityCreationCapability),
+    )
+    .finalize(components::thread_network_component_static!(
+        AlarmHw,
+        AesHw,
+        ThreadDriverCap
+    ));
+
+    // Incoming frames secured with the Thread MAC key
+    ieee802154_driver.set_key_procedure(thread_driver);
+    ieee802154_driver.set_device_procedure(thread_driver);
+
+    (eui64_driver, ieee802154_driver, udp_driver, thread_driver)
 }
 
 /// This is in a separate, inline(never) function so that its stack frame is
@@ -808,6 +845,10 @@ pub unsafe fn start_no_pconsole() -> (
         ),
     );
 
+    // Show the wear of the TicKV flash with the `kvhealth` console command.
+    kernel::hil::kv::KVHealth::set_health_client(tickv_kv_store, pconsole);
+    pconsole.set_kv_health(tickv_kv_store);
+
     let kv_store_permissions = components::kv::KVStorePermissionsComponent::new(tickv_kv_store)
         .finalize(components::kv_store_permissions_component_static!(
             TicKVKVStore
@@ -985,3 +1026,10 @@ pub unsafe fn start() -> (
     let _ = platform.pconsole.start();
     (kernel, platform, chip, peripherals, mux_alarm)
 }
+
+/// Save a crash record with `recorder` when the kernel panics.
+pub fn set_crash_recorder(recorder: &'static dyn kernel::debug::CrashRecorder) {
+    PANIC_RESOURCES.get().map(|resources| {
+        resources.crash_recorder.put(recorder);
+    });
+}
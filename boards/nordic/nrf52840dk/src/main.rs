@@ -8,20 +8,33 @@
 #![no_main]
 #![deny(missing_docs)]
 
+use capsules_core::virtualizers::virtual_flash::FlashUser;
+use capsules_extra::nonvolatile_to_pages::NonvolatileToPages;
+use capsules_system::process_policies::CrashRecordFaultPolicy;
+use kernel::component::Component;
 use kernel::debug;
+use kernel::hil;
 use kernel::platform::{KernelResources, SyscallDriverLookup};
-use kernel::{capabilities, create_capability};
+use kernel::process::ProcessCheckpointer;
+use kernel::{capabilities, create_capability, static_init};
 
 // State for loading and holding applications.
 // How should the kernel respond when a process faults.
 const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
     capsules_system::process_policies::PanicFaultPolicy {};
 
+kernel::define_capability_type!(CheckpointCap: capabilities::ProcessManagementCapability);
+
+/// Size of the snapshot of each process saved with the `checkpoint` console
+/// command. Processes using more RAM cannot be checkpointed.
+const CHECKPOINT_SLOT_SIZE: usize = 8192;
+
 struct Platform {
     base: nrf52840dk_lib::Platform,
     eui64_driver: &'static nrf52840dk_lib::Eui64Driver,
     ieee802154_driver: &'static nrf52840dk_lib::Ieee802154Driver,
     udp_driver: &'static nrf52840dk_lib::UdpDriver,
+    thread_driver: &'static nrf52840dk_lib::ThreadDriver,
 }
 
 impl SyscallDriverLookup for Platform {
@@ -33,6 +46,7 @@ impl SyscallDriverLookup for Platform {
             capsules_extra::eui64::DRIVER_NUM => f(Some(self.eui64_driver)),
             capsules_extra::net::udp::DRIVER_NUM => f(Some(self.udp_driver)),
             capsules_extra::ieee802154::DRIVER_NUM => f(Some(self.ieee802154_driver)),
+            capsules_extra::net::thread::driver::DRIVER_NUM => f(Some(self.thread_driver)),
             _ => self.base.with_driver(driver_num, f),
         }
     }
@@ -80,17 +94,113 @@ pub unsafe fn main() {
         nrf52840dk_lib::start();
 
     //--------------------------------------------------------------------------
-    // IEEE 802.15.4 and UDP
+    // IEEE 802.15.4, UDP and THREAD
     //--------------------------------------------------------------------------
 
-    let (eui64_driver, ieee802154_driver, udp_driver) =
+    let (eui64_driver, ieee802154_driver, udp_driver, thread_driver) =
         nrf52840dk_lib::ieee802154_udp(board_kernel, default_peripherals, mux_alarm);
 
+    //--------------------------------------------------------------------------
+    // PROCESS CHECKPOINTS
+    //--------------------------------------------------------------------------
+
+    // Processes are checkpointed with the `checkpoint` console command, into
+    // one slot of this volume each, and restored from it on boot.
+    kernel::storage_volume!(CHECKPOINTS, 32);
+
+    let mux_flash = components::flash::FlashMuxComponent::new(&default_peripherals.nrf52.nvmc)
+        .finalize(components::flash_mux_component_static!(
+            nrf52840::nvmc::Nvmc
+        ));
+    let checkpoint_flash = components::flash::FlashUserComponent::new(mux_flash).finalize(
+        components::flash_user_component_static!(nrf52840::nvmc::Nvmc),
+    );
+    let checkpoint_page = static_init!(nrf52840::nvmc::NrfPage, nrf52840::nvmc::NrfPage::default());
+    let checkpoint_storage = static_init!(
+        NonvolatileToPages<'static, FlashUser<'static, nrf52840::nvmc::Nvmc>>,
+        NonvolatileToPages::new(checkpoint_flash, checkpoint_page)
+    );
+    hil::flash::HasClient::set_client(checkpoint_flash, checkpoint_storage);
+
+    let process_checkpoint = components::process_checkpoint::ProcessCheckpointComponent::new(
+        board_kernel,
+        checkpoint_storage,
+        &CHECKPOINTS,
+        kernel::mint_defined_capability!(CheckpointCap),
+    )
+    .finalize(components::process_checkpoint_component_static!(
+        CheckpointCap,
+        CHECKPOINT_SLOT_SIZE
+    ));
+    process_checkpoint.set_client(base_platform.pconsole);
+    base_platform.pconsole.set_checkpointer(process_checkpoint);
+
+    //--------------------------------------------------------------------------
+    // CRASH LOG
+    //--------------------------------------------------------------------------
+
+    // Kernel panics and process faults are recorded in this volume, and shown
+    // with the `crashlog` console command. Processes on this board do not have
+    // fixed ShortIds, so none of them is allowed to read the log.
+    kernel::storage_volume!(CRASH_LOG, 8);
+
+    let crash_log_flash = components::flash::FlashUserComponent::new(mux_flash).finalize(
+        components::flash_user_component_static!(nrf52840::nvmc::Nvmc),
+    );
+    let crash_log = components::crash_log::CrashLogComponent::new(
+        &CRASH_LOG,
+        crash_log_flash,
+        board_kernel,
+        capsules_extra::crash_log::DRIVER_NUM,
+        &[],
+        create_capability!(capabilities::MemoryAllocationCapability),
+    )
+    .finalize(components::crash_log_component_static!(
+        FlashUser<'static, nrf52840::nvmc::Nvmc>
+    ));
+    nrf52840dk_lib::set_crash_recorder(crash_log);
+    base_platform.pconsole.set_crash_log(crash_log);
+
+    //--------------------------------------------------------------------------
+    // THREAD FRAME COUNTER
+    //--------------------------------------------------------------------------
+
+    // MLE messages are only sent once the frame counter has been restored, so
+    // that counter values are never reused after a reboot.
+    kernel::storage_volume!(MLE_FRAME_COUNTER, 1);
+
+    let mle_frame_counter_flash = components::flash::FlashUserComponent::new(mux_flash).finalize(
+        components::flash_user_component_static!(nrf52840::nvmc::Nvmc),
+    );
+    let mle_frame_counter_page =
+        static_init!(nrf52840::nvmc::NrfPage, nrf52840::nvmc::NrfPage::default());
+    let mle_frame_counter_storage = static_init!(
+        NonvolatileToPages<'static, FlashUser<'static, nrf52840::nvmc::Nvmc>>,
+        NonvolatileToPages::new(mle_frame_counter_flash, mle_frame_counter_page)
+    );
+    hil::flash::HasClient::set_client(mle_frame_counter_flash, mle_frame_counter_storage);
+    let _ = thread_driver.set_frame_counter_storage(
+        mle_frame_counter_storage,
+        core::ptr::addr_of!(MLE_FRAME_COUNTER) as usize,
+        static_init!(
+            [u8; capsules_extra::ieee802154::framer::FRAME_COUNTER_STORAGE_LEN],
+            [0; capsules_extra::ieee802154::framer::FRAME_COUNTER_STORAGE_LEN]
+        ),
+    );
+
+    let crash_printer = components::process_printer::ProcessPrinterTextComponent::new()
+        .finalize(components::process_printer_text_component_static!());
+    let fault_policy = static_init!(
+        CrashRecordFaultPolicy<'static>,
+        CrashRecordFaultPolicy::new(crash_log, crash_printer, &FAULT_RESPONSE)
+    );
+
     let platform = Platform {
         base: base_platform,
         eui64_driver,
         ieee802154_driver,
         udp_driver,
+        thread_driver,
     };
 
     // These symbols are defined in the linker script.
@@ -118,13 +228,14 @@ pub unsafe fn main() {
             core::ptr::addr_of_mut!(_sappmem),
             core::ptr::addr_of!(_eappmem) as usize - core::ptr::addr_of!(_sappmem) as usize,
         ),
-        &FAULT_RESPONSE,
+        fault_policy,
         &process_management_capability,
     )
     .unwrap_or_else(|err| {
         debug!("Error loading processes!");
         debug!("{:?}", err);
     });
+    process_checkpoint.restore();
 
     let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
     board_kernel.kernel_loop(
@@ -15,6 +15,7 @@ use capsules_core::rng::RngDriver;
 use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use components::console::ConsoleComponent;
 use components::debug_writer::DebugWriterComponent;
+use core::num::NonZeroU32;
 use core::ptr;
 use kernel::capabilities;
 use kernel::component::Component;
@@ -23,8 +24,10 @@ use kernel::debug::PanicResources;
 use kernel::deferred_call::DeferredCallClient;
 use kernel::hil;
 use kernel::hil::keyboard::Keyboard;
+use kernel::hil::time::{Alarm, AlarmClient, Frequency, Ticks, Ticks32, Time};
 use kernel::ipc::IPC;
 use kernel::platform::chip::InterruptService;
+use kernel::platform::scheduler_timer::SchedulerTimer;
 use kernel::platform::{KernelResources, SyscallDriverLookup};
 use kernel::syscall::SyscallDriver;
 use kernel::utilities::cells::OptionalCell;
@@ -36,8 +39,10 @@ use virtio_pci_x86::VirtIOPCIDevice;
 use x86::dma_fence::X86DmaFence;
 use x86::registers::bits32::paging::{PD, PDEntry, PT, PTEntry};
 use x86::registers::irq;
-use x86_q35::pit::{Pit, RELOAD_1KHZ};
-use x86_q35::{Pc, PcDefaultPeripherals};
+use x86_q35::hpet::{Hpet, HpetFreq};
+use x86_q35::lapic::LapicTimer;
+use x86_q35::pit::{Pit, PitFreq, RELOAD_1KHZ};
+use x86_q35::{InterruptController, Pc, PcDefaultPeripherals};
 
 mod multiboot;
 use multiboot::MultibootV1Header;
@@ -57,10 +62,12 @@ static MULTIBOOT_V1_HEADER: MultibootV1Header = MultibootV1Header::new(0);
 
 const NUM_PROCS: usize = 4;
 
+/// Interrupt controller to use. The chip falls back to the 8259 PIC if the processor has no Local
+/// APIC, which is the case for QEMU's `-cpu 486` unless the `apic` feature is requested.
+const INTERRUPT_CONTROLLER: InterruptController = InterruptController::Apic;
+
 type ChipHw = Pc<'static, PcDefaultPeripherals, VirtioDevices>;
-type AlarmHw = Pit<'static, RELOAD_1KHZ>;
-type SchedulerTimerHw =
-    components::virtual_scheduler_timer::VirtualSchedulerTimerComponentType<AlarmHw>;
+
 type ProcessPrinterInUse = capsules_system::process_printer::ProcessPrinterText;
 
 /// Resources for when a board panics used by io.rs.
@@ -91,10 +98,24 @@ pub static mut PAGE_DIR: PD = [PDEntry(0); 1024];
 #[cfg_attr(target_os = "none", link_section = ".pte")]
 pub static mut PAGE_TABLE: PT = [PTEntry(0); 1024];
 
+/// Returns the I/O APIC input to which the legacy interrupt pin of `dev` is connected.
+///
+/// On QEMU's Q35 machine type, the interrupt pins of devices on the root bus are rotated according
+/// to their slot across PIRQ E-H, which are connected to GSI 20-23. The interrupt line reported in
+/// the configuration space is the one used with the 8259 PIC instead.
+fn q35_pci_gsi(dev: &pci_x86::Device) -> Option<u32> {
+    if dev.bdf().bus() != 0 {
+        return None;
+    }
+
+    let pin = dev.int_pin()?;
+    Some(20 + (u32::from(dev.bdf().device()) + u32::from(pin)) % 4)
+}
+
 /// Initializes a Virtio transport driver for the given PCI device.
 ///
-/// Disables MSI/MSI-X interrupts for the device since the x86_q35 chip uses the 8259 PIC for
-/// interrupt management.
+/// Disables MSI/MSI-X interrupts for the device since the x86_q35 chip only supports legacy
+/// interrupt pins, routed through `interrupt_controller`.
 ///
 /// On success, returns a tuple containing the interrupt line number assigned to this device as well
 /// as a fully initialized Virtio PCI transport driver.
@@ -105,10 +126,14 @@ pub static mut PAGE_TABLE: PT = [PTEntry(0); 1024];
 fn init_virtio_dev(
     dev: pci_x86::Device,
     dev_type: VirtIODeviceType,
-) -> Option<(u8, VirtIOPCIDevice)> {
+    interrupt_controller: InterruptController,
+) -> Option<(u32, VirtIOPCIDevice)> {
     use pci_x86::cap::Cap;
 
-    let int_line = dev.int_line()?;
+    let int_line = match interrupt_controller {
+        InterruptController::Pic => u32::from(dev.int_line()?),
+        InterruptController::Apic => q35_pci_gsi(&dev)?,
+    };
 
     for cap in dev.capabilities() {
         match cap {
@@ -135,7 +160,8 @@ kernel::define_capability_type!(ProcessConsoleCap:
 /// Provides interrupt servicing logic for Virtio devices which may or may not be present at
 /// runtime.
 struct VirtioDevices {
-    rng: OptionalCell<(u8, &'static VirtIOPCIDevice)>,
+    rng: OptionalCell<(u32, &'static VirtIOPCIDevice)>,
+    blk: OptionalCell<(u32, &'static VirtIOPCIDevice)>,
 }
 
 impl InterruptService for VirtioDevices {
@@ -143,7 +169,14 @@ impl InterruptService for VirtioDevices {
         let mut handled = false;
 
         self.rng.map(|(int_line, dev)| {
-            if interrupt == (int_line as u32) {
+            if interrupt == int_line {
+                dev.handle_interrupt();
+                handled = true;
+            }
+        });
+
+        self.blk.map(|(int_line, dev)| {
+            if interrupt == int_line {
                 dev.handle_interrupt();
                 handled = true;
             }
@@ -153,11 +186,176 @@ impl InterruptService for VirtioDevices {
     }
 }
 
+/// Hardware alarm, which depends on whether the machine has an HPET
+///
+/// Both variants count in ticks of the HPET main counter. The PIT only advances once per interrupt,
+/// so its ticks are scaled up, and its alarms are rounded up to the next interrupt.
+pub enum AlarmHw {
+    /// HPET, used when present
+    Hpet(&'static Hpet<'static>),
+
+    /// Legacy PIT, used otherwise
+    Pit(&'static Pit<'static, RELOAD_1KHZ>),
+}
+
+impl AlarmHw {
+    /// Selects the HPET if it is present, otherwise the PIT.
+    ///
+    /// This starts the HPET main counter, but interrupts are only routed by [`AlarmHw::start`].
+    fn new(chip: &ChipHw) -> Self {
+        if chip.hpet.enable().is_ok() {
+            Self::Hpet(chip.hpet)
+        } else {
+            Self::Pit(chip.pit)
+        }
+    }
+
+    /// Starts generating timer interrupts.
+    fn start(&self) {
+        match self {
+            // The HPET was found by `AlarmHw::new`, so this cannot fail
+            Self::Hpet(hpet) => {
+                let _ = hpet.start();
+            }
+            Self::Pit(pit) => pit.start(),
+        }
+    }
+
+    /// Number of HPET ticks in a PIT tick
+    fn pit_scale() -> u32 {
+        HpetFreq::frequency() / PitFreq::<RELOAD_1KHZ>::frequency()
+    }
+}
+
+impl Time for AlarmHw {
+    type Frequency = HpetFreq;
+    type Ticks = Ticks32;
+
+    fn now(&self) -> Ticks32 {
+        match self {
+            Self::Hpet(hpet) => hpet.now(),
+            Self::Pit(pit) => pit.now().into_u32().wrapping_mul(Self::pit_scale()).into(),
+        }
+    }
+}
+
+impl Alarm<'static> for AlarmHw {
+    fn set_alarm_client(&self, client: &'static dyn AlarmClient) {
+        match self {
+            Self::Hpet(hpet) => hpet.set_alarm_client(client),
+            Self::Pit(pit) => pit.set_alarm_client(client),
+        }
+    }
+
+    fn set_alarm(&self, reference: Ticks32, dt: Ticks32) {
+        match self {
+            Self::Hpet(hpet) => hpet.set_alarm(reference, dt),
+            Self::Pit(pit) => {
+                let now = self.now();
+                let expire = reference.wrapping_add(dt);
+                let remaining = if now.within_range(reference, expire) {
+                    expire.wrapping_sub(now).into_u32()
+                } else {
+                    0
+                };
+                pit.set_alarm(
+                    pit.now(),
+                    remaining.div_ceil(Self::pit_scale()).max(1).into(),
+                );
+            }
+        }
+    }
+
+    fn get_alarm(&self) -> Ticks32 {
+        match self {
+            Self::Hpet(hpet) => hpet.get_alarm(),
+            Self::Pit(pit) => pit
+                .get_alarm()
+                .into_u32()
+                .wrapping_mul(Self::pit_scale())
+                .into(),
+        }
+    }
+
+    fn disarm(&self) -> Result<(), kernel::ErrorCode> {
+        match self {
+            Self::Hpet(hpet) => hpet.disarm(),
+            Self::Pit(pit) => pit.disarm(),
+        }
+    }
+
+    fn is_armed(&self) -> bool {
+        match self {
+            Self::Hpet(hpet) => hpet.is_armed(),
+            Self::Pit(pit) => pit.is_armed(),
+        }
+    }
+
+    fn minimum_dt(&self) -> Ticks32 {
+        match self {
+            Self::Hpet(hpet) => hpet.minimum_dt(),
+            Self::Pit(pit) => pit
+                .minimum_dt()
+                .into_u32()
+                .wrapping_mul(Self::pit_scale())
+                .into(),
+        }
+    }
+}
+
+/// Scheduler timer, which depends on the interrupt controller in use
+pub enum SchedulerTimerHw {
+    /// Local APIC timer, used with the APIC
+    Lapic(&'static LapicTimer),
+
+    /// Virtual alarm, used with the 8259 PIC
+    Alarm(
+        &'static components::virtual_scheduler_timer::VirtualSchedulerTimerComponentType<AlarmHw>,
+    ),
+}
+
+impl SchedulerTimer for SchedulerTimerHw {
+    fn start(&self, us: NonZeroU32) {
+        match self {
+            Self::Lapic(timer) => timer.start(us),
+            Self::Alarm(timer) => timer.start(us),
+        }
+    }
+
+    fn reset(&self) {
+        match self {
+            Self::Lapic(timer) => timer.reset(),
+            Self::Alarm(timer) => timer.reset(),
+        }
+    }
+
+    fn arm(&self) {
+        match self {
+            Self::Lapic(timer) => timer.arm(),
+            Self::Alarm(timer) => timer.arm(),
+        }
+    }
+
+    fn disarm(&self) {
+        match self {
+            Self::Lapic(timer) => timer.disarm(),
+            Self::Alarm(timer) => timer.disarm(),
+        }
+    }
+
+    fn get_remaining_us(&self) -> Option<NonZeroU32> {
+        match self {
+            Self::Lapic(timer) => timer.get_remaining_us(),
+            Self::Alarm(timer) => timer.get_remaining_us(),
+        }
+    }
+}
+
 pub struct QemuI386Q35Platform {
     pconsole: &'static capsules_core::process_console::ProcessConsole<
         'static,
         { capsules_core::process_console::DEFAULT_COMMAND_HISTORY_LEN },
-        VirtualMuxAlarm<'static, Pit<'static, RELOAD_1KHZ>>,
+        VirtualMuxAlarm<'static, AlarmHw>,
         ProcessConsoleCap,
     >,
     console: &'static Console<'static>,
@@ -165,14 +363,13 @@ pub struct QemuI386Q35Platform {
         'static,
         capsules_core::virtualizers::virtual_uart::UartDevice<'static>,
     >,
-    alarm: &'static capsules_core::alarm::AlarmDriver<
-        'static,
-        VirtualMuxAlarm<'static, Pit<'static, RELOAD_1KHZ>>,
-    >,
+    alarm: &'static capsules_core::alarm::AlarmDriver<'static, VirtualMuxAlarm<'static, AlarmHw>>,
     ipc: IPC<{ NUM_PROCS as u8 }>,
     scheduler: &'static SchedulerInUse,
     scheduler_timer: &'static SchedulerTimerHw,
     rng: Option<&'static RngDriver<'static, VirtIORng<'static, 'static, X86DmaFence>>>,
+    blk_storage:
+        Option<&'static capsules_extra::nonvolatile_storage_driver::NonvolatileStorage<'static>>,
 }
 
 impl SyscallDriverLookup for QemuI386Q35Platform {
@@ -191,6 +388,13 @@ impl SyscallDriverLookup for QemuI386Q35Platform {
                     f(None)
                 }
             }
+            capsules_extra::nonvolatile_storage_driver::DRIVER_NUM => {
+                if let Some(blk_storage) = self.blk_storage {
+                    f(Some(blk_storage))
+                } else {
+                    f(None)
+                }
+            }
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
         }
@@ -281,6 +485,7 @@ unsafe extern "cdecl" fn main() {
                     kernel::static_buf!(x86_q35::keyboard::Ps2Keyboard<'static>),
                 ),
                 &mut *ptr::addr_of_mut!(PAGE_DIR),
+                INTERRUPT_CONTROLLER,
             )
         )
     };
@@ -289,6 +494,7 @@ unsafe extern "cdecl" fn main() {
         VirtioDevices,
         VirtioDevices {
             rng: OptionalCell::empty(),
+            blk: OptionalCell::empty(),
         }
     );
     let chip: &'static Pc<PcDefaultPeripherals, VirtioDevices> = unsafe {
@@ -346,24 +552,19 @@ unsafe extern "cdecl" fn main() {
 
     // Create a shared virtualization mux layer on top of a single hardware
     // alarm.
-    let mux_alarm = static_init!(
-        MuxAlarm<'static, Pit<'static, RELOAD_1KHZ>>,
-        MuxAlarm::new(chip.pit),
-    );
-    hil::time::Alarm::set_alarm_client(chip.pit, mux_alarm);
+    let alarm_hw = static_init!(AlarmHw, AlarmHw::new(chip));
+    let mux_alarm = static_init!(MuxAlarm<'static, AlarmHw>, MuxAlarm::new(alarm_hw));
+    alarm_hw.set_alarm_client(mux_alarm);
 
     // Virtual alarm and driver for userspace
     let virtual_alarm_user = static_init!(
-        VirtualMuxAlarm<'static, Pit<'static, RELOAD_1KHZ>>,
+        VirtualMuxAlarm<'static, AlarmHw>,
         VirtualMuxAlarm::new(mux_alarm)
     );
     virtual_alarm_user.setup();
 
     let alarm = static_init!(
-        capsules_core::alarm::AlarmDriver<
-            'static,
-            VirtualMuxAlarm<'static, Pit<'static, RELOAD_1KHZ>>,
-        >,
+        capsules_core::alarm::AlarmDriver<'static, VirtualMuxAlarm<'static, AlarmHw>>,
         capsules_core::alarm::AlarmDriver::new(
             virtual_alarm_user,
             board_kernel.create_grant(capsules_core::alarm::DRIVER_NUM, &memory_allocation_cap)
@@ -378,6 +579,7 @@ unsafe extern "cdecl" fn main() {
     // Enumerate the PCI bus to find supported Virtio devices. If there are two instances of a
     // supported peripheral, we use the first one we encounter.
     let mut virtio_rng_dev = None;
+    let mut virtio_blk_dev = None;
     for dev in pci_x86::iter() {
         use virtio::devices::VirtIODeviceType;
         use virtio_pci_x86::{DEVICE_ID_BASE, VENDOR_ID};
@@ -404,6 +606,13 @@ unsafe extern "cdecl" fn main() {
             }
 
             virtio_rng_dev = Some(dev);
+        } else if dev_type == VirtIODeviceType::BlockDevice {
+            // Only consider first block device found
+            if virtio_blk_dev.is_some() {
+                continue;
+            }
+
+            virtio_blk_dev = Some(dev);
         }
     }
 
@@ -418,8 +627,12 @@ unsafe extern "cdecl" fn main() {
         use virtio::transports::VirtIOTransport;
 
         // Initialize PCI transport driver
-        let (int_line, transport) = init_virtio_dev(rng_dev, VirtIODeviceType::EntropySource)
-            .expect("virtio pci init failed");
+        let (int_line, transport) = init_virtio_dev(
+            rng_dev,
+            VirtIODeviceType::EntropySource,
+            default_peripherals.interrupt_controller(),
+        )
+        .expect("virtio pci init failed");
         let transport = static_init!(VirtIOPCIDevice, transport);
 
         // EntropySource requires a single Virtqueue for retrieved entropy
@@ -457,10 +670,95 @@ unsafe extern "cdecl" fn main() {
         None
     };
 
+    // If there is a VirtIO BlockDevice present, use the VirtIOBlk driver and
+    // expose the whole disk to userspace through the NonvolatileStorage
+    // driver.
+    let blk_storage: Option<
+        &'static capsules_extra::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+    > = if let Some(blk_dev) = virtio_blk_dev {
+        use capsules_extra::nonvolatile_storage_driver::NonvolatileStorage;
+        use kernel::hil::nonvolatile_storage::NonvolatileStorage as _;
+        use virtio::devices::virtio_blk::{REQUEST_HEADER_LEN, SECTOR_SIZE, VirtIOBlk};
+        use virtio::queues::Virtqueue;
+        use virtio::queues::split_queue::{
+            SplitVirtqueue, VirtqueueAvailableRing, VirtqueueDescriptors, VirtqueueUsedRing,
+        };
+        use virtio::transports::VirtIOTransport;
+
+        // Initialize PCI transport driver
+        let (int_line, transport) = init_virtio_dev(
+            blk_dev,
+            VirtIODeviceType::BlockDevice,
+            default_peripherals.interrupt_controller(),
+        )
+        .expect("virtio pci init failed");
+        let transport = static_init!(VirtIOPCIDevice, transport);
+
+        // Each request is a chain of 3 descriptors: the request header, the
+        // sector data and the status byte written by the device.
+        let descriptors = static_init!(VirtqueueDescriptors<3>, VirtqueueDescriptors::default(),);
+        let available_ring =
+            static_init!(VirtqueueAvailableRing<3>, VirtqueueAvailableRing::default(),);
+        let used_ring = static_init!(VirtqueueUsedRing<3>, VirtqueueUsedRing::default(),);
+        let queue = static_init!(
+            SplitVirtqueue<3, X86DmaFence>,
+            SplitVirtqueue::new(descriptors, available_ring, used_ring, dma_fence),
+        );
+        queue.set_transport(transport);
+
+        let header_buf = static_init!([u8; REQUEST_HEADER_LEN], [0; REQUEST_HEADER_LEN]);
+        let sector_buf = static_init!([u8; SECTOR_SIZE], [0; SECTOR_SIZE]);
+        let status_buf = static_init!([u8; 1], [0; 1]);
+
+        // VirtIO BlockDevice driver instantiation
+        let blk = static_init!(
+            VirtIOBlk<X86DmaFence>,
+            VirtIOBlk::new(queue, transport, header_buf, sector_buf, status_buf).unwrap(),
+        );
+        DeferredCallClient::register(blk);
+        queue.set_client(blk);
+
+        // Register the queue and driver with the transport, so interrupts
+        // are routed properly. This also reads the disk capacity.
+        let queues = static_init!([&'static dyn Virtqueue; 1], [queue; 1]);
+        transport.initialize(blk, queues).unwrap();
+
+        // Device is successfully initialized, register it with the VirtioDevices struct so that
+        // interrupts are routed properly
+        virtio_devs.blk.set((int_line, transport));
+
+        // Instantiate the userspace nonvolatile storage driver over the
+        // whole disk:
+        let nonvolatile_storage_buffer = static_init!(
+            [u8; capsules_extra::nonvolatile_storage_driver::BUF_LEN],
+            [0; capsules_extra::nonvolatile_storage_driver::BUF_LEN],
+        );
+        let nonvolatile_storage = static_init!(
+            NonvolatileStorage<'static>,
+            NonvolatileStorage::new(
+                blk,
+                board_kernel.create_grant(
+                    capsules_extra::nonvolatile_storage_driver::DRIVER_NUM,
+                    &memory_allocation_cap
+                ),
+                0,
+                blk.capacity(),
+                0,
+                0,
+                nonvolatile_storage_buffer,
+            ),
+        );
+        blk.set_client(nonvolatile_storage);
+
+        Some(nonvolatile_storage as &'static NonvolatileStorage<'static>)
+    } else {
+        None
+    };
+
     // ---------- INITIALIZE CHIP, ENABLE INTERRUPTS ---------
 
-    // PIT interrupts need to be started manually
-    chip.pit.start();
+    // Timer interrupts need to be started manually. The HPET replaces the PIT once started.
+    alarm_hw.start();
 
     // Enable interrupts after all drivers are initialized
     irq::enable();
@@ -496,7 +794,7 @@ unsafe extern "cdecl" fn main() {
         process_console_cap,
     )
     .finalize(components::process_console_component_static!(
-        Pit<'static, RELOAD_1KHZ>,
+        AlarmHw,
         ProcessConsoleCap,
     ));
 
@@ -542,11 +840,17 @@ unsafe extern "cdecl" fn main() {
     let scheduler = components::sched::cooperative::CooperativeComponent::new(processes)
         .finalize(components::cooperative_component_static!(NUM_PROCS));
 
-    let scheduler_timer =
-        components::virtual_scheduler_timer::VirtualSchedulerTimerComponent::new(mux_alarm)
-            .finalize(components::virtual_scheduler_timer_component_static!(
-                AlarmHw
-            ));
+    // The Local APIC timer does not need to be virtualized, so use it when available
+    let scheduler_timer = match default_peripherals.interrupt_controller() {
+        InterruptController::Apic => SchedulerTimerHw::Lapic(chip.lapic_timer),
+        InterruptController::Pic => SchedulerTimerHw::Alarm(
+            components::virtual_scheduler_timer::VirtualSchedulerTimerComponent::new(mux_alarm)
+                .finalize(components::virtual_scheduler_timer_component_static!(
+                    AlarmHw
+                )),
+        ),
+    };
+    let scheduler_timer = static_init!(SchedulerTimerHw, scheduler_timer);
 
     let platform = static_init!(
         QemuI386Q35Platform,
@@ -558,6 +862,7 @@ unsafe extern "cdecl" fn main() {
             scheduler,
             scheduler_timer,
             rng: rng_driver,
+            blk_storage,
             ipc: kernel::ipc::IPC::new(
                 board_kernel,
                 kernel::ipc::DRIVER_NUM,
@@ -572,6 +877,10 @@ unsafe extern "cdecl" fn main() {
     // Attach the keyboard button press callback to ourself.
     default_peripherals.keyboard.set_client(platform);
 
+    debug!(
+        "Using {:?} interrupt controller.",
+        default_peripherals.interrupt_controller()
+    );
     debug!("QEMU i486 \"Q35\" machine, initialization complete.");
     debug!("Entering main loop.");
 
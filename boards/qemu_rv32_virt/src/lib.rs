@@ -43,6 +43,7 @@ pub type ScreenHw = qemu_rv32_virt_chip::virtio::devices::virtio_gpu::VirtIOGPU<
 >;
 
 type AlarmHw = qemu_rv32_virt_chip::chip::QemuRv32VirtClint<'static>;
+type RtcHw = qemu_rv32_virt_chip::chip::QemuRv32VirtRtc<'static>;
 type SchedulerTimerHw =
     components::virtual_scheduler_timer::VirtualSchedulerTimerComponentType<AlarmHw>;
 type SchedulerInUse = components::sched::cooperative::CooperativeComponentType;
@@ -79,6 +80,7 @@ pub struct QemuRv32VirtPlatform {
         'static,
         VirtualMuxAlarm<'static, qemu_rv32_virt_chip::chip::QemuRv32VirtClint<'static>>,
     >,
+    date_time: &'static capsules_extra::date_time::DateTimeCapsule<'static, RtcHw>,
     pub ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
     scheduler: &'static SchedulerInUse,
     scheduler_timer: &'static SchedulerTimerHw,
@@ -104,12 +106,19 @@ pub struct QemuRv32VirtPlatform {
             RiscvCoherentDmaFence,
         >,
     >,
+    virtio_blk_storage:
+        Option<&'static capsules_extra::nonvolatile_storage_driver::NonvolatileStorage<'static>>,
 }
 
 impl QemuRv32VirtPlatform {
     pub fn process_console_start(&self) -> Result<(), ErrorCode> {
         self.pconsole.start()
     }
+
+    /// Show the records of `crash_log` with the `crashlog` console command.
+    pub fn process_console_set_crash_log(&self, crash_log: &'static dyn kernel::debug::CrashLog) {
+        self.pconsole.set_crash_log(crash_log);
+    }
 }
 
 /// Mapping of integer syscalls to objects that implement syscalls.
@@ -122,6 +131,7 @@ impl SyscallDriverLookup for QemuRv32VirtPlatform {
             capsules_core::console::DRIVER_NUM => f(Some(self.console)),
             capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
             capsules_core::low_level_debug::DRIVER_NUM => f(Some(self.lldb)),
+            capsules_extra::date_time::DRIVER_NUM => f(Some(self.date_time)),
             capsules_core::rng::DRIVER_NUM => {
                 if let Some(rng_driver) = self.rng {
                     f(Some(rng_driver))
@@ -136,6 +146,13 @@ impl SyscallDriverLookup for QemuRv32VirtPlatform {
                     f(None)
                 }
             }
+            capsules_extra::nonvolatile_storage_driver::DRIVER_NUM => {
+                if let Some(nonvolatile_storage_driver) = self.virtio_blk_storage {
+                    f(Some(nonvolatile_storage_driver))
+                } else {
+                    f(None)
+                }
+            }
 
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             _ => f(None),
@@ -246,10 +263,12 @@ pub unsafe fn start() -> (
             )
             .unwrap(),
         ),
+        // The MMIO region also covers the parallel flash banks at 0x20000000
+        // and 0x22000000, which boards may use for storage.
         rv32i::pmp::kernel_protection_mml_epmp::MMIORegion(
             rv32i::pmp::NAPOTRegionSpec::from_start_size(
                 core::ptr::null::<u8>(), // start
-                0x20000000,              // size
+                0x40000000,              // size
             )
             .unwrap(),
         ),
@@ -289,6 +308,7 @@ pub unsafe fn start() -> (
         QemuRv32VirtDefaultPeripherals,
         QemuRv32VirtDefaultPeripherals::new(),
     );
+    peripherals.init();
 
     // Create a shared UART channel for the console and for kernel
     // debug over the provided memory-mapped 16550-compatible
@@ -336,8 +356,14 @@ pub unsafe fn start() -> (
     // Collect supported VirtIO peripheral indicies and initialize them if they
     // are found. If there are two instances of a supported peripheral, the one
     // on a higher-indexed VirtIO transport is used.
-    let (mut virtio_gpu_idx, mut virtio_net_idx, mut virtio_rng_idx, mut virtio_input_idx) =
-        (None, None, None, None);
+    let (
+        mut virtio_gpu_idx,
+        mut virtio_net_idx,
+        mut virtio_rng_idx,
+        mut virtio_input_idx,
+        mut virtio_blk_idx,
+        mut virtio_console_idx,
+    ) = (None, None, None, None, None, None);
     for (i, virtio_device) in peripherals.virtio_mmio.iter().enumerate() {
         use qemu_rv32_virt_chip::virtio::devices::VirtIODeviceType;
         match virtio_device.query() {
@@ -353,6 +379,12 @@ pub unsafe fn start() -> (
             Ok(VirtIODeviceType::InputDevice) => {
                 virtio_input_idx = Some(i);
             }
+            Ok(VirtIODeviceType::BlockDevice) => {
+                virtio_blk_idx = Some(i);
+            }
+            Ok(VirtIODeviceType::Console) => {
+                virtio_console_idx = Some(i);
+            }
             _ => (),
         }
     }
@@ -662,6 +694,258 @@ pub unsafe fn start() -> (
         None
     };
 
+    // If there is a VirtIO BlockDevice present, use the VirtIOBlk driver and
+    // expose the whole disk to userspace through the NonvolatileStorage
+    // driver.
+    let virtio_blk_storage: Option<
+        &'static capsules_extra::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+    > = if let Some(blk_idx) = virtio_blk_idx {
+        use capsules_extra::nonvolatile_storage_driver::NonvolatileStorage;
+        use kernel::hil::nonvolatile_storage::NonvolatileStorage as _;
+        use qemu_rv32_virt_chip::virtio::devices::virtio_blk::{
+            REQUEST_HEADER_LEN, SECTOR_SIZE, VirtIOBlk,
+        };
+        use qemu_rv32_virt_chip::virtio::queues::Virtqueue;
+        use qemu_rv32_virt_chip::virtio::queues::split_queue::{
+            SplitVirtqueue, VirtqueueAvailableRing, VirtqueueDescriptors, VirtqueueUsedRing,
+        };
+        use qemu_rv32_virt_chip::virtio::transports::VirtIOTransport;
+
+        // Each request is a chain of 3 descriptors: the request header, the
+        // sector data and the status byte written by the device.
+        let descriptors = static_init!(VirtqueueDescriptors<3>, VirtqueueDescriptors::default(),);
+        let available_ring =
+            static_init!(VirtqueueAvailableRing<3>, VirtqueueAvailableRing::default(),);
+        let used_ring = static_init!(VirtqueueUsedRing<3>, VirtqueueUsedRing::default(),);
+        let queue = static_init!(
+            SplitVirtqueue<3, RiscvCoherentDmaFence>,
+            SplitVirtqueue::new(descriptors, available_ring, used_ring, dma_fence),
+        );
+        queue.set_transport(&peripherals.virtio_mmio[blk_idx]);
+
+        let header_buf = static_init!([u8; REQUEST_HEADER_LEN], [0; REQUEST_HEADER_LEN]);
+        let sector_buf = static_init!([u8; SECTOR_SIZE], [0; SECTOR_SIZE]);
+        let status_buf = static_init!([u8; 1], [0; 1]);
+
+        // VirtIO BlockDevice driver instantiation
+        let virtio_blk = static_init!(
+            VirtIOBlk<'static, RiscvCoherentDmaFence>,
+            VirtIOBlk::new(
+                queue,
+                &peripherals.virtio_mmio[blk_idx],
+                header_buf,
+                sector_buf,
+                status_buf,
+            )
+            .unwrap(),
+        );
+        kernel::deferred_call::DeferredCallClient::register(virtio_blk);
+        queue.set_client(virtio_blk);
+
+        // Register the queue and driver with the transport, so interrupts
+        // are routed properly. This also reads the disk capacity.
+        let mmio_queues = static_init!([&'static dyn Virtqueue; 1], [queue; 1]);
+        peripherals.virtio_mmio[blk_idx]
+            .initialize(virtio_blk, mmio_queues)
+            .unwrap();
+
+        // Instantiate the userspace nonvolatile storage driver over the
+        // whole disk:
+        let nonvolatile_storage_buffer = static_init!(
+            [u8; capsules_extra::nonvolatile_storage_driver::BUF_LEN],
+            [0; capsules_extra::nonvolatile_storage_driver::BUF_LEN],
+        );
+        let nonvolatile_storage = static_init!(
+            NonvolatileStorage<'static>,
+            NonvolatileStorage::new(
+                virtio_blk,
+                board_kernel.create_grant(
+                    capsules_extra::nonvolatile_storage_driver::DRIVER_NUM,
+                    &memory_allocation_cap
+                ),
+                0,
+                virtio_blk.capacity(),
+                0,
+                0,
+                nonvolatile_storage_buffer,
+            ),
+        );
+        virtio_blk.set_client(nonvolatile_storage);
+
+        Some(nonvolatile_storage as &'static NonvolatileStorage<'static>)
+    } else {
+        // No VirtIO BlockDevice discovered
+        None
+    };
+
+    // If there is a VirtIO Console present, use its first two ports as UARTs
+    // for the process console and the debug writer respectively, leaving the
+    // 16550 UART to the userspace console.
+    let virtio_console_muxes: Option<(
+        &'static capsules_core::virtualizers::virtual_uart::MuxUart<'static>,
+        &'static capsules_core::virtualizers::virtual_uart::MuxUart<'static>,
+    )> = if let Some(console_idx) = virtio_console_idx {
+        use qemu_rv32_virt_chip::virtio::devices::virtio_console::{
+            CONTROL_MESSAGE_LEN, CONTROL_RECEIVE_BUFFERS, VirtIOConsole, VirtIOConsolePort,
+        };
+        use qemu_rv32_virt_chip::virtio::queues::Virtqueue;
+        use qemu_rv32_virt_chip::virtio::queues::split_queue::{
+            SplitVirtqueue, VirtqueueAvailableRing, VirtqueueDescriptors, VirtqueueUsedRing,
+        };
+        use qemu_rv32_virt_chip::virtio::transports::VirtIOTransport;
+
+        // Every port has a receive and a transmit Virtqueue, each holding
+        // the single buffer of the current UART operation.
+
+        // Port 0 Virtqueues
+        let port0_rx_descriptors =
+            static_init!(VirtqueueDescriptors<1>, VirtqueueDescriptors::default(),);
+        let port0_rx_available_ring =
+            static_init!(VirtqueueAvailableRing<1>, VirtqueueAvailableRing::default(),);
+        let port0_rx_used_ring = static_init!(VirtqueueUsedRing<1>, VirtqueueUsedRing::default(),);
+        let port0_rx_queue = static_init!(
+            SplitVirtqueue<1, RiscvCoherentDmaFence>,
+            SplitVirtqueue::new(port0_rx_descriptors, port0_rx_available_ring, port0_rx_used_ring, dma_fence),
+        );
+        port0_rx_queue.set_transport(&peripherals.virtio_mmio[console_idx]);
+        let port0_tx_descriptors =
+            static_init!(VirtqueueDescriptors<1>, VirtqueueDescriptors::default(),);
+        let port0_tx_available_ring =
+            static_init!(VirtqueueAvailableRing<1>, VirtqueueAvailableRing::default(),);
+        let port0_tx_used_ring = static_init!(VirtqueueUsedRing<1>, VirtqueueUsedRing::default(),);
+        let port0_tx_queue = static_init!(
+            SplitVirtqueue<1, RiscvCoherentDmaFence>,
+            SplitVirtqueue::new(port0_tx_descriptors, port0_tx_available_ring, port0_tx_used_ring, dma_fence),
+        );
+        port0_tx_queue.set_transport(&peripherals.virtio_mmio[console_idx]);
+
+        // Port 1 Virtqueues
+        let port1_rx_descriptors =
+            static_init!(VirtqueueDescriptors<1>, VirtqueueDescriptors::default(),);
+        let port1_rx_available_ring =
+            static_init!(VirtqueueAvailableRing<1>, VirtqueueAvailableRing::default(),);
+        let port1_rx_used_ring = static_init!(VirtqueueUsedRing<1>, VirtqueueUsedRing::default(),);
+        let port1_rx_queue = static_init!(
+            SplitVirtqueue<1, RiscvCoherentDmaFence>,
+            SplitVirtqueue::new(port1_rx_descriptors, port1_rx_available_ring, port1_rx_used_ring, dma_fence),
+        );
+        port1_rx_queue.set_transport(&peripherals.virtio_mmio[console_idx]);
+        let port1_tx_descriptors =
+            static_init!(VirtqueueDescriptors<1>, VirtqueueDescriptors::default(),);
+        let port1_tx_available_ring =
+            static_init!(VirtqueueAvailableRing<1>, VirtqueueAvailableRing::default(),);
+        let port1_tx_used_ring = static_init!(VirtqueueUsedRing<1>, VirtqueueUsedRing::default(),);
+        let port1_tx_queue = static_init!(
+            SplitVirtqueue<1, RiscvCoherentDmaFence>,
+            SplitVirtqueue::new(port1_tx_descriptors, port1_tx_available_ring, port1_tx_used_ring, dma_fence),
+        );
+        port1_tx_queue.set_transport(&peripherals.virtio_mmio[console_idx]);
+
+        // Control Virtqueues, used to set up the ports in multiport mode
+        let control_rx_descriptors = static_init!(
+            VirtqueueDescriptors<CONTROL_RECEIVE_BUFFERS>,
+            VirtqueueDescriptors::default(),
+        );
+        let control_rx_available_ring = static_init!(
+            VirtqueueAvailableRing<CONTROL_RECEIVE_BUFFERS>,
+            VirtqueueAvailableRing::default(),
+        );
+        let control_rx_used_ring = static_init!(
+            VirtqueueUsedRing<CONTROL_RECEIVE_BUFFERS>,
+            VirtqueueUsedRing::default(),
+        );
+        let control_rx_queue = static_init!(
+            SplitVirtqueue<CONTROL_RECEIVE_BUFFERS, RiscvCoherentDmaFence>,
+            SplitVirtqueue::new(
+                control_rx_descriptors,
+                control_rx_available_ring,
+                control_rx_used_ring,
+                dma_fence
+            ),
+        );
+        control_rx_queue.set_transport(&peripherals.virtio_mmio[console_idx]);
+        let control_tx_descriptors =
+            static_init!(VirtqueueDescriptors<1>, VirtqueueDescriptors::default(),);
+        let control_tx_available_ring =
+            static_init!(VirtqueueAvailableRing<1>, VirtqueueAvailableRing::default(),);
+        let control_tx_used_ring =
+            static_init!(VirtqueueUsedRing<1>, VirtqueueUsedRing::default(),);
+        let control_tx_queue = static_init!(
+            SplitVirtqueue<1, RiscvCoherentDmaFence>,
+            SplitVirtqueue::new(control_tx_descriptors, control_tx_available_ring, control_tx_used_ring, dma_fence),
+        );
+        control_tx_queue.set_transport(&peripherals.virtio_mmio[console_idx]);
+
+        let control_rx_buffers = static_init!(
+            [[u8; CONTROL_MESSAGE_LEN]; CONTROL_RECEIVE_BUFFERS],
+            [[0; CONTROL_MESSAGE_LEN]; CONTROL_RECEIVE_BUFFERS],
+        );
+        let control_tx_buffer = static_init!([u8; CONTROL_MESSAGE_LEN], [0; CONTROL_MESSAGE_LEN]);
+
+        // Instantiate the ports and the VirtIO Console driver
+        let port0 = static_init!(
+            VirtIOConsolePort<'static, RiscvCoherentDmaFence>,
+            VirtIOConsolePort::new(port0_rx_queue, port0_tx_queue),
+        );
+        port0_rx_queue.set_client(port0);
+        port0_tx_queue.set_client(port0);
+        let port1 = static_init!(
+            VirtIOConsolePort<'static, RiscvCoherentDmaFence>,
+            VirtIOConsolePort::new(port1_rx_queue, port1_tx_queue),
+        );
+        port1_rx_queue.set_client(port1);
+        port1_tx_queue.set_client(port1);
+
+        let ports = static_init!(
+            [&'static VirtIOConsolePort<'static, RiscvCoherentDmaFence>; 2],
+            [port0, port1],
+        );
+        let virtio_console = static_init!(
+            VirtIOConsole<'static, RiscvCoherentDmaFence>,
+            VirtIOConsole::new(
+                ports,
+                control_rx_queue,
+                control_tx_queue,
+                control_rx_buffers,
+                control_tx_buffer,
+            ),
+        );
+        control_rx_queue.set_client(virtio_console);
+        control_tx_queue.set_client(virtio_console);
+
+        // Register the queues and driver with the transport, so interrupts
+        // are routed properly. The order of the queues is fixed by the
+        // VirtIO specification.
+        let mmio_queues = static_init!(
+            [&'static dyn Virtqueue; 6],
+            [
+                port0_rx_queue,
+                port0_tx_queue,
+                control_rx_queue,
+                control_tx_queue,
+                port1_rx_queue,
+                port1_tx_queue,
+            ],
+        );
+        peripherals.virtio_mmio[console_idx]
+            .initialize(virtio_console, mmio_queues)
+            .unwrap();
+
+        // Each port is shared through its own UART mux, just like a
+        // hardware UART
+        let port0_mux = components::console::UartMuxComponent::new(port0, 115200)
+            .finalize(components::uart_mux_component_static!());
+        let port1_mux = components::console::UartMuxComponent::new(port1, 115200)
+            .finalize(components::uart_mux_component_static!());
+
+        Some((port0_mux, port1_mux))
+    } else {
+        // No VirtIO Console discovered
+        None
+    };
+    let (process_console_uart_mux, debug_writer_uart_mux) =
+        virtio_console_muxes.unwrap_or((uart_mux, uart_mux));
+
     // ---------- INITIALIZE CHIP, ENABLE INTERRUPTS ---------
 
     let chip = static_init!(
@@ -694,7 +978,7 @@ pub unsafe fn start() -> (
     let process_console_cap = unsafe { kernel::mint_defined_capability!(ProcessConsoleCap) };
     let pconsole = components::process_console::ProcessConsoleComponent::new(
         board_kernel,
-        uart_mux,
+        process_console_uart_mux,
         mux_alarm,
         process_printer,
         None,
@@ -717,7 +1001,7 @@ pub unsafe fn start() -> (
     components::debug_writer::DebugWriterComponent::new::<
         <ChipHw as kernel::platform::chip::Chip>::ThreadIdProvider,
     >(
-        uart_mux,
+        debug_writer_uart_mux,
         create_capability!(capabilities::SetDebugWriterCapability),
     )
     .finalize(components::debug_writer_component_static!());
@@ -745,6 +1029,17 @@ pub unsafe fn start() -> (
         ))
     });
 
+    // ---------- DATE AND TIME ----------
+
+    // Userspace date and time driver over the Goldfish RTC
+    let date_time = components::date_time::DateTimeComponent::new(
+        board_kernel,
+        capsules_extra::date_time::DRIVER_NUM,
+        &peripherals.rtc,
+        create_capability!(capabilities::MemoryAllocationCapability),
+    )
+    .finalize(components::date_time_component_static!(RtcHw));
+
     // ---------- SCHEDULER ----------
 
     let scheduler = components::sched::cooperative::CooperativeComponent::new(processes)
@@ -761,12 +1056,14 @@ pub unsafe fn start() -> (
         console,
         alarm,
         lldb,
+        date_time,
         scheduler,
         scheduler_timer,
         rng: rng_driver,
         virtio_ethernet_tap,
         virtio_gpu_screen,
         virtio_input_keyboard,
+        virtio_blk_storage,
         ipc: kernel::ipc::IPC::new(
             board_kernel,
             kernel::ipc::DRIVER_NUM,
@@ -799,6 +1096,23 @@ pub unsafe fn start() -> (
     } else {
         debug!("- VirtIO Input device not found, disabling Input");
     }
+    if virtio_blk_storage.is_some() {
+        debug!("- Found VirtIO BlockDevice, enabling NonvolatileStorage");
+    } else {
+        debug!("- VirtIO BlockDevice not found, disabling NonvolatileStorage");
+    }
+    if virtio_console_muxes.is_some() {
+        debug!("- Found VirtIO Console, using it for the process console and debug output");
+    } else {
+        debug!("- VirtIO Console not found, using the UART for all console output");
+    }
 
     (board_kernel, platform, chip)
 }
+
+/// Save a crash record with `recorder` when the kernel panics.
+pub fn set_crash_recorder(recorder: &'static dyn kernel::debug::CrashRecorder) {
+    PANIC_RESOURCES.get().map(|resources| {
+        resources.crash_recorder.put(recorder);
+    });
+}
@@ -43,6 +43,7 @@ pub type ScreenHw = qemu_rv64_virt_chip::virtio::devices::virtio_gpu::VirtIOGPU<
 >;
 
 type AlarmHw = qemu_rv64_virt_chip::chip::QemuRv64VirtClint<'static>;
+type RtcHw = qemu_rv64_virt_chip::chip::QemuRv64VirtRtc<'static>;
 type SchedulerTimerHw =
     components::virtual_scheduler_timer::VirtualSchedulerTimerComponentType<AlarmHw>;
 type SchedulerInUse = components::sched::cooperative::CooperativeComponentType;
@@ -79,6 +80,7 @@ pub struct QemuRv64VirtPlatform {
         'static,
         VirtualMuxAlarm<'static, qemu_rv64_virt_chip::chip::QemuRv64VirtClint<'static>>,
     >,
+    date_time: &'static capsules_extra::date_time::DateTimeCapsule<'static, RtcHw>,
     pub ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
     scheduler: &'static SchedulerInUse,
     scheduler_timer: &'static SchedulerTimerHw,
@@ -122,6 +124,7 @@ impl SyscallDriverLookup for QemuRv64VirtPlatform {
             capsules_core::console::DRIVER_NUM => f(Some(self.console)),
             capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
             capsules_core::low_level_debug::DRIVER_NUM => f(Some(self.lldb)),
+            capsules_extra::date_time::DRIVER_NUM => f(Some(self.date_time)),
             capsules_core::rng::DRIVER_NUM => {
                 if let Some(rng_driver) = self.rng {
                     f(Some(rng_driver))
@@ -293,6 +296,7 @@ pub unsafe fn start() -> (
         QemuRv64VirtDefaultPeripherals,
         QemuRv64VirtDefaultPeripherals::new(),
     );
+    peripherals.init();
 
     // Create a shared UART channel for the console and for kernel
     // debug over the provided memory-mapped 16550-compatible
@@ -754,6 +758,17 @@ pub unsafe fn start() -> (
         ))
     });
 
+    // ---------- DATE AND TIME ----------
+
+    // Userspace date and time driver over the Goldfish RTC
+    let date_time = components::date_time::DateTimeComponent::new(
+        board_kernel,
+        capsules_extra::date_time::DRIVER_NUM,
+        &peripherals.rtc,
+        create_capability!(capabilities::MemoryAllocationCapability),
+    )
+    .finalize(components::date_time_component_static!(RtcHw));
+
     // ---------- SCHEDULER ----------
 
     let scheduler = components::sched::cooperative::CooperativeComponent::new(processes)
@@ -770,6 +785,7 @@ pub unsafe fn start() -> (
         console,
         alarm,
         lldb,
+        date_time,
         scheduler,
         scheduler_timer,
         rng: rng_driver,
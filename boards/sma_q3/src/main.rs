@@ -385,7 +385,7 @@ pub unsafe fn start() -> (
     let aes_mux = components::aes::AesMuxComponent::new(&base_peripherals.ecb)
         .finalize(components::aes_mux_component_static!(nrf52840::aes::AesECB));
 
-    let (ieee802154_radio, _mux_mac) = components::ieee802154::Ieee802154Component::new(
+    let (ieee802154_radio, _mux_mac, _) = components::ieee802154::Ieee802154Component::new(
         board_kernel,
         capsules_extra::ieee802154::DRIVER_NUM,
         &nrf52840_peripherals.ieee802154_radio,
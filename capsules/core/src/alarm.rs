@@ -572,7 +572,12 @@ mod test {
     };
     use kernel::utilities::cells::OptionalCell;
 
-    use super::{AlarmDriver, Expiration};
+    use capsules_test_harness::alarm::MockAlarm as HostAlarm;
+    use capsules_test_harness::process::{HostKernel, Upcall};
+    use capsules_test_harness::{deferred_call, leak};
+    use kernel::syscall::SyscallReturn;
+
+    use super::{AlarmDriver, DRIVER_NUM, Expiration};
 
     struct MockAlarm<'a, T: Ticks, F: Frequency> {
         current_ticks: Cell<T>,
@@ -1166,4 +1171,85 @@ mod test {
         assert_eq!(expiration.reference.into_u64(), 0xDEACCAFEB0BA_u64);
         assert_eq!(expiration.dt.into_u64(), 0x1BADB002_u64);
     }
+
+    #[test]
+    fn alarms_of_two_processes_fire_in_order() {
+        deferred_call::run(|| {
+            let kernel = HostKernel::new();
+            let alarm = leak(HostAlarm::<Freq10MHz>::new());
+            let driver = leak(AlarmDriver::new(alarm, kernel.create_grant(DRIVER_NUM)));
+            alarm.set_alarm_client(driver);
+            kernel.add_driver(DRIVER_NUM, driver);
+
+            let late = kernel.load_process("late");
+            let early = kernel.load_process("early");
+            for app in [&late, &early] {
+                assert!(matches!(
+                    app.subscribe(DRIVER_NUM, 0),
+                    SyscallReturn::SubscribeSuccess(..)
+                ));
+            }
+            assert!(matches!(
+                late.command(DRIVER_NUM, 5, 100, 0),
+                SyscallReturn::SuccessU32(100)
+            ));
+            assert!(matches!(
+                early.command(DRIVER_NUM, 5, 40, 0),
+                SyscallReturn::SuccessU32(40)
+            ));
+            assert_eq!(late.yield_wait(), None);
+            assert_eq!(early.yield_wait(), None);
+
+            assert_eq!(alarm.advance(40), 1);
+            assert_eq!(
+                early.yield_wait(),
+                Some(Upcall {
+                    driver_number: DRIVER_NUM,
+                    subscribe_number: 0,
+                    arguments: [40, 40, 0],
+                })
+            );
+            assert_eq!(late.yield_wait(), None);
+
+            // The alarm of `early` expired, so there is nothing to stop.
+            assert!(matches!(
+                early.command(DRIVER_NUM, 3, 0, 0),
+                SyscallReturn::Failure(ErrorCode::ALREADY)
+            ));
+
+            assert_eq!(alarm.advance(60), 1);
+            assert_eq!(
+                late.yield_wait(),
+                Some(Upcall {
+                    driver_number: DRIVER_NUM,
+                    subscribe_number: 0,
+                    arguments: [100, 100, 0],
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn stopped_alarm_does_not_fire() {
+        deferred_call::run(|| {
+            let kernel = HostKernel::new();
+            let alarm = leak(HostAlarm::<Freq10MHz>::new());
+            let driver = leak(AlarmDriver::new(alarm, kernel.create_grant(DRIVER_NUM)));
+            alarm.set_alarm_client(driver);
+            kernel.add_driver(DRIVER_NUM, driver);
+
+            let app = kernel.load_process("app");
+            app.subscribe(DRIVER_NUM, 0);
+            app.command(DRIVER_NUM, 5, 10, 0);
+            assert!(alarm.is_armed());
+            assert!(matches!(
+                app.command(DRIVER_NUM, 3, 0, 0),
+                SyscallReturn::Success
+            ));
+            assert!(!alarm.is_armed());
+
+            alarm.advance(10);
+            assert_eq!(app.yield_wait(), None);
+        });
+    }
 }
@@ -465,3 +465,125 @@ impl uart::ReceiveClient for Console<'_> {
         self.rx_buffer.replace(buffer);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capsules_test_harness::process::{App, HostKernel, Upcall};
+    use capsules_test_harness::uart::MockUart;
+    use capsules_test_harness::{deferred_call, leak, run_until_idle, static_buf};
+    use kernel::hil::uart::{Receive as _, Transmit as _};
+    use kernel::syscall::SyscallReturn;
+
+    /// Create a console with a small transmit buffer, so that writes take
+    /// several transmissions.
+    fn console() -> (&'static HostKernel, &'static MockUart<'static>) {
+        let kernel = HostKernel::new();
+        let uart = leak(MockUart::new());
+        let console = leak(Console::new(
+            uart,
+            static_buf(4),
+            static_buf(DEFAULT_BUF_SIZE),
+            kernel.create_grant(DRIVER_NUM),
+        ));
+        uart.set_transmit_client(console);
+        uart.set_receive_client(console);
+        kernel.add_driver(DRIVER_NUM, console);
+        (kernel, uart)
+    }
+
+    fn write_done(written: usize) -> Option<Upcall> {
+        Some(Upcall {
+            driver_number: DRIVER_NUM,
+            subscribe_number: upcall::WRITE_DONE,
+            arguments: [written, 0, 0],
+        })
+    }
+
+    fn start_write(app: &App, text: &[u8]) {
+        let buffer = app.allocate(text.len());
+        app.write(buffer, text);
+        app.allow_readonly(DRIVER_NUM, ro_allow::WRITE, buffer);
+        app.subscribe(DRIVER_NUM, upcall::WRITE_DONE);
+        assert!(matches!(
+            app.command(DRIVER_NUM, 1, text.len(), 0),
+            SyscallReturn::Success
+        ));
+    }
+
+    #[test]
+    fn writes_of_two_processes_are_serialized() {
+        deferred_call::run(|| {
+            let (kernel, uart) = console();
+            let first = kernel.load_process("first");
+            let second = kernel.load_process("second");
+
+            start_write(&first, b"hello");
+            start_write(&second, b"world");
+            assert_eq!(first.yield_wait(), None);
+            assert_eq!(second.yield_wait(), None);
+
+            run_until_idle(&[uart]);
+            assert_eq!(uart.take_transmitted(), b"helloworld");
+            assert_eq!(first.yield_wait(), write_done(5));
+            assert_eq!(second.yield_wait(), write_done(5));
+        });
+    }
+
+    #[test]
+    fn write_uses_the_last_allowed_buffer() {
+        deferred_call::run(|| {
+            let (kernel, uart) = console();
+            let app = kernel.load_process("app");
+
+            let old = app.allocate(3);
+            app.write(old, b"old");
+            assert!(matches!(
+                app.allow_readonly(DRIVER_NUM, ro_allow::WRITE, old),
+                SyscallReturn::AllowReadOnlySuccess(_, 0)
+            ));
+            // Swapping in a new buffer hands the old one back.
+            let new = app.allocate(3);
+            app.write(new, b"new");
+            assert!(matches!(
+                app.allow_readonly(DRIVER_NUM, ro_allow::WRITE, new),
+                SyscallReturn::AllowReadOnlySuccess(_, 3)
+            ));
+            app.subscribe(DRIVER_NUM, upcall::WRITE_DONE);
+            app.command(DRIVER_NUM, 1, 3, 0);
+
+            run_until_idle(&[uart]);
+            assert_eq!(uart.take_transmitted(), b"new");
+            assert_eq!(app.yield_wait(), write_done(3));
+        });
+    }
+
+    #[test]
+    fn read_fills_the_allowed_buffer() {
+        deferred_call::run(|| {
+            let (kernel, uart) = console();
+            let app = kernel.load_process("app");
+
+            let buffer = app.allocate(4);
+            app.allow_readwrite(DRIVER_NUM, rw_allow::READ, buffer);
+            app.subscribe(DRIVER_NUM, upcall::READ_DONE);
+            assert!(matches!(
+                app.command(DRIVER_NUM, 2, 4, 0),
+                SyscallReturn::Success
+            ));
+            assert_eq!(app.yield_wait(), None);
+
+            uart.receive_bytes(b"ping");
+            run_until_idle(&[uart]);
+            assert_eq!(
+                app.yield_wait(),
+                Some(Upcall {
+                    driver_number: DRIVER_NUM,
+                    subscribe_number: upcall::READ_DONE,
+                    arguments: [0, 4, 0],
+                })
+            );
+            assert_eq!(app.read(buffer), b"ping");
+        });
+    }
+}
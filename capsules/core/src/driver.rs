@@ -28,6 +28,8 @@ pub enum NUM {
     Ipc                   = 0x10000,
     AppLoader             = 0x10001,
     ProcessInfo           = 0x10002,
+    IpcMailbox            = 0x10003,
+    ProcessAccounting     = 0x10004,
 
     // HW Buses
     Spi                   = 0x20001,
@@ -47,6 +49,8 @@ pub enum NUM {
     Eui64                 = 0x30006,
     EthernetTap           = 0x30007,
     Wifi                  = 0x30008,
+    Tcp                   = 0x30009,
+    BlePeripheral         = 0x3000A,
 
     // Cryptography
     Rng                   = 0x40001,
@@ -62,6 +66,8 @@ pub enum NUM {
     SdCard                = 0x50002,
     Kv                    = 0x50003,
     IsolatedNvmStorage    = 0x50004,
+    FatFs                 = 0x50005,
+    CrashLog              = 0x50006,
 
     // Sensors
     Temperature           = 0x60000,
@@ -16,15 +16,22 @@ use kernel::capabilities::ProcessManagementCapability;
 use kernel::capabilities::ProcessStartCapability;
 use kernel::hil::time::ConvertTicks;
 use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 
 use kernel::ErrorCode;
 use kernel::Kernel;
 use kernel::debug;
+use kernel::dynamic_binary_storage::{DynamicBinaryCompactClient, DynamicBinaryStore};
+use kernel::hil::kv;
 use kernel::hil::time::{Alarm, AlarmClient};
 use kernel::hil::uart;
 use kernel::introspection::KernelInfo;
-use kernel::process::{ProcessPrinter, ProcessPrinterContext, State};
+use kernel::process::{
+    CheckpointClient, ProcessCheckpointer, ProcessPrinter, ProcessPrinterContext, State,
+};
+use kernel::scheduler::SchedulerInfo;
+use kernel::syscall_trace::{SyscallTrace, SyscallTraceContext};
 use kernel::utilities::binary_write::BinaryWrite;
 
 /// Buffer to hold outgoing data that is passed to the UART hardware.
@@ -44,7 +51,11 @@ pub const DEFAULT_COMMAND_HISTORY_LEN: usize = 10;
 /// List of valid commands for printing help. Consolidated as these are
 /// displayed in a few different cases.
 const VALID_COMMANDS_STR: &[u8] =
-    b"help status list stop start fault boot terminate process kernel reset panic console-start console-stop\r\n";
+    b"help status list stop start fault boot terminate process kernel reset panic crashlog compact checkpoint trace kvhealth console-start console-stop\r\n";
+
+/// Number of bytes of a crash record printed in each step of the writer state
+/// machine, small enough to fit in the queue buffer.
+const CRASH_RECORD_CHUNK_LEN: usize = 200;
 
 /// Escape character for ANSI escape sequences.
 const ESC: u8 = b'\x1B';
@@ -91,6 +102,21 @@ enum WriterState {
         index: isize,
         total: isize,
     },
+    CrashLogList {
+        index: usize,
+        total: usize,
+    },
+    CrashRecord {
+        index: usize,
+        offset: usize,
+    },
+    TraceList {
+        index: usize,
+        total: usize,
+    },
+    TraceBinary {
+        context: Option<SyscallTraceContext>,
+    },
 }
 
 /// Key that can be part from an escape sequence.
@@ -209,6 +235,20 @@ pub struct KernelAddresses {
     pub bss_end: *const u8,
 }
 
+/// Health of a KV store summed over its regions, collected by the `kvhealth`
+/// command one region at a time.
+#[derive(Clone, Copy, Default)]
+struct KVHealthTotals {
+    /// The next region to read.
+    region: usize,
+    min_erase_count: u32,
+    max_erase_count: u32,
+    total_erase_count: u64,
+    used: usize,
+    reclaimable: usize,
+    free: usize,
+}
+
 /// Track the operational state of the process console.
 #[derive(Clone, Copy, PartialEq)]
 enum ProcessConsoleState {
@@ -271,6 +311,31 @@ pub struct ProcessConsole<
     /// Function used to reset the device in bootloader mode
     reset_function: Option<fn() -> !>,
 
+    /// Persistent crash records to show with the `crashlog` command.
+    crash_log: OptionalCell<&'a dyn debug::CrashLog>,
+
+    /// Storage for dynamically loaded apps to compact with the `compact`
+    /// command.
+    binary_store: OptionalCell<&'a dyn DynamicBinaryStore>,
+
+    /// Whether the `compact` command is waiting for compaction to finish.
+    compacting: Cell<bool>,
+
+    /// System call trace controlled and shown with the `trace` command.
+    syscall_trace: OptionalCell<&'a dyn SyscallTrace>,
+
+    /// KV store whose wear is shown with the `kvhealth` command.
+    kv_health: OptionalCell<&'a dyn kv::KVHealth<'a>>,
+
+    /// Totals of the `kvhealth` command that is running.
+    kv_health_totals: OptionalCell<KVHealthTotals>,
+
+    /// Scheduler whose statistics `list` and `status` show.
+    scheduler_info: OptionalCell<&'a dyn SchedulerInfo>,
+
+    /// Saves process checkpoints with the `checkpoint` command.
+    checkpointer: OptionalCell<&'a dyn ProcessCheckpointer<'a>>,
+
     /// This capsule needs to use potentially dangerous APIs related to
     /// processes, and requires a capability to access those APIs.
     capability: C,
@@ -477,10 +542,53 @@ impl<
             kernel,
             kernel_addresses,
             reset_function,
+            crash_log: OptionalCell::empty(),
+            binary_store: OptionalCell::empty(),
+            compacting: Cell::new(false),
+            syscall_trace: OptionalCell::empty(),
+            kv_health: OptionalCell::empty(),
+            kv_health_totals: OptionalCell::empty(),
+            scheduler_info: OptionalCell::empty(),
+            checkpointer: OptionalCell::empty(),
             capability,
         }
     }
 
+    /// Set the crash log the `crashlog` command reads and clears.
+    pub fn set_crash_log(&self, crash_log: &'a dyn debug::CrashLog) {
+        self.crash_log.set(crash_log);
+    }
+
+    /// Set the app storage the `compact` command compacts. The process console
+    /// must also be set as the compact client of the app storage.
+    pub fn set_binary_store(&self, binary_store: &'a dyn DynamicBinaryStore) {
+        self.binary_store.set(binary_store);
+    }
+
+    /// Set the system call trace the `trace` command controls and shows.
+    pub fn set_syscall_trace(&self, syscall_trace: &'a dyn SyscallTrace) {
+        self.syscall_trace.set(syscall_trace);
+    }
+
+    /// Set the KV store the `kvhealth` command shows the wear of. The process
+    /// console must also be set as the health client of the KV store.
+    pub fn set_kv_health(&self, kv_health: &'a dyn kv::KVHealth<'a>) {
+        self.kv_health.set(kv_health);
+    }
+
+    /// Set the scheduler whose statistics, such as missed deadlines, the
+    /// `list` and `status` commands show.
+    pub fn set_scheduler_info(&self, scheduler_info: &'a dyn SchedulerInfo) {
+        self.scheduler_info.set(scheduler_info);
+    }
+
+    /// Set the checkpointer the `checkpoint` command saves processes with.
+    /// The process console must also be set as the client of the
+    /// checkpointer.
+    pub fn set_checkpointer(&self, checkpointer: &'a dyn ProcessCheckpointer<'a>) {
+        self.checkpointer.set(checkpointer);
+    }
+
     /// Start the process console listening for user commands.
     pub fn start(&self) -> Result<(), ErrorCode> {
         if self.mode.get() == ProcessConsoleState::Off {
@@ -563,6 +671,30 @@ impl<
                     }
                 }
             }
+            WriterState::CrashLogList { index, total } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::CrashLogList {
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::CrashRecord { index, offset } => {
+                WriterState::CrashRecord { index, offset }
+            }
+            WriterState::TraceList { index, total } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::TraceList {
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::TraceBinary { context } => WriterState::TraceBinary { context },
             WriterState::Empty => WriterState::Empty,
         }
     }
@@ -732,21 +864,144 @@ impl<
                             let _ = write(
                                 &mut console_writer,
                                 format_args!(
-                                    "{:<20}{:6}{:10}{:10}  {:2}/{:2}   {:?}\r\n",
+                                    "{:<20}{:6}{:10}{:10}  {:2}/{:2}   ",
                                     pname,
                                     process.debug_timeslice_expiration_count(),
                                     process.debug_syscall_count(),
                                     process.get_restart_count(),
                                     grants_used,
                                     grants_total,
-                                    process.get_state(),
                                 ),
                             );
+                            // Display missed deadlines, if the scheduler
+                            // gives the process deadlines.
+                            self.scheduler_info.map(|scheduler_info| {
+                                let _ = match scheduler_info.deadline_misses(process_id) {
+                                    Some(misses) => {
+                                        write(&mut console_writer, format_args!("{:6}  ", misses))
+                                    }
+                                    None => {
+                                        write(&mut console_writer, format_args!("{:>6}  ", "-"))
+                                    }
+                                };
+                            });
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!("{:?}\r\n", process.get_state()),
+                            );
 
                             let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
                         }
                     });
             }
+            WriterState::CrashLogList { index, total: _ } => {
+                self.crash_log.map(|crash_log| {
+                    crash_log.record(index).map(|record| {
+                        let mut console_writer = ConsoleWriter::new();
+                        let kind = match record.kind {
+                            debug::CrashKind::KernelPanic => "panic",
+                            debug::CrashKind::ProcessFault => "fault",
+                        };
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!(
+                                " {:<3}{:<8}{:<7}{:#010x}  {:#010x}  {:<20}\r\n",
+                                index,
+                                record.sequence,
+                                kind,
+                                record.registers.pc,
+                                record.registers.lr,
+                                str::from_utf8(record.process_name).unwrap_or("?"),
+                            ),
+                        );
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    });
+                });
+            }
+            WriterState::CrashRecord { index, offset } => {
+                // Print the summary text and debug output of the record in
+                // chunks, moving on to the next chunk after each is sent.
+                let chunk = self.crash_log.and_then(|crash_log| {
+                    crash_log.record(index).and_then(|record| {
+                        let mut console_writer = ConsoleWriter::new();
+                        if offset == 0 {
+                            let _ = write(&mut console_writer, format_args!("--- summary ---\r\n"));
+                        }
+                        let text_len = record.text.len();
+                        let (section, start) = if offset < text_len {
+                            (record.text, offset)
+                        } else {
+                            (record.debug, offset - text_len)
+                        };
+                        if offset == text_len {
+                            let _ =
+                                write(&mut console_writer, format_args!("\r\n--- debug ---\r\n"));
+                        }
+                        let len = cmp::min(CRASH_RECORD_CHUNK_LEN, section.len() - start);
+                        let _ = console_writer.write_buffer(&section[start..start + len]);
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                        if offset + len < text_len + record.debug.len() {
+                            Some(offset + len)
+                        } else {
+                            None
+                        }
+                    })
+                });
+
+                if let Some(offset) = chunk {
+                    self.writer_state
+                        .replace(WriterState::CrashRecord { index, offset });
+                } else {
+                    let _ = self.write_bytes(b"\r\n");
+                    self.writer_state.replace(WriterState::Empty);
+                    // As with `ProcessPrint`, the prompt is not printed by
+                    // the match on the next state.
+                    self.prompt();
+                }
+            }
+            WriterState::TraceList { index, total: _ } => {
+                self.syscall_trace.map(|syscall_trace| {
+                    syscall_trace.record(index).map(|record| {
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!(
+                                " {:<5}{:>10}  {:<7?}{:?}",
+                                index, record.timestamp_us, record.processid, record.syscall,
+                            ),
+                        );
+                        let _ = match record.return_value {
+                            Some(return_value) => write(
+                                &mut console_writer,
+                                format_args!(" = {:?}\r\n", return_value),
+                            ),
+                            None => write(&mut console_writer, format_args!("\r\n")),
+                        };
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    });
+                });
+            }
+            WriterState::TraceBinary { context } => {
+                // Write the binary trace in chunks, continuing from where the
+                // previous chunk ended after each is sent.
+                let mut console_writer = ConsoleWriter::new();
+                let new_context = self.syscall_trace.and_then(|syscall_trace| {
+                    syscall_trace.write_binary(&mut console_writer, context)
+                });
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+
+                if new_context.is_some() {
+                    self.writer_state.replace(WriterState::TraceBinary {
+                        context: new_context,
+                    });
+                } else {
+                    let _ = self.write_bytes(b"\r\n");
+                    self.writer_state.replace(WriterState::Empty);
+                    // As with `ProcessPrint`, the prompt is not printed by
+                    // the match on the next state.
+                    self.prompt();
+                }
+            }
             WriterState::Empty => {
                 self.prompt();
             }
@@ -895,7 +1150,11 @@ impl<
                         } else if clean_str.starts_with("list") {
                             let _ = self
                                 .write_bytes(b" PID    ShortID    Name                Quanta  ");
-                            let _ = self.write_bytes(b"Syscalls  Restarts  Grants  State\r\n");
+                            let _ = self.write_bytes(b"Syscalls  Restarts  Grants  ");
+                            if self.scheduler_info.is_some() {
+                                let _ = self.write_bytes(b"Misses  ");
+                            }
+                            let _ = self.write_bytes(b"State\r\n");
 
                             // Count the number of current processes.
                             let mut count = 0;
@@ -939,6 +1198,21 @@ impl<
                                 ),
                             );
                             let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            self.scheduler_info.map(|scheduler_info| {
+                                let mut misses = 0;
+                                self.kernel.process_each_capability(&self.capability, |proc| {
+                                    misses += scheduler_info
+                                        .deadline_misses(proc.processid())
+                                        .unwrap_or(0);
+                                });
+                                console_writer.clear();
+                                let _ = write(
+                                    &mut console_writer,
+                                    format_args!("Deadline misses: {}\r\n", misses),
+                                );
+                                let _ =
+                                    self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                            });
                         } else if clean_str.starts_with("process") {
                             let argument = clean_str.split_whitespace().nth(1);
                             argument.map(|name| {
@@ -1003,6 +1277,225 @@ impl<
                                     f();
                                 },
                             );
+                        } else if clean_str.starts_with("crashlog") {
+                            self.crash_log.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(b"No crash log on this board\r\n");
+                                },
+                                |crash_log| {
+                                    let argument = clean_str.split_whitespace().nth(1);
+                                    match argument {
+                                        None => {
+                                            let total = crash_log.record_count();
+                                            let _ = self.write_bytes(
+                                                b" #  Seq     Kind   PC          LR          Process\r\n",
+                                            );
+                                            if total > 0 {
+                                                // Start the state machine at the
+                                                // first record to print each
+                                                // separately.
+                                                let state =
+                                                    WriterState::CrashLogList { index: 0, total };
+                                                self.writer_state.replace(state);
+                                                self.create_state_buffer(state);
+                                            }
+                                        }
+                                        Some("clear") => match crash_log.clear() {
+                                            Ok(()) => {
+                                                let _ = self.write_bytes(b"Clearing crash log\r\n");
+                                            }
+                                            Err(_) => {
+                                                let _ = self.write_bytes(b"Crash log is busy\r\n");
+                                            }
+                                        },
+                                        Some(index) => {
+                                            match index
+                                                .parse::<usize>()
+                                                .ok()
+                                                .and_then(|index| {
+                                                    crash_log.record(index).map(|r| (index, r))
+                                                }) {
+                                                Some((index, record)) => {
+                                                    let mut console_writer = ConsoleWriter::new();
+                                                    let _ = write(
+                                                        &mut console_writer,
+                                                        format_args!(
+                                                            "Record {} (#{}): {}\r\n PC: {:#010x}  LR: {:#010x}\r\n Status: {:#010x} {:#010x} {:#010x} {:#010x}\r\n",
+                                                            index,
+                                                            record.sequence,
+                                                            str::from_utf8(record.process_name)
+                                                                .ok()
+                                                                .filter(|name| !name.is_empty())
+                                                                .unwrap_or("kernel"),
+                                                            record.registers.pc,
+                                                            record.registers.lr,
+                                                            record.registers.status[0],
+                                                            record.registers.status[1],
+                                                            record.registers.status[2],
+                                                            record.registers.status[3],
+                                                        ),
+                                                    );
+                                                    let _ = self.write_bytes(
+                                                        &(console_writer.buf)[..console_writer.size],
+                                                    );
+                                                    self.writer_state.replace(
+                                                        WriterState::CrashRecord { index, offset: 0 },
+                                                    );
+                                                }
+                                                None => {
+                                                    let _ = self.write_bytes(b"No such crash record\r\n");
+                                                }
+                                            }
+                                        }
+                                    }
+                                },
+                            );
+                        } else if clean_str.starts_with("compact") {
+                            self.binary_store.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(b"No app storage on this board\r\n");
+                                },
+                                |binary_store| match binary_store.compact(None) {
+                                    Ok(()) => {
+                                        self.compacting.set(true);
+                                        let _ = self.write_bytes(b"Compacting app storage\r\n");
+                                    }
+                                    Err(_) => {
+                                        let _ = self.write_bytes(b"App storage is busy\r\n");
+                                    }
+                                },
+                            );
+                        } else if clean_str.starts_with("checkpoint") {
+                            self.checkpointer.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(b"No checkpoints on this board\r\n");
+                                },
+                                |checkpointer| {
+                                    let result = match clean_str.split_whitespace().nth(1) {
+                                        None => checkpointer.checkpoint_all(),
+                                        Some(name) => self
+                                            .kernel
+                                            .process_iter_capability(&self.capability)
+                                            .find(|proc| proc.get_process_name() == name)
+                                            .map_or(Err(ErrorCode::INVAL), |proc| {
+                                                checkpointer.checkpoint(proc.processid())
+                                            }),
+                                    };
+                                    let mut console_writer = ConsoleWriter::new();
+                                    let _ = match result {
+                                        Ok(()) => write(
+                                            &mut console_writer,
+                                            format_args!("Checkpointing\r\n"),
+                                        ),
+                                        Err(ErrorCode::INVAL) => write(
+                                            &mut console_writer,
+                                            format_args!("Usage: checkpoint [<name>]\r\n"),
+                                        ),
+                                        Err(e) => write(
+                                            &mut console_writer,
+                                            format_args!("Unable to checkpoint: {:?}\r\n", e),
+                                        ),
+                                    };
+                                    let _ = self.write_bytes(
+                                        &(console_writer.buf)[..console_writer.size],
+                                    );
+                                },
+                            );
+                        } else if clean_str.starts_with("trace") {
+                            self.syscall_trace.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(b"No syscall trace on this board\r\n");
+                                },
+                                |syscall_trace| {
+                                    let mut arguments = clean_str.split_whitespace().skip(1);
+                                    match (arguments.next(), arguments.next()) {
+                                        (None, _) => {
+                                            let total = syscall_trace.record_count();
+                                            let _ = self.write_bytes(
+                                                b" #    Time (us)   PID    Syscall = Return\r\n",
+                                            );
+                                            if total > 0 {
+                                                let state =
+                                                    WriterState::TraceList { index: 0, total };
+                                                self.writer_state.replace(state);
+                                                self.create_state_buffer(state);
+                                            }
+                                        }
+                                        (Some(action @ ("start" | "stop")), Some(name)) => {
+                                            let enabled = action == "start";
+                                            self.kernel.process_each_capability(
+                                                &self.capability,
+                                                |proc| {
+                                                    if proc.get_process_name() != name {
+                                                        return;
+                                                    }
+                                                    let mut console_writer = ConsoleWriter::new();
+                                                    let _ = match syscall_trace
+                                                        .set_tracing(proc.processid(), enabled)
+                                                    {
+                                                        Ok(()) if enabled => write(
+                                                            &mut console_writer,
+                                                            format_args!("Tracing {}\r\n", name),
+                                                        ),
+                                                        Ok(()) => write(
+                                                            &mut console_writer,
+                                                            format_args!(
+                                                                "Stopped tracing {}\r\n",
+                                                                name
+                                                            ),
+                                                        ),
+                                                        Err(_) => write(
+                                                            &mut console_writer,
+                                                            format_args!(
+                                                                "Too many traced processes\r\n"
+                                                            ),
+                                                        ),
+                                                    };
+                                                    let _ = self.write_bytes(
+                                                        &(console_writer.buf)[..console_writer.size],
+                                                    );
+                                                },
+                                            );
+                                        }
+                                        (Some("raw"), None) => {
+                                            // The binary trace follows this
+                                            // line, so host tools can find it.
+                                            let _ = self.write_bytes(b"Binary syscall trace:\r\n");
+                                            self.writer_state
+                                                .replace(WriterState::TraceBinary { context: None });
+                                        }
+                                        (Some("clear"), None) => {
+                                            syscall_trace.clear();
+                                            let _ = self.write_bytes(b"Cleared syscall trace\r\n");
+                                        }
+                                        _ => {
+                                            let _ = self.write_bytes(
+                                                b"Usage: trace [start <name> | stop <name> | raw | clear]\r\n",
+                                            );
+                                        }
+                                    }
+                                },
+                            );
+                        } else if clean_str.starts_with("kvhealth") {
+                            self.kv_health.map_or_else(
+                                || {
+                                    let _ = self.write_bytes(b"No KV store on this board\r\n");
+                                },
+                                |kv_health| {
+                                    if self.kv_health_totals.is_some() {
+                                        let _ = self.write_bytes(b"KV store is busy\r\n");
+                                        return;
+                                    }
+                                    self.kv_health_totals.set(KVHealthTotals {
+                                        min_erase_count: u32::MAX,
+                                        ..KVHealthTotals::default()
+                                    });
+                                    if kv_health.region_health(0).is_err() {
+                                        self.kv_health_totals.clear();
+                                        let _ = self.write_bytes(b"Unable to read the KV store\r\n");
+                                    }
+                                },
+                            );
                         } else if clean_str.starts_with("panic") {
                             panic!("Process Console forced a kernel panic.");
                         } else {
@@ -1128,6 +1621,135 @@ impl<
     }
 }
 
+impl<
+    'a,
+    const COMMAND_HISTORY_LEN: usize,
+    A: Alarm<'a>,
+    C: ProcessManagementCapability + ProcessStartCapability,
+> DynamicBinaryCompactClient for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
+{
+    fn compact_done(&self, result: Result<(), ErrorCode>, moved: usize) {
+        if !self.compacting.take() {
+            return;
+        }
+        let mut console_writer = ConsoleWriter::new();
+        let _ = match result {
+            Ok(()) => write(
+                &mut console_writer,
+                format_args!("Compacted app storage, moved {} apps\r\n", moved),
+            ),
+            Err(e) => write(
+                &mut console_writer,
+                format_args!(
+                    "Failed to compact app storage after moving {} apps: {:?}\r\n",
+                    moved, e
+                ),
+            ),
+        };
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+}
+
+impl<
+    'a,
+    const COMMAND_HISTORY_LEN: usize,
+    A: Alarm<'a>,
+    C: ProcessManagementCapability + ProcessStartCapability,
+> CheckpointClient for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
+{
+    fn checkpoint_done(&self, processid: ProcessId, result: Result<(), ErrorCode>) {
+        let name = self.kernel.process_map_or_external(
+            "",
+            processid,
+            |proc| proc.get_process_name(),
+            &self.capability,
+        );
+        let mut console_writer = ConsoleWriter::new();
+        let _ = match result {
+            Ok(()) => write(
+                &mut console_writer,
+                format_args!("Checkpointed {}\r\n", name),
+            ),
+            Err(e) => write(
+                &mut console_writer,
+                format_args!("Failed to checkpoint {}: {:?}\r\n", name, e),
+            ),
+        };
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+}
+
+impl<
+    'a,
+    const COMMAND_HISTORY_LEN: usize,
+    A: Alarm<'a>,
+    C: ProcessManagementCapability + ProcessStartCapability,
+> kv::KVHealthClient for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
+{
+    fn region_health_complete(&self, result: Result<kv::KVRegionHealth, ErrorCode>, region: usize) {
+        let Some(mut totals) = self.kv_health_totals.take() else {
+            return;
+        };
+
+        let mut console_writer = ConsoleWriter::new();
+        match result {
+            Ok(health) => {
+                totals.region = region + 1;
+                totals.min_erase_count = totals.min_erase_count.min(health.erase_count);
+                totals.max_erase_count = totals.max_erase_count.max(health.erase_count);
+                totals.total_erase_count += health.erase_count as u64;
+                totals.used += health.used;
+                totals.reclaimable += health.reclaimable;
+                totals.free += health.free;
+
+                let next = self.kv_health.map_or(Err(ErrorCode::FAIL), |kv_health| {
+                    if totals.region < kv_health.region_count() {
+                        kv_health.region_health(totals.region).map(|()| true)
+                    } else {
+                        Ok(false)
+                    }
+                });
+                match next {
+                    Ok(true) => {
+                        // Wait for the next region.
+                        self.kv_health_totals.set(totals);
+                        return;
+                    }
+                    Ok(false) => {
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!(
+                                "KV store: {} regions, erase counts min {} max {} total {}\r\n \
+                                 Used {} reclaimable {} free {} bytes\r\n",
+                                totals.region,
+                                totals.min_erase_count,
+                                totals.max_erase_count,
+                                totals.total_erase_count,
+                                totals.used,
+                                totals.reclaimable,
+                                totals.free,
+                            ),
+                        );
+                    }
+                    Err(_) => {
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("Failed to read KV store region {}\r\n", totals.region),
+                        );
+                    }
+                }
+            }
+            Err(_) => {
+                let _ = write(
+                    &mut console_writer,
+                    format_args!("Failed to read KV store region {}\r\n", region),
+                );
+            }
+        }
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+}
+
 impl<
     'a,
     const COMMAND_HISTORY_LEN: usize,
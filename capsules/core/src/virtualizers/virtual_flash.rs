@@ -239,3 +239,105 @@ impl<F: hil::flash::Flash> hil::flash::Flash for FlashUser<'_, F> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capsules_test_harness::flash::{MockFlash, Page, static_page};
+    use capsules_test_harness::{deferred_call, leak, run_until_idle};
+    use kernel::hil::flash::{Error, Flash, HasClient};
+
+    type User = FlashUser<'static, MockFlash>;
+
+    struct Recorder {
+        read: Cell<Option<Result<(), Error>>>,
+        write: Cell<Option<Result<(), Error>>>,
+        erase: Cell<Option<Result<(), Error>>>,
+        buffer: TakeCell<'static, Page<512>>,
+    }
+
+    impl Recorder {
+        fn new() -> Self {
+            Self {
+                read: Cell::new(None),
+                write: Cell::new(None),
+                erase: Cell::new(None),
+                buffer: TakeCell::empty(),
+            }
+        }
+    }
+
+    impl hil::flash::Client<User> for Recorder {
+        fn read_complete(&self, buffer: &'static mut Page<512>, result: Result<(), Error>) {
+            self.buffer.replace(buffer);
+            self.read.set(Some(result));
+        }
+
+        fn write_complete(&self, buffer: &'static mut Page<512>, result: Result<(), Error>) {
+            self.buffer.replace(buffer);
+            self.write.set(Some(result));
+        }
+
+        fn erase_complete(&self, result: Result<(), Error>) {
+            self.erase.set(Some(result));
+        }
+    }
+
+    fn user(mux: &'static MuxFlash<'static, MockFlash>) -> (&'static User, &'static Recorder) {
+        let user = leak(FlashUser::new(mux));
+        let client = leak(Recorder::new());
+        user.set_client(client);
+        (user, client)
+    }
+
+    #[test]
+    fn operations_from_two_users_are_serialized() {
+        deferred_call::run(|| {
+            let flash = leak(MockFlash::new(4));
+            flash.write(2 * 512, &[0x5a; 512]);
+            let mux = leak(MuxFlash::new(flash));
+            flash.set_client(mux);
+            let (writer, writer_client) = user(mux);
+            let (reader, reader_client) = user(mux);
+
+            let page = static_page::<512>();
+            page.0.fill(0xa5);
+            assert!(writer.write_page(1, page).is_ok());
+            assert!(reader.read_page(2, static_page()).is_ok());
+            // Only one operation is handed to the flash at a time.
+            assert!(reader_client.read.get().is_none());
+
+            run_until_idle(&[flash]);
+
+            assert_eq!(writer_client.write.get(), Some(Ok(())));
+            assert_eq!(flash.read(512, 512), [0xa5; 512]);
+            assert_eq!(reader_client.read.get(), Some(Ok(())));
+            assert_eq!(
+                reader_client.buffer.map(|page| page.0 == [0x5a; 512]),
+                Some(true)
+            );
+        });
+    }
+
+    #[test]
+    fn failure_is_reported_to_the_user_in_flight() {
+        deferred_call::run(|| {
+            let flash = leak(MockFlash::new(2));
+            flash.write(0, &[0; 1024]);
+            let mux = leak(MuxFlash::new(flash));
+            flash.set_client(mux);
+            let (first, first_client) = user(mux);
+            let (second, second_client) = user(mux);
+
+            flash.fail_next();
+            assert!(first.erase_page(0).is_ok());
+            assert!(second.erase_page(1).is_ok());
+            run_until_idle(&[flash]);
+
+            assert_eq!(first_client.erase.get(), Some(Err(Error::FlashError)));
+            assert_eq!(flash.read(0, 512), [0; 512]);
+            assert_eq!(second_client.erase.get(), Some(Ok(())));
+            assert_eq!(flash.read(512, 512), [0xff; 512]);
+        });
+    }
+}
@@ -462,3 +462,97 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> i2c::SMBusDevice
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capsules_test_harness::i2c::{MockI2C, Script};
+    use capsules_test_harness::{deferred_call, leak, run_until_idle, static_buf};
+    use kernel::hil::i2c::I2CDevice as _;
+    use kernel::hil::i2c::I2CMaster;
+
+    struct Recorder {
+        status: Cell<Option<Result<(), Error>>>,
+        buffer: TakeCell<'static, [u8]>,
+    }
+
+    impl Recorder {
+        fn new() -> Self {
+            Self {
+                status: Cell::new(None),
+                buffer: TakeCell::empty(),
+            }
+        }
+    }
+
+    impl I2CClient for Recorder {
+        fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+            self.buffer.replace(buffer);
+            self.status.set(Some(status));
+        }
+    }
+
+    fn mux(i2c: &'static MockI2C<'static>) -> &'static MuxI2C<'static, MockI2C<'static>> {
+        let mux = leak(MuxI2C::new(i2c, None));
+        i2c.set_master_client(mux);
+        mux.register();
+        mux
+    }
+
+    #[test]
+    fn operations_from_two_devices_are_serialized() {
+        deferred_call::run(|| {
+            let i2c = leak(MockI2C::new());
+            let sensor = leak(Script::new());
+            sensor.expect_write(&[0x01]).expect_read(&[0x12, 0x34]);
+            let eeprom = leak(Script::new());
+            eeprom.expect_write(&[0x00, 0xaa]);
+            i2c.add_device(0x40, sensor);
+            i2c.add_device(0x50, eeprom);
+            let mux = mux(i2c);
+
+            let sensor_client = leak(Recorder::new());
+            let sensor_device = leak(I2CDevice::new(mux, 0x40));
+            sensor_device.set_client(sensor_client);
+            let eeprom_client = leak(Recorder::new());
+            let eeprom_device = leak(I2CDevice::new(mux, 0x50));
+            eeprom_device.set_client(eeprom_client);
+
+            let buffer = static_buf(2);
+            buffer[0] = 0x01;
+            assert!(sensor_device.write_read(buffer, 1, 2).is_ok());
+            let buffer = static_buf(2);
+            buffer.copy_from_slice(&[0x00, 0xaa]);
+            assert!(eeprom_device.write(buffer, 2).is_ok());
+
+            run_until_idle(&[i2c]);
+
+            assert_eq!(sensor_client.status.get(), Some(Ok(())));
+            assert_eq!(
+                sensor_client.buffer.map(|buffer| [buffer[0], buffer[1]]),
+                Some([0x12, 0x34])
+            );
+            assert_eq!(eeprom_client.status.get(), Some(Ok(())));
+            assert!(sensor.is_done());
+            assert!(eeprom.is_done());
+        });
+    }
+
+    #[test]
+    fn missing_device_is_not_acknowledged() {
+        deferred_call::run(|| {
+            let i2c = leak(MockI2C::new());
+            let mux = mux(i2c);
+
+            let client = leak(Recorder::new());
+            let device = leak(I2CDevice::new(mux, 0x41));
+            device.set_client(client);
+
+            assert!(device.read(static_buf(1), 1).is_ok());
+            run_until_idle(&[i2c]);
+
+            assert_eq!(client.status.get(), Some(Err(Error::AddressNak)));
+            assert!(client.buffer.is_some());
+        });
+    }
+}
@@ -65,7 +65,8 @@
 //!
 //! NOTE:
 //! 1. This capsule is not virtualized, and can only serve one app at a time.
-//! 2. This implementation currently only loads new apps. It does not update apps.
+//! 2. To update an app, write the new version and finalize it as for a new
+//!    app, and then request an update of the running app instead of a load.
 //! ```
 
 use core::cell::Cell;
@@ -100,8 +101,12 @@ mod upcall {
     pub const ABORT_DONE: usize = 4;
     /// Unload done callback.
     pub const UNLOAD_DONE: usize = 5;
+    /// Update done callback.
+    pub const UPDATE_DONE: usize = 6;
+    /// Compact done callback.
+    pub const COMPACT_DONE: usize = 7;
     /// Number of upcalls.
-    pub const COUNT: u8 = 6;
+    pub const COUNT: u8 = 8;
 }
 
 // Ids for read-only allow buffers
@@ -143,6 +148,9 @@ pub struct AppLoader<
     // What issued the currently executing call.
     current_process: OptionalCell<ProcessId>,
     new_app_length: Cell<usize>,
+    // Whether the current process requested compaction, which the kernel may
+    // also do for other clients.
+    compacting: Cell<bool>,
 }
 
 impl<
@@ -171,9 +179,52 @@ impl<
             buffer: TakeCell::new(buffer),
             current_process: OptionalCell::empty(),
             new_app_length: Cell::new(0),
+            compacting: Cell::new(false),
         }
     }
 
+    /// Signal the result of a load or update to the requesting app.
+    ///
+    /// Error Type Mapping.
+    ///
+    /// This method converts `ProcessLoadError` to `ErrorCode` so it can be
+    /// passed to userspace.
+    ///
+    /// Currently,
+    /// 1. ProcessLoadError::NotEnoughMemory       <==> ErrorCode::NOMEM
+    /// 2. ProcessLoadError::MpuInvalidFlashLength <==> ErrorCode::INVAL
+    /// 3. ProcessLoadError::ShortIdMismatch       <==> ErrorCode::INVAL
+    /// 4. ProcessLoadError::InternalError         <==> ErrorCode::OFF
+    /// 5. All other ProcessLoadError types        <==> ErrorCode::FAIL
+    fn load_or_update_done(&self, upcall_num: usize, result: Result<(), ProcessLoadError>) {
+        let status_code = match result {
+            Ok(()) => Ok(()),
+            Err(e) => match e {
+                ProcessLoadError::NotEnoughMemory => Err(ErrorCode::NOMEM),
+                ProcessLoadError::MpuInvalidFlashLength => Err(ErrorCode::INVAL),
+                ProcessLoadError::MpuConfigurationError => Err(ErrorCode::FAIL),
+                ProcessLoadError::MemoryAddressMismatch { .. } => Err(ErrorCode::FAIL),
+                ProcessLoadError::NoProcessSlot => Err(ErrorCode::FAIL),
+                ProcessLoadError::BinaryError(_) => Err(ErrorCode::FAIL),
+                ProcessLoadError::CheckError(_) => Err(ErrorCode::FAIL),
+                ProcessLoadError::ShortIdMismatch => Err(ErrorCode::INVAL),
+                // This error is usually a result of bug in the kernel
+                // so we return Powered OFF error, because that is unlikely.
+                ProcessLoadError::InternalError => Err(ErrorCode::OFF),
+            },
+        };
+
+        self.current_process.map(|processid| {
+            let _ = self.apps.enter(processid, move |app, kernel_data| {
+                app.pending_command = false;
+                // Signal the app.
+                self.current_process.take();
+                let _ =
+                    kernel_data.schedule_upcall(upcall_num, (into_statuscode(status_code), 0, 0));
+            });
+        });
+    }
+
     /// Copy data from the shared buffer with app and request kernel to
     /// write the app data to flash.
     fn write(&self, offset: usize, length: usize, processid: ProcessId) -> Result<(), ErrorCode> {
@@ -308,6 +359,23 @@ impl<
             });
         });
     }
+
+    /// Let the app know we are done compacting, and how many apps were moved
+    fn compact_done(&self, result: Result<(), ErrorCode>, moved: usize) {
+        if !self.compacting.take() {
+            return;
+        }
+        self.current_process.map(|processid| {
+            let _ = self.apps.enter(processid, move |app, kernel_data| {
+                // And then signal the app.
+                app.pending_command = false;
+
+                self.current_process.take();
+                let _ = kernel_data
+                    .schedule_upcall(upcall::COMPACT_DONE, (into_statuscode(result), moved, 0));
+            });
+        });
+    }
 }
 
 impl<
@@ -317,43 +385,15 @@ impl<
 > dynamic_binary_storage::DynamicProcessLoadClient for AppLoader<S, L, T>
 {
     /// Let the requesting app know we are done loading the new process
-    ///
-    /// Error Type Mapping.
-    ///
-    /// This method converts `ProcessLoadError` to `ErrorCode` so it can be
-    /// passed to userspace.
-    ///
-    /// Currently,
-    /// 1. ProcessLoadError::NotEnoughMemory       <==> ErrorCode::NOMEM
-    /// 2. ProcessLoadError::MpuInvalidFlashLength <==> ErrorCode::INVAL
-    /// 3. ProcessLoadError::InternalError         <==> ErrorCode::OFF
-    /// 4. All other ProcessLoadError types        <==> ErrorCode::FAIL
     fn load_done(&self, result: Result<(), ProcessLoadError>) {
-        let status_code = match result {
-            Ok(()) => Ok(()),
-            Err(e) => match e {
-                ProcessLoadError::NotEnoughMemory => Err(ErrorCode::NOMEM),
-                ProcessLoadError::MpuInvalidFlashLength => Err(ErrorCode::INVAL),
-                ProcessLoadError::MpuConfigurationError => Err(ErrorCode::FAIL),
-                ProcessLoadError::MemoryAddressMismatch { .. } => Err(ErrorCode::FAIL),
-                ProcessLoadError::NoProcessSlot => Err(ErrorCode::FAIL),
-                ProcessLoadError::BinaryError(_) => Err(ErrorCode::FAIL),
-                ProcessLoadError::CheckError(_) => Err(ErrorCode::FAIL),
-                // This error is usually a result of bug in the kernel
-                // so we return Powered OFF error, because that is unlikely.
-                ProcessLoadError::InternalError => Err(ErrorCode::OFF),
-            },
-        };
+        self.load_or_update_done(upcall::LOAD_DONE, result);
+    }
 
-        self.current_process.map(|processid| {
-            let _ = self.apps.enter(processid, move |app, kernel_data| {
-                app.pending_command = false;
-                // Signal the app.
-                self.current_process.take();
-                let _ = kernel_data
-                    .schedule_upcall(upcall::LOAD_DONE, (into_statuscode(status_code), 0, 0));
-            });
-        });
+    /// Let the requesting app know the new process replaced the previous
+    /// version, or that the update failed and the previous version was
+    /// restored
+    fn update_done(&self, result: Result<(), ProcessLoadError>) {
+        self.load_or_update_done(upcall::UPDATE_DONE, result);
     }
 }
 
@@ -428,6 +468,20 @@ impl<
     /// - `6`: Request kernel to unload a processs
     ///  - Returns Ok(()) when the application is successfully scheduled for unload
     ///  - Returns ErrorCode::FAIL when the unload fails
+    /// - `7`: Request kernel to load the new app in place of the running app
+    ///   with the ShortId in `arg1`. This is called instead of `4`.
+    ///  - Returns Ok(()) when the update has started. The update done upcall
+    ///    reports whether the new app replaced the running app, or failed to
+    ///    load and the running app was restored. The update fails with
+    ///    ErrorCode::INVAL if the new app does not have the same ShortId.
+    ///  - Returns ErrorCode::INVAL if there is no app with that ShortId or the
+    ///    new app has not been finalized
+    /// - `8`: Request kernel to move stored apps to close the gaps between
+    ///   them. Apps that are moved are restarted. The calling app is never
+    ///   moved.
+    ///  - Returns Ok(()) when compaction has started. The compact done upcall
+    ///    reports the number of apps that were moved.
+    ///  - Returns ErrorCode::BUSY if an app is being loaded or unloaded
     ///
     /// The driver returns ErrorCode::INVAL if any operation is called before the
     /// preceeding operation was invoked. For example, `write()` cannot be called before
@@ -568,6 +622,43 @@ impl<
                     }
                 }
             }
+
+            7 => {
+                // Request the kernel to replace the process with the ShortId
+                // in `arg1` with the new app.
+                let shortid = match NonZeroU32::new(arg1 as u32) {
+                    Some(id) => ShortId::Fixed(id),
+                    None => return CommandReturn::failure(ErrorCode::INVAL),
+                };
+                let res = self.load_driver.update(shortid);
+                match res {
+                    Ok(()) => {
+                        self.new_app_length.set(0);
+                        CommandReturn::success()
+                    }
+                    Err(e) => {
+                        self.new_app_length.set(0);
+                        self.current_process.take();
+                        CommandReturn::failure(e)
+                    }
+                }
+            }
+
+            8 => {
+                // Request the kernel to compact the stored apps. The binary of
+                // this process is not moved, so that it can be notified.
+                let res = self.storage_driver.compact(Some(processid));
+                match res {
+                    Ok(()) => {
+                        self.compacting.set(true);
+                        CommandReturn::success()
+                    }
+                    Err(e) => {
+                        self.current_process.take();
+                        CommandReturn::failure(e)
+                    }
+                }
+            }
             // Unsupported command numbers.
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
@@ -577,3 +668,305 @@ impl<
         self.apps.enter(processid, |_, _| {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use capsules_test_harness::loader::{AppFlash, DynamicBinaryStorage};
+    use capsules_test_harness::process::{App, HostKernel, Tbf, Upcall};
+    use capsules_test_harness::{deferred_call, leak, run_until_idle, static_buf};
+    use kernel::dynamic_binary_storage::{
+        DynamicBinaryStore, DynamicProcessLoad, DynamicProcessUnload,
+    };
+    use kernel::syscall::SyscallReturn;
+    use std::vec::Vec;
+
+    const FLASH_SIZE: usize = 4096;
+    /// Binaries are a power of two long, so that the loader stores them back
+    /// to back.
+    const BINARY_SIZE: usize = 256;
+    const OLD_ID: u32 = 0x10;
+
+    fn short_id(id: u32) -> ShortId {
+        ShortId::Fixed(NonZeroU32::new(id).unwrap())
+    }
+
+    fn binary(name: &str, id: u32) -> Vec<u8> {
+        Tbf::new(name).short_id(id).size(BINARY_SIZE).build()
+    }
+
+    /// A padding binary of `len` bytes, of which only the header is written.
+    fn padding(len: usize) -> Vec<u8> {
+        let mut header = [2u16.to_le_bytes(), 16u16.to_le_bytes()].concat();
+        header.extend_from_slice(&(len as u32).to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+        let checksum = [0, 4, 8]
+            .map(|i| u32::from_le_bytes(header[i..i + 4].try_into().unwrap()))
+            .iter()
+            .fold(0, |checksum, word| checksum ^ word);
+        header.extend_from_slice(&checksum.to_le_bytes());
+        header
+    }
+
+    /// Boot with `binaries` in flash at the given offsets, with the app loader
+    /// using the flash.
+    fn boot(binaries: &[(usize, Vec<u8>)]) -> (&'static HostKernel, &'static AppFlash) {
+        let kernel = HostKernel::new();
+        let flash = AppFlash::new(FLASH_SIZE);
+        for (offset, binary) in binaries {
+            flash.install(*offset, binary);
+        }
+
+        let grant = kernel.create_grant(DRIVER_NUM);
+        let storage: &'static DynamicBinaryStorage = kernel.dynamic_binary_storage(flash);
+        let loader = leak(AppLoader::new(
+            grant,
+            storage,
+            storage,
+            storage,
+            static_buf(BUF_LEN),
+        ));
+        storage.set_storage_client(loader);
+        storage.set_load_client(loader);
+        storage.set_unload_client(loader);
+        kernel.add_driver(DRIVER_NUM, loader);
+
+        run_until_idle(&[flash]);
+        (kernel, flash)
+    }
+
+    /// Subscribe to all upcalls of the app loader.
+    fn subscribe(app: &App) {
+        for upcall_num in 0..upcall::COUNT as usize {
+            app.subscribe(DRIVER_NUM, upcall_num);
+        }
+    }
+
+    /// Boot with the binary of process `OLD_ID` in flash, and load the process
+    /// which uses the app loader.
+    fn app_loader() -> (&'static HostKernel, &'static AppFlash, App) {
+        let (kernel, flash) = boot(&[(0, binary("old", OLD_ID))]);
+        assert_eq!(
+            kernel.process_flash_start(short_id(OLD_ID)),
+            Some(flash.address(0))
+        );
+        let app = kernel.load_process("updater");
+        subscribe(&app);
+        (kernel, flash, app)
+    }
+
+    /// Wait for the upcall which completes the last command, letting the
+    /// flash complete its operations.
+    fn wait(app: &App, flash: &AppFlash) -> Option<Upcall> {
+        app.yield_wait().or_else(|| {
+            run_until_idle(&[flash]);
+            app.yield_wait()
+        })
+    }
+
+    fn done(subscribe_number: usize, result: Result<(), ErrorCode>, arg: usize) -> Option<Upcall> {
+        Some(Upcall {
+            driver_number: DRIVER_NUM,
+            subscribe_number,
+            arguments: [into_statuscode(result), arg, 0],
+        })
+    }
+
+    /// Setup, write and finalize `binary` through the app loader.
+    fn store(app: &App, flash: &AppFlash, binary: &[u8]) {
+        assert!(matches!(
+            app.command(DRIVER_NUM, 1, binary.len(), 0),
+            SyscallReturn::Success
+        ));
+        assert_eq!(wait(app, flash), done(upcall::SETUP_DONE, Ok(()), 0));
+
+        let buffer = app.allocate(binary.len());
+        app.write(buffer, binary);
+        app.allow_readonly(DRIVER_NUM, ro_allow::WRITE, buffer);
+        assert!(matches!(
+            app.command(DRIVER_NUM, 2, 0, binary.len()),
+            SyscallReturn::Success
+        ));
+        assert_eq!(
+            wait(app, flash),
+            done(upcall::WRITE_DONE, Ok(()), binary.len())
+        );
+
+        assert!(matches!(
+            app.command(DRIVER_NUM, 3, 0, 0),
+            SyscallReturn::Success
+        ));
+        assert_eq!(wait(app, flash), done(upcall::FINALIZE_DONE, Ok(()), 0));
+    }
+
+    /// Whether there is a padding binary of `len` bytes at `offset`.
+    fn is_padding(flash: &AppFlash, offset: usize, len: usize) -> bool {
+        flash.read(offset, 16) == padding(len)
+    }
+
+    #[test]
+    fn update_replaces_the_process() {
+        deferred_call::run(|| {
+            let (kernel, flash, app) = app_loader();
+
+            store(&app, flash, &binary("new", OLD_ID));
+            assert!(matches!(
+                app.command(DRIVER_NUM, 7, OLD_ID as usize, 0),
+                SyscallReturn::Success
+            ));
+            assert_eq!(wait(&app, flash), done(upcall::UPDATE_DONE, Ok(()), 0));
+
+            assert_eq!(
+                kernel.process_flash_start(short_id(OLD_ID)),
+                Some(flash.address(BINARY_SIZE))
+            );
+            assert!(is_padding(flash, 0, BINARY_SIZE));
+        });
+    }
+
+    #[test]
+    fn update_with_a_different_short_id_is_rolled_back() {
+        deferred_call::run(|| {
+            let (kernel, flash, app) = app_loader();
+
+            store(&app, flash, &binary("other", 0x20));
+            assert!(matches!(
+                app.command(DRIVER_NUM, 7, OLD_ID as usize, 0),
+                SyscallReturn::Success
+            ));
+            assert_eq!(
+                wait(&app, flash),
+                done(upcall::UPDATE_DONE, Err(ErrorCode::INVAL), 0)
+            );
+
+            // The previous version runs again, and the new binary is gone.
+            assert_eq!(
+                kernel.process_flash_start(short_id(OLD_ID)),
+                Some(flash.address(0))
+            );
+            assert_eq!(kernel.process_flash_start(short_id(0x20)), None);
+            assert!(is_padding(flash, BINARY_SIZE, BINARY_SIZE));
+        });
+    }
+
+    #[test]
+    fn update_of_a_missing_process_is_rejected() {
+        deferred_call::run(|| {
+            let (kernel, flash, app) = app_loader();
+
+            store(&app, flash, &binary("new", 0x20));
+            assert!(matches!(
+                app.command(DRIVER_NUM, 7, 0x20, 0),
+                SyscallReturn::Failure(ErrorCode::INVAL)
+            ));
+            assert_eq!(
+                kernel.process_flash_start(short_id(OLD_ID)),
+                Some(flash.address(0))
+            );
+        });
+    }
+
+    #[test]
+    fn first_binary_goes_to_the_start_of_empty_flash() {
+        deferred_call::run(|| {
+            let (kernel, flash) = boot(&[]);
+            let app = kernel.load_process("installer");
+            subscribe(&app);
+
+            let new = binary("new", 0x20);
+            store(&app, flash, &new);
+            assert!(matches!(
+                app.command(DRIVER_NUM, 4, 0, 0),
+                SyscallReturn::Success
+            ));
+            assert_eq!(wait(&app, flash), done(upcall::LOAD_DONE, Ok(()), 0));
+
+            // There is no binary before or after the new one to pad up to.
+            assert_eq!(flash.read(0, BINARY_SIZE), new);
+            assert!(
+                flash
+                    .read(BINARY_SIZE, FLASH_SIZE - BINARY_SIZE)
+                    .iter()
+                    .all(|&byte| byte == 0xff)
+            );
+            assert_eq!(
+                kernel.process_flash_start(short_id(0x20)),
+                Some(flash.address(0))
+            );
+        });
+    }
+
+    #[test]
+    fn compaction_moves_binaries_into_gaps() {
+        deferred_call::run(|| {
+            let first = binary("first", 0x10);
+            let second = binary("second", 0x11);
+            let (kernel, flash) = boot(&[
+                (0, padding(BINARY_SIZE)),
+                (BINARY_SIZE, first.clone()),
+                (2 * BINARY_SIZE, padding(BINARY_SIZE)),
+                (3 * BINARY_SIZE, second.clone()),
+            ]);
+            let app = kernel.load_process("compactor");
+            subscribe(&app);
+
+            assert!(matches!(
+                app.command(DRIVER_NUM, 8, 0, 0),
+                SyscallReturn::Success
+            ));
+            assert_eq!(wait(&app, flash), done(upcall::COMPACT_DONE, Ok(()), 2));
+
+            // The binaries are stored back to back, followed by one padding
+            // binary over the rest of the gaps and the old copies.
+            assert_eq!(flash.read(0, BINARY_SIZE), first);
+            assert_eq!(flash.read(BINARY_SIZE, BINARY_SIZE), second);
+            assert!(is_padding(flash, 2 * BINARY_SIZE, 2 * BINARY_SIZE));
+
+            // The moved processes run from their new binaries.
+            assert_eq!(
+                kernel.process_flash_start(short_id(0x10)),
+                Some(flash.address(0))
+            );
+            assert_eq!(
+                kernel.process_flash_start(short_id(0x11)),
+                Some(flash.address(BINARY_SIZE))
+            );
+        });
+    }
+
+    #[test]
+    fn compaction_does_not_move_the_caller() {
+        deferred_call::run(|| {
+            let other = binary("other", 0x11);
+            let (kernel, flash) = boot(&[
+                (0, padding(BINARY_SIZE)),
+                (BINARY_SIZE, binary("compactor", 0x20)),
+                (2 * BINARY_SIZE, padding(BINARY_SIZE)),
+                (3 * BINARY_SIZE, other.clone()),
+            ]);
+            let app = kernel.attach(short_id(0x20));
+            subscribe(&app);
+
+            assert!(matches!(
+                app.command(DRIVER_NUM, 8, 0, 0),
+                SyscallReturn::Success
+            ));
+            assert_eq!(wait(&app, flash), done(upcall::COMPACT_DONE, Ok(()), 1));
+
+            // Only the binary after the caller was moved.
+            assert!(is_padding(flash, 0, BINARY_SIZE));
+            assert_eq!(
+                kernel.process_flash_start(short_id(0x20)),
+                Some(flash.address(BINARY_SIZE))
+            );
+            assert_eq!(flash.read(2 * BINARY_SIZE, BINARY_SIZE), other);
+            assert!(is_padding(flash, 3 * BINARY_SIZE, BINARY_SIZE));
+            assert_eq!(
+                kernel.process_flash_start(short_id(0x11)),
+                Some(flash.address(2 * BINARY_SIZE))
+            );
+        });
+    }
+}
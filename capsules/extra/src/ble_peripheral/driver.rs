@@ -0,0 +1,247 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! BLE peripheral userspace interface.
+//!
+//! Lets a process advertise as a connectable device and expose one GATT
+//! characteristic to a central such as a phone. The central can read and
+//! write the characteristic and subscribe to notifications of its value.
+//!
+//! A single process uses the driver at a time: the first one to configure
+//! it or start advertising owns it until it exits.
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+use super::gatt::{GattServer, GattServerClient, MAX_VALUE_LEN};
+use super::link_layer::{LinkLayer, MAX_ADV_DATA_LEN};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::BlePeripheral as usize;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// A central connected.
+    pub const CONNECTED: usize = 0;
+    /// The connection ended. Called with the disconnect reason.
+    pub const DISCONNECTED: usize = 1;
+    /// The central wrote the characteristic. Called with the length of the
+    /// value, which has been copied into the write buffer.
+    pub const WRITTEN: usize = 2;
+    /// The central subscribed (1) to or unsubscribed (0) from notifications.
+    pub const SUBSCRIBED: usize = 3;
+    /// A notification was delivered.
+    pub const NOTIFIED: usize = 4;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 5;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Advertising data, a sequence of AD structures of at most 31 bytes.
+    pub const ADV_DATA: usize = 0;
+    /// Characteristic value, at most 20 bytes.
+    pub const VALUE: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Receives values written by the central.
+    pub const WRITE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct BlePeripheralDriver<'a> {
+    link_layer: &'a dyn LinkLayer<'a>,
+    gatt: &'a GattServer<'a>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    owner: OptionalCell<ProcessId>,
+}
+
+impl<'a> BlePeripheralDriver<'a> {
+    pub fn new(
+        link_layer: &'a dyn LinkLayer<'a>,
+        gatt: &'a GattServer<'a>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> BlePeripheralDriver<'a> {
+        BlePeripheralDriver {
+            link_layer,
+            gatt,
+            apps: grant,
+            owner: OptionalCell::empty(),
+        }
+    }
+
+    /// Makes `processid` the owner of the driver, unless another process
+    /// that still exists owns it.
+    fn claim(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let owned_by_other = self.owner.map_or(false, |owner| {
+            owner != processid && self.apps.enter(owner, |_, _| ()).is_ok()
+        });
+        if owned_by_other {
+            return Err(ErrorCode::BUSY);
+        }
+        self.owner.set(processid);
+        Ok(())
+    }
+
+    /// Copies the first `N` bytes at most of a read-only buffer and returns
+    /// them with their length. Returns SIZE if the buffer is longer.
+    fn read_allowed<const N: usize>(
+        &self,
+        processid: ProcessId,
+        allow_num: usize,
+    ) -> Result<([u8; N], usize), ErrorCode> {
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(allow_num)
+                    .and_then(|buffer| {
+                        buffer.enter(|data| {
+                            if data.len() > N {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            let mut copy = [0; N];
+                            data.copy_to_slice(&mut copy[..data.len()]);
+                            Ok((copy, data.len()))
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    fn start_advertising(&self, processid: ProcessId, interval_ms: u32) -> Result<(), ErrorCode> {
+        let (adv_data, len) =
+            self.read_allowed::<MAX_ADV_DATA_LEN>(processid, ro_allow::ADV_DATA)?;
+        self.link_layer
+            .start_advertising(&adv_data[..len], interval_ms)
+    }
+
+    fn set_value(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let (value, len) = self.read_allowed::<MAX_VALUE_LEN>(processid, ro_allow::VALUE)?;
+        self.gatt.set_value(&value[..len])
+    }
+
+    fn schedule_upcall(&self, upcall_num: usize, data: (usize, usize, usize)) {
+        self.owner.map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let _ = kernel_data.schedule_upcall(upcall_num, data);
+            });
+        });
+    }
+}
+
+impl SyscallDriver for BlePeripheralDriver<'_> {
+    /// BLE peripheral control
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Set the 16-bit UUIDs of the service (`arg1`) and of its
+    ///   characteristic (`arg2`).
+    /// - `2`: Start connectable advertising of the advertising data buffer
+    ///   every `arg1` milliseconds (20 to 10240). Returns BUSY if already
+    ///   advertising or connected.
+    /// - `3`: Stop advertising.
+    /// - `4`: Disconnect from the central.
+    /// - `5`: Set the characteristic value to the contents of the value
+    ///   buffer.
+    /// - `6`: Notify the central of the characteristic value. Returns OFF if
+    ///   the central has not subscribed to notifications.
+    ///
+    /// Commands 1 to 6 return BUSY if another process owns the driver.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => return CommandReturn::success(),
+            1..=6 => {}
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+        if let Err(e) = self.claim(processid) {
+            return CommandReturn::failure(e);
+        }
+        let result = match command_num {
+            1 => {
+                if arg1 > u16::MAX as usize || arg2 > u16::MAX as usize {
+                    Err(ErrorCode::INVAL)
+                } else {
+                    self.gatt.set_uuids(arg1 as u16, arg2 as u16);
+                    Ok(())
+                }
+            }
+            2 => self.start_advertising(processid, arg1 as u32),
+            3 => self.link_layer.stop_advertising(),
+            4 => self.link_layer.disconnect(),
+            5 => self.set_value(processid),
+            6 => self.gatt.notify(),
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+        CommandReturn::from(result)
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+impl GattServerClient for BlePeripheralDriver<'_> {
+    fn connected(&self) {
+        self.schedule_upcall(upcall::CONNECTED, (0, 0, 0));
+    }
+
+    fn disconnected(&self, reason: u8) {
+        self.schedule_upcall(upcall::DISCONNECTED, (reason as usize, 0, 0));
+    }
+
+    fn value_written(&self, value: &[u8]) {
+        self.owner.map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let len = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::WRITE)
+                    .and_then(|write| {
+                        write.mut_enter(|buf| {
+                            let len = value.len().min(buf.len());
+                            buf[..len].copy_from_slice(&value[..len]);
+                            len
+                        })
+                    })
+                    .unwrap_or(0);
+                let _ = kernel_data.schedule_upcall(upcall::WRITTEN, (len, 0, 0));
+            });
+        });
+    }
+
+    fn notifications_enabled(&self, enabled: bool) {
+        self.schedule_upcall(upcall::SUBSCRIBED, (enabled as usize, 0, 0));
+    }
+
+    fn notify_done(&self) {
+        self.schedule_upcall(upcall::NOTIFIED, (0, 0, 0));
+    }
+}
@@ -0,0 +1,513 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Minimal ATT server with a fixed GATT database.
+//!
+//! The database holds the mandatory GAP service with the device name, and a
+//! single service with one characteristic that can be read, written and
+//! subscribed to for notifications:
+//!
+//! ```text
+//! Handle  Type                        Value
+//! 0x0001  Primary Service (0x2800)    GAP (0x1800)
+//! 0x0002  Characteristic (0x2803)     Read, 0x0003, Device Name (0x2a00)
+//! 0x0003  Device Name (0x2a00)        device name
+//! 0x0004  Primary Service (0x2800)    service UUID
+//! 0x0005  Characteristic (0x2803)     Read/Write/Notify, 0x0006, characteristic UUID
+//! 0x0006  characteristic UUID         characteristic value
+//! 0x0007  CCCD (0x2902)               notifications enabled
+//! ```
+//!
+//! Only 16-bit UUIDs are supported, and the ATT_MTU stays at its default of
+//! 23 bytes, so characteristic values are at most 20 bytes long.
+
+use core::cell::Cell;
+
+use kernel::ErrorCode;
+use kernel::utilities::cells::{MapCell, OptionalCell};
+
+use super::l2cap::{L2cap, L2capClient};
+
+/// ATT_MTU used for the whole connection.
+pub const ATT_MTU: usize = 23;
+
+/// Largest characteristic value that fits into a notification.
+pub const MAX_VALUE_LEN: usize = ATT_MTU - 3;
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 3, Part F], section 3.4.8
+mod att {
+    pub const ERROR_RSP: u8 = 0x01;
+    pub const EXCHANGE_MTU_REQ: u8 = 0x02;
+    pub const EXCHANGE_MTU_RSP: u8 = 0x03;
+    pub const FIND_INFORMATION_REQ: u8 = 0x04;
+    pub const FIND_INFORMATION_RSP: u8 = 0x05;
+    pub const FIND_BY_TYPE_VALUE_REQ: u8 = 0x06;
+    pub const FIND_BY_TYPE_VALUE_RSP: u8 = 0x07;
+    pub const READ_BY_TYPE_REQ: u8 = 0x08;
+    pub const READ_BY_TYPE_RSP: u8 = 0x09;
+    pub const READ_REQ: u8 = 0x0a;
+    pub const READ_RSP: u8 = 0x0b;
+    pub const READ_BLOB_REQ: u8 = 0x0c;
+    pub const READ_BLOB_RSP: u8 = 0x0d;
+    pub const READ_BY_GROUP_TYPE_REQ: u8 = 0x10;
+    pub const READ_BY_GROUP_TYPE_RSP: u8 = 0x11;
+    pub const WRITE_REQ: u8 = 0x12;
+    pub const WRITE_RSP: u8 = 0x13;
+    pub const HANDLE_VALUE_NTF: u8 = 0x1b;
+    pub const WRITE_CMD: u8 = 0x52;
+    /// Set in the opcodes of commands, which get no response.
+    pub const COMMAND_FLAG: u8 = 0x40;
+}
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 3, Part F], section 3.4.1.1
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum AttError {
+    InvalidHandle = 0x01,
+    WriteNotPermitted = 0x03,
+    RequestNotSupported = 0x06,
+    InvalidOffset = 0x07,
+    AttributeNotFound = 0x0a,
+    InvalidAttributeValueLength = 0x0d,
+    UnsupportedGroupType = 0x10,
+}
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 3, Part G], section 3
+const PRIMARY_SERVICE_UUID: u16 = 0x2800;
+const CHARACTERISTIC_UUID: u16 = 0x2803;
+const CCCD_UUID: u16 = 0x2902;
+const GAP_SERVICE_UUID: u16 = 0x1800;
+const DEVICE_NAME_UUID: u16 = 0x2a00;
+
+const PROPERTY_READ: u8 = 0x02;
+const PROPERTY_WRITE_WITHOUT_RESPONSE: u8 = 0x04;
+const PROPERTY_WRITE: u8 = 0x08;
+const PROPERTY_NOTIFY: u8 = 0x10;
+
+const GAP_SERVICE_HANDLE: u16 = 0x0001;
+const DEVICE_NAME_DECLARATION_HANDLE: u16 = 0x0002;
+const DEVICE_NAME_HANDLE: u16 = 0x0003;
+const SERVICE_HANDLE: u16 = 0x0004;
+const VALUE_DECLARATION_HANDLE: u16 = 0x0005;
+const VALUE_HANDLE: u16 = 0x0006;
+const CCCD_HANDLE: u16 = 0x0007;
+const LAST_HANDLE: u16 = CCCD_HANDLE;
+
+/// Notifications flag in the client characteristic configuration.
+const CCCD_NOTIFY: u16 = 0x0001;
+
+pub trait GattServerClient {
+    fn connected(&self);
+    /// The connection ended, see `link_layer::reason`.
+    fn disconnected(&self, reason: u8);
+    /// The central wrote `value` to the characteristic.
+    fn value_written(&self, value: &[u8]);
+    /// The central subscribed to or unsubscribed from notifications.
+    fn notifications_enabled(&self, enabled: bool);
+    /// The notification started with `GattServer::notify` was delivered.
+    fn notify_done(&self);
+}
+
+pub struct GattServer<'a> {
+    l2cap: &'a L2cap<'a>,
+    client: OptionalCell<&'a dyn GattServerClient>,
+    device_name: &'static [u8],
+    service_uuid: Cell<u16>,
+    characteristic_uuid: Cell<u16>,
+    value: MapCell<[u8; MAX_VALUE_LEN]>,
+    value_len: Cell<usize>,
+    notifications: Cell<bool>,
+    /// Response that L2CAP could not take yet. A length of 0 means empty.
+    response: MapCell<[u8; ATT_MTU]>,
+    response_len: Cell<usize>,
+    notify_pending: Cell<bool>,
+    /// Whether the PDU being sent is a notification.
+    notifying: Cell<bool>,
+}
+
+impl<'a> GattServer<'a> {
+    pub fn new(l2cap: &'a L2cap<'a>, device_name: &'static [u8]) -> GattServer<'a> {
+        GattServer {
+            l2cap,
+            client: OptionalCell::empty(),
+            device_name,
+            service_uuid: Cell::new(0),
+            characteristic_uuid: Cell::new(0),
+            value: MapCell::new([0; MAX_VALUE_LEN]),
+            value_len: Cell::new(0),
+            notifications: Cell::new(false),
+            response: MapCell::new([0; ATT_MTU]),
+            response_len: Cell::new(0),
+            notify_pending: Cell::new(false),
+            notifying: Cell::new(false),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn GattServerClient) {
+        self.client.set(client);
+    }
+
+    /// Set the 16-bit UUIDs of the service and of its characteristic.
+    pub fn set_uuids(&self, service: u16, characteristic: u16) {
+        self.service_uuid.set(service);
+        self.characteristic_uuid.set(characteristic);
+    }
+
+    /// Set the characteristic value returned to reads and notifications.
+    pub fn set_value(&self, value: &[u8]) -> Result<(), ErrorCode> {
+        if value.len() > MAX_VALUE_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        self.value
+            .map(|buf| buf[..value.len()].copy_from_slice(value));
+        self.value_len.set(value.len());
+        Ok(())
+    }
+
+    /// Notify the central of the current characteristic value. Returns OFF
+    /// if the central has not subscribed to notifications.
+    pub fn notify(&self) -> Result<(), ErrorCode> {
+        if !self.notifications.get() {
+            return Err(ErrorCode::OFF);
+        }
+        if self.notify_pending.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.notify_pending.set(true);
+        self.send_next();
+        Ok(())
+    }
+
+    // Send the waiting response, or else the pending notification, if L2CAP
+    // is free.
+    fn send_next(&self) {
+        let mut pdu = [0; ATT_MTU];
+        let len = self.response_len.get();
+        if len != 0 {
+            self.response
+                .map(|response| pdu[..len].copy_from_slice(&response[..len]));
+            if self.l2cap.send(&pdu[..len]).is_ok() {
+                self.response_len.set(0);
+                self.notifying.set(false);
+            }
+        } else if self.notify_pending.get() {
+            let value_len = self.value_len.get();
+            pdu[0] = att::HANDLE_VALUE_NTF;
+            pdu[1..3].copy_from_slice(&VALUE_HANDLE.to_le_bytes());
+            self.value
+                .map(|value| pdu[3..3 + value_len].copy_from_slice(&value[..value_len]));
+            if self.l2cap.send(&pdu[..3 + value_len]).is_ok() {
+                self.notify_pending.set(false);
+                self.notifying.set(true);
+            }
+        }
+    }
+
+    fn respond(&self, pdu: &[u8]) {
+        self.response
+            .map(|response| response[..pdu.len()].copy_from_slice(pdu));
+        self.response_len.set(pdu.len());
+        self.send_next();
+    }
+
+    fn respond_error(&self, request: u8, handle: u16, error: AttError) {
+        let handle = handle.to_le_bytes();
+        self.respond(&[att::ERROR_RSP, request, handle[0], handle[1], error as u8]);
+    }
+
+    fn attribute_type(&self, handle: u16) -> Option<u16> {
+        match handle {
+            GAP_SERVICE_HANDLE | SERVICE_HANDLE => Some(PRIMARY_SERVICE_UUID),
+            DEVICE_NAME_DECLARATION_HANDLE | VALUE_DECLARATION_HANDLE => Some(CHARACTERISTIC_UUID),
+            DEVICE_NAME_HANDLE => Some(DEVICE_NAME_UUID),
+            VALUE_HANDLE => Some(self.characteristic_uuid.get()),
+            CCCD_HANDLE => Some(CCCD_UUID),
+            _ => None,
+        }
+    }
+
+    /// Write the value of attribute `handle` into `out` and return its
+    /// length.
+    fn read_attribute(&self, handle: u16, out: &mut [u8; ATT_MTU]) -> Result<usize, AttError> {
+        let mut put = |value: &[u8]| {
+            let len = value.len().min(ATT_MTU);
+            out[..len].copy_from_slice(&value[..len]);
+            len
+        };
+        let declaration = |properties: u8, value_handle: u16, uuid: u16| {
+            let handle = value_handle.to_le_bytes();
+            let uuid = uuid.to_le_bytes();
+            [properties, handle[0], handle[1], uuid[0], uuid[1]]
+        };
+        match handle {
+            GAP_SERVICE_HANDLE => Ok(put(&GAP_SERVICE_UUID.to_le_bytes())),
+            DEVICE_NAME_DECLARATION_HANDLE => Ok(put(&declaration(
+                PROPERTY_READ,
+                DEVICE_NAME_HANDLE,
+                DEVICE_NAME_UUID,
+            ))),
+            DEVICE_NAME_HANDLE => Ok(put(self.device_name)),
+            SERVICE_HANDLE => Ok(put(&self.service_uuid.get().to_le_bytes())),
+            VALUE_DECLARATION_HANDLE => Ok(put(&declaration(
+                PROPERTY_READ | PROPERTY_WRITE | PROPERTY_WRITE_WITHOUT_RESPONSE | PROPERTY_NOTIFY,
+                VALUE_HANDLE,
+                self.characteristic_uuid.get(),
+            ))),
+            VALUE_HANDLE => {
+                let len = self.value_len.get();
+                Ok(self.value.map_or(0, |value| put(&value[..len])))
+            }
+            CCCD_HANDLE => {
+                let cccd = if self.notifications.get() {
+                    CCCD_NOTIFY
+                } else {
+                    0
+                };
+                Ok(put(&cccd.to_le_bytes()))
+            }
+            _ => Err(AttError::InvalidHandle),
+        }
+    }
+
+    fn write_attribute(&self, handle: u16, value: &[u8]) -> Result<(), AttError> {
+        match handle {
+            VALUE_HANDLE => {
+                if value.len() > MAX_VALUE_LEN {
+                    return Err(AttError::InvalidAttributeValueLength);
+                }
+                let _ = self.set_value(value);
+                self.client.map(|client| client.value_written(value));
+                Ok(())
+            }
+            CCCD_HANDLE => {
+                if value.len() != 2 {
+                    return Err(AttError::InvalidAttributeValueLength);
+                }
+                let enabled = u16::from_le_bytes([value[0], value[1]]) & CCCD_NOTIFY != 0;
+                self.notifications.set(enabled);
+                self.client
+                    .map(|client| client.notifications_enabled(enabled));
+                Ok(())
+            }
+            GAP_SERVICE_HANDLE..=LAST_HANDLE => Err(AttError::WriteNotPermitted),
+            _ => Err(AttError::InvalidHandle),
+        }
+    }
+
+    // Validate the handle range of a request, returning its (start, end).
+    fn handle_range(pdu: &[u8]) -> Result<(u16, u16), (u16, AttError)> {
+        let start = u16::from_le_bytes([pdu[1], pdu[2]]);
+        let end = u16::from_le_bytes([pdu[3], pdu[4]]);
+        if start == 0 || start > end {
+            Err((start, AttError::InvalidHandle))
+        } else {
+            Ok((start, end.min(LAST_HANDLE)))
+        }
+    }
+
+    // Handles a request and returns the length of the response in `rsp`.
+    fn handle_request(
+        &self,
+        pdu: &[u8],
+        rsp: &mut [u8; ATT_MTU],
+    ) -> Result<usize, (u16, AttError)> {
+        let request = pdu[0];
+        let handle_at = |offset: usize| -> Result<u16, (u16, AttError)> {
+            pdu.get(offset..offset + 2)
+                .map(|h| u16::from_le_bytes([h[0], h[1]]))
+                .ok_or((0, AttError::InvalidAttributeValueLength))
+        };
+        let mut value = [0; ATT_MTU];
+        match request {
+            att::EXCHANGE_MTU_REQ => {
+                rsp[0] = att::EXCHANGE_MTU_RSP;
+                rsp[1..3].copy_from_slice(&(ATT_MTU as u16).to_le_bytes());
+                Ok(3)
+            }
+            att::FIND_INFORMATION_REQ if pdu.len() == 5 => {
+                let (start, end) = Self::handle_range(pdu)?;
+                rsp[0] = att::FIND_INFORMATION_RSP;
+                // Format 1: handles with 16-bit UUIDs.
+                rsp[1] = 0x01;
+                let mut len = 2;
+                for handle in start..=end {
+                    if len + 4 > ATT_MTU {
+                        break;
+                    }
+                    if let Some(uuid) = self.attribute_type(handle) {
+                        rsp[len..len + 2].copy_from_slice(&handle.to_le_bytes());
+                        rsp[len + 2..len + 4].copy_from_slice(&uuid.to_le_bytes());
+                        len += 4;
+                    }
+                }
+                if len == 2 {
+                    Err((start, AttError::AttributeNotFound))
+                } else {
+                    Ok(len)
+                }
+            }
+            att::FIND_BY_TYPE_VALUE_REQ if pdu.len() >= 7 => {
+                let (start, end) = Self::handle_range(pdu)?;
+                let attribute_type = handle_at(5)?;
+                rsp[0] = att::FIND_BY_TYPE_VALUE_RSP;
+                let mut len = 1;
+                for handle in start..=end {
+                    if len + 4 > ATT_MTU {
+                        break;
+                    }
+                    if self.attribute_type(handle) != Some(attribute_type) {
+                        continue;
+                    }
+                    let value_len = self
+                        .read_attribute(handle, &mut value)
+                        .map_err(|e| (handle, e))?;
+                    if value[..value_len] != pdu[7..] {
+                        continue;
+                    }
+                    let group_end = Self::group_end(handle);
+                    rsp[len..len + 2].copy_from_slice(&handle.to_le_bytes());
+                    rsp[len + 2..len + 4].copy_from_slice(&group_end.to_le_bytes());
+                    len += 4;
+                }
+                if len == 1 {
+                    Err((start, AttError::AttributeNotFound))
+                } else {
+                    Ok(len)
+                }
+            }
+            att::READ_BY_TYPE_REQ | att::READ_BY_GROUP_TYPE_REQ if pdu.len() == 7 => {
+                let (start, end) = Self::handle_range(pdu)?;
+                let attribute_type = handle_at(5)?;
+                let grouped = request == att::READ_BY_GROUP_TYPE_REQ;
+                if grouped && attribute_type != PRIMARY_SERVICE_UUID {
+                    return Err((start, AttError::UnsupportedGroupType));
+                }
+                rsp[0] = if grouped {
+                    att::READ_BY_GROUP_TYPE_RSP
+                } else {
+                    att::READ_BY_TYPE_RSP
+                };
+                // All entries of a response have the same length, the first
+                // match decides it.
+                let header_len = if grouped { 4 } else { 2 };
+                let mut entry_len = 0;
+                let mut len = 2;
+                for handle in start..=end {
+                    if self.attribute_type(handle) != Some(attribute_type) {
+                        continue;
+                    }
+                    let value_len = self
+                        .read_attribute(handle, &mut value)
+                        .map_err(|e| (handle, e))?;
+                    let value_len = value_len.min(ATT_MTU - 2 - header_len);
+                    if entry_len == 0 {
+                        entry_len = header_len + value_len;
+                    } else if header_len + value_len != entry_len {
+                        break;
+                    }
+                    if len + entry_len > ATT_MTU {
+                        break;
+                    }
+                    rsp[len..len + 2].copy_from_slice(&handle.to_le_bytes());
+                    if grouped {
+                        rsp[len + 2..len + 4]
+                            .copy_from_slice(&Self::group_end(handle).to_le_bytes());
+                    }
+                    rsp[len + header_len..len + entry_len].copy_from_slice(&value[..value_len]);
+                    len += entry_len;
+                }
+                if entry_len == 0 {
+                    Err((start, AttError::AttributeNotFound))
+                } else {
+                    rsp[1] = entry_len as u8;
+                    Ok(len)
+                }
+            }
+            att::READ_REQ | att::READ_BLOB_REQ => {
+                let handle = handle_at(1)?;
+                let offset = if request == att::READ_BLOB_REQ {
+                    handle_at(3)? as usize
+                } else {
+                    0
+                };
+                let value_len = self
+                    .read_attribute(handle, &mut value)
+                    .map_err(|e| (handle, e))?;
+                if offset > value_len {
+                    return Err((handle, AttError::InvalidOffset));
+                }
+                rsp[0] = if request == att::READ_REQ {
+                    att::READ_RSP
+                } else {
+                    att::READ_BLOB_RSP
+                };
+                let len = (value_len - offset).min(ATT_MTU - 1);
+                rsp[1..1 + len].copy_from_slice(&value[offset..offset + len]);
+                Ok(1 + len)
+            }
+            att::WRITE_REQ => {
+                let handle = handle_at(1)?;
+                self.write_attribute(handle, &pdu[3..])
+                    .map_err(|e| (handle, e))?;
+                rsp[0] = att::WRITE_RSP;
+                Ok(1)
+            }
+            _ => Err((0, AttError::RequestNotSupported)),
+        }
+    }
+
+    // Last handle of the service starting at `handle`.
+    fn group_end(handle: u16) -> u16 {
+        if handle < SERVICE_HANDLE {
+            SERVICE_HANDLE - 1
+        } else {
+            LAST_HANDLE
+        }
+    }
+}
+
+impl L2capClient for GattServer<'_> {
+    fn connected(&self) {
+        self.notifications.set(false);
+        self.response_len.set(0);
+        self.notify_pending.set(false);
+        self.notifying.set(false);
+        self.client.map(|client| client.connected());
+    }
+
+    fn disconnected(&self, reason: u8) {
+        self.notifications.set(false);
+        self.response_len.set(0);
+        self.notify_pending.set(false);
+        self.notifying.set(false);
+        self.client.map(|client| client.disconnected(reason));
+    }
+
+    fn received(&self, pdu: &[u8]) {
+        let Some(&request) = pdu.first() else {
+            return;
+        };
+        if request == att::WRITE_CMD {
+            if pdu.len() >= 3 {
+                let _ = self.write_attribute(u16::from_le_bytes([pdu[1], pdu[2]]), &pdu[3..]);
+            }
+            return;
+        }
+        if request & att::COMMAND_FLAG != 0 {
+            // Unknown commands and confirmations need no response.
+            return;
+        }
+        let mut rsp = [0; ATT_MTU];
+        match self.handle_request(pdu, &mut rsp) {
+            Ok(len) => self.respond(&rsp[..len]),
+            Err((handle, error)) => self.respond_error(request, handle, error),
+        }
+    }
+
+    fn sent(&self) {
+        if self.notifying.replace(false) {
+            self.client.map(|client| client.notify_done());
+        }
+        self.send_next();
+    }
+}
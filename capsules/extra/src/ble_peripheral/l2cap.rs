@@ -0,0 +1,231 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! L2CAP for BLE, fixed channels only.
+//!
+//! Demultiplexes L2CAP PDUs received from the link layer by channel ID.
+//! Attribute protocol PDUs go to the `L2capClient`, typically the GATT
+//! server. The signaling and security manager channels are answered here:
+//! signaling requests are rejected and pairing is refused, which is what a
+//! central expects from a peripheral without these features.
+//!
+//! PDUs are never fragmented. The link layer carries at most 27 bytes, which
+//! holds the 4 byte L2CAP header and the default ATT_MTU of 23 bytes.
+
+use core::cell::Cell;
+
+use kernel::ErrorCode;
+use kernel::utilities::cells::{MapCell, OptionalCell};
+
+use super::link_layer::{LinkLayer, LinkLayerClient, MAX_DATA_PAYLOAD};
+
+pub const L2CAP_HEADER_LEN: usize = 4;
+
+/// Largest payload that fits into a single link layer PDU.
+pub const MAX_PAYLOAD_LEN: usize = MAX_DATA_PAYLOAD - L2CAP_HEADER_LEN;
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 3, Part A], section 2.1
+const ATT_CID: u16 = 0x0004;
+const SIGNALING_CID: u16 = 0x0005;
+const SMP_CID: u16 = 0x0006;
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 3, Part A], section 4
+const COMMAND_REJECT: u8 = 0x01;
+const COMMAND_NOT_UNDERSTOOD: u16 = 0x0000;
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 3, Part H], section 3.5
+const PAIRING_REQUEST: u8 = 0x01;
+const PAIRING_FAILED: u8 = 0x05;
+const PAIRING_NOT_SUPPORTED: u8 = 0x05;
+
+/// Client of the attribute protocol channel.
+pub trait L2capClient {
+    fn connected(&self);
+    /// The connection ended, see `link_layer::reason`.
+    fn disconnected(&self, reason: u8);
+    /// An ATT PDU was received.
+    fn received(&self, pdu: &[u8]);
+    /// The PDU passed to `L2cap::send` was delivered.
+    fn sent(&self);
+}
+
+/// Who the PDU currently queued in the link layer belongs to.
+#[derive(Copy, Clone, PartialEq)]
+enum Sender {
+    None,
+    Client,
+    L2cap,
+}
+
+/// A framed PDU waiting for the link layer. A length of 0 means empty.
+struct Slot {
+    frame: MapCell<[u8; MAX_DATA_PAYLOAD]>,
+    len: Cell<usize>,
+}
+
+impl Slot {
+    fn new() -> Slot {
+        Slot {
+            frame: MapCell::new([0; MAX_DATA_PAYLOAD]),
+            len: Cell::new(0),
+        }
+    }
+
+    fn store(&self, frame: &[u8]) {
+        self.frame
+            .map(|slot| slot[..frame.len()].copy_from_slice(frame));
+        self.len.set(frame.len());
+    }
+}
+
+pub struct L2cap<'a> {
+    link_layer: &'a dyn LinkLayer<'a>,
+    client: OptionalCell<&'a dyn L2capClient>,
+    sending: Cell<Sender>,
+    /// PDU from the client waiting for the link layer.
+    client_queue: Slot,
+    /// Response generated by L2CAP itself waiting for the link layer. Only
+    /// one is kept, the central waits for each response.
+    l2cap_queue: Slot,
+}
+
+impl<'a> L2cap<'a> {
+    pub fn new(link_layer: &'a dyn LinkLayer<'a>) -> L2cap<'a> {
+        L2cap {
+            link_layer,
+            client: OptionalCell::empty(),
+            sending: Cell::new(Sender::None),
+            client_queue: Slot::new(),
+            l2cap_queue: Slot::new(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn L2capClient) {
+        self.client.set(client);
+    }
+
+    /// Send an ATT PDU. Returns BUSY while a previous PDU has not been
+    /// delivered yet.
+    pub fn send(&self, pdu: &[u8]) -> Result<(), ErrorCode> {
+        if self.sending.get() == Sender::Client || self.client_queue.len.get() != 0 {
+            return Err(ErrorCode::BUSY);
+        }
+        let mut frame = [0; MAX_DATA_PAYLOAD];
+        let len = Self::frame(&mut frame, ATT_CID, pdu)?;
+        if self.sending.get() == Sender::None {
+            self.link_layer.send(&frame[..len])?;
+            self.sending.set(Sender::Client);
+        } else {
+            self.client_queue.store(&frame[..len]);
+        }
+        Ok(())
+    }
+
+    fn frame(frame: &mut [u8], cid: u16, pdu: &[u8]) -> Result<usize, ErrorCode> {
+        if pdu.len() > MAX_PAYLOAD_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        frame[0..2].copy_from_slice(&(pdu.len() as u16).to_le_bytes());
+        frame[2..4].copy_from_slice(&cid.to_le_bytes());
+        frame[L2CAP_HEADER_LEN..L2CAP_HEADER_LEN + pdu.len()].copy_from_slice(pdu);
+        Ok(L2CAP_HEADER_LEN + pdu.len())
+    }
+
+    fn respond(&self, cid: u16, pdu: &[u8]) {
+        if self.sending.get() == Sender::L2cap || self.l2cap_queue.len.get() != 0 {
+            return;
+        }
+        let mut frame = [0; MAX_DATA_PAYLOAD];
+        let Ok(len) = Self::frame(&mut frame, cid, pdu) else {
+            return;
+        };
+        if self.sending.get() == Sender::None {
+            if self.link_layer.send(&frame[..len]).is_ok() {
+                self.sending.set(Sender::L2cap);
+            }
+        } else {
+            self.l2cap_queue.store(&frame[..len]);
+        }
+    }
+
+    // Hand the next queued PDU to the link layer.
+    fn flush(&self) {
+        for (slot, sender) in [
+            (&self.l2cap_queue, Sender::L2cap),
+            (&self.client_queue, Sender::Client),
+        ] {
+            let len = slot.len.replace(0);
+            if len != 0 {
+                let mut frame = [0; MAX_DATA_PAYLOAD];
+                slot.frame
+                    .map(|queued| frame[..len].copy_from_slice(&queued[..len]));
+                if self.link_layer.send(&frame[..len]).is_ok() {
+                    self.sending.set(sender);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn reset(&self) {
+        self.sending.set(Sender::None);
+        self.client_queue.len.set(0);
+        self.l2cap_queue.len.set(0);
+    }
+}
+
+impl LinkLayerClient for L2cap<'_> {
+    fn connected(&self) {
+        self.reset();
+        self.client.map(|client| client.connected());
+    }
+
+    fn disconnected(&self, reason: u8) {
+        self.reset();
+        self.client.map(|client| client.disconnected(reason));
+    }
+
+    fn received(&self, payload: &[u8]) {
+        if payload.len() < L2CAP_HEADER_LEN {
+            return;
+        }
+        let len = u16::from_le_bytes([payload[0], payload[1]]) as usize;
+        let cid = u16::from_le_bytes([payload[2], payload[3]]);
+        let Some(pdu) = payload.get(L2CAP_HEADER_LEN..L2CAP_HEADER_LEN + len) else {
+            // Fragmented PDUs are not supported.
+            return;
+        };
+        match cid {
+            ATT_CID => {
+                self.client.map(|client| client.received(pdu));
+            }
+            SIGNALING_CID => {
+                // Reject every command, except rejections themselves.
+                if let (Some(&code), Some(&identifier)) = (pdu.first(), pdu.get(1))
+                    && code != COMMAND_REJECT
+                {
+                    let reason = COMMAND_NOT_UNDERSTOOD.to_le_bytes();
+                    self.respond(
+                        SIGNALING_CID,
+                        &[COMMAND_REJECT, identifier, 2, 0, reason[0], reason[1]],
+                    );
+                }
+            }
+            SMP_CID => {
+                if pdu.first() == Some(&PAIRING_REQUEST) {
+                    self.respond(SMP_CID, &[PAIRING_FAILED, PAIRING_NOT_SUPPORTED]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn sent(&self) {
+        let sender = self.sending.replace(Sender::None);
+        self.flush();
+        if sender == Sender::Client {
+            self.client.map(|client| client.sent());
+        }
+    }
+}
@@ -0,0 +1,1231 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! BLE link layer for the peripheral role.
+//!
+//! Advertises with connectable `ADV_IND` PDUs and accepts a `CONNECT_IND`
+//! from a central. Once connected it follows the central's connection
+//! events: at every anchor point it hops to the next data channel (channel
+//! selection algorithm #1), receives one packet from the central and answers
+//! it, acknowledging with the SN and NESN header bits.
+//!
+//! Timing critical work happens in the radio, see
+//! `kernel::hil::ble_connection`. The response of a connection event is
+//! staged before the event starts and received PDUs are processed once the
+//! exchange is over, so replies to the central go out in the following
+//! connection event.
+//!
+//! Limitations:
+//!
+//! - One exchange per connection event; the MD bit is never set.
+//! - As the response is staged before the central's packet arrives, its SN
+//!   and NESN bits only reflect packets of earlier connection events. Every
+//!   PDU therefore takes two connection events to be acknowledged.
+//! - No data length extension, so data PDUs carry at most 27 bytes and
+//!   L2CAP PDUs must not be fragmented.
+//! - No encryption, no scan responses and no peripheral latency (the link
+//!   layer listens in every connection event).
+//! - Window widening is a fixed margin rather than derived from the sleep
+//!   clock accuracy.
+
+use core::cell::Cell;
+
+use kernel::ErrorCode;
+use kernel::hil::ble_advertising::RadioChannel;
+use kernel::hil::ble_connection::{self, BleConnectionDriver, ConnectionClient};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+
+/// Length of the buffer handed to the radio. Large enough for any
+/// advertising channel PDU: a 2 byte header and up to 37 bytes of payload.
+pub const RADIO_BUF_LEN: usize = 39;
+
+/// Maximum payload of a data channel PDU without the data length extension.
+pub const MAX_DATA_PAYLOAD: usize = 27;
+
+/// Maximum length of the advertising data, i.e. the `AdvData` field.
+pub const MAX_ADV_DATA_LEN: usize = 31;
+
+/// Length of a device address.
+pub const ADDRESS_LEN: usize = 6;
+
+/// Disconnect reasons passed to `LinkLayerClient::disconnected`.
+///
+/// Bluetooth Core Specification v4.2 [Vol 2, Part D], section 1.3
+pub mod reason {
+    pub const CONNECTION_TIMEOUT: u8 = 0x08;
+    pub const REMOTE_USER_TERMINATED: u8 = 0x13;
+    pub const LOCAL_HOST_TERMINATED: u8 = 0x16;
+    pub const INSTANT_PASSED: u8 = 0x28;
+}
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 2.3
+const ADV_IND: u8 = 0b0000;
+const CONNECT_IND: u8 = 0b0101;
+const PDU_TYPE_MASK: u8 = 0b1111;
+const TXADD: u8 = 1 << 6;
+const RXADD: u8 = 1 << 7;
+const CONNECT_IND_LEN: usize = 34;
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 2.4
+const LLID_MASK: u8 = 0b11;
+const LLID_CONTINUATION: u8 = 0b01;
+const LLID_START: u8 = 0b10;
+const LLID_CONTROL: u8 = 0b11;
+const NESN: u8 = 1 << 2;
+const SN: u8 = 1 << 3;
+const DATA_HEADER_LEN: usize = 2;
+const DATA_PDU_LEN: usize = DATA_HEADER_LEN + MAX_DATA_PAYLOAD;
+
+/// LL control PDU opcodes, BLUETOOTH SPECIFICATION Version 4.2 [Vol 6,
+/// Part B], section 2.4.2
+mod opcode {
+    pub const CONNECTION_UPDATE_IND: u8 = 0x00;
+    pub const CHANNEL_MAP_IND: u8 = 0x01;
+    pub const TERMINATE_IND: u8 = 0x02;
+    pub const UNKNOWN_RSP: u8 = 0x07;
+    pub const FEATURE_REQ: u8 = 0x08;
+    pub const FEATURE_RSP: u8 = 0x09;
+    pub const VERSION_IND: u8 = 0x0c;
+    pub const PING_REQ: u8 = 0x12;
+    pub const PING_RSP: u8 = 0x13;
+}
+
+/// Version 4.1 of the specification, the last one before the data length
+/// extension.
+const LL_VERSION: u8 = 0x07;
+/// No company identifier has been assigned.
+const COMPANY_ID: u16 = 0xffff;
+
+const NUM_DATA_CHANNELS: u8 = 37;
+/// Connection timing is given in units of 1.25 ms.
+const TIMING_UNIT_US: u32 = 1250;
+/// Supervision timeouts are given in units of 10 ms.
+const TIMEOUT_UNIT_US: u32 = 10_000;
+/// How early to start listening before the expected anchor point.
+const WINDOW_WIDENING_US: u32 = 500;
+/// Time spent on each advertising channel: the `ADV_IND`, T_IFS and a full
+/// `CONNECT_IND`, plus margin.
+const ADV_CHANNEL_US: u32 = 1500;
+/// Preamble, access address and CRC surround every PDU.
+const PACKET_OVERHEAD_BYTES: u32 = 8;
+/// At 1 Mbit/s one byte takes 8 us on air.
+const BYTE_US: u32 = 8;
+/// BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 4.1
+const T_IFS_US: u32 = 150;
+
+/// Client of the link layer, typically L2CAP.
+pub trait LinkLayerClient {
+    /// A central connected.
+    fn connected(&self);
+
+    /// The connection ended for `reason`, see the `reason` module. Data
+    /// that was passed to `send` and not acknowledged yet is dropped.
+    fn disconnected(&self, reason: u8);
+
+    /// An L2CAP PDU was received.
+    fn received(&self, payload: &[u8]);
+
+    /// The payload passed to `send` was acknowledged by the central.
+    fn sent(&self);
+}
+
+pub trait LinkLayer<'a> {
+    fn set_client(&self, client: &'a dyn LinkLayerClient);
+
+    /// Start connectable advertising with `adv_data` as the advertising
+    /// data, once every `interval_ms` milliseconds. Advertising stops when a
+    /// central connects.
+    fn start_advertising(&self, adv_data: &[u8], interval_ms: u32) -> Result<(), ErrorCode>;
+
+    fn stop_advertising(&self) -> Result<(), ErrorCode>;
+
+    /// Queue an L2CAP PDU for transmission. Only one PDU can be queued at a
+    /// time, `LinkLayerClient::sent` is called once it has been
+    /// acknowledged.
+    fn send(&self, payload: &[u8]) -> Result<(), ErrorCode>;
+
+    /// Ask the central to end the connection.
+    /// `LinkLayerClient::disconnected` is called once the request has been
+    /// acknowledged.
+    fn disconnect(&self) -> Result<(), ErrorCode>;
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Waiting for the next advertising event.
+    AdvertisingIdle,
+    Advertising(RadioChannel),
+    Connected,
+}
+
+/// The PDU the central has not acknowledged yet.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum InFlight {
+    None,
+    Empty,
+    Control,
+    Data,
+}
+
+#[derive(Copy, Clone)]
+struct ConnectionUpdate {
+    window_offset_us: u32,
+    interval_us: u32,
+    supervision_timeout_us: u32,
+    instant: u16,
+}
+
+#[derive(Copy, Clone)]
+struct Connection<T: Ticks> {
+    /// Expected anchor point of the next connection event.
+    anchor: T,
+    /// Time the last packet with a valid CRC was received.
+    last_rx: T,
+    /// Whether a packet has been received in this connection.
+    established: bool,
+    interval_us: u32,
+    supervision_timeout_us: u32,
+    /// Counter of the next connection event.
+    event_counter: u16,
+    last_unmapped_channel: u8,
+    hop_increment: u8,
+    channel_map: [u8; 5],
+    sn: bool,
+    nesn: bool,
+    update: Option<ConnectionUpdate>,
+    channel_map_update: Option<([u8; 5], u16)>,
+}
+
+/// Channel selection algorithm #1, BLUETOOTH SPECIFICATION Version 4.2
+/// [Vol 6, Part B], section 4.5.8.2. Maps `unmapped` to a used channel.
+fn remap_channel(channel_map: &[u8; 5], unmapped: u8) -> u8 {
+    let used = |channel: u8| channel_map[channel as usize / 8] & (1 << (channel % 8)) != 0;
+    if used(unmapped) {
+        return unmapped;
+    }
+    let num_used = (0..NUM_DATA_CHANNELS).filter(|c| used(*c)).count();
+    if num_used == 0 {
+        return unmapped;
+    }
+    (0..NUM_DATA_CHANNELS)
+        .filter(|c| used(*c))
+        .nth(unmapped as usize % num_used)
+        .unwrap_or(unmapped)
+}
+
+fn le_u16(buf: &[u8]) -> u16 {
+    u16::from_le_bytes([buf[0], buf[1]])
+}
+
+pub struct PeripheralLinkLayer<'a, R: BleConnectionDriver<'a>, A: Alarm<'a>> {
+    radio: &'a R,
+    alarm: &'a A,
+    client: OptionalCell<&'a dyn LinkLayerClient>,
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    address: Cell<[u8; ADDRESS_LEN]>,
+    adv_data: MapCell<[u8; MAX_ADV_DATA_LEN]>,
+    adv_data_len: Cell<usize>,
+    adv_interval_ms: Cell<u32>,
+    /// Xorshift state for the advertising delay.
+    random_nonce: Cell<u32>,
+    connection: OptionalCell<Connection<A::Ticks>>,
+    /// PDU received in the last exchange, processed once the exchange is
+    /// over. A length of 0 means the slot is free.
+    rx_pdu: MapCell<[u8; DATA_PDU_LEN]>,
+    rx_len: Cell<usize>,
+    tx_data: MapCell<[u8; MAX_DATA_PAYLOAD]>,
+    tx_data_len: Cell<usize>,
+    tx_control: MapCell<[u8; MAX_DATA_PAYLOAD]>,
+    tx_control_len: Cell<usize>,
+    /// Length of the response staged for the current connection event.
+    response_len: Cell<usize>,
+    in_flight: Cell<InFlight>,
+    /// PDU acknowledged in the last exchange.
+    acked: Cell<InFlight>,
+    terminating: Cell<bool>,
+}
+
+impl<'a, R: BleConnectionDriver<'a>, A: Alarm<'a>> PeripheralLinkLayer<'a, R, A> {
+    pub fn new(
+        radio: &'a R,
+        alarm: &'a A,
+        buffer: &'static mut [u8],
+        address: [u8; ADDRESS_LEN],
+    ) -> PeripheralLinkLayer<'a, R, A> {
+        PeripheralLinkLayer {
+            radio,
+            alarm,
+            client: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+            state: Cell::new(State::Idle),
+            address: Cell::new(address),
+            adv_data: MapCell::new([0; MAX_ADV_DATA_LEN]),
+            adv_data_len: Cell::new(0),
+            adv_interval_ms: Cell::new(100),
+            random_nonce: Cell::new(0xdeadbeef),
+            connection: OptionalCell::empty(),
+            rx_pdu: MapCell::new([0; DATA_PDU_LEN]),
+            rx_len: Cell::new(0),
+            tx_data: MapCell::new([0; MAX_DATA_PAYLOAD]),
+            tx_data_len: Cell::new(0),
+            tx_control: MapCell::new([0; MAX_DATA_PAYLOAD]),
+            tx_control_len: Cell::new(0),
+            response_len: Cell::new(0),
+            in_flight: Cell::new(InFlight::None),
+            acked: Cell::new(InFlight::None),
+            terminating: Cell::new(false),
+        }
+    }
+
+    /// Set the static random device address, in the order it is sent on
+    /// air. The two most significant bits of the last byte must be set.
+    pub fn set_address(&self, address: [u8; ADDRESS_LEN]) {
+        self.address.set(address);
+    }
+
+    // Returns a new pseudo-random number, see the BLE advertising driver.
+    fn random_nonce(&self) -> u32 {
+        let mut nonce = self.random_nonce.get();
+        nonce ^= nonce << 13;
+        nonce ^= nonce >> 17;
+        nonce ^= nonce << 5;
+        self.random_nonce.set(nonce);
+        nonce
+    }
+
+    // Arm the alarm for `when`, which lies less than `horizon` ticks in the
+    // future. If it has already passed the alarm fires right away.
+    fn set_alarm_at(&self, when: A::Ticks, horizon: A::Ticks) {
+        let now = self.alarm.now();
+        let dt = when.wrapping_sub(now);
+        let dt = if dt > horizon { A::Ticks::from(0) } else { dt };
+        self.alarm.set_alarm(now, dt);
+    }
+
+    fn update_connection<F, T>(&self, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut Connection<A::Ticks>) -> T,
+    {
+        self.connection.get().map(|mut conn| {
+            let result = f(&mut conn);
+            self.connection.set(conn);
+            result
+        })
+    }
+
+    fn build_advertisement(&self, buf: &mut [u8]) -> usize {
+        let data_len = self.adv_data_len.get();
+        buf[0] = ADV_IND | TXADD;
+        buf[1] = (ADDRESS_LEN + data_len) as u8;
+        buf[2..2 + ADDRESS_LEN].copy_from_slice(&self.address.get());
+        self.adv_data.map(|data| {
+            buf[2 + ADDRESS_LEN..2 + ADDRESS_LEN + data_len].copy_from_slice(&data[..data_len])
+        });
+        2 + ADDRESS_LEN + data_len
+    }
+
+    fn advertise_on(&self, channel: RadioChannel) {
+        let Some(buf) = self.buffer.take() else {
+            return;
+        };
+        let len = self.build_advertisement(buf);
+        if let Err((_, buf)) = self.radio.transmit_then_receive(buf, len, channel) {
+            self.buffer.replace(buf);
+        }
+        self.state.set(State::Advertising(channel));
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_us(ADV_CHANNEL_US));
+    }
+
+    fn advertising_channel_done(&self, channel: RadioChannel) {
+        if let Ok(buf) = self.radio.stop() {
+            self.buffer.replace(buf);
+        }
+        match channel {
+            RadioChannel::AdvertisingChannel37 => {
+                self.advertise_on(RadioChannel::AdvertisingChannel38)
+            }
+            RadioChannel::AdvertisingChannel38 => {
+                self.advertise_on(RadioChannel::AdvertisingChannel39)
+            }
+            _ => {
+                // BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B],
+                // section 4.4.2.2: advDelay is a random 0 to 10 ms.
+                let delay_ms = self.random_nonce() % 10;
+                self.state.set(State::AdvertisingIdle);
+                self.alarm.set_alarm(
+                    self.alarm.now(),
+                    self.alarm
+                        .ticks_from_ms(self.adv_interval_ms.get() + delay_ms),
+                );
+            }
+        }
+    }
+
+    // Returns whether `pdu` is a CONNECT_IND addressed to this device.
+    fn is_connect_ind(&self, pdu: &[u8]) -> bool {
+        pdu.len() >= 2 + CONNECT_IND_LEN
+            && pdu[0] & PDU_TYPE_MASK == CONNECT_IND
+            && pdu[0] & RXADD != 0
+            && pdu[1] as usize == CONNECT_IND_LEN
+            && pdu[2 + ADDRESS_LEN..2 + 2 * ADDRESS_LEN] == self.address.get()
+    }
+
+    // BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 2.3.3.1
+    fn connect(&self, pdu: &[u8]) {
+        let ll_data = &pdu[2 + 2 * ADDRESS_LEN..];
+        let access_address = u32::from_le_bytes([ll_data[0], ll_data[1], ll_data[2], ll_data[3]]);
+        let crc_init = u32::from_le_bytes([ll_data[4], ll_data[5], ll_data[6], 0]);
+        let window_offset = le_u16(&ll_data[8..10]) as u32;
+        let interval = le_u16(&ll_data[10..12]) as u32;
+        let timeout = le_u16(&ll_data[14..16]) as u32;
+        let mut channel_map = [0; 5];
+        channel_map.copy_from_slice(&ll_data[16..21]);
+        channel_map[4] &= 0x1f;
+        let hop_increment = ll_data[21] & 0x1f;
+
+        let num_used: u32 = channel_map.iter().map(|b| b.count_ones()).sum();
+        if !(6..=3200).contains(&interval) || !(5..=16).contains(&hop_increment) || num_used < 2 {
+            // Invalid parameters, keep advertising.
+            return;
+        }
+
+        let _ = self.alarm.disarm();
+        self.radio.set_access_address(access_address, crc_init);
+
+        // The transmit window starts 1.25 ms plus the window offset after
+        // the end of the CONNECT_IND, which has just been received.
+        let now = self.alarm.now();
+        let first_anchor = now.wrapping_add(
+            self.alarm
+                .ticks_from_us(TIMING_UNIT_US + window_offset * TIMING_UNIT_US),
+        );
+        self.connection.set(Connection {
+            anchor: first_anchor,
+            last_rx: now,
+            established: false,
+            interval_us: interval * TIMING_UNIT_US,
+            supervision_timeout_us: timeout * TIMEOUT_UNIT_US,
+            event_counter: 0,
+            last_unmapped_channel: 0,
+            hop_increment,
+            channel_map,
+            sn: false,
+            nesn: false,
+            update: None,
+            channel_map_update: None,
+        });
+        self.rx_len.set(0);
+        self.tx_data_len.set(0);
+        self.tx_control_len.set(0);
+        self.in_flight.set(InFlight::None);
+        self.acked.set(InFlight::None);
+        self.terminating.set(false);
+        self.state.set(State::Connected);
+        self.alarm.set_alarm(
+            now,
+            self.alarm.ticks_from_us(
+                TIMING_UNIT_US + window_offset * TIMING_UNIT_US - WINDOW_WIDENING_US,
+            ),
+        );
+        self.client.map(|client| client.connected());
+    }
+
+    fn disconnected(&self, reason: u8) {
+        let _ = self.alarm.disarm();
+        if let Ok(buf) = self.radio.stop() {
+            self.buffer.replace(buf);
+        }
+        self.radio.set_access_address(
+            ble_connection::ADVERTISING_ACCESS_ADDRESS,
+            ble_connection::ADVERTISING_CRC_INIT,
+        );
+        self.connection.clear();
+        self.state.set(State::Idle);
+        self.rx_len.set(0);
+        self.tx_data_len.set(0);
+        self.tx_control_len.set(0);
+        self.in_flight.set(InFlight::None);
+        self.acked.set(InFlight::None);
+        self.terminating.set(false);
+        self.client.map(|client| client.disconnected(reason));
+    }
+
+    // Expected anchor point of the event after the one anchored at
+    // `anchor`, taking a connection update at that event into account.
+    fn next_anchor(&self, conn: &Connection<A::Ticks>, anchor: A::Ticks) -> A::Ticks {
+        let offset_us = match conn.update {
+            Some(update) if update.instant == conn.event_counter => {
+                conn.interval_us + update.window_offset_us
+            }
+            _ => conn.interval_us,
+        };
+        anchor.wrapping_add(self.alarm.ticks_from_us(offset_us))
+    }
+
+    // How far ahead the alarm is ever armed during a connection.
+    fn connection_horizon(&self, conn: &Connection<A::Ticks>) -> A::Ticks {
+        let update_us = conn
+            .update
+            .map_or(0, |update| update.interval_us + update.window_offset_us);
+        self.alarm
+            .ticks_from_us(2 * conn.interval_us + update_us + TIMING_UNIT_US)
+    }
+
+    fn connection_event(&self) {
+        // If the previous event is still listening, the central was not
+        // heard in it.
+        if let Ok(buf) = self.radio.stop() {
+            self.buffer.replace(buf);
+        }
+        let Some(conn) = self.connection.get() else {
+            return;
+        };
+
+        // BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 4.5.2
+        let timeout_us = if conn.established {
+            conn.supervision_timeout_us
+        } else {
+            6 * conn.interval_us
+        };
+        let now = self.alarm.now();
+        if now.wrapping_sub(conn.last_rx) > self.alarm.ticks_from_us(timeout_us) {
+            self.disconnected(reason::CONNECTION_TIMEOUT);
+            return;
+        }
+
+        let Some((channel, anchor, horizon)) = self.update_connection(|conn| {
+            if let Some(update) = conn.update {
+                if update.instant == conn.event_counter {
+                    conn.interval_us = update.interval_us;
+                    conn.supervision_timeout_us = update.supervision_timeout_us;
+                    conn.update = None;
+                }
+            }
+            if let Some((channel_map, instant)) = conn.channel_map_update {
+                if instant == conn.event_counter {
+                    conn.channel_map = channel_map;
+                    conn.channel_map_update = None;
+                }
+            }
+            let unmapped = (conn.last_unmapped_channel + conn.hop_increment) % NUM_DATA_CHANNELS;
+            conn.last_unmapped_channel = unmapped;
+            let channel = remap_channel(&conn.channel_map, unmapped);
+
+            let anchor = conn.anchor;
+            conn.event_counter = conn.event_counter.wrapping_add(1);
+            conn.anchor = self.next_anchor(conn, anchor);
+            (channel, conn.anchor, self.connection_horizon(conn))
+        }) else {
+            return;
+        };
+
+        if let (Some(buf), Some(channel)) = (
+            self.buffer.take(),
+            RadioChannel::from_channel_index(channel as u32),
+        ) {
+            let len = self.build_response(buf);
+            self.response_len.set(len);
+            if let Err((_, buf)) = self.radio.receive_then_transmit(buf, len, channel) {
+                self.buffer.replace(buf);
+            }
+        }
+        self.set_alarm_at(
+            anchor.wrapping_sub(self.alarm.ticks_from_us(WINDOW_WIDENING_US)),
+            horizon,
+        );
+    }
+
+    // Write the response for the next exchange into `tx` and return its
+    // length. It answers the packets received so far.
+    fn build_response(&self, tx: &mut [u8]) -> usize {
+        let Some(conn) = self.connection.get() else {
+            return 0;
+        };
+        if self.in_flight.get() == InFlight::None {
+            self.in_flight.set(if self.tx_control_len.get() != 0 {
+                InFlight::Control
+            } else if self.tx_data_len.get() != 0 {
+                InFlight::Data
+            } else {
+                InFlight::Empty
+            });
+        }
+        let (llid, len) = match self.in_flight.get() {
+            InFlight::Control => {
+                let len = self.tx_control_len.get();
+                self.tx_control.map(|pdu| {
+                    tx[DATA_HEADER_LEN..DATA_HEADER_LEN + len].copy_from_slice(&pdu[..len])
+                });
+                (LLID_CONTROL, len)
+            }
+            InFlight::Data => {
+                let len = self.tx_data_len.get();
+                self.tx_data.map(|pdu| {
+                    tx[DATA_HEADER_LEN..DATA_HEADER_LEN + len].copy_from_slice(&pdu[..len])
+                });
+                (LLID_START, len)
+            }
+            _ => (LLID_CONTINUATION, 0),
+        };
+        tx[0] = llid | if conn.nesn { NESN } else { 0 } | if conn.sn { SN } else { 0 };
+        tx[1] = len as u8;
+        DATA_HEADER_LEN + len
+    }
+
+    // Process the packet received in an exchange, after the response has
+    // been sent.
+    //
+    // BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 4.5.9
+    fn receive(&self, rx: &[u8]) {
+        let now = self.alarm.now();
+        let Some(mut conn) = self.connection.get() else {
+            return;
+        };
+        if rx.len() < DATA_HEADER_LEN {
+            return;
+        }
+
+        conn.last_rx = now;
+        conn.established = true;
+        // The central's packet started T_IFS and the response before now.
+        let airtime_us =
+            (rx.len() as u32 + self.response_len.get() as u32 + 2 * PACKET_OVERHEAD_BYTES)
+                * BYTE_US
+                + T_IFS_US;
+        conn.anchor = self.next_anchor(
+            &conn,
+            now.wrapping_sub(self.alarm.ticks_from_us(airtime_us)),
+        );
+
+        if (rx[0] & NESN != 0) != conn.sn {
+            // The central acknowledged our last PDU.
+            conn.sn = !conn.sn;
+            let acked = self.in_flight.replace(InFlight::None);
+            match acked {
+                InFlight::Data => self.tx_data_len.set(0),
+                InFlight::Control => self.tx_control_len.set(0),
+                _ => {}
+            }
+            self.acked.set(acked);
+        }
+        if (rx[0] & SN != 0) == conn.nesn {
+            let len = DATA_HEADER_LEN + rx[1] as usize;
+            if rx[1] == 0 {
+                conn.nesn = !conn.nesn;
+            } else if self.rx_len.get() == 0 && len <= DATA_PDU_LEN && len <= rx.len() {
+                self.rx_pdu
+                    .map(|pdu| pdu[..len].copy_from_slice(&rx[..len]));
+                self.rx_len.set(len);
+                conn.nesn = !conn.nesn;
+            }
+            // Otherwise leave NESN alone, so the central resends the PDU.
+        }
+        self.connection.set(conn);
+    }
+
+    // Called once an exchange is over and the radio is idle again.
+    fn exchange_done(&self) {
+        // The anchor moves to the time the central was actually heard.
+        if let Some(conn) = self.connection.get() {
+            self.set_alarm_at(
+                conn.anchor
+                    .wrapping_sub(self.alarm.ticks_from_us(WINDOW_WIDENING_US)),
+                self.connection_horizon(&conn),
+            );
+        }
+
+        match self.acked.replace(InFlight::None) {
+            InFlight::Data => {
+                self.client.map(|client| client.sent());
+            }
+            InFlight::Control => {
+                if self.terminating.get() {
+                    self.disconnected(reason::LOCAL_HOST_TERMINATED);
+                    return;
+                }
+            }
+            _ => {}
+        }
+
+        let len = self.rx_len.get();
+        if len == 0 {
+            return;
+        }
+        let mut pdu = [0; DATA_PDU_LEN];
+        self.rx_pdu.map(|rx_pdu| pdu.copy_from_slice(rx_pdu));
+        self.rx_len.set(0);
+        let payload = &pdu[DATA_HEADER_LEN..len];
+        match pdu[0] & LLID_MASK {
+            LLID_START => {
+                self.client.map(|client| client.received(payload));
+            }
+            LLID_CONTROL => self.handle_control(payload),
+            // Continuation fragments are not reassembled.
+            _ => {}
+        }
+    }
+
+    fn queue_control(&self, pdu: &[u8]) {
+        if self.tx_control_len.get() != 0 {
+            // Procedures are not run in parallel, a central waits for the
+            // previous response first.
+            return;
+        }
+        self.tx_control
+            .map(|buf| buf[..pdu.len()].copy_from_slice(pdu));
+        self.tx_control_len.set(pdu.len());
+    }
+
+    // Returns whether `instant` has already passed, which ends the
+    // connection.
+    fn instant_passed(&self, instant: u16) -> bool {
+        self.connection
+            .get()
+            .is_none_or(|conn| instant.wrapping_sub(conn.event_counter) >= 0x8000)
+    }
+
+    // BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 2.4.2
+    fn handle_control(&self, pdu: &[u8]) {
+        let Some(&op) = pdu.first() else {
+            return;
+        };
+        match op {
+            opcode::CONNECTION_UPDATE_IND if pdu.len() >= 12 => {
+                let instant = le_u16(&pdu[10..12]);
+                if self.instant_passed(instant) {
+                    self.disconnected(reason::INSTANT_PASSED);
+                    return;
+                }
+                let update = ConnectionUpdate {
+                    window_offset_us: le_u16(&pdu[2..4]) as u32 * TIMING_UNIT_US,
+                    interval_us: le_u16(&pdu[4..6]) as u32 * TIMING_UNIT_US,
+                    supervision_timeout_us: le_u16(&pdu[8..10]) as u32 * TIMEOUT_UNIT_US,
+                    instant,
+                };
+                self.update_connection(|conn| conn.update = Some(update));
+            }
+            opcode::CHANNEL_MAP_IND if pdu.len() >= 8 => {
+                let instant = le_u16(&pdu[6..8]);
+                if self.instant_passed(instant) {
+                    self.disconnected(reason::INSTANT_PASSED);
+                    return;
+                }
+                let mut channel_map = [0; 5];
+                channel_map.copy_from_slice(&pdu[1..6]);
+                channel_map[4] &= 0x1f;
+                self.update_connection(|conn| {
+                    conn.channel_map_update = Some((channel_map, instant))
+                });
+            }
+            opcode::TERMINATE_IND if pdu.len() >= 2 => self.disconnected(pdu[1]),
+            opcode::FEATURE_REQ => {
+                // No optional features are supported.
+                let mut rsp = [0; 9];
+                rsp[0] = opcode::FEATURE_RSP;
+                self.queue_control(&rsp);
+            }
+            opcode::VERSION_IND => {
+                let company = COMPANY_ID.to_le_bytes();
+                self.queue_control(&[
+                    opcode::VERSION_IND,
+                    LL_VERSION,
+                    company[0],
+                    company[1],
+                    0,
+                    0,
+                ]);
+            }
+            opcode::PING_REQ => self.queue_control(&[opcode::PING_RSP]),
+            opcode::UNKNOWN_RSP | opcode::FEATURE_RSP | opcode::PING_RSP => {}
+            _ => self.queue_control(&[opcode::UNKNOWN_RSP, op]),
+        }
+    }
+}
+
+impl<'a, R: BleConnectionDriver<'a>, A: Alarm<'a>> LinkLayer<'a> for PeripheralLinkLayer<'a, R, A> {
+    fn set_client(&self, client: &'a dyn LinkLayerClient) {
+        self.client.set(client);
+    }
+
+    fn start_advertising(&self, adv_data: &[u8], interval_ms: u32) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if adv_data.len() > MAX_ADV_DATA_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        self.adv_data
+            .map(|data| data[..adv_data.len()].copy_from_slice(adv_data));
+        self.adv_data_len.set(adv_data.len());
+        // BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 4.4.2.2
+        self.adv_interval_ms.set(interval_ms.clamp(20, 10240));
+        self.random_nonce.set(self.alarm.now().into_u32() | 1);
+        self.radio.set_access_address(
+            ble_connection::ADVERTISING_ACCESS_ADDRESS,
+            ble_connection::ADVERTISING_CRC_INIT,
+        );
+        self.advertise_on(RadioChannel::AdvertisingChannel37);
+        Ok(())
+    }
+
+    fn stop_advertising(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::AdvertisingIdle | State::Advertising(_) => {
+                let _ = self.alarm.disarm();
+                if let Ok(buf) = self.radio.stop() {
+                    self.buffer.replace(buf);
+                }
+                self.state.set(State::Idle);
+                Ok(())
+            }
+            _ => Err(ErrorCode::OFF),
+        }
+    }
+
+    fn send(&self, payload: &[u8]) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Connected || self.terminating.get() {
+            return Err(ErrorCode::OFF);
+        }
+        if payload.is_empty() || payload.len() > MAX_DATA_PAYLOAD {
+            return Err(ErrorCode::SIZE);
+        }
+        if self.tx_data_len.get() != 0 {
+            return Err(ErrorCode::BUSY);
+        }
+        self.tx_data
+            .map(|buf| buf[..payload.len()].copy_from_slice(payload));
+        self.tx_data_len.set(payload.len());
+        Ok(())
+    }
+
+    fn disconnect(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Connected {
+            return Err(ErrorCode::OFF);
+        }
+        if self.terminating.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.tx_control_len.get() != 0 {
+            return Err(ErrorCode::BUSY);
+        }
+        self.queue_control(&[opcode::TERMINATE_IND, reason::REMOTE_USER_TERMINATED]);
+        self.terminating.set(true);
+        Ok(())
+    }
+}
+
+impl<'a, R: BleConnectionDriver<'a>, A: Alarm<'a>> AlarmClient for PeripheralLinkLayer<'a, R, A> {
+    fn alarm(&self) {
+        match self.state.get() {
+            State::AdvertisingIdle => self.advertise_on(RadioChannel::AdvertisingChannel37),
+            State::Advertising(channel) => self.advertising_channel_done(channel),
+            State::Connected => self.connection_event(),
+            State::Idle => {}
+        }
+    }
+}
+
+impl<'a, R: BleConnectionDriver<'a>, A: Alarm<'a>> ConnectionClient
+    for PeripheralLinkLayer<'a, R, A>
+{
+    fn operation_done(&self, buf: &'static mut [u8], rx_len: usize, result: Result<(), ErrorCode>) {
+        match self.state.get() {
+            State::Advertising(_) => {
+                let connect = result.is_ok() && self.is_connect_ind(&buf[..rx_len]);
+                let mut pdu = [0; 2 + CONNECT_IND_LEN];
+                if connect {
+                    pdu.copy_from_slice(&buf[..2 + CONNECT_IND_LEN]);
+                }
+                self.buffer.replace(buf);
+                if connect {
+                    self.connect(&pdu);
+                }
+                // Otherwise the alarm moves on to the next channel.
+            }
+            State::Connected => {
+                if result.is_ok() {
+                    self.receive(&buf[..rx_len]);
+                }
+                self.buffer.replace(buf);
+                self.exchange_done();
+            }
+            _ => {
+                self.buffer.replace(buf);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use kernel::ErrorCode;
+    use kernel::hil::ble_advertising::RadioChannel;
+    use kernel::hil::ble_connection::{BleConnectionDriver, ConnectionClient};
+    use kernel::hil::time::{Alarm, AlarmClient, Freq1MHz, Ticks32, Time};
+    use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+    use super::*;
+
+    /// Radio that records the requested operations. Tests play the central
+    /// by calling the link layer's `ConnectionClient` methods.
+    struct SimRadio<'a> {
+        client: OptionalCell<&'a dyn ConnectionClient>,
+        buffer: TakeCell<'static, [u8]>,
+        channel: Cell<Option<RadioChannel>>,
+        access_address: Cell<u32>,
+        transmitted: Cell<usize>,
+    }
+
+    impl<'a> BleConnectionDriver<'a> for SimRadio<'a> {
+        fn set_access_address(&self, access_address: u32, _crc_init: u32) {
+            self.access_address.set(access_address);
+        }
+
+        fn transmit_then_receive(
+            &self,
+            buf: &'static mut [u8],
+            len: usize,
+            channel: RadioChannel,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            self.transmitted.set(len);
+            self.channel.set(Some(channel));
+            self.buffer.replace(buf);
+            Ok(())
+        }
+
+        fn receive_then_transmit(
+            &self,
+            buf: &'static mut [u8],
+            len: usize,
+            channel: RadioChannel,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            self.transmitted.set(len);
+            self.channel.set(Some(channel));
+            self.buffer.replace(buf);
+            Ok(())
+        }
+
+        fn stop(&self) -> Result<&'static mut [u8], ErrorCode> {
+            self.channel.set(None);
+            self.buffer.take().ok_or(ErrorCode::OFF)
+        }
+
+        fn set_connection_client(&self, client: &'a dyn ConnectionClient) {
+            self.client.set(client);
+        }
+    }
+
+    impl SimRadio<'_> {
+        /// Answer the advertisement currently on air with `pdu`.
+        fn answer(&self, pdu: &[u8]) {
+            let buf = self.buffer.take().unwrap();
+            buf[..pdu.len()].copy_from_slice(pdu);
+            self.channel.set(None);
+            self.client
+                .map(|client| client.operation_done(buf, pdu.len(), Ok(())));
+        }
+
+        /// Send `pdu` as the central and return the peripheral's response,
+        /// which was staged before `pdu` arrived.
+        fn exchange(&self, pdu: &[u8], crc_ok: bool) -> Vec<u8> {
+            let buf = self.buffer.take().unwrap();
+            self.channel.set(None);
+            let response = buf[..self.transmitted.get()].to_vec();
+            buf[..pdu.len()].copy_from_slice(pdu);
+            let result = if crc_ok { Ok(()) } else { Err(ErrorCode::FAIL) };
+            self.client
+                .map(|client| client.operation_done(buf, pdu.len(), result));
+            response
+        }
+    }
+
+    struct SimAlarm<'a> {
+        now: Cell<u32>,
+        expiration: Cell<Option<u32>>,
+        client: OptionalCell<&'a dyn AlarmClient>,
+    }
+
+    impl Time for SimAlarm<'_> {
+        type Frequency = Freq1MHz;
+        type Ticks = Ticks32;
+
+        fn now(&self) -> Ticks32 {
+            Ticks32::from(self.now.get())
+        }
+    }
+
+    impl<'a> Alarm<'a> for SimAlarm<'a> {
+        fn set_alarm_client(&self, client: &'a dyn AlarmClient) {
+            self.client.set(client);
+        }
+
+        fn set_alarm(&self, reference: Ticks32, dt: Ticks32) {
+            self.expiration
+                .set(Some(reference.wrapping_add(dt).into_u32()));
+        }
+
+        fn get_alarm(&self) -> Ticks32 {
+            Ticks32::from(self.expiration.get().unwrap_or(0))
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.expiration.set(None);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.expiration.get().is_some()
+        }
+
+        fn minimum_dt(&self) -> Ticks32 {
+            Ticks32::from(1)
+        }
+    }
+
+    impl SimAlarm<'_> {
+        /// Advance time to the armed alarm and fire it.
+        fn fire(&self) {
+            let expiration = self.expiration.take().unwrap();
+            self.now.set(expiration);
+            self.client.map(|client| client.alarm());
+        }
+    }
+
+    #[derive(Default)]
+    struct Client {
+        connected: Cell<bool>,
+        disconnected: Cell<Option<u8>>,
+        received: Cell<usize>,
+        sent: Cell<usize>,
+    }
+
+    impl LinkLayerClient for Client {
+        fn connected(&self) {
+            self.connected.set(true);
+        }
+
+        fn disconnected(&self, reason: u8) {
+            self.disconnected.set(Some(reason));
+        }
+
+        fn received(&self, payload: &[u8]) {
+            self.received.set(payload.len());
+        }
+
+        fn sent(&self) {
+            self.sent.set(self.sent.get() + 1);
+        }
+    }
+
+    const ADDRESS: [u8; ADDRESS_LEN] = [1, 2, 3, 4, 5, 0xc6];
+    const ACCESS_ADDRESS: u32 = 0x50654c2a;
+
+    type TestLinkLayer = PeripheralLinkLayer<'static, SimRadio<'static>, SimAlarm<'static>>;
+
+    fn setup() -> (
+        &'static SimRadio<'static>,
+        &'static SimAlarm<'static>,
+        &'static TestLinkLayer,
+        &'static Client,
+    ) {
+        let radio = Box::leak(Box::new(SimRadio {
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            channel: Cell::new(None),
+            access_address: Cell::new(0),
+            transmitted: Cell::new(0),
+        }));
+        let alarm = Box::leak(Box::new(SimAlarm {
+            now: Cell::new(1000),
+            expiration: Cell::new(None),
+            client: OptionalCell::empty(),
+        }));
+        let buffer = Box::leak(Box::new([0; RADIO_BUF_LEN]));
+        let ll = Box::leak(Box::new(PeripheralLinkLayer::new(
+            radio, alarm, buffer, ADDRESS,
+        )));
+        let client = Box::leak(Box::new(Client::default()));
+        radio.set_connection_client(ll);
+        alarm.set_alarm_client(ll);
+        ll.set_client(client);
+        (radio, alarm, ll, client)
+    }
+
+    fn connect_ind(interval: u16, timeout: u16, channel_map: [u8; 5], hop: u8) -> Vec<u8> {
+        let mut pdu = std::vec![CONNECT_IND | TXADD | RXADD, CONNECT_IND_LEN as u8];
+        pdu.extend_from_slice(&[0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5]);
+        pdu.extend_from_slice(&ADDRESS);
+        pdu.extend_from_slice(&ACCESS_ADDRESS.to_le_bytes());
+        pdu.extend_from_slice(&[0x17, 0x5a, 0xc3]);
+        pdu.push(2);
+        pdu.extend_from_slice(&1u16.to_le_bytes());
+        pdu.extend_from_slice(&interval.to_le_bytes());
+        pdu.extend_from_slice(&0u16.to_le_bytes());
+        pdu.extend_from_slice(&timeout.to_le_bytes());
+        pdu.extend_from_slice(&channel_map);
+        pdu.push(hop);
+        pdu
+    }
+
+    fn connected() -> (
+        &'static SimRadio<'static>,
+        &'static SimAlarm<'static>,
+        &'static TestLinkLayer,
+        &'static Client,
+    ) {
+        let (radio, alarm, ll, client) = setup();
+        ll.start_advertising(&[2, 1, 6], 100).unwrap();
+        radio.answer(&connect_ind(24, 100, [0xff, 0xff, 0xff, 0xff, 0x1f], 7));
+        assert!(client.connected.get());
+        alarm.fire();
+        (radio, alarm, ll, client)
+    }
+
+    #[test]
+    fn channel_selection() {
+        let all = [0xff, 0xff, 0xff, 0xff, 0x1f];
+        assert_eq!(remap_channel(&all, 12), 12);
+        // Only channels 1 and 3 used: unused channels map onto them by
+        // their index modulo 2.
+        let two = [0b1010, 0, 0, 0, 0];
+        assert_eq!(remap_channel(&two, 3), 3);
+        assert_eq!(remap_channel(&two, 4), 1);
+        assert_eq!(remap_channel(&two, 5), 3);
+    }
+
+    #[test]
+    fn advertises_on_all_channels() {
+        let (radio, alarm, ll, _) = setup();
+        ll.start_advertising(&[2, 1, 6], 100).unwrap();
+        assert_eq!(radio.transmitted.get(), 2 + ADDRESS_LEN + 3);
+        assert_eq!(
+            radio.channel.get(),
+            Some(RadioChannel::AdvertisingChannel37)
+        );
+        alarm.fire();
+        assert_eq!(
+            radio.channel.get(),
+            Some(RadioChannel::AdvertisingChannel38)
+        );
+        alarm.fire();
+        assert_eq!(
+            radio.channel.get(),
+            Some(RadioChannel::AdvertisingChannel39)
+        );
+        alarm.fire();
+        assert_eq!(radio.channel.get(), None);
+        alarm.fire();
+        assert_eq!(
+            radio.channel.get(),
+            Some(RadioChannel::AdvertisingChannel37)
+        );
+    }
+
+    #[test]
+    fn ignores_connect_ind_for_other_device() {
+        let (radio, _, ll, client) = setup();
+        ll.start_advertising(&[], 100).unwrap();
+        let mut pdu = connect_ind(24, 100, [0xff, 0xff, 0xff, 0xff, 0x1f], 7);
+        pdu[2 + ADDRESS_LEN] ^= 0xff;
+        radio.answer(&pdu);
+        assert!(!client.connected.get());
+    }
+
+    #[test]
+    fn connects_and_hops() {
+        let (radio, alarm, _, _) = connected();
+        assert_eq!(radio.access_address.get(), ACCESS_ADDRESS);
+        // First event uses unmapped channel 7, then 14.
+        assert_eq!(radio.channel.get(), Some(RadioChannel::DataChannel7));
+        radio.exchange(&[LLID_CONTINUATION, 0], true);
+        alarm.fire();
+        assert_eq!(radio.channel.get(), Some(RadioChannel::DataChannel14));
+    }
+
+    #[test]
+    fn acknowledges_and_retransmits() {
+        let (radio, alarm, ll, client) = connected();
+        ll.send(&[4, 0, 4, 0, 0x0b]).unwrap();
+
+        // The response to the central's data (SN=0, NESN=0) was staged
+        // before the data was queued: an empty PDU with SN=0, NESN=0. The
+        // data is accepted, but only acknowledged in the next event.
+        let rsp = radio.exchange(&[LLID_START, 5, 1, 0, 4, 0, 0x0a], true);
+        assert_eq!(rsp, [LLID_CONTINUATION, 0]);
+        assert_eq!(client.received.get(), 5);
+
+        // The central resends its data (SN=0) and acknowledges the empty
+        // PDU (NESN=1). The staged response acknowledges the data.
+        alarm.fire();
+        let rsp = radio.exchange(&[LLID_START | NESN, 5, 1, 0, 4, 0, 0x0a], true);
+        assert_eq!(rsp, [LLID_CONTINUATION | NESN, 0]);
+
+        // Our data goes out with SN=1. The central sends an empty PDU (SN=1)
+        // before it has seen the data.
+        alarm.fire();
+        let rsp = radio.exchange(&[LLID_CONTINUATION | SN | NESN, 0], true);
+        assert_eq!(rsp[0], LLID_START | NESN | SN);
+        assert_eq!(rsp[1], 5);
+        assert_eq!(client.sent.get(), 0);
+
+        // The data is sent again, since the staged response could not know
+        // that the central acknowledges it (NESN=0) in this event.
+        alarm.fire();
+        let rsp = radio.exchange(&[LLID_CONTINUATION | SN, 0], true);
+        assert_eq!(rsp[0], LLID_START | SN);
+        assert_eq!(client.sent.get(), 1);
+
+        // A corrupted packet is neither accepted nor acknowledged.
+        alarm.fire();
+        let rsp = radio.exchange(&[LLID_START, 5, 1, 0, 4, 0, 0x0a], false);
+        assert_eq!(rsp, [LLID_CONTINUATION, 0]);
+        alarm.fire();
+        let rsp = radio.exchange(&[LLID_CONTINUATION | SN | NESN, 0], true);
+        assert_eq!(rsp, [LLID_CONTINUATION, 0]);
+        assert_eq!(client.received.get(), 5);
+    }
+
+    #[test]
+    fn answers_control_procedures() {
+        let (radio, alarm, _, client) = connected();
+        radio.exchange(&[LLID_CONTROL, 1, opcode::FEATURE_REQ], true);
+        alarm.fire();
+        radio.exchange(&[LLID_CONTINUATION | SN | NESN, 0], true);
+        alarm.fire();
+        let rsp = radio.exchange(&[LLID_CONTINUATION | NESN, 0], true);
+        assert_eq!(rsp[0] & LLID_MASK, LLID_CONTROL);
+        assert_eq!(rsp[2], opcode::FEATURE_RSP);
+
+        alarm.fire();
+        radio.exchange(&[LLID_CONTROL | SN, 2, opcode::TERMINATE_IND, 0x13], true);
+        assert_eq!(
+            client.disconnected.get(),
+            Some(reason::REMOTE_USER_TERMINATED)
+        );
+    }
+
+    #[test]
+    fn supervision_timeout() {
+        let (_, alarm, _, client) = connected();
+        // 1 s timeout with a 30 ms interval: nothing heard, so the
+        // connection fails to establish after 6 intervals.
+        for _ in 0..8 {
+            if client.disconnected.get().is_some() {
+                break;
+            }
+            alarm.fire();
+        }
+        assert_eq!(client.disconnected.get(), Some(reason::CONNECTION_TIMEOUT));
+    }
+}
@@ -0,0 +1,33 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Bluetooth Low Energy peripheral role.
+//!
+//! A connectable BLE stack on top of `kernel::hil::ble_connection`, layered
+//! as in the specification:
+//!
+//! ```text
+//! +--------------------------------------------+
+//! | BlePeripheralDriver (syscall driver)       |
+//! +--------------------------------------------+
+//! | GattServer (ATT server, fixed database)    |
+//! +--------------------------------------------+
+//! | L2cap (fixed channels)                     |
+//! +--------------------------------------------+
+//! | PeripheralLinkLayer                        |
+//! +--------------------------------------------+
+//! | BleConnectionDriver (radio HIL)            |
+//! +--------------------------------------------+
+//! ```
+//!
+//! The link layer takes over the radio, so a board uses either this stack or
+//! the advertising-only `ble_advertising_driver`.
+
+pub mod driver;
+pub mod gatt;
+pub mod l2cap;
+pub mod link_layer;
+
+pub use self::driver::BlePeripheralDriver;
+pub use self::driver::DRIVER_NUM;
@@ -0,0 +1,753 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Persistent crash log stored in flash.
+//!
+//! This capsule keeps compact records of kernel panics and process faults in
+//! a region of flash allocated with [`storage_volume!`](kernel::storage_volume),
+//! so that they can be inspected after the board reboots. It implements
+//! [`CrashRecorder`], which the panic handler uses when it is set in the
+//! board's `PanicResources`, and [`CrashLog`], which the process console uses
+//! to print and clear the records. Processes can read and clear the records
+//! through the syscall interface, if the board lists their `ShortId` as a
+//! reader. Crash records can contain data from any process, so other
+//! processes can only check whether the driver exists.
+//!
+//! Each flash page of the volume holds one record. When every page holds a
+//! record, the oldest record is replaced. A record is laid out as follows, with
+//! all fields stored as little-endian `u32` values:
+//!
+//! ```text
+//! 0   magic ("TKCR")
+//! 4   CRC-32 of the record from offset 8 to the end of the debug output
+//! 8   sequence number
+//! 12  kind (1: kernel panic, 2: process fault)
+//! 16  process name length
+//! 20  summary text length
+//! 24  debug output length
+//! 28  PC
+//! 32  LR
+//! 36  four fault status registers
+//! 52  process name, summary text and debug output
+//! ```
+//!
+//! Registers are truncated to 32 bits on 64-bit platforms. Records persist
+//! across reboots, but are erased when a new kernel is flashed.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! storage_volume!(CRASH_LOG, 8);
+//!
+//! let crash_log = components::crash_log::CrashLogComponent::new(
+//!     &CRASH_LOG,
+//!     &peripherals.nvmc,
+//!     board_kernel,
+//!     capsules_extra::crash_log::DRIVER_NUM,
+//!     &[ShortId::Fixed(NonZeroU32::new(0x100).unwrap())],
+//!     create_capability!(capabilities::MemoryAllocationCapability),
+//! )
+//! .finalize(components::crash_log_component_static!(nrf52840::nvmc::Nvmc));
+//!
+//! PANIC_RESOURCES.get().map(|resources| {
+//!     resources.crash_recorder.put(crash_log);
+//! });
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::debug::{CrashKind, CrashLog, CrashRecord, CrashRecorder};
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::flash::{self, Flash};
+use kernel::platform::chip::FaultRegisters;
+use kernel::process::ShortId;
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::helpers::crc32_posix;
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::CrashLog as usize;
+
+/// Marks the start of a valid record.
+const MAGIC: u32 = u32::from_le_bytes(*b"TKCR");
+
+/// Byte offsets of the record header fields.
+mod offset {
+    pub const MAGIC: usize = 0;
+    pub const CRC: usize = 4;
+    pub const SEQUENCE: usize = 8;
+    pub const KIND: usize = 12;
+    pub const NAME_LEN: usize = 16;
+    pub const TEXT_LEN: usize = 20;
+    pub const DEBUG_LEN: usize = 24;
+    pub const PC: usize = 28;
+    pub const LR: usize = 32;
+    pub const STATUS: usize = 36;
+}
+
+/// Size of the record header in bytes.
+pub const HEADER_LEN: usize = 52;
+
+/// Maximum number of bytes of the process name stored in a record.
+const MAX_NAME_LEN: usize = 32;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Clear done callback.
+    pub const CLEAR_DONE: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer a record is copied into.
+    pub const READ: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Erasing the page of `slot` before writing the new record to it.
+    Erase(usize),
+    /// Writing the new record.
+    Write,
+    /// Erasing the page of `slot` while clearing all records.
+    Clear(usize),
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct CrashLogDriver<'a, F: Flash + 'static> {
+    /// Underlying storage volume.
+    volume: &'static [u8],
+    /// Flash interface.
+    driver: &'a F,
+    /// Buffer the record being built is held in.
+    pagebuffer: TakeCell<'static, F::Page>,
+    /// Size of a flash page.
+    page_size: usize,
+    state: Cell<State>,
+    /// Whether a record has been started with `begin()` but not committed.
+    recording: Cell<bool>,
+    /// Lengths of the sections of the record being built.
+    name_len: Cell<usize>,
+    text_len: Cell<usize>,
+    debug_len: Cell<usize>,
+    /// Process that asked to clear the log.
+    clearing_process: OptionalCell<ProcessId>,
+    /// Processes allowed to read and clear the log.
+    readers: &'static [ShortId],
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<0>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a, F: Flash + 'static> CrashLogDriver<'a, F> {
+    pub fn new(
+        volume: &'static [u8],
+        driver: &'a F,
+        pagebuffer: &'static mut F::Page,
+        readers: &'static [ShortId],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<0>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> Self {
+        let page_size = pagebuffer.as_mut().len();
+        Self {
+            volume,
+            driver,
+            pagebuffer: TakeCell::new(pagebuffer),
+            page_size,
+            state: Cell::new(State::Idle),
+            recording: Cell::new(false),
+            name_len: Cell::new(0),
+            text_len: Cell::new(0),
+            debug_len: Cell::new(0),
+            clearing_process: OptionalCell::empty(),
+            readers,
+            apps: grant,
+        }
+    }
+
+    /// Number of records the volume can hold.
+    fn slots(&self) -> usize {
+        self.volume.len() / self.page_size
+    }
+
+    fn page_number(&self, slot: usize) -> usize {
+        (self.volume.as_ptr() as usize + slot * self.page_size) / self.page_size
+    }
+
+    /// The contents of `slot` if it holds a valid record.
+    fn slot(&self, slot: usize) -> Option<&'static [u8]> {
+        let page = self
+            .volume
+            .get(slot * self.page_size..(slot + 1) * self.page_size)?;
+        if read_u32(page, offset::MAGIC) != MAGIC {
+            return None;
+        }
+        let length = HEADER_LEN
+            .checked_add(read_u32(page, offset::NAME_LEN) as usize)?
+            .checked_add(read_u32(page, offset::TEXT_LEN) as usize)?
+            .checked_add(read_u32(page, offset::DEBUG_LEN) as usize)?;
+        let record = page.get(..length)?;
+        if crc32_posix(&record[offset::SEQUENCE..]) != read_u32(page, offset::CRC) {
+            return None;
+        }
+        Some(record)
+    }
+
+    /// The raw bytes of the record `index`, where 0 is the most recent.
+    fn raw_record(&self, index: usize) -> Option<&'static [u8]> {
+        // The log holds few records, so rank them by counting the records
+        // newer than each rather than sorting.
+        (0..self.slots())
+            .filter_map(|slot| self.slot(slot))
+            .find(|record| {
+                let sequence = read_u32(record, offset::SEQUENCE);
+                let newer = (0..self.slots())
+                    .filter_map(|slot| self.slot(slot))
+                    .filter(|other| read_u32(other, offset::SEQUENCE) > sequence)
+                    .count();
+                newer == index
+            })
+    }
+
+    /// Write a byte slice into the record being built at `pos`, returning the
+    /// number of bytes written.
+    fn copy_in(&self, pos: usize, bytes: &[u8]) -> usize {
+        self.pagebuffer.map_or(0, |pagebuffer| {
+            let page = pagebuffer.as_mut();
+            let length = cmp::min(bytes.len(), page.len().saturating_sub(pos));
+            page[pos..pos + length].copy_from_slice(&bytes[..length]);
+            length
+        })
+    }
+
+    fn clear_done(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.clearing_process.take().map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let _ = kernel_data
+                    .schedule_upcall(upcall::CLEAR_DONE, (into_statuscode(result), 0, 0));
+            });
+        });
+    }
+}
+
+fn read_u32(buffer: &[u8], pos: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buffer[pos..pos + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn write_u32(buffer: &mut [u8], pos: usize, value: u32) {
+    buffer[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+impl<F: Flash + 'static> CrashRecorder for CrashLogDriver<'_, F> {
+    fn begin(&self, kind: CrashKind, registers: &FaultRegisters, process_name: Option<&str>) {
+        self.recording.set(false);
+        if self.state.get() != State::Idle {
+            return;
+        }
+
+        self.pagebuffer.map(|pagebuffer| {
+            let page = pagebuffer.as_mut();
+            page.fill(0xFF);
+            write_u32(page, offset::KIND, kind as u32);
+            write_u32(page, offset::PC, registers.pc as u32);
+            write_u32(page, offset::LR, registers.lr as u32);
+            for (i, status) in registers.status.iter().enumerate() {
+                write_u32(page, offset::STATUS + 4 * i, *status as u32);
+            }
+            self.recording.set(true);
+        });
+
+        let name = process_name.map_or(&[][..], |name| {
+            &name.as_bytes()[..cmp::min(name.len(), MAX_NAME_LEN)]
+        });
+        self.name_len.set(self.copy_in(HEADER_LEN, name));
+        self.text_len.set(0);
+        self.debug_len.set(0);
+    }
+
+    fn append(&self, text: &[u8]) {
+        if !self.recording.get() || self.debug_len.get() > 0 {
+            return;
+        }
+        let pos = HEADER_LEN + self.name_len.get() + self.text_len.get();
+        let written = self.copy_in(pos, text);
+        self.text_len.set(self.text_len.get() + written);
+    }
+
+    fn append_debug(&self, text: &[u8]) {
+        if !self.recording.get() {
+            return;
+        }
+        let start = HEADER_LEN + self.name_len.get() + self.text_len.get();
+        self.pagebuffer.map(|pagebuffer| {
+            let page = pagebuffer.as_mut();
+            let available = page.len().saturating_sub(start);
+            // Keep only the most recent output: drop the start of `text` if
+            // it does not fit at all, then the oldest output already stored.
+            let text = &text[text.len().saturating_sub(available)..];
+            let kept = cmp::min(self.debug_len.get(), available - text.len());
+            let old_end = start + self.debug_len.get();
+            page.copy_within(old_end - kept..old_end, start);
+            page[start + kept..start + kept + text.len()].copy_from_slice(text);
+            self.debug_len.set(kept + text.len());
+        });
+    }
+
+    fn commit(&self) -> Result<(), ErrorCode> {
+        if !self.recording.get() {
+            return Err(ErrorCode::OFF);
+        }
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.slots() == 0 {
+            return Err(ErrorCode::NOMEM);
+        }
+        self.recording.set(false);
+
+        // Replace an empty slot if there is one, otherwise the oldest record.
+        let mut sequence = 0;
+        let mut target: Option<(usize, Option<u32>)> = None;
+        for slot in 0..self.slots() {
+            let slot_sequence = self
+                .slot(slot)
+                .map(|record| read_u32(record, offset::SEQUENCE));
+            if let Some(slot_sequence) = slot_sequence {
+                sequence = cmp::max(sequence, slot_sequence.wrapping_add(1));
+            }
+            let better = match (target, slot_sequence) {
+                (None, _) => true,
+                (Some((_, Some(_))), None) => true,
+                (Some((_, Some(oldest))), Some(slot_sequence)) => slot_sequence < oldest,
+                (Some((_, None)), _) => false,
+            };
+            if better {
+                target = Some((slot, slot_sequence));
+            }
+        }
+        let slot = target.map_or(0, |(slot, _)| slot);
+
+        let length = HEADER_LEN + self.name_len.get() + self.text_len.get() + self.debug_len.get();
+        self.pagebuffer.map(|pagebuffer| {
+            let page = pagebuffer.as_mut();
+            write_u32(page, offset::MAGIC, MAGIC);
+            write_u32(page, offset::SEQUENCE, sequence);
+            write_u32(page, offset::NAME_LEN, self.name_len.get() as u32);
+            write_u32(page, offset::TEXT_LEN, self.text_len.get() as u32);
+            write_u32(page, offset::DEBUG_LEN, self.debug_len.get() as u32);
+            let crc = crc32_posix(&page[offset::SEQUENCE..length]);
+            write_u32(page, offset::CRC, crc);
+        });
+
+        self.driver.erase_page(self.page_number(slot))?;
+        self.state.set(State::Erase(slot));
+        Ok(())
+    }
+
+    fn is_busy(&self) -> bool {
+        matches!(self.state.get(), State::Erase(_) | State::Write)
+    }
+}
+
+impl<F: Flash + 'static> CrashLog for CrashLogDriver<'_, F> {
+    fn record_count(&self) -> usize {
+        (0..self.slots())
+            .filter(|slot| self.slot(*slot).is_some())
+            .count()
+    }
+
+    fn record(&self, index: usize) -> Option<CrashRecord<'_>> {
+        let record = self.raw_record(index)?;
+        let kind = match read_u32(record, offset::KIND) {
+            1 => CrashKind::KernelPanic,
+            _ => CrashKind::ProcessFault,
+        };
+        let mut status = [0; 4];
+        for (i, value) in status.iter_mut().enumerate() {
+            *value = read_u32(record, offset::STATUS + 4 * i) as usize;
+        }
+        let name_end = HEADER_LEN + read_u32(record, offset::NAME_LEN) as usize;
+        let text_end = name_end + read_u32(record, offset::TEXT_LEN) as usize;
+
+        Some(CrashRecord {
+            sequence: read_u32(record, offset::SEQUENCE),
+            kind,
+            registers: FaultRegisters {
+                pc: read_u32(record, offset::PC) as usize,
+                lr: read_u32(record, offset::LR) as usize,
+                status,
+            },
+            process_name: &record[HEADER_LEN..name_end],
+            text: &record[name_end..text_end],
+            debug: &record[text_end..],
+        })
+    }
+
+    fn clear(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.slots() == 0 {
+            return Err(ErrorCode::NOMEM);
+        }
+        self.driver.erase_page(self.page_number(0))?;
+        self.state.set(State::Clear(0));
+        Ok(())
+    }
+}
+
+impl<F: Flash + 'static> flash::Client<F> for CrashLogDriver<'_, F> {
+    fn read_complete(&self, read_buffer: &'static mut F::Page, _result: Result<(), flash::Error>) {
+        // Records are read directly from the storage volume, so this capsule
+        // never starts a read. Keep the buffer in case a flash driver calls
+        // this anyway.
+        self.pagebuffer.replace(read_buffer);
+    }
+
+    fn write_complete(&self, pagebuffer: &'static mut F::Page, _result: Result<(), flash::Error>) {
+        // A failed write leaves an invalid record behind, which is ignored.
+        self.pagebuffer.replace(pagebuffer);
+        self.state.set(State::Idle);
+    }
+
+    fn erase_complete(&self, result: Result<(), flash::Error>) {
+        match self.state.get() {
+            State::Erase(slot) => {
+                let started = result.is_ok()
+                    && self.pagebuffer.take().is_some_and(|pagebuffer| {
+                        match self.driver.write_page(self.page_number(slot), pagebuffer) {
+                            Ok(()) => true,
+                            Err((_, pagebuffer)) => {
+                                self.pagebuffer.replace(pagebuffer);
+                                false
+                            }
+                        }
+                    });
+                self.state
+                    .set(if started { State::Write } else { State::Idle });
+            }
+            State::Clear(slot) => {
+                if result.is_err() {
+                    self.clear_done(Err(ErrorCode::FAIL));
+                } else if slot + 1 == self.slots() {
+                    self.clear_done(Ok(()));
+                } else {
+                    match self.driver.erase_page(self.page_number(slot + 1)) {
+                        Ok(()) => self.state.set(State::Clear(slot + 1)),
+                        Err(e) => self.clear_done(Err(e)),
+                    }
+                }
+            }
+            State::Idle | State::Write => {}
+        }
+    }
+}
+
+/// Provide an interface for userland.
+impl<F: Flash + 'static> SyscallDriver for CrashLogDriver<'_, F> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Return the number of crash records.
+    /// - `2`: Copy record `arg1` (0 is the most recent) into read-write allow
+    ///   0, and return the length of the record.
+    /// - `3`: Erase all records. Upcall 0 is issued when done.
+    ///
+    /// Commands 1 to 3 return `NOSUPPORT` to processes that are not readers.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num != 0 && !self.readers.contains(&processid.short_app_id()) {
+            return CommandReturn::failure(ErrorCode::NOSUPPORT);
+        }
+
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.record_count() as u32),
+            2 => {
+                let Some(record) = self.raw_record(arg1) else {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                };
+                self.apps
+                    .enter(processid, |_, kernel_data| {
+                        kernel_data
+                            .get_readwrite_processbuffer(rw_allow::READ)
+                            .and_then(|read| {
+                                read.mut_enter(|app_buffer| {
+                                    let length = cmp::min(app_buffer.len(), record.len());
+                                    app_buffer[..length].copy_from_slice(&record[..length]);
+                                })
+                            })
+                            .map_err(ErrorCode::from)
+                    })
+                    .map_err(ErrorCode::from)
+                    .and_then(|result| result)
+                    .map_or_else(CommandReturn::failure, |()| {
+                        CommandReturn::success_u32(record.len() as u32)
+                    })
+            }
+            3 => match self.clear() {
+                Ok(()) => {
+                    self.clearing_process.set(processid);
+                    CommandReturn::success()
+                }
+                Err(e) => CommandReturn::failure(e),
+            },
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use capsules_test_harness::flash::MappedFlash;
+    use capsules_test_harness::loader::AppFlash;
+    use capsules_test_harness::process::{HostKernel, Tbf, Upcall};
+    use capsules_test_harness::{deferred_call, leak, run_until_idle};
+    use core::num::NonZeroU32;
+    use kernel::hil::flash::HasClient;
+    use kernel::syscall::SyscallReturn;
+    use std::boxed::Box;
+
+    const PAGE_SIZE: usize = 128;
+    const SLOTS: usize = 3;
+    const READER: u32 = 0x100;
+    const OTHER: u32 = 0x200;
+
+    type TestFlash = MappedFlash<PAGE_SIZE>;
+    type TestLog = CrashLogDriver<'static, TestFlash>;
+
+    fn crash_log() -> (&'static TestLog, &'static TestFlash, &'static HostKernel) {
+        let kernel = HostKernel::new();
+        let flash = leak(MappedFlash::new(SLOTS));
+        let crash_log = leak(CrashLogDriver::new(
+            flash.volume(),
+            flash,
+            Box::leak(Box::default()),
+            leak([ShortId::Fixed(NonZeroU32::new(READER).unwrap())]),
+            kernel.create_grant(DRIVER_NUM),
+        ));
+        flash.set_client(crash_log);
+        kernel.add_driver(DRIVER_NUM, crash_log);
+        (crash_log, flash, kernel)
+    }
+
+    const REGISTERS: FaultRegisters = FaultRegisters {
+        pc: 0x1000,
+        lr: 0x2000,
+        status: [1, 2, 3, 4],
+    };
+
+    /// Write a record with summary `text` and wait until it is stored.
+    fn record(crash_log: &TestLog, flash: &TestFlash, text: &[u8]) {
+        crash_log.begin(CrashKind::ProcessFault, &REGISTERS, Some("blink"));
+        crash_log.append(text);
+        assert_eq!(crash_log.commit(), Ok(()));
+        assert!(crash_log.is_busy());
+        run_until_idle(&[flash]);
+        assert!(!crash_log.is_busy());
+    }
+
+    #[test]
+    fn record_is_read_back() {
+        let (crash_log, flash, _) = crash_log();
+        crash_log.begin(CrashKind::KernelPanic, &REGISTERS, None);
+        crash_log.append(b"panicked ");
+        crash_log.append(b"at 'oops'");
+        crash_log.append_debug(b"debug output");
+        // Summary text after debug output is dropped.
+        crash_log.append(b"late");
+        assert_eq!(crash_log.commit(), Ok(()));
+        run_until_idle(&[flash]);
+
+        assert_eq!(crash_log.record_count(), 1);
+        let record = crash_log.record(0).unwrap();
+        assert_eq!(record.sequence, 0);
+        assert_eq!(record.kind, CrashKind::KernelPanic);
+        assert_eq!(record.registers.pc, REGISTERS.pc);
+        assert_eq!(record.registers.lr, REGISTERS.lr);
+        assert_eq!(record.registers.status, REGISTERS.status);
+        assert_eq!(record.process_name, b"");
+        assert_eq!(record.text, b"panicked at 'oops'");
+        assert_eq!(record.debug, b"debug output");
+        assert!(crash_log.record(1).is_none());
+    }
+
+    #[test]
+    fn only_the_most_recent_debug_output_is_kept() {
+        let (crash_log, flash, _) = crash_log();
+        let name = "a-process-name-longer-than-32-bytes";
+        crash_log.begin(CrashKind::ProcessFault, &REGISTERS, Some(name));
+        crash_log.append(b"fault");
+        let available = PAGE_SIZE - HEADER_LEN - MAX_NAME_LEN - 5;
+        let output: std::vec::Vec<u8> = (0..available as u8 + 10).collect();
+        crash_log.append_debug(&output[..20]);
+        crash_log.append_debug(&output[20..]);
+        assert_eq!(crash_log.commit(), Ok(()));
+        run_until_idle(&[flash]);
+
+        let record = crash_log.record(0).unwrap();
+        assert_eq!(record.process_name, &name.as_bytes()[..MAX_NAME_LEN]);
+        assert_eq!(record.text, b"fault");
+        assert_eq!(record.debug, &output[10..]);
+    }
+
+    #[test]
+    fn oldest_record_is_replaced() {
+        let (crash_log, flash, _) = crash_log();
+        for text in [b"0", b"1", b"2", b"3", b"4"] {
+            record(crash_log, flash, text);
+        }
+
+        assert_eq!(crash_log.record_count(), SLOTS);
+        for (index, text) in [b"4", b"3", b"2"].iter().enumerate() {
+            let record = crash_log.record(index).unwrap();
+            assert_eq!(record.text, *text);
+            assert_eq!(record.sequence as usize, 4 - index);
+        }
+    }
+
+    #[test]
+    fn corrupted_record_is_ignored() {
+        let (crash_log, flash, _) = crash_log();
+        record(crash_log, flash, b"0");
+        record(crash_log, flash, b"1");
+
+        // Change the text of the second record, in the second page.
+        flash.write(PAGE_SIZE + HEADER_LEN + "blink".len(), b"X");
+
+        assert_eq!(crash_log.record_count(), 1);
+        assert_eq!(crash_log.record(0).unwrap().text, b"0");
+        // The slot of the broken record is reused first.
+        record(crash_log, flash, b"2");
+        assert_eq!(crash_log.record_count(), 2);
+        assert_eq!(crash_log.record(1).unwrap().text, b"0");
+    }
+
+    #[test]
+    fn record_started_while_writing_is_dropped() {
+        let (crash_log, flash, _) = crash_log();
+        crash_log.begin(CrashKind::ProcessFault, &REGISTERS, None);
+        assert_eq!(crash_log.commit(), Ok(()));
+        crash_log.begin(CrashKind::KernelPanic, &REGISTERS, None);
+        assert_eq!(crash_log.commit(), Err(ErrorCode::OFF));
+        run_until_idle(&[flash]);
+
+        assert_eq!(crash_log.record_count(), 1);
+        assert_eq!(crash_log.record(0).unwrap().kind, CrashKind::ProcessFault);
+        assert_eq!(crash_log.commit(), Err(ErrorCode::OFF));
+    }
+
+    #[test]
+    fn failed_write_leaves_no_record() {
+        let (crash_log, flash, _) = crash_log();
+        crash_log.begin(CrashKind::ProcessFault, &REGISTERS, None);
+        assert_eq!(crash_log.commit(), Ok(()));
+        flash.fail_next();
+        run_until_idle(&[flash]);
+
+        assert!(!crash_log.is_busy());
+        assert_eq!(crash_log.record_count(), 0);
+        record(crash_log, flash, b"0");
+        assert_eq!(crash_log.record_count(), 1);
+    }
+
+    #[test]
+    fn only_readers_access_the_records() {
+        deferred_call::run(|| {
+            let (crash_log, flash, kernel) = crash_log();
+            record(crash_log, flash, b"fault");
+            let length = crash_log.raw_record(0).unwrap().len();
+
+            let apps = AppFlash::new(4096);
+            apps.install(0, &Tbf::new("reader").short_id(READER).size(1024).build());
+            apps.install(1024, &Tbf::new("other").short_id(OTHER).size(1024).build());
+            kernel.dynamic_binary_storage(apps);
+            run_until_idle(&[apps]);
+            let reader = kernel.attach(ShortId::Fixed(NonZeroU32::new(READER).unwrap()));
+            let other = kernel.attach(ShortId::Fixed(NonZeroU32::new(OTHER).unwrap()));
+
+            assert!(matches!(
+                other.command(DRIVER_NUM, 0, 0, 0),
+                SyscallReturn::Success
+            ));
+            for command in 1..=3 {
+                assert!(matches!(
+                    other.command(DRIVER_NUM, command, 0, 0),
+                    SyscallReturn::Failure(ErrorCode::NOSUPPORT)
+                ));
+            }
+
+            assert!(matches!(
+                reader.command(DRIVER_NUM, 1, 0, 0),
+                SyscallReturn::SuccessU32(1)
+            ));
+            let buffer = reader.allocate(PAGE_SIZE);
+            reader.allow_readwrite(DRIVER_NUM, rw_allow::READ, buffer);
+            assert!(matches!(
+                reader.command(DRIVER_NUM, 2, 0, 0),
+                SyscallReturn::SuccessU32(len) if len as usize == length
+            ));
+            assert_eq!(
+                reader.read(buffer)[..length],
+                crash_log.raw_record(0).unwrap()[..]
+            );
+            assert!(matches!(
+                reader.command(DRIVER_NUM, 2, 1, 0),
+                SyscallReturn::Failure(ErrorCode::INVAL)
+            ));
+
+            reader.subscribe(DRIVER_NUM, upcall::CLEAR_DONE);
+            assert!(matches!(
+                reader.command(DRIVER_NUM, 3, 0, 0),
+                SyscallReturn::Success
+            ));
+            run_until_idle(&[flash]);
+            assert_eq!(
+                reader.yield_wait(),
+                Some(Upcall {
+                    driver_number: DRIVER_NUM,
+                    subscribe_number: upcall::CLEAR_DONE,
+                    arguments: [0, 0, 0],
+                })
+            );
+            assert_eq!(crash_log.record_count(), 0);
+        });
+    }
+}
@@ -0,0 +1,1847 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Syscall driver for files on a FAT volume.
+//!
+//! All operations run one at a time on a single cached sector. Operations
+//! which need I/O are written as a state machine, [`State`], whose steps are
+//! restarted from the beginning whenever a sector they need has been loaded.
+//! A step therefore only updates the state once it is complete.
+//!
+//! Modified sectors are written back when another sector is needed and at the
+//! end of every operation, before the application is notified. Sectors of the
+//! FAT are written to every copy of the FAT.
+//!
+//! An I/O error unmounts the volume, which is mounted again by the next
+//! operation. However, the `NonvolatileStorage` HIL does not return the sector
+//! buffer when the storage driver refuses a read or write. The buffer is then
+//! lost, and every later operation fails with `RESERVE` until the board is
+//! reset.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let fat_fs = static_init!(
+//!     capsules_extra::fat_fs::FatFs<'static>,
+//!     capsules_extra::fat_fs::FatFs::new(
+//!         sd_card_storage,
+//!         board_kernel.create_grant(capsules_extra::fat_fs::DRIVER_NUM, &grant_cap),
+//!         buffer,
+//!     )
+//! );
+//! sd_card_storage.set_client(fat_fs);
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+
+use super::layout::{self, DirEntry, RawEntry, Volume};
+use super::layout::{DIR_ENTRY_LEN, ENTRIES_PER_SECTOR, SECTOR_SIZE};
+
+pub const DRIVER_NUM: usize = driver::NUM::FatFs as usize;
+
+/// Size of the buffer provided to this capsule: one sector.
+pub const BUF_LEN: usize = SECTOR_SIZE;
+
+/// Number of files each application can have open at the same time.
+pub const MAX_OPEN_FILES: usize = 4;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Open done callback.
+    pub const OPEN_DONE: usize = 0;
+    /// Read done callback.
+    pub const READ_DONE: usize = 1;
+    /// Write done callback.
+    pub const WRITE_DONE: usize = 2;
+    /// List done callback.
+    pub const LIST_DONE: usize = 3;
+    /// Remove done callback.
+    pub const REMOVE_DONE: usize = 4;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 5;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Data to write to a file.
+    pub const WRITE: usize = 0;
+    /// Name of the file to open or remove.
+    pub const NAME: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer for data read from a file, or the name of a listed file.
+    pub const READ: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Flags of the open command.
+mod open_flags {
+    pub const WRITE: usize = 1 << 0;
+    pub const CREATE: usize = 1 << 1;
+    pub const TRUNCATE: usize = 1 << 2;
+}
+
+/// Position of a directory entry on the volume.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct EntryLocation {
+    sector: u32,
+    index: usize,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct OpenFile {
+    /// Directory entry of the file, which also identifies it.
+    location: EntryLocation,
+    first_cluster: u32,
+    size: u32,
+    position: u32,
+    writable: bool,
+    /// Cluster holding `position`, or 0 if it has not been looked up yet.
+    cluster: u32,
+    /// Index of `cluster` in the cluster chain of the file.
+    cluster_index: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Command {
+    Open { dir_id: u32, flags: usize },
+    Read { handle: usize, len: usize },
+    Write { handle: usize, len: usize },
+    List { dir_id: u32, index: usize },
+    Remove { dir_id: u32 },
+}
+
+impl Command {
+    fn upcall(&self) -> usize {
+        match self {
+            Command::Open { .. } => upcall::OPEN_DONE,
+            Command::Read { .. } => upcall::READ_DONE,
+            Command::Write { .. } => upcall::WRITE_DONE,
+            Command::List { .. } => upcall::LIST_DONE,
+            Command::Remove { .. } => upcall::REMOVE_DONE,
+        }
+    }
+
+    fn handle(&self) -> Option<usize> {
+        match self {
+            Command::Read { handle, .. } | Command::Write { handle, .. } => Some(*handle),
+            _ => None,
+        }
+    }
+
+    fn dir_id(&self) -> u32 {
+        match self {
+            Command::Open { dir_id, .. }
+            | Command::List { dir_id, .. }
+            | Command::Remove { dir_id } => *dir_id,
+            _ => 0,
+        }
+    }
+
+    fn creates(&self) -> bool {
+        matches!(self, Command::Open { flags, .. } if flags & open_flags::CREATE != 0)
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    files: [Option<OpenFile>; MAX_OPEN_FILES],
+    pending: Option<Command>,
+}
+
+/// Progress of a scan through a directory.
+#[derive(Copy, Clone, Debug)]
+struct DirScan {
+    /// First cluster of the directory, 0 for the fixed root directory of
+    /// FAT12 and FAT16 volumes.
+    first_cluster: u32,
+    cluster: u32,
+    /// Sector within the cluster or the fixed root directory. Equal to the
+    /// sectors per cluster when the next cluster has to be looked up.
+    sector: u32,
+    /// First reusable entry seen so far.
+    free: Option<EntryLocation>,
+    /// Number of entries seen so far, for listing.
+    seen: usize,
+}
+
+impl DirScan {
+    fn new(first_cluster: u32) -> DirScan {
+        DirScan {
+            first_cluster,
+            cluster: first_cluster,
+            sector: 0,
+            free: None,
+            seen: 0,
+        }
+    }
+}
+
+/// What a newly allocated cluster is used for.
+#[derive(Copy, Clone, Debug)]
+enum Purpose {
+    /// A new application directory, whose entry goes into `slot`.
+    NewDirectory { slot: EntryLocation },
+    /// Extends the directory whose last cluster is `last`, to make room for
+    /// the entry of a new directory or file.
+    ExtendDirectory { last: u32, for_directory: bool },
+    /// Extends the file whose last cluster is `last`, 0 if it has none.
+    FileData { last: u32 },
+}
+
+#[derive(Copy, Clone, Debug)]
+enum State {
+    Idle,
+    /// Reading the boot sector, or the first sector of the partition the
+    /// master boot record points to.
+    Mount {
+        sector: u32,
+    },
+    /// Looking up the directory of a storage identifier in the root
+    /// directory.
+    FindDir(DirScan),
+    /// Looking up a file in an application directory.
+    FindFile(DirScan),
+    /// Searching the FAT for a free cluster, starting at `cluster`.
+    Allocate {
+        cluster: u32,
+        scanned: u32,
+        purpose: Purpose,
+    },
+    /// Marking a free cluster as the end of a chain.
+    Claim {
+        cluster: u32,
+        purpose: Purpose,
+    },
+    /// Zeroing the sectors of a new directory cluster.
+    ZeroCluster {
+        cluster: u32,
+        sector: u32,
+        purpose: Purpose,
+    },
+    /// Appending a claimed cluster to the end of a chain.
+    Link {
+        cluster: u32,
+        purpose: Purpose,
+    },
+    CreateEntry {
+        location: EntryLocation,
+        entry: DirEntry,
+    },
+    /// Updating the directory entry of a file, then freeing `free` and the
+    /// clusters following it if it is not 0.
+    UpdateEntry {
+        location: EntryLocation,
+        first_cluster: u32,
+        size: u32,
+        delete: bool,
+        free: u32,
+    },
+    FreeChain {
+        cluster: u32,
+    },
+    /// Copying data between a file and the application.
+    Transfer,
+    /// Writing back modified data before notifying the application.
+    Done(Result<(usize, usize), ErrorCode>),
+}
+
+/// What a step of the state machine did.
+enum Step {
+    /// The state changed, run the next step.
+    Continue,
+    /// Waiting for I/O.
+    Wait,
+    Finished(Result<(usize, usize), ErrorCode>),
+}
+
+/// I/O in progress on the storage.
+#[derive(Copy, Clone, Debug)]
+enum Io {
+    Idle,
+    Read {
+        sector: u32,
+    },
+    /// Writing back `copy` of the cached sector.
+    WriteBack {
+        copy: u32,
+    },
+}
+
+#[derive(Copy, Clone)]
+struct Operation {
+    processid: ProcessId,
+    command: Command,
+}
+
+pub struct FatFs<'a> {
+    storage: &'a dyn NonvolatileStorage<'a>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// The sector cache. It is lost if the storage refuses an operation.
+    buffer: TakeCell<'static, [u8]>,
+    cached: Cell<Option<u32>>,
+    dirty: Cell<bool>,
+    io: Cell<Io>,
+
+    volume: OptionalCell<Volume>,
+    /// Where to start looking for a free cluster.
+    next_free: Cell<u32>,
+
+    operation: OptionalCell<Operation>,
+    state: Cell<State>,
+    /// File name of the current operation in 8.3 form.
+    name: Cell<[u8; 11]>,
+    /// Directory entry of the file being opened.
+    found: Cell<Option<(EntryLocation, DirEntry)>>,
+    /// Bytes copied so far by the current read or write.
+    transferred: Cell<usize>,
+    /// Progress of a FAT entry access spanning two sectors: the cluster, the
+    /// number of bytes accessed and their previous contents.
+    fat_progress: Cell<Option<(u32, u32, u32)>>,
+}
+
+impl<'a> FatFs<'a> {
+    pub fn new(
+        storage: &'a dyn NonvolatileStorage<'a>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        buffer: &'static mut [u8; BUF_LEN],
+    ) -> FatFs<'a> {
+        FatFs {
+            storage,
+            apps: grant,
+            buffer: TakeCell::new(buffer),
+            cached: Cell::new(None),
+            dirty: Cell::new(false),
+            io: Cell::new(Io::Idle),
+            volume: OptionalCell::empty(),
+            next_free: Cell::new(2),
+            operation: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            name: Cell::new([b' '; 11]),
+            found: Cell::new(None),
+            transferred: Cell::new(0),
+            fat_progress: Cell::new(None),
+        }
+    }
+
+    /// Byte address of `sector`, if the storage interface can express it.
+    fn address(sector: u32) -> Result<usize, ErrorCode> {
+        (sector as usize)
+            .checked_mul(SECTOR_SIZE)
+            .filter(|address| address.checked_add(SECTOR_SIZE).is_some())
+            .ok_or(ErrorCode::FAIL)
+    }
+
+    /// Forget the volume after an I/O error. It is mounted again by the next
+    /// operation.
+    fn unmount(&self) {
+        self.volume.clear();
+        self.cached.set(None);
+        self.dirty.set(false);
+        self.fat_progress.set(None);
+        self.io.set(Io::Idle);
+    }
+
+    /// Make `sector` the cached sector. If `read` is false the sector is
+    /// about to be overwritten and is zeroed instead of being read.
+    ///
+    /// Returns whether the sector is available, or false if I/O was started.
+    fn load(&self, sector: u32, read: bool) -> Result<bool, ErrorCode> {
+        if self.cached.get() == Some(sector) {
+            return Ok(true);
+        }
+        if self.dirty.get() {
+            self.write_back(0)?;
+            return Ok(false);
+        }
+        if !read {
+            Self::address(sector)?;
+            self.buffer
+                .map(|buffer| buffer.fill(0))
+                .ok_or(ErrorCode::RESERVE)?;
+            self.cached.set(Some(sector));
+            return Ok(true);
+        }
+
+        let address = Self::address(sector)?;
+        let buffer = self.buffer.take().ok_or(ErrorCode::RESERVE)?;
+        self.cached.set(None);
+        match self.storage.read(buffer, address, SECTOR_SIZE) {
+            Ok(()) => {
+                self.io.set(Io::Read { sector });
+                Ok(false)
+            }
+            Err(_) => {
+                // The storage does not return the buffer on error.
+                self.unmount();
+                Err(ErrorCode::FAIL)
+            }
+        }
+    }
+
+    /// Write the cached sector to its `copy`. Only sectors of the FAT have
+    /// more than one copy.
+    fn write_back(&self, copy: u32) -> Result<(), ErrorCode> {
+        let sector = self.cached.get().ok_or(ErrorCode::FAIL)?;
+        let sector = self.volume.map_or(sector, |volume| {
+            if volume.is_fat_sector(sector) {
+                sector + copy * volume.fat_sectors
+            } else {
+                sector
+            }
+        });
+        let address = Self::address(sector)?;
+        let buffer = self.buffer.take().ok_or(ErrorCode::RESERVE)?;
+        match self.storage.write(buffer, address, SECTOR_SIZE) {
+            Ok(()) => {
+                self.io.set(Io::WriteBack { copy });
+                Ok(())
+            }
+            Err(_) => {
+                // The storage does not return the buffer on error.
+                self.unmount();
+                Err(ErrorCode::FAIL)
+            }
+        }
+    }
+
+    fn copies(&self, sector: u32) -> u32 {
+        self.volume.map_or(1, |volume| {
+            if volume.is_fat_sector(sector) {
+                volume.num_fats
+            } else {
+                1
+            }
+        })
+    }
+
+    fn with_sector<R>(&self, f: impl FnOnce(&mut [u8]) -> R) -> Result<R, ErrorCode> {
+        self.buffer
+            .map(|buffer| f(buffer))
+            .ok_or(ErrorCode::RESERVE)
+    }
+
+    /// Read the FAT entry of `cluster` and, if `new` is set, replace it.
+    ///
+    /// Returns the previous value of the entry, or `None` if I/O was started.
+    /// FAT12 entries may span two sectors, so the entry is accessed one byte
+    /// at a time and the progress kept in `fat_progress`.
+    fn fat_access(
+        &self,
+        volume: &Volume,
+        cluster: u32,
+        new: Option<u32>,
+    ) -> Result<Option<u32>, ErrorCode> {
+        let entry = volume.fat_entry(cluster);
+        let (mut done, mut raw) = match self.fat_progress.get() {
+            Some((c, done, raw)) if c == cluster => (done, raw),
+            _ => (0, 0),
+        };
+        while done < entry.len {
+            let offset = entry.offset + done;
+            let sector = volume.fat_start + offset / SECTOR_SIZE as u32;
+            if !self.load(sector, true)? {
+                self.fat_progress.set(Some((cluster, done, raw)));
+                return Ok(None);
+            }
+            self.with_sector(|buffer| {
+                let byte = &mut buffer[offset as usize % SECTOR_SIZE];
+                raw |= (*byte as u32) << (8 * done);
+                if let Some(value) = new {
+                    let mask = (entry.mask >> (8 * done)) as u8;
+                    let bits = (((value << entry.shift) & entry.mask) >> (8 * done)) as u8;
+                    *byte = (*byte & !mask) | bits;
+                }
+            })?;
+            if new.is_some() {
+                self.dirty.set(true);
+            }
+            done += 1;
+        }
+        self.fat_progress.set(None);
+        Ok(Some((raw & entry.mask) >> entry.shift))
+    }
+
+    fn own_file(&self, processid: ProcessId, handle: usize) -> Option<OpenFile> {
+        self.apps
+            .enter(processid, |app, _| app.files.get(handle).copied().flatten())
+            .ok()
+            .flatten()
+    }
+
+    fn store_file(&self, processid: ProcessId, handle: usize, file: OpenFile) {
+        let _ = self.apps.enter(processid, |app, _| {
+            if let Some(slot) = app.files.get_mut(handle) {
+                *slot = Some(file);
+            }
+        });
+    }
+
+    /// Whether the file at `location` is open, by any application, for
+    /// writing if `writers_only` is set.
+    fn is_open(&self, location: EntryLocation, writers_only: bool) -> bool {
+        self.apps.iter().any(|app| {
+            app.enter(|app, _| {
+                app.files
+                    .iter()
+                    .flatten()
+                    .any(|file| file.location == location && (file.writable || !writers_only))
+            })
+        })
+    }
+
+    /// The state after the volume has been mounted.
+    fn first_state(&self, command: Command) -> State {
+        match command {
+            Command::Read { .. } | Command::Write { .. } => State::Transfer,
+            _ => {
+                let root = self.volume.map_or(0, |volume| volume.root_cluster);
+                State::FindDir(DirScan::new(root))
+            }
+        }
+    }
+
+    /// The state once an entry for a new directory or file can be written to
+    /// `slot`.
+    fn slot_found(&self, slot: EntryLocation, for_directory: bool) -> State {
+        if for_directory {
+            State::Allocate {
+                cluster: self.next_free.get(),
+                scanned: 0,
+                purpose: Purpose::NewDirectory { slot },
+            }
+        } else {
+            State::CreateEntry {
+                location: slot,
+                entry: DirEntry::file(self.name.get()),
+            }
+        }
+    }
+
+    /// The state once a directory lacks a free entry. `last` is its last
+    /// cluster, 0 for the fixed root directory.
+    fn directory_full(&self, last: u32, for_directory: bool) -> State {
+        if last == 0 {
+            State::Done(Err(ErrorCode::NOMEM))
+        } else {
+            State::Allocate {
+                cluster: self.next_free.get(),
+                scanned: 0,
+                purpose: Purpose::ExtendDirectory {
+                    last,
+                    for_directory,
+                },
+            }
+        }
+    }
+
+    /// The state once the directory entries an operation changes are
+    /// updated.
+    fn finish(&self, operation: Operation) -> State {
+        match operation.command {
+            Command::Open { flags, .. } => {
+                let Some((location, entry)) = self.found.get() else {
+                    return State::Done(Err(ErrorCode::FAIL));
+                };
+                let file = OpenFile {
+                    location,
+                    first_cluster: entry.first_cluster,
+                    size: entry.size,
+                    position: 0,
+                    writable: flags & open_flags::WRITE != 0,
+                    cluster: 0,
+                    cluster_index: 0,
+                };
+                let handle = self
+                    .apps
+                    .enter(operation.processid, |app, _| {
+                        let handle = app.files.iter().position(|file| file.is_none())?;
+                        app.files[handle] = Some(file);
+                        Some(handle)
+                    })
+                    .ok()
+                    .flatten();
+                State::Done(handle.map(|handle| (handle, 0)).ok_or(ErrorCode::NOMEM))
+            }
+            Command::Write { .. } => State::Done(Ok((self.transferred.get(), 0))),
+            _ => State::Done(Ok((0, 0))),
+        }
+    }
+
+    /// Scan the current sector of `scan`, or look up the next cluster of
+    /// the directory. Returns the first entry for which `matches` is true.
+    fn scan_sector(
+        &self,
+        volume: &Volume,
+        scan: &mut DirScan,
+        mut matches: impl FnMut(&DirEntry, usize) -> bool,
+    ) -> Result<ScanResult, ErrorCode> {
+        if scan.first_cluster != 0 && scan.sector == volume.sectors_per_cluster {
+            let Some(value) = self.fat_access(volume, scan.cluster, None)? else {
+                return Ok(ScanResult::Wait);
+            };
+            return Ok(match volume.next_cluster(value) {
+                Some(next) => {
+                    scan.cluster = next;
+                    scan.sector = 0;
+                    ScanResult::Continue
+                }
+                None => ScanResult::End,
+            });
+        }
+
+        let sector = if scan.first_cluster == 0 {
+            volume.root_dir_start + scan.sector
+        } else {
+            volume.cluster_sector(scan.cluster) + scan.sector
+        };
+        if !self.load(sector, true)? {
+            return Ok(ScanResult::Wait);
+        }
+        self.with_sector(|buffer| {
+            for index in 0..ENTRIES_PER_SECTOR {
+                let location = EntryLocation { sector, index };
+                match DirEntry::parse(&buffer[index * DIR_ENTRY_LEN..][..DIR_ENTRY_LEN]) {
+                    RawEntry::End => {
+                        scan.free.get_or_insert(location);
+                        return ScanResult::End;
+                    }
+                    RawEntry::Free => {
+                        scan.free.get_or_insert(location);
+                    }
+                    RawEntry::Skip => {}
+                    RawEntry::Entry(entry) => {
+                        if matches(&entry, scan.seen) {
+                            return ScanResult::Found(location, entry);
+                        }
+                        scan.seen += 1;
+                    }
+                }
+            }
+            scan.sector += 1;
+            if scan.first_cluster == 0 && scan.sector == volume.root_dir_sectors {
+                ScanResult::End
+            } else {
+                ScanResult::Continue
+            }
+        })
+    }
+
+    /// Run one step of the current operation.
+    fn step(&self, operation: Operation) -> Result<Step, ErrorCode> {
+        let state = self.state.get();
+        if let State::Done(result) = state {
+            if self.volume.is_some() && self.dirty.get() {
+                self.write_back(0)?;
+                return Ok(Step::Wait);
+            }
+            return Ok(Step::Finished(result));
+        }
+        if let State::Mount { sector } = state {
+            return self.mount(operation, sector);
+        }
+        let Some(volume) = self.volume.get() else {
+            self.state.set(State::Mount { sector: 0 });
+            return Ok(Step::Continue);
+        };
+
+        let next = match state {
+            State::Idle | State::Mount { .. } | State::Done(_) => return Ok(Step::Wait),
+
+            State::FindDir(mut scan) => {
+                let name = layout::directory_name(operation.command.dir_id());
+                match self.scan_sector(&volume, &mut scan, |entry, _| {
+                    entry.is_directory() && entry.name == name
+                })? {
+                    ScanResult::Wait => return Ok(Step::Wait),
+                    ScanResult::Continue => State::FindDir(scan),
+                    ScanResult::Found(_, entry) => match volume.next_cluster(entry.first_cluster) {
+                        Some(cluster) => State::FindFile(DirScan::new(cluster)),
+                        None => State::Done(Err(ErrorCode::FAIL)),
+                    },
+                    ScanResult::End if operation.command.creates() => match scan.free {
+                        Some(slot) => self.slot_found(slot, true),
+                        None if scan.first_cluster == 0 => self.directory_full(0, true),
+                        None => self.directory_full(scan.cluster, true),
+                    },
+                    ScanResult::End => State::Done(Err(ErrorCode::NOSUPPORT)),
+                }
+            }
+
+            State::FindFile(mut scan) => {
+                let name = self.name.get();
+                let command = operation.command;
+                let result = self.scan_sector(&volume, &mut scan, |entry, seen| match command {
+                    Command::List { index, .. } => seen == index,
+                    _ => entry.name == name,
+                })?;
+                match (result, command) {
+                    (ScanResult::Wait, _) => return Ok(Step::Wait),
+                    (ScanResult::Continue, _) => State::FindFile(scan),
+
+                    (ScanResult::Found(_, entry), Command::List { .. }) => {
+                        let mut name = [0; 12];
+                        let len = layout::format_short_name(&entry.name, &mut name);
+                        let copied = self
+                            .apps
+                            .enter(operation.processid, |_, kernel_data| {
+                                kernel_data
+                                    .get_readwrite_processbuffer(rw_allow::READ)
+                                    .and_then(|read| {
+                                        read.mut_enter(|app_buffer| {
+                                            app_buffer
+                                                .get(0..len)
+                                                .map(|dest| dest.copy_from_slice(&name[0..len]))
+                                                .is_some()
+                                        })
+                                    })
+                                    .unwrap_or(false)
+                            })
+                            .unwrap_or(false);
+                        if copied {
+                            State::Done(Ok((len, entry.size as usize)))
+                        } else {
+                            State::Done(Err(ErrorCode::SIZE))
+                        }
+                    }
+                    (
+                        ScanResult::Found(_, entry),
+                        Command::Open { .. } | Command::Remove { .. },
+                    ) if entry.is_directory() => State::Done(Err(ErrorCode::INVAL)),
+                    (ScanResult::Found(location, entry), Command::Open { flags, .. }) => {
+                        let writing = flags & open_flags::WRITE != 0;
+                        if self.is_open(location, !writing) {
+                            State::Done(Err(ErrorCode::BUSY))
+                        } else if flags & open_flags::TRUNCATE != 0 && entry.size != 0 {
+                            self.found.set(Some((
+                                location,
+                                DirEntry {
+                                    first_cluster: 0,
+                                    size: 0,
+                                    ..entry
+                                },
+                            )));
+                            State::UpdateEntry {
+                                location,
+                                first_cluster: 0,
+                                size: 0,
+                                delete: false,
+                                free: entry.first_cluster,
+                            }
+                        } else {
+                            self.found.set(Some((location, entry)));
+                            self.finish(operation)
+                        }
+                    }
+                    (ScanResult::Found(location, entry), Command::Remove { .. }) => {
+                        if self.is_open(location, false) {
+                            State::Done(Err(ErrorCode::BUSY))
+                        } else {
+                            State::UpdateEntry {
+                                location,
+                                first_cluster: 0,
+                                size: 0,
+                                delete: true,
+                                free: entry.first_cluster,
+                            }
+                        }
+                    }
+                    (ScanResult::End, Command::Open { .. }) if command.creates() => {
+                        match scan.free {
+                            Some(slot) => self.slot_found(slot, false),
+                            None => self.directory_full(scan.cluster, false),
+                        }
+                    }
+                    _ => State::Done(Err(ErrorCode::NOSUPPORT)),
+                }
+            }
+
+            State::Allocate {
+                cluster,
+                scanned,
+                purpose,
+            } => {
+                if scanned >= volume.cluster_count {
+                    State::Done(Err(ErrorCode::NOMEM))
+                } else {
+                    let Some(value) = self.fat_access(&volume, cluster, None)? else {
+                        return Ok(Step::Wait);
+                    };
+                    // Clusters whose sectors cannot be addressed are skipped.
+                    let last_sector =
+                        volume.cluster_sector(cluster) + volume.sectors_per_cluster - 1;
+                    if value == 0 && Self::address(last_sector).is_ok() {
+                        State::Claim { cluster, purpose }
+                    } else {
+                        let next = if cluster + 1 >= volume.cluster_count + 2 {
+                            2
+                        } else {
+                            cluster + 1
+                        };
+                        State::Allocate {
+                            cluster: next,
+                            scanned: scanned + 1,
+                            purpose,
+                        }
+                    }
+                }
+            }
+
+            State::Claim { cluster, purpose } => {
+                if self
+                    .fat_access(&volume, cluster, Some(volume.end_of_chain()))?
+                    .is_none()
+                {
+                    return Ok(Step::Wait);
+                }
+                self.next_free.set(cluster);
+                match purpose {
+                    Purpose::FileData { .. } => State::Link { cluster, purpose },
+                    _ => State::ZeroCluster {
+                        cluster,
+                        sector: 0,
+                        purpose,
+                    },
+                }
+            }
+
+            State::ZeroCluster {
+                cluster,
+                sector,
+                purpose,
+            } => {
+                if !self.load(volume.cluster_sector(cluster) + sector, false)? {
+                    return Ok(Step::Wait);
+                }
+                if sector == 0 {
+                    if let Purpose::NewDirectory { .. } = purpose {
+                        self.with_sector(|buffer| layout::write_dot_entries(buffer, cluster, 0))?;
+                    }
+                }
+                self.dirty.set(true);
+                if sector + 1 < volume.sectors_per_cluster {
+                    State::ZeroCluster {
+                        cluster,
+                        sector: sector + 1,
+                        purpose,
+                    }
+                } else {
+                    match purpose {
+                        Purpose::NewDirectory { slot } => State::CreateEntry {
+                            location: slot,
+                            entry: DirEntry::directory(
+                                layout::directory_name(operation.command.dir_id()),
+                                cluster,
+                            ),
+                        },
+                        _ => State::Link { cluster, purpose },
+                    }
+                }
+            }
+
+            State::Link { cluster, purpose } => {
+                let last = match purpose {
+                    Purpose::ExtendDirectory { last, .. } | Purpose::FileData { last } => last,
+                    Purpose::NewDirectory { .. } => 0,
+                };
+                if last != 0 && self.fat_access(&volume, last, Some(cluster))?.is_none() {
+                    return Ok(Step::Wait);
+                }
+                match purpose {
+                    Purpose::ExtendDirectory { for_directory, .. } => {
+                        let slot = EntryLocation {
+                            sector: volume.cluster_sector(cluster),
+                            index: 0,
+                        };
+                        self.slot_found(slot, for_directory)
+                    }
+                    _ => {
+                        let handle = operation.command.handle().unwrap_or(0);
+                        if let Some(mut file) = self.own_file(operation.processid, handle) {
+                            if file.first_cluster == 0 {
+                                file.first_cluster = cluster;
+                                file.cluster_index = 0;
+                            } else {
+                                file.cluster_index += 1;
+                            }
+                            file.cluster = cluster;
+                            self.store_file(operation.processid, handle, file);
+                        }
+                        State::Transfer
+                    }
+                }
+            }
+
+            State::CreateEntry { location, entry } => {
+                if !self.load(location.sector, true)? {
+                    return Ok(Step::Wait);
+                }
+                self.with_sector(|buffer| {
+                    entry.write(&mut buffer[location.index * DIR_ENTRY_LEN..][..DIR_ENTRY_LEN])
+                })?;
+                self.dirty.set(true);
+                if entry.is_directory() {
+                    State::FindFile(DirScan::new(entry.first_cluster))
+                } else {
+                    self.found.set(Some((location, entry)));
+                    self.finish(operation)
+                }
+            }
+
+            State::UpdateEntry {
+                location,
+                first_cluster,
+                size,
+                delete,
+                free,
+            } => {
+                if !self.load(location.sector, true)? {
+                    return Ok(Step::Wait);
+                }
+                self.with_sector(|buffer| {
+                    let raw = &mut buffer[location.index * DIR_ENTRY_LEN..][..DIR_ENTRY_LEN];
+                    if delete {
+                        layout::set_deleted(raw);
+                    } else {
+                        layout::set_location(raw, first_cluster, size);
+                    }
+                })?;
+                self.dirty.set(true);
+                match volume.next_cluster(free) {
+                    Some(cluster) => State::FreeChain { cluster },
+                    None => self.finish(operation),
+                }
+            }
+
+            State::FreeChain { cluster } => {
+                let Some(value) = self.fat_access(&volume, cluster, Some(0))? else {
+                    return Ok(Step::Wait);
+                };
+                self.next_free.set(cmp::min(self.next_free.get(), cluster));
+                match volume.next_cluster(value) {
+                    Some(cluster) => State::FreeChain { cluster },
+                    None => self.finish(operation),
+                }
+            }
+
+            State::Transfer => match self.transfer(&volume, operation)? {
+                Some(next) => next,
+                None => return Ok(Step::Wait),
+            },
+        };
+        self.state.set(next);
+        Ok(Step::Continue)
+    }
+
+    fn mount(&self, operation: Operation, sector: u32) -> Result<Step, ErrorCode> {
+        if !self.load(sector, true)? {
+            return Ok(Step::Wait);
+        }
+        let volume = self.with_sector(|buffer| match Volume::parse(buffer, sector) {
+            Some(volume) => Ok(volume),
+            None if sector == 0 => Err(layout::mbr_partition_start(buffer)),
+            None => Err(None),
+        })?;
+        let next = match volume {
+            Ok(volume) if Self::address(volume.data_start).is_ok() => {
+                self.volume.set(volume);
+                self.next_free.set(2);
+                self.first_state(operation.command)
+            }
+            Err(Some(start)) => State::Mount { sector: start },
+            _ => State::Done(Err(ErrorCode::FAIL)),
+        };
+        self.state.set(next);
+        Ok(Step::Continue)
+    }
+
+    /// Copy the next part of a read or write, which at most extends to the
+    /// end of the current sector.
+    ///
+    /// Returns the next state, or `None` if I/O was started.
+    fn transfer(&self, volume: &Volume, operation: Operation) -> Result<Option<State>, ErrorCode> {
+        let (handle, len, writing) = match operation.command {
+            Command::Read { handle, len } => (handle, len, false),
+            Command::Write { handle, len } => (handle, len, true),
+            _ => return Ok(Some(State::Done(Err(ErrorCode::FAIL)))),
+        };
+        let Some(mut file) = self.own_file(operation.processid, handle) else {
+            return Ok(Some(State::Done(Err(ErrorCode::FAIL))));
+        };
+        let done = self.transferred.get();
+        let mut remaining = len - done;
+        if !writing {
+            remaining = cmp::min(remaining, (file.size - file.position) as usize);
+        }
+        if remaining == 0 {
+            return Ok(Some(if writing {
+                State::UpdateEntry {
+                    location: file.location,
+                    first_cluster: file.first_cluster,
+                    size: file.size,
+                    delete: false,
+                    free: 0,
+                }
+            } else {
+                State::Done(Ok((done, 0)))
+            }));
+        }
+
+        // Find the cluster holding the current position.
+        let index = file.position / volume.cluster_bytes();
+        if file.first_cluster == 0 {
+            return Ok(Some(if writing {
+                State::Allocate {
+                    cluster: self.next_free.get(),
+                    scanned: 0,
+                    purpose: Purpose::FileData { last: 0 },
+                }
+            } else {
+                State::Done(Err(ErrorCode::FAIL))
+            }));
+        }
+        if file.cluster == 0 || file.cluster_index > index {
+            file.cluster = file.first_cluster;
+            file.cluster_index = 0;
+        }
+        while file.cluster_index < index {
+            let Some(value) = self.fat_access(volume, file.cluster, None)? else {
+                self.store_file(operation.processid, handle, file);
+                return Ok(None);
+            };
+            match volume.next_cluster(value) {
+                Some(next) => {
+                    file.cluster = next;
+                    file.cluster_index += 1;
+                }
+                None if writing => {
+                    self.store_file(operation.processid, handle, file);
+                    return Ok(Some(State::Allocate {
+                        cluster: self.next_free.get(),
+                        scanned: 0,
+                        purpose: Purpose::FileData { last: file.cluster },
+                    }));
+                }
+                None => return Ok(Some(State::Done(Err(ErrorCode::FAIL)))),
+            }
+        }
+
+        let offset = (file.position % volume.cluster_bytes()) as usize;
+        let sector = volume.cluster_sector(file.cluster) + (offset / SECTOR_SIZE) as u32;
+        let start = offset % SECTOR_SIZE;
+        let chunk = cmp::min(remaining, SECTOR_SIZE - start);
+        // Sectors which are completely overwritten need not be read.
+        let whole_sector = start == 0 && chunk == SECTOR_SIZE;
+        if !self.load(sector, !(writing && whole_sector))? {
+            self.store_file(operation.processid, handle, file);
+            return Ok(None);
+        }
+
+        let copied = self
+            .apps
+            .enter(operation.processid, |_, kernel_data| {
+                self.buffer.map(|buffer| {
+                    let sector_data = &mut buffer[start..start + chunk];
+                    if writing {
+                        kernel_data
+                            .get_readonly_processbuffer(ro_allow::WRITE)
+                            .and_then(|write| {
+                                write.enter(|app_buffer| {
+                                    app_buffer
+                                        .get(done..done + chunk)
+                                        .map(|src| src.copy_to_slice(sector_data))
+                                        .is_some()
+                                })
+                            })
+                            .unwrap_or(false)
+                    } else {
+                        kernel_data
+                            .get_readwrite_processbuffer(rw_allow::READ)
+                            .and_then(|read| {
+                                read.mut_enter(|app_buffer| {
+                                    app_buffer
+                                        .get(done..done + chunk)
+                                        .map(|dest| dest.copy_from_slice(sector_data))
+                                        .is_some()
+                                })
+                            })
+                            .unwrap_or(false)
+                    }
+                })
+            })
+            .ok()
+            .flatten()
+            .unwrap_or(false);
+        if !copied {
+            // The buffer was unallowed or shrunk, end the transfer here.
+            return Ok(Some(if writing {
+                State::UpdateEntry {
+                    location: file.location,
+                    first_cluster: file.first_cluster,
+                    size: file.size,
+                    delete: false,
+                    free: 0,
+                }
+            } else {
+                State::Done(Ok((done, 0)))
+            }));
+        }
+        if writing {
+            self.dirty.set(true);
+        }
+
+        file.position += chunk as u32;
+        file.size = cmp::max(file.size, file.position);
+        self.store_file(operation.processid, handle, file);
+        self.transferred.set(done + chunk);
+        Ok(Some(State::Transfer))
+    }
+
+    /// Run the current operation until it waits for I/O or is finished.
+    fn run(&self) {
+        let Some(operation) = self.operation.get() else {
+            return;
+        };
+        loop {
+            match self.step(operation) {
+                Ok(Step::Continue) => {}
+                Ok(Step::Wait) => return,
+                Ok(Step::Finished(result)) => {
+                    self.complete(operation, result);
+                    return;
+                }
+                Err(e) => match self.state.get() {
+                    // Writing back failed, report the result anyway.
+                    State::Done(_) => {
+                        self.unmount();
+                    }
+                    _ => self.state.set(State::Done(Err(e))),
+                },
+            }
+        }
+    }
+
+    fn complete(&self, operation: Operation, result: Result<(usize, usize), ErrorCode>) {
+        self.operation.clear();
+        self.state.set(State::Idle);
+        let _ = self.apps.enter(operation.processid, |app, kernel_data| {
+            app.pending = None;
+            let (data0, data1) = result.unwrap_or((0, 0));
+            let _ = kernel_data.schedule_upcall(
+                operation.command.upcall(),
+                (into_statuscode(result.map(|_| ())), data0, data1),
+            );
+        });
+        self.check_queue();
+    }
+
+    /// Check an operation and gather what it needs from the application.
+    fn prepare(
+        &self,
+        processid: ProcessId,
+        command: Command,
+        app: &App,
+        kernel_data: &GrantKernelData,
+    ) -> Result<Command, ErrorCode> {
+        match command {
+            Command::Open { dir_id, flags } => {
+                if app.files.iter().all(|file| file.is_some()) {
+                    return Err(ErrorCode::NOMEM);
+                }
+                let writing = flags & open_flags::WRITE != 0;
+                let dir_id = self.check_directory(processid, dir_id, writing)?;
+                self.read_name(kernel_data)?;
+                Ok(Command::Open { dir_id, flags })
+            }
+            Command::List { dir_id, index } => {
+                let dir_id = self.check_directory(processid, dir_id, false)?;
+                Ok(Command::List { dir_id, index })
+            }
+            Command::Remove { dir_id } => {
+                let dir_id = self.check_directory(processid, dir_id, true)?;
+                self.read_name(kernel_data)?;
+                Ok(Command::Remove { dir_id })
+            }
+            Command::Read { handle, len } => {
+                let available = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::READ)
+                    .map_or(0, |read| read.len());
+                if available == 0 {
+                    return Err(ErrorCode::RESERVE);
+                }
+                Ok(Command::Read {
+                    handle,
+                    len: cmp::min(len, available),
+                })
+            }
+            Command::Write { handle, len } => {
+                let available = kernel_data
+                    .get_readonly_processbuffer(ro_allow::WRITE)
+                    .map_or(0, |write| write.len());
+                if available == 0 {
+                    return Err(ErrorCode::RESERVE);
+                }
+                let file = app
+                    .files
+                    .get(handle)
+                    .copied()
+                    .flatten()
+                    .ok_or(ErrorCode::INVAL)?;
+                // Files cannot grow beyond 4 GiB.
+                let len = cmp::min(len, available);
+                let max = (u32::MAX - file.position) as usize;
+                Ok(Command::Write {
+                    handle,
+                    len: cmp::min(len, max),
+                })
+            }
+        }
+    }
+
+    /// Resolve the storage identifier of a directory and check that the
+    /// process may read or, if `modify` is set, change its files.
+    fn check_directory(
+        &self,
+        processid: ProcessId,
+        dir_id: u32,
+        modify: bool,
+    ) -> Result<u32, ErrorCode> {
+        let permissions = processid
+            .get_storage_permissions()
+            .ok_or(ErrorCode::NOSUPPORT)?;
+        let dir_id = match dir_id {
+            0 => permissions.get_write_id().ok_or(ErrorCode::NOSUPPORT)?,
+            id => id,
+        };
+        let allowed = if modify {
+            permissions.check_modify_permission(dir_id)
+        } else {
+            permissions.check_read_permission(dir_id)
+        };
+        if allowed {
+            Ok(dir_id)
+        } else {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+
+    fn read_name(&self, kernel_data: &GrantKernelData) -> Result<(), ErrorCode> {
+        let name = kernel_data
+            .get_readonly_processbuffer(ro_allow::NAME)
+            .and_then(|name| {
+                name.enter(|app_name| {
+                    // Longer names are invalid anyway.
+                    let mut buffer = [0; 13];
+                    let len = cmp::min(app_name.len(), buffer.len());
+                    app_name[0..len].copy_to_slice(&mut buffer[0..len]);
+                    // Names may be NUL terminated.
+                    let len = buffer[0..len].iter().position(|c| *c == 0).unwrap_or(len);
+                    layout::short_name(&buffer[0..len])
+                })
+            })
+            .ok()
+            .flatten()
+            .ok_or(ErrorCode::INVAL)?;
+        self.name.set(name);
+        Ok(())
+    }
+
+    fn enqueue(&self, processid: ProcessId, command: Command) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| {
+                if app.pending.is_some() {
+                    return Err(ErrorCode::BUSY);
+                }
+                if let Some(handle) = command.handle() {
+                    let file = app
+                        .files
+                        .get(handle)
+                        .copied()
+                        .flatten()
+                        .ok_or(ErrorCode::INVAL)?;
+                    if matches!(command, Command::Write { .. }) && !file.writable {
+                        return Err(ErrorCode::NOSUPPORT);
+                    }
+                }
+                app.pending = Some(command);
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+
+        self.check_queue();
+        Ok(())
+    }
+
+    fn check_queue(&self) {
+        if self.operation.is_some() {
+            return;
+        }
+
+        for app in self.apps.iter() {
+            let processid = app.processid();
+            let started = app.enter(|app, kernel_data| {
+                let command = app.pending?;
+                match self.prepare(processid, command, app, kernel_data) {
+                    Ok(command) => Some(command),
+                    Err(e) => {
+                        app.pending = None;
+                        let _ = kernel_data
+                            .schedule_upcall(command.upcall(), (into_statuscode(Err(e)), 0, 0));
+                        None
+                    }
+                }
+            });
+            if let Some(command) = started {
+                self.operation.set(Operation { processid, command });
+                self.found.set(None);
+                self.transferred.set(0);
+                self.fat_progress.set(None);
+                self.state.set(match self.volume.get() {
+                    Some(_) => self.first_state(command),
+                    None => State::Mount { sector: 0 },
+                });
+                self.run();
+                return;
+            }
+        }
+    }
+}
+
+/// Result of scanning a directory sector.
+#[derive(Copy, Clone)]
+enum ScanResult {
+    Found(EntryLocation, DirEntry),
+    /// Moved on to the next sector.
+    Continue,
+    /// There are no further entries.
+    End,
+    Wait,
+}
+
+impl NonvolatileStorageClient for FatFs<'_> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        let Io::Read { sector } = self.io.replace(Io::Idle) else {
+            return;
+        };
+        if length == SECTOR_SIZE {
+            self.cached.set(Some(sector));
+        } else {
+            self.unmount();
+            self.state.set(State::Done(Err(ErrorCode::FAIL)));
+        }
+        self.run();
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        let Io::WriteBack { copy } = self.io.replace(Io::Idle) else {
+            return;
+        };
+        if length != SECTOR_SIZE {
+            self.unmount();
+            if !matches!(self.state.get(), State::Done(_)) {
+                self.state.set(State::Done(Err(ErrorCode::FAIL)));
+            }
+        } else if let Some(sector) = self.cached.get() {
+            if copy + 1 < self.copies(sector) {
+                if self.write_back(copy + 1).is_ok() {
+                    return;
+                }
+                self.state.set(State::Done(Err(ErrorCode::FAIL)));
+            } else {
+                self.dirty.set(false);
+            }
+        }
+        self.run();
+    }
+}
+
+/// Provide an interface for userland.
+impl SyscallDriver for FatFs<'_> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Open the file named in read-only allow 1 in directory `arg1`,
+    ///   with the flags in `arg2`.
+    /// - `2`: Read up to `arg2` bytes from file `arg1`.
+    /// - `3`: Write `arg2` bytes to file `arg1`.
+    /// - `4`: Move the position of file `arg1` to `arg2`.
+    /// - `5`: Close file `arg1`.
+    /// - `6`: Get the name of entry `arg2` of directory `arg1`.
+    /// - `7`: Remove the file named in read-only allow 1 from directory
+    ///   `arg1`.
+    /// - `8`: Return the size and position of file `arg1`.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let command = match command_num {
+            0 => return CommandReturn::success(),
+            1 => Command::Open {
+                dir_id: arg1 as u32,
+                flags: arg2,
+            },
+            2 => Command::Read {
+                handle: arg1,
+                len: arg2,
+            },
+            3 => Command::Write {
+                handle: arg1,
+                len: arg2,
+            },
+            6 => Command::List {
+                dir_id: arg1 as u32,
+                index: arg2,
+            },
+            7 => Command::Remove {
+                dir_id: arg1 as u32,
+            },
+            4 | 5 | 8 => {
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| {
+                        // The file may not change while it is being read or
+                        // written.
+                        if app.pending.and_then(|pending| pending.handle()) == Some(arg1)
+                            && command_num != 8
+                        {
+                            return Err(ErrorCode::BUSY);
+                        }
+                        let file = app.files.get_mut(arg1).ok_or(ErrorCode::INVAL)?;
+                        let open = file.as_mut().ok_or(ErrorCode::INVAL)?;
+                        match command_num {
+                            4 => {
+                                if arg2 > open.size as usize {
+                                    return Err(ErrorCode::INVAL);
+                                }
+                                open.position = arg2 as u32;
+                                Ok(CommandReturn::success())
+                            }
+                            5 => {
+                                *file = None;
+                                Ok(CommandReturn::success())
+                            }
+                            _ => Ok(CommandReturn::success_u32_u32(open.size, open.position)),
+                        }
+                    })
+                    .unwrap_or_else(|err| Err(err.into()));
+                return res.unwrap_or_else(CommandReturn::failure);
+            }
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+
+        if let Command::Open { flags, .. } = command {
+            let needs_write = open_flags::CREATE | open_flags::TRUNCATE;
+            if flags & needs_write != 0 && flags & open_flags::WRITE == 0 {
+                return CommandReturn::failure(ErrorCode::INVAL);
+            }
+        }
+
+        match self.enqueue(processid, command) {
+            Ok(()) => CommandReturn::success(),
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use capsules_test_harness::loader::AppFlash;
+    use capsules_test_harness::process::{App as Process, HostKernel, Tbf, Upcall};
+    use capsules_test_harness::{Simulated, deferred_call, leak, run_until_idle};
+    use core::cell::RefCell;
+    use core::num::NonZeroU32;
+    use kernel::process::ShortId;
+    use kernel::syscall::SyscallReturn;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    const APP_ID: u32 = 0x2a;
+
+    /// Sectors of the test volume: a FAT12 volume of 64 sectors with one
+    /// sector per cluster, two copies of a one sector FAT and a one sector
+    /// root directory. Cluster `n` is sector `n + 2`.
+    const FAT: usize = 1;
+    const FAT_COPY: usize = 2;
+    const ROOT_DIR: usize = 3;
+    const NUM_SECTORS: usize = 64;
+
+    fn volume() -> Vec<u8> {
+        let mut disk = std::vec![0; NUM_SECTORS * SECTOR_SIZE];
+        let boot = &mut disk[..SECTOR_SIZE];
+        boot[0] = 0xEB;
+        boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+        boot[13] = 1;
+        boot[14..16].copy_from_slice(&1u16.to_le_bytes());
+        boot[16] = 2;
+        boot[17..19].copy_from_slice(&16u16.to_le_bytes());
+        boot[19..21].copy_from_slice(&(NUM_SECTORS as u16).to_le_bytes());
+        boot[22..24].copy_from_slice(&1u16.to_le_bytes());
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+        for fat in [FAT, FAT_COPY] {
+            disk[fat * SECTOR_SIZE..][..3].copy_from_slice(&[0xF8, 0xFF, 0xFF]);
+        }
+        disk
+    }
+
+    /// Storage holding the test volume, which completes reads and writes when
+    /// stepped.
+    struct SimDisk {
+        data: RefCell<Vec<u8>>,
+        client: OptionalCell<&'static dyn NonvolatileStorageClient>,
+        pending: RefCell<Option<(bool, &'static mut [u8], usize, usize)>>,
+        /// Complete the next read without data, as after a bus error.
+        fail_read: Cell<bool>,
+        /// Refuse to start reads.
+        refuse: Cell<bool>,
+    }
+
+    impl SimDisk {
+        fn sector(&self, sector: usize) -> Vec<u8> {
+            self.data.borrow()[sector * SECTOR_SIZE..][..SECTOR_SIZE].to_vec()
+        }
+
+        fn start(
+            &self,
+            write: bool,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            if self.refuse.get() || self.pending.borrow().is_some() {
+                return Err(ErrorCode::BUSY);
+            }
+            *self.pending.borrow_mut() = Some((write, buffer, address, length));
+            Ok(())
+        }
+    }
+
+    impl NonvolatileStorage<'static> for SimDisk {
+        fn set_client(&self, client: &'static dyn NonvolatileStorageClient) {
+            self.client.set(client);
+        }
+
+        fn read(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            self.start(false, buffer, address, length)
+        }
+
+        fn write(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            self.start(true, buffer, address, length)
+        }
+    }
+
+    impl Simulated for SimDisk {
+        fn step(&self) -> bool {
+            let Some((write, buffer, address, length)) = self.pending.take() else {
+                return false;
+            };
+            let mut data = self.data.borrow_mut();
+            if write {
+                data[address..address + length].copy_from_slice(&buffer[..length]);
+                drop(data);
+                self.client.map(|client| client.write_done(buffer, length));
+            } else if self.fail_read.take() {
+                drop(data);
+                self.client.map(|client| client.read_done(buffer, 0));
+            } else {
+                buffer[..length].copy_from_slice(&data[address..address + length]);
+                drop(data);
+                self.client.map(|client| client.read_done(buffer, length));
+            }
+            true
+        }
+    }
+
+    /// Boot with the test volume and a process with the ShortId `APP_ID`,
+    /// which stores its files in the directory `0000002A`.
+    fn fat_fs() -> (&'static SimDisk, Process) {
+        let kernel = HostKernel::new();
+        let disk = leak(SimDisk {
+            data: RefCell::new(volume()),
+            client: OptionalCell::empty(),
+            pending: RefCell::new(None),
+            fail_read: Cell::new(false),
+            refuse: Cell::new(false),
+        });
+        let fat_fs = leak(FatFs::new(
+            disk,
+            kernel.create_grant(DRIVER_NUM),
+            Box::leak(Box::new([0; BUF_LEN])),
+        ));
+        disk.set_client(fat_fs);
+        kernel.add_driver(DRIVER_NUM, fat_fs);
+
+        let flash = AppFlash::new(4096);
+        flash.install(0, &Tbf::new("files").short_id(APP_ID).size(1024).build());
+        kernel.dynamic_binary_storage(flash);
+        run_until_idle(&[flash]);
+        let process = kernel.attach(ShortId::Fixed(NonZeroU32::new(APP_ID).unwrap()));
+        for upcall in 0..upcall::COUNT as usize {
+            process.subscribe(DRIVER_NUM, upcall);
+        }
+        (disk, process)
+    }
+
+    fn done(upcall: usize, result: Result<(usize, usize), ErrorCode>) -> Option<Upcall> {
+        let (data0, data1) = result.unwrap_or((0, 0));
+        Some(Upcall {
+            driver_number: DRIVER_NUM,
+            subscribe_number: upcall,
+            arguments: [into_statuscode(result.map(|_| ())), data0, data1],
+        })
+    }
+
+    /// Run `command` on the volume and return its upcall.
+    fn run(
+        disk: &SimDisk,
+        process: &Process,
+        command: usize,
+        arg1: usize,
+        arg2: usize,
+    ) -> Option<Upcall> {
+        assert!(matches!(
+            process.command(DRIVER_NUM, command, arg1, arg2),
+            SyscallReturn::Success
+        ));
+        run_until_idle(&[disk]);
+        process.yield_wait()
+    }
+
+    /// Open `name` in the directory of the process.
+    fn open(disk: &SimDisk, process: &Process, name: &[u8], flags: usize) -> Option<Upcall> {
+        let buffer = process.allocate(name.len());
+        process.write(buffer, name);
+        process.allow_readonly(DRIVER_NUM, ro_allow::NAME, buffer);
+        run(disk, process, 1, 0, flags)
+    }
+
+    fn write(disk: &SimDisk, process: &Process, handle: usize, data: &[u8]) -> Option<Upcall> {
+        let buffer = process.allocate(data.len());
+        process.write(buffer, data);
+        process.allow_readonly(DRIVER_NUM, ro_allow::WRITE, buffer);
+        run(disk, process, 3, handle, data.len())
+    }
+
+    /// Directory entry `index` of `sector`.
+    fn entry(sector: &[u8], index: usize) -> RawEntry {
+        DirEntry::parse(&sector[index * DIR_ENTRY_LEN..][..DIR_ENTRY_LEN])
+    }
+
+    /// FAT12 entry of `cluster` in the FAT `fat`.
+    fn fat_entry(fat: &[u8], cluster: usize) -> u16 {
+        let offset = cluster + cluster / 2;
+        let word = u16::from_le_bytes([fat[offset], fat[offset + 1]]);
+        if cluster & 1 == 1 {
+            word >> 4
+        } else {
+            word & 0xFFF
+        }
+    }
+
+    /// 600 bytes, which span two clusters.
+    fn contents() -> Vec<u8> {
+        (0..600).map(|i| i as u8).collect()
+    }
+
+    const WRITE_CREATE: usize = open_flags::WRITE | open_flags::CREATE;
+
+    #[test]
+    fn written_file_is_read_back() {
+        deferred_call::run(|| {
+            let (disk, process) = fat_fs();
+            let data = contents();
+
+            assert_eq!(
+                open(disk, &process, b"DATA.TXT", WRITE_CREATE),
+                done(upcall::OPEN_DONE, Ok((0, 0)))
+            );
+            assert_eq!(
+                write(disk, &process, 0, &data),
+                done(upcall::WRITE_DONE, Ok((600, 0)))
+            );
+            assert!(matches!(
+                process.command(DRIVER_NUM, 8, 0, 0),
+                SyscallReturn::SuccessU32U32(600, 600)
+            ));
+            assert!(matches!(
+                process.command(DRIVER_NUM, 5, 0, 0),
+                SyscallReturn::Success
+            ));
+
+            assert_eq!(
+                open(disk, &process, b"data.txt", 0),
+                done(upcall::OPEN_DONE, Ok((0, 0)))
+            );
+            let buffer = process.allocate(1024);
+            process.allow_readwrite(DRIVER_NUM, rw_allow::READ, buffer);
+            assert_eq!(
+                run(disk, &process, 2, 0, 1024),
+                done(upcall::READ_DONE, Ok((600, 0)))
+            );
+            assert_eq!(process.read(buffer)[..600], data[..]);
+
+            // Reading at the end of the file returns nothing.
+            assert_eq!(
+                run(disk, &process, 2, 0, 1024),
+                done(upcall::READ_DONE, Ok((0, 0)))
+            );
+        });
+    }
+
+    #[test]
+    fn writes_allocate_clusters_and_update_the_directory() {
+        deferred_call::run(|| {
+            let (disk, process) = fat_fs();
+
+            open(disk, &process, b"DATA.TXT", WRITE_CREATE);
+            write(disk, &process, 0, &contents());
+
+            // The directory of the process takes the first free cluster.
+            let RawEntry::Entry(directory) = entry(&disk.sector(ROOT_DIR), 0) else {
+                panic!("no directory entry");
+            };
+            assert!(directory.is_directory());
+            assert_eq!(&directory.name, b"0000002A   ");
+            assert_eq!(directory.first_cluster, 2);
+
+            // The file follows the `.` and `..` entries.
+            let directory_sector = disk.sector(2 + 2);
+            assert_eq!(entry(&directory_sector, 0), RawEntry::Skip);
+            assert_eq!(entry(&directory_sector, 1), RawEntry::Skip);
+            assert_eq!(
+                entry(&directory_sector, 2),
+                RawEntry::Entry(DirEntry {
+                    name: *b"DATA    TXT",
+                    attributes: 0,
+                    first_cluster: 3,
+                    size: 600,
+                })
+            );
+            assert_eq!(entry(&directory_sector, 3), RawEntry::End);
+
+            // Both copies of the FAT chain the clusters of the file.
+            let fat = disk.sector(FAT);
+            assert_eq!(fat, disk.sector(FAT_COPY));
+            assert_eq!(fat_entry(&fat, 2), 0xFFF);
+            assert_eq!(fat_entry(&fat, 3), 4);
+            assert_eq!(fat_entry(&fat, 4), 0xFFF);
+            assert_eq!(fat_entry(&fat, 5), 0);
+            assert_eq!(disk.sector(3 + 2)[..SECTOR_SIZE], contents()[..SECTOR_SIZE]);
+            assert_eq!(disk.sector(4 + 2)[..88], contents()[SECTOR_SIZE..]);
+        });
+    }
+
+    #[test]
+    fn files_of_other_applications_cannot_be_opened() {
+        deferred_call::run(|| {
+            let (disk, process) = fat_fs();
+            let name = process.allocate(8);
+            process.write(name, b"DATA.TXT");
+            process.allow_readonly(DRIVER_NUM, ro_allow::NAME, name);
+
+            assert_eq!(
+                run(disk, &process, 1, 0x99, 0),
+                done(upcall::OPEN_DONE, Err(ErrorCode::NOSUPPORT))
+            );
+            // Nothing was read from the volume.
+            assert!(!disk.step());
+        });
+    }
+
+    #[test]
+    fn failed_read_unmounts_the_volume() {
+        deferred_call::run(|| {
+            let (disk, process) = fat_fs();
+            open(disk, &process, b"DATA.TXT", WRITE_CREATE);
+            assert!(matches!(
+                process.command(DRIVER_NUM, 5, 0, 0),
+                SyscallReturn::Success
+            ));
+
+            disk.fail_read.set(true);
+            assert_eq!(
+                open(disk, &process, b"DATA.TXT", 0),
+                done(upcall::OPEN_DONE, Err(ErrorCode::FAIL))
+            );
+
+            // The volume is mounted again by the next operation.
+            assert_eq!(
+                open(disk, &process, b"DATA.TXT", 0),
+                done(upcall::OPEN_DONE, Ok((0, 0)))
+            );
+        });
+    }
+
+    #[test]
+    fn refused_read_loses_the_buffer() {
+        deferred_call::run(|| {
+            let (disk, process) = fat_fs();
+
+            disk.refuse.set(true);
+            assert_eq!(
+                open(disk, &process, b"DATA.TXT", WRITE_CREATE),
+                done(upcall::OPEN_DONE, Err(ErrorCode::FAIL))
+            );
+
+            disk.refuse.set(false);
+            assert_eq!(
+                open(disk, &process, b"DATA.TXT", WRITE_CREATE),
+                done(upcall::OPEN_DONE, Err(ErrorCode::RESERVE))
+            );
+        });
+    }
+}
@@ -0,0 +1,506 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! On-disk structures of FAT12, FAT16 and FAT32 volumes.
+//!
+//! Only 512 byte sectors and short (8.3) file names are supported. Long file
+//! name entries written by other systems are skipped, the files remain
+//! accessible through their short names.
+
+/// Size of a sector in bytes.
+pub const SECTOR_SIZE: usize = 512;
+/// Size of a directory entry in bytes.
+pub const DIR_ENTRY_LEN: usize = 32;
+/// Number of directory entries in a sector.
+pub const ENTRIES_PER_SECTOR: usize = SECTOR_SIZE / DIR_ENTRY_LEN;
+
+/// Attribute of entries which are directories.
+const ATTR_DIRECTORY: u8 = 0x10;
+/// Attribute of volume labels.
+const ATTR_VOLUME_ID: u8 = 0x08;
+/// Attribute combination marking long file name entries.
+const ATTR_LONG_NAME: u8 = 0x0F;
+/// First name byte of deleted entries.
+const DELETED: u8 = 0xE5;
+
+/// Date written to new entries, 1980-01-01. There is no notion of wall clock
+/// time here.
+const DEFAULT_DATE: u16 = (1 << 5) | 1;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// Geometry of a mounted volume. All sector numbers are absolute, i.e.
+/// relative to the start of the storage device.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Volume {
+    pub fat_type: FatType,
+    pub sectors_per_cluster: u32,
+    /// First sector of the first FAT.
+    pub fat_start: u32,
+    /// Length of each FAT in sectors.
+    pub fat_sectors: u32,
+    pub num_fats: u32,
+    /// First sector of the fixed root directory of FAT12 and FAT16 volumes.
+    pub root_dir_start: u32,
+    /// Length of the fixed root directory in sectors, 0 for FAT32.
+    pub root_dir_sectors: u32,
+    /// First cluster of the root directory of FAT32 volumes, 0 otherwise.
+    pub root_cluster: u32,
+    /// First sector of cluster 2.
+    pub data_start: u32,
+    /// Number of data clusters.
+    pub cluster_count: u32,
+}
+
+/// Location of a FAT entry, as a little endian word of `len` bytes at byte
+/// `offset` of the FAT, of which the entry occupies the bits in `mask`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FatEntry {
+    pub offset: u32,
+    pub len: u32,
+    pub shift: u32,
+    pub mask: u32,
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+fn has_signature(sector: &[u8]) -> bool {
+    sector.len() >= SECTOR_SIZE && sector[510] == 0x55 && sector[511] == 0xAA
+}
+
+/// Whether `sector` looks like the boot sector of a FAT volume.
+pub fn is_boot_sector(sector: &[u8]) -> bool {
+    has_signature(sector)
+        && (sector[0] == 0xEB || sector[0] == 0xE9)
+        && read_u16(sector, 11) as usize == SECTOR_SIZE
+}
+
+/// First sector of the first partition described by a master boot record.
+pub fn mbr_partition_start(sector: &[u8]) -> Option<u32> {
+    if !has_signature(sector) || sector[450] == 0 {
+        return None;
+    }
+    Some(read_u32(sector, 454)).filter(|start| *start != 0)
+}
+
+impl Volume {
+    /// Parse the boot sector of a volume starting at sector `start`.
+    pub fn parse(boot: &[u8], start: u32) -> Option<Volume> {
+        if !is_boot_sector(boot) {
+            return None;
+        }
+        let sectors_per_cluster = boot[13] as u32;
+        let reserved = read_u16(boot, 14) as u32;
+        let num_fats = boot[16] as u32;
+        let root_entries = read_u16(boot, 17) as u32;
+        let total_sectors = match read_u16(boot, 19) {
+            0 => read_u32(boot, 32),
+            n => n as u32,
+        };
+        let fat_sectors = match read_u16(boot, 22) {
+            0 => read_u32(boot, 36),
+            n => n as u32,
+        };
+        if !sectors_per_cluster.is_power_of_two()
+            || reserved == 0
+            || num_fats == 0
+            || fat_sectors == 0
+        {
+            return None;
+        }
+
+        let root_dir_sectors = (root_entries * DIR_ENTRY_LEN as u32).div_ceil(SECTOR_SIZE as u32);
+        let data_offset = reserved
+            .checked_add(num_fats.checked_mul(fat_sectors)?)?
+            .checked_add(root_dir_sectors)?;
+        let cluster_count = total_sectors.checked_sub(data_offset)? / sectors_per_cluster;
+
+        let fat_type = if cluster_count < 4085 {
+            FatType::Fat12
+        } else if cluster_count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+        let root_cluster = if fat_type == FatType::Fat32 {
+            let root_cluster = read_u32(boot, 44);
+            if root_entries != 0 || root_cluster < 2 || root_cluster >= cluster_count + 2 {
+                return None;
+            }
+            root_cluster
+        } else {
+            if root_entries == 0 {
+                return None;
+            }
+            0
+        };
+
+        // The FAT must be large enough to hold an entry for every cluster.
+        let fat_bytes = match fat_type {
+            FatType::Fat12 => (cluster_count + 2) * 3 / 2 + 1,
+            FatType::Fat16 => (cluster_count + 2) * 2,
+            FatType::Fat32 => (cluster_count + 2) * 4,
+        };
+        if fat_bytes > fat_sectors * SECTOR_SIZE as u32 {
+            return None;
+        }
+
+        let fat_start = start.checked_add(reserved)?;
+        Some(Volume {
+            fat_type,
+            sectors_per_cluster,
+            fat_start,
+            fat_sectors,
+            num_fats,
+            root_dir_start: fat_start + num_fats * fat_sectors,
+            root_dir_sectors,
+            root_cluster,
+            data_start: start.checked_add(data_offset)?,
+            cluster_count,
+        })
+    }
+
+    /// Size of a cluster in bytes.
+    pub fn cluster_bytes(&self) -> u32 {
+        self.sectors_per_cluster * SECTOR_SIZE as u32
+    }
+
+    /// First sector of `cluster`.
+    pub fn cluster_sector(&self, cluster: u32) -> u32 {
+        self.data_start + (cluster - 2) * self.sectors_per_cluster
+    }
+
+    /// Whether `sector` belongs to the first FAT.
+    pub fn is_fat_sector(&self, sector: u32) -> bool {
+        sector >= self.fat_start && sector < self.fat_start + self.fat_sectors
+    }
+
+    /// Location of the FAT entry of `cluster`.
+    pub fn fat_entry(&self, cluster: u32) -> FatEntry {
+        match self.fat_type {
+            FatType::Fat12 => FatEntry {
+                offset: cluster + cluster / 2,
+                len: 2,
+                shift: if cluster & 1 == 1 { 4 } else { 0 },
+                mask: if cluster & 1 == 1 { 0xFFF0 } else { 0x0FFF },
+            },
+            FatType::Fat16 => FatEntry {
+                offset: cluster * 2,
+                len: 2,
+                shift: 0,
+                mask: 0xFFFF,
+            },
+            // The upper four bits of FAT32 entries are reserved and must be
+            // preserved.
+            FatType::Fat32 => FatEntry {
+                offset: cluster * 4,
+                len: 4,
+                shift: 0,
+                mask: 0x0FFF_FFFF,
+            },
+        }
+    }
+
+    /// Value marking the last cluster of a chain.
+    pub fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    /// The cluster following a cluster whose FAT entry is `value`, or `None`
+    /// at the end of the chain. Values which do not refer to a data cluster
+    /// also end the chain.
+    pub fn next_cluster(&self, value: u32) -> Option<u32> {
+        (value >= 2 && value < self.cluster_count + 2).then_some(value)
+    }
+}
+
+/// Outcome of decoding a directory entry.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RawEntry {
+    /// This and all following entries are unused.
+    End,
+    /// A deleted entry that can be reused.
+    Free,
+    /// A long file name part, volume label or the `.` and `..` entries.
+    Skip,
+    Entry(DirEntry),
+}
+
+/// A short name directory entry.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DirEntry {
+    pub name: [u8; 11],
+    pub attributes: u8,
+    pub first_cluster: u32,
+    pub size: u32,
+}
+
+impl DirEntry {
+    pub fn file(name: [u8; 11]) -> DirEntry {
+        DirEntry {
+            name,
+            attributes: 0,
+            first_cluster: 0,
+            size: 0,
+        }
+    }
+
+    pub fn directory(name: [u8; 11], first_cluster: u32) -> DirEntry {
+        DirEntry {
+            name,
+            attributes: ATTR_DIRECTORY,
+            first_cluster,
+            size: 0,
+        }
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.attributes & ATTR_DIRECTORY != 0
+    }
+
+    /// Decode the 32 byte entry `raw`.
+    pub fn parse(raw: &[u8]) -> RawEntry {
+        let attributes = raw[11];
+        match raw[0] {
+            0 => RawEntry::End,
+            DELETED => RawEntry::Free,
+            b'.' => RawEntry::Skip,
+            _ if attributes & ATTR_LONG_NAME == ATTR_LONG_NAME => RawEntry::Skip,
+            _ if attributes & ATTR_VOLUME_ID != 0 => RawEntry::Skip,
+            first => {
+                let mut name = [0; 11];
+                name.copy_from_slice(&raw[0..11]);
+                // 0x05 stands for a name starting with 0xE5.
+                if first == 0x05 {
+                    name[0] = DELETED;
+                }
+                RawEntry::Entry(DirEntry {
+                    name,
+                    attributes,
+                    first_cluster: ((read_u16(raw, 20) as u32) << 16) | read_u16(raw, 26) as u32,
+                    size: read_u32(raw, 28),
+                })
+            }
+        }
+    }
+
+    /// Encode the entry into the 32 byte `raw`.
+    pub fn write(&self, raw: &mut [u8]) {
+        raw[0..DIR_ENTRY_LEN].fill(0);
+        raw[0..11].copy_from_slice(&self.name);
+        raw[11] = self.attributes;
+        // Creation, access and modification dates.
+        for offset in [16, 18, 24] {
+            raw[offset..offset + 2].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+        }
+        set_location(raw, self.first_cluster, self.size);
+    }
+}
+
+/// Update the first cluster and size of the encoded entry `raw`.
+pub fn set_location(raw: &mut [u8], first_cluster: u32, size: u32) {
+    raw[20..22].copy_from_slice(&((first_cluster >> 16) as u16).to_le_bytes());
+    raw[26..28].copy_from_slice(&(first_cluster as u16).to_le_bytes());
+    raw[28..32].copy_from_slice(&size.to_le_bytes());
+}
+
+/// Mark the encoded entry `raw` as deleted.
+pub fn set_deleted(raw: &mut [u8]) {
+    raw[0] = DELETED;
+}
+
+/// Write the `.` and `..` entries of a new directory into its first sector.
+pub fn write_dot_entries(sector: &mut [u8], cluster: u32, parent_cluster: u32) {
+    let mut name = [b' '; 11];
+    name[0] = b'.';
+    DirEntry::directory(name, cluster).write(&mut sector[0..DIR_ENTRY_LEN]);
+    name[1] = b'.';
+    DirEntry::directory(name, parent_cluster).write(&mut sector[DIR_ENTRY_LEN..2 * DIR_ENTRY_LEN]);
+}
+
+fn is_name_char(c: u8) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || b"!#$%&'()-@^_`{}~".contains(&c)
+}
+
+/// Convert a file name such as `log.txt` into the padded, upper case 8.3
+/// form stored in directory entries.
+pub fn short_name(name: &[u8]) -> Option<[u8; 11]> {
+    let (base, extension) = match name.iter().position(|c| *c == b'.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, &[][..]),
+    };
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    let (short_base, short_extension) = short.split_at_mut(8);
+    for (dst, src) in short_base
+        .iter_mut()
+        .zip(base)
+        .chain(short_extension.iter_mut().zip(extension))
+    {
+        let c = src.to_ascii_uppercase();
+        if !is_name_char(c) {
+            return None;
+        }
+        *dst = c;
+    }
+    Some(short)
+}
+
+/// Format a short name as `NAME.EXT` into `out`, returning the length.
+pub fn format_short_name(name: &[u8; 11], out: &mut [u8; 12]) -> usize {
+    let mut len = 0;
+    for c in name[0..8].iter().filter(|c| **c != b' ') {
+        out[len] = *c;
+        len += 1;
+    }
+    if name[8] != b' ' {
+        out[len] = b'.';
+        len += 1;
+        for c in name[8..11].iter().filter(|c| **c != b' ') {
+            out[len] = *c;
+            len += 1;
+        }
+    }
+    len
+}
+
+/// Name of the directory holding the files of storage identifier `id`: the
+/// identifier as eight upper case hexadecimal digits.
+pub fn directory_name(id: u32) -> [u8; 11] {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let mut name = [b' '; 11];
+    for (i, c) in name[0..8].iter_mut().enumerate() {
+        *c = HEX[((id >> (28 - 4 * i)) & 0xF) as usize];
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Boot sector of a 64 MiB FAT16 volume with 2 KiB clusters, as created
+    /// by `mkfs.fat -F 16 -s 4`.
+    fn fat16_boot_sector() -> [u8; SECTOR_SIZE] {
+        let mut boot = [0; SECTOR_SIZE];
+        boot[0] = 0xEB;
+        boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+        boot[13] = 4;
+        boot[14..16].copy_from_slice(&4u16.to_le_bytes());
+        boot[16] = 2;
+        boot[17..19].copy_from_slice(&512u16.to_le_bytes());
+        boot[22..24].copy_from_slice(&128u16.to_le_bytes());
+        boot[32..36].copy_from_slice(&131072u32.to_le_bytes());
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+        boot
+    }
+
+    #[test]
+    fn parses_fat16_geometry() {
+        let volume = Volume::parse(&fat16_boot_sector(), 2048).unwrap();
+        assert_eq!(volume.fat_type, FatType::Fat16);
+        assert_eq!(volume.fat_start, 2052);
+        assert_eq!(volume.root_dir_start, 2052 + 256);
+        assert_eq!(volume.root_dir_sectors, 32);
+        assert_eq!(volume.data_start, 2052 + 256 + 32);
+        assert_eq!(volume.cluster_count, (131072 - 292) / 4);
+        assert_eq!(volume.cluster_sector(3), volume.data_start + 4);
+    }
+
+    #[test]
+    fn rejects_non_fat_sectors() {
+        let mut boot = fat16_boot_sector();
+        boot[11..13].copy_from_slice(&4096u16.to_le_bytes());
+        assert!(Volume::parse(&boot, 0).is_none());
+        assert!(Volume::parse(&[0; SECTOR_SIZE], 0).is_none());
+    }
+
+    #[test]
+    fn finds_first_partition() {
+        let mut mbr = [0; SECTOR_SIZE];
+        mbr[450] = 0x0C;
+        mbr[454..458].copy_from_slice(&2048u32.to_le_bytes());
+        assert_eq!(mbr_partition_start(&mbr), None);
+        mbr[510] = 0x55;
+        mbr[511] = 0xAA;
+        assert_eq!(mbr_partition_start(&mbr), Some(2048));
+    }
+
+    #[test]
+    fn locates_fat12_entries() {
+        let mut boot = fat16_boot_sector();
+        boot[32..36].copy_from_slice(&8192u32.to_le_bytes());
+        let volume = Volume::parse(&boot, 0).unwrap();
+        assert_eq!(volume.fat_type, FatType::Fat12);
+        assert_eq!(volume.fat_entry(2).offset, 3);
+        assert_eq!(volume.fat_entry(2).mask, 0x0FFF);
+        assert_eq!(volume.fat_entry(3).offset, 4);
+        assert_eq!(volume.fat_entry(3).shift, 4);
+        assert_eq!(volume.next_cluster(0xFFF), None);
+        assert_eq!(volume.next_cluster(5), Some(5));
+    }
+
+    #[test]
+    fn converts_short_names() {
+        assert_eq!(short_name(b"log.txt"), Some(*b"LOG     TXT"));
+        assert_eq!(short_name(b"DATA0001"), Some(*b"DATA0001   "));
+        assert_eq!(short_name(b"toolongname.txt"), None);
+        assert_eq!(short_name(b"a.json"), None);
+        assert_eq!(short_name(b".txt"), None);
+        assert_eq!(short_name(b"a b.txt"), None);
+
+        let mut out = [0; 12];
+        let len = format_short_name(b"LOG     TXT", &mut out);
+        assert_eq!(&out[..len], b"LOG.TXT");
+        let len = format_short_name(b"DATA0001   ", &mut out);
+        assert_eq!(&out[..len], b"DATA0001");
+    }
+
+    #[test]
+    fn round_trips_directory_entries() {
+        let mut raw = [0xAA; DIR_ENTRY_LEN];
+        let entry = DirEntry {
+            name: *b"LOG     TXT",
+            attributes: 0,
+            first_cluster: 0x0001_0002,
+            size: 1234,
+        };
+        entry.write(&mut raw);
+        assert_eq!(DirEntry::parse(&raw), RawEntry::Entry(entry));
+
+        set_deleted(&mut raw);
+        assert_eq!(DirEntry::parse(&raw), RawEntry::Free);
+        assert_eq!(DirEntry::parse(&[0; DIR_ENTRY_LEN]), RawEntry::End);
+    }
+
+    #[test]
+    fn names_directories_by_storage_id() {
+        assert_eq!(&directory_name(0x2A), b"0000002A   ");
+        assert_eq!(&directory_name(0xDEADBEEF), b"DEADBEEF   ");
+    }
+}
@@ -0,0 +1,39 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Files on a FAT formatted storage device, such as an SD card.
+//!
+//! Volumes formatted with FAT12, FAT16 or FAT32 are supported, either
+//! starting at the beginning of the device or in the first partition of a
+//! master boot record. The volume is mounted on first use.
+//!
+//! Applications are isolated by their storage permissions: the files stored
+//! under a storage identifier live in a directory of the root directory named
+//! after the identifier in hexadecimal, e.g. `0000002A`. An application
+//! creates files in the directory of its write identifier and can open files
+//! in the directories of identifiers it has read or modify permission for.
+//! Only short (8.3) file names are supported.
+//!
+//! ```text
+//! +--------------------------------------------+
+//! | FatFs (syscall driver)                     |
+//! +--------------------------------------------+
+//! | layout (on-disk format)                    |
+//! +--------------------------------------------+
+//!     hil::nonvolatile_storage::NonvolatileStorage
+//! +--------------------------------------------+
+//! | Storage device, e.g. sdcard::SDCard        |
+//! +--------------------------------------------+
+//! ```
+//!
+//! The nonvolatile storage interface addresses bytes with a `usize`, so on
+//! 32-bit platforms only the first 4 GiB of a device can be used. Clusters
+//! beyond that are never allocated.
+
+pub mod driver;
+pub mod layout;
+
+pub use self::driver::BUF_LEN;
+pub use self::driver::DRIVER_NUM;
+pub use self::driver::FatFs;
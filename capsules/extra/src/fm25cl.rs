@@ -270,15 +270,16 @@ impl<'a, S: hil::spi::SpiMasterDevice<'a>> hil::spi::SpiMasterClient for FM25CL<
 
                 read_buffer.map(|read_buffer| {
                     self.client_buffer.take().map(move |buffer| {
-                        let read_len = buffer.len();
+                        // The first three bytes were clocked in while sending
+                        // the command and address.
+                        let read_len = cmp::min(buffer.len(), read_buffer.len() - 3);
 
-                        buffer[..(read_len - 3)]
-                            .copy_from_slice(&read_buffer[3..((read_len - 3) + 3)]);
+                        buffer[..read_len].copy_from_slice(&read_buffer[3..(read_len + 3)]);
 
                         self.rxbuffer.replace(read_buffer);
 
                         self.client
-                            .map(move |client| client.read_done(buffer, read_len - 3));
+                            .map(move |client| client.read_done(buffer, read_len));
                     });
                 });
             }
@@ -340,3 +341,108 @@ impl<'a, S: hil::spi::SpiMasterDevice<'a>> hil::nonvolatile_storage::Nonvolatile
         self.write(address as u16, buffer, length as u16)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capsules_test_harness::spi::MockSpiDevice;
+    use capsules_test_harness::{leak, run_until_idle, static_buf};
+    use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+    use kernel::hil::spi::SpiMasterDevice;
+
+    type Fram = FM25CL<'static, MockSpiDevice<'static>>;
+
+    struct Recorder {
+        read: Cell<Option<usize>>,
+        written: Cell<Option<usize>>,
+        status: Cell<Option<u8>>,
+        buffer: TakeCell<'static, [u8]>,
+    }
+
+    impl Recorder {
+        fn new() -> Self {
+            Self {
+                read: Cell::new(None),
+                written: Cell::new(None),
+                status: Cell::new(None),
+                buffer: TakeCell::empty(),
+            }
+        }
+    }
+
+    impl NonvolatileStorageClient for Recorder {
+        fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+            self.buffer.replace(buffer);
+            self.read.set(Some(length));
+        }
+
+        fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+            self.buffer.replace(buffer);
+            self.written.set(Some(length));
+        }
+    }
+
+    impl FM25CLClient for Recorder {
+        fn status(&self, status: u8) {
+            self.status.set(Some(status));
+        }
+
+        fn read(&self, _data: &'static mut [u8], _len: usize) {}
+
+        fn done(&self, _buffer: &'static mut [u8]) {}
+    }
+
+    fn fram() -> (
+        &'static Fram,
+        &'static MockSpiDevice<'static>,
+        &'static Recorder,
+    ) {
+        let spi = leak(MockSpiDevice::new());
+        let fram = leak(FM25CL::new(spi, static_buf(BUF_LEN), static_buf(BUF_LEN)));
+        spi.set_client(fram);
+        let client = leak(Recorder::new());
+        fram.set_client(client);
+        NonvolatileStorage::set_client(fram, client);
+        (fram, spi, client)
+    }
+
+    #[test]
+    fn write_enables_writes_then_sends_address_and_data() {
+        let (fram, spi, client) = fram();
+        let buffer = static_buf(4);
+        buffer.copy_from_slice(&[1, 2, 3, 4]);
+
+        fram.write(0x0102, buffer, 4).unwrap();
+        run_until_idle(&[spi]);
+        assert_eq!(client.written.get(), Some(4));
+        assert_eq!(
+            spi.take_written(),
+            [0x06, 0x02, 0x01, 0x02, 1, 2, 3, 4],
+            "WREN, then WRITE with the big-endian address and the data"
+        );
+    }
+
+    #[test]
+    fn read_returns_bytes_after_command_and_address() {
+        let (fram, spi, client) = fram();
+        // The FRAM drives MISO only once the command and address are sent.
+        spi.respond(&[0xff, 0xff, 0xff, 9, 8, 7, 6]);
+
+        fram.read(0x0010, static_buf(4), 4).unwrap();
+        run_until_idle(&[spi]);
+        assert_eq!(client.read.get(), Some(4));
+        assert_eq!(client.buffer.take().unwrap(), [9, 8, 7, 6]);
+        assert_eq!(spi.take_written()[..3], [0x03, 0x00, 0x10]);
+    }
+
+    #[test]
+    fn read_status_reports_second_byte() {
+        let (fram, spi, client) = fram();
+        spi.respond(&[0xff, 0x42]);
+
+        fram.read_status().unwrap();
+        run_until_idle(&[spi]);
+        assert_eq!(client.status.get(), Some(0x42));
+        assert_eq!(spi.take_written()[0], 0x05);
+    }
+}
@@ -572,3 +572,153 @@ impl<'a, S: hil::digest::Sha256 + hil::digest::DigestDataHash<'a, 32>> hil::dige
         Err(ErrorCode::NOSUPPORT)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use capsules_test_harness::digest::MockDigest;
+    use capsules_test_harness::{leak, run_until_idle, static_buf};
+    use kernel::hil::digest::{Digest, DigestDataHash, DigestHash, DigestVerify, HmacSha256};
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    type Hmac = HmacSha256Software<'static, MockDigest<'static, 32>>;
+
+    /// Stand-in for SHA-256 which is easy to compute in the tests: each byte
+    /// is mixed into one of the digest bytes, in turn.
+    fn hash(data: &[u8]) -> [u8; 32] {
+        let mut digest = [0u8; 32];
+        for (i, byte) in data.iter().enumerate() {
+            let out = &mut digest[i % 32];
+            *out = out.wrapping_mul(31).wrapping_add(*byte);
+        }
+        digest
+    }
+
+    /// HMAC as defined in RFC 2104, for keys no longer than a block.
+    fn expected_hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+        let mut padded = [0u8; SHA_BLOCK_LEN_BYTES];
+        padded[..key.len()].copy_from_slice(key);
+
+        let mut inner: Vec<u8> = padded.iter().map(|b| b ^ INNER_PAD_BYTE).collect();
+        inner.extend_from_slice(message);
+        let mut outer: Vec<u8> = padded.iter().map(|b| b ^ OUTER_PAD_BYTE).collect();
+        outer.extend_from_slice(&hash(&inner));
+        hash(&outer)
+    }
+
+    struct Recorder {
+        added: Cell<Option<Result<(), ErrorCode>>>,
+        hashed: Cell<Option<Result<(), ErrorCode>>>,
+        verified: Cell<Option<Result<bool, ErrorCode>>>,
+        digest: MapCell<&'static mut [u8; 32]>,
+    }
+
+    impl Recorder {
+        fn new() -> Self {
+            Self {
+                added: Cell::new(None),
+                hashed: Cell::new(None),
+                verified: Cell::new(None),
+                digest: MapCell::empty(),
+            }
+        }
+    }
+
+    impl hil::digest::ClientData<32> for Recorder {
+        fn add_data_done(&self, result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {
+            self.added.set(Some(result));
+        }
+
+        fn add_mut_data_done(
+            &self,
+            result: Result<(), ErrorCode>,
+            _data: SubSliceMut<'static, u8>,
+        ) {
+            self.added.set(Some(result));
+        }
+    }
+
+    impl hil::digest::ClientHash<32> for Recorder {
+        fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 32]) {
+            self.digest.replace(digest);
+            self.hashed.set(Some(result));
+        }
+    }
+
+    impl hil::digest::ClientVerify<32> for Recorder {
+        fn verification_done(
+            &self,
+            result: Result<bool, ErrorCode>,
+            compare: &'static mut [u8; 32],
+        ) {
+            self.digest.replace(compare);
+            self.verified.set(Some(result));
+        }
+    }
+
+    fn hmac() -> (
+        &'static Hmac,
+        &'static MockDigest<'static, 32>,
+        &'static Recorder,
+    ) {
+        let sha = leak(MockDigest::with_hash(hash));
+        let hmac = leak(HmacSha256Software::new(
+            sha,
+            static_buf(SHA_BLOCK_LEN_BYTES),
+            Box::leak(Box::new([0; 32])),
+        ));
+        DigestDataHash::set_client(sha, hmac);
+        let client = leak(Recorder::new());
+        Digest::set_client(hmac, client);
+        (hmac, sha, client)
+    }
+
+    #[test]
+    fn hmac_hashes_padded_key_then_message() {
+        let (hmac, sha, client) = hmac();
+        hmac.set_mode_hmacsha256(b"key").unwrap();
+        hmac.add_data(SubSlice::new(b"The quick brown fox"))
+            .unwrap();
+        run_until_idle(&[sha]);
+        assert_eq!(client.added.get(), Some(Ok(())));
+
+        hmac.run(Box::leak(Box::new([0; 32]))).unwrap();
+        run_until_idle(&[sha]);
+        assert_eq!(client.hashed.get(), Some(Ok(())));
+        assert_eq!(
+            client.digest.take().map(|digest| *digest),
+            Some(expected_hmac(b"key", b"The quick brown fox"))
+        );
+    }
+
+    #[test]
+    fn verify_compares_with_computed_hmac() {
+        let (hmac, sha, client) = hmac();
+        let good = expected_hmac(b"key", b"message");
+        let mut bad = good;
+        bad[0] ^= 1;
+
+        for (mac, matches) in [(good, true), (bad, false)] {
+            hmac.set_mode_hmacsha256(b"key").unwrap();
+            hmac.add_data(SubSlice::new(b"message")).unwrap();
+            run_until_idle(&[sha]);
+
+            hmac.verify(Box::leak(Box::new(mac))).unwrap();
+            run_until_idle(&[sha]);
+            assert_eq!(client.verified.get(), Some(Ok(matches)));
+            assert_eq!(client.digest.take().map(|compare| *compare), Some(mac));
+        }
+    }
+
+    #[test]
+    fn key_longer_than_a_block_is_rejected() {
+        let (hmac, _sha, _client) = hmac();
+        assert_eq!(
+            hmac.set_mode_hmacsha256(&[0; SHA_BLOCK_LEN_BYTES + 1]),
+            Err(ErrorCode::SIZE)
+        );
+    }
+}
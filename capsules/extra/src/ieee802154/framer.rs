@@ -69,6 +69,15 @@
 //! mac_device.set_transmit_client(radio_capsule);
 //! mac_device.set_receive_client(radio_capsule);
 //! ```
+//!
+//! Boards with nonvolatile storage should persist the frame counter, so that
+//! frame counters, and with them AES-CCM nonces, are never reused after a
+//! reboot. Secured frames are then only sent once the counter has been
+//! restored:
+//!
+//! ```rust,ignore
+//! mac_device.set_frame_counter_storage(storage, FRAME_COUNTER_ADDRESS, &mut FC_BUF)?;
+//! ```
 
 //
 // TODO: Encryption/decryption
@@ -87,12 +96,20 @@ use crate::net::stream::{encode_bytes, encode_u8, encode_u32};
 use core::cell::Cell;
 
 use kernel::ErrorCode;
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
 use kernel::hil::radio::{self, LQI_SIZE};
 use kernel::hil::symmetric_encryption::{AES128, AESCCM, CCMClient};
 use kernel::processbuffer::ReadableProcessSlice;
-use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
 
+/// Number of frame counter values which may be used before the value
+/// persisted in nonvolatile storage must be advanced.
+pub const FRAME_COUNTER_GUARD: u32 = 1000;
+
+/// Length of the frame counter as stored in nonvolatile storage.
+pub const FRAME_COUNTER_STORAGE_LEN: usize = 4;
+
 /// Wraps a static mutable byte slice along with header information
 /// for a payload.
 ///
@@ -330,6 +347,17 @@ pub struct Framer<'a, M: Mac<'a>, A: AESCCM<'a, AES128>> {
     mac: &'a M,
     aes_ccm: &'a A,
     data_sequence: Cell<u8>,
+    /// Frame counter of the next secured outgoing frame
+    frame_counter: Cell<u32>,
+    /// Nonvolatile storage and address holding the frame counter
+    frame_counter_storage: OptionalCell<(&'a dyn NonvolatileStorage<'a>, usize)>,
+    /// Buffer for reading and writing the persisted frame counter
+    frame_counter_buffer: TakeCell<'static, [u8]>,
+    /// Frame counter value persisted in storage; counter values below it may
+    /// be used. Without storage the counter is unbounded; once storage is set
+    /// this is 0, and no secured frame is sent, until the counter has been
+    /// restored from it.
+    frame_counter_limit: Cell<u32>,
 
     /// KeyDescriptor lookup procedure
     key_procedure: OptionalCell<&'a dyn KeyProcedure>,
@@ -360,6 +388,10 @@ impl<'a, M: Mac<'a>, A: AESCCM<'a, AES128>> Framer<'a, M, A> {
             mac,
             aes_ccm,
             data_sequence: Cell::new(0),
+            frame_counter: Cell::new(0),
+            frame_counter_storage: OptionalCell::empty(),
+            frame_counter_buffer: TakeCell::empty(),
+            frame_counter_limit: Cell::new(u32::MAX),
             key_procedure: OptionalCell::empty(),
             device_procedure: OptionalCell::empty(),
             tx_state: MapCell::new(TxState::Idle),
@@ -370,6 +402,44 @@ impl<'a, M: Mac<'a>, A: AESCCM<'a, AES128>> Framer<'a, M, A> {
         }
     }
 
+    /// Persists the outgoing frame counter at `address` in `storage`, and
+    /// restores it from there. `buffer` must be at least
+    /// `FRAME_COUNTER_STORAGE_LEN` bytes long.
+    ///
+    /// The stored value is advanced in steps of `FRAME_COUNTER_GUARD`, always
+    /// staying ahead of the counter values in use, so that the storage is only
+    /// written once every `FRAME_COUNTER_GUARD` secured frames. Secured frames
+    /// are refused from this call until the counter has been restored and the
+    /// first advanced value written back. Without storage, the counter is not
+    /// persisted and may be reused after a reboot.
+    pub fn set_frame_counter_storage(
+        &'a self,
+        storage: &'a dyn NonvolatileStorage<'a>,
+        address: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), ErrorCode> {
+        if buffer.len() < FRAME_COUNTER_STORAGE_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        storage.set_client(self);
+        self.frame_counter_storage.set((storage, address));
+        self.frame_counter_limit.set(0);
+        storage.read(buffer, address, FRAME_COUNTER_STORAGE_LEN)
+    }
+
+    /// Writes a new frame counter limit to storage, if storage is configured
+    /// and not in use. The limit only takes effect once the write completes.
+    fn persist_frame_counter(&self) -> Result<(), ErrorCode> {
+        self.frame_counter_storage
+            .map_or(Ok(()), |(storage, address)| {
+                self.frame_counter_buffer.take().map_or(Ok(()), |buffer| {
+                    let limit = self.frame_counter.get().saturating_add(FRAME_COUNTER_GUARD);
+                    buffer[..FRAME_COUNTER_STORAGE_LEN].copy_from_slice(&limit.to_le_bytes());
+                    storage.write(buffer, address, FRAME_COUNTER_STORAGE_LEN)
+                })
+            })
+    }
+
     /// Sets the IEEE 802.15.4 key lookup procedure to be used.
     pub fn set_key_procedure(&self, key_procedure: &'a dyn KeyProcedure) {
         self.key_procedure.set(key_procedure);
@@ -804,11 +874,25 @@ impl<'a, M: Mac<'a>, A: AESCCM<'a, AES128>> MacDevice<'a> for Framer<'a, M, A> {
                 MacAddress::Short(_) => return None,
             };
 
-            self.lookup_key(level, key_id).map(|key| {
-                // TODO: lookup frame counter for device
-                let frame_counter = 0;
+            self.lookup_key(level, key_id).and_then(|key| {
+                // The frame counter must never be reused, so only values
+                // below the persisted limit are used. The maximum value
+                // indicates that it has been exhausted.
+                let frame_counter = self.frame_counter.get();
+                let limit = self.frame_counter_limit.get();
+                if frame_counter == 0xffffffff || frame_counter >= limit {
+                    return None;
+                }
+                // Advance the persisted limit early, so that sending does
+                // not stall while the write is in progress. If the write
+                // cannot be started, the frame is refused and the limit
+                // stays where it is.
+                if limit - frame_counter <= FRAME_COUNTER_GUARD / 2 {
+                    self.persist_frame_counter().ok()?;
+                }
+                self.frame_counter.set(frame_counter + 1);
                 let nonce = get_ccm_nonce(&src_addr_long, frame_counter, level);
-                (
+                Some((
                     Security {
                         level,
                         asn_in_nonce: false,
@@ -817,11 +901,12 @@ impl<'a, M: Mac<'a>, A: AESCCM<'a, AES128>> MacDevice<'a> for Framer<'a, M, A> {
                     },
                     key,
                     nonce,
-                )
+                ))
             })
         });
         if security_needed.is_some() && security_desc.is_none() {
-            // If security was requested, fail when desired key was not found.
+            // If security was requested, fail when desired key was not found
+            // or no frame counter value may be used.
             return Err(buf);
         }
 
@@ -946,6 +1031,42 @@ impl<'a, M: Mac<'a>, A: AESCCM<'a, AES128>> radio::ConfigClient for Framer<'a, M
     }
 }
 
+impl<'a, M: Mac<'a>, A: AESCCM<'a, AES128>> NonvolatileStorageClient for Framer<'a, M, A> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        let restored = length >= FRAME_COUNTER_STORAGE_LEN;
+        if restored {
+            let mut stored = [0u8; FRAME_COUNTER_STORAGE_LEN];
+            stored.copy_from_slice(&buffer[..FRAME_COUNTER_STORAGE_LEN]);
+            let stored = u32::from_le_bytes(stored);
+            // Erased storage reads as all ones and holds no counter
+            if stored != 0xffffffff && stored > self.frame_counter.get() {
+                self.frame_counter.set(stored);
+            }
+        }
+        self.frame_counter_buffer.replace(buffer);
+
+        // Values up to the stored counter may have been used before the
+        // reboot, so move the persisted limit ahead right away. If the
+        // counter could not be read or the write fails, secured frames stay
+        // disabled.
+        if restored {
+            let _ = self.persist_frame_counter();
+        }
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        if length >= FRAME_COUNTER_STORAGE_LEN {
+            let mut written = [0u8; FRAME_COUNTER_STORAGE_LEN];
+            written.copy_from_slice(&buffer[..FRAME_COUNTER_STORAGE_LEN]);
+            let written = u32::from_le_bytes(written);
+            if written > self.frame_counter_limit.get() {
+                self.frame_counter_limit.set(written);
+            }
+        }
+        self.frame_counter_buffer.replace(buffer);
+    }
+}
+
 impl<'a, M: Mac<'a>, A: AESCCM<'a, AES128>> CCMClient for Framer<'a, M, A> {
     fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
         let mut tx_waiting = false;
@@ -1027,3 +1148,312 @@ impl<'a, M: Mac<'a>, A: AESCCM<'a, AES128>> CCMClient for Framer<'a, M, A> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+
+    use capsules_test_harness::{leak, static_buf};
+
+    use super::*;
+
+    const SRC_ADDR: [u8; 8] = [0x02, 0, 0, 0, 0, 0, 0, 0x01];
+    const PAN: PanID = 0xabcd;
+    const STORAGE_ADDRESS: usize = 0x40;
+
+    /// MAC layer for frames which are only prepared, never transmitted.
+    struct NoMac;
+
+    impl<'a> Mac<'a> for NoMac {
+        fn initialize(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn set_config_client(&self, _client: &'a dyn radio::ConfigClient) {}
+
+        fn set_transmit_client(&self, _client: &'a dyn radio::TxClient) {}
+
+        fn set_receive_client(&self, _client: &'a dyn radio::RxClient) {}
+
+        fn set_receive_buffer(&self, _buffer: &'static mut [u8]) {}
+
+        fn get_address(&self) -> u16 {
+            0
+        }
+
+        fn get_address_long(&self) -> [u8; 8] {
+            SRC_ADDR
+        }
+
+        fn get_pan(&self) -> u16 {
+            PAN
+        }
+
+        fn set_address(&self, _addr: u16) {}
+
+        fn set_address_long(&self, _addr: [u8; 8]) {}
+
+        fn set_pan(&self, _id: u16) {}
+
+        fn config_commit(&self) {}
+
+        fn is_on(&self) -> bool {
+            true
+        }
+
+        fn start(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn transmit(
+            &self,
+            full_mac_frame: &'static mut [u8],
+            _frame_len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            Err((ErrorCode::NOSUPPORT, full_mac_frame))
+        }
+    }
+
+    /// AES-CCM engine for frames which are only prepared, never secured.
+    struct NoCcm;
+
+    impl<'a> AESCCM<'a, AES128> for NoCcm {
+        fn set_client(&'a self, _client: &'a dyn CCMClient) {}
+
+        fn set_key(&self, _key: &[u8]) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn set_nonce(&self, _nonce: &[u8]) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn crypt(
+            &self,
+            buf: &'static mut [u8],
+            _a_off: usize,
+            _m_off: usize,
+            _m_len: usize,
+            _mic_len: usize,
+            _confidential: bool,
+            _encrypting: bool,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            Err((ErrorCode::NOSUPPORT, buf))
+        }
+    }
+
+    struct OneKey;
+
+    impl KeyProcedure for OneKey {
+        fn lookup_key(&self, _level: SecurityLevel, _key_id: KeyId) -> Option<[u8; 16]> {
+            Some([0x5a; 16])
+        }
+    }
+
+    /// Nonvolatile storage which holds on to the buffer of a read or write
+    /// until the test completes it.
+    #[derive(Default)]
+    struct SimStorage {
+        read: RefCell<Option<&'static mut [u8]>>,
+        written: RefCell<Option<&'static mut [u8]>>,
+        fail_writes: Cell<bool>,
+    }
+
+    impl SimStorage {
+        /// The value the pending write stores.
+        fn written_value(&self) -> Option<u32> {
+            self.written
+                .borrow()
+                .as_ref()
+                .map(|buf| u32::from_le_bytes(buf[..4].try_into().unwrap()))
+        }
+    }
+
+    impl<'a> NonvolatileStorage<'a> for SimStorage {
+        fn set_client(&self, _client: &'a dyn NonvolatileStorageClient) {}
+
+        fn read(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            assert_eq!((address, length), (STORAGE_ADDRESS, 4));
+            assert!(self.read.replace(Some(buffer)).is_none());
+            Ok(())
+        }
+
+        fn write(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            assert_eq!((address, length), (STORAGE_ADDRESS, 4));
+            if self.fail_writes.get() {
+                return Err(ErrorCode::FAIL);
+            }
+            assert!(self.written.replace(Some(buffer)).is_none());
+            Ok(())
+        }
+    }
+
+    type TestFramer = Framer<'static, NoMac, NoCcm>;
+
+    fn framer() -> &'static TestFramer {
+        let framer = leak(Framer::new(
+            leak(NoMac),
+            leak(NoCcm),
+            SubSliceMut::new(static_buf(radio::MAX_BUF_SIZE)),
+        ));
+        framer.set_key_procedure(leak(OneKey));
+        framer
+    }
+
+    /// Set up `storage` for the frame counter of `framer`, and restore the
+    /// counter from `stored`.
+    fn restore(framer: &'static TestFramer, storage: &'static SimStorage, stored: u32) {
+        assert_eq!(
+            framer.set_frame_counter_storage(storage, STORAGE_ADDRESS, static_buf(4)),
+            Ok(())
+        );
+        let buffer = storage.read.take().expect("the counter is not read");
+        buffer.copy_from_slice(&stored.to_le_bytes());
+        framer.read_done(buffer, 4);
+    }
+
+    /// Complete the pending write to storage.
+    fn write_done(framer: &TestFramer, storage: &SimStorage) {
+        let buffer = storage.written.take().expect("nothing is written");
+        framer.write_done(buffer, 4);
+    }
+
+    /// Prepare a secured frame, returning the frame counter it uses.
+    fn prepare_secured(framer: &TestFramer) -> Option<u32> {
+        framer
+            .prepare_data_frame(
+                static_buf(radio::MAX_BUF_SIZE),
+                PAN,
+                MacAddress::Short(0x0001),
+                PAN,
+                MacAddress::Long(SRC_ADDR),
+                Some((SecurityLevel::EncMic32, KeyId::Index(1))),
+            )
+            .ok()
+            .map(|_| framer.frame_counter.get() - 1)
+    }
+
+    #[test]
+    fn without_storage_the_counter_is_only_limited_by_exhaustion() {
+        let framer = framer();
+        assert_eq!(prepare_secured(framer), Some(0));
+        assert_eq!(prepare_secured(framer), Some(1));
+
+        framer.frame_counter.set(0xfffffffe);
+        assert_eq!(prepare_secured(framer), Some(0xfffffffe));
+        assert_eq!(prepare_secured(framer), None);
+    }
+
+    #[test]
+    fn unsecured_frames_do_not_use_the_counter() {
+        let framer = framer();
+        let storage = leak(SimStorage::default());
+        assert_eq!(
+            framer.set_frame_counter_storage(storage, STORAGE_ADDRESS, static_buf(4)),
+            Ok(())
+        );
+        let frame = framer.prepare_data_frame(
+            static_buf(radio::MAX_BUF_SIZE),
+            PAN,
+            MacAddress::Short(0x0001),
+            PAN,
+            MacAddress::Long(SRC_ADDR),
+            None,
+        );
+        assert!(frame.is_ok());
+        assert_eq!(framer.frame_counter.get(), 0);
+    }
+
+    #[test]
+    fn secured_frames_wait_for_the_counter_to_be_restored() {
+        let framer = framer();
+        let storage = leak(SimStorage::default());
+        assert_eq!(
+            framer.set_frame_counter_storage(storage, STORAGE_ADDRESS, static_buf(4)),
+            Ok(())
+        );
+        assert_eq!(prepare_secured(framer), None);
+
+        // The restored counter is moved ahead in storage before it is used.
+        let buffer = storage.read.take().unwrap();
+        buffer.copy_from_slice(&5000u32.to_le_bytes());
+        framer.read_done(buffer, 4);
+        assert_eq!(storage.written_value(), Some(5000 + FRAME_COUNTER_GUARD));
+        assert_eq!(prepare_secured(framer), None);
+
+        write_done(framer, storage);
+        assert_eq!(prepare_secured(framer), Some(5000));
+    }
+
+    #[test]
+    fn erased_storage_restores_no_counter() {
+        let framer = framer();
+        let storage = leak(SimStorage::default());
+        restore(framer, storage, 0xffffffff);
+        assert_eq!(storage.written_value(), Some(FRAME_COUNTER_GUARD));
+        write_done(framer, storage);
+        assert_eq!(prepare_secured(framer), Some(0));
+    }
+
+    #[test]
+    fn failed_read_keeps_secured_frames_disabled() {
+        let framer = framer();
+        let storage = leak(SimStorage::default());
+        assert_eq!(
+            framer.set_frame_counter_storage(storage, STORAGE_ADDRESS, static_buf(4)),
+            Ok(())
+        );
+        framer.read_done(storage.read.take().unwrap(), 0);
+        assert_eq!(storage.written_value(), None);
+        assert_eq!(prepare_secured(framer), None);
+    }
+
+    #[test]
+    fn limit_is_advanced_before_it_is_reached() {
+        let framer = framer();
+        let storage = leak(SimStorage::default());
+        restore(framer, storage, 0);
+        write_done(framer, storage);
+
+        framer.frame_counter.set(FRAME_COUNTER_GUARD / 2 - 1);
+        assert_eq!(prepare_secured(framer), Some(FRAME_COUNTER_GUARD / 2 - 1));
+        assert_eq!(storage.written_value(), None);
+
+        // Half of the guard is left: the next limit is written, and the
+        // current one stays in effect until the write completes.
+        assert_eq!(prepare_secured(framer), Some(FRAME_COUNTER_GUARD / 2));
+        let next_limit = FRAME_COUNTER_GUARD / 2 + FRAME_COUNTER_GUARD;
+        assert_eq!(storage.written_value(), Some(next_limit));
+        framer.frame_counter.set(FRAME_COUNTER_GUARD - 1);
+        assert_eq!(prepare_secured(framer), Some(FRAME_COUNTER_GUARD - 1));
+        assert_eq!(prepare_secured(framer), None);
+
+        write_done(framer, storage);
+        assert_eq!(prepare_secured(framer), Some(FRAME_COUNTER_GUARD));
+    }
+
+    #[test]
+    fn failed_write_refuses_the_frame() {
+        let framer = framer();
+        let storage = leak(SimStorage::default());
+        restore(framer, storage, 0);
+        write_done(framer, storage);
+
+        framer.frame_counter.set(FRAME_COUNTER_GUARD / 2);
+        storage.fail_writes.set(true);
+        assert_eq!(prepare_secured(framer), None);
+        // The counter value was not used, and the limit did not move.
+        assert_eq!(framer.frame_counter.get(), FRAME_COUNTER_GUARD / 2);
+        assert_eq!(framer.frame_counter_limit.get(), FRAME_COUNTER_GUARD);
+    }
+}
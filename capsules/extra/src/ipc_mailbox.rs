@@ -0,0 +1,506 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Message-queue inter-process communication.
+//!
+//! This capsule gives each process a bounded mailbox in its grant region.
+//! Processes send messages by copying them from a read-only allow buffer into
+//! the mailbox of another process, and receive the oldest message in their
+//! own mailbox by having it copied into a read-write allow buffer. Unlike the
+//! kernel's shared-buffer IPC, no memory is shared between processes, so
+//! clients and services do not need to agree on any locking or framing.
+//!
+//! Every message is tagged with the `ProcessId` identifier and the `ShortId`
+//! of its sender, so that a service can reply and decide whether to trust the
+//! sender. A receive always completes with an upcall, which lets processes
+//! block on receive with `yield-wait-for`.
+//!
+//! A process may only send messages if its TBF header allows it to call the
+//! send command of this driver, or if its TBF header does not specify any
+//! permissions. This check is made by the capsule, so it applies whether or
+//! not the board filters system calls with the TBF permissions.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let ipc_mailbox = components::ipc_mailbox::IpcMailboxComponent::new(
+//!     board_kernel,
+//!     capsules_extra::ipc_mailbox::DRIVER_NUM,
+//!     create_capability!(capabilities::MemoryAllocationCapability),
+//! )
+//! .finalize(components::ipc_mailbox_component_static!(4, 64));
+//! ```
+
+use core::cmp;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::process::ShortId;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+use tock_tbf::types::CommandPermissions;
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::IpcMailbox as usize;
+
+/// Command numbers.
+mod cmd {
+    /// Send the message in the read-only allow buffer.
+    pub const SEND: usize = 1;
+    /// Receive the oldest message into the read-write allow buffer.
+    pub const RECEIVE: usize = 2;
+    /// Find the identifier of the process with a `ShortId`.
+    pub const LOOKUP: usize = 3;
+    /// Get the identifier of the calling process.
+    pub const OWN_ID: usize = 4;
+}
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// A message was copied into the receive buffer.
+    pub const RECEIVED: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Message to send.
+    pub const SEND: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer received messages are copied into.
+    pub const RECEIVE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// A message waiting in a mailbox.
+struct Message<const MESSAGE_LEN: usize> {
+    /// `ProcessId` identifier of the sender.
+    sender: usize,
+    /// `ShortId` of the sender, or 0 if it does not have a fixed one.
+    short_id: u32,
+    len: usize,
+    data: [u8; MESSAGE_LEN],
+}
+
+impl<const MESSAGE_LEN: usize> Default for Message<MESSAGE_LEN> {
+    fn default() -> Self {
+        Self {
+            sender: 0,
+            short_id: 0,
+            len: 0,
+            data: [0; MESSAGE_LEN],
+        }
+    }
+}
+
+/// The mailbox of a process.
+pub struct App<const DEPTH: usize, const MESSAGE_LEN: usize> {
+    /// Ring buffer of messages, starting at `head`.
+    messages: [Message<MESSAGE_LEN>; DEPTH],
+    head: usize,
+    count: usize,
+    /// The process asked to receive a message while its mailbox was empty,
+    /// so the next message is delivered immediately.
+    receive_pending: bool,
+}
+
+impl<const DEPTH: usize, const MESSAGE_LEN: usize> Default for App<DEPTH, MESSAGE_LEN> {
+    fn default() -> Self {
+        Self {
+            messages: core::array::from_fn(|_| Message::default()),
+            head: 0,
+            count: 0,
+            receive_pending: false,
+        }
+    }
+}
+
+pub struct IpcMailbox<const DEPTH: usize, const MESSAGE_LEN: usize> {
+    apps: Grant<
+        App<DEPTH, MESSAGE_LEN>,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<const DEPTH: usize, const MESSAGE_LEN: usize> IpcMailbox<DEPTH, MESSAGE_LEN> {
+    pub fn new(
+        grant: Grant<
+            App<DEPTH, MESSAGE_LEN>,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> Self {
+        Self { apps: grant }
+    }
+
+    /// Check that the TBF permissions of `processid` allow it to send messages.
+    fn may_send(processid: ProcessId) -> bool {
+        match processid.get_command_permissions(DRIVER_NUM, cmd::SEND / 64) {
+            Some(CommandPermissions::NoPermsAtAll) => true,
+            Some(CommandPermissions::Mask(allowed)) => (1 << (cmd::SEND % 64)) & allowed > 0,
+            Some(CommandPermissions::NoPermsThisDriver) | None => false,
+        }
+    }
+
+    /// Copy the message in the send buffer of `sender` into the mailbox of
+    /// the process with identifier `receiver_id`.
+    fn send(&self, sender: ProcessId, receiver_id: usize) -> Result<(), ErrorCode> {
+        if !Self::may_send(sender) {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let receiver = self
+            .apps
+            .iter()
+            .map(|app| app.processid())
+            .find(|processid| processid.id() == receiver_id)
+            .ok_or(ErrorCode::INVAL)?;
+
+        // Copy the message out of the sender's grant first, so that only one
+        // grant is entered at a time. This also lets a process send messages
+        // to itself.
+        let mut message = Message::<MESSAGE_LEN> {
+            sender: sender.id(),
+            short_id: match sender.short_app_id() {
+                ShortId::Fixed(id) => id.get(),
+                ShortId::LocallyUnique => 0,
+            },
+            ..Message::default()
+        };
+        message.len = self
+            .apps
+            .enter(sender, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::SEND)
+                    .and_then(|send| {
+                        send.enter(|data| {
+                            if data.len() > MESSAGE_LEN {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            data.copy_to_slice(&mut message.data[..data.len()]);
+                            Ok(data.len())
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .map_err(ErrorCode::from)
+            .and_then(|result| result)?;
+
+        self.apps
+            .enter(receiver, |app, kernel_data| {
+                if app.receive_pending {
+                    app.receive_pending = false;
+                    if Self::deliver(&message, kernel_data).is_ok() {
+                        return Ok(());
+                    }
+                    // The receiver revoked its receive buffer while waiting,
+                    // so the message is queued until it receives again.
+                }
+                if app.count == DEPTH {
+                    Err(ErrorCode::BUSY)
+                } else {
+                    let tail = (app.head + app.count) % DEPTH;
+                    app.messages[tail] = message;
+                    app.count += 1;
+                    Ok(())
+                }
+            })
+            .map_err(ErrorCode::from)
+            .and_then(|result| result)
+    }
+
+    /// Deliver the oldest message in the mailbox of `processid`, or deliver
+    /// the next message to arrive if the mailbox is empty.
+    fn receive(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, kernel_data| {
+                if app.receive_pending {
+                    Err(ErrorCode::BUSY)
+                } else if app.count == 0 {
+                    if !Self::has_receive_buffer(kernel_data) {
+                        return Err(ErrorCode::RESERVE);
+                    }
+                    app.receive_pending = true;
+                    Ok(())
+                } else {
+                    Self::deliver(&app.messages[app.head], kernel_data)?;
+                    app.head = (app.head + 1) % DEPTH;
+                    app.count -= 1;
+                    Ok(())
+                }
+            })
+            .map_err(ErrorCode::from)
+            .and_then(|result| result)
+    }
+
+    fn has_receive_buffer(kernel_data: &GrantKernelData) -> bool {
+        kernel_data
+            .get_readwrite_processbuffer(rw_allow::RECEIVE)
+            .is_ok_and(|receive| receive.len() > 0)
+    }
+
+    /// Copy `message` into the receive buffer and signal the receiving
+    /// process. If the buffer is shorter than the message, only the start of
+    /// the message is copied.
+    ///
+    /// Fails with `RESERVE`, without signalling the process, if it has not
+    /// allowed a receive buffer.
+    fn deliver(
+        message: &Message<MESSAGE_LEN>,
+        kernel_data: &GrantKernelData,
+    ) -> Result<(), ErrorCode> {
+        if !Self::has_receive_buffer(kernel_data) {
+            return Err(ErrorCode::RESERVE);
+        }
+        kernel_data
+            .get_readwrite_processbuffer(rw_allow::RECEIVE)
+            .and_then(|receive| {
+                receive.mut_enter(|buffer| {
+                    let len = cmp::min(buffer.len(), message.len);
+                    buffer[..len].copy_from_slice(&message.data[..len]);
+                })
+            })
+            .or(Err(ErrorCode::RESERVE))?;
+        let _ = kernel_data.schedule_upcall(
+            upcall::RECEIVED,
+            (message.len, message.sender, message.short_id as usize),
+        );
+        Ok(())
+    }
+}
+
+/// Provide an interface for userland.
+impl<const DEPTH: usize, const MESSAGE_LEN: usize> SyscallDriver
+    for IpcMailbox<DEPTH, MESSAGE_LEN>
+{
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Send the message in read-only allow 0 to the process with
+    ///   identifier `arg1`.
+    /// - `2`: Receive the oldest message in the mailbox into read-write allow
+    ///   0. Upcall 0 is issued when a message has been received. Returns
+    ///   `RESERVE` if no receive buffer is allowed; a message sent while the
+    ///   buffer is revoked stays in the mailbox.
+    /// - `3`: Return the identifier of the process with the `ShortId` `arg1`.
+    /// - `4`: Return the identifier of the calling process.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            cmd::SEND => self.send(processid, arg1).into(),
+            cmd::RECEIVE => self.receive(processid).into(),
+            cmd::LOOKUP => self
+                .apps
+                .iter()
+                .map(|app| app.processid())
+                .find(|other| match other.short_app_id() {
+                    ShortId::Fixed(id) => id.get() as usize == arg1,
+                    ShortId::LocallyUnique => false,
+                })
+                .map_or(CommandReturn::failure(ErrorCode::INVAL), |other| {
+                    CommandReturn::success_u32(other.id() as u32)
+                }),
+            cmd::OWN_ID => CommandReturn::success_u32(processid.id() as u32),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capsules_test_harness::process::{App, Buffer, HostKernel, Upcall};
+    use capsules_test_harness::{deferred_call, leak};
+    use kernel::syscall::SyscallReturn;
+
+    const DEPTH: usize = 2;
+    const MESSAGE_LEN: usize = 8;
+
+    fn mailbox() -> &'static HostKernel {
+        let kernel = HostKernel::new();
+        let mailbox = leak(IpcMailbox::<DEPTH, MESSAGE_LEN>::new(
+            kernel.create_grant(DRIVER_NUM),
+        ));
+        kernel.add_driver(DRIVER_NUM, mailbox);
+        kernel
+    }
+
+    fn send(sender: &App, receiver: &App, message: &[u8]) -> SyscallReturn {
+        let buffer = sender.allocate(message.len());
+        sender.write(buffer, message);
+        sender.allow_readonly(DRIVER_NUM, ro_allow::SEND, buffer);
+        sender.command(DRIVER_NUM, cmd::SEND, receiver.id().id(), 0)
+    }
+
+    /// Allow a receive buffer and subscribe to the received upcall.
+    fn listen(receiver: &App) -> Buffer {
+        let buffer = receiver.allocate(MESSAGE_LEN);
+        receiver.allow_readwrite(DRIVER_NUM, rw_allow::RECEIVE, buffer);
+        receiver.subscribe(DRIVER_NUM, upcall::RECEIVED);
+        buffer
+    }
+
+    fn received(len: usize, sender: &App) -> Option<Upcall> {
+        Some(Upcall {
+            driver_number: DRIVER_NUM,
+            subscribe_number: upcall::RECEIVED,
+            arguments: [len, sender.id().id(), 0],
+        })
+    }
+
+    fn receive(receiver: &App, buffer: Buffer, sender: &App, message: &[u8]) {
+        assert!(matches!(
+            receiver.command(DRIVER_NUM, cmd::RECEIVE, 0, 0),
+            SyscallReturn::Success
+        ));
+        assert_eq!(receiver.yield_wait(), received(message.len(), sender));
+        assert_eq!(&receiver.read(buffer)[..message.len()], message);
+    }
+
+    #[test]
+    fn own_id_is_the_process_identifier() {
+        deferred_call::run(|| {
+            let kernel = mailbox();
+            let app = kernel.load_process("app");
+            assert!(matches!(
+                app.command(DRIVER_NUM, cmd::OWN_ID, 0, 0),
+                SyscallReturn::SuccessU32(id) if id as usize == app.id().id()
+            ));
+        });
+    }
+
+    #[test]
+    fn queued_messages_are_received_in_order() {
+        deferred_call::run(|| {
+            let kernel = mailbox();
+            let sender = kernel.load_process("sender");
+            let receiver = kernel.load_process("receiver");
+            let buffer = listen(&receiver);
+
+            assert!(matches!(
+                send(&sender, &receiver, b"first"),
+                SyscallReturn::Success
+            ));
+            assert!(matches!(
+                send(&sender, &receiver, b"second"),
+                SyscallReturn::Success
+            ));
+            receive(&receiver, buffer, &sender, b"first");
+            receive(&receiver, buffer, &sender, b"second");
+        });
+    }
+
+    #[test]
+    fn pending_receive_gets_the_next_message() {
+        deferred_call::run(|| {
+            let kernel = mailbox();
+            let sender = kernel.load_process("sender");
+            let receiver = kernel.load_process("receiver");
+            let buffer = listen(&receiver);
+
+            assert!(matches!(
+                receiver.command(DRIVER_NUM, cmd::RECEIVE, 0, 0),
+                SyscallReturn::Success
+            ));
+            assert_eq!(receiver.yield_wait(), None);
+            assert!(matches!(
+                send(&sender, &receiver, b"hello"),
+                SyscallReturn::Success
+            ));
+            assert_eq!(receiver.yield_wait(), received(5, &sender));
+            assert_eq!(&receiver.read(buffer)[..5], b"hello");
+        });
+    }
+
+    #[test]
+    fn full_mailbox_rejects_messages() {
+        deferred_call::run(|| {
+            let kernel = mailbox();
+            let sender = kernel.load_process("sender");
+            let receiver = kernel.load_process("receiver");
+            let buffer = listen(&receiver);
+
+            for message in [b"one", b"two"] {
+                assert!(matches!(
+                    send(&sender, &receiver, message),
+                    SyscallReturn::Success
+                ));
+            }
+            assert!(matches!(
+                send(&sender, &receiver, b"three"),
+                SyscallReturn::Failure(ErrorCode::BUSY)
+            ));
+            receive(&receiver, buffer, &sender, b"one");
+            receive(&receiver, buffer, &sender, b"two");
+        });
+    }
+
+    #[test]
+    fn receive_without_a_buffer_keeps_the_message() {
+        deferred_call::run(|| {
+            let kernel = mailbox();
+            let sender = kernel.load_process("sender");
+            let receiver = kernel.load_process("receiver");
+            receiver.subscribe(DRIVER_NUM, upcall::RECEIVED);
+
+            assert!(matches!(
+                send(&sender, &receiver, b"hello"),
+                SyscallReturn::Success
+            ));
+            assert!(matches!(
+                receiver.command(DRIVER_NUM, cmd::RECEIVE, 0, 0),
+                SyscallReturn::Failure(ErrorCode::RESERVE)
+            ));
+
+            let buffer = listen(&receiver);
+            receive(&receiver, buffer, &sender, b"hello");
+        });
+    }
+
+    #[test]
+    fn message_to_a_revoked_buffer_is_queued() {
+        deferred_call::run(|| {
+            let kernel = mailbox();
+            let sender = kernel.load_process("sender");
+            let receiver = kernel.load_process("receiver");
+            listen(&receiver);
+            assert!(matches!(
+                receiver.command(DRIVER_NUM, cmd::RECEIVE, 0, 0),
+                SyscallReturn::Success
+            ));
+            receiver.allow_readwrite(DRIVER_NUM, rw_allow::RECEIVE, Buffer::NULL);
+
+            assert!(matches!(
+                send(&sender, &receiver, b"hello"),
+                SyscallReturn::Success
+            ));
+
+            let buffer = receiver.allocate(MESSAGE_LEN);
+            receiver.allow_readwrite(DRIVER_NUM, rw_allow::RECEIVE, buffer);
+            receive(&receiver, buffer, &sender, b"hello");
+        });
+    }
+}
@@ -67,7 +67,7 @@ mod ro_allow {
 
 /// IDs for read-write allow buffers.
 mod rw_allow {
-    /// Output value for get.
+    /// Output value for get, and output keys for list keys.
     pub const VALUE: usize = 0;
     /// The number of RW allow buffers the kernel stores for this grant.
     pub const COUNT: u8 = 1;
@@ -89,6 +89,7 @@ enum UserSpaceOp {
     Add,
     Update,
     GarbageCollect,
+    ListKeys,
 }
 
 /// Contents of the grant for each app.
@@ -141,8 +142,9 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
         self.processid.map_or(Err(ErrorCode::RESERVE), |processid| {
             self.apps
                 .enter(processid, |app, kernel_data| {
-                    let key_len = if app.op.is_some() {
-                        // For all operations we need to copy in the key.
+                    let key_len = if app.op.is_some() && !app.op.contains(&UserSpaceOp::ListKeys) {
+                        // For all operations other than listing keys we need
+                        // to copy in the key.
                         kernel_data
                             .get_readonly_processbuffer(ro_allow::KEY)
                             .and_then(|buffer| {
@@ -271,6 +273,22 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
                             self.kv.garbage_collect()?;
                             return Ok(());
                         }
+                        Some(UserSpaceOp::ListKeys) => {
+                            let perms = processid
+                                .get_storage_permissions()
+                                .ok_or(ErrorCode::INVAL)?;
+
+                            if let Some(e) = self.value_buffer.take().map(|val_buf| {
+                                self.kv.list_keys(SubSliceMut::new(val_buf), perms).map_err(
+                                    |(keys_ret, e)| {
+                                        self.value_buffer.replace(keys_ret.take());
+                                        e
+                                    },
+                                )
+                            }) {
+                                return e;
+                            }
+                        }
 
                         _ => {}
                     }
@@ -296,7 +314,21 @@ impl<'a, V: kv::KVPermissions<'a>> KVStoreDriver<'a, V> {
             let started_command = if has_pending_op {
                 // Mark this driver as being in use.
                 self.processid.set(processid);
-                self.run() == Ok(())
+                self.run().map_or_else(
+                    |e| {
+                        // The command was accepted when it was queued, so
+                        // the failure is reported through the upcall.
+                        let _ = self.apps.enter(processid, |app, upcalls| {
+                            app.op.clear();
+                            let _ = upcalls.schedule_upcall(
+                                upcalls::VALUE,
+                                (errorcode::into_statuscode(Err(e)), 0, 0),
+                            );
+                        });
+                        false
+                    },
+                    |()| true,
+                )
             } else {
                 false
             };
@@ -490,6 +522,52 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for KVStoreDriver<'a, V> {
         self.processid.clear();
         self.check_queue();
     }
+
+    fn list_keys_complete(&self, result: Result<usize, ErrorCode>, keys: SubSliceMut<'static, u8>) {
+        self.processid.map(|id| {
+            self.apps.enter(id, |app, upcalls| {
+                if app.op.contains(&UserSpaceOp::ListKeys) {
+                    app.op.clear();
+
+                    // Copy the keys that were listed even if not all of them
+                    // fit, so userspace gets as many as possible.
+                    let keys_len = keys.len();
+                    let (ret, copy_len) = upcalls
+                        .get_readwrite_processbuffer(rw_allow::VALUE)
+                        .and_then(|buffer| {
+                            buffer.mut_enter(|appslice| {
+                                let copy_len = cmp::min(keys_len, appslice.len());
+                                appslice[..copy_len].copy_from_slice(&keys[..copy_len]);
+                                if copy_len < keys_len {
+                                    (Err(ErrorCode::SIZE), copy_len)
+                                } else {
+                                    (result.map(|_| ()), copy_len)
+                                }
+                            })
+                        })
+                        .unwrap_or((Err(ErrorCode::RESERVE), 0));
+
+                    // Signal the upcall with the number of bytes copied and
+                    // the number of keys the process has stored.
+                    let _ = upcalls.schedule_upcall(
+                        upcalls::VALUE,
+                        (
+                            errorcode::into_statuscode(ret),
+                            copy_len,
+                            result.unwrap_or(0),
+                        ),
+                    );
+                }
+            })
+        });
+
+        self.value_buffer.replace(keys.take());
+
+        // We have completed the operation so see if there is a queued operation
+        // to run next.
+        self.processid.clear();
+        self.check_queue();
+    }
 }
 
 impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
@@ -504,8 +582,8 @@ impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
             // check if present
             0 => CommandReturn::success(),
 
-            // get, set, delete, add, update, garbage collect
-            1 | 2 | 3 | 4 | 5 | 6 => {
+            // get, set, delete, add, update, garbage collect, list keys
+            1..=7 => {
                 if self.processid.is_none() {
                     // Nothing is using the KV store, so we can handle this
                     // request.
@@ -517,11 +595,15 @@ impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
                         4 => app.op.set(UserSpaceOp::Add),
                         5 => app.op.set(UserSpaceOp::Update),
                         6 => app.op.set(UserSpaceOp::GarbageCollect),
+                        7 => app.op.set(UserSpaceOp::ListKeys),
                         _ => {}
                     });
                     let ret = self.run();
 
                     if let Err(e) = ret {
+                        // The operation is not queued, the process learns
+                        // about the failure from the return value.
+                        let _ = self.apps.enter(processid, |app, _| app.op.clear());
                         self.processid.clear();
                         self.check_queue();
                         CommandReturn::failure(e)
@@ -547,6 +629,7 @@ impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
                                     4 => app.op.set(UserSpaceOp::Add),
                                     5 => app.op.set(UserSpaceOp::Update),
                                     6 => app.op.set(UserSpaceOp::GarbageCollect),
+                                    7 => app.op.set(UserSpaceOp::ListKeys),
                                     _ => {}
                                 }
                                 CommandReturn::success()
@@ -565,3 +648,292 @@ impl<'a, V: kv::KVPermissions<'a>> SyscallDriver for KVStoreDriver<'a, V> {
         self.apps.enter(processid, |_, _| {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use capsules_test_harness::process::{App as Process, HostKernel, Upcall};
+    use capsules_test_harness::{Simulated, deferred_call, leak, run_until_idle, static_buf};
+    use core::cell::RefCell;
+    use kernel::storage_permissions::StoragePermissions;
+    use kernel::syscall::SyscallReturn;
+    use std::collections::HashMap;
+    use std::vec::Vec;
+
+    enum Pending {
+        Get(SubSliceMut<'static, u8>, SubSliceMut<'static, u8>),
+        Set(SubSliceMut<'static, u8>, SubSliceMut<'static, u8>),
+    }
+
+    /// K-V store without a header, which ignores permissions and completes
+    /// `get` and `set` when stepped.
+    struct MockKV {
+        client: OptionalCell<&'static dyn kv::KVClient>,
+        values: RefCell<HashMap<Vec<u8>, Vec<u8>>>,
+        pending: RefCell<Option<Pending>>,
+    }
+
+    impl MockKV {
+        fn new() -> Self {
+            Self {
+                client: OptionalCell::empty(),
+                values: RefCell::new(HashMap::new()),
+                pending: RefCell::new(None),
+            }
+        }
+
+        fn start(
+            &self,
+            operation: Pending,
+        ) -> Result<
+            (),
+            (
+                SubSliceMut<'static, u8>,
+                SubSliceMut<'static, u8>,
+                ErrorCode,
+            ),
+        > {
+            if self.pending.borrow().is_some() {
+                let (Pending::Get(key, value) | Pending::Set(key, value)) = operation;
+                return Err((key, value, ErrorCode::BUSY));
+            }
+            *self.pending.borrow_mut() = Some(operation);
+            Ok(())
+        }
+    }
+
+    impl kv::KVPermissions<'static> for MockKV {
+        fn set_client(&self, client: &'static dyn kv::KVClient) {
+            self.client.set(client);
+        }
+
+        fn get(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result<
+            (),
+            (
+                SubSliceMut<'static, u8>,
+                SubSliceMut<'static, u8>,
+                ErrorCode,
+            ),
+        > {
+            self.start(Pending::Get(key, value))
+        }
+
+        fn set(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result<
+            (),
+            (
+                SubSliceMut<'static, u8>,
+                SubSliceMut<'static, u8>,
+                ErrorCode,
+            ),
+        > {
+            self.start(Pending::Set(key, value))
+        }
+
+        fn add(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result<
+            (),
+            (
+                SubSliceMut<'static, u8>,
+                SubSliceMut<'static, u8>,
+                ErrorCode,
+            ),
+        > {
+            Err((key, value, ErrorCode::NOSUPPORT))
+        }
+
+        fn update(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result<
+            (),
+            (
+                SubSliceMut<'static, u8>,
+                SubSliceMut<'static, u8>,
+                ErrorCode,
+            ),
+        > {
+            Err((key, value, ErrorCode::NOSUPPORT))
+        }
+
+        fn delete(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+            Err((key, ErrorCode::NOSUPPORT))
+        }
+
+        fn garbage_collect(&self) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+
+        fn list_keys(
+            &self,
+            keys: SubSliceMut<'static, u8>,
+            _permissions: StoragePermissions,
+        ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+            Err((keys, ErrorCode::NOSUPPORT))
+        }
+
+        fn header_size(&self) -> usize {
+            0
+        }
+    }
+
+    impl Simulated for MockKV {
+        fn step(&self) -> bool {
+            let Some(operation) = self.pending.take() else {
+                return false;
+            };
+            match operation {
+                Pending::Get(key, mut value) => {
+                    let result = match self.values.borrow().get(key.as_slice()) {
+                        Some(stored) => {
+                            value.slice(..stored.len());
+                            value.as_mut_slice().copy_from_slice(stored);
+                            Ok(())
+                        }
+                        None => Err(ErrorCode::NOSUPPORT),
+                    };
+                    self.client
+                        .map(|client| client.get_complete(result, key, value));
+                }
+                Pending::Set(key, value) => {
+                    self.values
+                        .borrow_mut()
+                        .insert(key.as_slice().to_vec(), value.as_slice().to_vec());
+                    self.client
+                        .map(|client| client.set_complete(Ok(()), key, value));
+                }
+            }
+            true
+        }
+    }
+
+    /// Create a driver with room for 8-byte keys and values.
+    fn driver() -> (&'static HostKernel, &'static MockKV) {
+        let kernel = HostKernel::new();
+        let kv = leak(MockKV::new());
+        let driver = leak(KVStoreDriver::new(
+            kv,
+            static_buf(8),
+            static_buf(8),
+            kernel.create_grant(DRIVER_NUM),
+        ));
+        kv::KVPermissions::set_client(kv, driver);
+        kernel.add_driver(DRIVER_NUM, driver);
+        (kernel, kv)
+    }
+
+    fn done(result: Result<(), ErrorCode>, len: usize) -> Option<Upcall> {
+        Some(Upcall {
+            driver_number: DRIVER_NUM,
+            subscribe_number: upcalls::VALUE,
+            arguments: [errorcode::into_statuscode(result), len, 0],
+        })
+    }
+
+    fn allow(process: &Process, allow_num: usize, data: &[u8]) {
+        let buffer = process.allocate(data.len());
+        process.write(buffer, data);
+        process.allow_readonly(DRIVER_NUM, allow_num, buffer);
+    }
+
+    /// Ask to set `key` to `value`.
+    fn set(process: &Process, key: &[u8], value: &[u8]) -> SyscallReturn {
+        allow(process, ro_allow::KEY, key);
+        allow(process, ro_allow::VALUE, value);
+        process.subscribe(DRIVER_NUM, upcalls::VALUE);
+        process.command(DRIVER_NUM, 2, 0, 0)
+    }
+
+    #[test]
+    fn get_copies_the_value_to_the_process() {
+        deferred_call::run(|| {
+            let (kernel, kv) = driver();
+            kv.values
+                .borrow_mut()
+                .insert(b"key".to_vec(), b"value".to_vec());
+            let process = kernel.load_process("app");
+
+            allow(&process, ro_allow::KEY, b"key");
+            let value = process.allocate(8);
+            process.allow_readwrite(DRIVER_NUM, rw_allow::VALUE, value);
+            process.subscribe(DRIVER_NUM, upcalls::VALUE);
+            assert!(matches!(
+                process.command(DRIVER_NUM, 1, 0, 0),
+                SyscallReturn::Success
+            ));
+            assert_eq!(process.yield_wait(), None);
+
+            run_until_idle(&[kv]);
+            assert_eq!(process.yield_wait(), done(Ok(()), 5));
+            assert_eq!(&process.read(value)[..5], b"value");
+        });
+    }
+
+    #[test]
+    fn requests_of_two_processes_are_serialized() {
+        deferred_call::run(|| {
+            let (kernel, kv) = driver();
+            let first = kernel.load_process("first");
+            let second = kernel.load_process("second");
+
+            assert!(matches!(set(&first, b"a", b"one"), SyscallReturn::Success));
+            assert!(matches!(set(&second, b"b", b"two"), SyscallReturn::Success));
+
+            run_until_idle(&[kv]);
+            assert_eq!(first.yield_wait(), done(Ok(()), 0));
+            assert_eq!(second.yield_wait(), done(Ok(()), 0));
+            assert_eq!(kv.values.borrow()[b"a".as_slice()], b"one");
+            assert_eq!(kv.values.borrow()[b"b".as_slice()], b"two");
+        });
+    }
+
+    #[test]
+    fn failed_commands_are_not_queued() {
+        deferred_call::run(|| {
+            let (kernel, kv) = driver();
+            let first = kernel.load_process("first");
+            let second = kernel.load_process("second");
+
+            // The value does not fit in the driver's buffer.
+            assert!(matches!(
+                set(&second, b"b", b"too long to store"),
+                SyscallReturn::Failure(ErrorCode::SIZE)
+            ));
+
+            // While the first process is using the store, the second one can
+            // queue a request, which fails when it is started.
+            assert!(matches!(set(&first, b"a", b"one"), SyscallReturn::Success));
+            assert!(matches!(
+                second.command(DRIVER_NUM, 2, 0, 0),
+                SyscallReturn::Success
+            ));
+
+            run_until_idle(&[kv]);
+            assert_eq!(first.yield_wait(), done(Ok(()), 0));
+            assert_eq!(second.yield_wait(), done(Err(ErrorCode::SIZE), 0));
+            assert_eq!(second.yield_wait(), None);
+            assert!(!kv.values.borrow().contains_key(b"b".as_slice()));
+        });
+    }
+}
@@ -25,7 +25,29 @@
 //!
 //!    hil::flash
 //! ```
+//!
+//! Storage quotas
+//! --------------
+//!
+//! To enforce storage quotas and to list the keys of a writer, this capsule
+//! keeps an index of the keys stored with each non-zero `write_id` that has a
+//! storage quota. Writers without a quota have no index. The index of a writer
+//! is stored in the underlying K-V store as a kernel-owned value under the key
+//! `INDEX_KEY_PREFIX` followed by the little-endian `write_id`. It is a list of
+//! entries, each a one byte key length, the length of the value as a
+//! little-endian `u32`, and the key. Only keys written after the index was
+//! introduced are tracked.
+//!
+//! The index is only written back when it changes, so overwriting a key with a
+//! value of the same length does not wear the flash any more than without
+//! quotas. When a key written by one `write_id` is overwritten with another
+//! `write_id` (which requires modify permission), the key moves from the index
+//! of the previous writer to the index of the new writer.
+//!
+//! The buffer the index is read into must hold `max_keys` entries; use
+//! [`index_length()`] to size it.
 
+use core::cell::Cell;
 use core::mem;
 use kernel::ErrorCode;
 use kernel::hil::kv;
@@ -41,6 +63,32 @@ enum Operation {
     Update,
     Delete,
     GarbageCollect,
+    ListKeys,
+}
+
+/// Step of an operation that accesses the index of a writer.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum IndexStep {
+    /// Reading the index before the operation.
+    Read,
+    /// Reading the index of the previous writer of an overwritten key.
+    ReadPrevious,
+    /// Writing the updated index after the operation.
+    Write,
+}
+
+/// Keys of the per-writer indexes start with this prefix. Processes cannot
+/// store keys with this prefix.
+pub const INDEX_KEY_PREFIX: &[u8] = b"tock.kvindex.";
+pub const INDEX_KEY_LENGTH: usize = INDEX_KEY_PREFIX.len() + mem::size_of::<u32>();
+
+/// Length of the key length and value length fields of an index entry.
+const INDEX_ENTRY_HEADER_LENGTH: usize = 5;
+
+/// Length of the buffer needed to read an index of up to `max_keys` keys of at
+/// most `max_key_length` bytes.
+pub const fn index_length(max_keys: usize, max_key_length: usize) -> usize {
+    HEADER_LENGTH + max_keys * (INDEX_ENTRY_HEADER_LENGTH + max_key_length)
 }
 
 /// Current version of the Tock K-V header.
@@ -73,6 +121,74 @@ impl KeyHeader {
     }
 }
 
+/// Iterator over the entries of an index, as (offset, key, value length).
+struct IndexEntries<'b> {
+    index: &'b [u8],
+    offset: usize,
+}
+
+impl<'b> IndexEntries<'b> {
+    fn new(index: &'b [u8]) -> Self {
+        Self { index, offset: 0 }
+    }
+}
+
+impl<'b> Iterator for IndexEntries<'b> {
+    type Item = (usize, &'b [u8], usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.index.get(self.offset..)?;
+        let key_len = *entry.first()? as usize;
+        let value_len = u32::from_le_bytes(entry.get(1..5)?.try_into().ok()?) as usize;
+        let key = entry.get(INDEX_ENTRY_HEADER_LENGTH..INDEX_ENTRY_HEADER_LENGTH + key_len)?;
+        let offset = self.offset;
+        self.offset += INDEX_ENTRY_HEADER_LENGTH + key_len;
+        Some((offset, key, value_len))
+    }
+}
+
+/// Return the number of key and value bytes, the number of keys and the
+/// length of the entries in `index`, not counting `key`.
+fn index_usage(index: &[u8], key: &[u8]) -> (usize, usize, usize) {
+    IndexEntries::new(index)
+        .filter(|(_, entry_key, _)| *entry_key != key)
+        .fold(
+            (0, 0, 0),
+            |(bytes, keys, len), (_, entry_key, value_len)| {
+                (
+                    bytes + entry_key.len() + value_len,
+                    keys + 1,
+                    len + INDEX_ENTRY_HEADER_LENGTH + entry_key.len(),
+                )
+            },
+        )
+}
+
+/// Remove `key` from the first `len` bytes of `index`. Returns the new length
+/// of the index.
+fn index_remove(index: &mut [u8], len: usize, key: &[u8]) -> usize {
+    match IndexEntries::new(&index[..len]).find(|(_, entry_key, _)| *entry_key == key) {
+        Some((offset, _, _)) => {
+            let entry_len = INDEX_ENTRY_HEADER_LENGTH + key.len();
+            index.copy_within(offset + entry_len..len, offset);
+            len - entry_len
+        }
+        None => len,
+    }
+}
+
+/// Append an entry for `key` to the first `len` bytes of `index`. Returns the
+/// new length of the index, or `None` if the entry does not fit.
+fn index_append(index: &mut [u8], len: usize, key: &[u8], value_len: usize) -> Option<usize> {
+    let key_len = u8::try_from(key.len()).ok()?;
+    let entry_len = INDEX_ENTRY_HEADER_LENGTH + key.len();
+    let entry = index.get_mut(len..len + entry_len)?;
+    entry[0] = key_len;
+    entry[1..5].copy_from_slice(&(value_len as u32).to_le_bytes());
+    entry[INDEX_ENTRY_HEADER_LENGTH..].copy_from_slice(key);
+    Some(len + entry_len)
+}
+
 /// Key-Value store with Tock-specific extensions for permissions and access
 /// control.
 ///
@@ -84,22 +200,48 @@ pub struct KVStorePermissions<'a, K: kv::KV<'a>> {
     client: OptionalCell<&'a dyn kv::KVClient>,
     operation: OptionalCell<Operation>,
 
+    key: MapCell<SubSliceMut<'static, u8>>,
     value: MapCell<SubSliceMut<'static, u8>>,
     valid_ids: OptionalCell<StoragePermissions>,
+
+    /// Key buffer for reading and writing indexes.
+    index_key: TakeCell<'static, [u8]>,
+    /// Buffer holding the index of `index_write_id`, with room for a header.
+    index_value: TakeCell<'static, [u8]>,
+    /// Length of the index in `index_value`, not including the header.
+    index_len: Cell<usize>,
+    /// The `write_id` whose index the current operation uses, if any.
+    index_write_id: OptionalCell<u32>,
+    /// The `write_id` that stored the key the current operation overwrites,
+    /// if it is not the writer of the operation.
+    previous_write_id: OptionalCell<u32>,
+    index_step: OptionalCell<IndexStep>,
+    /// Length of the value being stored by the current operation.
+    value_len: Cell<usize>,
 }
 
 impl<'a, K: kv::KV<'a>> KVStorePermissions<'a, K> {
     pub fn new(
         kv: &'a K,
         header_value: &'static mut [u8; HEADER_LENGTH],
+        index_key: &'static mut [u8; INDEX_KEY_LENGTH],
+        index_value: &'static mut [u8],
     ) -> KVStorePermissions<'a, K> {
         Self {
             kv,
             header_value: TakeCell::new(header_value),
             client: OptionalCell::empty(),
             operation: OptionalCell::empty(),
+            key: MapCell::empty(),
             value: MapCell::empty(),
             valid_ids: OptionalCell::empty(),
+            index_key: TakeCell::new(index_key),
+            index_value: TakeCell::new(index_value),
+            index_len: Cell::new(0),
+            index_write_id: OptionalCell::empty(),
+            previous_write_id: OptionalCell::empty(),
+            index_step: OptionalCell::empty(),
+            value_len: Cell::new(0),
         }
     }
 
@@ -131,6 +273,12 @@ impl<'a, K: kv::KV<'a>> KVStorePermissions<'a, K> {
             return Err((key, value, ErrorCode::SIZE));
         }
 
+        // Only the kernel may store keys in the index namespace, so that
+        // processes cannot create or squat on the index of another writer.
+        if write_id != 0 && key.as_slice().starts_with(INDEX_KEY_PREFIX) {
+            return Err((key, value, ErrorCode::INVAL));
+        }
+
         // Create the Tock header.
         let header = KeyHeader {
             version: HEADER_VERSION,
@@ -142,11 +290,19 @@ impl<'a, K: kv::KV<'a>> KVStorePermissions<'a, K> {
         header.copy_to_buf(value.as_mut_slice());
 
         self.operation.set(operation);
+        self.valid_ids.set(permissions);
+        self.value_len.set(value.len() - HEADER_LENGTH);
+        self.previous_write_id.clear();
+        // Only writers with a quota have an index. Keys stored by the kernel
+        // are not indexed.
+        if write_id == 0 || permissions.get_quota().is_none() {
+            self.index_write_id.clear();
+        } else {
+            self.index_write_id.set(write_id);
+        }
 
         match operation {
             Operation::Set | Operation::Update => {
-                self.valid_ids.set(permissions);
-
                 // We first read the key to see if we are allowed to overwrite it.
                 match self.header_value.take() {
                     Some(header_value) => match self.kv.get(key, SubSliceMut::new(header_value)) {
@@ -164,6 +320,23 @@ impl<'a, K: kv::KV<'a>> KVStorePermissions<'a, K> {
                 }
             }
 
+            Operation::Add if self.index_write_id.is_some() => {
+                // Since add will only succeed if the key is not already there,
+                // we do not need to check permissions, but we do need the
+                // index to check the quota.
+                match self.read_index(write_id, IndexStep::Read) {
+                    Ok(()) => {
+                        self.key.replace(key);
+                        self.value.replace(value);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        self.operation.clear();
+                        Err((key, value, e))
+                    }
+                }
+            }
+
             Operation::Add => {
                 // Since add will only succeed if the key is not already there,
                 // we do not have to worry about overwriting and do not need to
@@ -180,6 +353,371 @@ impl<'a, K: kv::KV<'a>> KVStorePermissions<'a, K> {
             _ => Err((key, value, ErrorCode::FAIL)),
         }
     }
+
+    /// Start reading the index of `write_id` into `index_value`, as `step` of
+    /// the current operation.
+    fn read_index(&self, write_id: u32, step: IndexStep) -> Result<(), ErrorCode> {
+        let index_key = self.index_key.take().ok_or(ErrorCode::FAIL)?;
+        let Some(index_value) = self.index_value.take() else {
+            self.index_key.replace(index_key);
+            return Err(ErrorCode::FAIL);
+        };
+
+        index_key[..INDEX_KEY_PREFIX.len()].copy_from_slice(INDEX_KEY_PREFIX);
+        index_key[INDEX_KEY_PREFIX.len()..].copy_from_slice(&write_id.to_le_bytes());
+
+        self.index_step.set(step);
+        match self
+            .kv
+            .get(SubSliceMut::new(index_key), SubSliceMut::new(index_value))
+        {
+            Ok(()) => Ok(()),
+            Err((index_key, index_value, e)) => {
+                self.index_step.clear();
+                self.index_key.replace(index_key.take());
+                self.index_value.replace(index_value.take());
+                Err(e)
+            }
+        }
+    }
+
+    /// Start writing the updated index in `index_value`.
+    fn write_index(&self) -> Result<(), ErrorCode> {
+        let index_key = self.index_key.take().ok_or(ErrorCode::FAIL)?;
+        let Some(index_value) = self.index_value.take() else {
+            self.index_key.replace(index_key);
+            return Err(ErrorCode::FAIL);
+        };
+
+        // Indexes are owned by the kernel so that processes cannot access
+        // them.
+        let header = KeyHeader {
+            version: HEADER_VERSION,
+            length: self.index_len.get() as u32,
+            write_id: 0,
+        };
+        header.copy_to_buf(index_value);
+
+        let mut value = SubSliceMut::new(index_value);
+        value.slice(..HEADER_LENGTH + self.index_len.get());
+
+        self.index_step.set(IndexStep::Write);
+        match self.kv.set(SubSliceMut::new(index_key), value) {
+            Ok(()) => Ok(()),
+            Err((index_key, index_value, e)) => {
+                self.index_step.clear();
+                self.index_key.replace(index_key.take());
+                self.index_value.replace(index_value.take());
+                Err(e)
+            }
+        }
+    }
+
+    /// Continue the current operation, after its permissions have been
+    /// checked, by reading the index it uses, if any.
+    fn check_index(&self) {
+        match self.index_write_id.get() {
+            Some(write_id) => {
+                if let Err(e) = self.read_index(write_id, IndexStep::Read) {
+                    self.complete(Err(e));
+                }
+            }
+            None => self.write_value(),
+        }
+    }
+
+    /// Record that the current operation overwrites a key stored by
+    /// `stored_id`, so that the key is removed from the index of that writer.
+    fn overwrites(&self, stored_id: u32) {
+        let write_id = self.valid_ids.get().and_then(|perms| perms.get_write_id());
+        if stored_id != 0 && write_id != Some(stored_id) {
+            self.previous_write_id.set(stored_id);
+        }
+    }
+
+    /// Handle the updated index having been written, which finishes the
+    /// current operation.
+    fn index_write_complete(
+        &self,
+        index_key: SubSliceMut<'static, u8>,
+        index_value: SubSliceMut<'static, u8>,
+    ) {
+        self.index_step.clear();
+        self.index_key.replace(index_key.take());
+        self.index_value.replace(index_value.take());
+        // The index is only written after the operation succeeded.
+        self.index_updated();
+    }
+
+    /// Continue the current operation, which succeeded, once the index of
+    /// its writer is up to date, by removing the key from the index of its
+    /// previous writer, if any.
+    fn index_updated(&self) {
+        match self.previous_write_id.take() {
+            Some(write_id) => {
+                self.index_write_id.set(write_id);
+                if self.read_index(write_id, IndexStep::ReadPrevious).is_err() {
+                    self.complete(Ok(()));
+                }
+            }
+            None => self.complete(Ok(())),
+        }
+    }
+
+    /// Handle the index of `index_write_id` having been read, and continue
+    /// the current operation.
+    fn index_read_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        index_key: SubSliceMut<'static, u8>,
+        index_value: SubSliceMut<'static, u8>,
+    ) {
+        let step = self.index_step.take();
+        self.index_key.replace(index_key.take());
+        let index_value = index_value.take();
+
+        let index_len = match result {
+            Ok(()) => {
+                let header = KeyHeader::new_from_buf(index_value);
+                let length = header.length as usize;
+                (header.version == HEADER_VERSION
+                    && header.write_id == 0
+                    && HEADER_LENGTH + length <= index_value.len())
+                .then_some(length)
+            }
+            // There is no index yet, so the writer has not stored any keys.
+            Err(ErrorCode::NOSUPPORT) => Some(0),
+            Err(_) => None,
+        };
+        self.index_value.replace(index_value);
+
+        if step == Some(IndexStep::ReadPrevious) {
+            // The operation itself already succeeded, so its result is
+            // reported even if the index cannot be updated.
+            if let Some(index_len) = index_len {
+                self.index_len.set(index_len);
+                if self.update_index(true) && self.write_index().is_ok() {
+                    return;
+                }
+            }
+            self.complete(Ok(()));
+            return;
+        }
+
+        let Some(index_len) = index_len else {
+            self.complete(Err(ErrorCode::FAIL));
+            return;
+        };
+        self.index_len.set(index_len);
+
+        match self.operation.get() {
+            Some(Operation::Set | Operation::Add | Operation::Update) => match self.check_quota() {
+                Ok(()) => self.write_value(),
+                Err(e) => self.complete(Err(e)),
+            },
+            Some(Operation::Delete) => self.write_value(),
+            Some(Operation::ListKeys) => self.list_keys_complete(),
+            _ => {}
+        }
+    }
+
+    /// Check that storing the current key and value keeps the writer within
+    /// its storage quota, and that the key fits in the index.
+    fn check_quota(&self) -> Result<(), ErrorCode> {
+        // Only writers with a quota have an index.
+        let Some(quota) = self.valid_ids.get().and_then(|perms| perms.get_quota()) else {
+            return Ok(());
+        };
+        let value_len = self.value_len.get();
+
+        let (key_len, usage, capacity) = self
+            .key
+            .and_then(|key| {
+                self.index_value.map(|index_value| {
+                    let index = &index_value[HEADER_LENGTH..HEADER_LENGTH + self.index_len.get()];
+                    (
+                        key.len(),
+                        index_usage(index, key.as_slice()),
+                        index_value.len() - HEADER_LENGTH,
+                    )
+                })
+            })
+            .ok_or(ErrorCode::FAIL)?;
+        let (bytes, keys, index_len) = usage;
+
+        if bytes + key_len + value_len > quota.max_bytes as usize
+            || keys + 1 > quota.max_keys as usize
+        {
+            return Err(ErrorCode::NOMEM);
+        }
+
+        // The index buffer should be sized for `max_keys` entries, but the key
+        // may be longer than it allows for.
+        if key_len > u8::MAX as usize || index_len + INDEX_ENTRY_HEADER_LENGTH + key_len > capacity
+        {
+            return Err(ErrorCode::NOMEM);
+        }
+        Ok(())
+    }
+
+    /// Update the index in `index_value` for the current key: remove the key
+    /// if `remove` is set, and otherwise record the length of its new value.
+    /// Returns whether the index changed and needs to be written.
+    fn update_index(&self, remove: bool) -> bool {
+        let value_len = self.value_len.get();
+        self.key
+            .map(|key| {
+                self.index_value.map(|index_value| {
+                    let index = &mut index_value[HEADER_LENGTH..];
+                    let key = key.as_slice();
+                    let len = self.index_len.get();
+                    if !remove
+                        && IndexEntries::new(&index[..len]).any(|(_, entry_key, entry_len)| {
+                            entry_key == key && entry_len == value_len
+                        })
+                    {
+                        return false;
+                    }
+
+                    let mut index_len = index_remove(index, len, key);
+                    if !remove {
+                        // The quota check ensured the entry fits.
+                        index_len =
+                            index_append(index, index_len, key, value_len).unwrap_or(index_len);
+                    }
+                    self.index_len.set(index_len);
+                    !remove || index_len != len
+                })
+            })
+            .flatten()
+            .unwrap_or(false)
+    }
+
+    /// Pass the current operation, with the key and value stored in `key` and
+    /// `value`, on to the underlying K-V store.
+    fn write_value(&self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let stash = |(key, value, e)| {
+            self.key.replace(key);
+            self.value.replace(value);
+            e
+        };
+
+        let result = match (self.operation.get(), self.value.take()) {
+            (Some(Operation::Set), Some(value)) => self.kv.set(key, value).map_err(stash),
+            (Some(Operation::Add), Some(value)) => self.kv.add(key, value).map_err(stash),
+            (Some(Operation::Update), Some(value)) => self.kv.update(key, value).map_err(stash),
+            (Some(Operation::Delete), None) => self.kv.delete(key).map_err(|(key, e)| {
+                self.key.replace(key);
+                e
+            }),
+            (_, value) => {
+                self.key.replace(key);
+                if let Some(value) = value {
+                    self.value.replace(value);
+                }
+                Err(ErrorCode::FAIL)
+            }
+        };
+
+        if let Err(e) = result {
+            self.complete(Err(e));
+        }
+    }
+
+    /// Update the indexes after the underlying K-V store completed the
+    /// current operation, or report the result to the client if there is no
+    /// index to update.
+    fn value_written(&self, result: Result<(), ErrorCode>) {
+        if result.is_err() {
+            self.complete(result);
+            return;
+        }
+
+        // If the index cannot be written the operation itself still
+        // succeeded, so its result is reported anyway.
+        let delete = self.operation.contains(&Operation::Delete);
+        if self.index_write_id.is_some() && self.update_index(delete) && self.write_index().is_ok()
+        {
+            return;
+        }
+        self.index_updated();
+    }
+
+    /// Finish the current operation and pass `result` with the stored buffers
+    /// to the client.
+    fn complete(&self, result: Result<(), ErrorCode>) {
+        self.index_write_id.clear();
+        self.previous_write_id.clear();
+        let key = self.key.take();
+        let value = self.value.take();
+
+        match (self.operation.take(), key, value) {
+            (Some(Operation::Set), Some(key), Some(value)) => {
+                self.client
+                    .map(move |cb| cb.set_complete(result, key, value));
+            }
+            (Some(Operation::Add), Some(key), Some(value)) => {
+                self.client
+                    .map(move |cb| cb.add_complete(result, key, value));
+            }
+            (Some(Operation::Update), Some(key), Some(value)) => {
+                self.client
+                    .map(move |cb| cb.update_complete(result, key, value));
+            }
+            (Some(Operation::Delete), Some(key), _) => {
+                self.client.map(move |cb| cb.delete_complete(result, key));
+            }
+            (Some(Operation::ListKeys), _, Some(mut keys)) => {
+                keys.slice(0..0);
+                self.client
+                    .map(move |cb| cb.list_keys_complete(result.map(|()| 0), keys));
+            }
+            _ => {}
+        }
+    }
+
+    /// Copy the keys in the index into the buffer of the current list keys
+    /// operation and pass it to the client.
+    fn list_keys_complete(&self) {
+        self.operation.clear();
+        self.index_write_id.clear();
+
+        let Some(mut keys) = self.value.take() else {
+            return;
+        };
+
+        let mut written = 0;
+        let mut count = 0;
+        let mut fits = true;
+        self.index_value.map(|index_value| {
+            let index = &index_value[HEADER_LENGTH..HEADER_LENGTH + self.index_len.get()];
+            let buffer = keys.as_mut_slice();
+
+            for (_, key, _) in IndexEntries::new(index) {
+                count += 1;
+                match buffer.get_mut(written..written + 1 + key.len()) {
+                    Some(entry) if fits => {
+                        entry[0] = key.len() as u8;
+                        entry[1..].copy_from_slice(key);
+                        written += entry.len();
+                    }
+                    _ => fits = false,
+                }
+            }
+        });
+
+        keys.slice(0..written);
+        let result = if fits {
+            Ok(count)
+        } else {
+            Err(ErrorCode::SIZE)
+        };
+        self.client
+            .map(move |cb| cb.list_keys_complete(result, keys));
+    }
 }
 
 impl<'a, K: kv::KV<'a>> kv::KVPermissions<'a> for KVStorePermissions<'a, K> {
@@ -275,6 +813,8 @@ impl<'a, K: kv::KV<'a>> kv::KVPermissions<'a> for KVStorePermissions<'a, K> {
 
         self.operation.set(Operation::Delete);
         self.valid_ids.set(permissions);
+        self.index_write_id.clear();
+        self.previous_write_id.clear();
 
         match self.header_value.take() {
             Some(header_value) => match self.kv.get(key, SubSliceMut::new(header_value)) {
@@ -299,6 +839,38 @@ impl<'a, K: kv::KV<'a>> kv::KVPermissions<'a> for KVStorePermissions<'a, K> {
         self.kv.garbage_collect()
     }
 
+    fn list_keys(
+        &self,
+        keys: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        let write_id = match permissions.get_write_id() {
+            Some(0) => return Err((keys, ErrorCode::NOSUPPORT)),
+            Some(write_id) => write_id,
+            None => return Err((keys, ErrorCode::INVAL)),
+        };
+
+        // Only writers with a quota have an index.
+        if permissions.get_quota().is_none() {
+            return Err((keys, ErrorCode::NOSUPPORT));
+        }
+
+        if self.operation.is_some() {
+            return Err((keys, ErrorCode::BUSY));
+        }
+
+        match self.read_index(write_id, IndexStep::Read) {
+            Ok(()) => {
+                self.operation.set(Operation::ListKeys);
+                self.valid_ids.set(permissions);
+                self.index_write_id.set(write_id);
+                self.value.replace(keys);
+                Ok(())
+            }
+            Err(e) => Err((keys, e)),
+        }
+    }
+
     fn header_size(&self) -> usize {
         HEADER_LENGTH
     }
@@ -311,6 +883,11 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
         key: SubSliceMut<'static, u8>,
         mut value: SubSliceMut<'static, u8>,
     ) {
+        if self.index_step.is_some() {
+            self.index_read_complete(result, key, value);
+            return;
+        }
+
         self.operation.map(|op| {
             match op {
                 Operation::Set => {
@@ -324,6 +901,9 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
                             self.valid_ids.map(|perms| {
                                 access_allowed = perms.check_modify_permission(header.write_id);
                             });
+                            if access_allowed {
+                                self.overwrites(header.write_id);
+                            }
                         }
                     } else if result.err() == Some(ErrorCode::NOSUPPORT) {
                         // Key wasn't found, so we can create it fresh.
@@ -333,18 +913,8 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
                     self.header_value.replace(value.take());
 
                     if access_allowed {
-                        self.value
-                            .take()
-                            .map(|set_value| match self.kv.set(key, set_value) {
-                                Ok(()) => {}
-
-                                Err((key, set_value, e)) => {
-                                    self.operation.clear();
-                                    self.client.map(move |cb| {
-                                        cb.set_complete(Err(e), key, set_value);
-                                    });
-                                }
-                            });
+                        self.key.replace(key);
+                        self.check_index();
                     } else {
                         self.operation.clear();
                         self.value.take().map(|set_value| {
@@ -365,24 +935,17 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
                             self.valid_ids.map(|perms| {
                                 access_allowed = perms.check_modify_permission(header.write_id);
                             });
+                            if access_allowed {
+                                self.overwrites(header.write_id);
+                            }
                         }
                     }
 
                     self.header_value.replace(value.take());
 
                     if access_allowed {
-                        self.value
-                            .take()
-                            .map(|set_value| match self.kv.update(key, set_value) {
-                                Ok(()) => {}
-
-                                Err((key, set_value, e)) => {
-                                    self.operation.clear();
-                                    self.client.map(move |cb| {
-                                        cb.update_complete(Err(e), key, set_value);
-                                    });
-                                }
-                            });
+                        self.key.replace(key);
+                        self.check_index();
                     } else {
                         self.operation.clear();
                         self.value.take().map(|set_value| {
@@ -407,22 +970,20 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
                             self.valid_ids.map(|perms| {
                                 access_allowed = perms.check_modify_permission(header.write_id);
                             });
+
+                            // The key is removed from the index of the writer
+                            // that stored it.
+                            if header.write_id != 0 {
+                                self.index_write_id.set(header.write_id);
+                            }
                         }
                     }
 
                     self.header_value.replace(value.take());
 
                     if access_allowed {
-                        match self.kv.delete(key) {
-                            Ok(()) => {}
-
-                            Err((key, e)) => {
-                                self.operation.clear();
-                                self.client.map(move |cb| {
-                                    cb.delete_complete(Err(e), key);
-                                });
-                            }
-                        }
+                        self.key.replace(key);
+                        self.check_index();
                     } else {
                         self.operation.clear();
                         self.client.map(move |cb| {
@@ -478,10 +1039,14 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
         key: SubSliceMut<'static, u8>,
         value: SubSliceMut<'static, u8>,
     ) {
-        self.operation.clear();
-        self.client.map(move |cb| {
-            cb.set_complete(result, key, value);
-        });
+        if self.index_step.contains(&IndexStep::Write) {
+            self.index_write_complete(key, value);
+            return;
+        }
+
+        self.key.replace(key);
+        self.value.replace(value);
+        self.value_written(result);
     }
 
     fn add_complete(
@@ -490,10 +1055,14 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
         key: SubSliceMut<'static, u8>,
         value: SubSliceMut<'static, u8>,
     ) {
-        self.operation.clear();
-        self.client.map(move |cb| {
-            cb.add_complete(result, key, value);
-        });
+        if self.index_step.contains(&IndexStep::Write) {
+            self.index_write_complete(key, value);
+            return;
+        }
+
+        self.key.replace(key);
+        self.value.replace(value);
+        self.value_written(result);
     }
 
     fn update_complete(
@@ -502,17 +1071,19 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
         key: SubSliceMut<'static, u8>,
         value: SubSliceMut<'static, u8>,
     ) {
-        self.operation.clear();
-        self.client.map(move |cb| {
-            cb.update_complete(result, key, value);
-        });
+        if self.index_step.contains(&IndexStep::Write) {
+            self.index_write_complete(key, value);
+            return;
+        }
+
+        self.key.replace(key);
+        self.value.replace(value);
+        self.value_written(result);
     }
 
     fn delete_complete(&self, result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
-        self.operation.clear();
-        self.client.map(move |cb| {
-            cb.delete_complete(result, key);
-        });
+        self.key.replace(key);
+        self.value_written(result);
     }
 
     fn garbage_collection_complete(&self, result: Result<(), ErrorCode>) {
@@ -521,4 +1092,69 @@ impl<'a, K: kv::KV<'a>> kv::KVClient for KVStorePermissions<'a, K> {
             cb.garbage_collection_complete(result);
         });
     }
+
+    fn list_keys_complete(
+        &self,
+        _result: Result<usize, ErrorCode>,
+        _keys: SubSliceMut<'static, u8>,
+    ) {
+        // The underlying K-V store does not list keys.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_with(entries: &[(&[u8], usize)]) -> ([u8; 64], usize) {
+        let mut index = [0; 64];
+        let mut len = 0;
+        for (key, value_len) in entries {
+            len = index_append(&mut index, len, key, *value_len).unwrap();
+        }
+        (index, len)
+    }
+
+    #[test]
+    fn index_length_fits_max_keys() {
+        let (_, len) = index_with(&[(b"abc", 1), (b"def", 2)]);
+        assert_eq!(index_length(2, 3), HEADER_LENGTH + len);
+        assert_eq!(index_length(0, 0), HEADER_LENGTH);
+    }
+
+    #[test]
+    fn index_append_fails_when_full() {
+        let mut index = [0; 10];
+        let len = index_append(&mut index, 0, b"abc", 4).unwrap();
+        assert_eq!(len, 8);
+        assert_eq!(index_append(&mut index, len, b"abc", 4), None);
+        assert_eq!(index_append(&mut [0; 300], 0, &[0; 256], 4), None);
+    }
+
+    #[test]
+    fn index_usage_excludes_key() {
+        let (index, len) = index_with(&[(b"a", 10), (b"bb", 20), (b"ccc", 30)]);
+        assert_eq!(index_usage(&index[..len], b"zz"), (66, 3, len));
+        assert_eq!(
+            index_usage(&index[..len], b"bb"),
+            (44, 2, len - INDEX_ENTRY_HEADER_LENGTH - 2)
+        );
+    }
+
+    #[test]
+    fn index_remove_keeps_other_entries() {
+        let (mut index, len) = index_with(&[(b"a", 10), (b"bb", 20), (b"ccc", 30)]);
+        let len_removed = index_remove(&mut index, len, b"bb");
+        assert_eq!(len_removed, len - INDEX_ENTRY_HEADER_LENGTH - 2);
+
+        let mut entries = IndexEntries::new(&index[..len_removed]);
+        assert_eq!(entries.next(), Some((0, &b"a"[..], 10)));
+        assert_eq!(
+            entries.next(),
+            Some((INDEX_ENTRY_HEADER_LENGTH + 1, &b"ccc"[..], 30))
+        );
+        assert_eq!(entries.next(), None);
+
+        assert_eq!(index_remove(&mut index, len_removed, b"bb"), len_removed);
+    }
 }
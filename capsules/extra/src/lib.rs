@@ -22,6 +22,7 @@ pub mod app_loader;
 pub mod at24c_eeprom;
 pub mod atecc508a;
 pub mod ble_advertising_driver;
+pub mod ble_peripheral;
 pub mod bme280;
 pub mod bmm150;
 pub mod bmp280;
@@ -32,6 +33,7 @@ pub mod buzzer_pwm;
 pub mod can;
 pub mod ccs811;
 pub mod chirp_i2c_moisture;
+pub mod crash_log;
 pub mod crc;
 pub mod cycle_count;
 pub mod cyw4343;
@@ -42,6 +44,7 @@ pub mod dfrobot_rainfall_sensor;
 pub mod distance;
 pub mod ethernet_tap;
 pub mod eui64;
+pub mod fat_fs;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700cq;
@@ -54,6 +57,7 @@ pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
 pub mod ieee802154;
+pub mod ipc_mailbox;
 pub mod isl29035;
 pub mod isolated_nonvolatile_storage_driver;
 pub mod kv_driver;
@@ -81,6 +85,7 @@ pub mod nrf51822_serialization;
 pub mod panic_button;
 pub mod pca9544a;
 pub mod pressure;
+pub mod process_accounting;
 pub mod process_info_driver;
 pub mod proximity;
 pub mod public_key_crypto;
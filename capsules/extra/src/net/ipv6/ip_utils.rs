@@ -11,6 +11,7 @@
 use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions};
 use crate::net::ieee802154::MacAddress;
 use crate::net::ipv6::IP6Header;
+use crate::net::tcp::{TCP_HDR_LEN, TCPHeader};
 use crate::net::udp::UDPHeader;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -230,6 +231,63 @@ pub fn compute_icmp_checksum(
     sum as u16
 }
 
+/// Computes the TCP checksum of a segment.
+///
+/// The checksum covers the IPv6 pseudo-header, the fixed part of the TCP
+/// header and `payload`, which holds everything following the fixed header
+/// (options and data). `tcp_header.get_len()` is used as the segment length,
+/// and the result is returned in host byte order.
+pub fn compute_tcp_checksum(ip6_header: &IP6Header, tcp_header: &TCPHeader, payload: &[u8]) -> u16 {
+    let tcp_len = tcp_header.get_len() as u32;
+    let mut sum: u32 = 0;
+
+    // IPv6 pseudo-header
+    let mut i = 0;
+    while i < 16 {
+        sum += (ip6_header.src_addr.0[i] as u32) << 8 | ip6_header.src_addr.0[i + 1] as u32;
+        sum += (ip6_header.dst_addr.0[i] as u32) << 8 | ip6_header.dst_addr.0[i + 1] as u32;
+        i += 2;
+    }
+    sum += tcp_len >> 16;
+    sum += tcp_len & 0xffff;
+    sum += ip6_nh::TCP as u32;
+
+    // Fixed TCP header
+    sum += tcp_header.src_port as u32;
+    sum += tcp_header.dst_port as u32;
+    sum += tcp_header.seq_num >> 16;
+    sum += tcp_header.seq_num & 0xffff;
+    sum += tcp_header.ack_num >> 16;
+    sum += tcp_header.ack_num & 0xffff;
+    sum += tcp_header.offset_and_control as u32;
+    sum += tcp_header.window as u32;
+    sum += tcp_header.cksum as u32;
+    sum += tcp_header.urg_ptr as u32;
+
+    // Options and data, padding an odd trailing byte with zero. Bytes beyond
+    // the end of `payload` are summed as zero as well.
+    let remaining = (tcp_len as usize)
+        .saturating_sub(TCP_HDR_LEN)
+        .min(payload.len());
+    let mut i: usize = 0;
+    while i < remaining {
+        let msb = (payload[i] as u32) << 8;
+        let lsb = if i + 1 < remaining {
+            payload[i + 1] as u32
+        } else {
+            0
+        };
+        sum += msb + lsb;
+        i += 2;
+    }
+
+    while sum > 0xffff {
+        sum = (sum >> 16) + (sum & 0xffff);
+    }
+
+    !sum as u16
+}
+
 pub fn compute_ipv6_ph_sum(ip6_header: &IP6Header) -> u32 {
     let mut sum: u32 = 0;
 
@@ -266,3 +324,27 @@ pub fn compute_sum(buf: &[u8], len: u16) -> u32 {
 
     sum
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tcp_checksum_treats_missing_payload_as_zero() {
+        let ip6_header = IP6Header::new();
+        let mut tcp_header = TCPHeader::new();
+        tcp_header.set_src_port(1234);
+        tcp_header.set_dst_port(80);
+        tcp_header.set_len((TCP_HDR_LEN + 5) as u16);
+
+        let padded = [1, 2, 3, 0, 0];
+        assert_eq!(
+            compute_tcp_checksum(&ip6_header, &tcp_header, &padded[..3]),
+            compute_tcp_checksum(&ip6_header, &tcp_header, &padded)
+        );
+        assert_eq!(
+            compute_tcp_checksum(&ip6_header, &tcp_header, &[]),
+            compute_tcp_checksum(&ip6_header, &tcp_header, &[0; 5])
+        );
+    }
+}
@@ -73,11 +73,13 @@
 // by 6LoWPAN) difficult.
 
 use crate::net::icmpv6::ICMP6Header;
-use crate::net::ipv6::ip_utils::{IPAddr, compute_icmp_checksum, compute_udp_checksum, ip6_nh};
+use crate::net::ipv6::ip_utils::{
+    IPAddr, compute_icmp_checksum, compute_tcp_checksum, compute_udp_checksum, ip6_nh,
+};
 use crate::net::stream::SResult;
 use crate::net::stream::{decode_bytes, decode_u8, decode_u16};
 use crate::net::stream::{encode_bytes, encode_u8, encode_u16};
-use crate::net::tcp::TCPHeader;
+use crate::net::tcp::{TCP_HDR_LEN, TCPHeader};
 use crate::net::udp::UDPHeader;
 
 use kernel::ErrorCode;
@@ -303,6 +305,16 @@ impl IP6Header {
                 }
                 Ok(())
             }
+            ip6_nh::TCP => {
+                let checksum = match TCPHeader::decode(buf).done() {
+                    Some((_offset, hdr)) => compute_tcp_checksum(self, &hdr, &buf[TCP_HDR_LEN..]),
+                    None => 0xffff, //Will be dropped, as ones comp -0 checksum is invalid
+                };
+                if checksum != 0 {
+                    return Err(ErrorCode::FAIL); //Incorrect cksum
+                }
+                Ok(())
+            }
             _ => Err(ErrorCode::NOSUPPORT),
         }
     }
@@ -374,7 +386,12 @@ impl<'a> IPPayload<'a> {
                 self.header = transport_header;
                 (ip6_nh::ICMP, length)
             }
-            _ => (ip6_nh::NO_NEXT, payload.len() as u16),
+            TransportHeader::TCP(mut tcp_header) => {
+                let length = (payload.len() + tcp_header.get_hdr_size()) as u16;
+                tcp_header.set_len(length);
+                self.header = TransportHeader::TCP(tcp_header);
+                (ip6_nh::TCP, length)
+            }
         }
     }
 
@@ -393,9 +410,7 @@ impl<'a> IPPayload<'a> {
         let (offset, _) = match self.header {
             TransportHeader::UDP(udp_header) => udp_header.encode(buf, offset).done().unwrap(),
             TransportHeader::ICMP(icmp_header) => icmp_header.encode(buf, offset).done().unwrap(),
-            _ => {
-                unimplemented!();
-            }
+            TransportHeader::TCP(tcp_header) => tcp_header.encode(buf, offset).done().unwrap(),
         };
         let payload_length = self.get_payload_length();
         let offset = enc_consume!(buf, offset; encode_bytes, &self.payload[..payload_length]);
@@ -410,8 +425,8 @@ impl<'a> IPPayload<'a> {
             TransportHeader::ICMP(icmp_header) => {
                 icmp_header.get_len() as usize - icmp_header.get_hdr_size()
             }
-            _ => {
-                unimplemented!();
+            TransportHeader::TCP(tcp_header) => {
+                tcp_header.get_len() as usize - tcp_header.get_hdr_size()
             }
         }
     }
@@ -459,7 +474,7 @@ impl<'a> IP6Packet<'a> {
         let transport_hdr_size = match self.payload.header {
             TransportHeader::UDP(udp_hdr) => udp_hdr.get_hdr_size(),
             TransportHeader::ICMP(icmp_header) => icmp_header.get_hdr_size(),
-            _ => unimplemented!(),
+            TransportHeader::TCP(tcp_header) => tcp_header.get_hdr_size(),
         };
         40 + transport_hdr_size
     }
@@ -485,8 +500,10 @@ impl<'a> IP6Packet<'a> {
                 let cksum = compute_icmp_checksum(&self.header, icmp_header, self.payload.payload);
                 icmp_header.set_cksum(cksum);
             }
-            _ => {
-                unimplemented!();
+            TransportHeader::TCP(ref mut tcp_header) => {
+                tcp_header.set_cksum(0);
+                let cksum = compute_tcp_checksum(&self.header, tcp_header, self.payload.payload);
+                tcp_header.set_cksum(cksum);
             }
         }
     }
@@ -22,12 +22,12 @@
 // interface.
 
 use crate::ieee802154::device::{MacDevice, TxClient};
-use crate::net::ieee802154::MacAddress;
+use crate::net::ieee802154::{KeyId, MacAddress, SecurityLevel};
 use crate::net::ipv6::ip_utils::IPAddr;
 use crate::net::ipv6::{IP6Header, IP6Packet, TransportHeader};
 use crate::net::network_capabilities::{IpVisibilityCapability, NetworkCapability};
 use crate::net::sixlowpan::sixlowpan_state::TxState;
-use crate::net::thread::thread_utils::{MULTICAST_IPV6, mac_from_ipv6};
+use crate::net::thread::thread_utils::{MULTICAST_IPV6, THREAD_PORT_NUMBER, mac_from_ipv6};
 
 use core::cell::Cell;
 
@@ -74,6 +74,13 @@ pub trait IP6Sender<'a> {
     /// `gateway` - MAC address to send the constructed packet to
     fn set_gateway(&self, gateway: MacAddress);
 
+    /// This method sets the link-layer security applied to the frames of
+    /// subsequently sent packets, or disables it if `security` is `None`.
+    ///
+    /// # Arguments
+    /// `security` - Security level and key identifier for the MAC frames
+    fn set_link_security(&self, security: Option<(SecurityLevel, KeyId)>);
+
     /// This method sets the `IP6Header` for the `IP6Sender` instance
     ///
     /// # Arguments
@@ -110,8 +117,8 @@ pub struct IP6SendStruct<'a, A: time::Alarm<'a>> {
     tx_buf: TakeCell<'static, [u8]>,
     sixlowpan: TxState<'a>,
     radio: &'a dyn MacDevice<'a>,
-    dst_mac_addr: MacAddress,
     src_mac_addr: MacAddress,
+    link_security: Cell<Option<(SecurityLevel, KeyId)>>,
     client: OptionalCell<&'a dyn IP6SendClient>,
     ip_vis: &'static IpVisibilityCapability,
 }
@@ -129,6 +136,10 @@ impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
         self.gateway.set(gateway);
     }
 
+    fn set_link_security(&self, security: Option<(SecurityLevel, KeyId)>) {
+        self.link_security.set(security);
+    }
+
     fn set_header(&mut self, ip6_header: IP6Header) {
         self.ip6_packet
             .map(|ip6_packet| ip6_packet.header = ip6_header);
@@ -158,13 +169,24 @@ impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
             // helper function to determine ipv6 to send to
             MacAddress::Long(mac_from_ipv6(dst))
         } else {
-            self.dst_mac_addr
+            self.gateway.get()
+        };
+
+        // Thread MLE messages are secured by MLE itself and must be sent
+        // without link-layer security, as they are used to establish the
+        // link keys in the first place (Thread Spec v1.3.0 sect. 4.10).
+        let security = match &transport_header {
+            TransportHeader::UDP(header) if header.get_dst_port() == THREAD_PORT_NUMBER => None,
+            _ => self.link_security.get(),
         };
 
         // TODO: add error handling here
-        let _ = self
-            .sixlowpan
-            .init(self.src_mac_addr, dst_mac_addr, self.radio.get_pan(), None);
+        let _ = self.sixlowpan.init(
+            self.src_mac_addr,
+            dst_mac_addr,
+            self.radio.get_pan(),
+            security,
+        );
 
         self.init_packet(dst, transport_header, payload);
 
@@ -191,8 +213,8 @@ impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
             tx_buf: TakeCell::new(tx_buf),
             sixlowpan,
             radio,
-            dst_mac_addr,
             src_mac_addr,
+            link_security: Cell::new(None),
             client: OptionalCell::empty(),
             ip_vis,
         }
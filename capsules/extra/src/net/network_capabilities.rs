@@ -5,8 +5,8 @@
 //! Capabilities for specifying capsule access to network resources
 //!
 //! A network capability specifies (1) with what IP addresses the holder of the
-//! capability may communicate, (2) from which UDP ports the holder may send,
-//! and (3) to which UDP ports the holder may send. In order to express various
+//! capability may communicate, (2) from which UDP or TCP ports the holder may
+//! send, and (3) to which UDP or TCP ports the holder may send. In order to express various
 //! ranges of IP addresses, one uses the AddrRange enum. One specifies ranges of
 //! ports using the PortRange enum.
 //!
@@ -14,7 +14,8 @@
 //! code (i.e. code that must use the unsafe keyword) since the constructor of
 //! a network capability requires the NetworkCapabilityCreationCapability capability. Code that
 //! checks these capabilities must possess the appropriate visibilty privileges.
-//! UDP visibility privileges are given through the UdpVisibilityCapability capability and IP
+//! UDP visibility privileges are given through the UdpVisibilityCapability capability, TCP
+//! visibility privileges through the TcpVisibilityCapability capability, and IP
 //! visibility privileges are given through the IpVisibilityCapability capability.
 //!
 //! An example of the visibility capabilities can be found in udp_port_table.rs.
@@ -88,6 +89,11 @@ pub struct UdpVisibilityCapability {
     _priv: (), // an empty private field
 }
 
+/// TCP visiblity capability.
+pub struct TcpVisibilityCapability {
+    _priv: (), // an empty private field
+}
+
 /// IP visiblity capability.
 pub struct IpVisibilityCapability {
     _priv: (), // an empty private field
@@ -101,6 +107,14 @@ impl UdpVisibilityCapability {
     }
 }
 
+impl TcpVisibilityCapability {
+    pub fn new(
+        _create_net_cap: &dyn NetworkCapabilityCreationCapability,
+    ) -> TcpVisibilityCapability {
+        TcpVisibilityCapability { _priv: () }
+    }
+}
+
 impl IpVisibilityCapability {
     pub fn new(
         _create_net_cap: &dyn NetworkCapabilityCreationCapability,
@@ -109,11 +123,12 @@ impl IpVisibilityCapability {
     }
 }
 
-/// Specifies access to network resourcess across the UDP and IP
+/// Specifies access to network resourcess across the UDP, TCP and IP
 /// layers.
 ///
-/// Access to layer-specific information is mediated by the
-/// UdpVsibilityCapability and the IpVisibilityCapability.
+/// The same port ranges govern both UDP and TCP. Access to layer-specific
+/// information is mediated by the UdpVsibilityCapability, the
+/// TcpVisibilityCapability and the IpVisibilityCapability.
 pub struct NetworkCapability {
     // can potentially add more
     remote_addrs: AddrRange, // IP addresses with which the holder may communicate
@@ -170,4 +185,20 @@ impl NetworkCapability {
     ) -> bool {
         self.local_ports.is_port_valid(local_port)
     }
+
+    pub fn remote_tcp_port_valid(
+        &self,
+        remote_port: u16,
+        _tcp_cap: &'static TcpVisibilityCapability,
+    ) -> bool {
+        self.remote_ports.is_port_valid(remote_port)
+    }
+
+    pub fn local_tcp_port_valid(
+        &self,
+        local_port: u16,
+        _tcp_cap: &'static TcpVisibilityCapability,
+    ) -> bool {
+        self.local_ports.is_port_valid(local_port)
+    }
 }
@@ -0,0 +1,459 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! TCP userspace interface.
+//!
+//! Implements a userspace interface for TCP connections. The driver owns a
+//! fixed pool of `TCPSocket`s; each process may hold one of them at a time.
+//! A process binds its socket to a local port, then either listens for an
+//! incoming connection or connects to a remote endpoint, and exchanges data
+//! through allowed buffers.
+//!
+//! Received data is appended to the process's read buffer, and the free space
+//! left in that buffer is advertised to the peer as the TCP receive window.
+//! The process acknowledges that it has processed data with the `consume`
+//! command, which opens the window again.
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::tcp::tcp_mux::{TCPClient, TCPSocket};
+use crate::net::util::host_slice_to_u16;
+
+use core::mem::size_of;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::MapCell;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Tcp as usize;
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// The connection is established. Called with the remote port; the
+    /// remote endpoint is also written into the config buffer.
+    pub const CONNECTED: usize = 0;
+    /// Data was appended to the read buffer. Called with the total number of
+    /// unconsumed bytes in the read buffer.
+    pub const RECEIVED: usize = 1;
+    /// A transmission completed. Called with a status code and the number of
+    /// bytes which were acknowledged by the peer.
+    pub const SENT: usize = 2;
+    /// The peer closed its side of the connection.
+    pub const REMOTE_CLOSED: usize = 3;
+    /// The connection is closed. Called with a status code which is `SUCCESS`
+    /// for an orderly close.
+    pub const CLOSED: usize = 4;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 5;
+}
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Write buffer. Contains the data to be transmitted.
+    pub const WRITE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Read buffer. Received data is appended to it.
+    pub const READ: usize = 0;
+    /// Config buffer. Holds the remote endpoint (16 byte address followed by
+    /// a 2 byte port) for `connect`, and receives the remote endpoint once a
+    /// connection is established.
+    pub const CFG: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+const ENDPOINT_LEN: usize = size_of::<IPAddr>() + size_of::<u16>();
+
+/// A socket available to processes, together with the kernel buffer that
+/// holds its outgoing data until the peer acknowledges it.
+pub struct TCPDriverSocket<'a> {
+    socket: &'a dyn TCPSocket<'a>,
+    tx_buffer: MapCell<SubSliceMut<'static, u8>>,
+    /// Capacity of `tx_buffer`, which is lent out while a send is pending.
+    tx_len: usize,
+}
+
+impl<'a> TCPDriverSocket<'a> {
+    pub fn new(socket: &'a dyn TCPSocket<'a>, tx_buffer: &'static mut [u8]) -> Self {
+        TCPDriverSocket {
+            socket,
+            tx_len: tx_buffer.len(),
+            tx_buffer: MapCell::new(SubSliceMut::new(tx_buffer)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Index of the socket owned by this process.
+    socket: Option<usize>,
+    /// Number of unconsumed bytes at the start of the read buffer.
+    rx_len: usize,
+}
+
+pub struct TCPDriver<'a> {
+    sockets: &'a [TCPDriverSocket<'a>],
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    net_cap: &'static NetworkCapability,
+}
+
+impl<'a> TCPDriver<'a> {
+    pub fn new(
+        sockets: &'a [TCPDriverSocket<'a>],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        net_cap: &'static NetworkCapability,
+    ) -> TCPDriver<'a> {
+        TCPDriver {
+            sockets,
+            apps: grant,
+            net_cap,
+        }
+    }
+
+    /// Registers the driver as the client of all of its sockets. Must be
+    /// called once the driver has been placed at its static address.
+    pub fn init(&'a self) {
+        for (id, slot) in self.sockets.iter().enumerate() {
+            slot.socket.set_client(self, id);
+        }
+    }
+
+    /// Returns the process owning socket `id`, if any.
+    fn owner(&self, id: usize) -> Option<ProcessId> {
+        self.apps.iter().find_map(|app| {
+            let processid = app.processid();
+            app.enter(|app, _| app.socket == Some(id))
+                .then_some(processid)
+        })
+    }
+
+    /// Returns the socket owned by `processid`.
+    fn socket_of(&self, processid: ProcessId) -> Result<&TCPDriverSocket<'a>, ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| app.socket)
+            .map_err(ErrorCode::from)?
+            .map(|id| &self.sockets[id])
+            .ok_or(ErrorCode::RESERVE)
+    }
+
+    /// Assigns a free socket to `processid` and binds it to `port`. Sockets
+    /// left behind by processes that no longer exist are reclaimed.
+    fn open(&self, processid: ProcessId, port: u16) -> Result<u16, ErrorCode> {
+        if self.socket_of(processid).is_ok() {
+            return Err(ErrorCode::ALREADY);
+        }
+        let id = (0..self.sockets.len())
+            .find(|id| self.owner(*id).is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        let socket = self.sockets[id].socket;
+        socket.abort();
+        let _ = socket.unbind();
+        let port = socket.bind(port, self.net_cap)?;
+        self.apps
+            .enter(processid, |app, _| {
+                app.socket = Some(id);
+                app.rx_len = 0;
+            })
+            .map_err(ErrorCode::from)?;
+        Ok(port)
+    }
+
+    fn release(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let socket = self.socket_of(processid)?.socket;
+        self.apps
+            .enter(processid, |app, _| app.socket = None)
+            .map_err(ErrorCode::from)?;
+        socket.abort();
+        socket.unbind()
+    }
+
+    fn connect(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let socket = self.socket_of(processid)?.socket;
+        let (addr, port) = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::CFG)
+                    .and_then(|cfg| {
+                        cfg.enter(|cfg| {
+                            if cfg.len() != ENDPOINT_LEN {
+                                return None;
+                            }
+                            let mut endpoint = [0; ENDPOINT_LEN];
+                            cfg.copy_to_slice(&mut endpoint);
+                            let mut addr = IPAddr::new();
+                            addr.0.copy_from_slice(&endpoint[..size_of::<IPAddr>()]);
+                            Some((addr, host_slice_to_u16(&endpoint[size_of::<IPAddr>()..])))
+                        })
+                    })
+                    .unwrap_or(None)
+            })
+            .map_err(ErrorCode::from)?
+            .ok_or(ErrorCode::INVAL)?;
+        self.apps
+            .enter(processid, |app, _| app.rx_len = 0)
+            .map_err(ErrorCode::from)?;
+        socket.connect(addr, port)
+    }
+
+    fn send(&self, processid: ProcessId, len: usize) -> Result<(), ErrorCode> {
+        let slot = self.socket_of(processid)?;
+        let mut buffer = slot.tx_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let result = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::WRITE)
+                    .and_then(|write| {
+                        write.enter(|payload| {
+                            if len == 0 || len > payload.len() {
+                                Err(ErrorCode::INVAL)
+                            } else if len > buffer.len() {
+                                Err(ErrorCode::SIZE)
+                            } else {
+                                payload[..len].copy_to_slice(&mut buffer[..len]);
+                                Ok(())
+                            }
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        if let Err(e) = result {
+            slot.tx_buffer.replace(buffer);
+            return Err(e);
+        }
+        buffer.slice(0..len);
+        slot.socket.send(buffer).map_err(|(e, mut buffer)| {
+            buffer.reset();
+            slot.tx_buffer.replace(buffer);
+            e
+        })
+    }
+
+    /// Drops the first `len` bytes of the read buffer, moving any remaining
+    /// data to its start, and advertises the larger window to the peer.
+    fn consume(&self, processid: ProcessId, len: usize) -> Result<(), ErrorCode> {
+        let socket = self.socket_of(processid)?.socket;
+        self.apps
+            .enter(processid, |app, kernel_data| {
+                let len = len.min(app.rx_len);
+                let remaining = app.rx_len - len;
+                if remaining > 0 {
+                    let _ = kernel_data
+                        .get_readwrite_processbuffer(rw_allow::READ)
+                        .and_then(|read| {
+                            read.mut_enter(|rbuf| {
+                                for i in 0..remaining {
+                                    rbuf[i].set(rbuf[len + i].get());
+                                }
+                            })
+                        });
+                }
+                app.rx_len = remaining;
+            })
+            .map_err(ErrorCode::from)?;
+        socket.window_update();
+        Ok(())
+    }
+}
+
+impl SyscallDriver for TCPDriver<'_> {
+    /// TCP control
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Open a socket and bind it to the local port in `arg1`, or to a
+    ///   free ephemeral port if `arg1` is 0. Returns the bound port. Returns
+    ///   ALREADY if the process already has a socket, NOMEM if no socket is
+    ///   free, BUSY if the port is in use and INVAL if the port is not
+    ///   permitted by the driver's network capability.
+    /// - `2`: Listen for an incoming connection on the bound port.
+    /// - `3`: Connect to the remote endpoint in the config buffer.
+    /// - `4`: Transmit the first `arg1` bytes of the write buffer. Returns BUSY
+    ///   if a previous transmission has not been acknowledged yet, SIZE if
+    ///   `arg1` exceeds the kernel buffer, and OFF if the connection is not
+    ///   established.
+    /// - `5`: Close the connection once all queued data has been sent.
+    /// - `6`: Abort the connection, sending a reset to the peer.
+    /// - `7`: Abort any connection and release the socket and its port.
+    /// - `8`: Mark the first `arg1` bytes of the read buffer as consumed.
+    /// - `9`: Get the connection state, as the numeric value of `TcpState`.
+    /// - `10`: Get the maximum number of bytes a single transmission can
+    ///   carry.
+    ///
+    /// Commands 2 to 9 return RESERVE if the process has not opened a socket.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let result = match command_num {
+            0 => Ok(()),
+            1 => {
+                if arg1 > u16::MAX as usize {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                return self
+                    .open(processid, arg1 as u16)
+                    .map_or_else(CommandReturn::failure, |port| {
+                        CommandReturn::success_u32(port as u32)
+                    });
+            }
+            2 => self
+                .socket_of(processid)
+                .and_then(|slot| slot.socket.listen()),
+            3 => self.connect(processid),
+            4 => self.send(processid, arg1),
+            5 => self
+                .socket_of(processid)
+                .and_then(|slot| slot.socket.close()),
+            6 => self.socket_of(processid).map(|slot| slot.socket.abort()),
+            7 => self.release(processid),
+            8 => self.consume(processid, arg1),
+            9 => {
+                return self
+                    .socket_of(processid)
+                    .map_or_else(CommandReturn::failure, |slot| {
+                        CommandReturn::success_u32(slot.socket.get_state() as u32)
+                    });
+            }
+            10 => {
+                // Sockets may have differently sized buffers, so report the
+                // size every socket can hold.
+                let len = self.sockets.iter().map(|slot| slot.tx_len).min();
+                return CommandReturn::success_u32(len.unwrap_or(0) as u32);
+            }
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+        CommandReturn::from(result)
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+impl TCPClient for TCPDriver<'_> {
+    fn connected(&self, id: usize, remote_addr: IPAddr, remote_port: u16) {
+        self.owner(id).map(|processid| {
+            let _ = self.apps.enter(processid, |app, kernel_data| {
+                app.rx_len = 0;
+                let _ = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::CFG)
+                    .and_then(|cfg| {
+                        cfg.mut_enter(|cfg| {
+                            if cfg.len() == ENDPOINT_LEN {
+                                let mut endpoint = [0; ENDPOINT_LEN];
+                                endpoint[..size_of::<IPAddr>()].copy_from_slice(&remote_addr.0);
+                                endpoint[size_of::<IPAddr>()..]
+                                    .copy_from_slice(&remote_port.to_ne_bytes());
+                                cfg.copy_from_slice(&endpoint);
+                            }
+                        })
+                    });
+                let _ =
+                    kernel_data.schedule_upcall(upcall::CONNECTED, (remote_port as usize, 0, 0));
+            });
+        });
+    }
+
+    fn received(&self, id: usize, payload: &[u8]) -> usize {
+        self.owner(id).map_or(0, |processid| {
+            self.apps
+                .enter(processid, |app, kernel_data| {
+                    let copied = kernel_data
+                        .get_readwrite_processbuffer(rw_allow::READ)
+                        .and_then(|read| {
+                            read.mut_enter(|rbuf| {
+                                let space = rbuf.len().saturating_sub(app.rx_len);
+                                let len = payload.len().min(space);
+                                rbuf[app.rx_len..app.rx_len + len].copy_from_slice(&payload[..len]);
+                                len
+                            })
+                        })
+                        .unwrap_or(0);
+                    if copied > 0 {
+                        app.rx_len += copied;
+                        let _ = kernel_data.schedule_upcall(upcall::RECEIVED, (app.rx_len, 0, 0));
+                    }
+                    copied
+                })
+                .unwrap_or(0)
+        })
+    }
+
+    fn receive_window(&self, id: usize) -> usize {
+        self.owner(id).map_or(0, |processid| {
+            self.apps
+                .enter(processid, |app, kernel_data| {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::READ)
+                        .map_or(0, |read| read.len().saturating_sub(app.rx_len))
+                })
+                .unwrap_or(0)
+        })
+    }
+
+    fn remote_closed(&self, id: usize) {
+        self.owner(id).map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let _ = kernel_data.schedule_upcall(upcall::REMOTE_CLOSED, (0, 0, 0));
+            });
+        });
+    }
+
+    fn send_done(
+        &self,
+        id: usize,
+        result: Result<(), ErrorCode>,
+        mut buf: SubSliceMut<'static, u8>,
+    ) {
+        let len = buf.len();
+        buf.reset();
+        self.sockets[id].tx_buffer.replace(buf);
+        let acked = if result.is_ok() { len } else { 0 };
+        self.owner(id).map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let _ = kernel_data.schedule_upcall(
+                    upcall::SENT,
+                    (kernel::errorcode::into_statuscode(result), acked, 0),
+                );
+            });
+        });
+    }
+
+    fn closed(&self, id: usize, result: Result<(), ErrorCode>) {
+        self.owner(id).map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let _ = kernel_data.schedule_upcall(
+                    upcall::CLOSED,
+                    (kernel::errorcode::into_statuscode(result), 0, 0),
+                );
+            });
+        });
+    }
+}
@@ -0,0 +1,16 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+pub mod driver;
+pub mod tcp_mux;
+
+pub use self::driver::DRIVER_NUM;
+pub use self::driver::TCPDriver;
+
+// Reexport the exports of the [`tcp`] module, to avoid redundant
+// module paths (e.g. `capsules::net::tcp::tcp::TCPHeader`)
+mod tcp;
+pub use tcp::TCP_HDR_LEN;
+pub use tcp::TCPHeader;
+pub use tcp::tcp_flags;
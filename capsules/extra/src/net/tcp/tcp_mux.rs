@@ -0,0 +1,1443 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Definition and implementation of a minimal TCP transport.
+//!
+//! The [TCPSocket](trait.TCPSocket.html) trait provides an interface for
+//! kernel capsules to open, use and close a single TCP connection, and the
+//! [TCPClient](trait.TCPClient.html) trait is implemented by upper layers to
+//! receive connection, data and completion events.
+//!
+//! [MuxTcp](struct.MuxTcp.html) sits on an `IP6Sender` and is the
+//! `IP6RecvClient` for incoming TCP segments. It holds a list of
+//! `TCPSocketStruct`s, demultiplexes received segments to them, and
+//! serializes their outgoing segments, as the underlying IP sender can only
+//! transmit one packet at a time. Unlike the UDP stack, TCP must keep state
+//! per connection, so all protocol logic lives in the socket and the mux only
+//! owns the resources shared between sockets: the IP sender, a segment
+//! buffer and a virtual alarm.
+//!
+//! The implementation follows RFC 793 with a number of simplifications
+//! suited to small devices:
+//!
+//! - Options are neither sent nor interpreted. The maximum segment size is
+//!   the size of the segment buffer handed to the mux.
+//! - Only in-order segments are accepted. Out-of-order data is dropped and
+//!   answered with an ACK for the next expected sequence number, so the peer
+//!   retransmits.
+//! - Each socket has at most one send buffer outstanding. It is returned to
+//!   the client once every byte in it has been acknowledged.
+//! - Timers are driven by a coarse periodic tick which only runs while some
+//!   socket has a retransmission or TIME-WAIT timer pending. Lost segments
+//!   are retransmitted go-back-N style with exponential backoff, and the
+//!   connection is aborted after `MAX_RETRANSMISSIONS` timeouts.
+//! - A listening socket accepts a single connection, becoming that
+//!   connection. To accept another one, the client must call `listen` again
+//!   once the socket is closed.
+
+use crate::net::ipv6::IP6Header;
+use crate::net::ipv6::TransportHeader;
+use crate::net::ipv6::ip_utils::{IPAddr, ip6_nh};
+use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::network_capabilities::{NetworkCapability, TcpVisibilityCapability};
+use crate::net::tcp::{TCPHeader, tcp_flags};
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::ErrorCode;
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+
+/// Period of the timer tick, in milliseconds, while any timer is pending.
+pub const TCP_TICK_MS: u32 = 100;
+/// Retransmission timeout used for a fresh connection.
+const INITIAL_RTO_MS: u32 = 1000;
+/// Upper bound for the exponentially backed-off retransmission timeout.
+const MAX_RTO_MS: u32 = 16000;
+/// Number of consecutive retransmission timeouts before giving up.
+const MAX_RETRANSMISSIONS: u8 = 6;
+/// Time spent in TIME-WAIT. Much shorter than the 2 MSL of RFC 793 to free
+/// up sockets quickly on constrained devices.
+const TIME_WAIT_MS: u32 = 2000;
+/// First port handed out when binding to port 0.
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// States of a TCP connection, as defined by RFC 793.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TcpState {
+    Closed = 0,
+    Listen = 1,
+    SynSent = 2,
+    SynReceived = 3,
+    Established = 4,
+    FinWait1 = 5,
+    FinWait2 = 6,
+    CloseWait = 7,
+    Closing = 8,
+    LastAck = 9,
+    TimeWait = 10,
+}
+
+/// Returns true if sequence number `a` is before `b`, modulo 2^32.
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// Returns true if sequence number `a` is before or equal to `b`.
+fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+/// Callbacks delivered to the user of a `TCPSocket`.
+///
+/// Each callback carries the `id` passed to `TCPSocket::set_client`, so that
+/// a single client can serve several sockets.
+pub trait TCPClient {
+    /// The connection is established, either because a `connect` completed
+    /// or because a listening socket accepted a connection from `remote_addr`
+    /// and `remote_port`.
+    fn connected(&self, id: usize, remote_addr: IPAddr, remote_port: u16);
+
+    /// In-order data has been received. The client returns how many bytes
+    /// of `payload` it consumed; only those are acknowledged, and the peer
+    /// retransmits the rest later. `payload` is never longer than the last
+    /// value returned by `receive_window`.
+    fn received(&self, id: usize, payload: &[u8]) -> usize;
+
+    /// The number of bytes the client is currently able to accept. This is
+    /// advertised to the peer as the receive window.
+    fn receive_window(&self, id: usize) -> usize;
+
+    /// The peer closed its direction of the connection (a FIN was received).
+    /// No further data will arrive, but data can still be sent until the
+    /// client calls `close`.
+    fn remote_closed(&self, id: usize);
+
+    /// Every byte of `buf` has been acknowledged by the peer, or the
+    /// connection failed before that happened.
+    fn send_done(&self, id: usize, result: Result<(), ErrorCode>, buf: SubSliceMut<'static, u8>);
+
+    /// The connection is over and the socket is back in the `Closed` state.
+    /// `result` is `Ok(())` for an orderly close, `Err(ErrorCode::FAIL)` if
+    /// the peer reset the connection, `Err(ErrorCode::NOACK)` if the peer
+    /// stopped responding and `Err(ErrorCode::CANCEL)` if the connection was
+    /// aborted locally.
+    fn closed(&self, id: usize, result: Result<(), ErrorCode>);
+}
+
+/// Interface to a single TCP connection.
+pub trait TCPSocket<'a> {
+    /// Sets the client of this socket and the `id` passed back in callbacks.
+    fn set_client(&self, client: &'a dyn TCPClient, id: usize);
+
+    /// Returns the current state of the connection.
+    fn get_state(&self) -> TcpState;
+
+    /// Binds the socket to local `port`, or to a free ephemeral port if
+    /// `port` is 0. `net_cap` must permit the local port, and is used to
+    /// check the remote endpoint of any later connection.
+    ///
+    /// Returns the bound port, `BUSY` if the port is used by another socket,
+    /// `INVAL` if `net_cap` does not allow it and `ALREADY` if the socket is
+    /// already bound.
+    fn bind(&self, port: u16, net_cap: &'static NetworkCapability) -> Result<u16, ErrorCode>;
+
+    /// Releases the local port of a closed socket.
+    fn unbind(&self) -> Result<(), ErrorCode>;
+
+    /// Returns the bound local port, or 0 if the socket is not bound.
+    fn get_local_port(&self) -> u16;
+
+    /// Returns the address and port of the remote endpoint.
+    fn get_remote(&self) -> (IPAddr, u16);
+
+    /// Passive open: waits for a connection on the bound port.
+    fn listen(&self) -> Result<(), ErrorCode>;
+
+    /// Active open: connects to `addr` and `port`. Completion is signalled
+    /// through `TCPClient::connected` or `TCPClient::closed`.
+    fn connect(&self, addr: IPAddr, port: u16) -> Result<(), ErrorCode>;
+
+    /// Queues `buf` for transmission. Only one buffer may be outstanding; it
+    /// is returned through `TCPClient::send_done`.
+    fn send(
+        &self,
+        buf: SubSliceMut<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)>;
+
+    /// Orderly close: sends a FIN once all queued data has been sent.
+    /// `TCPClient::closed` is called when the connection is fully closed. A
+    /// listening or still connecting socket is closed immediately, without a
+    /// callback.
+    fn close(&self) -> Result<(), ErrorCode>;
+
+    /// Resets the connection immediately, returning any outstanding send
+    /// buffer.
+    fn abort(&self);
+
+    /// Tells the socket that the client's receive window has grown, so that
+    /// the new window is advertised to the peer.
+    fn window_update(&self);
+}
+
+/// Multiplexes TCP sockets over a single `IP6Sender`.
+pub struct MuxTcp<'a, A: time::Alarm<'a>> {
+    sockets: List<'a, TCPSocketStruct<'a, A>>,
+    ip_sender: &'a dyn IP6Sender<'a>,
+    alarm: &'a A,
+    /// Segment payload buffer; its size is the maximum segment size.
+    tx_buffer: MapCell<SubSliceMut<'static, u8>>,
+    /// Whether a segment is currently being sent by the IP layer.
+    sending: Cell<bool>,
+    /// A reset queued in response to a segment no socket could accept.
+    pending_reset: OptionalCell<(IPAddr, TCPHeader)>,
+    /// Capability used when sending resets on behalf of no socket.
+    net_cap: &'static NetworkCapability,
+    tcp_vis: &'static TcpVisibilityCapability,
+    iss_seed: Cell<u32>,
+    next_ephemeral_port: Cell<u16>,
+}
+
+impl<'a, A: time::Alarm<'a>> MuxTcp<'a, A> {
+    pub fn new(
+        ip_sender: &'a dyn IP6Sender<'a>,
+        alarm: &'a A,
+        tx_buffer: SubSliceMut<'static, u8>,
+        net_cap: &'static NetworkCapability,
+        tcp_vis: &'static TcpVisibilityCapability,
+    ) -> MuxTcp<'a, A> {
+        MuxTcp {
+            sockets: List::new(),
+            ip_sender,
+            alarm,
+            tx_buffer: MapCell::new(tx_buffer),
+            sending: Cell::new(false),
+            pending_reset: OptionalCell::empty(),
+            net_cap,
+            tcp_vis,
+            iss_seed: Cell::new(0),
+            next_ephemeral_port: Cell::new(EPHEMERAL_PORT_START),
+        }
+    }
+
+    pub fn add_socket(&self, socket: &'a TCPSocketStruct<'a, A>) {
+        self.sockets.push_tail(socket);
+    }
+
+    fn port_in_use(&self, port: u16) -> bool {
+        self.sockets.iter().any(|s| s.local_port.get() == port)
+    }
+
+    fn ephemeral_port(&self, net_cap: &'static NetworkCapability) -> Option<u16> {
+        for _ in EPHEMERAL_PORT_START..=u16::MAX {
+            let port = self.next_ephemeral_port.get();
+            self.next_ephemeral_port
+                .set(port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START));
+            if !self.port_in_use(port) && net_cap.local_tcp_port_valid(port, self.tcp_vis) {
+                return Some(port);
+            }
+        }
+        None
+    }
+
+    /// Generates an initial sequence number. There is no entropy source
+    /// available here, so this mixes the current time into a simple LCG.
+    fn next_iss(&self) -> u32 {
+        let iss = self
+            .iss_seed
+            .get()
+            .wrapping_mul(1103515245)
+            .wrapping_add(12345)
+            ^ self.alarm.now().into_u32();
+        self.iss_seed.set(iss);
+        iss
+    }
+
+    /// Queues a reset in reply to segment `hdr` received from `src_addr`,
+    /// as described in the "Reset Generation" section of RFC 793.
+    fn queue_reset(&self, src_addr: IPAddr, hdr: &TCPHeader) {
+        if hdr.has_flags(tcp_flags::RST) || self.pending_reset.is_some() {
+            return;
+        }
+        let mut reset = TCPHeader::new();
+        reset.set_src_port(hdr.get_dst_port());
+        reset.set_dst_port(hdr.get_src_port());
+        if hdr.has_flags(tcp_flags::ACK) {
+            reset.set_seq_num(hdr.get_ack_num());
+            reset.set_flags(tcp_flags::RST);
+        } else {
+            reset.set_ack_num(hdr.get_seq_num().wrapping_add(hdr.get_seq_len()));
+            reset.set_flags(tcp_flags::RST | tcp_flags::ACK);
+        }
+        self.pending_reset.set((src_addr, reset));
+    }
+
+    fn start_tick(&self) {
+        if !self.alarm.is_armed() {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TCP_TICK_MS));
+        }
+    }
+
+    /// Sends segments until the IP layer is busy or nothing is left to send.
+    fn do_output(&self) {
+        while !self.sending.get() {
+            let sent = self.tx_buffer.take().is_some_and(|mut buf| {
+                buf.reset();
+                let next = match self.pending_reset.take() {
+                    Some((dst, reset)) => {
+                        buf.slice(0..0);
+                        Some((dst, reset, self.net_cap))
+                    }
+                    None => self
+                        .sockets
+                        .iter()
+                        .filter(|s| s.wants_output())
+                        .find_map(|s| s.output(&mut buf)),
+                };
+                let sent = next.is_some_and(|(dst, hdr, net_cap)| {
+                    // The IP layer may complete synchronously, so mark the
+                    // mux busy before handing the segment over.
+                    self.sending.set(true);
+                    let result =
+                        self.ip_sender
+                            .send_to(dst, TransportHeader::TCP(hdr), &buf, net_cap);
+                    if result.is_err() {
+                        // Lost segments are recovered by retransmission.
+                        self.sending.set(false);
+                    }
+                    result.is_ok()
+                });
+                self.tx_buffer.replace(buf);
+                sent
+            });
+            if !sent {
+                break;
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6SendClient for MuxTcp<'a, A> {
+    fn send_done(&self, _result: Result<(), ErrorCode>) {
+        self.sending.set(false);
+        self.do_output();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6RecvClient for MuxTcp<'a, A> {
+    fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        if ip_header.get_next_header() != ip6_nh::TCP {
+            return;
+        }
+        if let Some((offset, tcp_header)) = TCPHeader::decode(payload).done() {
+            let src_addr = ip_header.get_src_addr();
+            let data = &payload[offset..];
+            let socket = self
+                .sockets
+                .iter()
+                .find(|s| s.is_connection(src_addr, &tcp_header))
+                .or_else(|| {
+                    self.sockets.iter().find(|s| {
+                        s.state.get() == TcpState::Listen
+                            && s.local_port.get() == tcp_header.get_dst_port()
+                    })
+                });
+            match socket {
+                Some(socket) => socket.segment_arrives(src_addr, &tcp_header, data),
+                None => self.queue_reset(src_addr, &tcp_header),
+            }
+            self.do_output();
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for MuxTcp<'a, A> {
+    fn alarm(&self) {
+        let mut running = false;
+        for socket in self.sockets.iter() {
+            socket.tick(TCP_TICK_MS);
+            running |= socket.timer_ms.get() != 0;
+        }
+        if running {
+            self.start_tick();
+        }
+        self.do_output();
+    }
+}
+
+/// A single TCP connection managed by a `MuxTcp`.
+pub struct TCPSocketStruct<'a, A: time::Alarm<'a>> {
+    mux: &'a MuxTcp<'a, A>,
+    client: OptionalCell<&'a dyn TCPClient>,
+    client_id: Cell<usize>,
+    next: ListLink<'a, TCPSocketStruct<'a, A>>,
+    net_cap: OptionalCell<&'static NetworkCapability>,
+
+    state: Cell<TcpState>,
+    /// Local port, or 0 if unbound.
+    local_port: Cell<u16>,
+    remote_addr: Cell<IPAddr>,
+    remote_port: Cell<u16>,
+    /// Whether the connection was opened by `listen`.
+    passive: Cell<bool>,
+
+    // Send sequence variables
+    iss: Cell<u32>,
+    snd_una: Cell<u32>,
+    snd_nxt: Cell<u32>,
+    snd_wnd: Cell<u16>,
+    /// Data queued by the client, and the sequence number of its first byte.
+    tx_data: MapCell<SubSliceMut<'static, u8>>,
+    tx_seq: Cell<u32>,
+    /// Whether the client asked to close, and whether the FIN is in flight.
+    fin_pending: Cell<bool>,
+    fin_sent: Cell<bool>,
+
+    // Receive sequence variables
+    rcv_nxt: Cell<u32>,
+    ack_pending: Cell<bool>,
+
+    // Timer state. A non-zero `timer_ms` is the time left until the
+    // retransmission timeout or, in TIME-WAIT, until the socket closes.
+    timer_ms: Cell<u32>,
+    rto_ms: Cell<u32>,
+    retransmissions: Cell<u8>,
+}
+
+impl<'a, A: time::Alarm<'a>> ListNode<'a, TCPSocketStruct<'a, A>> for TCPSocketStruct<'a, A> {
+    fn next(&'a self) -> &'a ListLink<'a, TCPSocketStruct<'a, A>> {
+        &self.next
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> TCPSocketStruct<'a, A> {
+    pub fn new(mux: &'a MuxTcp<'a, A>) -> TCPSocketStruct<'a, A> {
+        TCPSocketStruct {
+            mux,
+            client: OptionalCell::empty(),
+            client_id: Cell::new(0),
+            next: ListLink::empty(),
+            net_cap: OptionalCell::empty(),
+            state: Cell::new(TcpState::Closed),
+            local_port: Cell::new(0),
+            remote_addr: Cell::new(IPAddr::new()),
+            remote_port: Cell::new(0),
+            passive: Cell::new(false),
+            iss: Cell::new(0),
+            snd_una: Cell::new(0),
+            snd_nxt: Cell::new(0),
+            snd_wnd: Cell::new(0),
+            tx_data: MapCell::empty(),
+            tx_seq: Cell::new(0),
+            fin_pending: Cell::new(false),
+            fin_sent: Cell::new(false),
+            rcv_nxt: Cell::new(0),
+            ack_pending: Cell::new(false),
+            timer_ms: Cell::new(0),
+            rto_ms: Cell::new(INITIAL_RTO_MS),
+            retransmissions: Cell::new(0),
+        }
+    }
+
+    /// Returns true if `hdr` from `src_addr` belongs to the connection held
+    /// by this socket.
+    fn is_connection(&self, src_addr: IPAddr, hdr: &TCPHeader) -> bool {
+        !matches!(self.state.get(), TcpState::Closed | TcpState::Listen)
+            && self.local_port.get() == hdr.get_dst_port()
+            && self.remote_port.get() == hdr.get_src_port()
+            && self.remote_addr.get() == src_addr
+    }
+
+    fn start_connection(&self) {
+        let iss = self.mux.next_iss();
+        self.iss.set(iss);
+        self.snd_una.set(iss);
+        self.snd_nxt.set(iss);
+        self.fin_pending.set(false);
+        self.fin_sent.set(false);
+        self.ack_pending.set(false);
+        self.rto_ms.set(INITIAL_RTO_MS);
+        self.retransmissions.set(0);
+    }
+
+    fn start_timer(&self, ms: u32) {
+        self.timer_ms.set(ms);
+        self.mux.start_tick();
+    }
+
+    fn stop_timer(&self) {
+        self.timer_ms.set(0);
+    }
+
+    fn advertised_window(&self) -> u16 {
+        self.client
+            .map_or(0, |client| client.receive_window(self.client_id.get()))
+            .min(u16::MAX as usize) as u16
+    }
+
+    /// Returns the number of bytes of queued data that have not been sent
+    /// yet, and their offset in the send buffer.
+    fn unsent_data(&self) -> (usize, usize) {
+        self.tx_data.map_or((0, 0), |data| {
+            let offset = self.snd_nxt.get().wrapping_sub(self.tx_seq.get()) as usize;
+            (offset, data.len().saturating_sub(offset))
+        })
+    }
+
+    /// Returns how many bytes of new data the peer's window allows. While
+    /// nothing is in flight a single byte is allowed into a zero window, so
+    /// that the retransmission timer probes for the window to reopen.
+    fn send_window(&self) -> usize {
+        let in_flight = self.snd_nxt.get().wrapping_sub(self.snd_una.get()) as usize;
+        if in_flight == 0 {
+            cmp::max(self.snd_wnd.get() as usize, 1)
+        } else {
+            (self.snd_wnd.get() as usize).saturating_sub(in_flight)
+        }
+    }
+
+    fn can_send_data(&self) -> bool {
+        matches!(
+            self.state.get(),
+            TcpState::Established | TcpState::CloseWait | TcpState::FinWait1 | TcpState::LastAck
+        )
+    }
+
+    fn wants_output(&self) -> bool {
+        match self.state.get() {
+            TcpState::Closed | TcpState::Listen => false,
+            TcpState::SynSent => self.snd_nxt.get() == self.snd_una.get(),
+            TcpState::SynReceived => {
+                self.snd_nxt.get() == self.snd_una.get() || self.ack_pending.get()
+            }
+            _ => {
+                let (_, unsent) = self.unsent_data();
+                self.ack_pending.get()
+                    || (self.can_send_data() && unsent > 0 && self.send_window() > 0)
+                    || (self.fin_pending.get() && !self.fin_sent.get() && unsent == 0)
+            }
+        }
+    }
+
+    /// Builds the next segment of this connection. The payload is copied
+    /// into `buf`, which is sliced to the payload length.
+    fn output(
+        &self,
+        buf: &mut SubSliceMut<'static, u8>,
+    ) -> Option<(IPAddr, TCPHeader, &'static NetworkCapability)> {
+        let net_cap = self.net_cap.get()?;
+        let mut hdr = TCPHeader::new();
+        hdr.set_src_port(self.local_port.get());
+        hdr.set_dst_port(self.remote_port.get());
+        hdr.set_window(self.advertised_window());
+        let mut len = 0;
+
+        match self.state.get() {
+            TcpState::Closed | TcpState::Listen => return None,
+            TcpState::SynSent | TcpState::SynReceived
+                if self.snd_nxt.get() == self.snd_una.get() =>
+            {
+                hdr.set_seq_num(self.iss.get());
+                if self.state.get() == TcpState::SynSent {
+                    hdr.set_flags(tcp_flags::SYN);
+                } else {
+                    hdr.set_ack_num(self.rcv_nxt.get());
+                    hdr.set_flags(tcp_flags::SYN | tcp_flags::ACK);
+                }
+                self.snd_nxt.set(self.iss.get().wrapping_add(1));
+            }
+            _ => {
+                let mut flags = tcp_flags::ACK;
+                hdr.set_seq_num(self.snd_nxt.get());
+                hdr.set_ack_num(self.rcv_nxt.get());
+
+                let (offset, unsent) = self.unsent_data();
+                if self.can_send_data() && unsent > 0 {
+                    len = cmp::min(cmp::min(unsent, buf.len()), self.send_window());
+                    if len > 0 {
+                        self.tx_data.map(|data| {
+                            buf[..len].copy_from_slice(&data[offset..offset + len]);
+                        });
+                        self.snd_nxt
+                            .set(self.snd_nxt.get().wrapping_add(len as u32));
+                        if len == unsent {
+                            flags |= tcp_flags::PSH;
+                        }
+                    }
+                }
+                if self.fin_pending.get() && !self.fin_sent.get() && len == unsent {
+                    flags |= tcp_flags::FIN;
+                    self.fin_sent.set(true);
+                    self.snd_nxt.set(self.snd_nxt.get().wrapping_add(1));
+                }
+                if len == 0 && flags & tcp_flags::FIN == 0 && !self.ack_pending.get() {
+                    return None;
+                }
+                hdr.set_flags(flags);
+            }
+        }
+
+        self.ack_pending.set(false);
+        if self.snd_nxt.get() != self.snd_una.get() && self.timer_ms.get() == 0 {
+            self.start_timer(self.rto_ms.get());
+        }
+        buf.slice(0..len);
+        Some((self.remote_addr.get(), hdr, net_cap))
+    }
+
+    /// Advances the timers of this socket by `elapsed` milliseconds.
+    fn tick(&self, elapsed: u32) {
+        let timer = self.timer_ms.get();
+        if timer == 0 {
+            return;
+        }
+        if timer > elapsed {
+            self.timer_ms.set(timer - elapsed);
+            return;
+        }
+        self.timer_ms.set(0);
+        if self.state.get() == TcpState::TimeWait {
+            self.finish(Ok(()));
+        } else if self.snd_nxt.get() != self.snd_una.get() {
+            self.retransmit_timeout();
+        }
+    }
+
+    fn retransmit_timeout(&self) {
+        let retransmissions = self.retransmissions.get() + 1;
+        if retransmissions > MAX_RETRANSMISSIONS {
+            self.finish(Err(ErrorCode::NOACK));
+            return;
+        }
+        self.retransmissions.set(retransmissions);
+        self.rto_ms
+            .set(cmp::min(self.rto_ms.get().saturating_mul(2), MAX_RTO_MS));
+        // Go back to the oldest unacknowledged byte; everything after it,
+        // including a FIN, is sent again.
+        self.snd_nxt.set(self.snd_una.get());
+        self.fin_sent.set(false);
+        self.start_timer(self.rto_ms.get());
+    }
+
+    /// Returns the socket to `Closed` and notifies the client.
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        self.state.set(TcpState::Closed);
+        self.stop_timer();
+        self.fin_pending.set(false);
+        self.fin_sent.set(false);
+        self.ack_pending.set(false);
+        let id = self.client_id.get();
+        if let Some(data) = self.tx_data.take() {
+            let send_result = result.and(Err(ErrorCode::FAIL));
+            self.client
+                .map(|client| client.send_done(id, send_result, data));
+        }
+        self.client.map(|client| client.closed(id, result));
+    }
+
+    fn enter_time_wait(&self) {
+        self.state.set(TcpState::TimeWait);
+        self.start_timer(TIME_WAIT_MS);
+    }
+
+    /// Handles a segment acknowledging new data, `snd_una < ack <= snd_nxt`.
+    fn process_ack(&self, ack: u32) {
+        self.snd_una.set(ack);
+        self.retransmissions.set(0);
+        self.rto_ms.set(INITIAL_RTO_MS);
+        if ack == self.snd_nxt.get() {
+            self.stop_timer();
+        } else {
+            self.start_timer(self.rto_ms.get());
+        }
+
+        let complete = self.tx_data.map_or(false, |data| {
+            seq_le(self.tx_seq.get().wrapping_add(data.len() as u32), ack)
+        });
+        if complete {
+            if let Some(data) = self.tx_data.take() {
+                let id = self.client_id.get();
+                self.client.map(|client| client.send_done(id, Ok(()), data));
+            }
+        }
+    }
+
+    fn segment_arrives(&self, src_addr: IPAddr, hdr: &TCPHeader, payload: &[u8]) {
+        let seq = hdr.get_seq_num();
+        let ack = hdr.get_ack_num();
+        match self.state.get() {
+            TcpState::Closed => {}
+            TcpState::Listen => {
+                if hdr.has_flags(tcp_flags::RST) {
+                    return;
+                }
+                if hdr.has_flags(tcp_flags::ACK) {
+                    self.mux.queue_reset(src_addr, hdr);
+                    return;
+                }
+                let remote_allowed = self.net_cap.map_or(false, |net_cap| {
+                    net_cap.remote_tcp_port_valid(hdr.get_src_port(), self.mux.tcp_vis)
+                });
+                if hdr.has_flags(tcp_flags::SYN) && remote_allowed {
+                    self.remote_addr.set(src_addr);
+                    self.remote_port.set(hdr.get_src_port());
+                    self.rcv_nxt.set(seq.wrapping_add(1));
+                    self.snd_wnd.set(hdr.get_window());
+                    self.start_connection();
+                    self.state.set(TcpState::SynReceived);
+                }
+            }
+            TcpState::SynSent => {
+                let ack_ok = ack == self.iss.get().wrapping_add(1);
+                if hdr.has_flags(tcp_flags::ACK) && !ack_ok {
+                    self.mux.queue_reset(src_addr, hdr);
+                    return;
+                }
+                if hdr.has_flags(tcp_flags::RST) {
+                    if hdr.has_flags(tcp_flags::ACK) {
+                        self.finish(Err(ErrorCode::FAIL));
+                    }
+                    return;
+                }
+                if hdr.has_flags(tcp_flags::SYN) {
+                    self.rcv_nxt.set(seq.wrapping_add(1));
+                    self.snd_wnd.set(hdr.get_window());
+                    self.ack_pending.set(true);
+                    if hdr.has_flags(tcp_flags::ACK) {
+                        self.snd_una.set(ack);
+                        self.stop_timer();
+                        self.retransmissions.set(0);
+                        self.state.set(TcpState::Established);
+                        let id = self.client_id.get();
+                        self.client
+                            .map(|client| client.connected(id, src_addr, hdr.get_src_port()));
+                    } else {
+                        // Simultaneous open: answer with a SYN-ACK.
+                        self.snd_nxt.set(self.snd_una.get());
+                        self.state.set(TcpState::SynReceived);
+                    }
+                }
+            }
+            _ => self.synchronized_segment_arrives(src_addr, hdr, payload),
+        }
+    }
+
+    /// Processes a segment in any state after the initial handshake has
+    /// started (SYN-RECEIVED and later).
+    fn synchronized_segment_arrives(&self, src_addr: IPAddr, hdr: &TCPHeader, payload: &[u8]) {
+        let seq = hdr.get_seq_num();
+        let ack = hdr.get_ack_num();
+        let id = self.client_id.get();
+
+        // Only segments starting exactly at the next expected sequence
+        // number are accepted. Anything else is answered with an ACK that
+        // tells the peer where to resume.
+        if seq != self.rcv_nxt.get() {
+            if !hdr.has_flags(tcp_flags::RST) {
+                self.ack_pending.set(true);
+            }
+            return;
+        }
+
+        if hdr.has_flags(tcp_flags::RST) {
+            if self.state.get() == TcpState::SynReceived && self.passive.get() {
+                self.stop_timer();
+                self.state.set(TcpState::Listen);
+            } else {
+                self.finish(Err(ErrorCode::FAIL));
+            }
+            return;
+        }
+
+        if hdr.has_flags(tcp_flags::SYN) {
+            self.mux.queue_reset(src_addr, hdr);
+            self.finish(Err(ErrorCode::FAIL));
+            return;
+        }
+
+        if !hdr.has_flags(tcp_flags::ACK) {
+            return;
+        }
+
+        if self.state.get() == TcpState::SynReceived {
+            if seq_lt(self.snd_una.get(), ack) && seq_le(ack, self.snd_nxt.get()) {
+                self.state.set(TcpState::Established);
+                self.client
+                    .map(|client| client.connected(id, src_addr, hdr.get_src_port()));
+            } else {
+                self.mux.queue_reset(src_addr, hdr);
+                return;
+            }
+        }
+
+        if seq_lt(self.snd_nxt.get(), ack) {
+            // Acknowledges something not yet sent.
+            self.ack_pending.set(true);
+            return;
+        }
+        if seq_lt(self.snd_una.get(), ack) {
+            self.process_ack(ack);
+        }
+        if seq_le(self.snd_una.get(), ack) {
+            self.snd_wnd.set(hdr.get_window());
+        }
+
+        let fin_acked = self.fin_sent.get() && ack == self.snd_nxt.get();
+        match self.state.get() {
+            TcpState::FinWait1 if fin_acked => self.state.set(TcpState::FinWait2),
+            TcpState::Closing if fin_acked => self.enter_time_wait(),
+            TcpState::LastAck if fin_acked => {
+                self.finish(Ok(()));
+                return;
+            }
+            _ => {}
+        }
+
+        if !payload.is_empty() {
+            if matches!(
+                self.state.get(),
+                TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+            ) {
+                let window = self.advertised_window() as usize;
+                let len = cmp::min(payload.len(), window);
+                let accepted = if len > 0 {
+                    self.client
+                        .map_or(0, |client| client.received(id, &payload[..len]))
+                        .min(len)
+                } else {
+                    0
+                };
+                self.rcv_nxt
+                    .set(self.rcv_nxt.get().wrapping_add(accepted as u32));
+                self.ack_pending.set(true);
+                if accepted < payload.len() {
+                    // Any FIN lies beyond what was accepted.
+                    return;
+                }
+            }
+        }
+
+        if hdr.has_flags(tcp_flags::FIN) {
+            self.rcv_nxt.set(self.rcv_nxt.get().wrapping_add(1));
+            self.ack_pending.set(true);
+            match self.state.get() {
+                TcpState::Established => {
+                    self.state.set(TcpState::CloseWait);
+                    self.client.map(|client| client.remote_closed(id));
+                }
+                TcpState::FinWait1 => {
+                    // The FIN was not acknowledged above, or the state would
+                    // be FIN-WAIT-2 by now.
+                    self.state.set(TcpState::Closing);
+                }
+                TcpState::FinWait2 => {
+                    self.enter_time_wait();
+                    self.client.map(|client| client.remote_closed(id));
+                }
+                TcpState::TimeWait => self.enter_time_wait(),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> TCPSocket<'a> for TCPSocketStruct<'a, A> {
+    fn set_client(&self, client: &'a dyn TCPClient, id: usize) {
+        self.client.set(client);
+        self.client_id.set(id);
+    }
+
+    fn get_state(&self) -> TcpState {
+        self.state.get()
+    }
+
+    fn bind(&self, port: u16, net_cap: &'static NetworkCapability) -> Result<u16, ErrorCode> {
+        if self.local_port.get() != 0 {
+            return Err(ErrorCode::ALREADY);
+        }
+        let port = if port == 0 {
+            self.mux.ephemeral_port(net_cap).ok_or(ErrorCode::NOMEM)?
+        } else if !net_cap.local_tcp_port_valid(port, self.mux.tcp_vis) {
+            return Err(ErrorCode::INVAL);
+        } else if self.mux.port_in_use(port) {
+            return Err(ErrorCode::BUSY);
+        } else {
+            port
+        };
+        self.local_port.set(port);
+        self.net_cap.set(net_cap);
+        Ok(port)
+    }
+
+    fn unbind(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != TcpState::Closed {
+            return Err(ErrorCode::BUSY);
+        }
+        self.local_port.set(0);
+        self.net_cap.clear();
+        Ok(())
+    }
+
+    fn get_local_port(&self) -> u16 {
+        self.local_port.get()
+    }
+
+    fn get_remote(&self) -> (IPAddr, u16) {
+        (self.remote_addr.get(), self.remote_port.get())
+    }
+
+    fn listen(&self) -> Result<(), ErrorCode> {
+        if self.local_port.get() == 0 {
+            return Err(ErrorCode::RESERVE);
+        }
+        match self.state.get() {
+            TcpState::Closed => {
+                self.passive.set(true);
+                self.state.set(TcpState::Listen);
+                Ok(())
+            }
+            TcpState::Listen => Err(ErrorCode::ALREADY),
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+
+    fn connect(&self, addr: IPAddr, port: u16) -> Result<(), ErrorCode> {
+        let net_cap = self.net_cap.get().ok_or(ErrorCode::RESERVE)?;
+        if !matches!(self.state.get(), TcpState::Closed | TcpState::Listen) {
+            return Err(ErrorCode::BUSY);
+        }
+        if port == 0 || !net_cap.remote_tcp_port_valid(port, self.mux.tcp_vis) {
+            return Err(ErrorCode::INVAL);
+        }
+        self.remote_addr.set(addr);
+        self.remote_port.set(port);
+        self.passive.set(false);
+        self.snd_wnd.set(0);
+        self.start_connection();
+        self.state.set(TcpState::SynSent);
+        self.mux.do_output();
+        Ok(())
+    }
+
+    fn send(
+        &self,
+        buf: SubSliceMut<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if !matches!(
+            self.state.get(),
+            TcpState::Established | TcpState::CloseWait
+        ) {
+            return Err((ErrorCode::OFF, buf));
+        }
+        if self.tx_data.is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        if buf.len() == 0 {
+            return Err((ErrorCode::SIZE, buf));
+        }
+        self.tx_seq.set(self.snd_nxt.get());
+        self.tx_data.replace(buf);
+        self.mux.do_output();
+        Ok(())
+    }
+
+    fn close(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            TcpState::Closed => Err(ErrorCode::ALREADY),
+            TcpState::Listen | TcpState::SynSent => {
+                self.stop_timer();
+                self.state.set(TcpState::Closed);
+                Ok(())
+            }
+            TcpState::SynReceived => {
+                self.abort();
+                Ok(())
+            }
+            TcpState::Established => {
+                self.fin_pending.set(true);
+                self.state.set(TcpState::FinWait1);
+                self.mux.do_output();
+                Ok(())
+            }
+            TcpState::CloseWait => {
+                self.fin_pending.set(true);
+                self.state.set(TcpState::LastAck);
+                self.mux.do_output();
+                Ok(())
+            }
+            _ => Err(ErrorCode::ALREADY),
+        }
+    }
+
+    fn abort(&self) {
+        match self.state.get() {
+            TcpState::Closed => {}
+            TcpState::Listen | TcpState::SynSent => {
+                self.stop_timer();
+                self.state.set(TcpState::Closed);
+            }
+            _ => {
+                let mut reset = TCPHeader::new();
+                reset.set_src_port(self.local_port.get());
+                reset.set_dst_port(self.remote_port.get());
+                reset.set_seq_num(self.snd_nxt.get());
+                reset.set_flags(tcp_flags::RST);
+                if self.mux.pending_reset.is_none() {
+                    self.mux.pending_reset.set((self.remote_addr.get(), reset));
+                }
+                self.finish(Err(ErrorCode::CANCEL));
+                self.mux.do_output();
+            }
+        }
+    }
+
+    fn window_update(&self) {
+        if matches!(
+            self.state.get(),
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+        ) {
+            self.ack_pending.set(true);
+            self.mux.do_output();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::{Cell, RefCell};
+    use std::vec;
+    use std::vec::Vec;
+
+    use capsules_test_harness::alarm::MockAlarm;
+    use capsules_test_harness::{leak, network_capability_creation, static_buf};
+    use kernel::hil::time::Alarm;
+
+    use crate::net::ieee802154::{KeyId, MacAddress, SecurityLevel};
+    use crate::net::network_capabilities::{AddrRange, PortRange};
+    use crate::net::tcp::TCP_HDR_LEN;
+
+    use super::*;
+
+    const LOCAL_PORT: u16 = 80;
+    const REMOTE_PORT: u16 = 5000;
+    const REMOTE_ADDR: IPAddr = IPAddr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02]);
+    /// Initial sequence number used by the simulated peer.
+    const PEER_ISS: u32 = 1000;
+
+    /// A segment handed to the IP layer by the mux.
+    struct Segment {
+        hdr: TCPHeader,
+        payload: Vec<u8>,
+    }
+
+    /// IP layer that records the segments it is asked to send. Each send
+    /// stays in progress until the test completes it.
+    #[derive(Default)]
+    struct SimIp {
+        sent: RefCell<Vec<Segment>>,
+        busy: Cell<bool>,
+    }
+
+    impl<'a> IP6Sender<'a> for SimIp {
+        fn set_client(&self, _client: &'a dyn IP6SendClient) {}
+
+        fn set_addr(&self, _src_addr: IPAddr) {}
+
+        fn set_gateway(&self, _gateway: MacAddress) {}
+
+        fn set_link_security(&self, _security: Option<(SecurityLevel, KeyId)>) {}
+
+        fn set_header(&mut self, _ip6_header: IP6Header) {}
+
+        fn send_to(
+            &self,
+            dst: IPAddr,
+            transport_header: TransportHeader,
+            payload: &SubSliceMut<'static, u8>,
+            _net_cap: &'static NetworkCapability,
+        ) -> Result<(), ErrorCode> {
+            assert_eq!(dst, REMOTE_ADDR);
+            assert!(!self.busy.get());
+            let TransportHeader::TCP(hdr) = transport_header else {
+                panic!("not a TCP segment");
+            };
+            self.busy.set(true);
+            self.sent.borrow_mut().push(Segment {
+                hdr,
+                payload: payload.as_slice().to_vec(),
+            });
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct Client {
+        connected: Cell<bool>,
+        received: RefCell<Vec<u8>>,
+        remote_closed: Cell<bool>,
+        send_done: Cell<Option<Result<(), ErrorCode>>>,
+        closed: Cell<Option<Result<(), ErrorCode>>>,
+    }
+
+    impl TCPClient for Client {
+        fn connected(&self, _id: usize, remote_addr: IPAddr, remote_port: u16) {
+            assert_eq!(remote_addr, REMOTE_ADDR);
+            assert_eq!(remote_port, REMOTE_PORT);
+            self.connected.set(true);
+        }
+
+        fn received(&self, _id: usize, payload: &[u8]) -> usize {
+            self.received.borrow_mut().extend_from_slice(payload);
+            payload.len()
+        }
+
+        fn receive_window(&self, _id: usize) -> usize {
+            100
+        }
+
+        fn remote_closed(&self, _id: usize) {
+            self.remote_closed.set(true);
+        }
+
+        fn send_done(
+            &self,
+            _id: usize,
+            result: Result<(), ErrorCode>,
+            _buf: SubSliceMut<'static, u8>,
+        ) {
+            self.send_done.set(Some(result));
+        }
+
+        fn closed(&self, _id: usize, result: Result<(), ErrorCode>) {
+            self.closed.set(Some(result));
+        }
+    }
+
+    type TestMux = MuxTcp<'static, MockAlarm<'static>>;
+
+    struct Harness {
+        ip: &'static SimIp,
+        alarm: &'static MockAlarm<'static>,
+        mux: &'static TestMux,
+        socket: &'static TCPSocketStruct<'static, MockAlarm<'static>>,
+        client: &'static Client,
+    }
+
+    impl Harness {
+        fn new() -> Harness {
+            let create_cap = network_capability_creation();
+            let ip = leak(SimIp::default());
+            let alarm = leak(MockAlarm::new());
+            let net_cap = leak(NetworkCapability::new(
+                AddrRange::Any,
+                PortRange::Any,
+                PortRange::Any,
+                create_cap,
+            ));
+            let tcp_vis = leak(TcpVisibilityCapability::new(create_cap));
+            let mux = leak(MuxTcp::new(
+                ip,
+                alarm,
+                SubSliceMut::new(static_buf(64)),
+                net_cap,
+                tcp_vis,
+            ));
+            alarm.set_alarm_client(mux);
+            let socket = leak(TCPSocketStruct::new(mux));
+            mux.add_socket(socket);
+            let client = leak(Client::default());
+            socket.set_client(client, 0);
+            assert_eq!(socket.bind(LOCAL_PORT, net_cap), Ok(LOCAL_PORT));
+            Harness {
+                ip,
+                alarm,
+                mux,
+                socket,
+                client,
+            }
+        }
+
+        /// Completes every pending send and returns the segments sent.
+        fn sent(&self) -> Vec<Segment> {
+            while self.ip.busy.take() {
+                self.mux.send_done(Ok(()));
+            }
+            self.ip.sent.take()
+        }
+
+        /// Returns the only segment sent since the last call.
+        fn sent_one(&self) -> Segment {
+            let mut sent = self.sent();
+            assert_eq!(sent.len(), 1);
+            sent.remove(0)
+        }
+
+        /// Delivers a segment from the peer.
+        fn deliver(&self, seq: u32, ack: Option<u32>, flags: u16, payload: &[u8]) {
+            let mut hdr = TCPHeader::new();
+            hdr.set_src_port(REMOTE_PORT);
+            hdr.set_dst_port(LOCAL_PORT);
+            hdr.set_seq_num(seq);
+            hdr.set_window(1000);
+            match ack {
+                Some(ack) => {
+                    hdr.set_ack_num(ack);
+                    hdr.set_flags(flags | tcp_flags::ACK);
+                }
+                None => hdr.set_flags(flags),
+            }
+            let mut segment = vec![0u8; TCP_HDR_LEN + payload.len()];
+            hdr.encode(&mut segment, 0).done().unwrap();
+            segment[TCP_HDR_LEN..].copy_from_slice(payload);
+
+            let mut ip_header = IP6Header::new();
+            ip_header.src_addr = REMOTE_ADDR;
+            ip_header.set_next_header(ip6_nh::TCP);
+            self.mux.receive(ip_header, &segment);
+        }
+
+        /// Advances time to the pending timer tick and fires it. Returns
+        /// false if no tick is pending.
+        fn tick(&self) -> bool {
+            self.alarm.fire()
+        }
+
+        /// Lets `ms` milliseconds pass. The alarm counts milliseconds.
+        fn advance_ms(&self, ms: u32) {
+            self.alarm.advance(ms);
+        }
+
+        /// Opens a connection actively and returns the local initial
+        /// sequence number.
+        fn establish(&self) -> u32 {
+            self.socket.connect(REMOTE_ADDR, REMOTE_PORT).unwrap();
+            let syn = self.sent_one();
+            let iss = syn.hdr.get_seq_num();
+            self.deliver(PEER_ISS, Some(iss.wrapping_add(1)), tcp_flags::SYN, &[]);
+            assert_eq!(self.socket.get_state(), TcpState::Established);
+            self.sent();
+            iss
+        }
+
+        fn send(&self, data: &[u8]) {
+            let buf = static_buf(data.len());
+            buf.copy_from_slice(data);
+            assert!(self.socket.send(SubSliceMut::new(buf)).is_ok());
+        }
+    }
+
+    #[test]
+    fn active_open() {
+        let h = Harness::new();
+        h.socket.connect(REMOTE_ADDR, REMOTE_PORT).unwrap();
+        assert_eq!(h.socket.get_state(), TcpState::SynSent);
+
+        let syn = h.sent_one();
+        assert_eq!(syn.hdr.get_flags() & tcp_flags::MASK, tcp_flags::SYN);
+        assert_eq!(syn.hdr.get_src_port(), LOCAL_PORT);
+        assert_eq!(syn.hdr.get_dst_port(), REMOTE_PORT);
+        let iss = syn.hdr.get_seq_num();
+
+        h.deliver(PEER_ISS, Some(iss.wrapping_add(1)), tcp_flags::SYN, &[]);
+        assert_eq!(h.socket.get_state(), TcpState::Established);
+        assert!(h.client.connected.get());
+
+        let ack = h.sent_one();
+        assert_eq!(ack.hdr.get_flags() & tcp_flags::MASK, tcp_flags::ACK);
+        assert_eq!(ack.hdr.get_seq_num(), iss.wrapping_add(1));
+        assert_eq!(ack.hdr.get_ack_num(), PEER_ISS + 1);
+    }
+
+    #[test]
+    fn passive_open() {
+        let h = Harness::new();
+        h.socket.listen().unwrap();
+        h.deliver(PEER_ISS, None, tcp_flags::SYN, &[]);
+        assert_eq!(h.socket.get_state(), TcpState::SynReceived);
+
+        let syn_ack = h.sent_one();
+        assert_eq!(
+            syn_ack.hdr.get_flags() & tcp_flags::MASK,
+            tcp_flags::SYN | tcp_flags::ACK
+        );
+        assert_eq!(syn_ack.hdr.get_ack_num(), PEER_ISS + 1);
+        assert!(!h.client.connected.get());
+
+        let iss = syn_ack.hdr.get_seq_num();
+        h.deliver(PEER_ISS + 1, Some(iss.wrapping_add(1)), 0, b"hi");
+        assert_eq!(h.socket.get_state(), TcpState::Established);
+        assert!(h.client.connected.get());
+        assert_eq!(h.client.received.borrow().as_slice(), b"hi");
+        assert_eq!(h.sent_one().hdr.get_ack_num(), PEER_ISS + 3);
+    }
+
+    #[test]
+    fn syn_to_closed_port_is_reset() {
+        let h = Harness::new();
+        h.deliver(PEER_ISS, None, tcp_flags::SYN, &[]);
+        let rst = h.sent_one();
+        assert_eq!(
+            rst.hdr.get_flags() & tcp_flags::MASK,
+            tcp_flags::RST | tcp_flags::ACK
+        );
+        assert_eq!(rst.hdr.get_ack_num(), PEER_ISS + 1);
+        assert_eq!(h.socket.get_state(), TcpState::Closed);
+    }
+
+    #[test]
+    fn data_is_retransmitted_until_acknowledged() {
+        let h = Harness::new();
+        let iss = h.establish();
+        h.send(b"hello");
+
+        let data = h.sent_one();
+        assert_eq!(data.payload, b"hello");
+        assert_eq!(data.hdr.get_seq_num(), iss.wrapping_add(1));
+
+        // Nothing is sent again before the retransmission timeout.
+        h.advance_ms(INITIAL_RTO_MS - TCP_TICK_MS);
+        assert!(h.sent().is_empty());
+        h.advance_ms(TCP_TICK_MS);
+        let retransmitted = h.sent_one();
+        assert_eq!(retransmitted.payload, b"hello");
+        assert_eq!(retransmitted.hdr.get_seq_num(), iss.wrapping_add(1));
+
+        // The timeout doubles after each retransmission.
+        h.advance_ms(INITIAL_RTO_MS);
+        assert!(h.sent().is_empty());
+        h.advance_ms(INITIAL_RTO_MS);
+        assert_eq!(h.sent_one().payload, b"hello");
+
+        assert_eq!(h.client.send_done.get(), None);
+        h.deliver(PEER_ISS + 1, Some(iss.wrapping_add(6)), 0, &[]);
+        assert_eq!(h.client.send_done.get(), Some(Ok(())));
+
+        // Once everything is acknowledged, the timer stops.
+        while h.tick() {}
+        assert!(h.sent().is_empty());
+        assert_eq!(h.socket.get_state(), TcpState::Established);
+    }
+
+    #[test]
+    fn connection_aborts_after_max_retransmissions() {
+        let h = Harness::new();
+        h.establish();
+        h.send(b"hello");
+        h.sent();
+
+        let mut retransmissions = 0;
+        while h.tick() {
+            retransmissions += h.sent().len();
+        }
+        assert_eq!(retransmissions, MAX_RETRANSMISSIONS as usize);
+        assert_eq!(h.socket.get_state(), TcpState::Closed);
+        assert_eq!(h.client.send_done.get(), Some(Err(ErrorCode::NOACK)));
+        assert_eq!(h.client.closed.get(), Some(Err(ErrorCode::NOACK)));
+    }
+
+    #[test]
+    fn active_close() {
+        let h = Harness::new();
+        let iss = h.establish();
+        h.socket.close().unwrap();
+        assert_eq!(h.socket.get_state(), TcpState::FinWait1);
+
+        let fin = h.sent_one();
+        assert!(fin.hdr.has_flags(tcp_flags::FIN | tcp_flags::ACK));
+        assert_eq!(fin.hdr.get_seq_num(), iss.wrapping_add(1));
+
+        h.deliver(PEER_ISS + 1, Some(iss.wrapping_add(2)), 0, &[]);
+        assert_eq!(h.socket.get_state(), TcpState::FinWait2);
+
+        h.deliver(PEER_ISS + 1, Some(iss.wrapping_add(2)), tcp_flags::FIN, &[]);
+        assert_eq!(h.socket.get_state(), TcpState::TimeWait);
+        assert!(h.client.remote_closed.get());
+        assert_eq!(h.sent_one().hdr.get_ack_num(), PEER_ISS + 2);
+
+        assert_eq!(h.client.closed.get(), None);
+        h.advance_ms(TIME_WAIT_MS);
+        assert_eq!(h.socket.get_state(), TcpState::Closed);
+        assert_eq!(h.client.closed.get(), Some(Ok(())));
+    }
+
+    #[test]
+    fn lost_fin_is_retransmitted() {
+        let h = Harness::new();
+        let iss = h.establish();
+        h.socket.close().unwrap();
+        assert!(h.sent_one().hdr.has_flags(tcp_flags::FIN));
+
+        h.advance_ms(INITIAL_RTO_MS);
+        let fin = h.sent_one();
+        assert!(fin.hdr.has_flags(tcp_flags::FIN));
+        assert_eq!(fin.hdr.get_seq_num(), iss.wrapping_add(1));
+    }
+
+    #[test]
+    fn passive_close() {
+        let h = Harness::new();
+        let iss = h.establish();
+        h.deliver(PEER_ISS + 1, Some(iss.wrapping_add(1)), tcp_flags::FIN, &[]);
+        assert_eq!(h.socket.get_state(), TcpState::CloseWait);
+        assert!(h.client.remote_closed.get());
+        assert_eq!(h.sent_one().hdr.get_ack_num(), PEER_ISS + 2);
+
+        h.socket.close().unwrap();
+        assert_eq!(h.socket.get_state(), TcpState::LastAck);
+        assert!(h.sent_one().hdr.has_flags(tcp_flags::FIN));
+
+        h.deliver(PEER_ISS + 2, Some(iss.wrapping_add(2)), 0, &[]);
+        assert_eq!(h.socket.get_state(), TcpState::Closed);
+        assert_eq!(h.client.closed.get(), Some(Ok(())));
+    }
+
+    #[test]
+    fn reset_closes_connection() {
+        let h = Harness::new();
+        h.establish();
+        h.send(b"hello");
+        h.sent();
+
+        // A reset outside the window is ignored.
+        h.deliver(PEER_ISS + 50, None, tcp_flags::RST, &[]);
+        assert_eq!(h.socket.get_state(), TcpState::Established);
+
+        h.deliver(PEER_ISS + 1, None, tcp_flags::RST, &[]);
+        assert_eq!(h.socket.get_state(), TcpState::Closed);
+        assert_eq!(h.client.send_done.get(), Some(Err(ErrorCode::FAIL)));
+        assert_eq!(h.client.closed.get(), Some(Err(ErrorCode::FAIL)));
+        assert!(h.sent().is_empty());
+    }
+
+    #[test]
+    fn abort_sends_reset() {
+        let h = Harness::new();
+        let iss = h.establish();
+        h.socket.abort();
+        assert_eq!(h.socket.get_state(), TcpState::Closed);
+        assert_eq!(h.client.closed.get(), Some(Err(ErrorCode::CANCEL)));
+
+        let rst = h.sent_one();
+        assert_eq!(rst.hdr.get_flags() & tcp_flags::MASK, tcp_flags::RST);
+        assert_eq!(rst.hdr.get_seq_num(), iss.wrapping_add(1));
+    }
+
+    #[test]
+    fn listener_returns_to_listen_on_reset() {
+        let h = Harness::new();
+        h.socket.listen().unwrap();
+        h.deliver(PEER_ISS, None, tcp_flags::SYN, &[]);
+        h.sent();
+        h.deliver(PEER_ISS + 1, None, tcp_flags::RST, &[]);
+        assert_eq!(h.socket.get_state(), TcpState::Listen);
+        assert_eq!(h.client.closed.get(), None);
+    }
+}
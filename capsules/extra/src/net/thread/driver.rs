@@ -13,11 +13,20 @@
 //!
 //! The Userland interface is incredibly simple at this juncture. An application
 //! can begin the Thread child/parent joining by issuing a syscall command
-//! with the MLE/MAC key as an argument. Only one userspace application can use/join
-//! the Thread network. Once a userspace application has joined the Thread network,
-//! the Thread network is considered locked. After the Thread network
-//! is "locked", other userspace applications attempting to join the network
-//! will return a failure. This is temporary and will eventually be replaced.
+//! with the MLE/MAC key as an argument. Several applications may join the same
+//! network (using the same key) and share the attachment; applications that
+//! provide a different key while a network is in use will fail. The
+//! attachment is released once all joined applications have left it.
+//!
+//! Once attached, the capsule keeps the attachment alive by periodically
+//! sending Child Update Requests to the parent, and reattaches if the parent
+//! stops responding. While attached, regular UDP datagrams (e.g. those sent
+//! through the userspace UDP driver) are secured with the Thread MAC key and
+//! routed to the parent. MLE messages are exempt and use MLE security instead.
+//!
+//! The MLE frame counter may optionally be persisted in nonvolatile storage
+//! (see `set_frame_counter_storage`), so that counter values are not reused
+//! after a reboot.
 
 // ------------------------------------------------------------------------------
 // Current Limitations
@@ -25,16 +34,21 @@
 // (1) A majority of the TLV fields used in the parent request/child id request
 //     are hardcoded. Future implementations need to provide options for specifying
 //     varied security policies.
-// (2) Current implementation joins the Thread network sucessfully and consistently
-//     but does not send update/heart beat messages to the parent prior to the child
-//     timing out.
-// (3) Currently no support for sending UDP messages across Thread interface. The
-//     current interface is unusable for sending data. It can only be used to
-//     join a network.
-
-use crate::ieee802154::framer::{self, get_ccm_nonce};
+// (2) The first parent to respond is chosen; parent responses are not compared
+//     by link quality.
+// (3) MAC frames secured with the Thread MAC key use the frame counter of the
+//     IEEE 802.15.4 framer, which is separate from the MLE frame counter. Each
+//     counter is only persisted if the board provides storage for it (see
+//     `Framer::set_frame_counter_storage` and `set_frame_counter_storage`);
+//     otherwise its values, and with them AES-CCM nonces, are reused after a
+//     reboot.
+
+use crate::ieee802154::framer::{
+    self, FRAME_COUNTER_GUARD, FRAME_COUNTER_STORAGE_LEN, get_ccm_nonce,
+};
 use crate::net::ieee802154::{KeyId, MacAddress, Security, SecurityLevel};
 use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::ipv6::ipv6_send::IP6Sender;
 use crate::net::network_capabilities::NetworkCapability;
 
 use crate::net::ieee802154;
@@ -43,8 +57,10 @@ use crate::net::thread::thread_utils::THREAD_PORT_NUMBER;
 use crate::net::thread::thread_utils::ThreadState;
 use crate::net::thread::thread_utils::generate_src_ipv6;
 use crate::net::thread::thread_utils::{
-    AUTH_DATA_LEN, AUX_SEC_HEADER_LENGTH, IPV6_LEN, MleCommand, NetworkKey, SECURITY_SUITE_LEN,
-    encode_cryp_data, form_child_id_req, form_parent_req, mac_from_ipv6,
+    AUTH_DATA_LEN, AUX_SEC_HEADER_LENGTH, ChildInfo, IPV6_LEN, KEEP_ALIVE_INTERVAL_S,
+    MAX_MISSED_UPDATES, MAX_PARENT_REQ_ATTEMPTS, MleCommand, NetworkKey, RESPONSE_TIMEOUT_MS,
+    SECURITY_SUITE_LEN, encode_cryp_data, form_child_id_req, form_child_update_req,
+    form_parent_req, mac_from_ipv6,
 };
 use crate::net::udp::udp_port_table::UdpPortManager;
 use crate::net::udp::udp_recv::UDPRecvClient;
@@ -56,18 +72,24 @@ use core::cell::Cell;
 use kernel::capabilities::UdpDriverCapability;
 use kernel::errorcode::into_statuscode;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
 use kernel::hil::symmetric_encryption::CCMClient;
 use kernel::hil::symmetric_encryption::{AES128, AESCCM};
-use kernel::hil::time;
+use kernel::hil::time::{self, ConvertTicks};
 use kernel::processbuffer::ReadableProcessBuffer;
 use kernel::syscall::{CommandReturn, SyscallDriver};
-use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
 use kernel::{ErrorCode, ProcessId};
 
 const SECURITY_SUITE_ENCRYP: u8 = 0;
 pub const DRIVER_NUM: usize = driver::NUM::Thread as usize;
 
+/// Key index used for link-layer security. Thread derives the index from the
+/// key sequence counter, which is always 0 for now (Thread Spec v1.3.0 sect.
+/// 7.2.2.2).
+const LINK_KEY_INDEX: u8 = 1;
+
 /// Ids for read-only allow buffers
 mod ro_allow {
     pub const WRITE: usize = 0;
@@ -86,17 +108,29 @@ mod ro_allow {
 
 /// IDs for subscribed upcalls.
 mod upcall {
+    /// Attaching to the network completed, with a status code as argument.
     pub const JOINCOMPLETE: usize = 0;
+    /// The parent stopped responding; the capsule is reattaching.
+    pub const DETACHED: usize = 1;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 2;
 }
 
 #[derive(Default)]
-pub struct App {}
+pub struct App {
+    /// Whether this application has joined the Thread network.
+    joined: bool,
+}
 
 #[allow(dead_code)]
 pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
     /// UDP sender
     sender: &'a dyn UDPSender<'a>,
 
+    /// IP sender below the UDP layer, used to configure link-layer security
+    /// and the next hop once attached
+    ip_sender: &'a dyn IP6Sender<'a>,
+
     /// AES crypto engine for MLE encryption
     aes_crypto: &'a dyn AESCCM<'a, AES128>,
 
@@ -104,7 +138,12 @@ pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
     alarm: &'a A,
 
     /// Grant of apps that use this thread driver.
-    apps: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<0>,
+    >,
 
     /// mac address of device
     src_mac_addr: [u8; 8],
@@ -138,21 +177,50 @@ pub struct ThreadNetworkDriver<'a, A: time::Alarm<'a>> {
 
     /// Length of the message passed to the crypto engine
     crypto_sizelock: MapCell<usize>,
+
+    /// Whether the pending crypto operation secures an outgoing message
+    /// (rather than unsecuring a received one)
+    encrypting: Cell<bool>,
+
+    /// Parameters assigned by the parent while attached
+    child: OptionalCell<ChildInfo>,
+
+    /// Number of parent requests sent in the current attach attempt
+    attach_attempts: Cell<u8>,
+
+    /// Number of consecutive child update requests left unanswered
+    missed_updates: Cell<u8>,
+
+    /// Nonvolatile storage and address holding the MLE frame counter
+    frame_count_storage: OptionalCell<(&'a dyn NonvolatileStorage<'a>, usize)>,
+
+    /// Buffer for reading and writing the persisted frame counter
+    frame_count_buffer: TakeCell<'static, [u8]>,
+
+    /// Frame counter value persisted in storage; counter values below it may
+    /// be used. Without storage the counter is unbounded; once storage is set
+    /// this is 0, and no MLE message is sent, until the counter has been
+    /// restored from it.
+    frame_count_limit: Cell<u32>,
 }
 
 // Note: For now, we initialize the Thread state as empty.
 // We replace the Thread state when the first userspace
 // application calls the Thread capsule to initiate a Thread network.
-// This serves to "lock" the Thread capsule to only one application.
-// For now, Tock only supports one application using the Thread network.
-// After the network is "locked" to one application, other userspace
-// applications requesting to join a Thread network will fail.
+// Other applications using the same network key share the network;
+// the state is emptied again once the last application leaves it.
 impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
     pub fn new(
         sender: &'a dyn UDPSender<'a>,
+        ip_sender: &'a dyn IP6Sender<'a>,
         aes_crypto: &'a dyn AESCCM<'a, AES128>,
         alarm: &'a A,
-        grant: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<0>,
+        >,
         src_mac_addr: [u8; 8],
         max_tx_pyld_len: usize,
         port_table: &'static UdpPortManager,
@@ -163,6 +231,7 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
     ) -> ThreadNetworkDriver<'a, A> {
         ThreadNetworkDriver {
             sender,
+            ip_sender,
             aes_crypto,
             alarm,
             apps: grant,
@@ -177,6 +246,13 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             frame_count: Cell::new(5),
             networkkey: MapCell::empty(),
             crypto_sizelock: MapCell::empty(),
+            encrypting: Cell::new(false),
+            child: OptionalCell::empty(),
+            attach_attempts: Cell::new(0),
+            missed_updates: Cell::new(0),
+            frame_count_storage: OptionalCell::empty(),
+            frame_count_buffer: TakeCell::empty(),
+            frame_count_limit: Cell::new(u32::MAX),
         }
     }
 
@@ -185,6 +261,118 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         self.networkkey.replace(NetworkKey { mle_key, mac_key });
     }
 
+    /// Persists the MLE frame counter at `address` in `storage`, and restores
+    /// it from there. `buffer` must be at least 4 bytes long.
+    ///
+    /// The stored value is advanced in steps of `FRAME_COUNTER_GUARD`, always
+    /// staying ahead of the counter values in use, so that the storage is only
+    /// written once every `FRAME_COUNTER_GUARD` messages. MLE messages are
+    /// refused from this call until the counter has been restored and the
+    /// first advanced value written back.
+    pub fn set_frame_counter_storage(
+        &'a self,
+        storage: &'a dyn NonvolatileStorage<'a>,
+        address: usize,
+        buffer: &'static mut [u8],
+    ) -> Result<(), ErrorCode> {
+        if buffer.len() < FRAME_COUNTER_STORAGE_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        storage.set_client(self);
+        self.frame_count_storage.set((storage, address));
+        self.frame_count_limit.set(0);
+        storage.read(buffer, address, FRAME_COUNTER_STORAGE_LEN)
+    }
+
+    /// Writes a new frame counter limit to storage, if storage is configured
+    /// and not in use. The limit only takes effect once the write completes.
+    fn persist_frame_count(&self) -> Result<(), ErrorCode> {
+        self.frame_count_storage
+            .map_or(Ok(()), |(storage, address)| {
+                self.frame_count_buffer.take().map_or(Ok(()), |buffer| {
+                    let limit = self.frame_count.get().saturating_add(FRAME_COUNTER_GUARD);
+                    buffer[..FRAME_COUNTER_STORAGE_LEN].copy_from_slice(&limit.to_le_bytes());
+                    storage.write(buffer, address, FRAME_COUNTER_STORAGE_LEN)
+                })
+            })
+    }
+
+    fn join(&self, processid: ProcessId, key: NetworkKey) -> Result<(), ErrorCode> {
+        // check the network key; if another key is in use, another userspace
+        // application has control of the Thread network and requesting
+        // applications for a different network should fail.
+        match self.networkkey.get() {
+            Some(netkey) if netkey != key => return Err(ErrorCode::BUSY),
+            Some(_) => (),
+            None => {
+                self.networkkey.replace(key);
+                // Thread state begins as detached
+                self.state.replace(ThreadState::Detached);
+            }
+        }
+
+        self.apps
+            .enter(processid, |app, _| app.joined = true)
+            .map_err(ErrorCode::from)?;
+
+        match self.state.map(|state| match state {
+            ThreadState::SEDActive(_, _) | ThreadState::SendUpdate(_, _) => Some(true),
+            ThreadState::Detached => Some(false),
+            _ => None,
+        }) {
+            Some(Some(true)) => {
+                // Already attached; notify only the joining application.
+                let _ = self.apps.enter(processid, |_, kernel_data| {
+                    let _ = kernel_data
+                        .schedule_upcall(upcall::JOINCOMPLETE, (into_statuscode(Ok(())), 0, 0));
+                });
+            }
+            Some(Some(false)) => {
+                self.attach_attempts.set(0);
+                self.send_parent_req();
+            }
+            // Attaching is underway, the application is notified once it
+            // completes.
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn leave(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        // While an MLE message is being sent, the attachment can not be torn
+        // down.
+        if self.state.map_or(false, |state| {
+            matches!(
+                state,
+                ThreadState::SendParentReq
+                    | ThreadState::SendChildIdReq(_)
+                    | ThreadState::SendUpdate(_, _)
+            )
+        }) {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let joined = self
+            .apps
+            .enter(processid, |app, _| {
+                core::mem::replace(&mut app.joined, false)
+            })
+            .map_err(ErrorCode::from)?;
+        if !joined {
+            return Err(ErrorCode::ALREADY);
+        }
+
+        if !self.apps.iter().any(|app| app.enter(|app, _| app.joined)) {
+            // The last application left, detach from the network.
+            let _ = self.alarm.disarm();
+            self.ip_sender.set_link_security(None);
+            self.child.clear();
+            self.networkkey.take();
+            self.state.take();
+        }
+        Ok(())
+    }
+
     fn send_parent_req(&self) {
         // UNCOMMENT TO DEBUG THREAD //
         // kernel::debug!("[Thread] Sending parent request...");
@@ -199,7 +387,8 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
                 // helper functions to form the request and send the parent request
                 // to the multicast IP/Mac Address
                 self.state.replace(ThreadState::SendParentReq);
-                let parent_req_mle = form_parent_req();
+                let parent_req_mle = form_parent_req(self.attach_attempts.get());
+                self.attach_attempts.set(self.attach_attempts.get() + 1);
                 let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
                 self.thread_mle_send(&parent_req_mle, MULTICAST_IPV6, src_ipv6)
                     .err()
@@ -234,6 +423,86 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         }
     }
 
+    fn send_child_update(&self, parent_ip: IPAddr, parent_mac: MacAddress) {
+        // Panicking on unwrap indicates the child info was cleared while
+        // attached (unreachable with proper state machine implementation)
+        let child_update_mle = form_child_update_req(&self.child.get().unwrap());
+        let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
+
+        self.state
+            .replace(ThreadState::SendUpdate(parent_ip, parent_mac));
+        if self
+            .thread_mle_send(&child_update_mle, parent_ip, src_ipv6)
+            .is_err()
+        {
+            // Count this as a missed update and retry after the next interval.
+            self.state
+                .replace(ThreadState::SEDActive(parent_ip, parent_mac));
+            self.start_keep_alive_timer();
+        }
+    }
+
+    /// Called when the parent stopped responding to child update requests.
+    /// Stops securing traffic for the parent and attempts to reattach.
+    fn detach_from_parent(&self) {
+        // UNCOMMENT TO DEBUG THREAD //
+        // kernel::debug!("[Thread] Lost parent, reattaching...");
+        self.ip_sender.set_link_security(None);
+        self.child.clear();
+        self.apps.each(|_, app, kernel_data| {
+            if app.joined {
+                let _ = kernel_data.schedule_upcall(upcall::DETACHED, (0, 0, 0));
+            }
+        });
+
+        self.state.replace(ThreadState::Detached);
+        self.attach_attempts.set(0);
+        self.send_parent_req();
+    }
+
+    /// Called when no response to the last parent request or child id
+    /// request arrived in time.
+    fn retry_attach(&self) {
+        self.state.replace(ThreadState::Detached);
+        if self.attach_attempts.get() < MAX_PARENT_REQ_ATTEMPTS {
+            self.send_parent_req();
+        } else {
+            self.terminate_child_join(Err(ErrorCode::NOACK));
+        }
+    }
+
+    /// Returns the state machine to where it was before an MLE message which
+    /// could not be handed to the UDP layer.
+    fn send_failed(&self) {
+        match self.state.take() {
+            Some(ThreadState::SendUpdate(parent_ip, parent_mac)) => {
+                // Counts as a missed update; retry after the next interval
+                self.state
+                    .replace(ThreadState::SEDActive(parent_ip, parent_mac));
+                self.start_keep_alive_timer();
+            }
+            Some(_) => {
+                self.state.replace(ThreadState::Detached);
+                self.terminate_child_join(Err(ErrorCode::FAIL));
+            }
+            None => (),
+        }
+    }
+
+    fn start_response_timer(&self) {
+        self.alarm.set_alarm(
+            self.alarm.now(),
+            self.alarm.ticks_from_ms(RESPONSE_TIMEOUT_MS),
+        );
+    }
+
+    fn start_keep_alive_timer(&self) {
+        self.alarm.set_alarm(
+            self.alarm.now(),
+            self.alarm.ticks_from_seconds(KEEP_ALIVE_INTERVAL_S),
+        );
+    }
+
     fn thread_mle_send(
         &self,
         mle_buf: &[u8],
@@ -242,11 +511,25 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
     ) -> Result<(), ErrorCode> {
         // TODO: Hardcoded encryption suite and auxiliary security; add support to send encrypted/unencrypted MLE
 
+        // The frame counter must never be reused, so only values below the
+        // persisted limit are used. The maximum value indicates that it has
+        // been exhausted.
+        let frame_count = self.frame_count.get();
+        let limit = self.frame_count_limit.get();
+        if frame_count == u32::MAX || frame_count >= limit {
+            return Err(ErrorCode::BUSY);
+        }
+        // Advance the persisted limit early, so that sending does not stall
+        // while the write is in progress.
+        if limit - frame_count <= FRAME_COUNTER_GUARD / 2 {
+            self.persist_frame_count()?;
+        }
+
         // We hardcode the auxiliary security for now
         let security = Security {
             level: SecurityLevel::EncMic32,
             asn_in_nonce: false,
-            frame_counter: Some(self.frame_count.get()),
+            frame_counter: Some(frame_count),
             key_id: KeyId::Source4Index([0, 0, 0, 0], 1),
         };
 
@@ -254,13 +537,20 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         self.send_buffer
             .take()
             .map_or(Err(ErrorCode::NOMEM), |send_buffer| {
-                self.perform_crypt_op(src_addr, dest_addr, security, mle_buf, send_buffer.take())
-                    .map_err(|(code, buf)| {
-                        // Error occured with cryptographic operation, replace buffer
-                        // for future transmissions and return error code
-                        self.send_buffer.replace(SubSliceMut::new(buf));
-                        code
-                    })
+                self.perform_crypt_op(
+                    src_addr,
+                    dest_addr,
+                    security,
+                    mle_buf,
+                    send_buffer.take(),
+                    true,
+                )
+                .map_err(|(code, buf)| {
+                    // Error occured with cryptographic operation, replace buffer
+                    // for future transmissions and return error code
+                    self.send_buffer.replace(SubSliceMut::new(buf));
+                    code
+                })
             })
     }
 
@@ -271,50 +561,84 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         self.recv_buffer
             .take()
             .map_or(Err(ErrorCode::NOMEM), |mut recv_buf| {
-                if recv_buf[0] == MleCommand::ParentResponse as u8 {
-                    // Received Parent Response -> form Child ID Request
-
-                    // UNCOMMENT TO DEBUG THREAD //
-                    // kernel::debug!("[Thread] Received Parent Response.");
-                    // kernel::debug!("[Thread] Sending Child ID Request...");
-
-                    let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
-
-                    let (output, offset) =
-                        form_child_id_req(recv_buf.as_slice(), self.frame_count.get())?;
-
-                    // Advance state machine
-                    self.state.replace(ThreadState::SendChildIdReq(sender_ip));
-
-                    self.thread_mle_send(&output[..offset], sender_ip, src_ipv6)?;
-                } else if recv_buf[0] == MleCommand::ChildIdResponse as u8 {
-                    // Receive child id response -> advance state machine
-                    self.state.replace(ThreadState::SEDActive(
-                        sender_ip,
-                        MacAddress::Long(mac_from_ipv6(sender_ip)),
-                    ));
-
-                    // TODO: once heart beats are implemented, we will set
-                    // the timer here (as seen below)
-                    // let curr_time = self.alarm.now();
-                    // self.alarm.set_alarm(
-                    //     curr_time,
-                    //     time::ConvertTicks::ticks_from_seconds(self.alarm, 5),
-                    // );
-                }
-
+                let res = self.handle_mle(recv_buf.as_slice(), sender_ip);
                 recv_buf.reset();
                 self.recv_buffer.replace(recv_buf);
-                Ok(())
+                res
             })
     }
 
+    fn handle_mle(&self, mle: &[u8], sender_ip: IPAddr) -> Result<(), ErrorCode> {
+        let in_state = |f: fn(&ThreadState) -> bool| self.state.map_or(false, |state| f(state));
+
+        if mle[0] == MleCommand::ParentResponse as u8 {
+            if !in_state(|state| matches!(state, ThreadState::WaitingParentRsp)) {
+                // Only the first parent response is answered
+                return Ok(());
+            }
+
+            // Received Parent Response -> form Child ID Request
+
+            // UNCOMMENT TO DEBUG THREAD //
+            // kernel::debug!("[Thread] Received Parent Response.");
+            // kernel::debug!("[Thread] Sending Child ID Request...");
+
+            let src_ipv6 = generate_src_ipv6(&self.src_mac_addr);
+
+            let (output, offset) = form_child_id_req(mle, self.frame_count.get())?;
+
+            // Advance state machine
+            self.state.replace(ThreadState::SendChildIdReq(sender_ip));
+
+            self.thread_mle_send(&output[..offset], sender_ip, src_ipv6)
+                .inspect_err(|_| {
+                    self.state.replace(ThreadState::Detached);
+                })?;
+        } else if mle[0] == MleCommand::ChildIdResponse as u8 {
+            if !in_state(|state| matches!(state, ThreadState::WaitingChildRsp)) {
+                return Ok(());
+            }
+
+            // A malformed response is dropped; the response timer retries
+            // attaching.
+            let Ok(child) = ChildInfo::from_child_id_rsp(&mle[1..]) else {
+                return Ok(());
+            };
+
+            // Receive child id response -> advance state machine
+            let parent_mac = MacAddress::Long(mac_from_ipv6(sender_ip));
+            self.state
+                .replace(ThreadState::SEDActive(sender_ip, parent_mac));
+            self.child.set(child);
+            self.missed_updates.set(0);
+
+            // Route all further traffic to the parent, secured with the MAC key
+            self.ip_sender.set_gateway(parent_mac);
+            self.ip_sender.set_link_security(Some((
+                SecurityLevel::EncMic32,
+                KeyId::Index(LINK_KEY_INDEX),
+            )));
+
+            self.start_keep_alive_timer();
+            self.terminate_child_join(Ok(()));
+        } else if mle[0] == MleCommand::ChildUpdateResponse as u8 {
+            if in_state(|state| matches!(state, ThreadState::SEDActive(_, _))) {
+                // The parent still considers us its child
+                self.missed_updates.set(0);
+            }
+        }
+        Ok(())
+    }
+
     fn terminate_child_join(&self, res: Result<(), ErrorCode>) {
         // Function to schedule upcall to userland on parent request termination. Notifies
-        // userland of the reason for termination with the first argument.
+        // joined applications of the reason for termination with the first argument.
 
-        self.apps.each(|_, _, kernel_data| {
-            let _ = kernel_data.schedule_upcall(upcall::JOINCOMPLETE, (into_statuscode(res), 0, 0));
+        self.apps.each(|_, app, kernel_data| {
+            if app.joined {
+                let _ =
+                    kernel_data.schedule_upcall(upcall::JOINCOMPLETE, (into_statuscode(res), 0, 0));
+            }
         });
     }
 
@@ -325,12 +649,14 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         security: Security,
         payload: &[u8],
         buf: &'static mut [u8],
+        encrypting: bool,
     ) -> Result<(), (ErrorCode, &'static mut [u8])> {
         // Wrapper function for performing the AES-128CCM encryption. This function generates the nonce,
         // sets the nonce/key for the crypto engine, generates the authenticated data, and initiates
         // the crypto operation.
 
-        // Note: The payload argument does not include aux sec header
+        // Note: The payload argument does not include aux sec header. When
+        // decrypting, it ends with the received mic
 
         // Obtain and unwrap frame counter
         let frame_counter = security.frame_counter;
@@ -348,6 +674,9 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         );
         let mle_key = self.networkkey.get();
         let mic_len = security.level.mic_len();
+        if !encrypting && payload.len() < mic_len {
+            return Err((ErrorCode::SIZE, buf));
+        }
         match mle_key {
             Some(netkey) => {
                 if self.aes_crypto.set_key(&netkey.mle_key).is_err()
@@ -387,7 +716,12 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
         let aux_sec_header = &mut [0u8; AUX_SEC_HEADER_LENGTH];
         Security::encode(&security, aux_sec_header);
 
-        let m_data_len = payload.len();
+        // When decrypting, the received mic follows the m data
+        let m_data_len = if encrypting {
+            payload.len()
+        } else {
+            payload.len() - mic_len
+        };
 
         // Encode auth data and payload into `buf`
         let encode_res = encode_cryp_data(src_addr, dst_addr, aux_sec_header, payload, buf).done();
@@ -425,10 +759,15 @@ impl<'a, A: time::Alarm<'a>> ThreadNetworkDriver<'a, A> {
             return Err((ErrorCode::BUSY, buf));
         }
 
-        // Store the length of the payload.
-        self.crypto_sizelock.replace(offset + mic_len);
+        // Store the length of the payload including the mic.
+        let total_len = if encrypting { offset + mic_len } else { offset };
+        self.crypto_sizelock.replace(total_len);
+        self.encrypting.set(encrypting);
         self.aes_crypto
-            .crypt(buf, 0, AUTH_DATA_LEN, m_data_len, mic_len, true, true)
+            .crypt(buf, 0, AUTH_DATA_LEN, m_data_len, mic_len, true, encrypting)
+            .inspect_err(|_| {
+                self.crypto_sizelock.take();
+            })
     }
 }
 
@@ -459,7 +798,12 @@ impl<'a, A: time::Alarm<'a>> framer::DeviceProcedure for ThreadNetworkDriver<'a,
 impl<'a, A: time::Alarm<'a>> SyscallDriver for ThreadNetworkDriver<'a, A> {
     /// ### `command_num`
     /// - `0`: Driver Check
-    /// - `1`: Add a new mle/mac networkkey and initiate a parent request.
+    /// - `1`: Join the Thread network using the mle/mac networkkey in the
+    ///   read-only allow buffer. Initiates a parent request if the device is
+    ///   not attached yet. Returns BUSY if a network with a different key is
+    ///   in use.
+    /// - `2`: Leave the Thread network. The device detaches once no joined
+    ///   application remains.
     fn command(
         &self,
         command_num: usize,
@@ -477,43 +821,29 @@ impl<'a, A: time::Alarm<'a>> SyscallDriver for ThreadNetworkDriver<'a, A> {
                         .get_readonly_processbuffer(ro_allow::WRITE)
                         .and_then(|ro_buf| {
                             ro_buf.enter(|src_key| {
-                                // check Thread state, if thread state is not empty,
-                                // another userspace application has control of the Thread
-                                // network and other requesting applications should fail.
-                                if self.state.is_some() {
-                                    return CommandReturn::failure(ErrorCode::BUSY);
-                                }
-
                                 // src key consists of the mle and mac keys; Thread
                                 // hash is performed in userland and 32 byte hash is
                                 // passed to thread capsule and entered as mac/mle key
                                 // (For key generation see Thread spec v1.3.0 7.1.4)
                                 if src_key.len() != 32 {
-                                    return CommandReturn::failure(ErrorCode::SIZE);
+                                    return Err(ErrorCode::SIZE);
                                 }
                                 let mut mle_key = [0u8; 16];
                                 let mut mac_key = [0u8; 16];
                                 src_key[..16].copy_to_slice(&mut mle_key);
                                 src_key[16..32].copy_to_slice(&mut mac_key);
-                                self.set_networkkey(mle_key, mac_key);
-
-                                // Thread state begins as detached if sucessfully joined
-                                self.state.replace(ThreadState::Detached);
-                                CommandReturn::success()
+                                Ok(NetworkKey { mle_key, mac_key })
                             })
                         })
-                        .unwrap_or(CommandReturn::failure(ErrorCode::INVAL))
+                        .unwrap_or(Err(ErrorCode::INVAL))
                 })
                 .map_or_else(
                     |err| CommandReturn::failure(err.into()),
-                    |ok_val| {
-                        // If no failure in saving the mle/mac key, initiate
-                        // sending the parent request
-                        self.send_parent_req();
-                        ok_val
-                    },
+                    |key| CommandReturn::from(key.and_then(|key| self.join(processid, key))),
                 ),
 
+            2 => CommandReturn::from(self.leave(processid)),
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -525,7 +855,8 @@ impl<'a, A: time::Alarm<'a>> SyscallDriver for ThreadNetworkDriver<'a, A> {
 
 impl<'a, A: time::Alarm<'a>> UDPSendClient for ThreadNetworkDriver<'a, A> {
     fn send_done(&self, _result: Result<(), ErrorCode>, mut dgram: SubSliceMut<'static, u8>) {
-        // TODO: handle result from send done and respond accordingly
+        // A failed transmission is treated like a lost message: the response
+        // timers retry as needed.
 
         // Panicking on unwrap indicates the state was taken without replacement
         // (unreachable with proper state machine implementation)
@@ -533,12 +864,19 @@ impl<'a, A: time::Alarm<'a>> UDPSendClient for ThreadNetworkDriver<'a, A> {
 
         // Advance state machine
         let next_state = match curr_state {
-            ThreadState::SendUpdate(dst_ip, dst_mac) => ThreadState::SEDActive(dst_ip, dst_mac),
+            ThreadState::SendUpdate(dst_ip, dst_mac) => {
+                self.start_keep_alive_timer();
+                ThreadState::SEDActive(dst_ip, dst_mac)
+            }
             ThreadState::SendUDPMsg => unimplemented!(),
-            ThreadState::SendChildIdReq(_) => ThreadState::WaitingChildRsp,
+            ThreadState::SendChildIdReq(_) => {
+                self.start_response_timer();
+                ThreadState::WaitingChildRsp
+            }
             ThreadState::SendParentReq => {
                 // UNCOMMENT TO DEBUG THREAD //
                 // kernel::debug!("[Thread] Completed sending parent request to multicast IP");
+                self.start_response_timer();
                 ThreadState::WaitingParentRsp
             }
             _ => panic!("Thread state machine diverged"),
@@ -554,20 +892,27 @@ impl<'a, A: time::Alarm<'a>> UDPSendClient for ThreadNetworkDriver<'a, A> {
 }
 
 impl<'a, A: time::Alarm<'a>> time::AlarmClient for ThreadNetworkDriver<'a, A> {
-    // TODO: This function is mainly here as a place holder as it will be needed
-    // for implementing timeouts/timing for sending heartbeat messages to the parent
-    // node
     fn alarm(&self) {
-        match self.state.take().unwrap() {
-            // TODO: Implement retries as defined in the thread spec (when timeouts occur)
-            ThreadState::Detached => unimplemented!("[Thread ALARM] Detached"),
-            ThreadState::SendParentReq => unimplemented!("[Thread ALARM] Send Parent Req"),
-            ThreadState::SendChildIdReq(_) => unimplemented!("[Thread ALARM] Send Child ID Req"),
-            ThreadState::SEDActive(_ipaddr, _mac) => {
-                // TODO: SEND HEARTBEAT to parent node
-                unimplemented!("[Thread ALARM] Send Heartbeat")
+        let Some(curr_state) = self.state.take() else {
+            // The network was left
+            return;
+        };
+
+        match curr_state {
+            ThreadState::WaitingParentRsp | ThreadState::WaitingChildRsp => self.retry_attach(),
+            ThreadState::SEDActive(parent_ip, parent_mac) => {
+                if self.missed_updates.get() >= MAX_MISSED_UPDATES {
+                    self.detach_from_parent();
+                } else {
+                    // The counter is reset once the parent responds
+                    self.missed_updates.set(self.missed_updates.get() + 1);
+                    self.send_child_update(parent_ip, parent_mac);
+                }
+            }
+            // MLE messages are underway; their completion restarts the timer.
+            _ => {
+                self.state.replace(curr_state);
             }
-            _ => panic!(""),
         }
     }
 }
@@ -581,12 +926,19 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
         _dst_port: u16,
         payload: &[u8],
     ) {
-        if payload[0] != SECURITY_SUITE_ENCRYP {
+        if payload.first() != Some(&SECURITY_SUITE_ENCRYP) {
             // Tock's current implementation of Thread ignores all messages that do not possess MLE encryption. This
             // is due to the Thread spec stating "Except for when specifically indicated, incoming
             // messages that are not secured with either MLE or link-layer security SHOULD be ignored." (v.1.3.0 sect 4.10)
             // UNCOMMENT TO DEBUG THREAD //
             // kernel::debug!("[Thread] DROPPED PACKET - Received unencrypted MLE packet.");
+            return;
+        }
+
+        // Messages are only processed while attaching to or attached to a
+        // network
+        if self.state.is_none() {
+            return;
         }
 
         // decode aux security header from packet into Security data type
@@ -614,9 +966,9 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
                     src_addr,
                     dst_addr,
                     security,
-                    &payload[SECURITY_SUITE_LEN + AUX_SEC_HEADER_LENGTH
-                        ..payload.len() - security.level.mic_len()],
+                    &payload[SECURITY_SUITE_LEN + AUX_SEC_HEADER_LENGTH..],
                     recv_buf.take(),
+                    false,
                 )
                 .unwrap_or_else(|(_code, buf)| {
                     // UNCOMMENT TO DEBUG THREAD alter _code to code//
@@ -632,12 +984,17 @@ impl<'a, A: time::Alarm<'a>> UDPRecvClient for ThreadNetworkDriver<'a, A> {
 }
 
 impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
-    fn crypt_done(&self, buf: &'static mut [u8], _res: Result<(), ErrorCode>, _tag_is_valid: bool) {
-        // TODO: check validity of result/tag and handle accordingly
-
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
         // Obtain the length of the payload from the sizelock
         let buf_len = self.crypto_sizelock.take().unwrap();
 
+        if !self.encrypting.get() && (res.is_err() || !tag_is_valid) {
+            // UNCOMMENT TO DEBUG THREAD //
+            // kernel::debug!("[Thread] DROPPED PACKET - MLE message integrity check failed.");
+            self.recv_buffer.replace(SubSliceMut::new(buf));
+            return;
+        }
+
         // The auth data contains the src_addr || dest_addr || aux_sec_header;
         // Recover src/dst addr from the auth data
         let mut src_ipv6 = [0u8; IPV6_LEN];
@@ -669,14 +1026,29 @@ impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
         // We create a new subslice that we will slice accordingly depending on if we are sending/receiving
         let mut assembled_subslice = SubSliceMut::new(buf);
 
+        if !self.encrypting.get() {
+            // Upon receiving messages, the receive logic only requires the MLE payload. Subsequently,
+            // we slice the assembled_subslice to exclude the security suite, aux sec header, and mic.
+            assembled_subslice
+                .slice(AUX_SEC_HEADER_LENGTH + SECURITY_SUITE_LEN..assembled_buf_len - mic_len);
+
+            // Move the decrypted MLE message into the recv_buf and execute the receiving logic. Upon
+            // an error in `recv_logic`, joining the network fails and schedule termination upcall
+            self.recv_buffer.replace(assembled_subslice);
+            if let Err(code) = self.recv_logic(IPAddr(src_ipv6)) {
+                self.terminate_child_join(Err(code))
+            }
+            return;
+        }
+
         // Panicking on unwrap indicates the state was taken without replacement
         // (unreachable with proper state machine implementation)
         let curr_state = self.state.take().unwrap();
 
         match curr_state {
-            ThreadState::SendParentReq | ThreadState::SendChildIdReq(_) => {
-                //TODO: Add alarm for timeouts
-
+            ThreadState::SendParentReq
+            | ThreadState::SendChildIdReq(_)
+            | ThreadState::SendUpdate(_, _) => {
                 // To send, we need to send: security suite || aux sec header || mle payload || mic
                 // which correlates to the assembled_buf_len
                 assembled_subslice.slice(..assembled_buf_len);
@@ -685,6 +1057,7 @@ impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
                     // Determine destination IP depending on message type
                     ThreadState::SendParentReq => MULTICAST_IPV6,
                     ThreadState::SendChildIdReq(dst_ipv6) => dst_ipv6,
+                    ThreadState::SendUpdate(dst_ipv6, _) => dst_ipv6,
                     _ => unreachable!(),
                 };
 
@@ -703,32 +1076,495 @@ impl<'a, A: time::Alarm<'a>> CCMClient for ThreadNetworkDriver<'a, A> {
                         self.driver_send_cap,
                         self.net_cap,
                     )
-                    .map_err(|buf| {
+                    .map_err(|mut buf| {
                         // if the sending fails prior to transmission, replace
-                        // the buffer and pass error accordingly to terminate_child_join
-                        // in following unwrap statement
+                        // the buffer and treat the message as lost
+                        buf.reset();
                         self.send_buffer.replace(buf);
-                        ErrorCode::FAIL
                     })
-                    .unwrap_or_else(|code| self.terminate_child_join(Err(code)));
+                    .unwrap_or_else(|()| self.send_failed());
             }
-            ThreadState::WaitingChildRsp => {
-                // TODO: Receive child response
+            _ => {
+                // Unreachable with proper state machine implementation
+                assembled_subslice.reset();
+                self.send_buffer.replace(assembled_subslice);
+                self.state.replace(curr_state);
             }
-            ThreadState::WaitingParentRsp => {
-                // Upon receiving messages, the receive logic only requires the MLE payload. Subsequently,
-                // we slice the assembled_subslice to exclude the security suite, aux sec header, and mic.
-                assembled_subslice
-                    .slice(AUX_SEC_HEADER_LENGTH + SECURITY_SUITE_LEN..assembled_buf_len - mic_len);
-
-                // Move the decrypted MLE message into the recv_buf and execute the receiving logic. Upon
-                // an error in `recv_logic`, joining the network fails and schedule termination upcall
-                self.recv_buffer.replace(assembled_subslice);
-                if let Err(code) = self.recv_logic(IPAddr(src_ipv6)) {
-                    self.terminate_child_join(Err(code))
-                }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> NonvolatileStorageClient for ThreadNetworkDriver<'a, A> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        let restored = length >= FRAME_COUNTER_STORAGE_LEN;
+        if restored {
+            let mut stored = [0u8; FRAME_COUNTER_STORAGE_LEN];
+            stored.copy_from_slice(&buffer[..FRAME_COUNTER_STORAGE_LEN]);
+            let stored = u32::from_le_bytes(stored);
+            // Erased storage reads as all ones and holds no counter
+            if stored != 0xffffffff && stored > self.frame_count.get() {
+                self.frame_count.set(stored);
             }
-            _ => (),
         }
+        self.frame_count_buffer.replace(buffer);
+
+        // Values up to the stored counter may have been used before the
+        // reboot, so move the persisted limit ahead right away. If the
+        // counter could not be read or the write fails, MLE messages stay
+        // disabled.
+        if restored {
+            let _ = self.persist_frame_count();
+        }
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        if length >= FRAME_COUNTER_STORAGE_LEN {
+            let mut written = [0u8; FRAME_COUNTER_STORAGE_LEN];
+            written.copy_from_slice(&buffer[..FRAME_COUNTER_STORAGE_LEN]);
+            let written = u32::from_le_bytes(written);
+            if written > self.frame_count_limit.get() {
+                self.frame_count_limit.set(written);
+            }
+        }
+        self.frame_count_buffer.replace(buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::{Cell, RefCell};
+    use std::vec::Vec;
+
+    use capsules_test_harness::alarm::MockAlarm;
+    use capsules_test_harness::process::{App, HostKernel, Upcall};
+    use capsules_test_harness::{
+        deferred_call, leak, network_capability_creation, port_table_creation, static_buf,
+        udp_driver_capability,
+    };
+    use kernel::hil::time::{Alarm, Ticks};
+    use kernel::syscall::SyscallReturn;
+
+    use crate::net::ipv6::ipv6_send::IP6SendClient;
+    use crate::net::ipv6::{IP6Header, TransportHeader};
+    use crate::net::network_capabilities::{AddrRange, PortRange, UdpVisibilityCapability};
+    use crate::net::udp::UDPHeader;
+    use crate::net::udp::udp_port_table::UdpPortBindingTx;
+
+    use super::*;
+
+    const SRC_MAC: [u8; 8] = [0x02, 0, 0, 0, 0, 0, 0, 0x01];
+    const PARENT_IP: IPAddr = IPAddr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x04, 0, 0, 0, 0, 0, 0, 0x02]);
+    const KEY: [u8; 32] = [0x5a; 32];
+    const MIC_LEN: usize = 4;
+
+    /// Parent Response carrying the challenge the Child ID Request answers.
+    const PARENT_RSP: [u8; 11] = [
+        MleCommand::ParentResponse as u8,
+        3,
+        8,
+        1,
+        2,
+        3,
+        4,
+        5,
+        6,
+        7,
+        8, // Challenge
+    ];
+    /// Child ID Response assigning RLOC16 0x1c01.
+    const CHILD_ID_RSP: [u8; 15] = [
+        MleCommand::ChildIdResponse as u8,
+        10,
+        2,
+        0x1c,
+        0x01, // Address16
+        11,
+        8,
+        0,
+        0,
+        0,
+        1,
+        64,
+        0,
+        0,
+        0, // Leader Data
+    ];
+
+    /// UDP layer that holds on to the datagram it is asked to send until the
+    /// test completes the send.
+    #[derive(Default)]
+    struct SimUdp {
+        pending: RefCell<Option<(IPAddr, SubSliceMut<'static, u8>)>>,
+    }
+
+    impl<'a> UDPSender<'a> for SimUdp {
+        fn set_client(&self, _client: &'a dyn UDPSendClient) {}
+
+        fn send_to(
+            &'a self,
+            _dest: IPAddr,
+            _dst_port: u16,
+            _buf: SubSliceMut<'static, u8>,
+            _net_cap: &'static NetworkCapability,
+        ) -> Result<(), SubSliceMut<'static, u8>> {
+            unimplemented!()
+        }
+
+        fn driver_send_to(
+            &'a self,
+            dest: IPAddr,
+            dst_port: u16,
+            src_port: u16,
+            buf: SubSliceMut<'static, u8>,
+            _driver_send_cap: &dyn UdpDriverCapability,
+            _net_cap: &'static NetworkCapability,
+        ) -> Result<(), SubSliceMut<'static, u8>> {
+            assert_eq!(dst_port, THREAD_PORT_NUMBER);
+            assert_eq!(src_port, THREAD_PORT_NUMBER);
+            assert!(self.pending.replace(Some((dest, buf))).is_none());
+            Ok(())
+        }
+
+        fn send(
+            &'a self,
+            _dest: IPAddr,
+            _udp_header: UDPHeader,
+            _buf: SubSliceMut<'static, u8>,
+            _net_cap: &'static NetworkCapability,
+        ) -> Result<(), SubSliceMut<'static, u8>> {
+            unimplemented!()
+        }
+
+        fn get_binding(&self) -> Option<UdpPortBindingTx> {
+            None
+        }
+
+        fn is_bound(&self) -> bool {
+            false
+        }
+
+        fn set_binding(&self, _binding: UdpPortBindingTx) -> Option<UdpPortBindingTx> {
+            None
+        }
+    }
+
+    /// IP layer recording how the driver configures it.
+    #[derive(Default)]
+    struct SimIp {
+        gateway: Cell<Option<MacAddress>>,
+        link_security: Cell<Option<(SecurityLevel, KeyId)>>,
+    }
+
+    impl<'a> IP6Sender<'a> for SimIp {
+        fn set_client(&self, _client: &'a dyn IP6SendClient) {}
+
+        fn set_addr(&self, _src_addr: IPAddr) {}
+
+        fn set_gateway(&self, gateway: MacAddress) {
+            self.gateway.set(Some(gateway));
+        }
+
+        fn set_link_security(&self, security: Option<(SecurityLevel, KeyId)>) {
+            self.link_security.set(security);
+        }
+
+        fn set_header(&mut self, _ip6_header: IP6Header) {}
+
+        fn send_to(
+            &self,
+            _dst: IPAddr,
+            _transport_header: TransportHeader,
+            _payload: &SubSliceMut<'static, u8>,
+            _net_cap: &'static NetworkCapability,
+        ) -> Result<(), ErrorCode> {
+            unimplemented!()
+        }
+    }
+
+    /// AES-CCM engine which leaves the message as it is, so that the test can
+    /// read the MLE messages sent and provide the ones received in plain
+    /// text. The operation stays pending until the test completes it.
+    #[derive(Default)]
+    struct SimCcm {
+        pending: RefCell<Option<&'static mut [u8]>>,
+    }
+
+    impl<'a> AESCCM<'a, AES128> for SimCcm {
+        fn set_client(&'a self, _client: &'a dyn CCMClient) {}
+
+        fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+            assert_eq!(key, &KEY[..16]);
+            Ok(())
+        }
+
+        fn set_nonce(&self, _nonce: &[u8]) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn crypt(
+            &self,
+            buf: &'static mut [u8],
+            _a_off: usize,
+            _m_off: usize,
+            _m_len: usize,
+            _mic_len: usize,
+            _confidential: bool,
+            _encrypting: bool,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            assert!(self.pending.replace(Some(buf)).is_none());
+            Ok(())
+        }
+    }
+
+    type TestDriver = ThreadNetworkDriver<'static, MockAlarm<'static>>;
+
+    struct Harness {
+        kernel: &'static HostKernel,
+        udp: &'static SimUdp,
+        ip: &'static SimIp,
+        ccm: &'static SimCcm,
+        alarm: &'static MockAlarm<'static>,
+        driver: &'static TestDriver,
+    }
+
+    impl Harness {
+        fn new() -> Harness {
+            let kernel = HostKernel::new();
+            let udp = leak(SimUdp::default());
+            let ip = leak(SimIp::default());
+            let ccm = leak(SimCcm::default());
+            let alarm = leak(MockAlarm::new());
+            let create_cap = network_capability_creation();
+            let port_table = leak(UdpPortManager::new(
+                port_table_creation(),
+                std::boxed::Box::leak(std::boxed::Box::new([None; 1])),
+                leak(UdpVisibilityCapability::new(create_cap)),
+            ));
+            let net_cap = leak(NetworkCapability::new(
+                AddrRange::Any,
+                PortRange::Any,
+                PortRange::Any,
+                create_cap,
+            ));
+            let driver = leak(ThreadNetworkDriver::new(
+                udp,
+                ip,
+                ccm,
+                alarm,
+                kernel.create_grant(DRIVER_NUM),
+                SRC_MAC,
+                100,
+                port_table,
+                SubSliceMut::new(static_buf(200)),
+                SubSliceMut::new(static_buf(200)),
+                udp_driver_capability(),
+                net_cap,
+            ));
+            alarm.set_alarm_client(driver);
+            kernel.add_driver(DRIVER_NUM, driver);
+            Harness {
+                kernel,
+                udp,
+                ip,
+                ccm,
+                alarm,
+                driver,
+            }
+        }
+
+        /// Load an application which joins the network with `KEY`.
+        fn join(&self) -> App {
+            let app = self.kernel.load_process("app");
+            let key = app.allocate(KEY.len());
+            app.write(key, &KEY);
+            app.allow_readonly(DRIVER_NUM, ro_allow::WRITE, key);
+            app.subscribe(DRIVER_NUM, upcall::JOINCOMPLETE);
+            app.subscribe(DRIVER_NUM, upcall::DETACHED);
+            assert!(matches!(
+                app.command(DRIVER_NUM, 1, 0, 0),
+                SyscallReturn::Success
+            ));
+            app
+        }
+
+        /// Complete securing and sending the pending MLE message. Returns its
+        /// destination and the MLE message.
+        fn sent(&self) -> (IPAddr, Vec<u8>) {
+            let buf = self.ccm.pending.take().expect("no MLE message is pending");
+            self.driver.crypt_done(buf, Ok(()), true);
+            let (dest, dgram) = self.udp.pending.take().expect("no datagram was sent");
+            let header_len = SECURITY_SUITE_LEN + AUX_SEC_HEADER_LENGTH;
+            let mle = dgram.as_slice()[header_len..dgram.len() - MIC_LEN].to_vec();
+            self.driver.send_done(Ok(()), dgram);
+            (dest, mle)
+        }
+
+        /// Receive the MLE message `mle` from the parent.
+        fn deliver(&self, mle: &[u8]) {
+            let security = Security {
+                level: SecurityLevel::EncMic32,
+                asn_in_nonce: false,
+                frame_counter: Some(0),
+                key_id: KeyId::Source4Index([0, 0, 0, 0], 1),
+            };
+            let mut payload = Vec::new();
+            payload.push(SECURITY_SUITE_ENCRYP);
+            let mut aux_sec_header = [0u8; AUX_SEC_HEADER_LENGTH];
+            Security::encode(&security, &mut aux_sec_header);
+            payload.extend_from_slice(&aux_sec_header);
+            payload.extend_from_slice(mle);
+            payload.extend_from_slice(&[0; MIC_LEN]);
+
+            let dst = generate_src_ipv6(&SRC_MAC);
+            self.driver.receive(
+                PARENT_IP,
+                dst,
+                THREAD_PORT_NUMBER,
+                THREAD_PORT_NUMBER,
+                &payload,
+            );
+            let buf = self.ccm.pending.take().expect("the message was dropped");
+            self.driver.crypt_done(buf, Ok(()), true);
+        }
+
+        /// Attach to the parent, starting with the pending parent request, and
+        /// check that `app` is notified.
+        fn attach(&self, app: &App) {
+            let (dest, mle) = self.sent();
+            assert_eq!(dest, MULTICAST_IPV6);
+            assert_eq!(mle[0], MleCommand::ParentRequest as u8);
+            self.deliver(&PARENT_RSP);
+            let (dest, mle) = self.sent();
+            assert_eq!(dest, PARENT_IP);
+            assert_eq!(mle[0], MleCommand::ChildIdRequest as u8);
+            self.deliver(&CHILD_ID_RSP);
+            assert_eq!(app.yield_wait(), joined(Ok(())));
+        }
+
+        /// Let the keep-alive interval pass, checking that the timer fires at
+        /// its end.
+        fn wait_keep_alive(&self) {
+            let interval = self.alarm.ticks_from_seconds(KEEP_ALIVE_INTERVAL_S);
+            assert_eq!(self.alarm.advance(interval.into_u32() - 1), 0);
+            assert_eq!(self.alarm.advance(1), 1);
+        }
+    }
+
+    fn joined(result: Result<(), ErrorCode>) -> Option<Upcall> {
+        Some(Upcall {
+            driver_number: DRIVER_NUM,
+            subscribe_number: upcall::JOINCOMPLETE,
+            arguments: [into_statuscode(result), 0, 0],
+        })
+    }
+
+    #[test]
+    fn parent_requests_are_retried_until_attaching_fails() {
+        deferred_call::run(|| {
+            let h = Harness::new();
+            let app = h.join();
+            let timeout = h.alarm.ticks_from_ms(RESPONSE_TIMEOUT_MS).into_u32();
+
+            for _ in 0..MAX_PARENT_REQ_ATTEMPTS {
+                let (dest, mle) = h.sent();
+                assert_eq!(dest, MULTICAST_IPV6);
+                assert_eq!(mle[0], MleCommand::ParentRequest as u8);
+                assert_eq!(h.alarm.advance(timeout - 1), 0);
+                assert!(h.ccm.pending.borrow().is_none());
+                assert_eq!(h.alarm.advance(1), 1);
+            }
+
+            // No further request is sent once all attempts failed.
+            assert!(h.ccm.pending.borrow().is_none());
+            assert_eq!(h.alarm.remaining(), None);
+            assert_eq!(app.yield_wait(), joined(Err(ErrorCode::NOACK)));
+        });
+    }
+
+    #[test]
+    fn late_child_id_response_is_ignored() {
+        deferred_call::run(|| {
+            let h = Harness::new();
+            let app = h.join();
+            h.sent();
+            h.deliver(&PARENT_RSP);
+            h.sent();
+
+            // The response timer expires before the parent answers; the
+            // device starts over with a new parent request.
+            let timeout = h.alarm.ticks_from_ms(RESPONSE_TIMEOUT_MS).into_u32();
+            assert_eq!(h.alarm.advance(timeout), 1);
+            let (dest, mle) = h.sent();
+            assert_eq!(dest, MULTICAST_IPV6);
+            assert_eq!(mle[0], MleCommand::ParentRequest as u8);
+
+            h.deliver(&CHILD_ID_RSP);
+            assert!(h.driver.child.is_none());
+            assert_eq!(h.ip.link_security.get(), None);
+            assert_eq!(app.yield_wait(), None);
+        });
+    }
+
+    #[test]
+    fn attached_child_sends_keep_alive_updates() {
+        deferred_call::run(|| {
+            let h = Harness::new();
+            let app = h.join();
+            h.attach(&app);
+            assert_eq!(
+                h.ip.gateway.get(),
+                Some(MacAddress::Long(mac_from_ipv6(PARENT_IP)))
+            );
+            assert_eq!(
+                h.ip.link_security.get(),
+                Some((SecurityLevel::EncMic32, KeyId::Index(LINK_KEY_INDEX)))
+            );
+
+            // As long as the parent answers, the child stays attached.
+            for _ in 0..=MAX_MISSED_UPDATES {
+                h.wait_keep_alive();
+                let (dest, mle) = h.sent();
+                assert_eq!(dest, PARENT_IP);
+                assert_eq!(mle[0], MleCommand::ChildUpdateRequest as u8);
+                // Source Address TLV with the assigned RLOC16
+                assert_eq!(mle[1..5], [0, 2, 0x1c, 0x01]);
+                h.deliver(&[MleCommand::ChildUpdateResponse as u8]);
+            }
+            assert!(h.driver.child.is_some());
+            assert_eq!(app.yield_wait(), None);
+        });
+    }
+
+    #[test]
+    fn unanswered_updates_detach_and_reattach() {
+        deferred_call::run(|| {
+            let h = Harness::new();
+            let app = h.join();
+            h.attach(&app);
+
+            for _ in 0..MAX_MISSED_UPDATES {
+                h.wait_keep_alive();
+                let (dest, mle) = h.sent();
+                assert_eq!(dest, PARENT_IP);
+                assert_eq!(mle[0], MleCommand::ChildUpdateRequest as u8);
+            }
+
+            // The next interval passes without a response either; the child
+            // gives up on its parent and looks for a new one.
+            h.wait_keep_alive();
+            assert!(h.driver.child.is_none());
+            assert_eq!(h.ip.link_security.get(), None);
+            assert_eq!(
+                app.yield_wait(),
+                Some(Upcall {
+                    driver_number: DRIVER_NUM,
+                    subscribe_number: upcall::DETACHED,
+                    arguments: [0, 0, 0],
+                })
+            );
+            h.attach(&app);
+        });
     }
 }
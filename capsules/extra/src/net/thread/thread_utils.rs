@@ -3,7 +3,7 @@
 // Copyright Tock Contributors 2023.
 
 use crate::net::stream::{SResult, encode_bytes};
-use crate::net::thread::tlv::{LinkMode, MulticastResponder, Tlv, unwrap_tlv_offset};
+use crate::net::thread::tlv::{LinkMode, MulticastResponder, Tlv, TlvType, unwrap_tlv_offset};
 use crate::net::{ieee802154::MacAddress, ipv6::ip_utils::IPAddr};
 pub const THREAD_PORT_NUMBER: u16 = 19788;
 
@@ -14,16 +14,76 @@ pub const AUX_SEC_HEADER_LENGTH: usize = 10;
 pub const AUTH_DATA_LEN: usize = 42;
 pub const IPV6_LEN: usize = 16;
 const PARENT_REQUEST_MLE_SIZE: usize = 21;
+const CHILD_UPDATE_REQUEST_MLE_SIZE: usize = 24;
+
+/// Timeout advertised to the parent, after which it removes a child it has
+/// not heard from (in seconds).
+pub const CHILD_TIMEOUT_S: u32 = 240;
+/// Interval between Child Update Requests keeping the attachment alive (in
+/// seconds). A few consecutive requests may be lost before the parent times
+/// out the child.
+pub const KEEP_ALIVE_INTERVAL_S: u32 = CHILD_TIMEOUT_S / 4;
+/// Number of consecutive unanswered Child Update Requests after which the
+/// child considers itself detached from its parent.
+pub const MAX_MISSED_UPDATES: u8 = 3;
+/// Time to wait for a Parent Response or Child ID Response (in milliseconds).
+pub const RESPONSE_TIMEOUT_MS: u32 = 1000;
+/// Number of Parent Requests sent before attaching fails (see the note at the
+/// end of this file).
+pub const MAX_PARENT_REQ_ATTEMPTS: u8 = 6;
+/// Number of initial Parent Requests which only solicit responses from
+/// routers.
+const ROUTER_ONLY_PARENT_REQ_ATTEMPTS: u8 = 2;
 pub const MULTICAST_IPV6: IPAddr = IPAddr([
     0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
 ]);
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct NetworkKey {
     pub mle_key: [u8; 16],
     pub mac_key: [u8; 16],
 }
 
+/// Parameters assigned by the parent in its Child ID Response, which must be
+/// repeated in the Child Update Requests sent to it.
+#[derive(Clone, Copy)]
+pub struct ChildInfo {
+    pub rloc16: u16,
+    pub partition_id: u32,
+    pub weighting: u8,
+    pub data_version: u8,
+    pub stable_data_version: u8,
+    pub leader_router_id: u8,
+}
+
+impl ChildInfo {
+    /// Recovers the child's parameters from the TLVs of a received Child ID
+    /// Response (excluding the command byte).
+    pub fn from_child_id_rsp(buf: &[u8]) -> Result<ChildInfo, ErrorCode> {
+        let rloc16 = match find_tlv(buf, TlvType::Address16) {
+            Some(Tlv::Address16(rloc16)) => rloc16,
+            _ => return Err(ErrorCode::FAIL),
+        };
+        match find_tlv(buf, TlvType::LeaderData) {
+            Some(Tlv::LeaderData {
+                partition_id,
+                weighting,
+                data_version,
+                stable_data_version,
+                leader_router_id,
+            }) => Ok(ChildInfo {
+                rloc16,
+                partition_id,
+                weighting,
+                data_version,
+                stable_data_version,
+                leader_router_id,
+            }),
+            _ => Err(ErrorCode::FAIL),
+        }
+    }
+}
+
 pub enum ThreadState {
     SendParentReq,
     WaitingParentRsp,
@@ -108,6 +168,21 @@ fn find_challenge(buf: &[u8]) -> Result<&[u8], ErrorCode> {
     Err(ErrorCode::FAIL)
 }
 
+/// Helper function to locate and decode the first TLV of type `tlv_type` in
+/// a received MLE packet.
+fn find_tlv(buf: &[u8], tlv_type: TlvType) -> Option<Tlv<'_>> {
+    let tlv_type = tlv_type as u8;
+    let mut index = 0;
+    while index + 1 < buf.len() {
+        let tlv_len = buf[index + 1] as usize;
+        if buf[index] == tlv_type {
+            return Tlv::decode(&buf[index..]).done().map(|(_, tlv)| tlv);
+        }
+        index += tlv_len + 2;
+    }
+    None
+}
+
 /// Function to encode the crypt data into a/m data
 pub fn encode_cryp_data(
     src_addr: IPAddr,
@@ -125,9 +200,12 @@ pub fn encode_cryp_data(
     stream_done!(off)
 }
 
-/// This helper function creates a parent request. For now,
-/// this implementation hard codes all values for the parent request
-pub fn form_parent_req() -> [u8; PARENT_REQUEST_MLE_SIZE] {
+/// This helper function creates a parent request.
+///
+/// For now, this implementation hard codes all values for the parent request
+/// except for the scan mask, which depends on the number of previous
+/// attempts `attempt` (Thread Spec v1.3.0 sect. 4.5.1).
+pub fn form_parent_req(attempt: u8) -> [u8; PARENT_REQUEST_MLE_SIZE] {
     // TODO: form parent request from alterable values, generate
     // challenge from random number generator
     let mut output = [0u8; PARENT_REQUEST_MLE_SIZE];
@@ -156,8 +234,13 @@ pub fn form_parent_req() -> [u8; PARENT_REQUEST_MLE_SIZE] {
     ));
 
     // Scan Mask TLV //
+    let scan_mask = if attempt < ROUTER_ONLY_PARENT_REQ_ATTEMPTS {
+        MulticastResponder::Router as u8
+    } else {
+        MulticastResponder::Router as u8 | MulticastResponder::EndDevice as u8
+    };
     offset += unwrap_tlv_offset(Tlv::encode(
-        &Tlv::ScanMask(MulticastResponder::Router as u8),
+        &Tlv::ScanMask(scan_mask),
         &mut output[offset..],
     ));
 
@@ -227,7 +310,7 @@ pub fn form_child_id_req(
 
     // Timeout TLV //
     offset += unwrap_tlv_offset(Tlv::encode(
-        &Tlv::Timeout((10_u32).to_be()),
+        &Tlv::Timeout(CHILD_TIMEOUT_S.to_be()),
         &mut output[offset..],
     ));
 
@@ -246,6 +329,56 @@ pub fn form_child_id_req(
     Ok((output, offset))
 }
 
+/// This helper function creates a child update request, which keeps the
+/// child's attachment to its parent alive (Thread Spec v1.3.0 sect. 4.7.3).
+pub fn form_child_update_req(child: &ChildInfo) -> [u8; CHILD_UPDATE_REQUEST_MLE_SIZE] {
+    let mut output = [0u8; CHILD_UPDATE_REQUEST_MLE_SIZE];
+    let mut offset = 0;
+
+    /* -- Child Update Request TLVs (Thread Spec 4.7.3.1 (v1.3.0)) --
+    Source Address TLV
+    Mode TLV
+    Timeout TLV
+    Leader Data TLV
+    */
+
+    // Command: Child Update Request //
+    output[0..1].copy_from_slice(&[MleCommand::ChildUpdateRequest as u8]);
+    offset += 1;
+
+    // Source Address TLV //
+    offset += unwrap_tlv_offset(Tlv::encode(
+        &Tlv::SourceAddress(child.rloc16.to_be()),
+        &mut output[offset..],
+    ));
+
+    // Mode TLV //
+    offset += unwrap_tlv_offset(Tlv::encode(
+        &Tlv::Mode(LinkMode::FullThreadDevice as u8 + LinkMode::ReceiverOnWhenIdle as u8),
+        &mut output[offset..],
+    ));
+
+    // Timeout TLV //
+    offset += unwrap_tlv_offset(Tlv::encode(
+        &Tlv::Timeout(CHILD_TIMEOUT_S.to_be()),
+        &mut output[offset..],
+    ));
+
+    // Leader Data TLV //
+    unwrap_tlv_offset(Tlv::encode(
+        &Tlv::LeaderData {
+            partition_id: child.partition_id.to_be(),
+            weighting: child.weighting,
+            data_version: child.data_version,
+            stable_data_version: child.stable_data_version,
+            leader_router_id: child.leader_router_id,
+        },
+        &mut output[offset..],
+    ));
+
+    output
+}
+
 /*
 This is just here as a note for when retries are added
 ==================================================================================================
@@ -285,3 +418,77 @@ SENDING/RETRYING PARENT REQUESTS THREAD SPEC v1.3.0 -- section 4.5.1
 
 
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHILD: ChildInfo = ChildInfo {
+        rloc16: 0x1c01,
+        partition_id: 0x1234_5678,
+        weighting: 64,
+        data_version: 7,
+        stable_data_version: 3,
+        leader_router_id: 9,
+    };
+
+    /// The TLVs of a Child ID Response assigning `CHILD`, preceded by an
+    /// unrelated Source Address TLV.
+    const CHILD_ID_RSP: [u8; 18] = [
+        0, 2, 0x04, 0x00, // Source Address
+        10, 2, 0x1c, 0x01, // Address16
+        11, 8, 0x12, 0x34, 0x56, 0x78, 64, 7, 3, 9, // Leader Data
+    ];
+
+    fn assert_child(child: &ChildInfo) {
+        assert_eq!(child.rloc16, CHILD.rloc16);
+        assert_eq!(child.partition_id, CHILD.partition_id);
+        assert_eq!(child.weighting, CHILD.weighting);
+        assert_eq!(child.data_version, CHILD.data_version);
+        assert_eq!(child.stable_data_version, CHILD.stable_data_version);
+        assert_eq!(child.leader_router_id, CHILD.leader_router_id);
+    }
+
+    #[test]
+    fn child_update_request_repeats_the_child_parameters() {
+        let update = form_child_update_req(&CHILD);
+        assert_eq!(update[0], MleCommand::ChildUpdateRequest as u8);
+        // Source Address: the RLOC16 assigned by the parent
+        assert_eq!(update[1..5], [0, 2, 0x1c, 0x01]);
+        // Mode: rx-on-when-idle, full Thread device
+        assert_eq!(update[5..8], [1, 1, 0b0000_1010]);
+        // Timeout
+        assert_eq!(update[8..14], [2, 4, 0, 0, 0, 240]);
+        // Leader Data, as received in the Child ID Response
+        assert_eq!(update[14..], CHILD_ID_RSP[8..]);
+    }
+
+    #[test]
+    fn child_id_response_is_parsed() {
+        assert_child(&ChildInfo::from_child_id_rsp(&CHILD_ID_RSP).unwrap());
+    }
+
+    #[test]
+    fn child_id_response_without_address16_is_rejected() {
+        assert_eq!(
+            ChildInfo::from_child_id_rsp(&CHILD_ID_RSP[8..]).err(),
+            Some(ErrorCode::FAIL)
+        );
+    }
+
+    #[test]
+    fn child_id_response_without_leader_data_is_rejected() {
+        assert_eq!(
+            ChildInfo::from_child_id_rsp(&CHILD_ID_RSP[..8]).err(),
+            Some(ErrorCode::FAIL)
+        );
+    }
+
+    #[test]
+    fn truncated_child_id_response_is_rejected() {
+        assert_eq!(
+            ChildInfo::from_child_id_rsp(&CHILD_ID_RSP[..CHILD_ID_RSP.len() - 1]).err(),
+            Some(ErrorCode::FAIL)
+        );
+    }
+}
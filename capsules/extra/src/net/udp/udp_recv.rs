@@ -13,7 +13,7 @@
 //! received packets to the appropriate client.
 
 use crate::net::ipv6::IP6Header;
-use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::ipv6::ip_utils::{IPAddr, ip6_nh};
 use crate::net::ipv6::ipv6_recv::IP6RecvClient;
 use crate::net::udp::UDPHeader;
 use crate::net::udp::driver::UDPDriver;
@@ -47,6 +47,9 @@ impl<'a> MuxUdpReceiver<'a> {
 
 impl IP6RecvClient for MuxUdpReceiver<'_> {
     fn receive(&self, ip_header: IP6Header, payload: &[u8]) {
+        if ip_header.get_next_header() != ip6_nh::UDP {
+            return;
+        }
         if let Some((offset, udp_header)) = UDPHeader::decode(payload).done() {
             let len = udp_header.get_len() as usize;
             let dst_port = udp_header.get_dst_port();
@@ -57,6 +57,11 @@ impl<'a, T: IP6Sender<'a>> MuxUdpSender<'a, T> {
         }
     }
 
+    /// Returns the IP sender shared by all UDP senders on this mux.
+    pub fn ip_sender(&self) -> &'a dyn IP6Sender<'a> {
+        self.ip_sender
+    }
+
     fn send_to(
         &self,
         dest: IPAddr,
@@ -0,0 +1,283 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Allow userspace to read the resource accounting of processes.
+//!
+//! This lets a supervisor process watch how much CPU time, how many system
+//! calls and how much memory other processes use, for example to decide to
+//! stop a runaway process or to size the RAM of an app. The values are the
+//! ones the kernel provides through
+//! [`KernelInfo`](kernel::introspection::KernelInfo), and are counted since
+//! the process was last started. CPU time, per-driver system call counts and
+//! memory high-water marks are only recorded if the board uses a
+//! [`ProcessStandardDebug`](kernel::process::ProcessStandardDebug)
+//! implementation that records them, such as `ProcessStandardDebugFull`.
+//!
+//! This capsule requires a capability, as it reveals information about other
+//! processes.
+//!
+//! ## Commands
+//!
+//! - 0: Check driver exists.
+//! - 1: Get the CPU time, in microseconds, the process with the process ID in
+//!   `data1` has used, as a u64.
+//! - 2: Get the number of system calls the process with the process ID in
+//!   `data1` has called.
+//! - 3: Get the memory high-water marks of the process with the process ID in
+//!   `data1`: the largest number of bytes accessible to the process and the
+//!   largest number of bytes of its memory the kernel has used for grants.
+//! - 4: Fill the allow RW buffer with the per-driver system call counts of the
+//!   process with the process ID in `data1`, as pairs of u32 (driver number,
+//!   number of system calls). Returns the number of pairs, which may be more
+//!   than fit in the buffer.
+//!
+//! All commands other than 0 return `INVAL` if there is no process with the
+//! given process ID.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let process_accounting = components::process_accounting::ProcessAccountingComponent::new(
+//!     board_kernel,
+//!     capsules_extra::process_accounting::DRIVER_NUM,
+//!     create_capability!(capabilities::ProcessManagementCapability),
+//!     create_capability!(capabilities::MemoryAllocationCapability),
+//! )
+//! .finalize(components::process_accounting_component_static!(ProcessMgmtCap));
+//! ```
+
+use kernel::Kernel;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::introspection::KernelInfo;
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::ProcessAccounting as usize;
+
+mod rw_allow {
+    pub const SYSCALL_COUNTS: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+pub struct ProcessAccounting<C: ProcessManagementCapability> {
+    apps: Grant<(), UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    /// Reference to the kernel object so we can find processes.
+    kernel: &'static Kernel,
+    kernel_info: KernelInfo,
+    /// Capability needed to inspect processes.
+    capability: C,
+}
+
+impl<C: ProcessManagementCapability> ProcessAccounting<C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        grant: Grant<(), UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+        capability: C,
+    ) -> Self {
+        Self {
+            apps: grant,
+            kernel,
+            kernel_info: KernelInfo::new(kernel),
+            capability,
+        }
+    }
+
+    /// Find the process with the process ID `id`.
+    fn find_process(&self, id: usize) -> Option<ProcessId> {
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.processid().id() == id {
+                    found = Some(process.processid());
+                }
+            });
+        found
+    }
+
+    /// Copy the per-driver syscall counts of `app` into the allow buffer of
+    /// `processid`, and return the number of drivers.
+    fn copy_syscall_counts(&self, processid: ProcessId, app: ProcessId) -> Result<u32, ErrorCode> {
+        let mut count = 0;
+        self.apps
+            .enter(processid, |_app, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::SYSCALL_COUNTS)
+                    .and_then(|shared| {
+                        shared.mut_enter(|s| {
+                            let mut chunks = s.chunks(2 * size_of::<u32>());
+                            while let Some((driver_num, syscalls)) = self
+                                .kernel_info
+                                .app_driver_syscall_count(app, count, &self.capability)
+                            {
+                                if let Some(chunk) = chunks.next() {
+                                    let mut pair = [0; 8];
+                                    pair[0..4].copy_from_slice(&(driver_num as u32).to_le_bytes());
+                                    pair[4..8].copy_from_slice(&(syscalls as u32).to_le_bytes());
+                                    let _ = chunk.copy_from_slice_or_err(&pair);
+                                }
+                                count += 1;
+                            }
+                        })
+                    })
+            })
+            .and_then(|result| result)
+            .map_err(ErrorCode::from)?;
+        Ok(count as u32)
+    }
+}
+
+impl<C: ProcessManagementCapability> SyscallDriver for ProcessAccounting<C> {
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            // Driver existence check
+            0 => CommandReturn::success(),
+
+            1 => self
+                .find_process(data1)
+                .map_or(CommandReturn::failure(ErrorCode::INVAL), |app| {
+                    CommandReturn::success_u64(
+                        self.kernel_info.app_cpu_time_us(app, &self.capability),
+                    )
+                }),
+
+            2 => self
+                .find_process(data1)
+                .map_or(CommandReturn::failure(ErrorCode::INVAL), |app| {
+                    CommandReturn::success_u32(
+                        self.kernel_info.number_app_syscalls(app, &self.capability) as u32,
+                    )
+                }),
+
+            3 => self
+                .find_process(data1)
+                .map_or(CommandReturn::failure(ErrorCode::INVAL), |app| {
+                    let (app_memory, grant_memory) = self
+                        .kernel_info
+                        .app_memory_high_water_marks(app, &self.capability);
+                    CommandReturn::success_u32_u32(app_memory as u32, grant_memory as u32)
+                }),
+
+            4 => self
+                .find_process(data1)
+                .ok_or(ErrorCode::INVAL)
+                .and_then(|app| self.copy_syscall_counts(processid, app))
+                .map_or_else(CommandReturn::failure, CommandReturn::success_u32),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capsules_test_harness::process::{App, Buffer, HostKernel};
+    use capsules_test_harness::{deferred_call, leak, process_management_capability};
+    use kernel::syscall::{Syscall, SyscallReturn};
+
+    /// A driver number no driver is registered for.
+    const MISSING_DRIVER_NUM: usize = 0x9999;
+
+    fn accounting() -> &'static HostKernel {
+        let kernel = HostKernel::new();
+        let accounting = leak(ProcessAccounting::new(
+            kernel.kernel(),
+            kernel.create_grant(DRIVER_NUM),
+            process_management_capability(),
+        ));
+        kernel.add_driver(DRIVER_NUM, accounting);
+        kernel
+    }
+
+    fn memory_high_water_marks(monitor: &App, app: &App) -> (u32, u32) {
+        match monitor.command(DRIVER_NUM, 3, app.id().id(), 0) {
+            SyscallReturn::SuccessU32U32(app_memory, grant_memory) => (app_memory, grant_memory),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn syscalls_are_counted_per_driver() {
+        deferred_call::run(|| {
+            let kernel = accounting();
+            let monitor = kernel.load_process("monitor");
+            let app = kernel.load_process("app");
+
+            app.command(MISSING_DRIVER_NUM, 0, 0, 0);
+            app.command(DRIVER_NUM, 0, 0, 0);
+            app.command(MISSING_DRIVER_NUM, 1, 0, 0);
+            app.syscall(Syscall::Memop {
+                operand: 2,
+                arg0: 0,
+            });
+
+            assert!(matches!(
+                monitor.command(DRIVER_NUM, 2, app.id().id(), 0),
+                SyscallReturn::SuccessU32(4)
+            ));
+
+            let buffer = monitor.allocate(8);
+            monitor.allow_readwrite(DRIVER_NUM, rw_allow::SYSCALL_COUNTS, buffer);
+            assert!(matches!(
+                monitor.command(DRIVER_NUM, 4, app.id().id(), 0),
+                SyscallReturn::SuccessU32(2)
+            ));
+            let counts = monitor.read(buffer);
+            assert_eq!(counts[0..4], (MISSING_DRIVER_NUM as u32).to_le_bytes());
+            assert_eq!(counts[4..8], 2u32.to_le_bytes());
+        });
+    }
+
+    #[test]
+    fn memory_high_water_marks_do_not_drop() {
+        deferred_call::run(|| {
+            let kernel = accounting();
+            let monitor = kernel.load_process("monitor");
+            let app = kernel.load_process("app");
+
+            let (app_memory, grant_memory) = memory_high_water_marks(&monitor, &app);
+            app.allocate(256);
+            app.syscall(Syscall::Memop {
+                operand: 1,
+                arg0: -256isize as usize,
+            });
+            // Allowing a buffer allocates the grant of the app.
+            app.allow_readwrite(DRIVER_NUM, rw_allow::SYSCALL_COUNTS, Buffer::NULL);
+
+            let (new_app_memory, new_grant_memory) = memory_high_water_marks(&monitor, &app);
+            assert_eq!(new_app_memory, app_memory + 256);
+            assert!(new_grant_memory > grant_memory);
+        });
+    }
+
+    #[test]
+    fn unknown_process_is_rejected() {
+        deferred_call::run(|| {
+            let kernel = accounting();
+            let monitor = kernel.load_process("monitor");
+            for command in 1..=4 {
+                assert!(matches!(
+                    monitor.command(DRIVER_NUM, command, 0x1234, 0),
+                    SyscallReturn::Failure(ErrorCode::INVAL)
+                ));
+            }
+        });
+    }
+}
@@ -1678,3 +1678,111 @@ impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for SDCardDriver<'a, A> {
         self.grants.enter(processid, |_, _| {})
     }
 }
+
+/// Nonvolatile storage interface for an SD card.
+///
+/// This is used to layer a filesystem such as `fat_fs` on top of the SDCard.
+/// Only whole, aligned 512 byte blocks can be read or written. After a failed
+/// operation the buffer is returned with a length of 0.
+///
+/// The card is initialized again whenever it is inserted. Boards without a
+/// detect pin have to call `SDCard::initialize` themselves.
+pub struct SDCardNonvolatileStorage<'a, A: hil::time::Alarm<'a>> {
+    sdcard: &'a SDCard<'a, A>,
+    client: OptionalCell<&'a dyn hil::nonvolatile_storage::NonvolatileStorageClient>,
+    /// Whether the pending operation is a write.
+    writing: OptionalCell<bool>,
+}
+
+impl<'a, A: hil::time::Alarm<'a>> SDCardNonvolatileStorage<'a, A> {
+    pub fn new(sdcard: &'a SDCard<'a, A>) -> SDCardNonvolatileStorage<'a, A> {
+        SDCardNonvolatileStorage {
+            sdcard,
+            client: OptionalCell::empty(),
+            writing: OptionalCell::empty(),
+        }
+    }
+
+    fn block(address: usize, length: usize, buffer: &[u8]) -> Result<u32, ErrorCode> {
+        if length != 512 || buffer.len() < length || !address.is_multiple_of(512) {
+            return Err(ErrorCode::INVAL);
+        }
+        u32::try_from(address / 512).or(Err(ErrorCode::INVAL))
+    }
+}
+
+impl<'a, A: hil::time::Alarm<'a>> hil::nonvolatile_storage::NonvolatileStorage<'a>
+    for SDCardNonvolatileStorage<'a, A>
+{
+    fn set_client(&self, client: &'a dyn hil::nonvolatile_storage::NonvolatileStorageClient) {
+        self.client.set(client);
+    }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.writing.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let sector = Self::block(address, length, buffer)?;
+        self.sdcard.read_blocks(buffer, sector, 1)?;
+        self.writing.set(false);
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.writing.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let sector = Self::block(address, length, buffer)?;
+        self.sdcard.write_blocks(buffer, sector, 1)?;
+        self.writing.set(true);
+        Ok(())
+    }
+}
+
+/// Handle callbacks from SDCard
+impl<'a, A: hil::time::Alarm<'a>> SDCardClient for SDCardNonvolatileStorage<'a, A> {
+    fn card_detection_changed(&self, installed: bool) {
+        if installed {
+            let _ = self.sdcard.initialize();
+        }
+    }
+
+    fn init_done(&self, _block_size: u32, _total_size: u64) {}
+
+    fn read_done(&self, data: &'static mut [u8], len: usize) {
+        self.writing.clear();
+        self.client.map(move |client| client.read_done(data, len));
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8]) {
+        self.writing.clear();
+        self.client
+            .map(move |client| client.write_done(buffer, 512));
+    }
+
+    fn error(&self, _error: u32) {
+        // The SDCard keeps the buffer of a failed operation.
+        let Some(writing) = self.writing.take() else {
+            return;
+        };
+        self.sdcard.client_buffer.take().map(|buffer| {
+            self.client.map(move |client| {
+                if writing {
+                    client.write_done(buffer, 0);
+                } else {
+                    client.read_done(buffer, 0);
+                }
+            });
+        });
+    }
+}
@@ -35,8 +35,10 @@ use core::cell::Cell;
 use core::marker::PhantomData;
 use kernel::ErrorCode;
 use kernel::debug;
+use kernel::hil::kv::KVRegionHealth;
 use kernel::utilities::cells::{MapCell, TakeCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
+use tickv::KeyCursor;
 
 #[derive(Clone, Copy, PartialEq)]
 enum CurrentState {
@@ -168,4 +170,16 @@ impl<S: KVSystem<'static, K = T>, T: KeyType + core::fmt::Debug> KVSystemClient<
             }
         }
     }
+
+    fn region_health_complete(&self, result: Result<KVRegionHealth, ErrorCode>, region: usize) {
+        debug!("Region {} health: {:?}", region, result);
+    }
+
+    fn next_key_complete(
+        &self,
+        result: Result<(usize, KeyCursor), ErrorCode>,
+        key: &'static mut T,
+    ) {
+        debug!("Next key {:?}: {:?}", key, result);
+    }
 }
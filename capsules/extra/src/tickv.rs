@@ -37,9 +37,10 @@ use core::cell::Cell;
 use kernel::ErrorCode;
 use kernel::hil::flash::{self, Flash};
 use kernel::hil::hasher::{self, Hasher};
+use kernel::hil::kv::KVRegionHealth;
 use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
 use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
-use tickv::AsyncTicKV;
+use tickv::{AsyncTicKV, KeyCursor};
 
 /// The type of keys, this should define the output size of the digest
 /// operations.
@@ -95,6 +96,20 @@ pub trait KVSystemClient<K: KeyType> {
     ///
     /// - `result`: Nothing on success, 'ErrorCode' on error
     fn garbage_collect_complete(&self, result: Result<(), ErrorCode>);
+
+    /// This callback is called when the region_health operation completes.
+    ///
+    /// - `result`: The region health on success, 'ErrorCode' on error
+    /// - `region`: The region that was read
+    fn region_health_complete(&self, result: Result<KVRegionHealth, ErrorCode>, region: usize);
+
+    /// This callback is called when the next_key operation completes.
+    ///
+    /// - `result`: The length of the value stored with the key and the cursor
+    ///   to continue from on success, 'ErrorCode' on error. `NOSUPPORT` if
+    ///   there are no more keys.
+    /// - `key`: The key buffer, holding the hashed key that was found
+    fn next_key_complete(&self, result: Result<(usize, KeyCursor), ErrorCode>, key: &'static mut K);
 }
 
 pub trait KVSystem<'a> {
@@ -193,6 +208,45 @@ pub trait KVSystem<'a> {
     /// - `INVAL`: An invalid parameter was passed.
     /// - `NODEVICE`: No KV store was setup.
     fn garbage_collect(&self) -> Result<(), ErrorCode>;
+
+    /// Returns the number of regions in the KV Store.
+    fn region_count(&self) -> usize;
+
+    /// Read the erase count and the used, reclaimable and free bytes of a
+    /// region.
+    ///
+    /// - `region`: The region to read, less than `region_count()`.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `ErrorCode`s are:
+    /// - `BUSY`: An operation is already in progress.
+    /// - `INVAL`: The region does not exist.
+    /// - `FAIL`: The region could not be read.
+    fn region_health(&self, region: usize) -> Result<(), ErrorCode>;
+
+    /// Find the next valid key after a cursor.
+    ///
+    /// Keys are found in the order they are stored, and the hashed keys are
+    /// returned.
+    ///
+    /// - `cursor`: The position to start looking from. Use
+    ///   `KeyCursor::default()` to start from the beginning of the store.
+    /// - `key`: A buffer to store the hashed key that was found.
+    ///
+    /// On success nothing will be returned.
+    /// On error the key and `Result<(), ErrorCode>` will be returned.
+    ///
+    /// The possible `ErrorCode`s are:
+    /// - `BUSY`: An operation is already in progress.
+    /// - `NOSUPPORT`: There are no more keys.
+    /// - `FAIL`: An internal failure.
+    fn next_key(
+        &self,
+        cursor: KeyCursor,
+        key: &'static mut Self::K,
+    ) -> Result<(), (&'static mut Self::K, ErrorCode)>;
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -203,6 +257,8 @@ enum Operation {
     AppendKey,
     InvalidateKey,
     GarbageCollect,
+    RegionHealth,
+    NextKey,
 }
 
 /// Wrapper object that provides the flash interface TicKV expects using the
@@ -302,6 +358,8 @@ pub struct TicKVSystem<'a, F: Flash + 'static, H: Hasher<'a, 8>, const PAGE_SIZE
     /// Holder for a buffer containing a value being read from or written to the
     /// key-value store.
     value_buffer: MapCell<SubSliceMut<'static, u8>>,
+    /// The region read by the region health operation.
+    region: Cell<usize>,
     /// Callback client when the `KVSystem` operation completes.
     client: OptionalCell<&'a dyn KVSystemClient<TicKVKeyType>>,
 }
@@ -329,6 +387,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> TicKVSystem<'a, F,
             unhashed_key_buffer: MapCell::empty(),
             key_buffer: TakeCell::empty(),
             value_buffer: MapCell::empty(),
+            region: Cell::new(0),
             client: OptionalCell::empty(),
         }
     }
@@ -341,7 +400,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> TicKVSystem<'a, F,
     fn complete_init(&self) {
         self.operation.set(Operation::None);
         match self.next_operation.get() {
-            Operation::None | Operation::Init => {}
+            Operation::None | Operation::Init | Operation::RegionHealth | Operation::NextKey => {}
             Operation::GetKey => {
                 if let Err((key, value, error)) = self.get_value(
                     self.key_buffer.take().unwrap(),
@@ -554,6 +613,44 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                 }
                 _ => {}
             },
+            Operation::RegionHealth => {
+                self.operation.set(Operation::None);
+                let result = ret
+                    .ok()
+                    .and(self.tickv.take_region_health())
+                    .map(|health| KVRegionHealth {
+                        erase_count: health.erase_count,
+                        used: health.used,
+                        reclaimable: health.reclaimable,
+                        free: health.free,
+                    })
+                    .ok_or(ErrorCode::FAIL);
+                self.client.map(|cb| {
+                    cb.region_health_complete(result, self.region.get());
+                });
+            }
+            Operation::NextKey => match ret {
+                Err(tickv::error_codes::ErrorCode::ReadNotReady(_)) => {
+                    // Need to read the next region.
+                }
+                _ => {
+                    self.operation.set(Operation::None);
+                    let key = self.key_buffer.take().unwrap();
+                    let result = match (ret, self.tickv.take_key_entry()) {
+                        (Ok(_), Some(entry)) => {
+                            *key = entry.hashed_key.to_be_bytes();
+                            Ok((entry.value_length, entry.next))
+                        }
+                        (Err(tickv::error_codes::ErrorCode::KeyNotFound), _) => {
+                            Err(ErrorCode::NOSUPPORT)
+                        }
+                        _ => Err(ErrorCode::FAIL),
+                    };
+                    self.client.map(move |cb| {
+                        cb.next_key_complete(result, key);
+                    });
+                }
+            },
             _ => unreachable!(),
         }
     }
@@ -585,6 +682,29 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                     cb.invalidate_key_complete(Ok(()), self.key_buffer.take().unwrap());
                 });
             }
+            Operation::GarbageCollect => {
+                // The region header of an erased region has been written,
+                // continue with the next region.
+                let (ret, tickv_buf, tickv_buf_len) = self.tickv.continue_operation();
+
+                // If we got the buffer back from TicKV then store it.
+                tickv_buf.map(|buf| {
+                    let mut val_buf = SubSliceMut::new(buf);
+                    if tickv_buf_len > 0 {
+                        val_buf.slice(0..tickv_buf_len);
+                    }
+                    self.value_buffer.replace(val_buf);
+                });
+
+                if let Ok(tickv::success_codes::SuccessCode::Complete)
+                | Ok(tickv::success_codes::SuccessCode::Written) = ret
+                {
+                    self.operation.set(Operation::None);
+                    self.client.map(|cb| {
+                        cb.garbage_collect_complete(Ok(()));
+                    });
+                }
+            }
             _ => unreachable!(),
         }
     }
@@ -777,4 +897,66 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
             }
         }
     }
+
+    fn region_count(&self) -> usize {
+        self.tickv.tickv.region_count()
+    }
+
+    fn region_health(&self, region: usize) -> Result<(), ErrorCode> {
+        if region >= self.region_count() {
+            return Err(ErrorCode::INVAL);
+        }
+
+        match self.operation.get() {
+            Operation::None => {
+                self.operation.set(Operation::RegionHealth);
+                self.region.set(region);
+
+                match self.tickv.region_health(region) {
+                    Ok(_ret) => Ok(()),
+                    Err(_e) => {
+                        self.operation.set(Operation::None);
+                        Err(ErrorCode::FAIL)
+                    }
+                }
+            }
+            _ => {
+                // An operation is already in process, or the init process is
+                // still occurring.
+                Err(ErrorCode::BUSY)
+            }
+        }
+    }
+
+    fn next_key(
+        &self,
+        cursor: KeyCursor,
+        key: &'static mut Self::K,
+    ) -> Result<(), (&'static mut Self::K, ErrorCode)> {
+        match self.operation.get() {
+            Operation::None => {
+                self.operation.set(Operation::NextKey);
+
+                match self.tickv.next_key(cursor) {
+                    Ok(_ret) => {
+                        self.key_buffer.replace(key);
+                        Ok(())
+                    }
+                    Err(tickv::error_codes::ErrorCode::KeyNotFound) => {
+                        self.operation.set(Operation::None);
+                        Err((key, ErrorCode::NOSUPPORT))
+                    }
+                    Err(_e) => {
+                        self.operation.set(Operation::None);
+                        Err((key, ErrorCode::FAIL))
+                    }
+                }
+            }
+            _ => {
+                // An operation is already in process, or the init process is
+                // still occurring.
+                Err((key, ErrorCode::BUSY))
+            }
+        }
+    }
 }
@@ -32,6 +32,7 @@ use kernel::ErrorCode;
 use kernel::hil::kv;
 use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
 use kernel::utilities::leasable_buffer::SubSliceMut;
+use tickv::KeyCursor;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 enum Operation {
@@ -41,6 +42,7 @@ enum Operation {
     Update,
     Delete,
     GarbageCollect,
+    RegionHealth,
 }
 
 /// `TicKVKVStore` implements the KV interface using the TicKV KVSystem
@@ -50,6 +52,7 @@ pub struct TicKVKVStore<'a, K: KVSystem<'a> + KVSystem<'a, K = T>, T: 'static +
     hashed_key: TakeCell<'static, T>,
 
     client: OptionalCell<&'a dyn kv::KVClient>,
+    health_client: OptionalCell<&'a dyn kv::KVHealthClient>,
     operation: OptionalCell<Operation>,
 
     unhashed_key: MapCell<SubSliceMut<'static, u8>>,
@@ -62,6 +65,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> TicKVKVStore<'a, K, T> {
             kv,
             hashed_key: TakeCell::new(key),
             client: OptionalCell::empty(),
+            health_client: OptionalCell::empty(),
             operation: OptionalCell::empty(),
             unhashed_key: MapCell::empty(),
             value: MapCell::empty(),
@@ -227,6 +231,31 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> kv::KV<'a> for TicKVKVStore<'a, K,
     }
 }
 
+impl<'a, K: KVSystem<'a, K = T>, T: KeyType> kv::KVHealth<'a> for TicKVKVStore<'a, K, T> {
+    fn set_health_client(&self, client: &'a dyn kv::KVHealthClient) {
+        self.health_client.set(client);
+    }
+
+    fn region_count(&self) -> usize {
+        self.kv.region_count()
+    }
+
+    fn region_health(&self, region: usize) -> Result<(), ErrorCode> {
+        if self.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.operation.set(Operation::RegionHealth);
+
+        if let Err(e) = self.kv.region_health(region) {
+            self.operation.clear();
+            Err(e)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<'a, K, T> {
     fn generate_key_complete(
         &self,
@@ -276,7 +305,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
                             cb.delete_complete(Err(ErrorCode::FAIL), unhashed_key);
                         });
                     }
-                    Operation::GarbageCollect => {}
+                    Operation::GarbageCollect | Operation::RegionHealth => {}
                 }
             } else {
                 match op {
@@ -365,7 +394,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
                             });
                         }
                     },
-                    Operation::GarbageCollect => {}
+                    Operation::GarbageCollect | Operation::RegionHealth => {}
                 }
             }
         });
@@ -457,7 +486,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
                     });
                 });
             }
-            Operation::GarbageCollect => {}
+            Operation::GarbageCollect | Operation::RegionHealth => {}
         });
     }
 
@@ -580,7 +609,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
                     });
                 });
             }
-            Operation::GarbageCollect => {}
+            Operation::GarbageCollect | Operation::RegionHealth => {}
         });
     }
 
@@ -590,4 +619,19 @@ impl<'a, K: KVSystem<'a, K = T>, T: KeyType> KVSystemClient<T> for TicKVKVStore<
             cb.garbage_collection_complete(result);
         });
     }
+
+    fn region_health_complete(&self, result: Result<kv::KVRegionHealth, ErrorCode>, region: usize) {
+        self.operation.clear();
+        self.health_client.map(move |cb| {
+            cb.region_health_complete(result, region);
+        });
+    }
+
+    fn next_key_complete(
+        &self,
+        _result: Result<(usize, KeyCursor), ErrorCode>,
+        _key: &'static mut T,
+    ) {
+        // Keys are not listed through the K-V store.
+    }
 }
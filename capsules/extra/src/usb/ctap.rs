@@ -153,6 +153,7 @@ impl<'a, U: hil::usb::UsbController<'a>> CtapHid<'a, U> {
                 endpoints,
                 Some(&HID_DESCRIPTOR),
                 None,
+                None, // No DFU descriptor
             );
 
         CtapHid {
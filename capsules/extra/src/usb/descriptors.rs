@@ -436,6 +436,7 @@ pub fn create_descriptor_buffers(
     endpoint_descriptors: &[&[EndpointDescriptor]],
     hid_descriptor: Option<&HIDDescriptor>,
     cdc_descriptor: Option<&[CdcInterfaceDescriptor]>,
+    dfu_descriptor: Option<&DfuFunctionalDescriptor>,
 ) -> (DeviceBuffer, DescriptorBuffer) {
     // Create device descriptor buffer and fill.
     // Cell doesn't implement Copy, so here we are.
@@ -506,8 +507,12 @@ pub fn create_descriptor_buffers(
     // descriptors.
 
     // Configuration Descriptor. We assume there is only one configuration
-    // descriptor, since this is very common for most USB devices.
-    configuration_descriptor.num_interfaces = interface_descriptor.len() as u8;
+    // descriptor, since this is very common for most USB devices. Alternate
+    // settings of an interface do not count as separate interfaces.
+    configuration_descriptor.num_interfaces = interface_descriptor
+        .iter()
+        .filter(|d| d.alternate_setting == 0)
+        .count() as u8;
 
     // Calculate the length of all dependent descriptors.
     // TODO should we be erroring here if len > 128? Otherwise we'll probably
@@ -519,7 +524,8 @@ pub fn create_descriptor_buffers(
                 .map(|descs| descs.iter().map(|d| d.size()).sum::<usize>())
                 .sum::<usize>()
             + hid_descriptor.map_or(0, |d| d.size())
-            + cdc_descriptor.map_or(0, |ds| ds.iter().map(|d| d.size()).sum::<usize>());
+            + cdc_descriptor.map_or(0, |ds| ds.iter().map(|d| d.size()).sum::<usize>())
+            + dfu_descriptor.map_or(0, |d| d.size());
 
     // Set the number of endpoints for each interface descriptor.
     for (i, d) in interface_descriptor.iter_mut().enumerate() {
@@ -555,6 +561,15 @@ pub fn create_descriptor_buffers(
             }
         }
 
+        // If there is a DFU functional descriptor, we include
+        // it with the first interface descriptor.
+        if i == 0 {
+            // DFU descriptor, if any.
+            if let Some(ddfu) = dfu_descriptor {
+                len += ddfu.write_to(&other_buf.buf[len..]);
+            }
+        }
+
         // Endpoints for each interface.
         for de in endpoint_descriptors[i] {
             len += de.write_to(&other_buf.buf[len..]);
@@ -873,6 +888,42 @@ impl Descriptor for CdcInterfaceDescriptor {
     }
 }
 
+/// The DFU functional descriptor, which tells the host how a Device Firmware
+/// Upgrade interface accepts firmware.
+pub struct DfuFunctionalDescriptor {
+    pub can_download: bool,
+    pub can_upload: bool,
+    /// The device can still communicate over USB after the manifestation phase.
+    pub manifestation_tolerant: bool,
+    /// The device detaches and reattaches itself on a DFU_DETACH request.
+    pub will_detach: bool,
+    /// Time in ms the device waits for a USB reset after a DFU_DETACH request.
+    pub detach_timeout: u16,
+    /// Maximum number of bytes the device accepts per control write.
+    pub transfer_size: u16,
+}
+
+impl Descriptor for DfuFunctionalDescriptor {
+    fn size(&self) -> usize {
+        9
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        buf[0].set(9); // Size of descriptor
+        buf[1].set(0x21); // DFU FUNCTIONAL
+        buf[2].set(
+            u8::from(self.can_download)
+                | u8::from(self.can_upload) << 1
+                | u8::from(self.manifestation_tolerant) << 2
+                | u8::from(self.will_detach) << 3,
+        );
+        put_u16(&buf[3..5], self.detach_timeout);
+        put_u16(&buf[5..7], self.transfer_size);
+        put_u16(&buf[7..9], 0x0110); // DFU 1.1
+        9
+    }
+}
+
 /// The data structure sent in a CDC-ACM Set Line Coding message.
 #[derive(Debug, Copy, Clone)]
 pub struct CdcAcmSetLineCodingData {
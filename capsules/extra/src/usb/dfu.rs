@@ -0,0 +1,969 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Device Firmware Upgrade Class Device for USB
+//!
+//! This capsule implements the USB DFU 1.1 protocol so that standard host
+//! tools such as `dfu-util` can install new applications on a Tock device
+//! without a debugger or an external bootloader.
+//!
+//! The device always enumerates in DFU mode and exposes up to two alternate
+//! settings:
+//!
+//! - Alternate setting 0 writes a TBF application binary through the
+//!   [`DynamicBinaryStore`](dynamic_binary_storage::DynamicBinaryStore)
+//!   interface. The total size of the binary is taken from the TBF header in
+//!   the first block. Once the host signals the end of the download, the binary
+//!   is finalized and the new process is loaded.
+//! - Alternate setting 1, if a staging area is provided, writes the image
+//!   as-is into a region of a
+//!   [`NonvolatileStorage`](hil::nonvolatile_storage::NonvolatileStorage)
+//!   device, typically `NonvolatileToPages` on top of the chip's flash
+//!   controller. Installing a staged kernel image is left to the bootloader.
+//!
+//! Uploads are not supported. The device is manifestation tolerant, so it
+//! returns to the idle state once a download has been installed and another
+//! download can follow without a reset.
+//!
+//! As this capsule is the client of the binary store and the process loader,
+//! it cannot be used together with the `app_loader` capsule on the same
+//! storage.
+//!
+//! Usage
+//! -----
+//!
+//! ```shell
+//! $ dfu-util -a 0 -D app.tbf
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use super::descriptors;
+use super::descriptors::DfuFunctionalDescriptor;
+use super::descriptors::InterfaceDescriptor;
+use super::usbc_client_ctrl::ClientCtrl;
+
+use kernel::ErrorCode;
+use kernel::dynamic_binary_storage;
+use kernel::hil;
+use kernel::hil::usb::TransferType;
+use kernel::process::ProcessLoadError;
+use kernel::utilities::cells::TakeCell;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+
+/// Number of bytes the host sends in each DFU_DNLOAD request. The buffer passed
+/// to [`Dfu::new`] must be at least this long.
+pub const TRANSFER_SIZE: usize = 1024;
+
+/// Time in ms the host should wait before asking for the status again while
+/// we are writing a block or installing the image.
+const POLL_TIMEOUT_MS: u32 = 10;
+
+static LANGUAGES: &[u16; 1] = &[
+    0x0409, // English (United States)
+];
+
+/// Alternate setting writing applications through the dynamic binary store.
+const ALT_APP: u8 = 0;
+/// Alternate setting writing to the staging area.
+const ALT_STAGING: u8 = 1;
+
+/// Class-specific requests defined by the DFU specification.
+#[derive(PartialEq)]
+enum DfuRequest {
+    Detach = 0,
+    Dnload = 1,
+    Upload = 2,
+    GetStatus = 3,
+    ClrStatus = 4,
+    GetState = 5,
+    Abort = 6,
+    NotSupported,
+}
+
+impl From<u8> for DfuRequest {
+    fn from(num: u8) -> Self {
+        match num {
+            0 => DfuRequest::Detach,
+            1 => DfuRequest::Dnload,
+            2 => DfuRequest::Upload,
+            3 => DfuRequest::GetStatus,
+            4 => DfuRequest::ClrStatus,
+            5 => DfuRequest::GetState,
+            6 => DfuRequest::Abort,
+            _ => DfuRequest::NotSupported,
+        }
+    }
+}
+
+/// Standard SET_INTERFACE request code, used by the host to select an
+/// alternate setting.
+const SET_INTERFACE: u8 = 11;
+
+/// DFU device states, with the values reported in DFU_GETSTATUS and
+/// DFU_GETSTATE.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    /// Waiting for a download to start.
+    Idle = 2,
+    /// Receiving a block from the host.
+    DnloadSync = 3,
+    /// Writing a block to storage.
+    DnBusy = 4,
+    /// Waiting for the next block or the end of the download.
+    DnloadIdle = 5,
+    /// Installing the downloaded image.
+    Manifest = 7,
+    /// A download failed. The host must clear the status to continue.
+    Error = 10,
+}
+
+/// Status codes reported in DFU_GETSTATUS.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Status {
+    Ok = 0x00,
+    ErrTarget = 0x01,
+    ErrFile = 0x02,
+    ErrWrite = 0x03,
+    ErrAddress = 0x08,
+    ErrNotDone = 0x09,
+    ErrFirmware = 0x0a,
+    ErrStalledPkt = 0x0f,
+}
+
+/// States of the Control Endpoint related to DFU.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum CtrlState {
+    /// No ongoing ctrl transaction, or one handled by `ClientCtrl`.
+    Idle,
+    /// The host is sending a block with DFU_DNLOAD.
+    Download,
+    /// The host has sent a DFU_GETSTATUS request.
+    GetStatus,
+    /// The host has sent a DFU_GETSTATE request.
+    GetState,
+}
+
+/// USB Device Firmware Upgrade class device.
+pub struct Dfu<'a, U: 'a> {
+    /// Helper USB client library for handling many USB operations.
+    client_ctrl: ClientCtrl<'a, 'static, U>,
+
+    /// Storage for new application binaries.
+    app_store: &'a dyn dynamic_binary_storage::DynamicBinaryStore,
+    /// Loader for the new process once its binary is stored.
+    app_loader: &'a dyn dynamic_binary_storage::DynamicProcessLoad,
+    /// Optional storage for staging a kernel image.
+    staging: Option<&'a dyn hil::nonvolatile_storage::NonvolatileStorage<'a>>,
+    /// Address in `staging` of the start of the staging area.
+    staging_address: usize,
+    /// Size of the staging area in bytes.
+    staging_length: usize,
+
+    /// Current DFU state.
+    state: Cell<State>,
+    /// Status reported to the host, explaining the error state.
+    status: Cell<Status>,
+    /// Current state of the Control Endpoint.
+    ctrl_state: Cell<CtrlState>,
+    /// Alternate setting selected by the host.
+    alternate_setting: Cell<u8>,
+
+    /// Buffer holding the block being downloaded. It is lost if the storage
+    /// refuses to write it.
+    buffer: TakeCell<'static, [u8]>,
+    /// Length of the block being downloaded.
+    block_length: Cell<usize>,
+    /// Number of bytes of the block received so far.
+    block_received: Cell<usize>,
+    /// Offset in the image of the block being downloaded.
+    image_offset: Cell<usize>,
+    /// Total length of the application binary being downloaded.
+    image_length: Cell<usize>,
+    /// Whether `app_store` has been set up for a new binary which is not yet
+    /// finalized or aborted.
+    app_setup: Cell<bool>,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> Dfu<'a, U> {
+    pub fn new(
+        controller: &'a U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 5],
+        app_store: &'a dyn dynamic_binary_storage::DynamicBinaryStore,
+        app_loader: &'a dyn dynamic_binary_storage::DynamicProcessLoad,
+        staging: Option<&'a dyn hil::nonvolatile_storage::NonvolatileStorage<'a>>,
+        staging_address: usize,
+        staging_length: usize,
+        buffer: &'static mut [u8],
+    ) -> Self {
+        let interfaces: &mut [InterfaceDescriptor] = &mut [
+            InterfaceDescriptor {
+                interface_number: 0,
+                alternate_setting: ALT_APP,
+                interface_class: 0xfe,    // Application specific
+                interface_subclass: 0x01, // Device firmware upgrade
+                interface_protocol: 0x02, // DFU mode
+                string_index: 4,
+                ..InterfaceDescriptor::default()
+            },
+            InterfaceDescriptor {
+                interface_number: 0,
+                alternate_setting: ALT_STAGING,
+                interface_class: 0xfe,    // Application specific
+                interface_subclass: 0x01, // Device firmware upgrade
+                interface_protocol: 0x02, // DFU mode
+                string_index: 5,
+                ..InterfaceDescriptor::default()
+            },
+        ];
+        let num_alternate_settings = if staging.is_some() { 2 } else { 1 };
+
+        let dfu_descriptor = DfuFunctionalDescriptor {
+            can_download: true,
+            can_upload: false,
+            manifestation_tolerant: true,
+            will_detach: false,
+            detach_timeout: 0,
+            transfer_size: TRANSFER_SIZE as u16,
+        };
+
+        let (device_descriptor_buffer, other_descriptor_buffer) =
+            descriptors::create_descriptor_buffers(
+                descriptors::DeviceDescriptor {
+                    vendor_id,
+                    product_id,
+                    manufacturer_string: 1,
+                    product_string: 2,
+                    serial_number_string: 3,
+                    max_packet_size_ep0: max_ctrl_packet_size,
+                    ..descriptors::DeviceDescriptor::default()
+                },
+                descriptors::ConfigurationDescriptor::default(),
+                &mut interfaces[..num_alternate_settings],
+                &[&[], &[]], // DFU only uses the control endpoint
+                None,        // No HID descriptor
+                None,        // No CDC descriptor array
+                Some(&dfu_descriptor),
+            );
+
+        Self {
+            client_ctrl: ClientCtrl::new(
+                controller,
+                device_descriptor_buffer,
+                other_descriptor_buffer,
+                None, // No HID descriptor
+                None, // No report descriptor
+                LANGUAGES,
+                strings,
+            ),
+            app_store,
+            app_loader,
+            staging,
+            staging_address,
+            staging_length,
+            state: Cell::new(State::Idle),
+            status: Cell::new(Status::Ok),
+            ctrl_state: Cell::new(CtrlState::Idle),
+            alternate_setting: Cell::new(ALT_APP),
+            buffer: TakeCell::new(buffer),
+            block_length: Cell::new(0),
+            block_received: Cell::new(0),
+            image_offset: Cell::new(0),
+            image_length: Cell::new(0),
+            app_setup: Cell::new(false),
+        }
+    }
+
+    #[inline]
+    pub fn controller(&self) -> &'a U {
+        self.client_ctrl.controller()
+    }
+
+    /// Whether we are waiting on the storage or the process loader.
+    fn busy(&self) -> bool {
+        matches!(self.state.get(), State::DnBusy | State::Manifest)
+    }
+
+    /// Handle a DFU class request. Returns `None` if the request should be
+    /// stalled.
+    fn handle_request(&self, request: DfuRequest, length: usize) -> Option<()> {
+        match request {
+            DfuRequest::Detach => {
+                // We are always in DFU mode, there is nothing to detach from.
+                Some(())
+            }
+            DfuRequest::Dnload => match self.state.get() {
+                State::Idle | State::DnloadIdle if length > 0 => {
+                    let Some(capacity) = self.buffer.map(|buf| buf.len()) else {
+                        // The buffer was lost when the storage refused a write.
+                        self.error(Status::ErrTarget);
+                        return None;
+                    };
+                    if length > capacity {
+                        self.error(Status::ErrStalledPkt);
+                        return None;
+                    }
+                    self.block_length.set(length);
+                    self.block_received.set(0);
+                    self.state.set(State::DnloadSync);
+                    self.ctrl_state.set(CtrlState::Download);
+                    Some(())
+                }
+                State::DnloadIdle => {
+                    // A zero length download ends the image.
+                    self.manifest();
+                    Some(())
+                }
+                _ => {
+                    self.error(Status::ErrStalledPkt);
+                    None
+                }
+            },
+            DfuRequest::GetStatus => {
+                self.ctrl_state.set(CtrlState::GetStatus);
+                Some(())
+            }
+            DfuRequest::GetState => {
+                self.ctrl_state.set(CtrlState::GetState);
+                Some(())
+            }
+            DfuRequest::ClrStatus => {
+                if self.state.get() == State::Error {
+                    self.state.set(State::Idle);
+                    self.status.set(Status::Ok);
+                }
+                Some(())
+            }
+            DfuRequest::Abort => {
+                if self.busy() {
+                    None
+                } else {
+                    self.cancel();
+                    self.state.set(State::Idle);
+                    Some(())
+                }
+            }
+            DfuRequest::Upload | DfuRequest::NotSupported => {
+                self.error(Status::ErrStalledPkt);
+                None
+            }
+        }
+    }
+
+    /// Write the block we just received.
+    fn write_block(&self) {
+        self.state.set(State::DnBusy);
+
+        match self.alternate_setting.get() {
+            ALT_APP => {
+                if self.app_setup.get() {
+                    self.write_app_block();
+                    return;
+                }
+
+                // This is the first block of the binary. Find its total size
+                // in the TBF header and ask for space to store it.
+                let total_size = self.buffer.map_or(None, |buf| {
+                    if self.block_length.get() < 8 {
+                        None
+                    } else {
+                        Some(u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize)
+                    }
+                });
+                match total_size {
+                    Some(total_size) => match self.app_store.setup(total_size) {
+                        Ok(_) => {
+                            // Wait for `setup_done()` to write the block.
+                            self.app_setup.set(true);
+                            self.image_length.set(total_size);
+                        }
+                        Err(_) => self.error(Status::ErrAddress),
+                    },
+                    None => self.error(Status::ErrFile),
+                }
+            }
+            _ => {
+                let offset = self.image_offset.get();
+                let length = self.block_length.get();
+                if offset + length > self.staging_length {
+                    self.error(Status::ErrAddress);
+                    return;
+                }
+                match (self.staging, self.buffer.take()) {
+                    (Some(staging), Some(buf)) => {
+                        // The storage does not return the buffer on error.
+                        if staging
+                            .write(buf, self.staging_address + offset, length)
+                            .is_err()
+                        {
+                            self.error(Status::ErrWrite);
+                        }
+                    }
+                    _ => self.error(Status::ErrTarget),
+                }
+            }
+        }
+    }
+
+    /// Write the block we just received to the application binary.
+    fn write_app_block(&self) {
+        let offset = self.image_offset.get();
+        let length = self.block_length.get();
+        if offset + length > self.image_length.get() {
+            self.error(Status::ErrAddress);
+            return;
+        }
+        match self.buffer.take() {
+            Some(buf) => {
+                let mut write_buffer = SubSliceMut::new(buf);
+                write_buffer.slice(..length);
+                // The storage does not return the buffer on error.
+                if self.app_store.write(write_buffer, offset).is_err() {
+                    self.error(Status::ErrWrite);
+                }
+            }
+            None => self.error(Status::ErrTarget),
+        }
+    }
+
+    /// The block we were writing has been stored.
+    fn block_done(&self) {
+        self.image_offset
+            .set(self.image_offset.get() + self.block_length.get());
+        self.state.set(State::DnloadIdle);
+    }
+
+    /// Install the downloaded image.
+    fn manifest(&self) {
+        match self.alternate_setting.get() {
+            ALT_APP => {
+                if !self.app_setup.get() || self.image_offset.get() != self.image_length.get() {
+                    self.error(Status::ErrNotDone);
+                    return;
+                }
+                self.state.set(State::Manifest);
+                if self.app_store.finalize().is_err() {
+                    self.error(Status::ErrFirmware);
+                }
+            }
+            _ => {
+                // The bootloader installs staged images, we are done.
+                self.manifest_done();
+            }
+        }
+    }
+
+    fn manifest_done(&self) {
+        self.image_offset.set(0);
+        self.image_length.set(0);
+        self.state.set(State::Idle);
+    }
+
+    /// Give up on the current download.
+    fn cancel(&self) {
+        if self.app_setup.take() {
+            let _ = self.app_store.abort();
+        }
+        self.image_offset.set(0);
+        self.image_length.set(0);
+    }
+
+    /// Give up on the current download and report `status` to the host. If
+    /// we are already in the error state, the first error is kept until the
+    /// host clears it.
+    fn error(&self, status: Status) {
+        self.cancel();
+        if self.state.get() != State::Error {
+            self.status.set(status);
+        }
+        self.state.set(State::Error);
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for Dfu<'a, U> {
+    fn enable(&'a self) {
+        // Set up the default control endpoint
+        self.client_ctrl.enable();
+    }
+
+    fn attach(&'a self) {
+        self.client_ctrl.attach();
+    }
+
+    fn bus_reset(&'a self) {
+        if !self.busy() {
+            self.cancel();
+            self.state.set(State::Idle);
+            self.status.set(Status::Ok);
+        }
+        self.alternate_setting.set(ALT_APP);
+    }
+
+    /// Handle a Control Setup transaction.
+    ///
+    /// We handle the DFU class requests here, as well as SET_INTERFACE which
+    /// the host uses to choose where the download goes.
+    fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        let Some(setup_data) = descriptors::SetupData::get(&self.client_ctrl.ctrl_buffer.buf)
+        else {
+            return self.client_ctrl.ctrl_setup(endpoint);
+        };
+
+        match setup_data.request_type.request_type() {
+            descriptors::RequestType::Class => {
+                match self.handle_request(
+                    DfuRequest::from(setup_data.request_code),
+                    setup_data.length as usize,
+                ) {
+                    Some(()) => hil::usb::CtrlSetupResult::Ok,
+                    None => hil::usb::CtrlSetupResult::ErrGeneric,
+                }
+            }
+            descriptors::RequestType::Standard
+                if setup_data.request_code == SET_INTERFACE
+                    && matches!(
+                        setup_data.request_type.recipient(),
+                        descriptors::Recipient::Interface
+                    ) =>
+            {
+                let alternate_setting = setup_data.value;
+                let num_alternate_settings = if self.staging.is_some() { 2 } else { 1 };
+                if alternate_setting >= num_alternate_settings || self.busy() {
+                    hil::usb::CtrlSetupResult::ErrGeneric
+                } else {
+                    if alternate_setting as u8 != self.alternate_setting.get() {
+                        self.cancel();
+                        self.alternate_setting.set(alternate_setting as u8);
+                    }
+                    hil::usb::CtrlSetupResult::Ok
+                }
+            }
+            _ => self.client_ctrl.ctrl_setup(endpoint),
+        }
+    }
+
+    /// Handle a Control In transaction
+    fn ctrl_in(&'a self, endpoint: usize) -> hil::usb::CtrlInResult {
+        let buf = &self.client_ctrl.ctrl_buffer.buf;
+        match self.ctrl_state.get() {
+            CtrlState::GetStatus => {
+                let poll_timeout = if self.busy() { POLL_TIMEOUT_MS } else { 0 };
+                let poll_timeout = poll_timeout.to_le_bytes();
+                buf[0].set(self.status.get() as u8);
+                buf[1].set(poll_timeout[0]);
+                buf[2].set(poll_timeout[1]);
+                buf[3].set(poll_timeout[2]);
+                buf[4].set(self.state.get() as u8);
+                buf[5].set(0); // No status description string
+                hil::usb::CtrlInResult::Packet(6, true)
+            }
+            CtrlState::GetState => {
+                buf[0].set(self.state.get() as u8);
+                hil::usb::CtrlInResult::Packet(1, true)
+            }
+            _ => self.client_ctrl.ctrl_in(endpoint),
+        }
+    }
+
+    /// Handle a Control Out transaction
+    fn ctrl_out(&'a self, endpoint: usize, packet_bytes: u32) -> hil::usb::CtrlOutResult {
+        if self.ctrl_state.get() != CtrlState::Download {
+            return self.client_ctrl.ctrl_out(endpoint, packet_bytes);
+        }
+
+        let received = self.block_received.get();
+        let length = self.block_length.get();
+        let copy_length = cmp::min(packet_bytes as usize, length - received);
+        let packet = &self.client_ctrl.ctrl_buffer.buf;
+        self.buffer.map(|buf| {
+            for i in 0..copy_length {
+                buf[received + i] = packet[i].get();
+            }
+        });
+        self.block_received.set(received + copy_length);
+
+        if received + copy_length >= length {
+            self.ctrl_state.set(CtrlState::Idle);
+            self.write_block();
+        }
+        hil::usb::CtrlOutResult::Ok
+    }
+
+    fn ctrl_status(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status(endpoint)
+    }
+
+    /// Handle the completion of a Control transfer
+    fn ctrl_status_complete(&'a self, endpoint: usize) {
+        self.ctrl_state.set(CtrlState::Idle);
+        self.client_ctrl.ctrl_status_complete(endpoint)
+    }
+
+    fn packet_in(&'a self, _transfer_type: TransferType, _endpoint: usize) -> hil::usb::InResult {
+        // DFU only uses the control endpoint.
+        hil::usb::InResult::Delay
+    }
+
+    fn packet_out(
+        &'a self,
+        _transfer_type: TransferType,
+        _endpoint: usize,
+        _packet_bytes: u32,
+    ) -> hil::usb::OutResult {
+        // DFU only uses the control endpoint.
+        hil::usb::OutResult::Ok
+    }
+
+    fn packet_transmitted(&'a self, _endpoint: usize) {}
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> dynamic_binary_storage::DynamicBinaryStoreClient
+    for Dfu<'a, U>
+{
+    fn setup_done(&self, result: Result<(), ErrorCode>) {
+        if self.state.get() != State::DnBusy {
+            return;
+        }
+        match result {
+            Ok(()) => self.write_app_block(),
+            Err(_) => self.error(Status::ErrAddress),
+        }
+    }
+
+    fn write_done(&self, result: Result<(), ErrorCode>, buffer: &'static mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+        if self.state.get() != State::DnBusy {
+            return;
+        }
+        match result {
+            Ok(()) => self.block_done(),
+            Err(_) => self.error(Status::ErrWrite),
+        }
+    }
+
+    fn finalize_done(&self, result: Result<(), ErrorCode>) {
+        if self.state.get() != State::Manifest {
+            return;
+        }
+        self.app_setup.set(false);
+        match result.and_then(|()| self.app_loader.load()) {
+            // Wait for `load_done()`.
+            Ok(()) => {}
+            Err(_) => self.error(Status::ErrFirmware),
+        }
+    }
+
+    fn abort_done(&self, _result: Result<(), ErrorCode>) {}
+
+    fn compact_done(&self, _result: Result<(), ErrorCode>, _moved: usize) {}
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> dynamic_binary_storage::DynamicProcessLoadClient
+    for Dfu<'a, U>
+{
+    fn load_done(&self, result: Result<(), ProcessLoadError>) {
+        if self.state.get() != State::Manifest {
+            return;
+        }
+        match result {
+            Ok(()) => self.manifest_done(),
+            Err(_) => self.error(Status::ErrFirmware),
+        }
+    }
+
+    fn update_done(&self, _result: Result<(), ProcessLoadError>) {}
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::nonvolatile_storage::NonvolatileStorageClient
+    for Dfu<'a, U>
+{
+    fn read_done(&self, buffer: &'static mut [u8], _length: usize) {
+        self.buffer.replace(buffer);
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        if self.state.get() != State::DnBusy {
+            return;
+        }
+        if length == self.block_length.get() {
+            self.block_done();
+        } else {
+            self.error(Status::ErrWrite);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use capsules_test_harness::loader::AppFlash;
+    use capsules_test_harness::process::{HostKernel, Tbf};
+    use capsules_test_harness::{deferred_call, leak, run_until_idle, static_buf};
+    use core::num::NonZeroU32;
+    use kernel::dynamic_binary_storage::{DynamicBinaryStore, DynamicProcessLoad};
+    use kernel::hil::usb::Client as _;
+    use kernel::process::ShortId;
+    use kernel::utilities::registers::InMemoryRegister;
+
+    const FLASH_SIZE: usize = 8192;
+    /// Binaries are a power of two long, so that the loader stores them back
+    /// to back. This one is sent in two blocks.
+    const BINARY_SIZE: usize = 2 * TRANSFER_SIZE;
+    const APP_ID: u32 = 0x20;
+
+    static STRINGS: &[&str; 5] = &["Tock", "DFU", "0", "Application", "Staging"];
+
+    /// USB controller for tests which call the control endpoint handlers of
+    /// the DFU class directly.
+    struct NoUsb;
+
+    impl hil::usb::UsbController<'static> for NoUsb {
+        fn set_client(&self, _client: &'static dyn hil::usb::Client<'static>) {}
+        fn endpoint_set_ctrl_buffer(&self, _buf: &'static [InMemoryRegister<u8>]) {}
+        fn endpoint_set_in_buffer(&self, _endpoint: usize, _buf: &'static [InMemoryRegister<u8>]) {}
+        fn endpoint_set_out_buffer(&self, _endpoint: usize, _buf: &'static [InMemoryRegister<u8>]) {
+        }
+        fn enable_as_device(&self, _speed: hil::usb::DeviceSpeed) {}
+        fn attach(&self) {}
+        fn detach(&self) {}
+        fn set_address(&self, _addr: u16) {}
+        fn enable_address(&self) {}
+        fn endpoint_in_enable(&self, _transfer_type: TransferType, _endpoint: usize) {}
+        fn endpoint_out_enable(&self, _transfer_type: TransferType, _endpoint: usize) {}
+        fn endpoint_in_out_enable(&self, _transfer_type: TransferType, _endpoint: usize) {}
+        fn endpoint_resume_in(&self, _endpoint: usize) {}
+        fn endpoint_resume_out(&self, _endpoint: usize) {}
+    }
+
+    /// Staging storage which refuses every write, as a busy or failed flash
+    /// controller would.
+    struct RefusingStorage;
+
+    impl hil::nonvolatile_storage::NonvolatileStorage<'static> for RefusingStorage {
+        fn set_client(
+            &self,
+            _client: &'static dyn hil::nonvolatile_storage::NonvolatileStorageClient,
+        ) {
+        }
+        fn read(
+            &self,
+            _buffer: &'static mut [u8],
+            _address: usize,
+            _length: usize,
+        ) -> Result<(), ErrorCode> {
+            Err(ErrorCode::FAIL)
+        }
+        fn write(
+            &self,
+            _buffer: &'static mut [u8],
+            _address: usize,
+            _length: usize,
+        ) -> Result<(), ErrorCode> {
+            Err(ErrorCode::FAIL)
+        }
+    }
+
+    type TestDfu = Dfu<'static, NoUsb>;
+
+    fn short_id(id: u32) -> ShortId {
+        ShortId::Fixed(NonZeroU32::new(id).unwrap())
+    }
+
+    /// Boot with empty app flash, with DFU storing binaries in it.
+    fn dfu() -> (&'static HostKernel, &'static AppFlash, &'static TestDfu) {
+        dfu_with_staging(None)
+    }
+
+    /// Boot with empty app flash and an optional kernel staging area of
+    /// `FLASH_SIZE` bytes.
+    fn dfu_with_staging(
+        staging: Option<&'static dyn hil::nonvolatile_storage::NonvolatileStorage<'static>>,
+    ) -> (&'static HostKernel, &'static AppFlash, &'static TestDfu) {
+        let kernel = HostKernel::new();
+        let flash = AppFlash::new(FLASH_SIZE);
+        let storage = kernel.dynamic_binary_storage(flash);
+        let dfu = leak(Dfu::new(
+            leak(NoUsb),
+            64,
+            0x6667,
+            0xabcd,
+            STRINGS,
+            storage,
+            storage,
+            staging,
+            0,
+            if staging.is_some() { FLASH_SIZE } else { 0 },
+            static_buf(TRANSFER_SIZE),
+        ));
+        storage.set_storage_client(dfu);
+        storage.set_load_client(dfu);
+
+        run_until_idle(&[flash]);
+        (kernel, flash, dfu)
+    }
+
+    /// Start a DFU class request on the control endpoint.
+    fn request(
+        dfu: &'static TestDfu,
+        request: DfuRequest,
+        direction_in: bool,
+        length: usize,
+    ) -> hil::usb::CtrlSetupResult {
+        let request_type: u8 = if direction_in { 0xa1 } else { 0x21 };
+        let setup = [
+            request_type,
+            request as u8,
+            0,
+            0,
+            0,
+            0,
+            length as u8,
+            (length >> 8) as u8,
+        ];
+        for (register, byte) in dfu.client_ctrl.ctrl_buffer.buf.iter().zip(setup) {
+            register.set(byte);
+        }
+        dfu.ctrl_setup(0)
+    }
+
+    /// Send `block` with DFU_DNLOAD. An empty block ends the download.
+    fn download(dfu: &'static TestDfu, block: &[u8]) {
+        assert!(matches!(
+            request(dfu, DfuRequest::Dnload, false, block.len()),
+            hil::usb::CtrlSetupResult::Ok
+        ));
+        for packet in block.chunks(64) {
+            for (register, byte) in dfu.client_ctrl.ctrl_buffer.buf.iter().zip(packet) {
+                register.set(*byte);
+            }
+            assert!(matches!(
+                dfu.ctrl_out(0, packet.len() as u32),
+                hil::usb::CtrlOutResult::Ok
+            ));
+        }
+        dfu.ctrl_status_complete(0);
+    }
+
+    /// Read the status and state with DFU_GETSTATUS.
+    fn get_status(dfu: &'static TestDfu) -> (Status, State) {
+        assert!(matches!(
+            request(dfu, DfuRequest::GetStatus, true, 6),
+            hil::usb::CtrlSetupResult::Ok
+        ));
+        assert!(matches!(
+            dfu.ctrl_in(0),
+            hil::usb::CtrlInResult::Packet(6, true)
+        ));
+        let buf = &dfu.client_ctrl.ctrl_buffer.buf;
+        assert_eq!(buf[0].get(), dfu.status.get() as u8);
+        assert_eq!(buf[4].get(), dfu.state.get() as u8);
+        dfu.ctrl_status_complete(0);
+        (dfu.status.get(), dfu.state.get())
+    }
+
+    fn binary() -> std::vec::Vec<u8> {
+        Tbf::new("new").short_id(APP_ID).size(BINARY_SIZE).build()
+    }
+
+    #[test]
+    fn downloaded_app_is_loaded() {
+        deferred_call::run(|| {
+            let (kernel, flash, dfu) = dfu();
+
+            for block in binary().chunks(TRANSFER_SIZE) {
+                download(dfu, block);
+                run_until_idle(&[flash]);
+                assert_eq!(get_status(dfu), (Status::Ok, State::DnloadIdle));
+            }
+            assert_eq!(kernel.process_flash_start(short_id(APP_ID)), None);
+
+            download(dfu, &[]);
+            run_until_idle(&[flash]);
+            assert_eq!(get_status(dfu), (Status::Ok, State::Idle));
+            assert_eq!(
+                kernel.process_flash_start(short_id(APP_ID)),
+                Some(flash.address(0))
+            );
+        });
+    }
+
+    #[test]
+    fn incomplete_download_is_rejected() {
+        deferred_call::run(|| {
+            let (kernel, flash, dfu) = dfu();
+
+            download(dfu, &binary()[..TRANSFER_SIZE]);
+            run_until_idle(&[flash]);
+            download(dfu, &[]);
+            run_until_idle(&[flash]);
+            assert_eq!(get_status(dfu), (Status::ErrNotDone, State::Error));
+            assert_eq!(kernel.process_flash_start(short_id(APP_ID)), None);
+
+            // Nothing can be downloaded until the host clears the error.
+            assert!(matches!(
+                request(dfu, DfuRequest::Dnload, false, TRANSFER_SIZE),
+                hil::usb::CtrlSetupResult::ErrGeneric
+            ));
+            assert_eq!(get_status(dfu), (Status::ErrNotDone, State::Error));
+            assert!(matches!(
+                request(dfu, DfuRequest::ClrStatus, false, 0),
+                hil::usb::CtrlSetupResult::Ok
+            ));
+            assert_eq!(get_status(dfu), (Status::Ok, State::Idle));
+        });
+    }
+
+    #[test]
+    fn uploads_and_oversized_blocks_are_stalled() {
+        deferred_call::run(|| {
+            let (_kernel, _flash, dfu) = dfu();
+
+            assert!(matches!(
+                request(dfu, DfuRequest::Upload, true, TRANSFER_SIZE),
+                hil::usb::CtrlSetupResult::ErrGeneric
+            ));
+            assert_eq!(get_status(dfu), (Status::ErrStalledPkt, State::Error));
+            assert!(matches!(
+                request(dfu, DfuRequest::ClrStatus, false, 0),
+                hil::usb::CtrlSetupResult::Ok
+            ));
+
+            assert!(matches!(
+                request(dfu, DfuRequest::Dnload, false, TRANSFER_SIZE + 1),
+                hil::usb::CtrlSetupResult::ErrGeneric
+            ));
+            assert_eq!(get_status(dfu), (Status::ErrStalledPkt, State::Error));
+        });
+    }
+
+    #[test]
+    fn refused_staging_write_loses_the_buffer() {
+        deferred_call::run(|| {
+            let (_kernel, _flash, dfu) = dfu_with_staging(Some(leak(RefusingStorage)));
+            dfu.alternate_setting.set(ALT_STAGING);
+
+            download(dfu, &[0xa5; TRANSFER_SIZE]);
+            assert_eq!(get_status(dfu), (Status::ErrWrite, State::Error));
+            assert!(matches!(
+                request(dfu, DfuRequest::ClrStatus, false, 0),
+                hil::usb::CtrlSetupResult::Ok
+            ));
+
+            // There is no buffer left to receive another block.
+            assert!(matches!(
+                request(dfu, DfuRequest::Dnload, false, TRANSFER_SIZE),
+                hil::usb::CtrlSetupResult::ErrGeneric
+            ));
+            assert_eq!(get_status(dfu), (Status::ErrTarget, State::Error));
+        });
+    }
+}
@@ -5,7 +5,9 @@
 pub mod cdc;
 pub mod ctap;
 pub mod descriptors;
+pub mod dfu;
 pub mod keyboard_hid;
+pub mod msc;
 pub mod usb_user;
 pub mod usbc_client;
 pub mod usbc_client_ctrl;
@@ -0,0 +1,1346 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Mass Storage Class Device for USB
+//!
+//! This capsule exposes a region of any
+//! [`NonvolatileStorage`](hil::nonvolatile_storage::NonvolatileStorage)
+//! implementation to a USB host as a removable drive. It implements the
+//! Bulk-Only Transport (BOT) protocol with the SCSI transparent command set,
+//! which is understood by the built-in drivers of all common host operating
+//! systems.
+//!
+//! The drive has a single logical unit made of 512 byte blocks. Block `n` is
+//! stored at `start_address + n * 512` in the underlying storage, and the drive
+//! covers `length / 512` blocks. Data is moved one block at a time through a
+//! single buffer, so throughput is bound by the latency of the storage driver.
+//!
+//! The `NonvolatileStorage` HIL does not return the buffer when the storage
+//! driver refuses a read or write. The command then fails with a MEDIUM ERROR,
+//! and every later command which needs the buffer (all but TEST UNIT READY,
+//! START STOP UNIT, PREVENT ALLOW MEDIUM REMOVAL, VERIFY(10) and SYNCHRONIZE
+//! CACHE(10)) fails with a HARDWARE ERROR until the board is reset.
+//!
+//! Only the commands hosts need to mount and use a drive are supported:
+//! INQUIRY, TEST UNIT READY, REQUEST SENSE, MODE SENSE(6), READ FORMAT
+//! CAPACITIES, READ CAPACITY(10), READ(10), WRITE(10), START STOP UNIT, PREVENT
+//! ALLOW MEDIUM REMOVAL, VERIFY(10) and SYNCHRONIZE CACHE(10). INQUIRY only
+//! returns the standard inquiry data, not vital product data pages. All other
+//! commands fail with an ILLEGAL REQUEST sense key.
+
+use core::cell::Cell;
+use core::cmp;
+
+use super::descriptors;
+use super::descriptors::Buffer64;
+use super::descriptors::EndpointAddress;
+use super::descriptors::EndpointDescriptor;
+use super::descriptors::InterfaceDescriptor;
+use super::descriptors::TransferDirection;
+use super::usbc_client_ctrl::ClientCtrl;
+
+use kernel::hil;
+use kernel::hil::usb::TransferType;
+use kernel::utilities::cells::TakeCell;
+use kernel::utilities::registers::InMemoryRegister;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+
+/// Identifying number for the endpoint when transferring data from us to the
+/// host.
+const ENDPOINT_IN_NUM: usize = 1;
+/// Identifying number for the endpoint when transferring data from the host to
+/// us.
+const ENDPOINT_OUT_NUM: usize = 2;
+
+const N_ENDPOINTS: usize = 2;
+
+/// Size of the bulk endpoint packets.
+const PACKET_SIZE: usize = 64;
+
+/// Size of the blocks exposed to the host. The buffer passed to
+/// [`MassStorage::new`] must be at least this long.
+pub const BLOCK_SIZE: usize = 512;
+
+static LANGUAGES: &[u16; 1] = &[
+    0x0409, // English (United States)
+];
+
+/// Class-specific control requests defined by the Bulk-Only Transport
+/// specification.
+const GET_MAX_LUN: u8 = 0xfe;
+const BULK_ONLY_MASS_STORAGE_RESET: u8 = 0xff;
+
+/// Command Block Wrapper, sent by the host to start a command.
+const CBW_SIGNATURE: u32 = 0x43425355;
+const CBW_LENGTH: usize = 31;
+
+/// Command Status Wrapper, sent to the host once a command has finished.
+const CSW_SIGNATURE: u32 = 0x53425355;
+const CSW_LENGTH: usize = 13;
+
+/// SCSI operation codes.
+const TEST_UNIT_READY: u8 = 0x00;
+const REQUEST_SENSE: u8 = 0x03;
+const INQUIRY: u8 = 0x12;
+const MODE_SENSE_6: u8 = 0x1a;
+const START_STOP_UNIT: u8 = 0x1b;
+const PREVENT_ALLOW_MEDIUM_REMOVAL: u8 = 0x1e;
+const READ_FORMAT_CAPACITIES: u8 = 0x23;
+const READ_CAPACITY_10: u8 = 0x25;
+const READ_10: u8 = 0x28;
+const WRITE_10: u8 = 0x2a;
+const VERIFY_10: u8 = 0x2f;
+const SYNCHRONIZE_CACHE_10: u8 = 0x35;
+
+/// Sense data reported through REQUEST SENSE after a command fails.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Sense {
+    key: u8,
+    asc: u8,
+    ascq: u8,
+}
+
+const SENSE_NONE: Sense = Sense {
+    key: 0x00,
+    asc: 0x00,
+    ascq: 0x00,
+};
+const SENSE_INVALID_COMMAND: Sense = Sense {
+    key: 0x05, // ILLEGAL REQUEST
+    asc: 0x20, // INVALID COMMAND OPERATION CODE
+    ascq: 0x00,
+};
+const SENSE_INVALID_FIELD: Sense = Sense {
+    key: 0x05, // ILLEGAL REQUEST
+    asc: 0x24, // INVALID FIELD IN CDB
+    ascq: 0x00,
+};
+const SENSE_OUT_OF_RANGE: Sense = Sense {
+    key: 0x05, // ILLEGAL REQUEST
+    asc: 0x21, // LOGICAL BLOCK ADDRESS OUT OF RANGE
+    ascq: 0x00,
+};
+const SENSE_READ_ERROR: Sense = Sense {
+    key: 0x03, // MEDIUM ERROR
+    asc: 0x11, // UNRECOVERED READ ERROR
+    ascq: 0x00,
+};
+const SENSE_WRITE_ERROR: Sense = Sense {
+    key: 0x03, // MEDIUM ERROR
+    asc: 0x0c, // WRITE ERROR
+    ascq: 0x00,
+};
+const SENSE_INTERNAL_FAILURE: Sense = Sense {
+    key: 0x04, // HARDWARE ERROR
+    asc: 0x44, // INTERNAL TARGET FAILURE
+    ascq: 0x00,
+};
+
+/// Value of the `bCSWStatus` field of the Command Status Wrapper.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum CommandStatus {
+    Passed = 0,
+    Failed = 1,
+}
+
+/// States of the Bulk-Only Transport.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    /// Waiting for the host to send a Command Block Wrapper.
+    Idle,
+    /// Sending `data_len` bytes of the buffer to the host.
+    DataIn,
+    /// Receiving `data_len` bytes from the host into the buffer.
+    DataOut,
+    /// The command failed while the host still has data to send us. We accept
+    /// and drop the rest of it before sending the status.
+    DataOutDiscard,
+    /// Waiting for the storage to finish reading a block.
+    StorageRead,
+    /// Waiting for the storage to finish writing a block.
+    StorageWrite,
+    /// Sending the Command Status Wrapper.
+    Status,
+}
+
+/// States of the Control Endpoint related to the mass storage class.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum CtrlState {
+    /// No ongoing ctrl transaction, or one handled by `ClientCtrl`.
+    Idle,
+    /// The host has sent a GET_MAX_LUN request.
+    GetMaxLun,
+}
+
+/// Fields of a Command Block Wrapper we need to act on a command.
+struct CommandBlockWrapper {
+    tag: u32,
+    data_transfer_length: u32,
+    direction_in: bool,
+    lun: u8,
+    command_block: [u8; 16],
+}
+
+impl CommandBlockWrapper {
+    fn get(p: &[InMemoryRegister<u8>], length: usize) -> Option<Self> {
+        if length != CBW_LENGTH || p.len() < CBW_LENGTH {
+            return None;
+        }
+        let u32_at = |i: usize| {
+            u32::from_le_bytes([p[i].get(), p[i + 1].get(), p[i + 2].get(), p[i + 3].get()])
+        };
+        if u32_at(0) != CBW_SIGNATURE {
+            return None;
+        }
+        let command_block_length = p[14].get() as usize;
+        if command_block_length == 0 || command_block_length > 16 {
+            return None;
+        }
+        let mut command_block = [0; 16];
+        for i in 0..command_block_length {
+            command_block[i] = p[15 + i].get();
+        }
+        Some(CommandBlockWrapper {
+            tag: u32_at(4),
+            data_transfer_length: u32_at(8),
+            direction_in: p[12].get() & (1 << 7) != 0,
+            lun: p[13].get() & 0x0f,
+            command_block,
+        })
+    }
+}
+
+/// USB Mass Storage Class device using the Bulk-Only Transport.
+pub struct MassStorage<'a, U: 'a> {
+    /// Helper USB client library for handling many USB operations.
+    client_ctrl: ClientCtrl<'a, 'static, U>,
+
+    /// 64 byte buffers for each endpoint.
+    buffers: [Buffer64; N_ENDPOINTS],
+
+    /// Storage holding the contents of the drive.
+    storage: &'a dyn hil::nonvolatile_storage::NonvolatileStorage<'a>,
+    /// Address in `storage` of the first block of the drive.
+    start_address: usize,
+    /// Number of blocks exposed to the host.
+    block_count: u32,
+
+    /// Manufacturer, product and serial number strings. The first two are also
+    /// reported in the SCSI INQUIRY data.
+    strings: &'static [&'static str; 3],
+
+    /// Current state of the Bulk-Only Transport.
+    state: Cell<State>,
+    /// Current state of the Control Endpoint.
+    ctrl_state: Cell<CtrlState>,
+
+    /// Block sized buffer used for storage reads and writes and for composing
+    /// SCSI responses. It is lost if the storage refuses an operation.
+    buffer: TakeCell<'static, [u8]>,
+    /// Whether `buffer` has been passed to the storage driver. This can remain
+    /// set after a reset aborted the command that started the operation.
+    storage_busy: Cell<bool>,
+    /// Whether we returned `OutResult::Delay` for the last OUT packet and need
+    /// to resume the OUT endpoint.
+    out_delayed: Cell<bool>,
+
+    /// Tag of the current command, echoed back in its status.
+    tag: Cell<u32>,
+    /// Number of bytes the host expects to transfer for the current command.
+    expected_length: Cell<u32>,
+    /// Whether the host expects data from us for the current command.
+    direction_in: Cell<bool>,
+    /// Number of bytes transferred so far for the current command.
+    transferred: Cell<u32>,
+    /// Number of bytes received and dropped after the current command failed.
+    /// They are not transferred, and count towards the residue.
+    discarded: Cell<u32>,
+    /// Status that will be reported for the current command.
+    status: Cell<CommandStatus>,
+
+    /// Next block to read or write for READ(10) and WRITE(10).
+    lba: Cell<u32>,
+    /// Number of blocks left to read or write for READ(10) and WRITE(10).
+    blocks_remaining: Cell<u32>,
+    /// Number of bytes of `buffer` to send or receive in the data phase.
+    data_len: Cell<usize>,
+    /// Number of bytes of `buffer` already sent or received.
+    data_offset: Cell<usize>,
+
+    /// Sense data describing why the last command failed.
+    sense: Cell<Sense>,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> MassStorage<'a, U> {
+    pub fn new(
+        controller: &'a U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        storage: &'a dyn hil::nonvolatile_storage::NonvolatileStorage<'a>,
+        start_address: usize,
+        length: usize,
+        buffer: &'static mut [u8],
+    ) -> Self {
+        let interfaces: &mut [InterfaceDescriptor] = &mut [InterfaceDescriptor {
+            interface_number: 0,
+            interface_class: 0x08,    // Mass storage
+            interface_subclass: 0x06, // SCSI transparent command set
+            interface_protocol: 0x50, // Bulk-only transport
+            ..InterfaceDescriptor::default()
+        }];
+
+        let endpoints: &[&[EndpointDescriptor]] = &[&[
+            EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_IN_NUM,
+                    TransferDirection::DeviceToHost,
+                ),
+                transfer_type: TransferType::Bulk,
+                max_packet_size: PACKET_SIZE as u16,
+                interval: 0,
+            },
+            EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_OUT_NUM,
+                    TransferDirection::HostToDevice,
+                ),
+                transfer_type: TransferType::Bulk,
+                max_packet_size: PACKET_SIZE as u16,
+                interval: 0,
+            },
+        ]];
+
+        let (device_descriptor_buffer, other_descriptor_buffer) =
+            descriptors::create_descriptor_buffers(
+                descriptors::DeviceDescriptor {
+                    vendor_id,
+                    product_id,
+                    manufacturer_string: 1,
+                    product_string: 2,
+                    serial_number_string: 3,
+                    max_packet_size_ep0: max_ctrl_packet_size,
+                    ..descriptors::DeviceDescriptor::default()
+                },
+                descriptors::ConfigurationDescriptor::default(),
+                interfaces,
+                endpoints,
+                None, // No HID descriptor
+                None, // No CDC descriptor array
+                None, // No DFU descriptor
+            );
+
+        Self {
+            client_ctrl: ClientCtrl::new(
+                controller,
+                device_descriptor_buffer,
+                other_descriptor_buffer,
+                None, // No HID descriptor
+                None, // No report descriptor
+                LANGUAGES,
+                strings,
+            ),
+            buffers: [Buffer64::default(), Buffer64::default()],
+            storage,
+            start_address,
+            block_count: u32::try_from(length / BLOCK_SIZE).unwrap_or(u32::MAX),
+            strings,
+            state: Cell::new(State::Idle),
+            ctrl_state: Cell::new(CtrlState::Idle),
+            buffer: TakeCell::new(buffer),
+            storage_busy: Cell::new(false),
+            out_delayed: Cell::new(false),
+            tag: Cell::new(0),
+            expected_length: Cell::new(0),
+            direction_in: Cell::new(false),
+            transferred: Cell::new(0),
+            discarded: Cell::new(0),
+            status: Cell::new(CommandStatus::Passed),
+            lba: Cell::new(0),
+            blocks_remaining: Cell::new(0),
+            data_len: Cell::new(0),
+            data_offset: Cell::new(0),
+            sense: Cell::new(SENSE_NONE),
+        }
+    }
+
+    #[inline]
+    pub fn controller(&self) -> &'a U {
+        self.client_ctrl.controller()
+    }
+
+    #[inline]
+    fn buffer(&'a self, i: usize) -> &'a [InMemoryRegister<u8>; 64] {
+        &self.buffers[i - 1].buf
+    }
+
+    /// Abort the current command and wait for a new Command Block Wrapper.
+    fn reset(&self) {
+        self.state.set(State::Idle);
+        self.blocks_remaining.set(0);
+        self.resume_out();
+    }
+
+    /// Let the controller deliver the OUT packet we previously delayed.
+    fn resume_out(&self) {
+        if self.out_delayed.take() {
+            self.controller().endpoint_resume_out(ENDPOINT_OUT_NUM);
+        }
+    }
+
+    /// Start executing the command described by `cbw`.
+    fn handle_command(&self, cbw: CommandBlockWrapper) {
+        self.tag.set(cbw.tag);
+        self.expected_length.set(cbw.data_transfer_length);
+        self.direction_in.set(cbw.direction_in);
+        self.transferred.set(0);
+        self.discarded.set(0);
+        self.status.set(CommandStatus::Passed);
+        self.blocks_remaining.set(0);
+
+        if cbw.lun != 0 {
+            self.fail(SENSE_INVALID_FIELD);
+            return;
+        }
+
+        let cb = &cbw.command_block;
+        match cb[0] {
+            TEST_UNIT_READY
+            | START_STOP_UNIT
+            | PREVENT_ALLOW_MEDIUM_REMOVAL
+            | VERIFY_10
+            | SYNCHRONIZE_CACHE_10 => {
+                // Nothing to do: the medium is always present and every write
+                // has reached the storage by the time we report its status.
+                self.finish();
+            }
+            REQUEST_SENSE => {
+                let sense = self.sense.replace(SENSE_NONE);
+                self.respond(cb[4] as usize, |buf| {
+                    buf[..18].fill(0);
+                    buf[0] = 0x70; // Current error, fixed format
+                    buf[2] = sense.key;
+                    buf[7] = 10; // Additional sense length
+                    buf[12] = sense.asc;
+                    buf[13] = sense.ascq;
+                    18
+                });
+            }
+            INQUIRY if cb[1] & 0x01 != 0 => {
+                // Vital product data pages are not supported.
+                self.fail(SENSE_INVALID_FIELD);
+            }
+            INQUIRY => {
+                let allocation_length = u16::from_be_bytes([cb[3], cb[4]]) as usize;
+                self.respond(allocation_length, |buf| {
+                    buf[..36].fill(b' ');
+                    buf[0] = 0x00; // Direct access block device
+                    buf[1] = 0x80; // Removable medium
+                    buf[2] = 0x04; // SPC-2
+                    buf[3] = 0x02; // Response data format
+                    buf[4] = 36 - 5; // Additional length
+                    buf[5..8].fill(0);
+                    copy_padded(&mut buf[8..16], self.strings[0]);
+                    copy_padded(&mut buf[16..32], self.strings[1]);
+                    buf[32..36].copy_from_slice(b"1.0 ");
+                    36
+                });
+            }
+            MODE_SENSE_6 => {
+                self.respond(cb[4] as usize, |buf| {
+                    buf[0] = 3; // Mode data length
+                    buf[1] = 0; // Medium type
+                    buf[2] = 0; // Not write protected
+                    buf[3] = 0; // No block descriptors
+                    4
+                });
+            }
+            READ_FORMAT_CAPACITIES => {
+                let allocation_length = u16::from_be_bytes([cb[7], cb[8]]) as usize;
+                let block_count = self.block_count;
+                self.respond(allocation_length, |buf| {
+                    buf[0..3].fill(0);
+                    buf[3] = 8; // Capacity list length
+                    buf[4..8].copy_from_slice(&block_count.to_be_bytes());
+                    buf[8] = 0x02; // Formatted media
+                    buf[9..12].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes()[1..]);
+                    12
+                });
+            }
+            READ_CAPACITY_10 => {
+                let last_lba = self.block_count.saturating_sub(1);
+                self.respond(8, |buf| {
+                    buf[0..4].copy_from_slice(&last_lba.to_be_bytes());
+                    buf[4..8].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes());
+                    8
+                });
+            }
+            READ_10 | WRITE_10 => {
+                let lba = u32::from_be_bytes([cb[2], cb[3], cb[4], cb[5]]);
+                let blocks = u16::from_be_bytes([cb[7], cb[8]]) as u32;
+                let is_read = cb[0] == READ_10;
+
+                if lba as u64 + blocks as u64 > self.block_count as u64 {
+                    self.fail(SENSE_OUT_OF_RANGE);
+                } else if is_read != cbw.direction_in
+                    || (blocks as u64 * BLOCK_SIZE as u64) > cbw.data_transfer_length as u64
+                {
+                    self.fail(SENSE_INVALID_FIELD);
+                } else if blocks == 0 {
+                    self.finish();
+                } else {
+                    self.lba.set(lba);
+                    self.blocks_remaining.set(blocks);
+                    if is_read {
+                        self.read_block();
+                    } else {
+                        self.data_len.set(BLOCK_SIZE);
+                        self.data_offset.set(0);
+                        self.state.set(State::DataOut);
+                    }
+                }
+            }
+            _ => {
+                self.fail(SENSE_INVALID_COMMAND);
+            }
+        }
+    }
+
+    /// Send a SCSI response composed by `compose` in the buffer. `compose`
+    /// returns the length of the response, which is truncated to what the host
+    /// asked for.
+    fn respond<F: FnOnce(&mut [u8]) -> usize>(&self, allocation_length: usize, compose: F) {
+        match self.buffer.map(compose) {
+            Some(len) => {
+                let len = cmp::min(
+                    cmp::min(len, allocation_length),
+                    self.expected_length.get() as usize,
+                );
+                if len == 0 || !self.direction_in.get() {
+                    self.finish();
+                } else {
+                    self.data_len.set(len);
+                    self.data_offset.set(0);
+                    self.state.set(State::DataIn);
+                    self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+                }
+            }
+            None => self.fail(SENSE_INTERNAL_FAILURE),
+        }
+    }
+
+    /// Read the block at `lba` into the buffer.
+    fn read_block(&self) {
+        match self.buffer.take() {
+            Some(buf) => {
+                let address = self.start_address + self.lba.get() as usize * BLOCK_SIZE;
+                self.state.set(State::StorageRead);
+                self.storage_busy.set(true);
+                if self.storage.read(buf, address, BLOCK_SIZE).is_err() {
+                    // The storage does not return the buffer on error.
+                    self.storage_busy.set(false);
+                    self.fail(SENSE_READ_ERROR);
+                }
+            }
+            None => self.fail(SENSE_INTERNAL_FAILURE),
+        }
+    }
+
+    /// Write the buffer to the block at `lba`.
+    fn write_block(&self) {
+        match self.buffer.take() {
+            Some(buf) => {
+                let address = self.start_address + self.lba.get() as usize * BLOCK_SIZE;
+                self.state.set(State::StorageWrite);
+                self.storage_busy.set(true);
+                if self.storage.write(buf, address, BLOCK_SIZE).is_err() {
+                    // The storage does not return the buffer on error.
+                    self.storage_busy.set(false);
+                    self.fail(SENSE_WRITE_ERROR);
+                }
+            }
+            None => self.fail(SENSE_INTERNAL_FAILURE),
+        }
+    }
+
+    /// Mark the current command as failed and finish it.
+    fn fail(&self, sense: Sense) {
+        self.sense.set(sense);
+        self.status.set(CommandStatus::Failed);
+        self.finish();
+    }
+
+    /// End the data phase of the current command and report its status.
+    ///
+    /// If we transferred less data than the host expects, the difference is
+    /// reported as the residue. For OUT transfers the host still sends all of
+    /// its data, so we drop it before sending the status. For IN transfers we
+    /// end the data phase with a zero length packet if the host cannot tell it
+    /// is over from a short packet.
+    fn finish(&self) {
+        let expected = self.expected_length.get();
+        let transferred = self.transferred.get();
+        if transferred < expected {
+            if !self.direction_in.get() {
+                self.state.set(State::DataOutDiscard);
+                self.resume_out();
+                return;
+            } else if transferred.is_multiple_of(PACKET_SIZE as u32) {
+                self.blocks_remaining.set(0);
+                self.data_len.set(0);
+                self.data_offset.set(0);
+                self.state.set(State::DataIn);
+                self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+                return;
+            }
+        }
+        self.send_status();
+    }
+
+    fn send_status(&self) {
+        self.state.set(State::Status);
+        self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+    }
+}
+
+/// Copy `s` into `dst`, truncating it or padding it with spaces as needed.
+fn copy_padded(dst: &mut [u8], s: &str) {
+    dst.fill(b' ');
+    let len = cmp::min(dst.len(), s.len());
+    dst[..len].copy_from_slice(&s.as_bytes()[..len]);
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for MassStorage<'a, U> {
+    fn enable(&'a self) {
+        // Set up the default control endpoint
+        self.client_ctrl.enable();
+
+        // Setup buffers for IN and OUT data transfer.
+        self.controller()
+            .endpoint_set_in_buffer(ENDPOINT_IN_NUM, self.buffer(ENDPOINT_IN_NUM));
+        self.controller()
+            .endpoint_in_enable(TransferType::Bulk, ENDPOINT_IN_NUM);
+
+        self.controller()
+            .endpoint_set_out_buffer(ENDPOINT_OUT_NUM, self.buffer(ENDPOINT_OUT_NUM));
+        self.controller()
+            .endpoint_out_enable(TransferType::Bulk, ENDPOINT_OUT_NUM);
+    }
+
+    fn attach(&'a self) {
+        self.client_ctrl.attach();
+    }
+
+    fn bus_reset(&'a self) {
+        self.reset();
+    }
+
+    /// Handle a Control Setup transaction.
+    ///
+    /// The Bulk-Only Transport defines two class requests: GET_MAX_LUN, which
+    /// we answer with `0` as we only have one logical unit, and the mass
+    /// storage reset, which aborts the current command.
+    fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        let class_request = descriptors::SetupData::get(&self.client_ctrl.ctrl_buffer.buf)
+            .filter(|setup_data| {
+                matches!(
+                    setup_data.request_type.request_type(),
+                    descriptors::RequestType::Class
+                )
+            })
+            .map(|setup_data| setup_data.request_code);
+
+        match class_request {
+            Some(GET_MAX_LUN) => {
+                self.ctrl_state.set(CtrlState::GetMaxLun);
+                hil::usb::CtrlSetupResult::Ok
+            }
+            Some(BULK_ONLY_MASS_STORAGE_RESET) => {
+                self.reset();
+                hil::usb::CtrlSetupResult::Ok
+            }
+            _ => self.client_ctrl.ctrl_setup(endpoint),
+        }
+    }
+
+    /// Handle a Control In transaction
+    fn ctrl_in(&'a self, endpoint: usize) -> hil::usb::CtrlInResult {
+        if self.ctrl_state.get() == CtrlState::GetMaxLun {
+            self.client_ctrl.ctrl_buffer.buf[0].set(0);
+            hil::usb::CtrlInResult::Packet(1, true)
+        } else {
+            self.client_ctrl.ctrl_in(endpoint)
+        }
+    }
+
+    /// Handle a Control Out transaction
+    fn ctrl_out(&'a self, endpoint: usize, packet_bytes: u32) -> hil::usb::CtrlOutResult {
+        self.client_ctrl.ctrl_out(endpoint, packet_bytes)
+    }
+
+    fn ctrl_status(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status(endpoint)
+    }
+
+    /// Handle the completion of a Control transfer
+    fn ctrl_status_complete(&'a self, endpoint: usize) {
+        self.ctrl_state.set(CtrlState::Idle);
+        self.client_ctrl.ctrl_status_complete(endpoint)
+    }
+
+    /// Handle a Bulk IN transaction.
+    ///
+    /// This sends the next packet of the data phase, or the Command Status
+    /// Wrapper once the data phase is over.
+    fn packet_in(&'a self, transfer_type: TransferType, endpoint: usize) -> hil::usb::InResult {
+        if !matches!(transfer_type, TransferType::Bulk) || endpoint != ENDPOINT_IN_NUM {
+            return hil::usb::InResult::Delay;
+        }
+
+        let packet = self.buffer(endpoint);
+        match self.state.get() {
+            // A zero length packet ends the data phase; it does not need the
+            // buffer, which may have been lost.
+            State::DataIn if self.data_len.get() == 0 => hil::usb::InResult::Packet(0),
+            State::DataIn => self.buffer.map_or(hil::usb::InResult::Delay, |buf| {
+                let offset = self.data_offset.get();
+                let to_send = cmp::min(packet.len(), self.data_len.get() - offset);
+                for i in 0..to_send {
+                    packet[i].set(buf[offset + i]);
+                }
+                self.data_offset.set(offset + to_send);
+                self.transferred
+                    .set(self.transferred.get() + to_send as u32);
+                hil::usb::InResult::Packet(to_send)
+            }),
+            State::Status => {
+                let expected = self.expected_length.get();
+                let residue = expected.saturating_sub(self.transferred.get());
+                let fields = [
+                    CSW_SIGNATURE.to_le_bytes(),
+                    self.tag.get().to_le_bytes(),
+                    residue.to_le_bytes(),
+                ];
+                for (i, b) in fields.iter().flatten().enumerate() {
+                    packet[i].set(*b);
+                }
+                packet[12].set(self.status.get() as u8);
+                hil::usb::InResult::Packet(CSW_LENGTH)
+            }
+            _ => hil::usb::InResult::Delay,
+        }
+    }
+
+    /// Handle a Bulk OUT transaction.
+    ///
+    /// In the idle state this is a Command Block Wrapper, otherwise it is data
+    /// for a WRITE(10) command.
+    fn packet_out(
+        &'a self,
+        transfer_type: TransferType,
+        endpoint: usize,
+        packet_bytes: u32,
+    ) -> hil::usb::OutResult {
+        if !matches!(transfer_type, TransferType::Bulk) || endpoint != ENDPOINT_OUT_NUM {
+            return hil::usb::OutResult::Ok;
+        }
+
+        let packet = self.buffer(endpoint);
+        match self.state.get() {
+            State::Idle if !self.storage_busy.get() => {
+                match CommandBlockWrapper::get(packet, packet_bytes as usize) {
+                    Some(cbw) => {
+                        self.handle_command(cbw);
+                        hil::usb::OutResult::Ok
+                    }
+                    // Not a valid command. Stall until the host resets us.
+                    None => hil::usb::OutResult::Error,
+                }
+            }
+            State::DataOut => {
+                let offset = self.data_offset.get();
+                let copy_length = cmp::min(packet_bytes as usize, self.data_len.get() - offset);
+                self.buffer.map(|buf| {
+                    for i in 0..copy_length {
+                        buf[offset + i] = packet[i].get();
+                    }
+                });
+                self.data_offset.set(offset + copy_length);
+                self.transferred.set(self.transferred.get() + packet_bytes);
+
+                if offset + copy_length >= self.data_len.get() {
+                    self.write_block();
+                }
+                hil::usb::OutResult::Ok
+            }
+            State::DataOutDiscard => {
+                let discarded = self.discarded.get() + packet_bytes;
+                self.discarded.set(discarded);
+                if self.transferred.get() + discarded >= self.expected_length.get() {
+                    self.send_status();
+                }
+                hil::usb::OutResult::Ok
+            }
+            _ => {
+                // We are busy with the current command. The host will resend
+                // this packet once we resume the endpoint.
+                self.out_delayed.set(true);
+                hil::usb::OutResult::Delay
+            }
+        }
+    }
+
+    fn packet_transmitted(&'a self, endpoint: usize) {
+        if endpoint != ENDPOINT_IN_NUM {
+            return;
+        }
+
+        match self.state.get() {
+            State::DataIn => {
+                if self.data_offset.get() < self.data_len.get() {
+                    // More of the buffer to send.
+                    self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+                } else if self.data_len.get() == 0 {
+                    // We just ended the data phase with a zero length packet.
+                    self.send_status();
+                } else if self.blocks_remaining.get() > 0 {
+                    self.read_block();
+                } else {
+                    self.finish();
+                }
+            }
+            State::Status => {
+                self.state.set(State::Idle);
+                self.resume_out();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::nonvolatile_storage::NonvolatileStorageClient
+    for MassStorage<'a, U>
+{
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        self.storage_busy.set(false);
+
+        if self.state.get() != State::StorageRead {
+            // The command was aborted by a reset while we were reading.
+            self.resume_out();
+        } else if length != BLOCK_SIZE {
+            self.fail(SENSE_READ_ERROR);
+        } else {
+            self.lba.set(self.lba.get() + 1);
+            self.blocks_remaining.set(self.blocks_remaining.get() - 1);
+            self.data_len.set(BLOCK_SIZE);
+            self.data_offset.set(0);
+            self.state.set(State::DataIn);
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        }
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+        self.storage_busy.set(false);
+
+        if self.state.get() != State::StorageWrite {
+            // The command was aborted by a reset while we were writing.
+            self.resume_out();
+        } else if length != BLOCK_SIZE {
+            self.fail(SENSE_WRITE_ERROR);
+        } else {
+            self.lba.set(self.lba.get() + 1);
+            self.blocks_remaining.set(self.blocks_remaining.get() - 1);
+            if self.blocks_remaining.get() > 0 {
+                self.data_len.set(BLOCK_SIZE);
+                self.data_offset.set(0);
+                self.state.set(State::DataOut);
+                self.resume_out();
+            } else {
+                self.finish();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use capsules_test_harness::{leak, static_buf};
+    use core::cell::RefCell;
+    use hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+    use hil::usb::{Client, InResult, OutResult, UsbController};
+    use kernel::ErrorCode;
+    use std::vec::Vec;
+
+    /// Number of blocks of the simulated drive.
+    const DRIVE_BLOCKS: usize = 8;
+    /// The drive starts after the first block of the simulated storage.
+    const START_ADDRESS: usize = BLOCK_SIZE;
+
+    static STRINGS: &[&str; 3] = &["Tock", "Drive", "0"];
+
+    fn packet(bytes: &[u8]) -> Buffer64 {
+        let packet = Buffer64::default();
+        for (register, byte) in packet.buf.iter().zip(bytes) {
+            register.set(*byte);
+        }
+        packet
+    }
+
+    /// A READ(10) of 2 blocks at block 5 with tag 0x1234.
+    const READ_CBW: [u8; CBW_LENGTH] = [
+        0x55, 0x53, 0x42, 0x43, // Signature
+        0x34, 0x12, 0x00, 0x00, // Tag
+        0x00, 0x04, 0x00, 0x00, // Data transfer length
+        0x80, // Flags: data in
+        0x00, // LUN
+        10,   // Command block length
+        READ_10, 0, 0, 0, 0, 5, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    #[test]
+    fn command_block_wrapper_is_parsed() {
+        let cbw = CommandBlockWrapper::get(&packet(&READ_CBW).buf, CBW_LENGTH).unwrap();
+        assert_eq!(cbw.tag, 0x1234);
+        assert_eq!(cbw.data_transfer_length, 2 * BLOCK_SIZE as u32);
+        assert!(cbw.direction_in);
+        assert_eq!(cbw.lun, 0);
+        assert_eq!(cbw.command_block[..10], READ_CBW[15..25]);
+    }
+
+    #[test]
+    fn invalid_command_block_wrappers_are_rejected() {
+        // Wrong length.
+        assert!(CommandBlockWrapper::get(&packet(&READ_CBW).buf, CBW_LENGTH - 1).is_none());
+
+        // Wrong signature.
+        let mut cbw = READ_CBW;
+        cbw[0] = 0;
+        assert!(CommandBlockWrapper::get(&packet(&cbw).buf, CBW_LENGTH).is_none());
+
+        // Command block length out of range.
+        for command_block_length in [0, 17] {
+            let mut cbw = READ_CBW;
+            cbw[14] = command_block_length;
+            assert!(CommandBlockWrapper::get(&packet(&cbw).buf, CBW_LENGTH).is_none());
+        }
+    }
+
+    #[test]
+    fn strings_are_padded_with_spaces() {
+        let mut dst = [0; 8];
+        copy_padded(&mut dst, "Tock");
+        assert_eq!(&dst, b"Tock    ");
+        copy_padded(&mut dst, "Tock Contributors");
+        assert_eq!(&dst, b"Tock Con");
+    }
+
+    /// USB controller which counts how often the OUT endpoint was resumed.
+    /// The test plays the host by calling the bulk endpoint handlers.
+    #[derive(Default)]
+    struct SimUsb {
+        out_resumed: Cell<usize>,
+    }
+
+    impl UsbController<'static> for SimUsb {
+        fn set_client(&self, _client: &'static dyn Client<'static>) {}
+        fn endpoint_set_ctrl_buffer(&self, _buf: &'static [InMemoryRegister<u8>]) {}
+        fn endpoint_set_in_buffer(&self, _endpoint: usize, _buf: &'static [InMemoryRegister<u8>]) {}
+        fn endpoint_set_out_buffer(&self, _endpoint: usize, _buf: &'static [InMemoryRegister<u8>]) {
+        }
+        fn enable_as_device(&self, _speed: hil::usb::DeviceSpeed) {}
+        fn attach(&self) {}
+        fn detach(&self) {}
+        fn set_address(&self, _addr: u16) {}
+        fn enable_address(&self) {}
+        fn endpoint_in_enable(&self, _transfer_type: TransferType, _endpoint: usize) {}
+        fn endpoint_out_enable(&self, _transfer_type: TransferType, _endpoint: usize) {}
+        fn endpoint_in_out_enable(&self, _transfer_type: TransferType, _endpoint: usize) {}
+        fn endpoint_resume_in(&self, _endpoint: usize) {}
+        fn endpoint_resume_out(&self, endpoint: usize) {
+            assert_eq!(endpoint, ENDPOINT_OUT_NUM);
+            self.out_resumed.set(self.out_resumed.get() + 1);
+        }
+    }
+
+    /// A storage operation started by the drive: whether it is a write, the
+    /// buffer, the address and the length.
+    type Operation = (bool, &'static mut [u8], usize, usize);
+
+    /// In-memory storage which holds on to an operation until the test
+    /// completes it.
+    #[derive(Default)]
+    struct SimStorage {
+        data: RefCell<Vec<u8>>,
+        client: Cell<Option<&'static dyn NonvolatileStorageClient>>,
+        pending: RefCell<Option<Operation>>,
+        refuse: Cell<bool>,
+    }
+
+    impl SimStorage {
+        /// Complete the pending operation, if any.
+        fn complete(&self) -> bool {
+            let Some((write, buffer, address, length)) = self.pending.take() else {
+                return false;
+            };
+            let mut data = self.data.borrow_mut();
+            let client = self.client.get().unwrap();
+            if write {
+                data[address..address + length].copy_from_slice(&buffer[..length]);
+                drop(data);
+                client.write_done(buffer, length);
+            } else {
+                buffer[..length].copy_from_slice(&data[address..address + length]);
+                drop(data);
+                client.read_done(buffer, length);
+            }
+            true
+        }
+
+        fn start(&self, operation: Operation) -> Result<(), ErrorCode> {
+            if self.refuse.get() {
+                return Err(ErrorCode::FAIL);
+            }
+            assert!(operation.2 + operation.3 <= self.data.borrow().len());
+            assert!(self.pending.replace(Some(operation)).is_none());
+            Ok(())
+        }
+    }
+
+    impl NonvolatileStorage<'static> for SimStorage {
+        fn set_client(&self, client: &'static dyn NonvolatileStorageClient) {
+            self.client.set(Some(client));
+        }
+
+        fn read(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            self.start((false, buffer, address, length))
+        }
+
+        fn write(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            self.start((true, buffer, address, length))
+        }
+    }
+
+    type TestMsc = MassStorage<'static, SimUsb>;
+
+    /// The content of block `lba` of the drive before the test writes to it.
+    fn block_pattern(lba: usize) -> Vec<u8> {
+        (0..BLOCK_SIZE).map(|i| (lba * 7 + i) as u8).collect()
+    }
+
+    /// Plays the host of a drive on simulated storage.
+    struct Host {
+        usb: &'static SimUsb,
+        storage: &'static SimStorage,
+        msc: &'static TestMsc,
+        next_tag: Cell<u32>,
+    }
+
+    /// Status of a command, as reported in its Command Status Wrapper.
+    #[derive(Debug, PartialEq)]
+    struct Csw {
+        tag: u32,
+        residue: u32,
+        status: u8,
+    }
+
+    impl Host {
+        fn new() -> Host {
+            let usb = leak(SimUsb::default());
+            let storage = leak(SimStorage::default());
+            let mut data = std::vec![0xff; START_ADDRESS];
+            for lba in 0..=DRIVE_BLOCKS {
+                data.extend(block_pattern(lba));
+            }
+            storage.data.replace(data);
+            let msc = leak(MassStorage::new(
+                usb,
+                64,
+                0x6667,
+                0xabcd,
+                STRINGS,
+                storage,
+                START_ADDRESS,
+                DRIVE_BLOCKS * BLOCK_SIZE,
+                static_buf(BLOCK_SIZE),
+            ));
+            storage.set_client(msc);
+            msc.enable();
+            Host {
+                usb,
+                storage,
+                msc,
+                next_tag: Cell::new(1),
+            }
+        }
+
+        /// The content of block `lba` of the drive in storage.
+        fn stored_block(&self, lba: usize) -> Vec<u8> {
+            let address = START_ADDRESS + lba * BLOCK_SIZE;
+            self.storage.data.borrow()[address..address + BLOCK_SIZE].to_vec()
+        }
+
+        /// Send a packet to the OUT endpoint.
+        fn packet_out(&self, bytes: &[u8]) -> OutResult {
+            for (register, byte) in self.msc.buffer(ENDPOINT_OUT_NUM).iter().zip(bytes) {
+                register.set(*byte);
+            }
+            self.msc
+                .packet_out(TransferType::Bulk, ENDPOINT_OUT_NUM, bytes.len() as u32)
+        }
+
+        /// Send a packet to the OUT endpoint, resending it while the drive
+        /// delays it because the storage is busy.
+        fn packet_out_retried(&self, bytes: &[u8]) {
+            loop {
+                match self.packet_out(bytes) {
+                    OutResult::Ok => return,
+                    OutResult::Delay => {
+                        let resumed = self.usb.out_resumed.get();
+                        assert!(self.storage.complete(), "delayed without a reason");
+                        assert_eq!(self.usb.out_resumed.get(), resumed + 1);
+                    }
+                    OutResult::Error => panic!("the OUT endpoint stalled"),
+                }
+            }
+        }
+
+        /// Send a Command Block Wrapper for the command block `cb`, expecting
+        /// `length` bytes of data in the given direction. Returns its tag.
+        fn command(&self, length: u32, direction_in: bool, cb: &[u8]) -> u32 {
+            let tag = self.next_tag.get();
+            self.next_tag.set(tag + 1);
+            let mut cbw = [0; CBW_LENGTH];
+            cbw[0..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+            cbw[4..8].copy_from_slice(&tag.to_le_bytes());
+            cbw[8..12].copy_from_slice(&length.to_le_bytes());
+            cbw[12] = if direction_in { 0x80 } else { 0 };
+            cbw[14] = cb.len() as u8;
+            cbw[15..15 + cb.len()].copy_from_slice(cb);
+            self.packet_out_retried(&cbw);
+            tag
+        }
+
+        /// Receive packets from the IN endpoint, completing storage
+        /// operations along the way, until the drive has nothing left to
+        /// send. Returns the data, and the status ending the command.
+        fn receive(&self) -> (Vec<u8>, Csw) {
+            let mut packets = Vec::new();
+            loop {
+                match self.msc.packet_in(TransferType::Bulk, ENDPOINT_IN_NUM) {
+                    InResult::Packet(len) => {
+                        let packet = self.msc.buffer(ENDPOINT_IN_NUM);
+                        packets.push(packet[..len].iter().map(|b| b.get()).collect::<Vec<_>>());
+                        self.msc.packet_transmitted(ENDPOINT_IN_NUM);
+                    }
+                    InResult::Delay => {
+                        if !self.storage.complete() {
+                            break;
+                        }
+                    }
+                    InResult::Error => panic!("the IN endpoint stalled"),
+                }
+            }
+            let csw = packets.pop().expect("no status was sent");
+            assert_eq!(csw.len(), CSW_LENGTH);
+            assert_eq!(csw[0..4], CSW_SIGNATURE.to_le_bytes());
+            let u32_at = |i: usize| u32::from_le_bytes(csw[i..i + 4].try_into().unwrap());
+            (
+                packets.concat(),
+                Csw {
+                    tag: u32_at(4),
+                    residue: u32_at(8),
+                    status: csw[12],
+                },
+            )
+        }
+
+        fn request_sense(&self) -> (u8, u8) {
+            let tag = self.command(18, true, &[REQUEST_SENSE, 0, 0, 0, 18, 0]);
+            let (data, csw) = self.receive();
+            assert_eq!(
+                csw,
+                Csw {
+                    tag,
+                    residue: 0,
+                    status: 0
+                }
+            );
+            (data[2], data[12])
+        }
+    }
+
+    fn rw10(opcode: u8, lba: u32, blocks: u16) -> [u8; 10] {
+        let lba = lba.to_be_bytes();
+        let blocks = blocks.to_be_bytes();
+        [
+            opcode, 0, lba[0], lba[1], lba[2], lba[3], 0, blocks[0], blocks[1], 0,
+        ]
+    }
+
+    #[test]
+    fn multi_block_read() {
+        let host = Host::new();
+        let tag = host.command(3 * BLOCK_SIZE as u32, true, &rw10(READ_10, 2, 3));
+        let (data, csw) = host.receive();
+        assert_eq!(
+            csw,
+            Csw {
+                tag,
+                residue: 0,
+                status: 0
+            }
+        );
+        assert_eq!(data, [2, 3, 4].map(block_pattern).concat());
+    }
+
+    #[test]
+    fn multi_block_write() {
+        let host = Host::new();
+        let blocks: Vec<u8> = (0..2 * BLOCK_SIZE).map(|i| (i / 3) as u8).collect();
+        let tag = host.command(2 * BLOCK_SIZE as u32, false, &rw10(WRITE_10, 6, 2));
+        for packet in blocks.chunks(PACKET_SIZE) {
+            host.packet_out_retried(packet);
+        }
+        let (data, csw) = host.receive();
+        assert!(data.is_empty());
+        assert_eq!(
+            csw,
+            Csw {
+                tag,
+                residue: 0,
+                status: 0
+            }
+        );
+        assert_eq!(host.stored_block(5), block_pattern(5));
+        assert_eq!(host.stored_block(6), blocks[..BLOCK_SIZE]);
+        assert_eq!(host.stored_block(7), blocks[BLOCK_SIZE..]);
+
+        // The written blocks are read back.
+        host.command(2 * BLOCK_SIZE as u32, true, &rw10(READ_10, 6, 2));
+        assert_eq!(host.receive().0, blocks);
+    }
+
+    #[test]
+    fn failed_write_drops_the_data_sent_by_the_host() {
+        let host = Host::new();
+        // The last block is out of range.
+        let length = 2 * BLOCK_SIZE as u32;
+        let tag = host.command(length, false, &rw10(WRITE_10, DRIVE_BLOCKS as u32 - 1, 2));
+
+        // The status is only sent once the host sent all the data it
+        // announced.
+        for _ in 0..length as usize / PACKET_SIZE {
+            assert!(matches!(
+                host.msc.packet_in(TransferType::Bulk, ENDPOINT_IN_NUM),
+                InResult::Delay
+            ));
+            assert!(matches!(host.packet_out(&[0; PACKET_SIZE]), OutResult::Ok));
+        }
+        let (_, csw) = host.receive();
+        assert_eq!(
+            csw,
+            Csw {
+                tag,
+                residue: length,
+                status: CommandStatus::Failed as u8
+            }
+        );
+        assert!(host.storage.pending.borrow().is_none());
+        assert_eq!(
+            host.stored_block(DRIVE_BLOCKS - 1),
+            block_pattern(DRIVE_BLOCKS - 1)
+        );
+        assert_eq!(
+            host.request_sense(),
+            (SENSE_OUT_OF_RANGE.key, SENSE_OUT_OF_RANGE.asc)
+        );
+    }
+
+    #[test]
+    fn reset_aborts_a_read() {
+        let host = Host::new();
+        host.command(2 * BLOCK_SIZE as u32, true, &rw10(READ_10, 0, 2));
+        assert!(host.storage.pending.borrow().is_some());
+
+        // Bulk-Only Mass Storage Reset, while the first block is read.
+        let setup = [0x21, BULK_ONLY_MASS_STORAGE_RESET, 0, 0, 0, 0, 0, 0];
+        for (register, byte) in host.msc.client_ctrl.ctrl_buffer.buf.iter().zip(setup) {
+            register.set(byte);
+        }
+        assert!(matches!(
+            host.msc.ctrl_setup(0),
+            hil::usb::CtrlSetupResult::Ok
+        ));
+        host.msc.ctrl_status_complete(0);
+
+        // The next command is delayed until the storage returns the buffer,
+        // and the aborted read sends nothing.
+        let tag = host.command(BLOCK_SIZE as u32, true, &rw10(READ_10, 5, 1));
+        let (data, csw) = host.receive();
+        assert_eq!(
+            csw,
+            Csw {
+                tag,
+                residue: 0,
+                status: 0
+            }
+        );
+        assert_eq!(data, block_pattern(5));
+    }
+
+    #[test]
+    fn refused_read_loses_the_buffer() {
+        let host = Host::new();
+        host.storage.refuse.set(true);
+        let tag = host.command(BLOCK_SIZE as u32, true, &rw10(READ_10, 0, 1));
+        let (data, csw) = host.receive();
+        assert!(data.is_empty());
+        assert_eq!(
+            csw,
+            Csw {
+                tag,
+                residue: BLOCK_SIZE as u32,
+                status: CommandStatus::Failed as u8
+            }
+        );
+
+        // The buffer is gone, so even REQUEST SENSE fails from now on, but
+        // commands without data still pass.
+        host.storage.refuse.set(false);
+        let tag = host.command(18, true, &[REQUEST_SENSE, 0, 0, 0, 18, 0]);
+        assert_eq!(
+            host.receive().1,
+            Csw {
+                tag,
+                residue: 18,
+                status: CommandStatus::Failed as u8
+            }
+        );
+        let tag = host.command(0, false, &[TEST_UNIT_READY, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            host.receive().1,
+            Csw {
+                tag,
+                residue: 0,
+                status: CommandStatus::Passed as u8
+            }
+        );
+    }
+}
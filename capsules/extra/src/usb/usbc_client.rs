@@ -98,6 +98,7 @@ impl<'a, C: hil::usb::UsbController<'a>> Client<'a, C> {
                 endpoints,
                 None, // No HID descriptor
                 None, // No CDC descriptor array
+                None, // No DFU descriptor
             );
 
         Client {
@@ -49,6 +49,7 @@ enum Operation {
     Add,
     Update,
     GarbageCollect,
+    ListKeys,
 }
 
 pub struct VirtualKVPermissions<'a, V: kv::KVPermissions<'a>> {
@@ -235,6 +236,24 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVPermissions<'a> for VirtualKVPermission
         self.mux_kv.do_next_op(false)
     }
 
+    fn list_keys(
+        &self,
+        keys: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+        if self.operation.is_some() {
+            return Err((keys, ErrorCode::BUSY));
+        }
+
+        self.operation.set(Operation::ListKeys);
+        self.valid_ids.set(permissions);
+        self.value.replace(keys);
+
+        self.mux_kv
+            .do_next_op(false)
+            .map_err(|e| (self.value.take().unwrap(), e))
+    }
+
     fn header_size(&self) -> usize {
         self.mux_kv.kv.header_size()
     }
@@ -283,6 +302,33 @@ impl<'a, V: kv::KVPermissions<'a>> MuxKVPermissions<'a, V> {
                     };
                 }
 
+                // ListKeys doesn't have a key either, the keys are written to
+                // the value buffer.
+                if op == Operation::ListKeys {
+                    return node.value.take().map_or(Ok(()), |keys| {
+                        node.valid_ids.map_or(Ok(()), |perms| {
+                            match self.kv.list_keys(keys, perms) {
+                                Ok(()) => {
+                                    self.inflight.set(node);
+                                    Ok(())
+                                }
+                                Err((keys, e)) => {
+                                    node.operation.clear();
+                                    if async_op {
+                                        node.client.map(move |cb| {
+                                            cb.list_keys_complete(Err(e), keys);
+                                        });
+                                        Ok(())
+                                    } else {
+                                        node.value.replace(keys);
+                                        Err(e)
+                                    }
+                                }
+                            }
+                        })
+                    });
+                }
+
                 node.key.take().map_or(Ok(()), |key| match op {
                     Operation::Get => node.value.take().map_or(Ok(()), |value| {
                         node.valid_ids.map_or(Ok(()), |perms| {
@@ -397,7 +443,7 @@ impl<'a, V: kv::KVPermissions<'a>> MuxKVPermissions<'a, V> {
                                 }
                             })
                     }
-                    Operation::GarbageCollect => Err(ErrorCode::NOSUPPORT),
+                    Operation::GarbageCollect | Operation::ListKeys => Err(ErrorCode::NOSUPPORT),
                 })
             })
         })
@@ -490,4 +536,15 @@ impl<'a, V: kv::KVPermissions<'a>> kv::KVClient for MuxKVPermissions<'a, V> {
 
         let _ = self.do_next_op(true);
     }
+
+    fn list_keys_complete(&self, result: Result<usize, ErrorCode>, keys: SubSliceMut<'static, u8>) {
+        self.inflight.take().map(|node| {
+            node.operation.clear();
+            node.client.map(move |cb| {
+                cb.list_keys_complete(result, keys);
+            });
+        });
+
+        let _ = self.do_next_op(true);
+    }
 }
@@ -0,0 +1,466 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Checkpoint processes to nonvolatile storage and restore them on boot.
+//!
+//! This capsule saves a snapshot of a process (its RAM, app break and
+//! registers, see [`Process::checkpoint`]) into a region of flash allocated
+//! with [`storage_volume!`](kernel::storage_volume), and on the next boot
+//! resumes each process from its snapshot if the snapshot was taken from the
+//! same process binary. This lets long-running processes survive a reset (for
+//! example, one triggered by a watchdog) without losing their state.
+//!
+//! The volume is split into equally sized slots, one per process, with the
+//! slot size set by the length of the buffer passed to the capsule. Processes
+//! are assigned slots in the order they appear in the process array, so a
+//! snapshot is only found again if the same set of processes is loaded. Grant
+//! state is not part of a snapshot: a restored process must re-establish any
+//! subscriptions or allows it needs, and a process that was yielded when the
+//! snapshot was taken resumes as if its yield returned without an upcall.
+//!
+//! Snapshots persist across reboots, but are erased when a new kernel is
+//! flashed. The volume is written through a
+//! [`NonvolatileStorage`](kernel::hil::nonvolatile_storage::NonvolatileStorage)
+//! whose addresses must match the physical addresses of the flash, as is the
+//! case for `NonvolatileToPages` over on-chip flash. A `NonvolatileStorage`
+//! that rejects a write does not hand the buffer back, so after such a
+//! failure every further checkpoint fails with `ErrorCode::FAIL`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! storage_volume!(CHECKPOINTS, 32);
+//!
+//! let process_checkpoint = components::process_checkpoint::ProcessCheckpointComponent::new(
+//!     board_kernel,
+//!     nv_to_page,
+//!     &CHECKPOINTS,
+//!     checkpoint_cap,
+//! )
+//! .finalize(components::process_checkpoint_component_static!(CheckpointCap, 8192));
+//!
+//! // After processes are loaded, before starting the kernel loop.
+//! process_checkpoint.restore();
+//!
+//! // Optionally, take checkpoints with the `checkpoint` console command.
+//! ProcessCheckpointer::set_client(process_checkpoint, process_console);
+//! process_console.set_checkpointer(process_checkpoint);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::ErrorCode;
+use kernel::Kernel;
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::process::{CheckpointClient, Process, ProcessCheckpointer, ProcessId};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+pub struct ProcessCheckpoint<'a, CAP: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    storage: &'a dyn NonvolatileStorage<'a>,
+    /// Flash region holding the checkpoint slots, readable in place.
+    volume: &'static [u8],
+    /// Buffer a checkpoint is serialized into before it is written to
+    /// storage. Its length is the size of each slot in `volume`.
+    buffer: TakeCell<'static, [u8]>,
+    slot_size: usize,
+    /// Process whose checkpoint is currently being written, its slot and the
+    /// length of the checkpoint.
+    current: OptionalCell<(ProcessId, usize, usize)>,
+    /// Whether we are working through all processes for `checkpoint_all()`.
+    checkpointing_all: Cell<bool>,
+    client: OptionalCell<&'a dyn CheckpointClient>,
+    capability: CAP,
+}
+
+impl<'a, CAP: ProcessManagementCapability> ProcessCheckpoint<'a, CAP> {
+    pub fn new(
+        kernel: &'static Kernel,
+        storage: &'a dyn NonvolatileStorage<'a>,
+        volume: &'static [u8],
+        buffer: &'static mut [u8],
+        capability: CAP,
+    ) -> Self {
+        Self {
+            kernel,
+            storage,
+            volume,
+            slot_size: buffer.len(),
+            buffer: TakeCell::new(buffer),
+            current: OptionalCell::empty(),
+            checkpointing_all: Cell::new(false),
+            client: OptionalCell::empty(),
+            capability,
+        }
+    }
+
+    /// Restore every loaded process that has a valid checkpoint in its slot.
+    ///
+    /// This must be called after processes are loaded and before the kernel
+    /// loop starts. Processes without a matching checkpoint start normally.
+    /// Returns the number of processes that were restored.
+    pub fn restore(&self) -> usize {
+        let mut restored = 0;
+        for (slot, process) in self
+            .kernel
+            .process_iter_capability(&self.capability)
+            .enumerate()
+        {
+            if self
+                .slot(slot)
+                .is_some_and(|snapshot| process.restore_checkpoint(snapshot).is_ok())
+            {
+                restored += 1;
+            }
+        }
+        restored
+    }
+
+    /// Start writing the checkpoint of the first process at or after `slot`
+    /// that can be checkpointed, reporting processes that cannot be. Signals
+    /// completion if there are no processes left.
+    fn checkpoint_from(&self, slot: usize) {
+        for (slot, process) in self
+            .kernel
+            .process_iter_capability(&self.capability)
+            .enumerate()
+            .skip(slot)
+        {
+            match self.write_checkpoint(process, slot) {
+                Ok(()) => return,
+                Err(e) => {
+                    self.client
+                        .map(|client| client.checkpoint_done(process.processid(), Err(e)));
+                }
+            }
+        }
+
+        self.checkpointing_all.set(false);
+        self.client.map(|client| client.checkpoint_all_done());
+    }
+
+    fn write_checkpoint(&self, process: &dyn Process, slot: usize) -> Result<(), ErrorCode> {
+        let address = self.slot(slot).ok_or(ErrorCode::NOMEM)?.as_ptr() as usize;
+        // No write is in progress, so the buffer is only missing if the
+        // storage rejected a write and kept it.
+        let buffer = self.buffer.take().ok_or(ErrorCode::FAIL)?;
+
+        let length = match process.checkpoint(buffer) {
+            Ok(length) => length,
+            Err(e) => {
+                self.buffer.replace(buffer);
+                return Err(e);
+            }
+        };
+
+        self.storage.write(buffer, address, length)?;
+        self.current.set((process.processid(), slot, length));
+        Ok(())
+    }
+
+    /// The region of the volume holding the checkpoint for `slot`, if the
+    /// volume is large enough to have that slot.
+    fn slot(&self, slot: usize) -> Option<&'static [u8]> {
+        let start = slot.checked_mul(self.slot_size)?;
+        self.volume.get(start..start.checked_add(self.slot_size)?)
+    }
+}
+
+impl<'a, CAP: ProcessManagementCapability> ProcessCheckpointer<'a> for ProcessCheckpoint<'a, CAP> {
+    /// Checkpoint a single process to its slot in storage.
+    ///
+    /// On success, `checkpoint_done()` is called once the checkpoint has been
+    /// written. Returns `ErrorCode::BUSY` if another checkpoint is being
+    /// written, `ErrorCode::INVAL` if the process does not exist,
+    /// `ErrorCode::NOMEM` if the volume has no slot for the process, and
+    /// `ErrorCode::FAIL` if the storage rejected an earlier write.
+    fn checkpoint(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if self.current.is_some() || self.checkpointing_all.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let (slot, process) = self
+            .kernel
+            .process_iter_capability(&self.capability)
+            .enumerate()
+            .find(|(_, process)| process.processid() == processid)
+            .ok_or(ErrorCode::INVAL)?;
+        self.write_checkpoint(process, slot)
+    }
+
+    /// Checkpoint every loaded process, one after the other.
+    ///
+    /// `checkpoint_done()` is called for each process, followed by
+    /// `checkpoint_all_done()`. Processes that cannot be checkpointed at the
+    /// moment (for example, because they are waiting on a specific upcall)
+    /// are reported with an error and skipped.
+    fn checkpoint_all(&self) -> Result<(), ErrorCode> {
+        if self.current.is_some() || self.checkpointing_all.get() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.checkpointing_all.set(true);
+        self.checkpoint_from(0);
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn CheckpointClient) {
+        self.client.set(client);
+    }
+}
+
+impl<CAP: ProcessManagementCapability> NonvolatileStorageClient for ProcessCheckpoint<'_, CAP> {
+    fn read_done(&self, _buffer: &'static mut [u8], _length: usize) {}
+
+    fn write_done(&self, buffer: &'static mut [u8], written: usize) {
+        self.buffer.replace(buffer);
+
+        if let Some((processid, slot, length)) = self.current.take() {
+            let result = if written == length {
+                Ok(())
+            } else {
+                Err(ErrorCode::FAIL)
+            };
+            self.client
+                .map(|client| client.checkpoint_done(processid, result));
+
+            if self.checkpointing_all.get() {
+                self.checkpoint_from(slot + 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    use capsules_test_harness::process::{App, HostKernel};
+    use capsules_test_harness::{deferred_call, leak, static_buf};
+
+    use super::*;
+
+    /// Size of a checkpoint slot, large enough for the RAM of a test process.
+    const SLOT_SIZE: usize = 32 * 1024;
+
+    /// Storage which keeps each write pending until the test completes it.
+    struct MockStorage {
+        client: OptionalCell<&'static dyn NonvolatileStorageClient>,
+        pending: TakeCell<'static, [u8]>,
+        /// Address and length of every accepted write.
+        writes: RefCell<Vec<(usize, usize)>>,
+        reject: Cell<bool>,
+    }
+
+    impl MockStorage {
+        fn new() -> Self {
+            Self {
+                client: OptionalCell::empty(),
+                pending: TakeCell::empty(),
+                writes: RefCell::new(Vec::new()),
+                reject: Cell::new(false),
+            }
+        }
+
+        /// Complete the pending write, reporting `written` bytes.
+        fn complete(&self, written: usize) {
+            let buffer = self.pending.take().expect("no write is pending");
+            self.client.map(|client| client.write_done(buffer, written));
+        }
+
+        /// Complete the pending write in full.
+        fn complete_all(&self) {
+            let length = self.writes.borrow().last().unwrap().1;
+            self.complete(length);
+        }
+    }
+
+    impl NonvolatileStorage<'static> for MockStorage {
+        fn set_client(&self, client: &'static dyn NonvolatileStorageClient) {
+            self.client.set(client);
+        }
+
+        fn read(&self, _: &'static mut [u8], _: usize, _: usize) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+
+        fn write(
+            &self,
+            buffer: &'static mut [u8],
+            address: usize,
+            length: usize,
+        ) -> Result<(), ErrorCode> {
+            if self.reject.get() || self.pending.is_some() {
+                return Err(ErrorCode::FAIL);
+            }
+            self.writes.borrow_mut().push((address, length));
+            self.pending.replace(buffer);
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct Results {
+        done: RefCell<Vec<(ProcessId, Result<(), ErrorCode>)>>,
+        all_done: Cell<bool>,
+    }
+
+    impl CheckpointClient for Results {
+        fn checkpoint_done(&self, processid: ProcessId, result: Result<(), ErrorCode>) {
+            self.done.borrow_mut().push((processid, result));
+        }
+
+        fn checkpoint_all_done(&self) {
+            self.all_done.set(true);
+        }
+    }
+
+    type TestCheckpoint = ProcessCheckpoint<'static, CheckpointCap>;
+    type CheckpointCap = capsules_test_harness::ProcessManagementCap;
+
+    /// Load a process for each of `names` and checkpoint them into a volume
+    /// with two slots.
+    fn setup(
+        names: &[&str],
+    ) -> (
+        &'static TestCheckpoint,
+        &'static MockStorage,
+        &'static Results,
+        &'static [u8],
+        Vec<App>,
+    ) {
+        let kernel = HostKernel::new();
+        let apps = names.iter().map(|name| kernel.load_process(name)).collect();
+        let storage = leak(MockStorage::new());
+        let volume: &'static [u8] = static_buf(2 * SLOT_SIZE);
+        let checkpoint = leak(ProcessCheckpoint::new(
+            kernel.kernel(),
+            storage,
+            volume,
+            static_buf(SLOT_SIZE),
+            capsules_test_harness::process_management_capability(),
+        ));
+        storage.set_client(checkpoint);
+        let results = leak(Results::default());
+        checkpoint.set_client(results);
+        (checkpoint, storage, results, volume, apps)
+    }
+
+    fn slot_address(volume: &[u8], slot: usize) -> usize {
+        volume.as_ptr() as usize + slot * SLOT_SIZE
+    }
+
+    #[test]
+    fn writes_checkpoint_to_process_slot() {
+        deferred_call::run(|| {
+            let (checkpoint, storage, results, volume, apps) = setup(&["first", "second"]);
+
+            assert_eq!(checkpoint.checkpoint(apps[1].id()), Ok(()));
+            assert_eq!(checkpoint.checkpoint(apps[0].id()), Err(ErrorCode::BUSY));
+            let (address, length) = storage.writes.borrow()[0];
+            assert_eq!(address, slot_address(volume, 1));
+            assert!(length > 0 && length <= SLOT_SIZE);
+
+            storage.complete_all();
+            assert_eq!(*results.done.borrow(), [(apps[1].id(), Ok(()))]);
+            assert!(!results.all_done.get());
+
+            // The buffer is back, so the next checkpoint can be taken.
+            assert_eq!(checkpoint.checkpoint(apps[0].id()), Ok(()));
+            assert_eq!(storage.writes.borrow()[1].0, slot_address(volume, 0));
+        });
+    }
+
+    #[test]
+    fn checkpoints_all_processes_in_order() {
+        deferred_call::run(|| {
+            let (checkpoint, storage, results, volume, apps) = setup(&["first", "second"]);
+
+            assert_eq!(checkpoint.checkpoint_all(), Ok(()));
+            assert_eq!(checkpoint.checkpoint(apps[0].id()), Err(ErrorCode::BUSY));
+            storage.complete_all();
+            storage.complete_all();
+
+            let addresses: Vec<usize> = storage.writes.borrow().iter().map(|w| w.0).collect();
+            assert_eq!(
+                addresses,
+                [slot_address(volume, 0), slot_address(volume, 1)]
+            );
+            assert_eq!(
+                *results.done.borrow(),
+                [(apps[0].id(), Ok(())), (apps[1].id(), Ok(()))]
+            );
+            assert!(results.all_done.get());
+        });
+    }
+
+    #[test]
+    fn reports_processes_without_a_slot() {
+        deferred_call::run(|| {
+            let (checkpoint, storage, results, _, apps) = setup(&["a", "b", "c"]);
+
+            assert_eq!(checkpoint.checkpoint(apps[2].id()), Err(ErrorCode::NOMEM));
+
+            assert_eq!(checkpoint.checkpoint_all(), Ok(()));
+            storage.complete_all();
+            storage.complete_all();
+            assert_eq!(
+                *results.done.borrow(),
+                [
+                    (apps[0].id(), Ok(())),
+                    (apps[1].id(), Ok(())),
+                    (apps[2].id(), Err(ErrorCode::NOMEM)),
+                ]
+            );
+            assert!(results.all_done.get());
+        });
+    }
+
+    #[test]
+    fn reports_short_write() {
+        deferred_call::run(|| {
+            let (checkpoint, storage, results, _, apps) = setup(&["first"]);
+
+            assert_eq!(checkpoint.checkpoint(apps[0].id()), Ok(()));
+            storage.complete(0);
+            assert_eq!(
+                *results.done.borrow(),
+                [(apps[0].id(), Err(ErrorCode::FAIL))]
+            );
+            assert_eq!(checkpoint.checkpoint(apps[0].id()), Ok(()));
+        });
+    }
+
+    #[test]
+    fn reports_rejected_write() {
+        deferred_call::run(|| {
+            let (checkpoint, storage, results, _, apps) = setup(&["first", "second"]);
+
+            storage.reject.set(true);
+            assert_eq!(checkpoint.checkpoint(apps[0].id()), Err(ErrorCode::FAIL));
+            assert!(results.done.borrow().is_empty());
+
+            // The storage kept the buffer: checkpoints fail rather than
+            // reporting the capsule as busy forever, and `checkpoint_all`
+            // still reports every process and finishes.
+            storage.reject.set(false);
+            assert_eq!(checkpoint.checkpoint(apps[1].id()), Err(ErrorCode::FAIL));
+            assert_eq!(checkpoint.checkpoint_all(), Ok(()));
+            assert_eq!(
+                *results.done.borrow(),
+                [
+                    (apps[0].id(), Err(ErrorCode::FAIL)),
+                    (apps[1].id(), Err(ErrorCode::FAIL)),
+                ]
+            );
+            assert!(results.all_done.get());
+            assert!(storage.writes.borrow().is_empty());
+        });
+    }
+}
@@ -8,9 +8,11 @@
 //! managing processes. For example, these policies control decisions such as
 //! whether a specific process should be restarted.
 
+use kernel::debug::{CrashKind, CrashRecordWriter, CrashRecorder};
 use kernel::process;
 use kernel::process::Process;
 use kernel::process::ProcessFaultPolicy;
+use kernel::process::ProcessPrinter;
 
 /// Simply panic the entire board if a process faults.
 pub struct PanicFaultPolicy {}
@@ -116,3 +118,46 @@ impl ProcessFaultPolicy for ThresholdRestartThenPanicFaultPolicy {
         }
     }
 }
+
+/// Save a crash record of each process fault to a [`CrashRecorder`], then
+/// decide what to do using another fault policy.
+///
+/// The record holds the fault registers of the process, taken from its stored
+/// state by `Process::fault_registers()`, the name of the process, and the
+/// process overview from the `ProcessPrinter`. If the recorder is still busy
+/// writing an earlier record, the fault is not recorded.
+pub struct CrashRecordFaultPolicy<'a> {
+    recorder: &'a dyn CrashRecorder,
+    printer: &'a dyn ProcessPrinter,
+    policy: &'a dyn ProcessFaultPolicy,
+}
+
+impl<'a> CrashRecordFaultPolicy<'a> {
+    pub fn new(
+        recorder: &'a dyn CrashRecorder,
+        printer: &'a dyn ProcessPrinter,
+        policy: &'a dyn ProcessFaultPolicy,
+    ) -> CrashRecordFaultPolicy<'a> {
+        CrashRecordFaultPolicy {
+            recorder,
+            printer,
+            policy,
+        }
+    }
+}
+
+impl ProcessFaultPolicy for CrashRecordFaultPolicy<'_> {
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        if !self.recorder.is_busy() {
+            self.recorder.begin(
+                CrashKind::ProcessFault,
+                &process.fault_registers(),
+                Some(process.get_process_name()),
+            );
+            let mut writer = CrashRecordWriter(self.recorder);
+            self.printer.print_overview(process, &mut writer, None);
+            let _ = self.recorder.commit();
+        }
+        self.policy.action(process)
+    }
+}
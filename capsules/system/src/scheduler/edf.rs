@@ -0,0 +1,389 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Earliest deadline first scheduler with CPU reservations for Tock
+//!
+//! The board gives some processes a reservation: `budget_us` of CPU time in
+//! every period of `period_us`. These real-time processes are scheduled
+//! earliest deadline first, where the deadline of each job is the end of the
+//! current period. All other processes are best-effort and run round robin
+//! whenever no real-time process can run.
+//!
+//! This scheduler can be summarized by the following rules:
+//!
+//! - Rule 1: At the start of each period the budget of a real-time process is
+//!   replenished and its deadline moves to the end of the new period.
+//! - Rule 2: Of the ready real-time processes with budget left, the one with
+//!   the earliest deadline runs.
+//! - Rule 3: A real-time process runs with its remaining budget as its
+//!   timeslice, so the `SchedulerTimer` preempts it once the budget is
+//!   exhausted. It then waits for its next period, even if the CPU is idle.
+//!   This keeps an overrunning process from delaying the others.
+//! - Rule 4: Timeslices end at the next period boundary of any real-time
+//!   process, so that a newly released job preempts the running process if
+//!   its deadline is earlier.
+//! - Rule 5: If a real-time process still has work left when its period ends,
+//!   meaning it was ready at some point in the period and did not yield with
+//!   nothing left to do, it missed its deadline. Misses are printed with
+//!   `debug!` and counted. The counts are available through `SchedulerInfo`,
+//!   which the process console shows in its `list` and `status` commands.
+//!
+//! The scheduler does no admission control. The board must ensure that the
+//! sum of `budget_us / period_us` over all reservations is at most 1, and
+//! should leave some room for the kernel and best-effort processes.
+
+use core::cell::Cell;
+use core::num::NonZeroU32;
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::platform::chip::Chip;
+use kernel::process::{ProcessId, ProcessSlot, StoppedExecutingReason};
+use kernel::scheduler::{Scheduler, SchedulerInfo, SchedulingDecision};
+use kernel::utilities::cells::OptionalCell;
+
+/// CPU reservation of a real-time process.
+#[derive(Copy, Clone, Debug)]
+pub struct Reservation {
+    /// Name of the process, as in its TBF header.
+    pub process_name: &'static str,
+    /// Length of each period in microseconds.
+    pub period_us: u32,
+    /// CPU time the process may use in each period in microseconds.
+    pub budget_us: u32,
+}
+
+/// Nodes store per-process state
+pub struct EdfProcessNode<'a, A: 'static + time::Alarm<'static>> {
+    proc: &'static ProcessSlot,
+    /// The process the state below belongs to.
+    processid: OptionalCell<ProcessId>,
+    /// Reservation of the process, `None` if it is best-effort.
+    reservation: OptionalCell<&'static Reservation>,
+    /// End of the current period.
+    deadline: Cell<A::Ticks>,
+    budget_left_us: Cell<u32>,
+    /// Whether the process has had work in this period it has not finished.
+    pending: Cell<bool>,
+    deadline_misses: Cell<u32>,
+    next: ListLink<'a, EdfProcessNode<'a, A>>,
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> EdfProcessNode<'a, A> {
+    pub fn new(proc: &'static ProcessSlot) -> EdfProcessNode<'a, A> {
+        EdfProcessNode {
+            proc,
+            processid: OptionalCell::empty(),
+            reservation: OptionalCell::empty(),
+            deadline: Cell::new(A::Ticks::from(0)),
+            budget_left_us: Cell::new(0),
+            pending: Cell::new(false),
+            deadline_misses: Cell::new(0),
+            next: ListLink::empty(),
+        }
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> ListNode<'a, EdfProcessNode<'a, A>>
+    for EdfProcessNode<'a, A>
+{
+    fn next(&'a self) -> &'a ListLink<'a, EdfProcessNode<'a, A>> {
+        &self.next
+    }
+}
+
+pub struct EdfSched<'a, A: 'static + time::Alarm<'static>> {
+    alarm: &'static A,
+    reservations: &'static [Reservation],
+    pub processes: List<'a, EdfProcessNode<'a, A>>,
+    /// Node of the process that ran last.
+    last: OptionalCell<&'a EdfProcessNode<'a, A>>,
+    /// Whether the last timeslice was the remaining budget of the process.
+    last_budget_limited: Cell<bool>,
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> EdfSched<'a, A> {
+    /// How long a best-effort process can run before being pre-empted
+    pub const DEFAULT_TIMESLICE_US: u32 = 10000;
+    /// Shortest timeslice handed out, so that switching processes does not
+    /// take longer than running them.
+    const MIN_TIMESLICE_US: u32 = 100;
+
+    pub fn new(alarm: &'static A, reservations: &'static [Reservation]) -> Self {
+        Self {
+            alarm,
+            reservations,
+            processes: List::new(),
+            last: OptionalCell::empty(),
+            last_budget_limited: Cell::new(false),
+        }
+    }
+
+    /// Brings the state of `node` up to date at `now`: looks up the
+    /// reservation of a newly loaded process, and starts a new period once
+    /// the current one has ended.
+    fn update(&self, node: &EdfProcessNode<'a, A>, now: A::Ticks) {
+        let Some(proc) = node.proc.get() else {
+            node.processid.clear();
+            node.reservation.clear();
+            return;
+        };
+        if node.processid.get() != Some(proc.processid()) {
+            node.processid.set(proc.processid());
+            node.reservation.insert(
+                self.reservations
+                    .iter()
+                    .find(|reservation| reservation.process_name == proc.get_process_name()),
+            );
+            // Start the first period right away.
+            node.deadline.set(now);
+            node.pending.set(false);
+            node.deadline_misses.set(0);
+        }
+        let Some(reservation) = node.reservation.get() else {
+            return;
+        };
+
+        let period = self.alarm.ticks_from_us(reservation.period_us);
+        let deadline = node.deadline.get();
+        if !now.within_range(deadline.wrapping_sub(period), deadline) {
+            if node.pending.get() {
+                node.deadline_misses.set(node.deadline_misses.get() + 1);
+                kernel::debug!(
+                    "Process {} missed its deadline ({} misses).",
+                    proc.get_process_name(),
+                    node.deadline_misses.get()
+                );
+            }
+            // If whole periods passed, for example because the process was
+            // not ready, start the new period now.
+            node.deadline.set(if now.wrapping_sub(deadline) < period {
+                deadline.wrapping_add(period)
+            } else {
+                now.wrapping_add(period)
+            });
+            node.budget_left_us.set(reservation.budget_us);
+            node.pending.set(false);
+        }
+        if proc.ready() {
+            node.pending.set(true);
+        }
+    }
+
+    /// Moves `node` to the tail of the list, so that best-effort processes
+    /// take turns.
+    fn move_to_tail(&self, node: &'a EdfProcessNode<'a, A>) {
+        while let Some(head) = self.processes.pop_head() {
+            self.processes.push_tail(head);
+            if core::ptr::eq(head, node) {
+                break;
+            }
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for EdfSched<'_, A> {
+    fn next(&self) -> SchedulingDecision {
+        let now = self.alarm.now();
+        for node in self.processes.iter() {
+            self.update(node, now);
+        }
+
+        // Find the ready real-time process with budget left and the earliest
+        // deadline, the earliest period boundary, and the earliest period
+        // start of a process waiting for budget.
+        let mut earliest = None;
+        let mut next_boundary: Option<A::Ticks> = None;
+        let mut next_replenish: Option<A::Ticks> = None;
+        for node in self.processes.iter() {
+            let (Some(proc), true) = (node.proc.get(), node.reservation.is_some()) else {
+                continue;
+            };
+            let until_deadline = node.deadline.get().wrapping_sub(now);
+            next_boundary = Some(next_boundary.map_or(until_deadline, |t| t.min(until_deadline)));
+            if !proc.ready() {
+                continue;
+            }
+            if node.budget_left_us.get() == 0 {
+                next_replenish =
+                    Some(next_replenish.map_or(until_deadline, |t| t.min(until_deadline)));
+            } else if earliest.is_none_or(|(_, t)| until_deadline < t) {
+                earliest = Some((node, until_deadline));
+            }
+        }
+
+        let (node, timeslice) = match earliest {
+            Some((node, _)) => (node, node.budget_left_us.get()),
+            None => {
+                let best_effort = self.processes.iter().find(|node| {
+                    node.reservation.is_none() && node.proc.get().is_some_and(|proc| proc.ready())
+                });
+                match best_effort {
+                    Some(node) => (node, Self::DEFAULT_TIMESLICE_US),
+                    None => {
+                        // Nothing can run. Make sure we wake up to give a
+                        // waiting process its new budget.
+                        if let Some(dt) = next_replenish {
+                            self.alarm.set_alarm(now, dt);
+                        }
+                        return SchedulingDecision::TrySleep;
+                    }
+                }
+            }
+        };
+        let until_boundary = next_boundary.map_or(u32::MAX, |t| self.alarm.ticks_to_us(t));
+        self.last_budget_limited
+            .set(earliest.is_some() && timeslice <= until_boundary);
+        let timeslice = timeslice.min(until_boundary).max(Self::MIN_TIMESLICE_US);
+
+        // `node` was found above, so the process exists.
+        let next = node.proc.get().unwrap().processid();
+        self.last.set(node);
+
+        SchedulingDecision::RunProcess((next, NonZeroU32::new(timeslice)))
+    }
+
+    fn result(&self, result: StoppedExecutingReason, execution_time_us: Option<u32>) {
+        let Some(node) = self.last.take() else {
+            return;
+        };
+        let execution_time_us = execution_time_us.unwrap(); // should never fail as we never run cooperatively
+
+        if node.reservation.is_some() {
+            if result == StoppedExecutingReason::TimesliceExpired && self.last_budget_limited.get()
+            {
+                node.budget_left_us.set(0);
+            } else {
+                node.budget_left_us
+                    .set(node.budget_left_us.get().saturating_sub(execution_time_us));
+            }
+        } else if result != StoppedExecutingReason::KernelPreemption {
+            self.move_to_tail(node);
+        }
+
+        if result == StoppedExecutingReason::NoWorkLeft {
+            node.pending.set(false);
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>> SchedulerInfo for EdfSched<'_, A> {
+    /// Number of deadlines the process has missed since it started, or
+    /// `None` if it has no reservation.
+    fn deadline_misses(&self, processid: ProcessId) -> Option<u32> {
+        self.processes
+            .iter()
+            .find(|node| node.processid.get() == Some(processid))
+            .and_then(|node| node.reservation.map(|_| node.deadline_misses.get()))
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>> time::AlarmClient for EdfSched<'_, A> {
+    fn alarm(&self) {
+        // Only used to wake the chip, `next()` replenishes the budgets.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use capsules_test_harness::alarm::MockAlarm;
+    use capsules_test_harness::chip::HostChip;
+    use capsules_test_harness::process::{App, HostKernel};
+    use capsules_test_harness::{deferred_call, leak};
+    use kernel::hil::time::Freq1MHz;
+
+    use super::*;
+
+    type TestAlarm = MockAlarm<'static, Freq1MHz>;
+    type TestSched = EdfSched<'static, TestAlarm>;
+
+    static RESERVATIONS: [Reservation; 2] = [
+        Reservation {
+            process_name: "slow",
+            period_us: 10_000,
+            budget_us: 2_000,
+        },
+        Reservation {
+            process_name: "fast",
+            period_us: 5_000,
+            budget_us: 1_000,
+        },
+    ];
+
+    /// Load a process for each of `names` and schedule them with EDF.
+    fn setup(names: &[&str]) -> (&'static TestAlarm, &'static TestSched, Vec<App>) {
+        let kernel = HostKernel::new();
+        let apps = names.iter().map(|name| kernel.load_process(name)).collect();
+        let alarm = leak(TestAlarm::new());
+        let sched = leak(EdfSched::new(alarm, &RESERVATIONS));
+        for slot in kernel.processes() {
+            sched.processes.push_tail(leak(EdfProcessNode::new(slot)));
+        }
+        (alarm, sched, apps)
+    }
+
+    /// The process to run and its timeslice, or `None` to sleep.
+    fn next(sched: &TestSched) -> Option<(ProcessId, u32)> {
+        match Scheduler::<HostChip>::next(sched) {
+            SchedulingDecision::RunProcess((id, timeslice)) => {
+                Some((id, timeslice.map_or(0, NonZeroU32::get)))
+            }
+            SchedulingDecision::TrySleep => None,
+        }
+    }
+
+    fn result(sched: &TestSched, reason: StoppedExecutingReason, execution_time_us: u32) {
+        Scheduler::<HostChip>::result(sched, reason, Some(execution_time_us));
+    }
+
+    #[test]
+    fn runs_earliest_deadline_first() {
+        deferred_call::run(|| {
+            let (alarm, sched, apps) = setup(&["slow", "fast", "idle"]);
+
+            // Both periods start now, the shorter one ends first.
+            assert_eq!(next(sched), Some((apps[1].id(), 1_000)));
+            result(sched, StoppedExecutingReason::TimesliceExpired, 1_000);
+            alarm.advance(1_000);
+
+            assert_eq!(next(sched), Some((apps[0].id(), 2_000)));
+            result(sched, StoppedExecutingReason::TimesliceExpired, 2_000);
+            alarm.advance(2_000);
+
+            // All budgets are used up, the best-effort process runs until
+            // the next period starts.
+            assert_eq!(next(sched), Some((apps[2].id(), 2_000)));
+            result(sched, StoppedExecutingReason::TimesliceExpired, 2_000);
+            alarm.advance(2_000);
+
+            assert_eq!(next(sched), Some((apps[1].id(), 1_000)));
+        });
+    }
+
+    #[test]
+    fn counts_missed_deadlines() {
+        deferred_call::run(|| {
+            let (alarm, sched, apps) = setup(&["fast", "idle"]);
+
+            // Preempted with work left when the period ends.
+            assert_eq!(next(sched), Some((apps[0].id(), 1_000)));
+            result(sched, StoppedExecutingReason::KernelPreemption, 300);
+            alarm.advance(5_000);
+            assert_eq!(next(sched), Some((apps[0].id(), 1_000)));
+            assert_eq!(sched.deadline_misses(apps[0].id()), Some(1));
+            assert_eq!(sched.deadline_misses(apps[1].id()), None);
+
+            // Finishing its work in time is not a miss.
+            result(sched, StoppedExecutingReason::NoWorkLeft, 100);
+            assert_eq!(apps[0].yield_wait(), None);
+            alarm.advance(5_000);
+            assert_eq!(next(sched), Some((apps[1].id(), 5_000)));
+            assert_eq!(sched.deadline_misses(apps[0].id()), Some(1));
+        });
+    }
+}
@@ -0,0 +1,82 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Storage permissions policy that limits how much storage applications use.
+//!
+//! This wraps another storage permissions policy and adds a storage quota to
+//! the permissions it assigns. Applications with a fixed `ShortId` listed in
+//! `app_quotas` get the quota listed for them, all other applications get the
+//! default quota (if any).
+
+use kernel::capabilities::ApplicationStorageCapability;
+use kernel::platform::chip::Chip;
+use kernel::process::Process;
+use kernel::process::ShortId;
+use kernel::process::{ProcessStandard, ProcessStandardStoragePermissionsPolicy};
+use kernel::storage_permissions::{StoragePermissions, StorageQuota};
+
+pub struct QuotaStoragePermissions<
+    C: Chip,
+    D: kernel::process::ProcessStandardDebug,
+    P: ProcessStandardStoragePermissionsPolicy<C, D> + 'static,
+    CAP: ApplicationStorageCapability,
+> {
+    policy: &'static P,
+    default_quota: Option<StorageQuota>,
+    /// Quotas for specific applications, as (`ShortId`, quota).
+    app_quotas: &'static [(u32, StorageQuota)],
+    cap: CAP,
+    _chip: core::marker::PhantomData<C>,
+    _debug: core::marker::PhantomData<D>,
+}
+
+impl<
+    C: Chip,
+    D: kernel::process::ProcessStandardDebug,
+    P: ProcessStandardStoragePermissionsPolicy<C, D>,
+    CAP: ApplicationStorageCapability,
+> QuotaStoragePermissions<C, D, P, CAP>
+{
+    pub fn new(
+        policy: &'static P,
+        default_quota: Option<StorageQuota>,
+        app_quotas: &'static [(u32, StorageQuota)],
+        cap: CAP,
+    ) -> Self {
+        Self {
+            policy,
+            default_quota,
+            app_quotas,
+            cap,
+            _chip: core::marker::PhantomData,
+            _debug: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<
+    C: Chip,
+    D: kernel::process::ProcessStandardDebug,
+    P: ProcessStandardStoragePermissionsPolicy<C, D>,
+    CAP: ApplicationStorageCapability,
+> ProcessStandardStoragePermissionsPolicy<C, D> for QuotaStoragePermissions<C, D, P, CAP>
+{
+    fn get_permissions(&self, process: &ProcessStandard<C, D>) -> StoragePermissions {
+        let permissions = self.policy.get_permissions(process);
+
+        let app_quota = match process.short_app_id() {
+            ShortId::Fixed(id) => self
+                .app_quotas
+                .iter()
+                .find(|(short_id, _)| *short_id == id.get())
+                .map(|(_, quota)| *quota),
+            ShortId::LocallyUnique => None,
+        };
+
+        match app_quota.or(self.default_quota) {
+            Some(quota) => permissions.with_quota(quota, &self.cap),
+            None => permissions,
+        }
+    }
+}
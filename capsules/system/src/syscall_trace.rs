@@ -0,0 +1,199 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Ring buffer of system call trace records.
+//!
+//! `SyscallTraceBuffer` records the system calls of the processes tracing has
+//! been enabled for, with a timestamp and the value returned to the process.
+//! When the buffer is full the oldest records are overwritten. The records can
+//! be read one at a time, or written in the binary trace format described in
+//! [`kernel::syscall_trace`] for host tools.
+//!
+//! The kernel only reports system calls if it is built with the
+//! `record_syscalls` feature.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let syscall_trace = components::syscall_trace::SyscallTraceComponent::new(
+//!     board_kernel,
+//!     &base_peripherals.rtc,
+//!     create_capability!(capabilities::ProcessManagementCapability),
+//! )
+//! .finalize(components::syscall_trace_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     NUM_PROCS,
+//!     64
+//! ));
+//! process_console.set_syscall_trace(syscall_trace);
+//! ```
+
+use core::cell::Cell;
+
+use crate::write_to_binary::WriteToBinaryOffsetWrapper;
+use kernel::ErrorCode;
+use kernel::ProcessId;
+use kernel::hil::time::{ConvertTicks, Time};
+use kernel::syscall::{Syscall, SyscallReturn};
+use kernel::syscall_trace::{
+    SyscallTrace, SyscallTraceContext, SyscallTraceRecord, SyscallTracer, encode_trace_header,
+};
+use kernel::utilities::binary_write::BinaryWrite;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+/// Keeps the most recent system calls of up to `NUM_PROCS` traced processes.
+pub struct SyscallTraceBuffer<'a, T: Time, const NUM_PROCS: usize> {
+    time: &'a T,
+    records: TakeCell<'static, [Option<SyscallTraceRecord>]>,
+    /// Slot the next record is stored in.
+    next: Cell<usize>,
+    /// Number of valid records, ending just before `next`.
+    count: Cell<usize>,
+    traced: [OptionalCell<ProcessId>; NUM_PROCS],
+    /// A binary trace is being written, so new records are dropped to keep the
+    /// trace consistent.
+    writing: Cell<bool>,
+}
+
+impl<'a, T: Time, const NUM_PROCS: usize> SyscallTraceBuffer<'a, T, NUM_PROCS> {
+    pub fn new(time: &'a T, records: &'static mut [Option<SyscallTraceRecord>]) -> Self {
+        Self {
+            time,
+            records: TakeCell::new(records),
+            next: Cell::new(0),
+            count: Cell::new(0),
+            traced: [const { OptionalCell::empty() }; NUM_PROCS],
+            writing: Cell::new(false),
+        }
+    }
+
+    /// Index into the buffer of the record `index`, where 0 is the oldest.
+    fn slot(&self, index: usize, capacity: usize) -> usize {
+        (self.next.get() + capacity - self.count.get() + index) % capacity
+    }
+}
+
+impl<T: Time, const NUM_PROCS: usize> SyscallTracer for SyscallTraceBuffer<'_, T, NUM_PROCS> {
+    fn syscall_called(&self, processid: ProcessId, syscall: &Syscall) {
+        if !self.is_tracing(processid) || self.writing.get() {
+            return;
+        }
+        let timestamp_us = self.time.ticks_to_us(self.time.now());
+        self.records.map(|records| {
+            if records.is_empty() {
+                return;
+            }
+            records[self.next.get()] = Some(SyscallTraceRecord {
+                timestamp_us,
+                processid,
+                syscall: *syscall,
+                return_value: None,
+            });
+            self.next.set((self.next.get() + 1) % records.len());
+            self.count
+                .set(core::cmp::min(self.count.get() + 1, records.len()));
+        });
+    }
+
+    fn syscall_returned(&self, processid: ProcessId, return_value: &SyscallReturn) {
+        if !self.is_tracing(processid) || self.writing.get() {
+            return;
+        }
+        // The return value belongs to the newest record of the process, unless
+        // that record already has one.
+        self.records.map(|records| {
+            let capacity = records.len();
+            let newest = (0..self.count.get())
+                .rev()
+                .map(|index| self.slot(index, capacity))
+                .find(|&slot| records[slot].is_some_and(|record| record.processid == processid));
+            if let Some(record) = newest
+                .and_then(|slot| records[slot].as_mut())
+                .filter(|record| record.return_value.is_none())
+            {
+                record.return_value = Some(*return_value);
+            }
+        });
+    }
+}
+
+impl<T: Time, const NUM_PROCS: usize> SyscallTrace for SyscallTraceBuffer<'_, T, NUM_PROCS> {
+    fn set_tracing(&self, processid: ProcessId, enabled: bool) -> Result<(), ErrorCode> {
+        let current = self
+            .traced
+            .iter()
+            .find(|traced| traced.contains(&processid));
+        match (current, enabled) {
+            (Some(_), true) => Ok(()),
+            (Some(traced), false) => {
+                traced.clear();
+                Ok(())
+            }
+            (None, true) => {
+                let free = self
+                    .traced
+                    .iter()
+                    .find(|traced| traced.is_none())
+                    .ok_or(ErrorCode::NOMEM)?;
+                free.set(processid);
+                Ok(())
+            }
+            (None, false) => Ok(()),
+        }
+    }
+
+    fn is_tracing(&self, processid: ProcessId) -> bool {
+        self.traced.iter().any(|traced| traced.contains(&processid))
+    }
+
+    fn record_count(&self) -> usize {
+        self.count.get()
+    }
+
+    fn record(&self, index: usize) -> Option<SyscallTraceRecord> {
+        if index >= self.count.get() {
+            return None;
+        }
+        self.records
+            .and_then(|records| records[self.slot(index, records.len())])
+    }
+
+    fn clear(&self) {
+        self.next.set(0);
+        self.count.set(0);
+    }
+
+    // Like `ProcessPrinterText::print_overview()`, this writes the whole trace
+    // on each call and drops the bytes before the offset in the context.
+    fn write_binary(
+        &self,
+        writer: &mut dyn BinaryWrite,
+        context: Option<SyscallTraceContext>,
+    ) -> Option<SyscallTraceContext> {
+        let mut bww = WriteToBinaryOffsetWrapper::new(writer);
+        bww.set_offset(context.map_or(0, |c| c.offset));
+
+        let count = self.record_count();
+        let _ = bww.write_bytes(&encode_trace_header(count));
+        for index in 0..count {
+            if bww.bytes_remaining() {
+                break;
+            }
+            if let Some(record) = self.record(index) {
+                let _ = bww.write_bytes(&record.encode());
+            }
+        }
+
+        if bww.bytes_remaining() {
+            self.writing.set(true);
+            Some(SyscallTraceContext {
+                offset: bww.get_index(),
+            })
+        } else {
+            self.writing.set(false);
+            None
+        }
+    }
+}
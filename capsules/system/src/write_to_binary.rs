@@ -82,15 +82,15 @@ impl<'a> WriteToBinaryOffsetWrapper<'a> {
     pub fn bytes_remaining(&self) -> bool {
         self.bytes_remaining
     }
-}
 
-impl core::fmt::Write for WriteToBinaryOffsetWrapper<'_> {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        let string_len = s.len();
-        if self.index + string_len < self.offset {
+    /// Write binary data, skipping the bytes before the offset. This is the
+    /// binary counterpart of the `core::fmt::Write` implementation.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        let bytes_len = bytes.len();
+        if self.index + bytes_len < self.offset {
             // We are still waiting for `self.offset` bytes to be send before we
             // actually start printing.
-            self.index += string_len;
+            self.index += bytes_len;
             Ok(())
         } else {
             // We need to be printing at least some of this.
@@ -104,13 +104,11 @@ impl core::fmt::Write for WriteToBinaryOffsetWrapper<'_> {
 
             // Calculate the number of bytes we are going to pass to the
             // binary_writer.
-            let to_send = string_len - start;
+            let to_send = bytes_len - start;
 
             // Actually do the write. This will return how many bytes it was
             // able to print.
-            let ret = self
-                .binary_writer
-                .write_buffer(&(s).as_bytes()[start..string_len]);
+            let ret = self.binary_writer.write_buffer(&bytes[start..bytes_len]);
 
             match ret {
                 Ok(bytes_sent) => {
@@ -126,8 +124,15 @@ impl core::fmt::Write for WriteToBinaryOffsetWrapper<'_> {
 
                     Ok(())
                 }
-                Err(()) => Err(core::fmt::Error),
+                Err(()) => Err(()),
             }
         }
     }
 }
+
+impl core::fmt::Write for WriteToBinaryOffsetWrapper<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_bytes(s.as_bytes())
+            .map_err(|()| core::fmt::Error)
+    }
+}
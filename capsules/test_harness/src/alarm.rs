@@ -0,0 +1,180 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Simulated alarm with time controlled by the test.
+//!
+//! Time does not pass on its own: [`MockAlarm::now`](Time::now) keeps
+//! returning the same value until the test moves time forward with
+//! [`MockAlarm::advance`] or [`MockAlarm::set_now`]. Like most alarm
+//! peripherals, the alarm disarms itself before calling the client.
+
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+use kernel::ErrorCode;
+use kernel::hil::time::{Alarm, AlarmClient, Freq1KHz, Frequency, Ticks, Ticks32, Time};
+use kernel::utilities::cells::OptionalCell;
+
+use crate::Simulated;
+
+pub struct MockAlarm<'a, F: Frequency = Freq1KHz> {
+    now: Cell<Ticks32>,
+    reference: Cell<Ticks32>,
+    dt: Cell<Ticks32>,
+    armed: Cell<bool>,
+    client: OptionalCell<&'a dyn AlarmClient>,
+    _frequency: PhantomData<F>,
+}
+
+impl<F: Frequency> MockAlarm<'_, F> {
+    pub fn new() -> Self {
+        Self {
+            now: Cell::new(0.into()),
+            reference: Cell::new(0.into()),
+            dt: Cell::new(0.into()),
+            armed: Cell::new(false),
+            client: OptionalCell::empty(),
+            _frequency: PhantomData,
+        }
+    }
+
+    /// Jump to `now` without firing the alarm, even if it expires in between.
+    pub fn set_now(&self, now: u32) {
+        self.now.set(now.into());
+    }
+
+    /// Ticks left until the alarm expires, or `None` if it is not armed.
+    pub fn remaining(&self) -> Option<u32> {
+        if self.armed.get() {
+            let elapsed = self.now.get().wrapping_sub(self.reference.get());
+            Some(self.dt.get().into_u32().saturating_sub(elapsed.into_u32()))
+        } else {
+            None
+        }
+    }
+
+    /// Let `ticks` ticks pass, firing the alarm every time it expires along the
+    /// way.
+    ///
+    /// Returns how many times the alarm fired.
+    pub fn advance(&self, ticks: u32) -> usize {
+        let end = self.now.get().wrapping_add(ticks.into());
+        let mut left = ticks;
+        let mut fired = 0;
+        while let Some(remaining) = self.remaining() {
+            if remaining > left {
+                break;
+            }
+            left -= remaining;
+            self.fire();
+            fired += 1;
+        }
+        self.now.set(end);
+        fired
+    }
+
+    /// Move time forward to the alarm expiration, if it has not passed yet,
+    /// and fire it.
+    ///
+    /// Returns `false` if the alarm was not armed.
+    pub fn fire(&self) -> bool {
+        match self.remaining() {
+            Some(remaining) => {
+                self.now.set(self.now.get().wrapping_add(remaining.into()));
+                self.armed.set(false);
+                self.client.map(|client| client.alarm());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<F: Frequency> Time for MockAlarm<'_, F> {
+    type Frequency = F;
+    type Ticks = Ticks32;
+
+    fn now(&self) -> Ticks32 {
+        self.now.get()
+    }
+}
+
+impl<'a, F: Frequency> Alarm<'a> for MockAlarm<'a, F> {
+    fn set_alarm_client(&self, client: &'a dyn AlarmClient) {
+        self.client.set(client);
+    }
+
+    fn set_alarm(&self, reference: Ticks32, dt: Ticks32) {
+        self.reference.set(reference);
+        self.dt.set(dt);
+        self.armed.set(true);
+    }
+
+    fn get_alarm(&self) -> Ticks32 {
+        self.reference.get().wrapping_add(self.dt.get())
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.armed.set(false);
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.armed.get()
+    }
+
+    fn minimum_dt(&self) -> Ticks32 {
+        1.into()
+    }
+}
+
+impl<F: Frequency> Simulated for MockAlarm<'_, F> {
+    /// Fire the alarm if it is armed.
+    fn step(&self) -> bool {
+        self.fire()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter(Cell<usize>);
+
+    impl AlarmClient for Counter {
+        fn alarm(&self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn fires_when_time_passes_expiration() {
+        let alarm: MockAlarm = MockAlarm::new();
+        let counter = Counter(Cell::new(0));
+        alarm.set_alarm_client(&counter);
+        alarm.set_now(100);
+        alarm.set_alarm(alarm.now(), 50.into());
+
+        assert_eq!(alarm.advance(49), 0);
+        assert_eq!(alarm.remaining(), Some(1));
+        assert_eq!(alarm.advance(10), 1);
+        assert_eq!(counter.0.get(), 1);
+        assert_eq!(alarm.now().into_u32(), 159);
+        assert!(!alarm.is_armed());
+    }
+
+    #[test]
+    fn fire_handles_wraparound() {
+        let alarm: MockAlarm = MockAlarm::new();
+        let counter = Counter(Cell::new(0));
+        alarm.set_alarm_client(&counter);
+        alarm.set_now(u32::MAX - 5);
+        alarm.set_alarm(alarm.now(), 10.into());
+
+        assert!(alarm.fire());
+        assert_eq!(alarm.now().into_u32(), 4);
+        assert!(!alarm.fire());
+        assert_eq!(counter.0.get(), 1);
+    }
+}
@@ -0,0 +1,289 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Simulated chip for running processes on the host.
+//!
+//! There is no userspace code to switch to. Instead, the context switch of
+//! [`HostBoundary`] returns the system call the test queued for the process,
+//! and records the return values and function calls the kernel passes back.
+//! The boundary tells processes apart by the start of their memory, which the
+//! kernel passes to every call.
+//!
+//! [`HostMpu`] does not protect anything, it only hands out memory for
+//! processes the way an MPU would.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+use kernel::ErrorCode;
+use kernel::platform::chip::Chip;
+use kernel::platform::mpu::{self, MpuConfigDefault, Region};
+use kernel::process::FunctionCall;
+use kernel::syscall::{ContextSwitchReason, Syscall, SyscallReturn, UserspaceKernelBoundary};
+
+use crate::deferred_call::HostThreadIdProvider;
+
+/// Alignment of the memory block given to each process.
+const MEMORY_ALIGNMENT: usize = 8;
+
+/// Memory "protection" which only allocates process memory.
+pub struct HostMpu;
+
+// SAFETY: There is no isolation to uphold on the host: the simulated processes
+// are test code which only accesses memory through the kernel.
+unsafe impl mpu::MPU for HostMpu {
+    type MpuConfig = MpuConfigDefault;
+
+    fn enable_app_mpu(&self) {}
+
+    unsafe fn disable_app_mpu(&self) {}
+
+    fn number_total_regions(&self) -> usize {
+        0
+    }
+
+    fn new_config(&self) -> Option<Self::MpuConfig> {
+        Some(MpuConfigDefault)
+    }
+
+    fn reset_config(&self, _config: &mut Self::MpuConfig) {}
+
+    fn allocate_region(
+        &self,
+        unallocated_memory_start: *const u8,
+        _unallocated_memory_size: usize,
+        min_region_size: usize,
+        _permissions: mpu::Permissions,
+        _config: &mut Self::MpuConfig,
+    ) -> Option<Region> {
+        Some(Region::new(unallocated_memory_start, min_region_size))
+    }
+
+    fn remove_memory_region(
+        &self,
+        _region: Region,
+        _config: &mut Self::MpuConfig,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn allocate_app_memory_region(
+        &self,
+        unallocated_memory_start: *const u8,
+        unallocated_memory_size: usize,
+        min_memory_size: usize,
+        _initial_app_memory_size: usize,
+        _initial_kernel_memory_size: usize,
+        _permissions: mpu::Permissions,
+        _config: &mut Self::MpuConfig,
+    ) -> Option<(*const u8, usize)> {
+        let padding = unallocated_memory_start.align_offset(MEMORY_ALIGNMENT);
+        if padding + min_memory_size > unallocated_memory_size {
+            None
+        } else {
+            Some((
+                unallocated_memory_start.wrapping_add(padding),
+                min_memory_size,
+            ))
+        }
+    }
+
+    fn update_app_memory_region(
+        &self,
+        app_memory_break: *const u8,
+        kernel_memory_break: *const u8,
+        _permissions: mpu::Permissions,
+        _config: &mut Self::MpuConfig,
+    ) -> Result<(), ()> {
+        if app_memory_break > kernel_memory_break {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn configure_mpu(&self, _config: &Self::MpuConfig) {}
+}
+
+/// What the kernel and a simulated process exchanged.
+#[derive(Default)]
+struct Context {
+    syscall: Option<Syscall>,
+    return_value: Option<SyscallReturn>,
+    function_calls: VecDeque<FunctionCall>,
+}
+
+/// Context switch to processes played by the test.
+pub struct HostBoundary {
+    contexts: RefCell<HashMap<usize, Context>>,
+}
+
+impl HostBoundary {
+    fn new() -> Self {
+        Self {
+            contexts: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn with_context<R>(&self, memory_start: usize, f: impl FnOnce(&mut Context) -> R) -> R {
+        f(self.contexts.borrow_mut().entry(memory_start).or_default())
+    }
+
+    /// Make the process whose memory starts at `memory_start` issue `syscall`
+    /// the next time the kernel switches to it.
+    pub(crate) fn push_syscall(&self, memory_start: usize, syscall: Syscall) {
+        self.with_context(memory_start, |context| {
+            assert!(
+                context.syscall.replace(syscall).is_none(),
+                "the process already has a pending system call"
+            );
+        });
+    }
+
+    pub(crate) fn has_syscall(&self, memory_start: usize) -> bool {
+        self.with_context(memory_start, |context| context.syscall.is_some())
+    }
+
+    pub(crate) fn take_return_value(&self, memory_start: usize) -> Option<SyscallReturn> {
+        self.with_context(memory_start, |context| context.return_value.take())
+    }
+
+    pub(crate) fn take_function_call(&self, memory_start: usize) -> Option<FunctionCall> {
+        self.with_context(memory_start, |context| context.function_calls.pop_front())
+    }
+}
+
+impl UserspaceKernelBoundary for HostBoundary {
+    type StoredState = ();
+
+    fn initial_process_app_brk_size(&self) -> usize {
+        0
+    }
+
+    unsafe fn initialize_process(
+        &self,
+        accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut Self::StoredState,
+    ) -> Result<(), ()> {
+        self.contexts
+            .borrow_mut()
+            .insert(accessible_memory_start.addr(), Context::default());
+        Ok(())
+    }
+
+    unsafe fn set_syscall_return_value(
+        &self,
+        accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut Self::StoredState,
+        return_value: SyscallReturn,
+    ) -> Result<(), ()> {
+        self.with_context(accessible_memory_start.addr(), |context| {
+            context.return_value = Some(return_value);
+        });
+        Ok(())
+    }
+
+    unsafe fn set_process_function(
+        &self,
+        accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut Self::StoredState,
+        upcall: FunctionCall,
+    ) -> Result<(), ()> {
+        self.with_context(accessible_memory_start.addr(), |context| {
+            context.function_calls.push_back(upcall);
+        });
+        Ok(())
+    }
+
+    unsafe fn switch_to_process(
+        &self,
+        accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &mut Self::StoredState,
+    ) -> (ContextSwitchReason, Option<*const u8>) {
+        // Without a queued system call the process keeps computing until it
+        // is interrupted.
+        let reason = match self.with_context(accessible_memory_start.addr(), |context| {
+            context.syscall.take()
+        }) {
+            Some(syscall) => ContextSwitchReason::SyscallFired { syscall },
+            None => ContextSwitchReason::Interrupted,
+        };
+        (reason, None)
+    }
+
+    unsafe fn print_context(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &Self::StoredState,
+        writer: &mut dyn Write,
+    ) {
+        let _ = writeln!(writer, " Simulated process, no registers");
+    }
+
+    fn store_context(
+        &self,
+        _state: &Self::StoredState,
+        _out: &mut [u8],
+    ) -> Result<usize, ErrorCode> {
+        Ok(0)
+    }
+
+    fn load_context(&self, _state: &mut Self::StoredState, _input: &[u8]) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
+
+/// Chip with no peripherals, running processes played by the test.
+pub struct HostChip {
+    mpu: HostMpu,
+    boundary: HostBoundary,
+}
+
+impl HostChip {
+    pub fn new() -> Self {
+        Self {
+            mpu: HostMpu,
+            boundary: HostBoundary::new(),
+        }
+    }
+}
+
+impl Chip for HostChip {
+    type MPU = HostMpu;
+    type ThreadIdProvider = HostThreadIdProvider;
+    type UserspaceKernelBoundary = HostBoundary;
+
+    fn init() {}
+
+    fn service_pending_interrupts(&self) {}
+
+    fn has_pending_interrupts(&self) -> bool {
+        false
+    }
+
+    fn mpu(&self) -> &Self::MPU {
+        &self.mpu
+    }
+
+    fn userspace_kernel_boundary(&self) -> &Self::UserspaceKernelBoundary {
+        &self.boundary
+    }
+
+    fn sleep(&self) {}
+
+    fn with_interrupts_disabled<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        f()
+    }
+
+    unsafe fn print_state(_this: Option<&Self>, _writer: &mut dyn Write) {}
+}
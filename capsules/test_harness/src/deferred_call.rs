@@ -0,0 +1,103 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Deferred calls on the host.
+//!
+//! The kernel keeps the deferred call state in globals which are bound to the
+//! single thread that initialized them. `cargo test` runs each test on a
+//! thread of its own, so this module owns one long-lived kernel thread which
+//! binds the state once, and runs test bodies on it one at a time through
+//! [`run`]. The state is reset before each test body, so every test starts
+//! with all 32 deferred calls available.
+
+use std::panic;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use kernel::deferred_call::DeferredCall;
+use kernel::platform::chip::ThreadIdProvider;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    static THREAD_ID: usize = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Provides a distinct ID for every host thread.
+pub enum HostThreadIdProvider {}
+
+// SAFETY: Each thread draws its ID from a global counter the first time it
+// asks for it and keeps it for its lifetime, so no two threads ever share an
+// ID.
+unsafe impl ThreadIdProvider for HostThreadIdProvider {
+    fn running_thread_id() -> usize {
+        THREAD_ID.with(|id| *id)
+    }
+}
+
+static KERNEL_THREAD: OnceLock<mpsc::Sender<Job>> = OnceLock::new();
+
+fn kernel_thread() -> &'static mpsc::Sender<Job> {
+    KERNEL_THREAD.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name(String::from("kernel"))
+            .spawn(move || {
+                kernel::deferred_call::initialize_deferred_call_state::<HostThreadIdProvider>();
+                for job in receiver {
+                    job();
+                }
+            })
+            .expect("failed to spawn the kernel thread");
+        sender
+    })
+}
+
+/// Run `test` on the kernel thread with a fresh deferred call state, and
+/// return its result.
+///
+/// All objects using deferred calls must be created inside `test`. If `test`
+/// panics, the panic is propagated to the calling thread so that the test
+/// fails as usual.
+pub fn run<F, R>(test: F) -> R
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let (result_sender, result_receiver) = mpsc::channel();
+    let job: Job = Box::new(move || {
+        // SAFETY: Deferred calls created by earlier tests belong to objects
+        // which are only reachable from those tests, which have finished.
+        unsafe { kernel::deferred_call::reset_deferred_call_state() };
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(test));
+        let _ = result_sender.send(result);
+    });
+    kernel_thread()
+        .send(job)
+        .expect("the kernel thread has stopped");
+    match result_receiver
+        .recv()
+        .expect("the kernel thread has stopped")
+    {
+        Ok(result) => result,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// Service the next pending deferred call.
+///
+/// Returns `true` if a deferred call was pending. Outside of [`run`] there
+/// are never any deferred calls, and this returns `false`.
+pub fn service_next() -> bool {
+    if DeferredCall::has_tasks() {
+        DeferredCall::service_next_pending();
+        true
+    } else {
+        false
+    }
+}
@@ -0,0 +1,256 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Simulated digest engine.
+//!
+//! The engine records all data added to it, which tests can inspect with
+//! [`MockDigest::data`]. The digest is computed by a function chosen by the
+//! test. The default one is a simple, non-cryptographic mix of the data which
+//! is enough to tell different inputs apart.
+
+use std::cell::{Cell, RefCell};
+
+use kernel::ErrorCode;
+use kernel::hil::digest::{
+    Client, ClientData, ClientDataHash, ClientDataVerify, ClientHash, ClientVerify, Digest,
+    DigestData, DigestDataHash, DigestDataVerify, DigestHash, DigestVerify, Sha256, Sha384, Sha512,
+};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+
+use crate::Simulated;
+
+enum Operation<const DIGEST_LEN: usize> {
+    AddData(SubSlice<'static, u8>),
+    AddMutData(SubSliceMut<'static, u8>),
+    Run(&'static mut [u8; DIGEST_LEN]),
+    Verify(&'static mut [u8; DIGEST_LEN]),
+}
+
+/// Default digest function: mixes each byte into one of the digest bytes, in
+/// turn.
+fn mix<const DIGEST_LEN: usize>(data: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut digest = [0u8; DIGEST_LEN];
+    for (i, byte) in data.iter().enumerate() {
+        let out = &mut digest[i % DIGEST_LEN];
+        *out = out.wrapping_mul(31).wrapping_add(*byte);
+    }
+    digest
+}
+
+pub struct MockDigest<'a, const DIGEST_LEN: usize> {
+    data_client: OptionalCell<&'a dyn ClientData<DIGEST_LEN>>,
+    hash_client: OptionalCell<&'a dyn ClientHash<DIGEST_LEN>>,
+    verify_client: OptionalCell<&'a dyn ClientVerify<DIGEST_LEN>>,
+    hash: fn(&[u8]) -> [u8; DIGEST_LEN],
+    data: RefCell<Vec<u8>>,
+    operation: MapCell<Operation<DIGEST_LEN>>,
+    cancelled: Cell<bool>,
+}
+
+impl<const DIGEST_LEN: usize> MockDigest<'_, DIGEST_LEN> {
+    pub fn new() -> Self {
+        Self::with_hash(mix)
+    }
+
+    /// Create an engine which computes digests with `hash`.
+    pub fn with_hash(hash: fn(&[u8]) -> [u8; DIGEST_LEN]) -> Self {
+        Self {
+            data_client: OptionalCell::empty(),
+            hash_client: OptionalCell::empty(),
+            verify_client: OptionalCell::empty(),
+            hash,
+            data: RefCell::new(Vec::new()),
+            operation: MapCell::empty(),
+            cancelled: Cell::new(false),
+        }
+    }
+
+    /// The data added since the last digest was computed or the data was
+    /// cleared.
+    pub fn data(&self) -> Vec<u8> {
+        self.data.borrow().clone()
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.operation.is_some()
+    }
+
+    /// Compute the digest of the data added so far, and start over.
+    fn finish(&self) -> [u8; DIGEST_LEN] {
+        (self.hash)(&self.data.take())
+    }
+}
+
+impl<'a, const DIGEST_LEN: usize> DigestData<'a, DIGEST_LEN> for MockDigest<'a, DIGEST_LEN> {
+    fn set_data_client(&'a self, client: &'a dyn ClientData<DIGEST_LEN>) {
+        self.data_client.set(client);
+    }
+
+    fn add_data(
+        &self,
+        data: SubSlice<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSlice<'static, u8>)> {
+        if self.operation.is_some() {
+            Err((ErrorCode::BUSY, data))
+        } else if data.len() == 0 {
+            Err((ErrorCode::SIZE, data))
+        } else {
+            self.operation.replace(Operation::AddData(data));
+            Ok(())
+        }
+    }
+
+    fn add_mut_data(
+        &self,
+        data: SubSliceMut<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if self.operation.is_some() {
+            Err((ErrorCode::BUSY, data))
+        } else if data.len() == 0 {
+            Err((ErrorCode::SIZE, data))
+        } else {
+            self.operation.replace(Operation::AddMutData(data));
+            Ok(())
+        }
+    }
+
+    fn clear_data(&self) {
+        self.data.take();
+        if self.operation.is_some() {
+            self.cancelled.set(true);
+        }
+    }
+}
+
+impl<'a, const DIGEST_LEN: usize> DigestHash<'a, DIGEST_LEN> for MockDigest<'a, DIGEST_LEN> {
+    fn set_hash_client(&'a self, client: &'a dyn ClientHash<DIGEST_LEN>) {
+        self.hash_client.set(client);
+    }
+
+    fn run(
+        &'a self,
+        digest: &'static mut [u8; DIGEST_LEN],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; DIGEST_LEN])> {
+        if self.operation.is_some() {
+            Err((ErrorCode::BUSY, digest))
+        } else {
+            self.operation.replace(Operation::Run(digest));
+            Ok(())
+        }
+    }
+}
+
+impl<'a, const DIGEST_LEN: usize> DigestVerify<'a, DIGEST_LEN> for MockDigest<'a, DIGEST_LEN> {
+    fn set_verify_client(&'a self, client: &'a dyn ClientVerify<DIGEST_LEN>) {
+        self.verify_client.set(client);
+    }
+
+    fn verify(
+        &'a self,
+        compare: &'static mut [u8; DIGEST_LEN],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; DIGEST_LEN])> {
+        if self.operation.is_some() {
+            Err((ErrorCode::BUSY, compare))
+        } else {
+            self.operation.replace(Operation::Verify(compare));
+            Ok(())
+        }
+    }
+}
+
+impl<'a, const DIGEST_LEN: usize> Digest<'a, DIGEST_LEN> for MockDigest<'a, DIGEST_LEN> {
+    fn set_client(&'a self, client: &'a dyn Client<DIGEST_LEN>) {
+        self.data_client.set(client);
+        self.hash_client.set(client);
+        self.verify_client.set(client);
+    }
+}
+
+impl<'a, const DIGEST_LEN: usize> DigestDataHash<'a, DIGEST_LEN> for MockDigest<'a, DIGEST_LEN> {
+    fn set_client(&'a self, client: &'a dyn ClientDataHash<DIGEST_LEN>) {
+        self.data_client.set(client);
+        self.hash_client.set(client);
+    }
+}
+
+impl<'a, const DIGEST_LEN: usize> DigestDataVerify<'a, DIGEST_LEN> for MockDigest<'a, DIGEST_LEN> {
+    fn set_client(&'a self, client: &'a dyn ClientDataVerify<DIGEST_LEN>) {
+        self.data_client.set(client);
+        self.verify_client.set(client);
+    }
+}
+
+impl<const DIGEST_LEN: usize> Sha256 for MockDigest<'_, DIGEST_LEN> {
+    fn set_mode_sha256(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
+
+impl<const DIGEST_LEN: usize> Sha384 for MockDigest<'_, DIGEST_LEN> {
+    fn set_mode_sha384(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
+
+impl<const DIGEST_LEN: usize> Sha512 for MockDigest<'_, DIGEST_LEN> {
+    fn set_mode_sha512(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+}
+
+impl<const DIGEST_LEN: usize> Simulated for MockDigest<'_, DIGEST_LEN> {
+    /// Finish adding data, or computing or verifying the digest.
+    fn step(&self) -> bool {
+        let Some(operation) = self.operation.take() else {
+            return false;
+        };
+        let cancelled = self.cancelled.take();
+
+        match operation {
+            Operation::AddData(mut data) => {
+                let result = if cancelled {
+                    Err(ErrorCode::CANCEL)
+                } else {
+                    self.data.borrow_mut().extend_from_slice(data.as_slice());
+                    data.slice(data.len()..);
+                    Ok(())
+                };
+                self.data_client
+                    .map(move |client| client.add_data_done(result, data));
+            }
+            Operation::AddMutData(mut data) => {
+                let result = if cancelled {
+                    Err(ErrorCode::CANCEL)
+                } else {
+                    self.data.borrow_mut().extend_from_slice(data.as_slice());
+                    data.slice(data.len()..);
+                    Ok(())
+                };
+                self.data_client
+                    .map(move |client| client.add_mut_data_done(result, data));
+            }
+            Operation::Run(digest) => {
+                let result = if cancelled {
+                    Err(ErrorCode::CANCEL)
+                } else {
+                    *digest = self.finish();
+                    Ok(())
+                };
+                self.hash_client
+                    .map(move |client| client.hash_done(result, digest));
+            }
+            Operation::Verify(compare) => {
+                let result = if cancelled {
+                    Err(ErrorCode::CANCEL)
+                } else {
+                    Ok(self.finish() == *compare)
+                };
+                self.verify_client
+                    .map(move |client| client.verification_done(result, compare));
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,382 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Simulated flash backed by RAM.
+//!
+//! Like NOR flash, erasing a page sets all of its bytes to `0xff` and writing
+//! can only clear bits, so a capsule which forgets to erase before writing
+//! ends up with corrupted data just as it would on hardware.
+//!
+//! [`MockFlash`] numbers its pages from 0. [`MappedFlash`] is mapped into
+//! memory instead, for capsules which read a storage volume directly and
+//! compute page numbers from its address.
+
+use std::alloc::{self, Layout};
+use std::cell::{Cell, RefCell};
+
+use kernel::ErrorCode;
+use kernel::hil::flash::{Client, Error, Flash, HasClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+use crate::Simulated;
+
+/// A page of [`MockFlash`].
+pub struct Page<const PAGE_SIZE: usize>(pub [u8; PAGE_SIZE]);
+
+impl<const PAGE_SIZE: usize> Default for Page<PAGE_SIZE> {
+    fn default() -> Self {
+        Self([0; PAGE_SIZE])
+    }
+}
+
+impl<const PAGE_SIZE: usize> AsMut<[u8]> for Page<PAGE_SIZE> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+/// Allocate a zeroed, leaked page buffer to pass to the flash.
+pub fn static_page<const PAGE_SIZE: usize>() -> &'static mut Page<PAGE_SIZE> {
+    Box::leak(Box::default())
+}
+
+#[derive(Copy, Clone)]
+enum Operation {
+    Read(usize),
+    Write(usize),
+    Erase(usize),
+}
+
+pub struct MockFlash<const PAGE_SIZE: usize = 512> {
+    client: OptionalCell<&'static dyn Client<Self>>,
+    memory: RefCell<Vec<u8>>,
+    operation: Cell<Option<Operation>>,
+    buffer: TakeCell<'static, Page<PAGE_SIZE>>,
+    fail_next: Cell<bool>,
+}
+
+impl<const PAGE_SIZE: usize> MockFlash<PAGE_SIZE> {
+    /// Create a flash of `num_pages` erased pages.
+    pub fn new(num_pages: usize) -> Self {
+        Self {
+            client: OptionalCell::empty(),
+            memory: RefCell::new(vec![0xff; num_pages * PAGE_SIZE]),
+            operation: Cell::new(None),
+            buffer: TakeCell::empty(),
+            fail_next: Cell::new(false),
+        }
+    }
+
+    /// Copy `len` bytes of flash starting at `offset`.
+    pub fn read(&self, offset: usize, len: usize) -> Vec<u8> {
+        self.memory.borrow()[offset..offset + len].to_vec()
+    }
+
+    /// Overwrite flash starting at `offset` with `data`, ignoring the usual
+    /// write restrictions. This is meant to set up the initial contents.
+    pub fn write(&self, offset: usize, data: &[u8]) {
+        self.memory.borrow_mut()[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    /// Make the next operation report [`Error::FlashError`] when it completes.
+    pub fn fail_next(&self) {
+        self.fail_next.set(true);
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.operation.get().is_some()
+    }
+
+    fn num_pages(&self) -> usize {
+        self.memory.borrow().len() / PAGE_SIZE
+    }
+
+    fn start(&self, operation: Operation, page_number: usize) -> Result<(), ErrorCode> {
+        if self.operation.get().is_some() {
+            Err(ErrorCode::BUSY)
+        } else if page_number >= self.num_pages() {
+            Err(ErrorCode::INVAL)
+        } else {
+            self.operation.set(Some(operation));
+            Ok(())
+        }
+    }
+}
+
+impl<C: Client<Self>, const PAGE_SIZE: usize> HasClient<'static, C> for MockFlash<PAGE_SIZE> {
+    fn set_client(&self, client: &'static C) {
+        self.client.set(client);
+    }
+}
+
+impl<const PAGE_SIZE: usize> Flash for MockFlash<PAGE_SIZE> {
+    type Page = Page<PAGE_SIZE>;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.start(Operation::Read(page_number), page_number) {
+            Ok(()) => {
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.start(Operation::Write(page_number), page_number) {
+            Ok(()) => {
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        self.start(Operation::Erase(page_number), page_number)
+    }
+}
+
+impl<const PAGE_SIZE: usize> Simulated for MockFlash<PAGE_SIZE> {
+    /// Carry out the pending read, write or erase.
+    fn step(&self) -> bool {
+        let Some(operation) = self.operation.take() else {
+            return false;
+        };
+        let result = if self.fail_next.take() {
+            Err(Error::FlashError)
+        } else {
+            Ok(())
+        };
+
+        match operation {
+            Operation::Read(page_number) => {
+                self.buffer.take().map(|buffer| {
+                    if result.is_ok() {
+                        buffer
+                            .0
+                            .copy_from_slice(&self.read(page_number * PAGE_SIZE, PAGE_SIZE));
+                    }
+                    self.client
+                        .map(move |client| client.read_complete(buffer, result));
+                });
+            }
+            Operation::Write(page_number) => {
+                self.buffer.take().map(|buffer| {
+                    if result.is_ok() {
+                        let start = page_number * PAGE_SIZE;
+                        let mut memory = self.memory.borrow_mut();
+                        for (byte, data) in memory[start..start + PAGE_SIZE]
+                            .iter_mut()
+                            .zip(buffer.0.iter())
+                        {
+                            *byte &= *data;
+                        }
+                    }
+                    self.client
+                        .map(move |client| client.write_complete(buffer, result));
+                });
+            }
+            Operation::Erase(page_number) => {
+                if result.is_ok() {
+                    let start = page_number * PAGE_SIZE;
+                    self.memory.borrow_mut()[start..start + PAGE_SIZE].fill(0xff);
+                }
+                self.client.map(|client| client.erase_complete(result));
+            }
+        }
+        true
+    }
+}
+
+/// Simulated memory-mapped flash, like the flash a storage volume allocated
+/// with [`storage_volume!`](kernel::storage_volume) lives in.
+///
+/// Page `n` is the memory at address `n * PAGE_SIZE`, and
+/// [`MappedFlash::volume`] gives access to that memory like the storage
+/// volume does. Only the pages of the volume can be accessed.
+pub struct MappedFlash<const PAGE_SIZE: usize = 512> {
+    client: OptionalCell<&'static dyn Client<Self>>,
+    memory: &'static [Cell<u8>],
+    operation: Cell<Option<Operation>>,
+    buffer: TakeCell<'static, Page<PAGE_SIZE>>,
+    fail_next: Cell<bool>,
+}
+
+impl<const PAGE_SIZE: usize> MappedFlash<PAGE_SIZE> {
+    /// Create a flash of `num_pages` erased pages.
+    pub fn new(num_pages: usize) -> Self {
+        let layout = Layout::from_size_align(num_pages * PAGE_SIZE, PAGE_SIZE)
+            .expect("PAGE_SIZE must be a power of two");
+        assert!(layout.size() > 0, "the flash needs at least one page");
+        // SAFETY: The layout is not empty. The memory is never freed, as the
+        // volume borrows it for `'static`.
+        let memory = unsafe { alloc::alloc(layout) };
+        assert!(!memory.is_null(), "out of memory");
+        // SAFETY: `memory` points to `layout.size()` bytes, which are
+        // initialized here and owned by the flash from now on.
+        let memory = unsafe {
+            memory.write_bytes(0xff, layout.size());
+            core::slice::from_raw_parts_mut(memory, layout.size())
+        };
+        Self {
+            client: OptionalCell::empty(),
+            memory: Cell::from_mut(memory).as_slice_of_cells(),
+            operation: Cell::new(None),
+            buffer: TakeCell::empty(),
+            fail_next: Cell::new(false),
+        }
+    }
+
+    /// The memory of the flash, to pass to the capsule as its storage volume.
+    ///
+    /// Like memory-mapped flash, its contents change when the flash completes
+    /// a write or erase.
+    pub fn volume(&self) -> &'static [u8] {
+        // SAFETY: `Cell<u8>` has the layout of `u8`, and the memory is never
+        // freed. It is only modified by `step()` and `write()`, in between
+        // the accesses of the capsule, just like hardware modifies
+        // memory-mapped flash.
+        unsafe { core::slice::from_raw_parts(self.memory.as_ptr().cast(), self.memory.len()) }
+    }
+
+    /// Overwrite the volume starting at `offset` with `data`, ignoring the
+    /// usual write restrictions. This is meant to set up the initial contents
+    /// or to corrupt them.
+    pub fn write(&self, offset: usize, data: &[u8]) {
+        for (byte, data) in self.memory[offset..offset + data.len()].iter().zip(data) {
+            byte.set(*data);
+        }
+    }
+
+    /// Make the next operation report [`Error::FlashError`] when it completes.
+    pub fn fail_next(&self) {
+        self.fail_next.set(true);
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.operation.get().is_some()
+    }
+
+    /// The volume offset of page `page_number`, if it is part of the volume.
+    fn offset(&self, page_number: usize) -> Option<usize> {
+        let page = page_number.checked_sub(self.memory.as_ptr().addr() / PAGE_SIZE)?;
+        (page < self.memory.len() / PAGE_SIZE).then_some(page * PAGE_SIZE)
+    }
+
+    fn start(&self, operation: Operation, page_number: usize) -> Result<(), ErrorCode> {
+        if self.operation.get().is_some() {
+            Err(ErrorCode::BUSY)
+        } else if self.offset(page_number).is_none() {
+            Err(ErrorCode::INVAL)
+        } else {
+            self.operation.set(Some(operation));
+            Ok(())
+        }
+    }
+}
+
+impl<C: Client<Self>, const PAGE_SIZE: usize> HasClient<'static, C> for MappedFlash<PAGE_SIZE> {
+    fn set_client(&self, client: &'static C) {
+        self.client.set(client);
+    }
+}
+
+impl<const PAGE_SIZE: usize> Flash for MappedFlash<PAGE_SIZE> {
+    type Page = Page<PAGE_SIZE>;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.start(Operation::Read(page_number), page_number) {
+            Ok(()) => {
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.start(Operation::Write(page_number), page_number) {
+            Ok(()) => {
+                self.buffer.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        self.start(Operation::Erase(page_number), page_number)
+    }
+}
+
+impl<const PAGE_SIZE: usize> Simulated for MappedFlash<PAGE_SIZE> {
+    /// Carry out the pending read, write or erase.
+    fn step(&self) -> bool {
+        let Some(operation) = self.operation.take() else {
+            return false;
+        };
+        let result = if self.fail_next.take() {
+            Err(Error::FlashError)
+        } else {
+            Ok(())
+        };
+
+        match operation {
+            Operation::Read(page_number) => {
+                self.buffer.take().map(|buffer| {
+                    if result.is_ok() {
+                        let start = self.offset(page_number).unwrap();
+                        buffer
+                            .0
+                            .copy_from_slice(&self.volume()[start..start + PAGE_SIZE]);
+                    }
+                    self.client
+                        .map(move |client| client.read_complete(buffer, result));
+                });
+            }
+            Operation::Write(page_number) => {
+                self.buffer.take().map(|buffer| {
+                    if result.is_ok() {
+                        let start = self.offset(page_number).unwrap();
+                        for (byte, data) in self.memory[start..start + PAGE_SIZE]
+                            .iter()
+                            .zip(buffer.0.iter())
+                        {
+                            byte.set(byte.get() & *data);
+                        }
+                    }
+                    self.client
+                        .map(move |client| client.write_complete(buffer, result));
+                });
+            }
+            Operation::Erase(page_number) => {
+                if result.is_ok() {
+                    let start = self.offset(page_number).unwrap();
+                    self.memory[start..start + PAGE_SIZE]
+                        .iter()
+                        .for_each(|byte| byte.set(0xff));
+                }
+                self.client.map(|client| client.erase_complete(result));
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,272 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Simulated I2C bus with attached devices.
+//!
+//! Devices implement [`Device`] and are attached to an address with
+//! [`MockI2C::add_device`]. Transfers to addresses without a device fail with
+//! [`Error::AddressNak`]. [`Script`] is a device which checks the transfers
+//! against a list of expected ones, which is usually enough to test a sensor
+//! driver.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+use kernel::hil::i2c::{Error, I2CHwMasterClient, I2CMaster};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+use crate::Simulated;
+
+/// A device attached to a [`MockI2C`] bus.
+pub trait Device {
+    /// The controller wrote `data` to the device.
+    fn write(&self, data: &[u8]) -> Result<(), Error>;
+
+    /// The controller reads `buffer.len()` bytes from the device.
+    fn read(&self, buffer: &mut [u8]) -> Result<(), Error>;
+}
+
+/// A transfer in flight: address, write length and read length.
+#[derive(Copy, Clone)]
+struct Transfer {
+    addr: u8,
+    write_len: usize,
+    read_len: usize,
+}
+
+pub struct MockI2C<'a> {
+    client: OptionalCell<&'a dyn I2CHwMasterClient>,
+    devices: RefCell<Vec<(u8, &'a dyn Device)>>,
+    enabled: Cell<bool>,
+    buffer: TakeCell<'static, [u8]>,
+    transfer: Cell<Option<Transfer>>,
+}
+
+impl<'a> MockI2C<'a> {
+    pub fn new() -> Self {
+        Self {
+            client: OptionalCell::empty(),
+            devices: RefCell::new(Vec::new()),
+            enabled: Cell::new(false),
+            buffer: TakeCell::empty(),
+            transfer: Cell::new(None),
+        }
+    }
+
+    /// Attach `device` to the bus at `addr`.
+    pub fn add_device(&self, addr: u8, device: &'a dyn Device) {
+        self.devices.borrow_mut().push((addr, device));
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.transfer.get().is_some()
+    }
+
+    fn device(&self, addr: u8) -> Option<&'a dyn Device> {
+        self.devices
+            .borrow()
+            .iter()
+            .find(|(device_addr, _)| *device_addr == addr)
+            .map(|(_, device)| *device)
+    }
+
+    fn start(
+        &self,
+        transfer: Transfer,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        if self.transfer.get().is_some() {
+            Err((Error::Busy, buffer))
+        } else if transfer.write_len > buffer.len() || transfer.read_len > buffer.len() {
+            Err((Error::Overrun, buffer))
+        } else {
+            self.transfer.set(Some(transfer));
+            self.buffer.replace(buffer);
+            Ok(())
+        }
+    }
+}
+
+impl<'a> I2CMaster<'a> for MockI2C<'a> {
+    fn set_master_client(&self, master_client: &'a dyn I2CHwMasterClient) {
+        self.client.set(master_client);
+    }
+
+    fn enable(&self) {
+        self.enabled.set(true);
+    }
+
+    fn disable(&self) {
+        self.enabled.set(false);
+    }
+
+    fn write_read(
+        &self,
+        addr: u8,
+        data: &'static mut [u8],
+        write_len: usize,
+        read_len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        self.start(
+            Transfer {
+                addr,
+                write_len,
+                read_len,
+            },
+            data,
+        )
+    }
+
+    fn write(
+        &self,
+        addr: u8,
+        data: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        self.start(
+            Transfer {
+                addr,
+                write_len: len,
+                read_len: 0,
+            },
+            data,
+        )
+    }
+
+    fn read(
+        &self,
+        addr: u8,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        self.start(
+            Transfer {
+                addr,
+                write_len: 0,
+                read_len: len,
+            },
+            buffer,
+        )
+    }
+}
+
+impl Simulated for MockI2C<'_> {
+    /// Run the pending transfer against the addressed device.
+    fn step(&self) -> bool {
+        let Some(transfer) = self.transfer.take() else {
+            return false;
+        };
+        let Some(buffer) = self.buffer.take() else {
+            return false;
+        };
+
+        let status = match self.device(transfer.addr) {
+            Some(device) => {
+                let write = if transfer.write_len > 0 {
+                    device.write(&buffer[..transfer.write_len])
+                } else {
+                    Ok(())
+                };
+                write.and_then(|()| {
+                    if transfer.read_len > 0 {
+                        device.read(&mut buffer[..transfer.read_len])
+                    } else {
+                        Ok(())
+                    }
+                })
+            }
+            None => Err(Error::AddressNak),
+        };
+        self.client
+            .map(move |client| client.command_complete(buffer, status));
+        true
+    }
+}
+
+#[derive(Debug)]
+enum Expected {
+    Write(Vec<u8>),
+    Read(Vec<u8>),
+    Nak,
+}
+
+/// A device which expects a fixed sequence of transfers.
+///
+/// Each expected write must match the data the controller writes, and each
+/// expected read provides the bytes returned to the controller. An unexpected
+/// transfer panics, failing the test.
+pub struct Script {
+    expected: RefCell<VecDeque<Expected>>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self {
+            expected: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Expect the controller to write `data`.
+    pub fn expect_write(&self, data: &[u8]) -> &Self {
+        self.expected
+            .borrow_mut()
+            .push_back(Expected::Write(data.to_vec()));
+        self
+    }
+
+    /// Expect the controller to read `data.len()` bytes, and return `data`.
+    pub fn expect_read(&self, data: &[u8]) -> &Self {
+        self.expected
+            .borrow_mut()
+            .push_back(Expected::Read(data.to_vec()));
+        self
+    }
+
+    /// Do not acknowledge the next write or read.
+    pub fn nak(&self) -> &Self {
+        self.expected.borrow_mut().push_back(Expected::Nak);
+        self
+    }
+
+    /// Whether all expected transfers have happened.
+    pub fn is_done(&self) -> bool {
+        self.expected.borrow().is_empty()
+    }
+}
+
+impl Device for Script {
+    fn write(&self, data: &[u8]) -> Result<(), Error> {
+        match self.expected.borrow_mut().pop_front() {
+            Some(Expected::Write(expected)) => {
+                assert_eq!(data, expected.as_slice(), "unexpected I2C write data");
+                Ok(())
+            }
+            Some(Expected::Nak) => Err(Error::DataNak),
+            other => panic!(
+                "unexpected I2C write of {:02x?}, expected {:?}",
+                data, other
+            ),
+        }
+    }
+
+    fn read(&self, buffer: &mut [u8]) -> Result<(), Error> {
+        match self.expected.borrow_mut().pop_front() {
+            Some(Expected::Read(data)) => {
+                assert_eq!(buffer.len(), data.len(), "unexpected I2C read length");
+                buffer.copy_from_slice(&data);
+                Ok(())
+            }
+            Some(Expected::Nak) => Err(Error::DataNak),
+            other => panic!(
+                "unexpected I2C read of {} bytes, expected {:?}",
+                buffer.len(),
+                other
+            ),
+        }
+    }
+}
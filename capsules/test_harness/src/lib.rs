@@ -0,0 +1,163 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Host-side test harness for capsules.
+//!
+//! This crate provides simulated implementations of common HILs so that
+//! capsules, virtualizers and syscall drivers can be unit tested with `cargo
+//! test` on the development machine.
+//!
+//! The simulated peripherals never complete an operation on their own. Like
+//! real hardware, an accepted operation stays pending until the test decides
+//! that the "interrupt" fires, by calling [`Simulated::step`] on the peripheral
+//! or by running [`run_until_idle`], which services deferred calls and steps
+//! the given peripherals until nothing is left to do. This makes it possible
+//! to check the state of a capsule while an operation is in flight, and to
+//! interleave operations from several clients in a controlled order.
+//!
+//! Capsules hold `'static` buffers, so tests typically allocate them with
+//! [`static_buf`] and leak them. Flash pages are allocated the same way with
+//! [`flash::static_page`].
+//!
+//! Capsules which use deferred calls must be created and exercised inside
+//! [`deferred_call::run`], which runs the test on the thread owning the kernel
+//! deferred call state.
+//!
+//! Syscall drivers are tested through the [`process`] module, in which the
+//! test plays the role of one or more processes running on a simulated kernel:
+//! it issues system calls and checks the upcalls the kernel delivers.
+//!
+//! ```rust,ignore
+//! use capsules_test_harness::i2c::{MockI2C, Script};
+//!
+//! deferred_call::run(|| {
+//!     let i2c = leak(MockI2C::new());
+//!     let sensor = leak(Script::new());
+//!     sensor.expect_write(&[0x01]).expect_read(&[0x12, 0x34]);
+//!     i2c.add_device(0x40, sensor);
+//!
+//!     // Create the capsule under test on top of `i2c` and start a read.
+//!
+//!     run_until_idle(&[i2c]);
+//!     assert!(sensor.is_done());
+//! });
+//! ```
+
+pub mod alarm;
+pub mod chip;
+pub mod deferred_call;
+pub mod digest;
+pub mod flash;
+pub mod i2c;
+pub mod loader;
+pub mod process;
+pub mod spi;
+pub mod uart;
+
+use kernel::capabilities::{
+    CreatePortTableCapability, NetworkCapabilityCreationCapability, ProcessManagementCapability,
+    UdpDriverCapability,
+};
+use kernel::create_capability;
+use kernel::utilities::StaticRef;
+
+/// Upper bound on the number of steps [`run_until_idle`] takes before it gives
+/// up, so that a capsule which never settles fails the test instead of hanging
+/// it.
+const MAX_STEPS: usize = 10_000;
+
+/// A simulated peripheral with operations that complete under the control of
+/// the test.
+pub trait Simulated {
+    /// Complete the oldest pending operation, issuing the callback to the
+    /// client.
+    ///
+    /// Returns `true` if an operation was completed, `false` if nothing was
+    /// pending.
+    fn step(&self) -> bool;
+}
+
+/// Run deferred calls and complete pending operations on `peripherals` until
+/// none are left.
+///
+/// Deferred calls are serviced first, as the kernel loop would. Returns the
+/// number of deferred calls and operations completed.
+///
+/// # Panics
+///
+/// Panics if the system has not settled after a large number of steps.
+pub fn run_until_idle(peripherals: &[&dyn Simulated]) -> usize {
+    for steps in 0..MAX_STEPS {
+        if deferred_call::service_next() {
+            continue;
+        }
+        if !peripherals.iter().any(|peripheral| peripheral.step()) {
+            return steps;
+        }
+    }
+    panic!("system did not settle after {} steps", MAX_STEPS);
+}
+
+/// Move `value` to the heap and leak it, providing the `'static` lifetime
+/// capsules expect.
+pub fn leak<T>(value: T) -> &'static T {
+    Box::leak(Box::new(value))
+}
+
+/// Create a capability allowing network capabilities to be created.
+///
+/// Capsules forbid unsafe code and therefore cannot create capabilities
+/// themselves, but some of them take a `NetworkCapability` as an argument.
+pub fn network_capability_creation() -> &'static dyn NetworkCapabilityCreationCapability {
+    leak(create_capability!(NetworkCapabilityCreationCapability))
+}
+
+/// Create a capability allowing UDP port tables to be created, for capsules
+/// whose tests set up a UDP stack.
+pub fn port_table_creation() -> &'static dyn CreatePortTableCapability {
+    leak(create_capability!(CreatePortTableCapability))
+}
+
+/// Create a capability allowing datagrams to be sent from any UDP port, like
+/// the userspace UDP driver does.
+pub fn udp_driver_capability() -> &'static dyn UdpDriverCapability {
+    leak(create_capability!(UdpDriverCapability))
+}
+
+/// A capability to manage processes, for capsules that take one as an
+/// argument and tests that need to name its type.
+pub struct ProcessManagementCap(());
+
+// SAFETY: Capabilities may be created by trusted code, which the test harness
+// is. Capsules cannot use this type outside of their tests.
+unsafe impl ProcessManagementCapability for ProcessManagementCap {}
+
+/// Create a capability to manage processes.
+pub fn process_management_capability() -> ProcessManagementCap {
+    ProcessManagementCap(())
+}
+
+/// Allocate a zeroed, leaked buffer of `len` bytes.
+pub fn static_buf(len: usize) -> &'static mut [u8] {
+    Box::leak(vec![0; len].into_boxed_slice())
+}
+
+/// Allocate zeroed, leaked memory for a block of memory-mapped registers,
+/// aligned to 4 KiB like a peripheral.
+///
+/// Chip drivers can then be tested with the test playing the role of the
+/// hardware, setting and checking the registers. `T` must be valid when
+/// zeroed, which register types are.
+pub fn static_registers<T>() -> StaticRef<T> {
+    let layout = std::alloc::Layout::new::<T>()
+        .align_to(4096)
+        .expect("invalid register block layout");
+    // SAFETY: Register blocks have a non-zero size. Registers are valid when
+    // zeroed, and the memory is never freed.
+    unsafe {
+        let registers = std::alloc::alloc_zeroed(layout).cast::<T>();
+        assert!(!registers.is_null(), "failed to allocate the registers");
+        StaticRef::new(registers)
+    }
+}
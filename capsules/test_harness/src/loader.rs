@@ -0,0 +1,332 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Dynamic process loading on a [`HostKernel`].
+//!
+//! [`AppFlash`] is the flash process binaries are stored in. The kernel reads
+//! the binaries directly, as it reads memory-mapped flash, and writes them
+//! through the [`NonvolatileStorage`] interface, with each write taking effect
+//! when the test calls [`Simulated::step`].
+//!
+//! [`HostKernel::dynamic_binary_storage`] loads the binaries already stored in
+//! an [`AppFlash`] with the asynchronous process loader, and returns the
+//! [`DynamicBinaryStorage`] that stores and loads new binaries at runtime.
+//! Processes it loads get the fixed ShortId of their TBF header, and are not
+//! required to have credentials. They may store data under their ShortId, and
+//! only read their own data:
+//!
+//! ```rust,ignore
+//! deferred_call::run(|| {
+//!     let kernel = HostKernel::new();
+//!     let flash = AppFlash::new(4096);
+//!     flash.install(0, &Tbf::new("old").short_id(0x10).size(256).build());
+//!
+//!     let storage = kernel.dynamic_binary_storage(flash);
+//!     run_until_idle(&[flash]);
+//!     let old = ShortId::Fixed(NonZeroU32::new(0x10).unwrap());
+//!     assert_eq!(kernel.process_flash_start(old), Some(flash.address(0)));
+//! });
+//! ```
+
+use std::alloc::Layout;
+use std::cell::Cell;
+
+use kernel::ErrorCode;
+use kernel::capabilities::{ApplicationStorageCapability, ProcessManagementCapability};
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::dynamic_binary_storage::{BUF_LEN, SequentialDynamicBinaryStorage};
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::process::{
+    Process, ProcessBinary, ProcessLoadingAsync, ProcessStandard, ProcessStandardDebugFull,
+    ProcessStandardStoragePermissionsPolicy, SequentialProcessLoaderMachine, ShortId,
+};
+use kernel::process_checker::{
+    AppCredentialsPolicy, AppCredentialsPolicyClient, AppUniqueness, Compress,
+    ProcessCheckerMachine,
+};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use tock_tbf::types::TbfFooterV2Credentials;
+
+use crate::chip::HostChip;
+use crate::process::{APP_MEMORY_SIZE, HostKernel, PanicFaultPolicy};
+use crate::{Simulated, leak, static_buf};
+
+/// Number of processes the loader has memory for, counting processes which
+/// are loaded again after an update or after their binary was moved.
+const NUM_LOADED_PROCS: usize = 8;
+
+/// The process loader of a [`HostKernel`].
+pub type Loader = SequentialProcessLoaderMachine<'static, HostChip, ProcessStandardDebugFull>;
+
+/// Storage for process binaries loaded at runtime.
+pub type DynamicBinaryStorage =
+    SequentialDynamicBinaryStorage<'static, 'static, HostChip, ProcessStandardDebugFull, AppFlash>;
+
+#[derive(Copy, Clone)]
+enum Operation {
+    Read(usize, usize),
+    Write(usize, usize),
+}
+
+/// Flash holding process binaries, addressed by the absolute addresses the
+/// kernel uses for them.
+///
+/// Unlike [`MockFlash`](crate::flash::MockFlash), writes simply overwrite
+/// the previous contents: the dynamic binary storage relies on the
+/// nonvolatile storage layer to erase as needed.
+pub struct AppFlash {
+    memory: *mut u8,
+    len: usize,
+    client: OptionalCell<&'static dyn NonvolatileStorageClient>,
+    operation: Cell<Option<Operation>>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl AppFlash {
+    /// Create an erased flash of `len` bytes, aligned to `len` so that the
+    /// loader places binaries the same way in every test. `len` must be a
+    /// power of two.
+    pub fn new(len: usize) -> &'static Self {
+        let layout = Layout::from_size_align(len, len).expect("invalid flash size");
+        // SAFETY: `layout` has a non-zero size.
+        let memory = unsafe { std::alloc::alloc(layout) };
+        assert!(!memory.is_null(), "failed to allocate the flash");
+        // SAFETY: `memory` was just allocated with room for `len` bytes.
+        unsafe { memory.write_bytes(0xff, len) };
+        leak(Self {
+            memory,
+            len,
+            client: OptionalCell::empty(),
+            operation: Cell::new(None),
+            buffer: TakeCell::empty(),
+        })
+    }
+
+    /// The flash as the kernel reads it.
+    pub fn flash(&self) -> &'static [u8] {
+        // SAFETY: The memory is never freed. Like memory-mapped flash, it is
+        // changed while the kernel holds this slice, but only by `step()` and
+        // `install()`, when the kernel is not reading it.
+        unsafe { core::slice::from_raw_parts(self.memory, self.len) }
+    }
+
+    /// Absolute address of `offset` into the flash.
+    pub fn address(&self, offset: usize) -> usize {
+        self.memory.addr() + offset
+    }
+
+    /// Copy `len` bytes of flash starting at `offset`.
+    pub fn read(&self, offset: usize, len: usize) -> Vec<u8> {
+        self.flash()[offset..offset + len].to_vec()
+    }
+
+    /// Store `binary` at `offset`, as if it had been flashed before boot.
+    pub fn install(&self, offset: usize, binary: &[u8]) {
+        assert!(offset + binary.len() <= self.len, "the binary does not fit");
+        // SAFETY: The destination is in bounds, see `flash()`.
+        unsafe {
+            core::ptr::copy_nonoverlapping(binary.as_ptr(), self.memory.add(offset), binary.len())
+        };
+    }
+
+    fn start(
+        &self,
+        operation: Operation,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        let start = self.memory.addr();
+        if self.operation.get().is_some() {
+            Err(ErrorCode::BUSY)
+        } else if length > buffer.len() || address < start || address + length > start + self.len {
+            Err(ErrorCode::INVAL)
+        } else {
+            self.operation.set(Some(operation));
+            self.buffer.replace(buffer);
+            Ok(())
+        }
+    }
+}
+
+impl NonvolatileStorage<'static> for AppFlash {
+    fn set_client(&self, client: &'static dyn NonvolatileStorageClient) {
+        self.client.set(client);
+    }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        self.start(Operation::Read(address, length), buffer, address, length)
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        self.start(Operation::Write(address, length), buffer, address, length)
+    }
+}
+
+impl Simulated for AppFlash {
+    /// Carry out the pending read or write.
+    fn step(&self) -> bool {
+        let Some(operation) = self.operation.take() else {
+            return false;
+        };
+        self.buffer.take().map(|buffer| match operation {
+            Operation::Read(address, length) => {
+                let offset = address - self.memory.addr();
+                buffer[..length].copy_from_slice(&self.read(offset, length));
+                self.client
+                    .map(move |client| client.read_done(buffer, length));
+            }
+            Operation::Write(address, length) => {
+                self.install(address - self.memory.addr(), &buffer[..length]);
+                self.client
+                    .map(move |client| client.write_done(buffer, length));
+            }
+        });
+        true
+    }
+}
+
+/// Run every process, whether or not it has credentials.
+struct NoCredentials;
+
+impl<'a> AppCredentialsPolicy<'a> for NoCredentials {
+    fn set_client(&self, _client: &'a dyn AppCredentialsPolicyClient<'a>) {}
+
+    fn require_credentials(&self) -> bool {
+        false
+    }
+
+    fn check_credentials(
+        &self,
+        credentials: TbfFooterV2Credentials,
+        integrity_region: &'a [u8],
+    ) -> Result<(), (ErrorCode, TbfFooterV2Credentials, &'a [u8])> {
+        Err((ErrorCode::NOSUPPORT, credentials, integrity_region))
+    }
+}
+
+/// Assign the ShortId in the TBF header of a process, relying on the kernel
+/// to keep two processes with the same ShortId from running.
+struct TbfShortIds;
+
+impl AppUniqueness for TbfShortIds {
+    fn different_identifier(&self, _process_a: &ProcessBinary, _process_b: &ProcessBinary) -> bool {
+        true
+    }
+
+    fn different_identifier_process(
+        &self,
+        _process_a: &ProcessBinary,
+        _process_b: &dyn Process,
+    ) -> bool {
+        true
+    }
+
+    fn different_identifier_processes(
+        &self,
+        _process_a: &dyn Process,
+        _process_b: &dyn Process,
+    ) -> bool {
+        true
+    }
+}
+
+impl Compress for TbfShortIds {
+    fn to_short_id(&self, process: &ProcessBinary) -> ShortId {
+        process.header.get_fixed_short_id().into()
+    }
+}
+
+/// Let processes with a fixed ShortId store data under it.
+struct ShortIdStorage;
+
+impl ProcessStandardStoragePermissionsPolicy<HostChip, ProcessStandardDebugFull>
+    for ShortIdStorage
+{
+    fn get_permissions(
+        &self,
+        process: &ProcessStandard<HostChip, ProcessStandardDebugFull>,
+    ) -> StoragePermissions {
+        match process.short_app_id() {
+            ShortId::Fixed(id) => {
+                let capability = create_capability!(ApplicationStorageCapability);
+                StoragePermissions::new_self_only(id, &capability)
+            }
+            ShortId::LocallyUnique => StoragePermissions::new_null(),
+        }
+    }
+}
+
+impl HostKernel {
+    /// Start loading the processes stored in `flash`, and return the storage
+    /// for binaries loaded at runtime.
+    ///
+    /// The processes are loaded by deferred calls, so they exist once the
+    /// test runs them, for example with [`run_until_idle`](crate::run_until_idle).
+    /// All grants must be created before calling this.
+    pub fn dynamic_binary_storage(
+        &'static self,
+        flash: &'static AppFlash,
+    ) -> &'static DynamicBinaryStorage {
+        let checker = leak(ProcessCheckerMachine::new(leak(NoCredentials)));
+        let proc_binaries = Box::leak(Box::new([const { None }; NUM_LOADED_PROCS]));
+        let memory: *mut [u8] =
+            Box::into_raw(vec![0u8; NUM_LOADED_PROCS * APP_MEMORY_SIZE].into_boxed_slice());
+        self.set_loader_memory(memory.cast());
+        // SAFETY: `memory` was just leaked, so this is the only reference to
+        // it. As for processes loaded with `load_process()`, the kernel and
+        // `App` only access process memory through raw pointers.
+        let app_memory = unsafe { &mut *memory };
+        let capability = create_capability!(ProcessManagementCapability);
+        let loader: &'static Loader = leak(SequentialProcessLoaderMachine::new(
+            checker,
+            proc_binaries,
+            self.kernel(),
+            self.chip(),
+            flash.flash(),
+            app_memory,
+            &PanicFaultPolicy,
+            &ShortIdStorage,
+            &TbfShortIds,
+            &capability,
+        ));
+        checker.set_client(loader);
+        loader.register();
+
+        let storage = leak(SequentialDynamicBinaryStorage::new(
+            self.kernel(),
+            flash,
+            loader,
+            static_buf(BUF_LEN),
+        ));
+        flash.set_client(storage);
+        loader.set_runtime_client(storage);
+        storage.register();
+
+        loader.start();
+        storage
+    }
+
+    /// Absolute address of the binary of the process with ShortId `short_id`,
+    /// if there is one.
+    pub fn process_flash_start(&self, short_id: ShortId) -> Option<usize> {
+        let capability = create_capability!(ProcessManagementCapability);
+        self.kernel()
+            .process_iter_capability(&capability)
+            .find(|process| process.short_app_id() == short_id)
+            .map(|process| process.get_addresses().flash_start)
+    }
+}
@@ -0,0 +1,675 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Processes played by the test.
+//!
+//! [`HostKernel`] is a kernel running on a [`HostChip`], with syscall drivers
+//! registered by the test. Each [`App`] loaded into it is a real process,
+//! loaded from a generated TBF binary, with grants and memory managed by the
+//! kernel. The test issues the system calls of the process through the
+//! methods of [`App`], which run the kernel until it returns to the process.
+//!
+//! Like a real process, an [`App`] receives upcalls only while it waits in
+//! `yield`. [`App::yield_wait`] returns the upcall the kernel delivered, if
+//! any:
+//!
+//! ```rust,ignore
+//! deferred_call::run(|| {
+//!     let kernel = HostKernel::new();
+//!     let alarm = leak(MockAlarm::<Freq1KHz>::new());
+//!     let driver = leak(AlarmDriver::new(alarm, kernel.create_grant(DRIVER_NUM)));
+//!     alarm.set_alarm_client(driver);
+//!     kernel.add_driver(DRIVER_NUM, driver);
+//!
+//!     let app = kernel.load_process("timer");
+//!     app.subscribe(DRIVER_NUM, 0);
+//!     app.command(DRIVER_NUM, 5, 10 << 22, 0);
+//!     assert_eq!(app.yield_wait(), None);
+//!
+//!     alarm.advance(10);
+//!     let upcall = app.yield_wait().unwrap();
+//!     assert_eq!(upcall.driver_number, DRIVER_NUM);
+//! });
+//! ```
+
+use std::cell::{Cell, RefCell};
+
+use kernel::Kernel;
+use kernel::ProcessId;
+use kernel::capabilities::{
+    MainLoopCapability, MemoryAllocationCapability, ProcessManagementCapability,
+};
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCall;
+use kernel::grant::{AllowRoSize, AllowRwSize, Grant, UpcallSize};
+use kernel::ipc::IPC;
+use kernel::platform::chip::Chip;
+use kernel::platform::{KernelResources, SyscallDriverLookup};
+use kernel::process::{
+    FaultAction, FunctionCall, FunctionCallSource, Process, ProcessArray, ProcessFaultPolicy,
+    ProcessSlot, ShortId, State,
+};
+use kernel::scheduler::{Scheduler, SchedulingDecision};
+use kernel::syscall::{Syscall, SyscallDriver, SyscallReturn, YieldVariant};
+use kernel::utilities::capability_ptr::CapabilityPtr;
+use kernel::utilities::machine_register::MachineRegister;
+
+use crate::chip::HostChip;
+use crate::leak;
+
+/// Maximum number of processes in a [`HostKernel`].
+const NUM_PROCS: usize = 4;
+
+/// RAM requested by each process in its TBF header.
+const APP_RAM_SIZE: u32 = 16 * 1024;
+
+/// Memory set aside for each process, including the kernel-owned part.
+pub(crate) const APP_MEMORY_SIZE: usize = 2 * APP_RAM_SIZE as usize;
+
+/// Size of the "code" following the TBF header. Upcall function pointers point
+/// into it.
+const APP_CODE_SIZE: usize = 16;
+
+/// A TBF binary with a Main, Package Name and Kernel Version header, and
+/// optionally a ShortId header.
+pub struct Tbf<'a> {
+    name: &'a str,
+    short_id: Option<u32>,
+    size: usize,
+}
+
+impl<'a> Tbf<'a> {
+    /// A binary for a process called `name`.
+    pub fn new(name: &'a str) -> Self {
+        Self {
+            name,
+            short_id: None,
+            size: 0,
+        }
+    }
+
+    /// Give the process the fixed ShortId `short_id`.
+    pub fn short_id(self, short_id: u32) -> Self {
+        Self {
+            short_id: Some(short_id),
+            ..self
+        }
+    }
+
+    /// Pad the binary to `size` bytes, for example to match the alignment
+    /// rules of the process loader.
+    pub fn size(self, size: usize) -> Self {
+        Self { size, ..self }
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        fn tlv(header: &mut Vec<u8>, tipe: u16, value: &[u8]) {
+            header.extend_from_slice(&tipe.to_le_bytes());
+            header.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            header.extend_from_slice(value);
+            header.resize(header.len().next_multiple_of(4), 0);
+        }
+
+        // Base header, the sizes and checksum are filled in below.
+        let mut header = Vec::new();
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&[0; 6]);
+        // Flags: enabled.
+        header.extend_from_slice(&1u32.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+
+        // Main: init function offset, protected trailer size and minimum RAM.
+        let main = [0, 0, APP_RAM_SIZE].map(u32::to_le_bytes).concat();
+        tlv(&mut header, 1, &main);
+        tlv(&mut header, 3, self.name.as_bytes());
+        let version = [kernel::KERNEL_MAJOR_VERSION, kernel::KERNEL_MINOR_VERSION]
+            .map(u16::to_le_bytes)
+            .concat();
+        tlv(&mut header, 8, &version);
+        if let Some(short_id) = self.short_id {
+            tlv(&mut header, 10, &short_id.to_le_bytes());
+        }
+
+        let header_size = header.len();
+        let total_size = self.size.max(header_size + APP_CODE_SIZE);
+        header[2..4].copy_from_slice(&(header_size as u16).to_le_bytes());
+        header[4..8].copy_from_slice(&(total_size as u32).to_le_bytes());
+        let checksum = header
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .map(|word| u32::from_le_bytes(*word))
+            .fold(0, |checksum, word| checksum ^ word);
+        header[12..16].copy_from_slice(&checksum.to_le_bytes());
+
+        header.resize(total_size, 0);
+        header
+    }
+}
+
+/// Fail the test when a process faults.
+pub(crate) struct PanicFaultPolicy;
+
+impl ProcessFaultPolicy for PanicFaultPolicy {
+    fn action(&self, _process: &dyn Process) -> FaultAction {
+        FaultAction::Panic
+    }
+}
+
+/// Runs whichever process has something to do.
+///
+/// A process in the `Running` state only has something to do when the test
+/// queued a system call for it. Otherwise the process is "computing", and
+/// returns to the kernel only once the test issues its next system call.
+pub struct HostScheduler {
+    kernel: &'static Kernel,
+    chip: &'static HostChip,
+}
+
+impl HostScheduler {
+    fn can_progress(&self, process: &dyn Process) -> bool {
+        match process.get_state() {
+            State::Running => self
+                .chip
+                .userspace_kernel_boundary()
+                .has_syscall(process.get_addresses().sram_start),
+            _ => process.ready(),
+        }
+    }
+
+    fn has_work(&self) -> bool {
+        let capability = create_capability!(ProcessManagementCapability);
+        self.kernel
+            .process_iter_capability(&capability)
+            .any(|process| self.can_progress(process))
+    }
+}
+
+impl Scheduler<HostChip> for HostScheduler {
+    fn next(&self) -> SchedulingDecision {
+        let capability = create_capability!(ProcessManagementCapability);
+        self.kernel
+            .process_iter_capability(&capability)
+            .find(|process| self.can_progress(*process))
+            .map_or(SchedulingDecision::TrySleep, |process| {
+                SchedulingDecision::RunProcess((process.processid(), None))
+            })
+    }
+
+    fn result(&self, _: kernel::process::StoppedExecutingReason, _: Option<u32>) {}
+
+    fn continue_process(&self, id: ProcessId, _chip: &HostChip) -> bool {
+        let capability = create_capability!(ProcessManagementCapability);
+        !DeferredCall::has_tasks()
+            && self.kernel.process_map_or_external(
+                false,
+                id,
+                |process| self.can_progress(process),
+                &capability,
+            )
+    }
+}
+
+/// A kernel with the syscall drivers of a test.
+pub struct HostKernel {
+    kernel: &'static Kernel,
+    processes: &'static [ProcessSlot],
+    chip: &'static HostChip,
+    scheduler: HostScheduler,
+    drivers: RefCell<Vec<(usize, &'static dyn SyscallDriver)>>,
+    /// Memory of the processes loaded by the process loader, if any.
+    loader_memory: Cell<*mut u8>,
+}
+
+impl HostKernel {
+    pub fn new() -> &'static Self {
+        let processes = leak(ProcessArray::<NUM_PROCS>::new());
+        let kernel = leak(Kernel::new(processes.as_slice()));
+        let chip = leak(HostChip::new());
+        leak(Self {
+            kernel,
+            processes: processes.as_slice(),
+            chip,
+            scheduler: HostScheduler { kernel, chip },
+            drivers: RefCell::new(Vec::new()),
+            loader_memory: Cell::new(core::ptr::null_mut()),
+        })
+    }
+
+    pub fn kernel(&self) -> &'static Kernel {
+        self.kernel
+    }
+
+    pub(crate) fn chip(&self) -> &'static HostChip {
+        self.chip
+    }
+
+    pub(crate) fn set_loader_memory(&self, memory: *mut u8) {
+        self.loader_memory.set(memory);
+    }
+
+    /// The process slots of the kernel, for example to set up a scheduler
+    /// under test.
+    pub fn processes(&self) -> &'static [ProcessSlot] {
+        self.processes
+    }
+
+    /// Create a grant for the driver `driver_num`.
+    ///
+    /// All grants must be created before the first process is loaded.
+    pub fn create_grant<
+        T: Default,
+        Upcalls: UpcallSize,
+        AllowROs: AllowRoSize,
+        AllowRWs: AllowRwSize,
+    >(
+        &self,
+        driver_num: usize,
+    ) -> Grant<T, Upcalls, AllowROs, AllowRWs> {
+        let capability = create_capability!(MemoryAllocationCapability);
+        self.kernel.create_grant(driver_num, &capability)
+    }
+
+    /// Make `driver` handle the system calls to `driver_num`.
+    pub fn add_driver(&self, driver_num: usize, driver: &'static dyn SyscallDriver) {
+        self.drivers.borrow_mut().push((driver_num, driver));
+    }
+
+    /// Load a new process called `name`, and start it.
+    pub fn load_process(&'static self, name: &str) -> App {
+        let flash: &'static [u8] = Box::leak(Tbf::new(name).build().into_boxed_slice());
+        let memory: *mut [u8] = Box::into_raw(vec![0u8; APP_MEMORY_SIZE].into_boxed_slice());
+
+        let capability = create_capability!(ProcessManagementCapability);
+        // SAFETY: `memory` was just leaked, so this is the only reference to
+        // it. The kernel does not keep the reference, it only accesses process
+        // memory through raw pointers, as `App` does.
+        let app_memory = unsafe { &mut *memory };
+        kernel::process::load_processes(
+            self.kernel,
+            self.chip,
+            flash,
+            app_memory,
+            &PanicFaultPolicy,
+            &capability,
+        )
+        .expect("failed to load the process");
+        let process = self
+            .kernel
+            .process_iter_capability(&capability)
+            .find(|process| process.get_addresses().flash_start == flash.as_ptr().addr())
+            .expect("failed to create the process");
+        self.start_app(process, memory.cast())
+    }
+
+    /// Play the process with ShortId `short_id`, which the process loader
+    /// loaded from an [`AppFlash`](crate::loader::AppFlash), and start it.
+    pub fn attach(&'static self, short_id: ShortId) -> App {
+        let capability = create_capability!(ProcessManagementCapability);
+        let process = self
+            .kernel
+            .process_iter_capability(&capability)
+            .find(|process| process.short_app_id() == short_id)
+            .expect("no process with this ShortId");
+        let memory = self.loader_memory.get();
+        assert!(
+            !memory.is_null(),
+            "the process was not loaded by the process loader"
+        );
+        self.start_app(process, memory)
+    }
+
+    /// Play `process`, whose memory is part of the allocation `memory`
+    /// points into.
+    fn start_app(&'static self, process: &dyn Process, memory: *mut u8) -> App {
+        let app = App {
+            kernel: self,
+            id: process.processid(),
+            memory,
+            memory_start: process.get_addresses().sram_start,
+            upcall_fn: process.get_addresses().flash_non_protected_start,
+        };
+
+        // Let the process start, so that it can issue system calls.
+        self.run();
+        let init = self
+            .chip
+            .userspace_kernel_boundary()
+            .take_function_call(app.memory_start);
+        assert!(
+            matches!(
+                init,
+                Some(FunctionCall {
+                    source: FunctionCallSource::Kernel,
+                    ..
+                })
+            ),
+            "the process was not started"
+        );
+        app
+    }
+
+    /// Run the kernel until it has serviced all deferred calls and no process
+    /// has anything left to do.
+    pub fn run(&self) {
+        let capability = create_capability!(MainLoopCapability);
+        for _ in 0..crate::MAX_STEPS {
+            if !DeferredCall::has_tasks() && !self.scheduler.has_work() {
+                return;
+            }
+            self.kernel.kernel_loop_operation::<_, _, 0>(
+                self,
+                self.chip,
+                None::<&IPC<0>>,
+                true,
+                &capability,
+            );
+        }
+        panic!("the kernel is still busy after {} steps", crate::MAX_STEPS);
+    }
+}
+
+impl SyscallDriverLookup for HostKernel {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&dyn SyscallDriver>) -> R,
+    {
+        let driver = self
+            .drivers
+            .borrow()
+            .iter()
+            .find(|(num, _)| *num == driver_num)
+            .map(|(_, driver)| *driver);
+        f(driver)
+    }
+}
+
+impl KernelResources<HostChip> for HostKernel {
+    type SyscallDriverLookup = Self;
+    type SyscallFilter = ();
+    type ProcessFault = ();
+    type Scheduler = HostScheduler;
+    type SchedulerTimer = ();
+    type WatchDog = ();
+    type ContextSwitchCallback = ();
+
+    fn syscall_driver_lookup(&self) -> &Self::SyscallDriverLookup {
+        self
+    }
+
+    fn syscall_filter(&self) -> &Self::SyscallFilter {
+        &()
+    }
+
+    fn process_fault(&self) -> &Self::ProcessFault {
+        &()
+    }
+
+    fn scheduler(&self) -> &Self::Scheduler {
+        &self.scheduler
+    }
+
+    fn scheduler_timer(&self) -> &Self::SchedulerTimer {
+        &()
+    }
+
+    fn watchdog(&self) -> &Self::WatchDog {
+        &()
+    }
+
+    fn context_switch_callback(&self) -> &Self::ContextSwitchCallback {
+        &()
+    }
+}
+
+/// An upcall delivered to a process.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Upcall {
+    pub driver_number: usize,
+    pub subscribe_number: usize,
+    pub arguments: [usize; 3],
+}
+
+/// A buffer in the memory of an [`App`].
+#[derive(Copy, Clone, Debug)]
+pub struct Buffer {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Buffer {
+    /// The null buffer, used to revoke an allow.
+    pub const NULL: Self = Self {
+        ptr: core::ptr::null_mut(),
+        len: 0,
+    };
+}
+
+/// A process whose system calls are issued by the test.
+pub struct App {
+    kernel: &'static HostKernel,
+    id: ProcessId,
+    memory: *mut u8,
+    memory_start: usize,
+    upcall_fn: usize,
+}
+
+impl App {
+    pub fn id(&self) -> ProcessId {
+        self.id
+    }
+
+    fn state(&self) -> State {
+        let capability = create_capability!(ProcessManagementCapability);
+        self.kernel.kernel.process_map_or_external(
+            State::Terminated,
+            self.id,
+            |process| process.get_state(),
+            &capability,
+        )
+    }
+
+    fn boundary(&self) -> &crate::chip::HostBoundary {
+        self.kernel.chip.userspace_kernel_boundary()
+    }
+
+    /// Issue `syscall` and return what the kernel returned to the process.
+    pub fn syscall(&self, syscall: Syscall) -> SyscallReturn {
+        assert_eq!(
+            self.state(),
+            State::Running,
+            "only a running process can issue system calls"
+        );
+        self.boundary().push_syscall(self.memory_start, syscall);
+        self.kernel.run();
+        self.boundary()
+            .take_return_value(self.memory_start)
+            .expect("the kernel did not return from the system call")
+    }
+
+    pub fn command(
+        &self,
+        driver_number: usize,
+        subdriver_number: usize,
+        arg0: usize,
+        arg1: usize,
+    ) -> SyscallReturn {
+        self.syscall(Syscall::Command {
+            driver_number,
+            subdriver_number,
+            arg0,
+            arg1,
+        })
+    }
+
+    /// Subscribe to the upcall, which [`App::yield_wait`] then returns.
+    pub fn subscribe(&self, driver_number: usize, subdriver_number: usize) -> SyscallReturn {
+        self.syscall(Syscall::Subscribe {
+            driver_number,
+            subdriver_number,
+            upcall_ptr: CapabilityPtr::from(self.upcall_fn),
+            appdata: MachineRegister::from(0),
+        })
+    }
+
+    /// Subscribe the null upcall, which is never delivered.
+    pub fn unsubscribe(&self, driver_number: usize, subdriver_number: usize) -> SyscallReturn {
+        self.syscall(Syscall::Subscribe {
+            driver_number,
+            subdriver_number,
+            upcall_ptr: CapabilityPtr::default(),
+            appdata: MachineRegister::from(0),
+        })
+    }
+
+    pub fn allow_readwrite(
+        &self,
+        driver_number: usize,
+        subdriver_number: usize,
+        buffer: Buffer,
+    ) -> SyscallReturn {
+        self.syscall(Syscall::ReadWriteAllow {
+            driver_number,
+            subdriver_number,
+            allow_address: buffer.ptr,
+            allow_size: buffer.len,
+        })
+    }
+
+    pub fn allow_userspace_readable(
+        &self,
+        driver_number: usize,
+        subdriver_number: usize,
+        buffer: Buffer,
+    ) -> SyscallReturn {
+        self.syscall(Syscall::UserspaceReadableAllow {
+            driver_number,
+            subdriver_number,
+            allow_address: buffer.ptr,
+            allow_size: buffer.len,
+        })
+    }
+
+    pub fn allow_readonly(
+        &self,
+        driver_number: usize,
+        subdriver_number: usize,
+        buffer: Buffer,
+    ) -> SyscallReturn {
+        self.syscall(Syscall::ReadOnlyAllow {
+            driver_number,
+            subdriver_number,
+            allow_address: buffer.ptr,
+            allow_size: buffer.len,
+        })
+    }
+
+    /// Grow the memory of the process with `sbrk` and return the new
+    /// `len`-byte buffer.
+    pub fn allocate(&self, len: usize) -> Buffer {
+        let capability = create_capability!(ProcessManagementCapability);
+        let app_break = self.kernel.kernel.process_map_or_external(
+            0,
+            self.id,
+            |process| process.get_addresses().sram_app_brk,
+            &capability,
+        );
+        let result = self.syscall(Syscall::Memop {
+            operand: 1,
+            arg0: len,
+        });
+        assert!(
+            matches!(result, SyscallReturn::SuccessPtr(_)),
+            "sbrk failed with {:?}",
+            result
+        );
+        Buffer {
+            ptr: self.memory.wrapping_add(app_break - self.memory.addr()),
+            len,
+        }
+    }
+
+    /// Copy the contents of `buffer`.
+    pub fn read(&self, buffer: Buffer) -> Vec<u8> {
+        let mut data = vec![0; buffer.len];
+        // SAFETY: The buffer was allocated in the process memory, which is
+        // never freed. The kernel only accesses it while handling a system
+        // call or running a deferred call or "interrupt", and not while test
+        // code runs.
+        unsafe { core::ptr::copy_nonoverlapping(buffer.ptr, data.as_mut_ptr(), buffer.len) };
+        data
+    }
+
+    /// Overwrite the start of `buffer` with `data`.
+    pub fn write(&self, buffer: Buffer, data: &[u8]) {
+        assert!(data.len() <= buffer.len, "the data does not fit the buffer");
+        // SAFETY: See `read()`.
+        unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), buffer.ptr, data.len()) };
+    }
+
+    /// Wait for an upcall with `yield-wait`.
+    ///
+    /// Returns the upcall if the kernel delivered one. Otherwise the process
+    /// keeps waiting, and the next call to `yield_wait` returns the next
+    /// upcall.
+    pub fn yield_wait(&self) -> Option<Upcall> {
+        match self.state() {
+            State::Running => {
+                self.boundary().push_syscall(
+                    self.memory_start,
+                    Syscall::Yield {
+                        yield_type: YieldVariant::Wait,
+                    },
+                );
+                self.kernel.run();
+            }
+            State::Yielded => self.kernel.run(),
+            state => panic!("cannot yield-wait in state {:?}", state),
+        }
+        self.boundary()
+            .take_function_call(self.memory_start)
+            .map(|call| match call.source {
+                FunctionCallSource::Driver(upcall_id) => Upcall {
+                    driver_number: upcall_id.driver_num,
+                    subscribe_number: upcall_id.subscribe_num,
+                    arguments: [call.argument0, call.argument1, call.argument2],
+                },
+                FunctionCallSource::Kernel => panic!("unexpected kernel function call"),
+            })
+    }
+
+    /// Wait for the upcall `subdriver_number` of `driver_number` with
+    /// `yield-waitfor`, without running its upcall function.
+    ///
+    /// Returns the upcall arguments if the upcall was scheduled. Otherwise the
+    /// process keeps waiting, like with [`App::yield_wait`].
+    pub fn yield_wait_for(
+        &self,
+        driver_number: usize,
+        subdriver_number: usize,
+    ) -> Option<[usize; 3]> {
+        match self.state() {
+            State::Running => {
+                self.boundary().push_syscall(
+                    self.memory_start,
+                    Syscall::Yield {
+                        yield_type: YieldVariant::WaitFor {
+                            driver_number,
+                            subdriver_number,
+                        },
+                    },
+                );
+                self.kernel.run();
+            }
+            State::YieldedFor(_) => self.kernel.run(),
+            state => panic!("cannot yield-waitfor in state {:?}", state),
+        }
+        self.boundary()
+            .take_return_value(self.memory_start)
+            .map(|value| match value {
+                SyscallReturn::YieldWaitFor(a0, a1, a2) => [a0, a1, a2],
+                other => panic!("unexpected return value {:?}", other),
+            })
+    }
+}
@@ -0,0 +1,157 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Simulated SPI peripheral behind a chip select.
+//!
+//! Bytes written by the controller are recorded and can be checked with
+//! [`MockSpiDevice::take_written`]. Bytes queued by the test with
+//! [`MockSpiDevice::respond`] are clocked back to the controller in order;
+//! once the queue is empty the peripheral returns `0xff`, like an undriven
+//! MISO line with a pull-up.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+use kernel::ErrorCode;
+use kernel::hil::spi::{ClockPhase, ClockPolarity, SpiMasterClient, SpiMasterDevice};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+
+use crate::Simulated;
+
+pub struct MockSpiDevice<'a> {
+    client: OptionalCell<&'a dyn SpiMasterClient>,
+    polarity: Cell<ClockPolarity>,
+    phase: Cell<ClockPhase>,
+    rate: Cell<u32>,
+    write_buffer: MapCell<SubSliceMut<'static, u8>>,
+    read_buffer: MapCell<SubSliceMut<'static, u8>>,
+    written: RefCell<Vec<u8>>,
+    responses: RefCell<VecDeque<u8>>,
+}
+
+impl MockSpiDevice<'_> {
+    pub fn new() -> Self {
+        Self {
+            client: OptionalCell::empty(),
+            polarity: Cell::new(ClockPolarity::IdleLow),
+            phase: Cell::new(ClockPhase::SampleLeading),
+            rate: Cell::new(1_000_000),
+            write_buffer: MapCell::empty(),
+            read_buffer: MapCell::empty(),
+            written: RefCell::new(Vec::new()),
+            responses: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Return and clear all bytes written by the controller so far.
+    pub fn take_written(&self) -> Vec<u8> {
+        self.written.take()
+    }
+
+    /// Queue `data` to be read by the controller.
+    pub fn respond(&self, data: &[u8]) {
+        self.responses.borrow_mut().extend(data);
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.write_buffer.is_some()
+    }
+}
+
+impl<'a> SpiMasterDevice<'a> for MockSpiDevice<'a> {
+    fn set_client(&self, client: &'a dyn SpiMasterClient) {
+        self.client.set(client);
+    }
+
+    fn configure(&self, cpol: ClockPolarity, cpal: ClockPhase, rate: u32) -> Result<(), ErrorCode> {
+        self.set_rate(rate)?;
+        self.polarity.set(cpol);
+        self.phase.set(cpal);
+        Ok(())
+    }
+
+    fn read_write_bytes(
+        &self,
+        write_buffer: SubSliceMut<'static, u8>,
+        read_buffer: Option<SubSliceMut<'static, u8>>,
+    ) -> Result<
+        (),
+        (
+            ErrorCode,
+            SubSliceMut<'static, u8>,
+            Option<SubSliceMut<'static, u8>>,
+        ),
+    > {
+        if self.write_buffer.is_some() {
+            return Err((ErrorCode::BUSY, write_buffer, read_buffer));
+        }
+        if write_buffer.len() == 0 {
+            return Err((ErrorCode::INVAL, write_buffer, read_buffer));
+        }
+        self.write_buffer.replace(write_buffer);
+        if let Some(read_buffer) = read_buffer {
+            self.read_buffer.replace(read_buffer);
+        }
+        Ok(())
+    }
+
+    fn set_rate(&self, rate: u32) -> Result<(), ErrorCode> {
+        if rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.rate.set(rate);
+        Ok(())
+    }
+
+    fn get_rate(&self) -> u32 {
+        self.rate.get()
+    }
+
+    fn set_polarity(&self, polarity: ClockPolarity) -> Result<(), ErrorCode> {
+        self.polarity.set(polarity);
+        Ok(())
+    }
+
+    fn get_polarity(&self) -> ClockPolarity {
+        self.polarity.get()
+    }
+
+    fn set_phase(&self, phase: ClockPhase) -> Result<(), ErrorCode> {
+        self.phase.set(phase);
+        Ok(())
+    }
+
+    fn get_phase(&self) -> ClockPhase {
+        self.phase.get()
+    }
+}
+
+impl Simulated for MockSpiDevice<'_> {
+    /// Clock out the pending transfer.
+    fn step(&self) -> bool {
+        let Some(write_buffer) = self.write_buffer.take() else {
+            return false;
+        };
+        let mut read_buffer = self.read_buffer.take();
+
+        let len = match read_buffer {
+            Some(ref read_buffer) => write_buffer.len().min(read_buffer.len()),
+            None => write_buffer.len(),
+        };
+        self.written
+            .borrow_mut()
+            .extend_from_slice(&write_buffer.as_slice()[..len]);
+        if let Some(ref mut read_buffer) = read_buffer {
+            let mut responses = self.responses.borrow_mut();
+            for byte in read_buffer.as_mut_slice()[..len].iter_mut() {
+                *byte = responses.pop_front().unwrap_or(0xff);
+            }
+        }
+
+        self.client
+            .map(move |client| client.read_write_done(write_buffer, read_buffer, Ok(len)));
+        true
+    }
+}
@@ -0,0 +1,221 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Simulated UART.
+//!
+//! Transmitted bytes are recorded and can be checked with
+//! [`MockUart::take_transmitted`]. Bytes sent by the test with
+//! [`MockUart::receive_bytes`] are queued, and handed to the client as it posts
+//! receive buffers. Only buffer operations are supported.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+use kernel::ErrorCode;
+use kernel::hil::uart;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+use crate::Simulated;
+
+pub struct MockUart<'a> {
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+    rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
+    parameters: Cell<Option<uart::Parameters>>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    tx_aborted: Cell<bool>,
+    transmitted: RefCell<Vec<u8>>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    rx_count: Cell<usize>,
+    rx_aborted: Cell<bool>,
+    rx_fifo: RefCell<VecDeque<u8>>,
+}
+
+impl MockUart<'_> {
+    pub fn new() -> Self {
+        Self {
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            parameters: Cell::new(None),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            tx_aborted: Cell::new(false),
+            transmitted: RefCell::new(Vec::new()),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_count: Cell::new(0),
+            rx_aborted: Cell::new(false),
+            rx_fifo: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// The parameters of the last successful `configure()` call.
+    pub fn parameters(&self) -> Option<uart::Parameters> {
+        self.parameters.get()
+    }
+
+    /// Return and clear all bytes transmitted so far.
+    pub fn take_transmitted(&self) -> Vec<u8> {
+        self.transmitted.take()
+    }
+
+    /// Queue `data` as if it arrived on the RX line.
+    pub fn receive_bytes(&self, data: &[u8]) {
+        self.rx_fifo.borrow_mut().extend(data);
+    }
+
+    pub fn is_transmitting(&self) -> bool {
+        self.tx_buffer.is_some()
+    }
+
+    pub fn is_receiving(&self) -> bool {
+        self.rx_buffer.is_some()
+    }
+
+    fn complete_transmit(&self) -> bool {
+        let Some(buffer) = self.tx_buffer.take() else {
+            return false;
+        };
+        let (len, result) = if self.tx_aborted.take() {
+            (0, Err(ErrorCode::CANCEL))
+        } else {
+            let len = self.tx_len.get();
+            self.transmitted
+                .borrow_mut()
+                .extend_from_slice(&buffer[..len]);
+            (len, Ok(()))
+        };
+        self.tx_client
+            .map(move |client| client.transmitted_buffer(buffer, len, result));
+        true
+    }
+
+    fn complete_receive(&self) -> bool {
+        if self.rx_buffer.is_none() {
+            return false;
+        }
+
+        let aborted = self.rx_aborted.take();
+        let start = self.rx_count.get();
+        let mut count = start;
+        self.rx_buffer.map(|buffer| {
+            let mut fifo = self.rx_fifo.borrow_mut();
+            while count < self.rx_len.get() && !aborted {
+                match fifo.pop_front() {
+                    Some(byte) => {
+                        buffer[count] = byte;
+                        count += 1;
+                    }
+                    None => break,
+                }
+            }
+        });
+        self.rx_count.set(count);
+
+        if aborted || count == self.rx_len.get() {
+            let (result, error) = if aborted {
+                (Err(ErrorCode::CANCEL), uart::Error::Aborted)
+            } else {
+                (Ok(()), uart::Error::None)
+            };
+            self.rx_buffer.take().map(|buffer| {
+                self.rx_client
+                    .map(move |client| client.received_buffer(buffer, count, result, error));
+            });
+            true
+        } else {
+            count != start
+        }
+    }
+}
+
+impl uart::Configure for MockUart<'_> {
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        if params.baud_rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.parameters.set(Some(params));
+        Ok(())
+    }
+}
+
+impl<'a> uart::Transmit<'a> for MockUart<'a> {
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.tx_buffer.is_some() {
+            Err((ErrorCode::BUSY, tx_buffer))
+        } else if tx_len == 0 || tx_len > tx_buffer.len() {
+            Err((ErrorCode::SIZE, tx_buffer))
+        } else {
+            self.tx_len.set(tx_len);
+            self.tx_buffer.replace(tx_buffer);
+            Ok(())
+        }
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        if self.tx_buffer.is_some() {
+            self.tx_aborted.set(true);
+            Err(ErrorCode::BUSY)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> uart::Receive<'a> for MockUart<'a> {
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.rx_buffer.is_some() {
+            Err((ErrorCode::BUSY, rx_buffer))
+        } else if rx_len == 0 || rx_len > rx_buffer.len() {
+            Err((ErrorCode::SIZE, rx_buffer))
+        } else {
+            self.rx_len.set(rx_len);
+            self.rx_count.set(0);
+            self.rx_buffer.replace(rx_buffer);
+            Ok(())
+        }
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        if self.rx_buffer.is_some() {
+            self.rx_aborted.set(true);
+            Err(ErrorCode::BUSY)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Simulated for MockUart<'_> {
+    /// Finish the pending transmission, or move queued bytes into the pending
+    /// receive buffer, completing it once it is full.
+    fn step(&self) -> bool {
+        self.complete_transmit() || self.complete_receive()
+    }
+}
@@ -7,6 +7,7 @@
 use core::fmt::Write;
 use cortexm4f::{CortexM4F, CortexMVariant};
 use kernel::platform::chip::Chip;
+use kernel::platform::chip::FaultRegisters;
 use kernel::platform::chip::InterruptService;
 
 pub struct Apollo3<I: InterruptService + 'static> {
@@ -167,4 +168,8 @@ impl<I: InterruptService + 'static> Chip for Apollo3<I> {
     unsafe fn print_state(_this: Option<&Self>, write: &mut dyn Write) {
         CortexM4F::print_cortexm_state(write);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        CortexM4F::fault_registers()
+    }
 }
@@ -5,6 +5,7 @@
 use core::fmt::Write;
 use kernel::debug;
 use kernel::hil::time::Freq32KHz;
+use kernel::platform::chip::FaultRegisters;
 use kernel::platform::chip::InterruptService;
 use kernel::utilities::registers::interfaces::Readable;
 
@@ -149,6 +150,10 @@ impl<'a, I: InterruptService + 'a> kernel::platform::chip::Chip for ArtyExx<'a,
     unsafe fn print_state(_this: Option<&Self>, write: &mut dyn Write) {
         rv32i::print_riscv_state(write);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        rv32i::fault_registers()
+    }
 }
 
 // Setup the function that should run when a trap happens.
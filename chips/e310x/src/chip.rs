@@ -8,6 +8,7 @@ use core::fmt::Write;
 use core::ptr::addr_of;
 use kernel::debug;
 use kernel::platform::chip::Chip;
+use kernel::platform::chip::FaultRegisters;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
 use rv32i::csr;
 use rv32i::csr::{CSR, mcause, mie::mie, mip::mip};
@@ -180,6 +181,10 @@ impl<'a, I: InterruptService + 'a> kernel::platform::chip::Chip for E310x<'a, I>
     unsafe fn print_state(_this: Option<&Self>, writer: &mut dyn Write) {
         rv32i::print_riscv_state(writer);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        rv32i::fault_registers()
+    }
 }
 
 fn handle_exception(exception: mcause::Exception) {
@@ -7,7 +7,7 @@
 use core::fmt::{Display, Write};
 use core::marker::PhantomData;
 use core::ptr::addr_of;
-use kernel::platform::chip::{Chip, InterruptService};
+use kernel::platform::chip::{Chip, FaultRegisters, InterruptService};
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use rv32i::csr::{CSR, mcause, mie::mie, mtvec::mtvec};
 use rv32i::pmp::{PMPUserMPU, TORUserPMP};
@@ -324,6 +324,10 @@ impl<
             let _ = writer.write_fmt(format_args!("{}", t.mpu.pmp));
         }
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        rv32i::fault_registers()
+    }
 }
 
 fn handle_exception(exception: mcause::Exception) {
@@ -7,7 +7,7 @@
 use core::fmt::Write;
 use core::ptr::addr_of;
 
-use kernel::platform::chip::{Chip, InterruptService};
+use kernel::platform::chip::{Chip, FaultRegisters, InterruptService};
 use kernel::utilities::StaticRef;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 
@@ -202,6 +202,10 @@ impl<'a, I: InterruptService + 'a> Chip for Esp32C3<'a, I> {
             spp
         ));
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        rv32i::fault_registers()
+    }
 }
 
 fn handle_exception(exception: mcause::Exception) {
@@ -7,7 +7,7 @@
 use core::fmt::Write;
 use cortexm7::{CortexM7, CortexMVariant};
 use kernel::debug;
-use kernel::platform::chip::{Chip, InterruptService};
+use kernel::platform::chip::{Chip, FaultRegisters, InterruptService};
 
 use crate::nvic;
 
@@ -156,4 +156,8 @@ impl<I: InterruptService + 'static> Chip for Imxrt10xx<I> {
     unsafe fn print_state(_this: Option<&Self>, write: &mut dyn Write) {
         CortexM7::print_cortexm_state(write);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        CortexM7::fault_registers()
+    }
 }
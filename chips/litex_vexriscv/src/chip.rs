@@ -7,6 +7,7 @@
 use core::fmt::Write;
 use core::ptr::addr_of;
 use kernel::debug;
+use kernel::platform::chip::FaultRegisters;
 use kernel::platform::chip::InterruptService;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
 use rv32i::csr::{CSR, mcause, mie::mie};
@@ -115,6 +116,10 @@ impl<I: 'static + InterruptService> kernel::platform::chip::Chip for LiteXVexRis
             let _ = writer.write_fmt(format_args!("{}", t.pmp_mpu.pmp));
         }
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        rv32i::fault_registers()
+    }
 }
 
 fn handle_exception(exception: mcause::Exception) {
@@ -8,6 +8,7 @@ use core::panic;
 
 use cortexm33::{CortexM33, CortexMVariant};
 use kernel::platform::chip::Chip;
+use kernel::platform::chip::FaultRegisters;
 use kernel::platform::chip::InterruptService;
 
 use crate::clocks::Clock;
@@ -100,6 +101,10 @@ impl<I: InterruptService> Chip for Lpc55s69<'_, I> {
     unsafe fn print_state(_this: Option<&Self>, writer: &mut dyn Write) {
         CortexM33::print_cortexm_state(writer);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        CortexM33::fault_registers()
+    }
 }
 
 pub struct Lpc55s69DefaultPeripheral<'a> {
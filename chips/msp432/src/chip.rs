@@ -5,6 +5,7 @@
 use core::fmt::Write;
 use cortexm4::{CortexM4, CortexMVariant};
 use kernel::platform::chip::Chip;
+use kernel::platform::chip::FaultRegisters;
 
 use crate::nvic;
 use crate::wdt;
@@ -155,4 +156,8 @@ impl<'a, I: InterruptService + 'a> Chip for Msp432<'a, I> {
     unsafe fn print_state(_this: Option<&Self>, write: &mut dyn Write) {
         CortexM4::print_cortexm_state(write);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        CortexM4::fault_registers()
+    }
 }
@@ -44,11 +44,14 @@ use core::ptr::addr_of_mut;
 use kernel::ErrorCode;
 use kernel::hil::ble_advertising;
 use kernel::hil::ble_advertising::RadioChannel;
+use kernel::hil::ble_connection;
 use kernel::utilities::StaticRef;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 use kernel::utilities::registers::interfaces::{Readable, Writeable};
-use kernel::utilities::registers::{ReadOnly, ReadWrite, WriteOnly, register_bitfields};
+use kernel::utilities::registers::{
+    FieldValue, ReadOnly, ReadWrite, WriteOnly, register_bitfields,
+};
 use nrf5x::constants::TxPower;
 
 const RADIO_BASE: StaticRef<RadioRegisters> =
@@ -536,12 +539,47 @@ register_bitfields! [u32,
 static mut PAYLOAD: [u8; nrf5x::constants::RADIO_PAYLOAD_LENGTH] =
     [0x00; nrf5x::constants::RADIO_PAYLOAD_LENGTH];
 
+/// Packet transmitted during a `BleConnectionDriver` operation. It is kept
+/// apart from `PAYLOAD`, which receives the other packet of the operation, so
+/// that both can be set up before the radio starts.
+static mut CONNECTION_TX: [u8; nrf5x::constants::RADIO_PAYLOAD_LENGTH] =
+    [0x00; nrf5x::constants::RADIO_PAYLOAD_LENGTH];
+
+// BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 4.1 Inter Frame Space
+const T_IFS_US: u32 = 150;
+
+/// Upper bound on the polls of a radio event that is due within
+/// microseconds, such as the end of a ramp-up.
+const EVENT_WAIT_POLLS: usize = 10_000;
+
+/// Which half of a combined `BleConnectionDriver` operation the radio is in.
+///
+/// The shortcuts configured for the operation switch between transmitting and
+/// receiving in hardware; the interrupt handler only tracks progress.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum ConnectionOperation {
+    None,
+    /// `transmit_then_receive`, packet being transmitted
+    Transmitting,
+    /// `transmit_then_receive`, waiting for the answer
+    ReceivingAnswer,
+    /// `receive_then_transmit`, waiting for the packet
+    Receiving,
+    /// `receive_then_transmit`, response being transmitted
+    TransmittingResponse(Result<(), ErrorCode>),
+}
+
 pub struct Radio<'a> {
     registers: StaticRef<RadioRegisters>,
     tx_power: Cell<TxPower>,
     rx_client: OptionalCell<&'a dyn ble_advertising::RxClient>,
     tx_client: OptionalCell<&'a dyn ble_advertising::TxClient>,
     buffer: TakeCell<'static, [u8]>,
+    access_address: Cell<u32>,
+    crc_init: Cell<u32>,
+    connection_client: OptionalCell<&'a dyn ble_connection::ConnectionClient>,
+    connection_operation: Cell<ConnectionOperation>,
+    connection_buffer: TakeCell<'static, [u8]>,
 }
 
 impl<'a> Radio<'a> {
@@ -552,6 +590,11 @@ impl<'a> Radio<'a> {
             rx_client: OptionalCell::empty(),
             tx_client: OptionalCell::empty(),
             buffer: TakeCell::empty(),
+            access_address: Cell::new(ble_connection::ADVERTISING_ACCESS_ADDRESS),
+            crc_init: Cell::new(ble_connection::ADVERTISING_CRC_INIT),
+            connection_client: OptionalCell::empty(),
+            connection_operation: Cell::new(ConnectionOperation::None),
+            connection_buffer: TakeCell::empty(),
         }
     }
 
@@ -601,6 +644,11 @@ impl<'a> Radio<'a> {
 
     #[inline(never)]
     pub fn handle_interrupt(&self) {
+        if self.connection_operation.get() != ConnectionOperation::None {
+            self.handle_connection_interrupt();
+            return;
+        }
+
         self.disable_all_interrupts();
 
         if self.registers.event_ready.is_set(Event::READY) {
@@ -660,6 +708,155 @@ impl<'a> Radio<'a> {
         self.enable_interrupts();
     }
 
+    // Only the END event is of interest during connection operations, the
+    // shortcuts take care of starting and turning the radio around.
+    fn handle_connection_interrupt(&self) {
+        if !self.registers.event_end.is_set(Event::READY) {
+            return;
+        }
+        self.registers.event_end.write(Event::READY::CLEAR);
+        self.registers.event_ready.write(Event::READY::CLEAR);
+        self.registers.event_address.write(Event::READY::CLEAR);
+        self.registers.event_payload.write(Event::READY::CLEAR);
+
+        let result = if self.registers.crcstatus.is_set(Event::READY) {
+            Ok(())
+        } else {
+            Err(ErrorCode::FAIL)
+        };
+
+        match self.connection_operation.get() {
+            ConnectionOperation::Transmitting => {
+                if self.end_turnaround().is_err() {
+                    self.abort_connection_operation();
+                    return;
+                }
+                self.connection_operation
+                    .set(ConnectionOperation::ReceivingAnswer);
+            }
+            ConnectionOperation::ReceivingAnswer => {
+                self.finish_connection_operation();
+                self.complete_connection_operation(result);
+            }
+            ConnectionOperation::Receiving => {
+                if self.end_turnaround().is_err() {
+                    self.abort_connection_operation();
+                    return;
+                }
+                self.connection_operation
+                    .set(ConnectionOperation::TransmittingResponse(result));
+            }
+            ConnectionOperation::TransmittingResponse(result) => {
+                self.finish_connection_operation();
+                self.complete_connection_operation(result);
+            }
+            ConnectionOperation::None => (),
+        }
+        self.registers.intenset.write(Interrupt::END::SET);
+    }
+
+    // Called at the end of the first packet of an operation. The shortcuts
+    // are already turning the radio around, which must not be interfered
+    // with, so wait until the DISABLED event shows that the turnaround has
+    // started before removing the shortcut that would turn the radio around
+    // again after the second packet.
+    fn end_turnaround(&self) -> Result<(), ErrorCode> {
+        self.wait_for_event(&self.registers.event_disabled)?;
+        self.registers.event_disabled.write(Event::READY::CLEAR);
+        self.registers
+            .shorts
+            .write(Shortcut::READY_START::SET + Shortcut::END_DISABLE::SET);
+        Ok(())
+    }
+
+    // Hand the received packet, which is in `PAYLOAD`, to the client.
+    fn complete_connection_operation(&self, result: Result<(), ErrorCode>) {
+        if let Some(buf) = self.connection_buffer.take() {
+            // Length is: S0 (1 Byte) + Length (1 Byte) + Payload
+            let len = unsafe { PAYLOAD[1] as usize + 2 };
+            let len = core::cmp::min(len, buf.len());
+            buf[..len].copy_from_slice(unsafe { &(&*addr_of!(PAYLOAD))[..len] });
+            self.connection_client
+                .map(|client| client.operation_done(buf, len, result));
+        }
+    }
+
+    // Give up on an operation whose radio event never came. The state of the
+    // radio is unknown, so it is switched off and the client gets no packet.
+    fn abort_connection_operation(&self) {
+        self.registers.task_disable.write(Task::ENABLE::SET);
+        self.finish_connection_operation();
+        if let Some(buf) = self.connection_buffer.take() {
+            self.connection_client
+                .map(|client| client.operation_done(buf, 0, Err(ErrorCode::FAIL)));
+        }
+    }
+
+    // Busy-wait for `event`, which the radio raises within microseconds.
+    // Returns `Err(ErrorCode::FAIL)` if it does not.
+    fn wait_for_event(&self, event: &ReadWrite<u32, Event::Register>) -> Result<(), ErrorCode> {
+        for _ in 0..EVENT_WAIT_POLLS {
+            if event.is_set(Event::READY) {
+                return Ok(());
+            }
+        }
+        Err(ErrorCode::FAIL)
+    }
+
+    fn finish_connection_operation(&self) {
+        self.connection_operation.set(ConnectionOperation::None);
+        self.registers.shorts.set(0);
+        self.disable_all_interrupts();
+        self.radio_off();
+    }
+
+    // Start a combined operation. `buf` is copied to the transmit buffer,
+    // `buffers` hold the packet buffers of the first and the second packet
+    // and `shorts` decide whether the radio turns around into RX or TX after
+    // the first packet.
+    fn start_connection_operation(
+        &self,
+        buf: &'static mut [u8],
+        len: usize,
+        channel: RadioChannel,
+        operation: ConnectionOperation,
+        buffers: (u32, u32),
+        shorts: FieldValue<u32, Shortcut::Register>,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let len = core::cmp::min(len, nrf5x::constants::RADIO_PAYLOAD_LENGTH);
+        unsafe {
+            (&mut *addr_of_mut!(CONNECTION_TX))[..len].copy_from_slice(&buf[..len]);
+        }
+        self.connection_operation.set(operation);
+        self.ble_initialize(channel, self.access_address.get(), self.crc_init.get());
+        self.registers
+            .tifs
+            .write(InterFrameSpacing::TIFS.val(T_IFS_US));
+        self.registers.event_end.write(Event::READY::CLEAR);
+        self.registers.event_disabled.write(Event::READY::CLEAR);
+        self.registers.shorts.write(shorts);
+        self.disable_all_interrupts();
+        self.registers.intenset.write(Interrupt::END::SET);
+
+        let (first, second) = buffers;
+        self.registers.packetptr.set(first);
+        match operation {
+            ConnectionOperation::Transmitting => self.tx(),
+            _ => self.rx(),
+        }
+        // PACKETPTR is latched by the START task, which the READY_START
+        // shortcut triggers once the radio has ramped up. The buffer for the
+        // second packet can be set from then on, long before the turnaround.
+        if let Err(e) = self.wait_for_event(&self.registers.event_ready) {
+            self.registers.task_disable.write(Task::ENABLE::SET);
+            self.finish_connection_operation();
+            return Err((e, buf));
+        }
+        self.registers.packetptr.set(second);
+        self.connection_buffer.replace(buf);
+        Ok(())
+    }
+
     pub fn enable_interrupts(&self) {
         self.registers.intenset.write(
             Interrupt::READY::SET
@@ -692,7 +889,7 @@ impl<'a> Radio<'a> {
         buf
     }
 
-    fn ble_initialize(&self, channel: RadioChannel) {
+    fn ble_initialize(&self, channel: RadioChannel, access_address: u32, crc_init: u32) {
         self.radio_on();
 
         self.ble_set_tx_power();
@@ -706,31 +903,30 @@ impl<'a> Radio<'a> {
         self.set_rx_address();
 
         self.ble_set_packet_config();
-        self.ble_set_advertising_access_address();
+        self.ble_set_access_address(access_address);
 
-        self.ble_set_crc_config();
+        self.ble_set_crc_config(crc_init);
 
         self.set_dma_ptr();
     }
 
     // BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 3.1.1 CRC Generation
-    fn ble_set_crc_config(&self) {
+    fn ble_set_crc_config(&self, crc_init: u32) {
         self.registers
             .crccnf
             .write(CrcConfiguration::LEN::THREE + CrcConfiguration::SKIPADDR::EXCLUDE);
-        self.registers
-            .crcinit
-            .set(nrf5x::constants::RADIO_CRCINIT_BLE);
+        self.registers.crcinit.set(crc_init);
         self.registers
             .crcpoly
             .set(nrf5x::constants::RADIO_CRCPOLY_BLE);
     }
 
     // BLUETOOTH SPECIFICATION Version 4.2 [Vol 6, Part B], section 2.1.2 Access Address
-    // Set access address to 0x8E89BED6
-    fn ble_set_advertising_access_address(&self) {
-        self.registers.prefix0.set(0x0000008e);
-        self.registers.base0.set(0x89bed600);
+    // Advertising uses 0x8E89BED6, connections the address from CONNECT_IND.
+    // With a 3 byte base the most significant byte goes into the prefix.
+    fn ble_set_access_address(&self, access_address: u32) {
+        self.registers.prefix0.set(access_address >> 24);
+        self.registers.base0.set(access_address << 8);
     }
 
     // Packet configuration
@@ -799,13 +995,21 @@ impl<'a> ble_advertising::BleAdvertisementDriver<'a> for Radio<'a> {
     fn transmit_advertisement(&self, buf: &'static mut [u8], _len: usize, channel: RadioChannel) {
         let res = self.replace_radio_buffer(buf);
         self.buffer.replace(res);
-        self.ble_initialize(channel);
+        self.ble_initialize(
+            channel,
+            ble_connection::ADVERTISING_ACCESS_ADDRESS,
+            nrf5x::constants::RADIO_CRCINIT_BLE,
+        );
         self.tx();
         self.enable_interrupts();
     }
 
     fn receive_advertisement(&self, channel: RadioChannel) {
-        self.ble_initialize(channel);
+        self.ble_initialize(
+            channel,
+            ble_connection::ADVERTISING_ACCESS_ADDRESS,
+            nrf5x::constants::RADIO_CRCINIT_BLE,
+        );
         self.rx();
         self.enable_interrupts();
     }
@@ -835,3 +1039,62 @@ impl ble_advertising::BleConfig for Radio<'_> {
         }
     }
 }
+
+impl<'a> ble_connection::BleConnectionDriver<'a> for Radio<'a> {
+    fn set_access_address(&self, access_address: u32, crc_init: u32) {
+        self.access_address.set(access_address);
+        self.crc_init.set(crc_init & 0xffffff);
+    }
+
+    fn transmit_then_receive(
+        &self,
+        buf: &'static mut [u8],
+        len: usize,
+        channel: RadioChannel,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.connection_operation.get() != ConnectionOperation::None {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        self.start_connection_operation(
+            buf,
+            len,
+            channel,
+            ConnectionOperation::Transmitting,
+            (addr_of!(CONNECTION_TX) as u32, addr_of!(PAYLOAD) as u32),
+            Shortcut::READY_START::SET + Shortcut::END_DISABLE::SET + Shortcut::DISABLED_RXEN::SET,
+        )
+    }
+
+    fn receive_then_transmit(
+        &self,
+        buf: &'static mut [u8],
+        len: usize,
+        channel: RadioChannel,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.connection_operation.get() != ConnectionOperation::None {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        self.start_connection_operation(
+            buf,
+            len,
+            channel,
+            ConnectionOperation::Receiving,
+            (addr_of!(PAYLOAD) as u32, addr_of!(CONNECTION_TX) as u32),
+            Shortcut::READY_START::SET + Shortcut::END_DISABLE::SET + Shortcut::DISABLED_TXEN::SET,
+        )
+    }
+
+    fn stop(&self) -> Result<&'static mut [u8], ErrorCode> {
+        if self.connection_operation.get() == ConnectionOperation::None {
+            return Err(ErrorCode::OFF);
+        }
+        self.registers.shorts.set(0);
+        self.registers.task_disable.write(Task::ENABLE::SET);
+        self.finish_connection_operation();
+        self.connection_buffer.take().ok_or(ErrorCode::FAIL)
+    }
+
+    fn set_connection_client(&self, client: &'a dyn ble_connection::ConnectionClient) {
+        self.connection_client.set(client);
+    }
+}
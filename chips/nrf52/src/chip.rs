@@ -6,6 +6,7 @@
 
 use core::fmt::Write;
 use cortexm4f::{CortexM4F, CortexMVariant, nvic};
+use kernel::platform::chip::FaultRegisters;
 use kernel::platform::chip::InterruptService;
 use kernel::utilities::StaticRef;
 
@@ -203,4 +204,8 @@ impl<'a, I: InterruptService + 'a> kernel::platform::chip::Chip for NRF52<'a, I>
     unsafe fn print_state(_this: Option<&Self>, write: &mut dyn Write) {
         CortexM4F::print_cortexm_state(write);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        CortexM4F::fault_registers()
+    }
 }
@@ -137,4 +137,7 @@ pub mod offset {
 
     /// Interrupt line, 8 bits
     pub const INT_LINE: u16 = 0x3C;
+
+    /// Interrupt pin, 8 bits
+    pub const INT_PIN: u16 = 0x3D;
 }
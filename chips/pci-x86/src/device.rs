@@ -126,6 +126,11 @@ impl Device {
         Self { bdf }
     }
 
+    /// Returns the BDF identifier of this device.
+    pub const fn bdf(&self) -> Bdf {
+        self.bdf
+    }
+
     /// Reads an 8-bit value from this device's PCI configuration space.
     #[inline]
     pub fn read8(&self, offset: u16) -> u8 {
@@ -297,4 +302,21 @@ impl Device {
 
         Some(val)
     }
+    /// Returns the legacy interrupt pin used by this device, if applicable.
+    ///
+    /// Pins are numbered from 0 for INTA# to 3 for INTD#.
+    #[inline]
+    pub fn int_pin(&self) -> Option<u8> {
+        // Config register only exists for normal devices
+        if self.header_type() != 0 {
+            return None;
+        }
+
+        // Per the spec, zero indicates the device does not use an interrupt pin, and 1-4
+        // correspond to INTA#-INTD#
+        match self.read8(offset::INT_PIN) {
+            pin @ 1..=4 => Some(pin - 1),
+            _ => None,
+        }
+    }
 }
@@ -7,6 +7,7 @@
 use core::fmt::Write;
 use kernel::hil::gpio::Configure;
 use kernel::platform::chip::Chip;
+use kernel::platform::chip::FaultRegisters;
 use kernel::platform::chip::InterruptService;
 
 use crate::cpuss_ppu;
@@ -157,6 +158,10 @@ impl<I: InterruptService> Chip for Psc3<'_, I> {
     unsafe fn print_state(_this: Option<&Self>, writer: &mut dyn Write) {
         CortexM33::print_cortexm_state(writer);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        CortexM33::fault_registers()
+    }
 }
 
 pub struct Psc3DefaultPeripherals<'a> {
@@ -3,6 +3,7 @@
 // Copyright OxidOS Automotive 2025 SRL.
 
 use kernel::platform::chip::Chip;
+use kernel::platform::chip::FaultRegisters;
 use kernel::platform::chip::InterruptService;
 
 use crate::{cpuss, gpio, hsiom, peri, scb, srss, tcpwm};
@@ -55,6 +56,10 @@ impl<I: InterruptService> Chip for Psoc62xa<'_, I> {
         CortexM0P::print_cortexm_state(writer);
     }
 
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        CortexM0P::fault_registers()
+    }
+
     fn userspace_kernel_boundary(&self) -> &Self::UserspaceKernelBoundary {
         &self.userspace_kernel_boundary
     }
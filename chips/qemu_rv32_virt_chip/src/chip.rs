@@ -9,7 +9,7 @@ use core::ptr::addr_of;
 
 use kernel::debug;
 use kernel::hil::time::Freq10MHz;
-use kernel::platform::chip::{Chip, InterruptService};
+use kernel::platform::chip::{Chip, FaultRegisters, InterruptService};
 
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
 
@@ -29,6 +29,8 @@ type QemuRv32VirtPMP = rv32i::pmp::PMPUserMPU<
 
 pub type QemuRv32VirtClint<'a> = sifive::clint::Clint<'a, Freq10MHz>;
 
+pub type QemuRv32VirtRtc<'a> = qemu_virt_chip::goldfish_rtc::GoldfishRtc<'a>;
+
 pub struct QemuRv32VirtChip<'a, I: InterruptService + 'a> {
     userspace_kernel_boundary: rv32i::syscall::SysCall,
     pmp: QemuRv32VirtPMP,
@@ -39,6 +41,7 @@ pub struct QemuRv32VirtChip<'a, I: InterruptService + 'a> {
 
 pub struct QemuRv32VirtDefaultPeripherals<'a> {
     pub uart0: qemu_virt_chip::uart::Uart16550<'a>,
+    pub rtc: QemuRv32VirtRtc<'a>,
     pub virtio_mmio: [VirtIOMMIODevice; 8],
 }
 
@@ -46,6 +49,7 @@ impl QemuRv32VirtDefaultPeripherals<'_> {
     pub fn new() -> Self {
         Self {
             uart0: qemu_virt_chip::uart::Uart16550::new(crate::uart::UART0_BASE),
+            rtc: qemu_virt_chip::goldfish_rtc::GoldfishRtc::new(crate::rtc::RTC_BASE),
             virtio_mmio: [
                 VirtIOMMIODevice::new(crate::virtio_mmio::VIRTIO_MMIO_0_BASE),
                 VirtIOMMIODevice::new(crate::virtio_mmio::VIRTIO_MMIO_1_BASE),
@@ -58,12 +62,18 @@ impl QemuRv32VirtDefaultPeripherals<'_> {
             ],
         }
     }
+
+    // Necessary for registering deferred calls
+    pub fn init(&'static self) {
+        kernel::deferred_call::DeferredCallClient::register(&self.rtc);
+    }
 }
 
 impl InterruptService for QemuRv32VirtDefaultPeripherals<'_> {
     fn service_interrupt(&self, interrupt: u32) -> bool {
         match interrupt {
             interrupts::UART0 => self.uart0.handle_interrupt(),
+            interrupts::RTC => self.rtc.handle_interrupt(),
             interrupts::VIRTIO_MMIO_0 => self.virtio_mmio[0].handle_interrupt(),
             interrupts::VIRTIO_MMIO_1 => self.virtio_mmio[1].handle_interrupt(),
             interrupts::VIRTIO_MMIO_2 => self.virtio_mmio[2].handle_interrupt(),
@@ -182,6 +192,10 @@ impl<'a, I: InterruptService + 'a> Chip for QemuRv32VirtChip<'a, I> {
             let _ = writer.write_fmt(format_args!("{}", t.pmp.pmp));
         }
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        rv32i::fault_registers()
+    }
 }
 
 fn handle_exception(exception: mcause::Exception) {
@@ -0,0 +1,13 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! QEMU's CFI parallel flash banks
+
+use kernel::utilities::StaticRef;
+use qemu_virt_chip::cfi_flash::CfiFlashBank;
+
+/// Second flash bank (`-drive if=pflash,unit=1`). The first bank is not
+/// exposed, since providing it makes QEMU boot from it.
+pub const PFLASH1_BASE: StaticRef<CfiFlashBank> =
+    unsafe { StaticRef::new(0x2200_0000 as *const CfiFlashBank) };
@@ -13,5 +13,7 @@ pub mod virtio_mmio;
 
 pub mod chip;
 pub mod clint;
+pub mod flash;
 pub mod plic;
+pub mod rtc;
 pub mod uart;
@@ -9,7 +9,7 @@ use core::ptr::addr_of;
 
 use kernel::debug;
 use kernel::hil::time::Freq10MHz;
-use kernel::platform::chip::{Chip, InterruptService};
+use kernel::platform::chip::{Chip, FaultRegisters, InterruptService};
 
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
 
@@ -26,6 +26,8 @@ type QemuRv64VirtPMP = rv64i::pmp::PMPUserMPU<8, rv64i::pmp::simple::SimplePMP<1
 
 pub type QemuRv64VirtClint<'a> = sifive::clint::Clint<'a, Freq10MHz>;
 
+pub type QemuRv64VirtRtc<'a> = qemu_virt_chip::goldfish_rtc::GoldfishRtc<'a>;
+
 pub struct QemuRv64VirtChip<'a, I: InterruptService + 'a> {
     userspace_kernel_boundary: rv64i::syscall::SysCall,
     pmp: QemuRv64VirtPMP,
@@ -36,6 +38,7 @@ pub struct QemuRv64VirtChip<'a, I: InterruptService + 'a> {
 
 pub struct QemuRv64VirtDefaultPeripherals<'a> {
     pub uart0: qemu_virt_chip::uart::Uart16550<'a>,
+    pub rtc: QemuRv64VirtRtc<'a>,
     pub virtio_mmio: [VirtIOMMIODevice; 8],
 }
 
@@ -43,6 +46,7 @@ impl QemuRv64VirtDefaultPeripherals<'_> {
     pub fn new() -> Self {
         Self {
             uart0: qemu_virt_chip::uart::Uart16550::new(crate::uart::UART0_BASE),
+            rtc: qemu_virt_chip::goldfish_rtc::GoldfishRtc::new(crate::rtc::RTC_BASE),
             virtio_mmio: [
                 VirtIOMMIODevice::new(crate::virtio_mmio::VIRTIO_MMIO_0_BASE),
                 VirtIOMMIODevice::new(crate::virtio_mmio::VIRTIO_MMIO_1_BASE),
@@ -55,12 +59,18 @@ impl QemuRv64VirtDefaultPeripherals<'_> {
             ],
         }
     }
+
+    // Necessary for registering deferred calls
+    pub fn init(&'static self) {
+        kernel::deferred_call::DeferredCallClient::register(&self.rtc);
+    }
 }
 
 impl InterruptService for QemuRv64VirtDefaultPeripherals<'_> {
     fn service_interrupt(&self, interrupt: u32) -> bool {
         match interrupt {
             interrupts::UART0 => self.uart0.handle_interrupt(),
+            interrupts::RTC => self.rtc.handle_interrupt(),
             interrupts::VIRTIO_MMIO_0 => self.virtio_mmio[0].handle_interrupt(),
             interrupts::VIRTIO_MMIO_1 => self.virtio_mmio[1].handle_interrupt(),
             interrupts::VIRTIO_MMIO_2 => self.virtio_mmio[2].handle_interrupt(),
@@ -179,6 +189,10 @@ impl<'a, I: InterruptService + 'a> Chip for QemuRv64VirtChip<'a, I> {
             let _ = writer.write_fmt(format_args!("{}", t.pmp.pmp));
         }
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        rv64i::fault_registers()
+    }
 }
 
 fn handle_exception(exception: mcause::Exception) {
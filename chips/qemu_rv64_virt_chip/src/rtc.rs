@@ -0,0 +1,11 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! QEMU's memory mapped Goldfish RTC
+
+use kernel::utilities::StaticRef;
+use qemu_virt_chip::goldfish_rtc::GoldfishRtcRegisters;
+
+pub const RTC_BASE: StaticRef<GoldfishRtcRegisters> =
+    unsafe { StaticRef::new(0x0010_1000 as *const GoldfishRtcRegisters) };
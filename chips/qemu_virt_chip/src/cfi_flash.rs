@@ -0,0 +1,406 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! QEMU's CFI parallel flash, using the Intel command set.
+//!
+//! The QEMU `virt` machines have two 32 MiB flash banks, each made of two
+//! interleaved 16-bit devices on a 32-bit bus. Commands are therefore written
+//! to both devices at once, and the status register is read from both.
+//!
+//! The erase blocks of these banks are 256 KiB, which is too large to buffer
+//! in RAM. QEMU does not model the restriction of NOR flash that programming
+//! can only clear bits: a programmed word is stored as is. This driver relies
+//! on that to provide 4 KiB pages. Writing a page programs all of its words,
+//! and erasing a page programs it with `0xFF`. The driver therefore only works
+//! with emulated flash.
+//!
+//! Page numbers are absolute, i.e. the address of a page divided by
+//! [`PAGE_SIZE`], so that capsules which address flash by its location in
+//! memory, like `nonvolatile_to_pages`, can be used directly.
+//!
+//! QEMU completes operations immediately, so the driver issues them
+//! synchronously and signals their completion with a deferred call.
+
+use core::cell::Cell;
+
+use kernel::ErrorCode;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil;
+use kernel::utilities::StaticRef;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::ReadWrite;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+
+/// Size of a flash bank.
+pub const BANK_SIZE: usize = 32 * 1024 * 1024;
+
+/// Size of a page as seen through `hil::flash::Flash`.
+pub const PAGE_SIZE: usize = 4096;
+
+const WORD_SIZE: usize = 4;
+
+/// A flash bank, which reads as memory in read array mode.
+#[repr(C)]
+pub struct CfiFlashBank {
+    words: [ReadWrite<u32>; BANK_SIZE / WORD_SIZE],
+}
+
+/// Intel command set commands, repeated for both devices of the bus.
+const CMD_READ_ARRAY: u32 = 0x00FF_00FF;
+const CMD_READ_STATUS: u32 = 0x0070_0070;
+const CMD_CLEAR_STATUS: u32 = 0x0050_0050;
+const CMD_PROGRAM: u32 = 0x0040_0040;
+
+/// Status register bits, for both devices of the bus.
+const STATUS_READY: u32 = 0x0080_0080;
+/// Erase, program, voltage and block lock errors.
+const STATUS_ERRORS: u32 = 0x003A_003A;
+
+/// A page of flash. Users of this module must pass an object of this type to
+/// use the `hil::flash::Flash` interface.
+pub struct CfiFlashPage(pub [u8; PAGE_SIZE]);
+
+impl Default for CfiFlashPage {
+    fn default() -> Self {
+        Self([0; PAGE_SIZE])
+    }
+}
+
+impl AsMut<[u8]> for CfiFlashPage {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Operation {
+    Read,
+    Write,
+    Erase,
+}
+
+pub struct CfiFlash {
+    bank: StaticRef<CfiFlashBank>,
+    /// Number of the first page of the bank.
+    first_page: usize,
+    client: OptionalCell<&'static dyn hil::flash::Client<CfiFlash>>,
+    buffer: TakeCell<'static, CfiFlashPage>,
+    operation: Cell<Option<(Operation, Result<(), hil::flash::Error>)>>,
+    deferred_call: DeferredCall,
+}
+
+impl CfiFlash {
+    pub fn new(bank: StaticRef<CfiFlashBank>) -> Self {
+        let address = core::ptr::from_ref::<CfiFlashBank>(&bank).addr();
+        Self {
+            bank,
+            first_page: address / PAGE_SIZE,
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            operation: Cell::new(None),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Index of the first word of `page_number` in the bank, if the page is
+    /// in the bank.
+    fn page_word_index(&self, page_number: usize) -> Option<usize> {
+        let page = page_number.checked_sub(self.first_page)?;
+        if page < BANK_SIZE / PAGE_SIZE {
+            Some(page * PAGE_SIZE / WORD_SIZE)
+        } else {
+            None
+        }
+    }
+
+    /// Program the words of a page, leaving the bank in read array mode.
+    fn program(
+        &self,
+        first_word: usize,
+        mut words: impl Iterator<Item = u32>,
+    ) -> Result<(), hil::flash::Error> {
+        let result = self.bank.words[first_word..first_word + PAGE_SIZE / WORD_SIZE]
+            .iter()
+            .try_for_each(|word| {
+                word.set(CMD_PROGRAM);
+                word.set(words.next().unwrap_or(u32::MAX));
+                self.wait_ready(word)
+            });
+        if result.is_err() {
+            self.bank.words[first_word].set(CMD_CLEAR_STATUS);
+        }
+        self.bank.words[first_word].set(CMD_READ_ARRAY);
+        result
+    }
+
+    /// Wait for the last operation on `word` to complete, and check its
+    /// status. This spins without a bound, as QEMU completes operations
+    /// immediately.
+    fn wait_ready(&self, word: &ReadWrite<u32>) -> Result<(), hil::flash::Error> {
+        word.set(CMD_READ_STATUS);
+        let mut status = word.get();
+        while status & STATUS_READY != STATUS_READY {
+            status = word.get();
+        }
+
+        if status & STATUS_ERRORS != 0 {
+            Err(hil::flash::Error::FlashError)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn complete(&self, operation: Operation, result: Result<(), hil::flash::Error>) {
+        self.operation.set(Some((operation, result)));
+        self.deferred_call.set();
+    }
+}
+
+impl<C: hil::flash::Client<Self>> hil::flash::HasClient<'static, C> for CfiFlash {
+    fn set_client(&self, client: &'static C) {
+        self.client.set(client);
+    }
+}
+
+impl hil::flash::Flash for CfiFlash {
+    type Page = CfiFlashPage;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if self.operation.get().is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        let Some(first_word) = self.page_word_index(page_number) else {
+            return Err((ErrorCode::INVAL, buf));
+        };
+
+        for (bytes, word) in buf
+            .0
+            .as_chunks_mut::<WORD_SIZE>()
+            .0
+            .iter_mut()
+            .zip(&self.bank.words[first_word..])
+        {
+            *bytes = word.get().to_le_bytes();
+        }
+
+        self.buffer.replace(buf);
+        self.complete(Operation::Read, Ok(()));
+        Ok(())
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if self.operation.get().is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        let Some(first_word) = self.page_word_index(page_number) else {
+            return Err((ErrorCode::INVAL, buf));
+        };
+
+        let result = self.program(
+            first_word,
+            buf.0
+                .as_chunks::<WORD_SIZE>()
+                .0
+                .iter()
+                .map(|bytes| u32::from_le_bytes(*bytes)),
+        );
+
+        self.buffer.replace(buf);
+        self.complete(Operation::Write, result);
+        Ok(())
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        if self.operation.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let Some(first_word) = self.page_word_index(page_number) else {
+            return Err(ErrorCode::INVAL);
+        };
+
+        let result = self.program(first_word, core::iter::empty());
+
+        self.complete(Operation::Erase, result);
+        Ok(())
+    }
+}
+
+impl DeferredCallClient for CfiFlash {
+    fn handle_deferred_call(&self) {
+        let Some((operation, result)) = self.operation.take() else {
+            return;
+        };
+
+        match operation {
+            Operation::Read => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.client
+                        .map(move |client| client.read_complete(buffer, result));
+                }
+            }
+            Operation::Write => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.client
+                        .map(move |client| client.write_complete(buffer, result));
+                }
+            }
+            Operation::Erase => {
+                self.client.map(|client| client.erase_complete(result));
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use capsules_test_harness::{deferred_call, leak, run_until_idle, static_registers};
+    use hil::flash::{Flash, HasClient};
+    use std::boxed::Box;
+
+    const PAGES: usize = BANK_SIZE / PAGE_SIZE;
+
+    struct Recorder {
+        read: Cell<Option<Result<(), hil::flash::Error>>>,
+        buffer: TakeCell<'static, CfiFlashPage>,
+    }
+
+    impl hil::flash::Client<CfiFlash> for Recorder {
+        fn read_complete(
+            &self,
+            buffer: &'static mut CfiFlashPage,
+            result: Result<(), hil::flash::Error>,
+        ) {
+            self.buffer.replace(buffer);
+            self.read.set(Some(result));
+        }
+
+        fn write_complete(
+            &self,
+            _buffer: &'static mut CfiFlashPage,
+            _result: Result<(), hil::flash::Error>,
+        ) {
+        }
+
+        fn erase_complete(&self, _result: Result<(), hil::flash::Error>) {}
+    }
+
+    fn flash() -> (&'static CfiFlash, &'static Recorder) {
+        // The status register of a bank in RAM never reads as ready, so only
+        // reads can complete.
+        let flash = leak(CfiFlash::new(static_registers()));
+        flash.register();
+        let client = leak(Recorder {
+            read: Cell::new(None),
+            buffer: TakeCell::empty(),
+        });
+        flash.set_client(client);
+        (flash, client)
+    }
+
+    fn page() -> &'static mut CfiFlashPage {
+        Box::leak(Box::default())
+    }
+
+    #[test]
+    fn page_word_index_covers_only_the_bank() {
+        deferred_call::run(|| {
+            let (flash, _) = flash();
+            let first = flash.first_page;
+
+            assert_eq!(flash.page_word_index(first), Some(0));
+            assert_eq!(
+                flash.page_word_index(first + 1),
+                Some(PAGE_SIZE / WORD_SIZE)
+            );
+            assert_eq!(
+                flash.page_word_index(first + PAGES - 1),
+                Some((PAGES - 1) * PAGE_SIZE / WORD_SIZE)
+            );
+            assert_eq!(flash.page_word_index(first + PAGES), None);
+            assert_eq!(flash.page_word_index(first - 1), None);
+        });
+    }
+
+    #[test]
+    fn page_numbers_are_absolute() {
+        deferred_call::run(|| {
+            let (flash, client) = flash();
+            let first = flash.first_page;
+            assert_eq!(first, flash.bank.words.as_ptr().addr() / PAGE_SIZE);
+            flash.bank.words[PAGE_SIZE / WORD_SIZE].set(0x4433_2211);
+
+            // Page 1 of the bank is not page 1 of the address space.
+            assert!(matches!(
+                flash.read_page(1, page()),
+                Err((ErrorCode::INVAL, _))
+            ));
+
+            assert!(flash.read_page(first + 1, page()).is_ok());
+            run_until_idle(&[]);
+            assert_eq!(client.read.get(), Some(Ok(())));
+            let buffer = client.buffer.take().unwrap();
+            assert_eq!(buffer.0[..5], [0x11, 0x22, 0x33, 0x44, 0]);
+        });
+    }
+
+    #[test]
+    fn pages_outside_the_bank_are_rejected() {
+        deferred_call::run(|| {
+            let (flash, _) = flash();
+            let past_end = flash.first_page + PAGES;
+
+            assert!(matches!(
+                flash.read_page(past_end, page()),
+                Err((ErrorCode::INVAL, _))
+            ));
+            assert!(matches!(
+                flash.write_page(past_end, page()),
+                Err((ErrorCode::INVAL, _))
+            ));
+            assert_eq!(flash.erase_page(past_end), Err(ErrorCode::INVAL));
+        });
+    }
+
+    #[test]
+    fn operations_are_rejected_until_the_last_one_completes() {
+        deferred_call::run(|| {
+            let (flash, client) = flash();
+            let first = flash.first_page;
+
+            assert!(flash.read_page(first, page()).is_ok());
+            assert!(matches!(
+                flash.read_page(first, page()),
+                Err((ErrorCode::BUSY, _))
+            ));
+            assert!(matches!(
+                flash.write_page(first, page()),
+                Err((ErrorCode::BUSY, _))
+            ));
+            assert_eq!(flash.erase_page(first), Err(ErrorCode::BUSY));
+
+            run_until_idle(&[]);
+            assert_eq!(client.read.get(), Some(Ok(())));
+            assert!(
+                flash
+                    .read_page(first, client.buffer.take().unwrap())
+                    .is_ok()
+            );
+        });
+    }
+}
@@ -0,0 +1,413 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! QEMU's Goldfish real time clock.
+//!
+//! The Goldfish RTC is a 64-bit counter of nanoseconds since the Unix epoch,
+//! which QEMU initializes from the host clock. It has a single alarm, raising
+//! an interrupt once the counter reaches the alarm value.
+//!
+//! This driver implements:
+//!
+//! - `hil::date_time::DateTime`, converting the counter to and from a UTC
+//!   calendar date.
+//! - `hil::time::Alarm`, with nanosecond ticks.
+//!
+//! Both interfaces share the counter: setting the date moves the time base of
+//! the alarm. Boards using the alarm should therefore not let userspace set
+//! the date.
+
+use kernel::ErrorCode;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::date_time::{self, DateTimeClient, DateTimeValues, DayOfWeek, Month};
+use kernel::hil::time::{self, ConvertTicks, Freq1GHz, Ticks, Ticks64, Time};
+use kernel::utilities::StaticRef;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{ReadOnly, ReadWrite, WriteOnly, register_structs};
+
+register_structs! {
+    pub GoldfishRtcRegisters {
+        /// Low 32 bits of the time. Reading latches the high 32 bits.
+        (0x00 => time_low: ReadWrite<u32>),
+        /// High 32 bits of the time, as latched by the last read of
+        /// `time_low`.
+        (0x04 => time_high: ReadWrite<u32>),
+        /// Low 32 bits of the alarm. Writing arms the alarm.
+        (0x08 => alarm_low: ReadWrite<u32>),
+        /// High 32 bits of the alarm.
+        (0x0C => alarm_high: ReadWrite<u32>),
+        /// Whether the alarm raises an interrupt.
+        (0x10 => irq_enabled: ReadWrite<u32>),
+        /// Writing disarms the alarm.
+        (0x14 => clear_alarm: WriteOnly<u32>),
+        /// Whether the alarm is armed.
+        (0x18 => alarm_status: ReadOnly<u32>),
+        /// Writing acknowledges the interrupt.
+        (0x1C => clear_interrupt: WriteOnly<u32>),
+        (0x20 => @END),
+    }
+}
+
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Days from 1970-01-01 to 0000-03-01 in the proleptic Gregorian calendar.
+///
+/// Counting years from March puts the leap day at the end of the year, which
+/// keeps the conversions below free of special cases.
+const EPOCH_OFFSET_DAYS: u64 = 719_468;
+const DAYS_PER_ERA: u64 = 146_097;
+
+/// Convert a number of days since the Unix epoch into a date.
+///
+/// This is the `civil_from_days` algorithm by Howard Hinnant, restricted to
+/// dates after the epoch.
+fn date_from_days(days: u64) -> (u64, u8, u8) {
+    let days = days + EPOCH_OFFSET_DAYS;
+    let era = days / DAYS_PER_ERA;
+    let day_of_era = days % DAYS_PER_ERA;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Month starting from March, in 0..=11
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month as u8, day as u8)
+}
+
+/// Convert a date into a number of days since the Unix epoch.
+///
+/// This is the `days_from_civil` algorithm by Howard Hinnant. The date must
+/// not be before the epoch.
+fn days_from_date(year: u64, month: u8, day: u8) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let month = u64::from(month);
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + u64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * DAYS_PER_ERA + day_of_era - EPOCH_OFFSET_DAYS
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u16, month: Month) -> u8 {
+    match month {
+        Month::February if is_leap_year(year) => 29,
+        Month::February => 28,
+        Month::April | Month::June | Month::September | Month::November => 30,
+        _ => 31,
+    }
+}
+
+fn month_from_number(month: u8) -> Month {
+    match month {
+        1 => Month::January,
+        2 => Month::February,
+        3 => Month::March,
+        4 => Month::April,
+        5 => Month::May,
+        6 => Month::June,
+        7 => Month::July,
+        8 => Month::August,
+        9 => Month::September,
+        10 => Month::October,
+        11 => Month::November,
+        _ => Month::December,
+    }
+}
+
+fn month_into_number(month: Month) -> u8 {
+    match month {
+        Month::January => 1,
+        Month::February => 2,
+        Month::March => 3,
+        Month::April => 4,
+        Month::May => 5,
+        Month::June => 6,
+        Month::July => 7,
+        Month::August => 8,
+        Month::September => 9,
+        Month::October => 10,
+        Month::November => 11,
+        Month::December => 12,
+    }
+}
+
+/// Day of the week of a number of days since the Unix epoch, which was a
+/// Thursday.
+fn day_of_week_from_days(days: u64) -> DayOfWeek {
+    match (days + 4) % 7 {
+        0 => DayOfWeek::Sunday,
+        1 => DayOfWeek::Monday,
+        2 => DayOfWeek::Tuesday,
+        3 => DayOfWeek::Wednesday,
+        4 => DayOfWeek::Thursday,
+        5 => DayOfWeek::Friday,
+        _ => DayOfWeek::Saturday,
+    }
+}
+
+fn date_time_from_seconds(seconds: u64) -> Result<DateTimeValues, ErrorCode> {
+    let days = seconds / SECONDS_PER_DAY;
+    let seconds_of_day = seconds % SECONDS_PER_DAY;
+    let (year, month, day) = date_from_days(days);
+
+    Ok(DateTimeValues {
+        year: u16::try_from(year).map_err(|_| ErrorCode::FAIL)?,
+        month: month_from_number(month),
+        day,
+        day_of_week: day_of_week_from_days(days),
+        hour: (seconds_of_day / 3600) as u8,
+        minute: (seconds_of_day / 60 % 60) as u8,
+        seconds: (seconds_of_day % 60) as u8,
+    })
+}
+
+/// Seconds since the Unix epoch of a date. The day of the week is ignored,
+/// as it follows from the date.
+fn seconds_from_date_time(date_time: DateTimeValues) -> Result<u64, ErrorCode> {
+    if date_time.year < 1970
+        || date_time.day == 0
+        || date_time.day > days_in_month(date_time.year, date_time.month)
+        || date_time.hour > 23
+        || date_time.minute > 59
+        || date_time.seconds > 59
+    {
+        return Err(ErrorCode::INVAL);
+    }
+
+    let days = days_from_date(
+        u64::from(date_time.year),
+        month_into_number(date_time.month),
+        date_time.day,
+    );
+    Ok(days * SECONDS_PER_DAY
+        + u64::from(date_time.hour) * 3600
+        + u64::from(date_time.minute) * 60
+        + u64::from(date_time.seconds))
+}
+
+#[derive(Clone, Copy)]
+enum DeferredCallTask {
+    Get(Result<DateTimeValues, ErrorCode>),
+    Set,
+}
+
+pub struct GoldfishRtc<'a> {
+    registers: StaticRef<GoldfishRtcRegisters>,
+    alarm_client: OptionalCell<&'a dyn time::AlarmClient>,
+    date_time_client: OptionalCell<&'a dyn DateTimeClient>,
+    deferred_call: DeferredCall,
+    deferred_call_task: OptionalCell<DeferredCallTask>,
+}
+
+impl GoldfishRtc<'_> {
+    pub fn new(base: StaticRef<GoldfishRtcRegisters>) -> Self {
+        Self {
+            registers: base,
+            alarm_client: OptionalCell::empty(),
+            date_time_client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+            deferred_call_task: OptionalCell::empty(),
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        self.registers.clear_interrupt.set(1);
+        self.alarm_client.map(|client| client.alarm());
+    }
+
+    fn read_time(&self) -> u64 {
+        // Reading the low half latches the high half, so the two are
+        // consistent.
+        let low = self.registers.time_low.get();
+        let high = self.registers.time_high.get();
+        (u64::from(high) << 32) | u64::from(low)
+    }
+
+    fn write_time(&self, time: u64) {
+        // Each write moves the counter by the difference to the written half,
+        // so write the high half first to land on `time` once the low half is
+        // written.
+        self.registers.time_high.set((time >> 32) as u32);
+        self.registers.time_low.set(time as u32);
+    }
+}
+
+impl Time for GoldfishRtc<'_> {
+    type Frequency = Freq1GHz;
+    type Ticks = Ticks64;
+
+    fn now(&self) -> Ticks64 {
+        Ticks64::from(self.read_time())
+    }
+}
+
+impl<'a> time::Alarm<'a> for GoldfishRtc<'a> {
+    fn set_alarm_client(&self, client: &'a dyn time::AlarmClient) {
+        self.alarm_client.set(client);
+    }
+
+    fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
+        // An alarm in the past fires right away, so there is no need to
+        // check whether the counter already passed it.
+        let expire = reference.wrapping_add(dt).into_u64();
+        self.registers.irq_enabled.set(1);
+        self.registers.alarm_high.set((expire >> 32) as u32);
+        self.registers.alarm_low.set(expire as u32);
+    }
+
+    fn get_alarm(&self) -> Self::Ticks {
+        let low = self.registers.alarm_low.get();
+        let high = self.registers.alarm_high.get();
+        Ticks64::from((u64::from(high) << 32) | u64::from(low))
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.registers.clear_alarm.set(1);
+        self.registers.clear_interrupt.set(1);
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.registers.alarm_status.get() != 0
+    }
+
+    fn minimum_dt(&self) -> Self::Ticks {
+        self.ticks_from_us(1)
+    }
+}
+
+impl<'a> date_time::DateTime<'a> for GoldfishRtc<'a> {
+    fn get_date_time(&self) -> Result<(), ErrorCode> {
+        match self.deferred_call_task.get() {
+            Some(DeferredCallTask::Get(_)) => Err(ErrorCode::ALREADY),
+            Some(DeferredCallTask::Set) => Err(ErrorCode::BUSY),
+            None => {
+                let seconds = self.read_time() / NANOSECONDS_PER_SECOND;
+                self.deferred_call_task
+                    .set(DeferredCallTask::Get(date_time_from_seconds(seconds)));
+                self.deferred_call.set();
+                Ok(())
+            }
+        }
+    }
+
+    fn set_date_time(&self, date_time: DateTimeValues) -> Result<(), ErrorCode> {
+        match self.deferred_call_task.get() {
+            Some(DeferredCallTask::Get(_)) => Err(ErrorCode::BUSY),
+            Some(DeferredCallTask::Set) => Err(ErrorCode::ALREADY),
+            None => {
+                let seconds = seconds_from_date_time(date_time)?;
+                self.write_time(seconds * NANOSECONDS_PER_SECOND);
+                self.deferred_call_task.set(DeferredCallTask::Set);
+                self.deferred_call.set();
+                Ok(())
+            }
+        }
+    }
+
+    fn set_client(&self, client: &'a dyn DateTimeClient) {
+        self.date_time_client.set(client);
+    }
+}
+
+impl DeferredCallClient for GoldfishRtc<'_> {
+    fn handle_deferred_call(&self) {
+        if let Some(task) = self.deferred_call_task.take() {
+            self.date_time_client.map(|client| match task {
+                DeferredCallTask::Get(result) => client.get_date_time_done(result),
+                DeferredCallTask::Set => client.set_date_time_done(Ok(())),
+            });
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{date_time_from_seconds, seconds_from_date_time};
+    use kernel::ErrorCode;
+    use kernel::hil::date_time::{DateTimeValues, DayOfWeek, Month};
+
+    const fn date_time(
+        year: u16,
+        month: Month,
+        day: u8,
+        day_of_week: DayOfWeek,
+        (hour, minute, seconds): (u8, u8, u8),
+    ) -> DateTimeValues {
+        DateTimeValues {
+            year,
+            month,
+            day,
+            day_of_week,
+            hour,
+            minute,
+            seconds,
+        }
+    }
+
+    #[test]
+    fn test_date_time_conversion() {
+        const TEST_VECTORS: [(u64, DateTimeValues); 6] = [
+            (
+                0,
+                date_time(1970, Month::January, 1, DayOfWeek::Thursday, (0, 0, 0)),
+            ),
+            (
+                951_782_400,
+                date_time(2000, Month::February, 29, DayOfWeek::Tuesday, (0, 0, 0)),
+            ),
+            (
+                951_868_799,
+                date_time(2000, Month::February, 29, DayOfWeek::Tuesday, (23, 59, 59)),
+            ),
+            (
+                1_234_567_890,
+                date_time(2009, Month::February, 13, DayOfWeek::Friday, (23, 31, 30)),
+            ),
+            (
+                4_107_542_400,
+                date_time(2100, Month::March, 1, DayOfWeek::Monday, (0, 0, 0)),
+            ),
+            (
+                1_790_812_800,
+                date_time(2026, Month::October, 1, DayOfWeek::Thursday, (0, 0, 0)),
+            ),
+        ];
+        for (seconds, expected) in &TEST_VECTORS {
+            assert_eq!(date_time_from_seconds(*seconds), Ok(*expected));
+            assert_eq!(seconds_from_date_time(*expected), Ok(*seconds));
+        }
+    }
+
+    #[test]
+    fn test_invalid_date_time() {
+        const TEST_VECTORS: [DateTimeValues; 5] = [
+            date_time(1969, Month::December, 31, DayOfWeek::Wednesday, (0, 0, 0)),
+            date_time(2100, Month::February, 29, DayOfWeek::Monday, (0, 0, 0)),
+            date_time(2026, Month::April, 31, DayOfWeek::Friday, (0, 0, 0)),
+            date_time(2026, Month::April, 0, DayOfWeek::Friday, (0, 0, 0)),
+            date_time(2026, Month::April, 1, DayOfWeek::Wednesday, (24, 0, 0)),
+        ];
+        for date_time in &TEST_VECTORS {
+            assert_eq!(seconds_from_date_time(*date_time), Err(ErrorCode::INVAL));
+        }
+    }
+}
@@ -7,4 +7,6 @@
 #![forbid(unsafe_code)]
 #![no_std]
 
+pub mod cfi_flash;
+pub mod goldfish_rtc;
 pub mod uart;
@@ -6,6 +6,7 @@
 
 use core::fmt::Write;
 use kernel::platform::chip::Chip;
+use kernel::platform::chip::FaultRegisters;
 use kernel::platform::chip::InterruptService;
 
 use crate::adc;
@@ -128,6 +129,10 @@ impl<I: InterruptService> Chip for Rp2040<'_, I> {
     unsafe fn print_state(_this: Option<&Self>, writer: &mut dyn Write) {
         CortexM0P::print_cortexm_state(writer);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        CortexM0P::fault_registers()
+    }
 }
 
 pub struct Rp2040DefaultPeripherals<'a> {
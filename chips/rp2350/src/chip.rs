@@ -6,6 +6,7 @@
 
 use core::fmt::Write;
 use kernel::platform::chip::Chip;
+use kernel::platform::chip::FaultRegisters;
 use kernel::platform::chip::InterruptService;
 
 use crate::clocks::Clocks;
@@ -114,6 +115,10 @@ impl<I: InterruptService> Chip for Rp2350<'_, I> {
     unsafe fn print_state(_this: Option<&Self>, writer: &mut dyn Write) {
         CortexM33::print_cortexm_state(writer);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        CortexM33::fault_registers()
+    }
 }
 
 pub struct Rp2350DefaultPeripherals<'a> {
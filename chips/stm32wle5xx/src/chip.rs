@@ -7,6 +7,7 @@
 use core::fmt::Write;
 use cortexm4::{CortexM4, CortexMVariant};
 use kernel::platform::chip::Chip;
+use kernel::platform::chip::FaultRegisters;
 use kernel::platform::chip::InterruptService;
 
 use crate::chip_specific::chip_specs::ChipSpecs as ChipSpecsTrait;
@@ -187,4 +188,8 @@ impl<'a, I: InterruptService + 'a> Chip for Stm32wle5xx<'a, I> {
     unsafe fn print_state(_this: Option<&Self>, write: &mut dyn Write) {
         CortexM4::print_cortexm_state(write);
     }
+
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        CortexM4::fault_registers()
+    }
 }
@@ -72,6 +72,11 @@ impl VirtioCap<'_> {
     fn offset(&self) -> u32 {
         self.0.read32(8)
     }
+
+    /// Returns the length in bytes of the configuration structure.
+    fn length(&self) -> u32 {
+        self.0.read32(12)
+    }
 }
 
 register_bitfields![
@@ -222,6 +227,9 @@ pub struct VirtIOPCIDevice {
     isr_cfg: &'static IsrStatusCfg,
     notify_base: usize,
     notify_off_multiplier: usize,
+    /// Address and length of the device-specific configuration structure, if
+    /// the device has one.
+    device_cfg: Option<(usize, usize)>,
     queues: OptionalCell<&'static [&'static dyn Virtqueue]>,
 }
 
@@ -232,6 +240,7 @@ impl VirtIOPCIDevice {
         let mut isr_cfg_ptr = ptr::null_mut::<IsrStatusCfg>();
         let mut notify_base = 0;
         let mut notify_off_multiplier = 0;
+        let mut device_cfg = None;
 
         // Iterate over Virtio capabilities
         for cap in dev.capabilities() {
@@ -266,6 +275,12 @@ impl VirtIOPCIDevice {
                     notify_off_multiplier = cap.0.read32(16) as usize;
                 }
 
+                CfgType::Device => {
+                    if device_cfg.is_none() {
+                        device_cfg = Some((addr, cap.length() as usize));
+                    }
+                }
+
                 _ => {}
             }
         }
@@ -288,6 +303,7 @@ impl VirtIOPCIDevice {
             isr_cfg,
             notify_base,
             notify_off_multiplier,
+            device_cfg,
             queues: OptionalCell::empty(),
         })
     }
@@ -514,4 +530,16 @@ impl VirtIOTransport for VirtIOPCIDevice {
             notify_ptr.write_volatile(queue_id as u16);
         }
     }
+
+    fn read_device_config(&self, offset: usize) -> Option<u32> {
+        let (base, length) = self.device_cfg?;
+        if !offset.is_multiple_of(4) || offset + 4 > length {
+            return None;
+        }
+
+        // Safety: The offset lies within the device configuration structure
+        // reported by the device, which we assume to be valid.
+        let ptr = (base + offset) as *const u32;
+        Some(unsafe { ptr.read_volatile() })
+    }
 }
@@ -4,6 +4,8 @@
 
 use kernel::ErrorCode;
 
+pub mod virtio_blk;
+pub mod virtio_console;
 pub mod virtio_gpu;
 pub mod virtio_input;
 pub mod virtio_net;
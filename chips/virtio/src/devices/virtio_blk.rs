@@ -0,0 +1,822 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! VirtIO block device driver.
+//!
+//! Exposes a virtio-blk disk both as [`NonvolatileStorage`], addressed in
+//! bytes, and as [`Flash`] with emulated pages of one 512 byte sector each.
+//! Erasing a page fills it with `0xFF`, like erased NOR flash.
+//!
+//! All requests are transferred one sector at a time through an internal
+//! sector buffer, so `NonvolatileStorage` accesses do not need to be aligned:
+//! partially written sectors are read, modified and written back. Only one
+//! operation, through either interface, can be in progress at a time.
+//!
+//! The driver does not negotiate `VIRTIO_BLK_F_FLUSH`, which makes the device
+//! write-through: a completed write is on the backing storage.
+//!
+//! A `NonvolatileStorage` request that passes the argument checks but cannot
+//! be submitted to the device is not reported through the return value, as
+//! that would lose the client's buffer. The request instead completes with a
+//! length of 0 from a deferred call.
+//!
+//! [`NonvolatileStorage`]: kernel::hil::nonvolatile_storage::NonvolatileStorage
+//! [`Flash`]: kernel::hil::flash::Flash
+
+use core::cell::Cell;
+
+use kernel::ErrorCode;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil;
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::platform::dma_fence::DmaFence;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{SubSliceMut, SubSliceMutImmut};
+
+use super::super::devices::{VirtIODeviceDriver, VirtIODeviceType};
+use super::super::queues::split_queue::{
+    SplitVirtqueue, SplitVirtqueueClient, VirtqueueBuffer, VirtqueueReturnBuffer,
+};
+use super::super::transports::VirtIOTransport;
+
+/// Size of a sector, the unit in which the device is addressed.
+pub const SECTOR_SIZE: usize = 512;
+
+/// Size of the request header buffer to pass to [`VirtIOBlk::new`].
+pub const REQUEST_HEADER_LEN: usize = 16;
+
+// Request types and status values, VirtIO 1.1 section 5.2.6
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+// Offset of the capacity (in sectors) in the device configuration space
+const CONFIG_CAPACITY: usize = 0;
+
+/// An emulated flash page, one sector of the disk.
+pub struct VirtIOBlkPage(pub [u8; SECTOR_SIZE]);
+
+impl Default for VirtIOBlkPage {
+    fn default() -> Self {
+        Self([0; SECTOR_SIZE])
+    }
+}
+
+impl AsMut<[u8]> for VirtIOBlkPage {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Operation {
+    Idle,
+    /// Reading `length` bytes at `address`, of which `done` have been read.
+    Read {
+        address: usize,
+        length: usize,
+        done: usize,
+    },
+    /// Writing `length` bytes at `address`, of which `done` have been written.
+    /// `merging` is set while reading a sector that is only partially
+    /// overwritten.
+    Write {
+        address: usize,
+        length: usize,
+        done: usize,
+        merging: bool,
+    },
+    FlashRead,
+    FlashWrite,
+    FlashErase,
+    /// A `NonvolatileStorage` request could not be submitted, its buffer is
+    /// returned from the deferred call.
+    Aborted {
+        write: bool,
+    },
+}
+
+pub struct VirtIOBlk<'a, F: DmaFence> {
+    queue: &'a SplitVirtqueue<'static, 'static, 3, F>,
+    transport: &'a dyn VirtIOTransport,
+    capacity_sectors: Cell<u64>,
+    header: TakeCell<'static, [u8]>,
+    sector: TakeCell<'static, [u8]>,
+    status: TakeCell<'static, [u8]>,
+    operation: Cell<Operation>,
+    /// Buffer of the `NonvolatileStorage` client.
+    buffer: TakeCell<'static, [u8]>,
+    /// Page of the `Flash` client.
+    page: TakeCell<'static, VirtIOBlkPage>,
+    storage_client: OptionalCell<&'a dyn NonvolatileStorageClient>,
+    flash_client: OptionalCell<&'a dyn hil::flash::Client<VirtIOBlk<'a, F>>>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, F: DmaFence> VirtIOBlk<'a, F> {
+    /// Create a driver for the device behind `transport`.
+    ///
+    /// `queue` is the request queue, which must hold 3 descriptors per
+    /// request. `header` must be [`REQUEST_HEADER_LEN`] bytes long, `sector`
+    /// [`SECTOR_SIZE`] bytes and `status` 1 byte, otherwise this returns
+    /// `Err(ErrorCode::SIZE)`.
+    pub fn new(
+        queue: &'a SplitVirtqueue<'static, 'static, 3, F>,
+        transport: &'a dyn VirtIOTransport,
+        header: &'static mut [u8],
+        sector: &'static mut [u8],
+        status: &'static mut [u8],
+    ) -> Result<VirtIOBlk<'a, F>, ErrorCode> {
+        if header.len() < REQUEST_HEADER_LEN || sector.len() < SECTOR_SIZE || status.is_empty() {
+            return Err(ErrorCode::SIZE);
+        }
+
+        queue.enable_used_callbacks();
+
+        Ok(VirtIOBlk {
+            queue,
+            transport,
+            capacity_sectors: Cell::new(0),
+            header: TakeCell::new(header),
+            sector: TakeCell::new(sector),
+            status: TakeCell::new(status),
+            operation: Cell::new(Operation::Idle),
+            buffer: TakeCell::empty(),
+            page: TakeCell::empty(),
+            storage_client: OptionalCell::empty(),
+            flash_client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        })
+    }
+
+    /// Size of the disk in bytes. Only valid once the device is initialized.
+    pub fn capacity(&self) -> usize {
+        (self.capacity_sectors.get() * SECTOR_SIZE as u64)
+            .try_into()
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Submit a request transferring the sector buffer from or to `sector`.
+    fn request(&self, request_type: u32, sector: u64) -> Result<(), ErrorCode> {
+        let (header, data, status) =
+            match (self.header.take(), self.sector.take(), self.status.take()) {
+                (Some(header), Some(data), Some(status)) => (header, data, status),
+                (header, data, status) => {
+                    // Keep the buffers we have for when the missing one is
+                    // back.
+                    header.map(|buffer| self.header.replace(buffer));
+                    data.map(|buffer| self.sector.replace(buffer));
+                    status.map(|buffer| self.status.replace(buffer));
+                    return Err(ErrorCode::BUSY);
+                }
+            };
+
+        header[0..4].copy_from_slice(&request_type.to_le_bytes());
+        header[4..8].copy_from_slice(&[0; 4]);
+        header[8..16].copy_from_slice(&sector.to_le_bytes());
+
+        let mut header_slice = SubSliceMut::new(header);
+        header_slice.slice(0..REQUEST_HEADER_LEN);
+        let mut data_slice = SubSliceMut::new(data);
+        data_slice.slice(0..SECTOR_SIZE);
+        let mut status_slice = SubSliceMut::new(status);
+        status_slice.slice(0..1);
+
+        let mut buffer_chain = [
+            Some(VirtqueueBuffer::DeviceReadable(SubSliceMutImmut::Mutable(
+                header_slice,
+            ))),
+            Some(if request_type == VIRTIO_BLK_T_OUT {
+                VirtqueueBuffer::DeviceReadable(SubSliceMutImmut::Mutable(data_slice))
+            } else {
+                VirtqueueBuffer::DeviceWriteable(data_slice)
+            }),
+            Some(VirtqueueBuffer::DeviceWriteable(status_slice)),
+        ];
+
+        self.queue
+            .provide_buffer_chain(&mut buffer_chain)
+            .inspect_err(|_| {
+                // The queue did not take the chain, take our buffers back.
+                self.restore_buffers(buffer_chain.iter_mut().map(|buffer| buffer.take()));
+            })
+    }
+
+    /// Return the header, sector and status buffers of a request to their
+    /// cells.
+    ///
+    /// A buffer missing from the chain leaves its cell empty, which makes
+    /// every later request fail with `BUSY` instead of panicking.
+    fn restore_buffers(&self, mut buffers: impl Iterator<Item = Option<VirtqueueBuffer<'static>>>) {
+        let mut restore = |cell: &TakeCell<'static, [u8]>| match buffers.next().flatten() {
+            Some(VirtqueueBuffer::DeviceReadable(SubSliceMutImmut::Mutable(buffer)))
+            | Some(VirtqueueBuffer::DeviceWriteable(buffer)) => {
+                cell.replace(buffer.take());
+            }
+            _ => {}
+        };
+        restore(&self.header);
+        restore(&self.sector);
+        restore(&self.status);
+    }
+
+    /// Complete a `NonvolatileStorage` request that could not be submitted
+    /// with a length of 0, handing the buffer back from a deferred call.
+    fn abort(&self, write: bool) {
+        self.operation.set(Operation::Aborted { write });
+        self.deferred_call.set();
+    }
+
+    /// The part of the sector buffer that the byte operation touches next:
+    /// the sector number, and the offset and length within the sector.
+    fn next_chunk(address: usize, length: usize, done: usize) -> (u64, usize, usize) {
+        let position = address + done;
+        let offset = position % SECTOR_SIZE;
+        let len = (SECTOR_SIZE - offset).min(length - done);
+        ((position / SECTOR_SIZE) as u64, offset, len)
+    }
+
+    fn check_range(&self, address: usize, length: usize) -> Result<(), ErrorCode> {
+        match address.checked_add(length) {
+            Some(end) if length > 0 && end <= self.capacity() => Ok(()),
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+
+    /// Start transferring the next sector of a byte write.
+    fn write_next(&self, address: usize, length: usize, done: usize) -> Result<(), ErrorCode> {
+        let (sector, _, len) = Self::next_chunk(address, length, done);
+        if len == SECTOR_SIZE {
+            // The whole sector is overwritten, no need to read it first.
+            self.buffer.map(|buffer| {
+                self.sector
+                    .map(|data| data[..SECTOR_SIZE].copy_from_slice(&buffer[done..done + len]))
+            });
+            self.operation.set(Operation::Write {
+                address,
+                length,
+                done,
+                merging: false,
+            });
+            self.request(VIRTIO_BLK_T_OUT, sector)
+        } else {
+            self.operation.set(Operation::Write {
+                address,
+                length,
+                done,
+                merging: true,
+            });
+            self.request(VIRTIO_BLK_T_IN, sector)
+        }
+    }
+
+    fn finish_read(&self, length: usize) {
+        self.operation.set(Operation::Idle);
+        if let Some(buffer) = self.buffer.take() {
+            self.storage_client
+                .map(move |client| client.read_done(buffer, length));
+        }
+    }
+
+    fn finish_write(&self, length: usize) {
+        self.operation.set(Operation::Idle);
+        if let Some(buffer) = self.buffer.take() {
+            self.storage_client
+                .map(move |client| client.write_done(buffer, length));
+        }
+    }
+
+    fn finish_flash(&self, result: Result<(), hil::flash::Error>) {
+        let operation = self.operation.replace(Operation::Idle);
+        if operation == Operation::FlashErase {
+            self.flash_client
+                .map(|client| client.erase_complete(result));
+            return;
+        }
+        let Some(page) = self.page.take() else {
+            return;
+        };
+        if operation == Operation::FlashRead && result.is_ok() {
+            self.sector
+                .map(|data| page.0.copy_from_slice(&data[..SECTOR_SIZE]));
+        }
+        self.flash_client.map(move |client| {
+            if operation == Operation::FlashRead {
+                client.read_complete(page, result)
+            } else {
+                client.write_complete(page, result)
+            }
+        });
+    }
+
+    /// Continue the current operation after a request completed.
+    fn request_done(&self, ok: bool) {
+        match self.operation.get() {
+            Operation::Idle | Operation::Aborted { .. } => {}
+            Operation::Read {
+                address,
+                length,
+                done,
+            } => {
+                if !ok {
+                    self.finish_read(done);
+                    return;
+                }
+                let (_, offset, len) = Self::next_chunk(address, length, done);
+                self.buffer.map(|buffer| {
+                    self.sector.map(|data| {
+                        buffer[done..done + len].copy_from_slice(&data[offset..offset + len])
+                    })
+                });
+                let done = done + len;
+                if done == length {
+                    self.finish_read(done);
+                    return;
+                }
+                let (sector, _, _) = Self::next_chunk(address, length, done);
+                self.operation.set(Operation::Read {
+                    address,
+                    length,
+                    done,
+                });
+                if self.request(VIRTIO_BLK_T_IN, sector).is_err() {
+                    self.finish_read(done);
+                }
+            }
+            Operation::Write {
+                address,
+                length,
+                done,
+                merging,
+            } => {
+                if !ok {
+                    self.finish_write(done);
+                    return;
+                }
+                let (sector, offset, len) = Self::next_chunk(address, length, done);
+                if merging {
+                    // The sector has been read, overwrite our part of it and
+                    // write it back.
+                    self.buffer.map(|buffer| {
+                        self.sector.map(|data| {
+                            data[offset..offset + len].copy_from_slice(&buffer[done..done + len])
+                        })
+                    });
+                    self.operation.set(Operation::Write {
+                        address,
+                        length,
+                        done,
+                        merging: false,
+                    });
+                    if self.request(VIRTIO_BLK_T_OUT, sector).is_err() {
+                        self.finish_write(done);
+                    }
+                    return;
+                }
+                let done = done + len;
+                if done == length || self.write_next(address, length, done).is_err() {
+                    self.finish_write(done);
+                }
+            }
+            Operation::FlashRead | Operation::FlashWrite | Operation::FlashErase => {
+                self.finish_flash(if ok {
+                    Ok(())
+                } else {
+                    Err(hil::flash::Error::FlashError)
+                });
+            }
+        }
+    }
+
+    fn flash_request(&self, operation: Operation, page_number: usize) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if page_number as u64 >= self.capacity_sectors.get() {
+            return Err(ErrorCode::INVAL);
+        }
+        let request_type = match operation {
+            Operation::FlashRead => VIRTIO_BLK_T_IN,
+            _ => VIRTIO_BLK_T_OUT,
+        };
+        self.operation.set(operation);
+        self.request(request_type, page_number as u64)
+            .inspect_err(|_| self.operation.set(Operation::Idle))
+    }
+}
+
+impl<F: DmaFence> SplitVirtqueueClient<'static> for VirtIOBlk<'_, F> {
+    fn buffer_chain_ready(
+        &self,
+        _queue_number: u32,
+        buffer_chain: &mut [Option<VirtqueueReturnBuffer<'static>>],
+        _bytes_used: usize,
+    ) {
+        self.restore_buffers(
+            buffer_chain
+                .iter_mut()
+                .map(|buffer| buffer.take().map(|buffer| buffer.virtqueue_buffer)),
+        );
+        let ok = self
+            .status
+            .map_or(false, |status| status[0] == VIRTIO_BLK_S_OK);
+        self.request_done(ok);
+    }
+}
+
+impl<F: DmaFence> VirtIODeviceDriver for VirtIOBlk<'_, F> {
+    fn negotiate_features(&self, _offered_features: u64) -> Option<u64> {
+        // None of the optional features are required. In particular, not
+        // negotiating VIRTIO_BLK_F_FLUSH keeps the device write-through.
+        Some(0)
+    }
+
+    fn device_type(&self) -> VirtIODeviceType {
+        VirtIODeviceType::BlockDevice
+    }
+
+    fn pre_device_initialization(&self) -> Result<(), ErrorCode> {
+        // The capacity is a 64-bit field. Disks are not resized at runtime,
+        // so reading its two halves one after the other is consistent.
+        let low = self
+            .transport
+            .read_device_config(CONFIG_CAPACITY)
+            .ok_or(ErrorCode::NOSUPPORT)?;
+        let high = self
+            .transport
+            .read_device_config(CONFIG_CAPACITY + 4)
+            .ok_or(ErrorCode::NOSUPPORT)?;
+        self.capacity_sectors
+            .set(((high as u64) << 32) | low as u64);
+        Ok(())
+    }
+}
+
+impl<'a, F: DmaFence> NonvolatileStorage<'a> for VirtIOBlk<'a, F> {
+    fn set_client(&self, client: &'a dyn NonvolatileStorageClient) {
+        self.storage_client.set(client);
+    }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if length > buffer.len() {
+            return Err(ErrorCode::SIZE);
+        }
+        self.check_range(address, length)?;
+
+        let (sector, _, _) = Self::next_chunk(address, length, 0);
+        self.buffer.replace(buffer);
+        self.operation.set(Operation::Read {
+            address,
+            length,
+            done: 0,
+        });
+        if self.request(VIRTIO_BLK_T_IN, sector).is_err() {
+            self.abort(false);
+        }
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if length > buffer.len() {
+            return Err(ErrorCode::SIZE);
+        }
+        self.check_range(address, length)?;
+
+        self.buffer.replace(buffer);
+        if self.write_next(address, length, 0).is_err() {
+            self.abort(true);
+        }
+        Ok(())
+    }
+}
+
+impl<F: DmaFence> DeferredCallClient for VirtIOBlk<'_, F> {
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+
+    fn handle_deferred_call(&self) {
+        if let Operation::Aborted { write } = self.operation.get() {
+            if write {
+                self.finish_write(0);
+            } else {
+                self.finish_read(0);
+            }
+        }
+    }
+}
+
+impl<'a, F: DmaFence, C: hil::flash::Client<Self>> hil::flash::HasClient<'a, C>
+    for VirtIOBlk<'a, F>
+{
+    fn set_client(&'a self, client: &'a C) {
+        self.flash_client.set(client);
+    }
+}
+
+impl<F: DmaFence> hil::flash::Flash for VirtIOBlk<'_, F> {
+    type Page = VirtIOBlkPage;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        match self.flash_request(Operation::FlashRead, page_number) {
+            Ok(()) => {
+                self.page.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if self.operation.get() != Operation::Idle {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        self.sector
+            .map(|data| data[..SECTOR_SIZE].copy_from_slice(&buf.0));
+        match self.flash_request(Operation::FlashWrite, page_number) {
+            Ok(()) => {
+                self.page.replace(buf);
+                Ok(())
+            }
+            Err(e) => Err((e, buf)),
+        }
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.sector.map(|data| data[..SECTOR_SIZE].fill(0xFF));
+        self.flash_request(Operation::FlashErase, page_number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::queues::split_queue::sim::{self, HostFence};
+    use capsules_test_harness::{deferred_call, leak, run_until_idle, static_buf};
+    use core::cell::RefCell;
+    use kernel::deferred_call::DeferredCallClient;
+    use std::vec::Vec;
+
+    const NUM_SECTORS: usize = 8;
+
+    type TestBlk = VirtIOBlk<'static, HostFence>;
+
+    /// The simulated disk, which serves requests from the request queue.
+    struct Disk {
+        device: sim::Device<3>,
+        data: RefCell<Vec<u8>>,
+        /// Type and sector of every request served so far.
+        requests: RefCell<Vec<(u32, u64)>>,
+        /// Fail the request for this sector.
+        bad_sector: Cell<Option<u64>>,
+    }
+
+    impl Disk {
+        /// Serve requests until the driver has none left.
+        fn serve(&self) {
+            while let Some(chain) = self.device.pop() {
+                let [header, data, status] = &chain.buffers[..] else {
+                    panic!("request without 3 buffers");
+                };
+                let header = header.read();
+                let request_type = u32::from_le_bytes(header[0..4].try_into().unwrap());
+                let sector = u64::from_le_bytes(header[8..16].try_into().unwrap());
+                self.requests.borrow_mut().push((request_type, sector));
+
+                let range = sector as usize * SECTOR_SIZE..(sector as usize + 1) * SECTOR_SIZE;
+                let ok = self.bad_sector.get() != Some(sector);
+                let mut written = 1;
+                if ok && request_type == VIRTIO_BLK_T_IN {
+                    data.write(&self.data.borrow()[range]);
+                    written += SECTOR_SIZE;
+                } else if ok {
+                    self.data.borrow_mut()[range].copy_from_slice(&data.read());
+                }
+                status.write(&[if ok { VIRTIO_BLK_S_OK } else { 1 }]);
+                self.device.complete(chain, written);
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Client {
+        /// Whether the last completed operation was a write, and its length.
+        done: Cell<Option<(bool, usize)>>,
+        buffer: RefCell<Option<&'static mut [u8]>>,
+    }
+
+    impl NonvolatileStorageClient for Client {
+        fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+            self.done.set(Some((false, length)));
+            *self.buffer.borrow_mut() = Some(buffer);
+        }
+
+        fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+            self.done.set(Some((true, length)));
+            *self.buffer.borrow_mut() = Some(buffer);
+        }
+    }
+
+    /// A disk of `NUM_SECTORS` sectors whose bytes count up from 0.
+    fn blk() -> (&'static TestBlk, &'static Disk, &'static Client) {
+        let transport = sim::Transport::new(0, std::vec![NUM_SECTORS as u32, 0]);
+        let queue = sim::queue::<3>(transport);
+        let blk = leak(
+            VirtIOBlk::new(
+                queue,
+                transport,
+                static_buf(REQUEST_HEADER_LEN),
+                static_buf(SECTOR_SIZE),
+                static_buf(1),
+            )
+            .unwrap(),
+        );
+        queue.set_client(blk);
+        blk.register();
+        assert!(matches!(
+            transport.initialize(blk, leak([queue as &dyn crate::queues::Virtqueue])),
+            Ok(VirtIODeviceType::BlockDevice)
+        ));
+
+        let disk = leak(Disk {
+            device: sim::Device::new(queue),
+            data: RefCell::new((0..NUM_SECTORS * SECTOR_SIZE).map(|i| i as u8).collect()),
+            requests: RefCell::new(Vec::new()),
+            bad_sector: Cell::new(None),
+        });
+        let client = leak(Client::default());
+        NonvolatileStorage::set_client(blk, client);
+        (blk, disk, client)
+    }
+
+    fn pattern(address: usize, length: usize) -> Vec<u8> {
+        (address..address + length).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn capacity_is_read_from_the_configuration() {
+        deferred_call::run(|| {
+            let (blk, _, _) = blk();
+            assert_eq!(blk.capacity(), NUM_SECTORS * SECTOR_SIZE);
+        });
+    }
+
+    #[test]
+    fn unaligned_read_spans_sectors() {
+        deferred_call::run(|| {
+            let (blk, disk, client) = blk();
+
+            assert_eq!(blk.read(static_buf(600), 500, 600), Ok(()));
+            disk.serve();
+            assert_eq!(client.done.get(), Some((false, 600)));
+            assert_eq!(
+                client.buffer.borrow().as_deref(),
+                Some(&pattern(500, 600)[..])
+            );
+            assert_eq!(
+                *disk.requests.borrow(),
+                [
+                    (VIRTIO_BLK_T_IN, 0),
+                    (VIRTIO_BLK_T_IN, 1),
+                    (VIRTIO_BLK_T_IN, 2)
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn unaligned_write_merges_partial_sectors() {
+        deferred_call::run(|| {
+            let (blk, disk, client) = blk();
+            let buffer = static_buf(600);
+            buffer.fill(0xA5);
+
+            assert_eq!(blk.write(buffer, 500, 600), Ok(()));
+            disk.serve();
+            assert_eq!(client.done.get(), Some((true, 600)));
+
+            // The partially written first and last sectors are read first,
+            // the middle one is overwritten as a whole.
+            assert_eq!(
+                *disk.requests.borrow(),
+                [
+                    (VIRTIO_BLK_T_IN, 0),
+                    (VIRTIO_BLK_T_OUT, 0),
+                    (VIRTIO_BLK_T_OUT, 1),
+                    (VIRTIO_BLK_T_IN, 2),
+                    (VIRTIO_BLK_T_OUT, 2),
+                ]
+            );
+            let data = disk.data.borrow();
+            assert_eq!(data[..500], pattern(0, 500)[..]);
+            assert!(data[500..1100].iter().all(|byte| *byte == 0xA5));
+            assert_eq!(
+                data[1100..],
+                pattern(1100, NUM_SECTORS * SECTOR_SIZE - 1100)[..]
+            );
+        });
+    }
+
+    #[test]
+    fn failed_request_ends_the_transfer() {
+        deferred_call::run(|| {
+            let (blk, disk, client) = blk();
+            disk.bad_sector.set(Some(1));
+
+            // Only the part of the first sector is read.
+            assert_eq!(blk.read(static_buf(600), 500, 600), Ok(()));
+            disk.serve();
+            assert_eq!(client.done.get(), Some((false, 12)));
+
+            // Another operation can start.
+            let buffer = client.buffer.take().unwrap();
+            assert_eq!(blk.write(buffer, 0, SECTOR_SIZE), Ok(()));
+            disk.serve();
+            assert_eq!(client.done.get(), Some((true, SECTOR_SIZE)));
+        });
+    }
+
+    #[test]
+    fn invalid_requests_are_rejected() {
+        deferred_call::run(|| {
+            let (blk, disk, _) = blk();
+            let capacity = NUM_SECTORS * SECTOR_SIZE;
+
+            assert_eq!(
+                blk.read(static_buf(SECTOR_SIZE), capacity - 1, 2),
+                Err(ErrorCode::INVAL)
+            );
+            assert_eq!(
+                blk.read(static_buf(SECTOR_SIZE), 0, 0),
+                Err(ErrorCode::INVAL)
+            );
+            assert_eq!(
+                blk.write(static_buf(SECTOR_SIZE), usize::MAX, 1),
+                Err(ErrorCode::INVAL)
+            );
+            assert_eq!(
+                blk.write(static_buf(10), 0, SECTOR_SIZE),
+                Err(ErrorCode::SIZE)
+            );
+
+            assert_eq!(blk.read(static_buf(SECTOR_SIZE), 0, SECTOR_SIZE), Ok(()));
+            assert_eq!(
+                blk.write(static_buf(SECTOR_SIZE), 0, SECTOR_SIZE),
+                Err(ErrorCode::BUSY)
+            );
+            disk.serve();
+            assert_eq!(disk.requests.borrow().len(), 1);
+        });
+    }
+
+    #[test]
+    fn unsubmitted_request_completes_from_a_deferred_call() {
+        deferred_call::run(|| {
+            let (blk, disk, client) = blk();
+            // Without the header buffer no request can be submitted.
+            let header = blk.header.take().unwrap();
+
+            assert_eq!(blk.write(static_buf(SECTOR_SIZE), 0, SECTOR_SIZE), Ok(()));
+            assert_eq!(client.done.get(), None);
+            run_until_idle(&[]);
+            assert_eq!(client.done.get(), Some((true, 0)));
+            assert!(disk.device.pop().is_none());
+
+            blk.header.replace(header);
+            let buffer = client.buffer.take().unwrap();
+            assert_eq!(blk.read(buffer, 0, SECTOR_SIZE), Ok(()));
+            disk.serve();
+            assert_eq!(client.done.get(), Some((false, SECTOR_SIZE)));
+        });
+    }
+}
@@ -0,0 +1,775 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Support for the VirtIO Console Device
+//!
+//! <https://docs.oasis-open.org/virtio/virtio/v1.2/csd01/virtio-v1.2-csd01.html#x1-2900003>
+//!
+//! Each port of the console device is exposed as a [`VirtIOConsolePort`],
+//! which implements the UART HIL and can thus be used with
+//! `virtual_uart::MuxUart` just like a hardware UART. The [`VirtIOConsole`]
+//! drives the device itself and, if more than one port is used, performs the
+//! multiport control handshake with the device.
+//!
+//! The device uses the following virtqueues, which must be passed to the
+//! transport in this order:
+//!
+//! - port 0 receive queue, port 0 transmit queue,
+//! - control receive queue, control transmit queue (only with more than one
+//!   port),
+//! - port 1 receive queue, port 1 transmit queue, and so on.
+//!
+//! Data received while no receive operation is outstanding on a port is held
+//! back by the device, so no input is lost. Data transmitted on a port which
+//! no host-side backend is connected to is discarded by the device.
+
+use core::cell::Cell;
+
+use kernel::ErrorCode;
+use kernel::hil::uart;
+use kernel::platform::dma_fence::DmaFence;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{SubSliceMut, SubSliceMutImmut};
+use kernel::utilities::registers::{LocalRegisterCopy, register_bitfields};
+
+use crate::devices::{VirtIODeviceDriver, VirtIODeviceType};
+use crate::queues::split_queue::{
+    SplitVirtqueue, SplitVirtqueueClient, VirtqueueBuffer, VirtqueueReturnBuffer,
+};
+
+register_bitfields![u64,
+    VirtIOConsoleFeatures [
+        VirtIOConsoleFSize OFFSET(0) NUMBITS(1),
+        VirtIOConsoleFMultiport OFFSET(1) NUMBITS(1),
+        VirtIOConsoleFEmergWrite OFFSET(2) NUMBITS(1),
+    ]
+];
+
+/// Length of a control message: a 32-bit port id, a 16-bit event and a
+/// 16-bit value.
+pub const CONTROL_MESSAGE_LEN: usize = 8;
+
+/// Number of buffers for control messages from the device.
+///
+/// QEMU drops control messages if no buffer is available, and announces all
+/// ports at once after the driver reports that it is ready. This is thus also
+/// the maximum number of ports that can be announced reliably.
+pub const CONTROL_RECEIVE_BUFFERS: usize = 4;
+
+/// Control message events.
+const DEVICE_READY: u16 = 0;
+const DEVICE_ADD: u16 = 1;
+const DEVICE_REMOVE: u16 = 2;
+const PORT_READY: u16 = 3;
+const PORT_OPEN: u16 = 6;
+
+/// Control message the driver still has to send for a port.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PendingControl {
+    None,
+    /// Acknowledge that the port was added.
+    PortReady,
+    /// Tell the device that the port is open for input.
+    PortOpen,
+}
+
+/// A single port of a VirtIO console, usable as a UART.
+pub struct VirtIOConsolePort<'a, F: DmaFence> {
+    receiveq: &'a SplitVirtqueue<'static, 'static, 1, F>,
+    transmitq: &'a SplitVirtqueue<'static, 'static, 1, F>,
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+    rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
+    tx_len: Cell<usize>,
+    transmitting: Cell<bool>,
+    /// Number of bytes requested by the outstanding receive operation, 0 if
+    /// there is none.
+    rx_len: Cell<usize>,
+    rx_position: Cell<usize>,
+    rx_aborting: Cell<bool>,
+    pending_control: Cell<PendingControl>,
+}
+
+impl<'a, F: DmaFence> VirtIOConsolePort<'a, F> {
+    pub fn new(
+        receiveq: &'a SplitVirtqueue<'static, 'static, 1, F>,
+        transmitq: &'a SplitVirtqueue<'static, 'static, 1, F>,
+    ) -> Self {
+        receiveq.enable_used_callbacks();
+        transmitq.enable_used_callbacks();
+
+        Self {
+            receiveq,
+            transmitq,
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            tx_len: Cell::new(0),
+            transmitting: Cell::new(false),
+            rx_len: Cell::new(0),
+            rx_position: Cell::new(0),
+            rx_aborting: Cell::new(false),
+            pending_control: Cell::new(PendingControl::None),
+        }
+    }
+
+    fn received(&self, mut rx_buffer: SubSliceMut<'static, u8>, bytes_used: usize) {
+        let position = self.rx_position.get() + bytes_used;
+        self.rx_position.set(position);
+
+        if position < self.rx_len.get() && !self.rx_aborting.get() {
+            // The device hands over whatever input it has, wait for the rest
+            // of the requested bytes.
+            rx_buffer.slice(bytes_used..);
+            let mut buffer_chain = [Some(VirtqueueBuffer::DeviceWriteable(rx_buffer))];
+            if self
+                .receiveq
+                .provide_buffer_chain(&mut buffer_chain)
+                .is_ok()
+            {
+                return;
+            }
+            let Some(VirtqueueBuffer::DeviceWriteable(returned)) = buffer_chain[0].take() else {
+                panic!("VirtQueue returned DeviceReadable buffer")
+            };
+            rx_buffer = returned;
+        }
+
+        let rval = if position < self.rx_len.get() {
+            Err(ErrorCode::CANCEL)
+        } else {
+            Ok(())
+        };
+        self.rx_len.set(0);
+        self.rx_aborting.set(false);
+
+        let rx_buffer = rx_buffer.take();
+        self.rx_client.map(move |client| {
+            client.received_buffer(rx_buffer, position, rval, uart::Error::None)
+        });
+    }
+}
+
+impl<F: DmaFence> SplitVirtqueueClient<'static> for VirtIOConsolePort<'_, F> {
+    fn buffer_chain_ready(
+        &self,
+        queue_number: u32,
+        buffer_chain: &mut [Option<VirtqueueReturnBuffer<'static>>],
+        bytes_used: usize,
+    ) {
+        if Some(queue_number) == self.receiveq.queue_number() {
+            let VirtqueueBuffer::DeviceWriteable(rx_buffer) = buffer_chain[0]
+                .take()
+                .expect("No receive buffer")
+                .virtqueue_buffer
+            else {
+                panic!("VirtQueue returned DeviceReadable buffer")
+            };
+            self.received(rx_buffer, bytes_used);
+        } else if Some(queue_number) == self.transmitq.queue_number() {
+            let VirtqueueBuffer::DeviceReadable(SubSliceMutImmut::Mutable(tx_buffer)) =
+                buffer_chain[0]
+                    .take()
+                    .expect("No transmit buffer")
+                    .virtqueue_buffer
+            else {
+                panic!("VirtQueue returned DeviceWriteable or immutable buffer")
+            };
+            self.transmitting.set(false);
+
+            let tx_buffer = tx_buffer.take();
+            self.tx_client
+                .map(move |client| client.transmitted_buffer(tx_buffer, self.tx_len.get(), Ok(())));
+        } else {
+            panic!("Callback from unknown queue");
+        }
+    }
+}
+
+impl<F: DmaFence> uart::Configure for VirtIOConsolePort<'_, F> {
+    fn configure(&self, _params: uart::Parameters) -> Result<(), ErrorCode> {
+        // There is no physical line, so any parameters are fine.
+        Ok(())
+    }
+}
+
+impl<'a, F: DmaFence> uart::Transmit<'a> for VirtIOConsolePort<'a, F> {
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.transmitting.get() {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+        if tx_len == 0 || tx_len > tx_buffer.len() {
+            return Err((ErrorCode::SIZE, tx_buffer));
+        }
+        if self.transmitq.queue_number().is_none() {
+            return Err((ErrorCode::OFF, tx_buffer));
+        }
+
+        let mut tx_sub_slice = SubSliceMut::new(tx_buffer);
+        tx_sub_slice.slice(0..tx_len);
+        let mut buffer_chain = [Some(VirtqueueBuffer::DeviceReadable(
+            SubSliceMutImmut::Mutable(tx_sub_slice),
+        ))];
+
+        self.transmitq
+            .provide_buffer_chain(&mut buffer_chain)
+            .map_err(|err| {
+                let Some(VirtqueueBuffer::DeviceReadable(SubSliceMutImmut::Mutable(tx_sub_slice))) =
+                    buffer_chain[0].take()
+                else {
+                    panic!("VirtQueue returned DeviceWriteable or immutable buffer")
+                };
+                (err, tx_sub_slice.take())
+            })?;
+
+        self.tx_len.set(tx_len);
+        self.transmitting.set(true);
+        Ok(())
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        if self.transmitting.get() {
+            // Buffers cannot be taken back from the device, the transmission
+            // completes with a callback.
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a, F: DmaFence> uart::Receive<'a> for VirtIOConsolePort<'a, F> {
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.rx_len.get() != 0 {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+        if rx_len == 0 || rx_len > rx_buffer.len() {
+            return Err((ErrorCode::SIZE, rx_buffer));
+        }
+        if self.receiveq.queue_number().is_none() {
+            return Err((ErrorCode::OFF, rx_buffer));
+        }
+
+        let mut rx_sub_slice = SubSliceMut::new(rx_buffer);
+        rx_sub_slice.slice(0..rx_len);
+        let mut buffer_chain = [Some(VirtqueueBuffer::DeviceWriteable(rx_sub_slice))];
+
+        self.receiveq
+            .provide_buffer_chain(&mut buffer_chain)
+            .map_err(|err| {
+                let Some(VirtqueueBuffer::DeviceWriteable(rx_sub_slice)) = buffer_chain[0].take()
+                else {
+                    panic!("VirtQueue returned DeviceReadable buffer")
+                };
+                (err, rx_sub_slice.take())
+            })?;
+
+        self.rx_len.set(rx_len);
+        self.rx_position.set(0);
+        self.rx_aborting.set(false);
+        Ok(())
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        if self.rx_len.get() == 0 {
+            return Ok(());
+        }
+        // Buffers cannot be taken back from the device. The receive ends with
+        // `Err(CANCEL)` as soon as the device returns any input.
+        self.rx_aborting.set(true);
+        Err(ErrorCode::FAIL)
+    }
+}
+
+/// Driver for a VirtIO console device with one or more ports.
+pub struct VirtIOConsole<'a, F: DmaFence> {
+    ports: &'a [&'a VirtIOConsolePort<'a, F>],
+    control_receiveq: &'a SplitVirtqueue<'static, 'static, CONTROL_RECEIVE_BUFFERS, F>,
+    control_transmitq: &'a SplitVirtqueue<'static, 'static, 1, F>,
+    control_receive_buffers:
+        TakeCell<'static, [[u8; CONTROL_MESSAGE_LEN]; CONTROL_RECEIVE_BUFFERS]>,
+    control_transmit_buffer: OptionalCell<&'static mut [u8]>,
+    device_ready_pending: Cell<bool>,
+}
+
+impl<'a, F: DmaFence> VirtIOConsole<'a, F> {
+    pub fn new(
+        ports: &'a [&'a VirtIOConsolePort<'a, F>],
+        control_receiveq: &'a SplitVirtqueue<'static, 'static, CONTROL_RECEIVE_BUFFERS, F>,
+        control_transmitq: &'a SplitVirtqueue<'static, 'static, 1, F>,
+        control_receive_buffers: &'static mut [[u8; CONTROL_MESSAGE_LEN]; CONTROL_RECEIVE_BUFFERS],
+        control_transmit_buffer: &'static mut [u8; CONTROL_MESSAGE_LEN],
+    ) -> Self {
+        control_receiveq.enable_used_callbacks();
+        control_transmitq.enable_used_callbacks();
+
+        Self {
+            ports,
+            control_receiveq,
+            control_transmitq,
+            control_receive_buffers: TakeCell::new(control_receive_buffers),
+            control_transmit_buffer: OptionalCell::new(control_transmit_buffer),
+            device_ready_pending: Cell::new(false),
+        }
+    }
+
+    /// Whether the driver uses the multiport feature and control queues.
+    fn multiport(&self) -> bool {
+        self.ports.len() > 1
+    }
+
+    /// Send the next pending control message, unless one is in flight.
+    fn send_next_control_message(&self) {
+        let Some(buffer) = self.control_transmit_buffer.take() else {
+            return;
+        };
+
+        let message = if self.device_ready_pending.take() {
+            Some((0, DEVICE_READY))
+        } else {
+            self.ports
+                .iter()
+                .enumerate()
+                .find_map(|(id, port)| match port.pending_control.get() {
+                    PendingControl::None => None,
+                    PendingControl::PortReady => {
+                        port.pending_control.set(PendingControl::PortOpen);
+                        Some((id as u32, PORT_READY))
+                    }
+                    PendingControl::PortOpen => {
+                        port.pending_control.set(PendingControl::None);
+                        Some((id as u32, PORT_OPEN))
+                    }
+                })
+        };
+        let Some((id, event)) = message else {
+            self.control_transmit_buffer.replace(buffer);
+            return;
+        };
+
+        buffer[0..4].copy_from_slice(&id.to_le_bytes());
+        buffer[4..6].copy_from_slice(&event.to_le_bytes());
+        buffer[6..8].copy_from_slice(&1u16.to_le_bytes());
+
+        let mut buffer_chain = [Some(VirtqueueBuffer::DeviceReadable(
+            SubSliceMutImmut::Mutable(SubSliceMut::new(buffer)),
+        ))];
+        self.control_transmitq
+            .provide_buffer_chain(&mut buffer_chain)
+            .unwrap();
+    }
+
+    fn handle_control_message(&self, message: &[u8]) {
+        let (Some(id), Some(event)) = (
+            message.get(0..4).and_then(|b| b.try_into().ok()),
+            message.get(4..6).and_then(|b| b.try_into().ok()),
+        ) else {
+            return;
+        };
+        let port = self.ports.get(u32::from_le_bytes(id) as usize);
+
+        match u16::from_le_bytes(event) {
+            DEVICE_ADD => {
+                // Ports the board did not set up are never acknowledged and
+                // stay unused.
+                if let Some(port) = port {
+                    port.pending_control.set(PendingControl::PortReady);
+                    self.send_next_control_message();
+                }
+            }
+            DEVICE_REMOVE => {
+                if let Some(port) = port {
+                    port.pending_control.set(PendingControl::None);
+                }
+            }
+            // The remaining events inform about the host side of a port, its
+            // name, or its terminal size, none of which matter for a UART.
+            _ => (),
+        }
+    }
+}
+
+impl<F: DmaFence> SplitVirtqueueClient<'static> for VirtIOConsole<'_, F> {
+    fn buffer_chain_ready(
+        &self,
+        queue_number: u32,
+        buffer_chain: &mut [Option<VirtqueueReturnBuffer<'static>>],
+        bytes_used: usize,
+    ) {
+        if Some(queue_number) == self.control_receiveq.queue_number() {
+            let VirtqueueBuffer::DeviceWriteable(message) = buffer_chain[0]
+                .take()
+                .expect("No control message buffer")
+                .virtqueue_buffer
+            else {
+                panic!("VirtQueue returned DeviceReadable buffer")
+            };
+            let message = message.take();
+            self.handle_control_message(&message[..bytes_used.min(message.len())]);
+
+            self.control_receiveq
+                .provide_buffer_chain(&mut [Some(VirtqueueBuffer::DeviceWriteable(
+                    SubSliceMut::new(message),
+                ))])
+                .unwrap();
+        } else if Some(queue_number) == self.control_transmitq.queue_number() {
+            let VirtqueueBuffer::DeviceReadable(SubSliceMutImmut::Mutable(buffer)) = buffer_chain
+                [0]
+            .take()
+            .expect("No control message buffer")
+            .virtqueue_buffer
+            else {
+                panic!("VirtQueue returned DeviceWriteable or immutable buffer")
+            };
+            self.control_transmit_buffer.replace(buffer.take());
+            self.send_next_control_message();
+        } else {
+            panic!("Callback from unknown queue");
+        }
+    }
+}
+
+impl<F: DmaFence> VirtIODeviceDriver for VirtIOConsole<'_, F> {
+    fn negotiate_features(&self, offered_features: u64) -> Option<u64> {
+        let offered_features =
+            LocalRegisterCopy::<u64, VirtIOConsoleFeatures::Register>::new(offered_features);
+        let mut negotiated_features =
+            LocalRegisterCopy::<u64, VirtIOConsoleFeatures::Register>::new(0);
+
+        if self.multiport() {
+            // Ports other than port 0 only exist with VIRTIO_CONSOLE_F_MULTIPORT.
+            if !offered_features.is_set(VirtIOConsoleFeatures::VirtIOConsoleFMultiport) {
+                return None;
+            }
+            negotiated_features.modify(VirtIOConsoleFeatures::VirtIOConsoleFMultiport::SET);
+        }
+
+        Some(negotiated_features.get())
+    }
+
+    fn device_type(&self) -> VirtIODeviceType {
+        VirtIODeviceType::Console
+    }
+
+    fn device_initialized(&self) -> Result<(), ErrorCode> {
+        if !self.multiport() {
+            return Ok(());
+        }
+
+        // The device announces its ports through control messages once the
+        // driver is ready, so provide buffers for them first.
+        let buffers = self
+            .control_receive_buffers
+            .take()
+            .ok_or(ErrorCode::ALREADY)?;
+        for buffer in buffers.iter_mut() {
+            self.control_receiveq.provide_buffer_chain(&mut [Some(
+                VirtqueueBuffer::DeviceWriteable(SubSliceMut::new(buffer)),
+            )])?;
+        }
+
+        self.device_ready_pending.set(true);
+        self.send_next_control_message();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::queues::Virtqueue;
+    use crate::queues::split_queue::sim::{self, HostFence};
+    use crate::transports::{VirtIOInitializationError, VirtIOTransport};
+    use capsules_test_harness::{leak, static_buf};
+    use core::cell::RefCell;
+    use kernel::hil::uart::{Receive, Transmit};
+    use std::vec::Vec;
+
+    const MULTIPORT: u64 = 1 << 1;
+
+    type Port = VirtIOConsolePort<'static, HostFence>;
+
+    #[derive(Default)]
+    struct Client {
+        transmitted: Cell<Option<usize>>,
+        received: RefCell<Option<(Vec<u8>, Result<(), ErrorCode>)>>,
+    }
+
+    impl uart::TransmitClient for Client {
+        fn transmitted_buffer(
+            &self,
+            _tx_buffer: &'static mut [u8],
+            tx_len: usize,
+            rval: Result<(), ErrorCode>,
+        ) {
+            assert_eq!(rval, Ok(()));
+            self.transmitted.set(Some(tx_len));
+        }
+    }
+
+    impl uart::ReceiveClient for Client {
+        fn received_buffer(
+            &self,
+            rx_buffer: &'static mut [u8],
+            rx_len: usize,
+            rval: Result<(), ErrorCode>,
+            _error: uart::Error,
+        ) {
+            *self.received.borrow_mut() = Some((rx_buffer[..rx_len].to_vec(), rval));
+        }
+    }
+
+    /// A port with its receive and transmit device ends.
+    struct TestPort {
+        port: &'static Port,
+        rx: sim::Device<1>,
+        tx: sim::Device<1>,
+        client: &'static Client,
+    }
+
+    fn port(transport: &'static sim::Transport) -> TestPort {
+        let receiveq = sim::queue::<1>(transport);
+        let transmitq = sim::queue::<1>(transport);
+        let port = leak(VirtIOConsolePort::new(receiveq, transmitq));
+        receiveq.set_client(port);
+        transmitq.set_client(port);
+        let client = leak(Client::default());
+        port.set_transmit_client(client);
+        port.set_receive_client(client);
+        TestPort {
+            port,
+            rx: sim::Device::new(receiveq),
+            tx: sim::Device::new(transmitq),
+            client,
+        }
+    }
+
+    /// A console with a single port.
+    fn console() -> TestPort {
+        let transport = sim::Transport::new(0, Vec::new());
+        let port = port(transport);
+        let console = leak(VirtIOConsole::new(
+            leak([port.port]),
+            sim::queue::<CONTROL_RECEIVE_BUFFERS>(transport),
+            sim::queue::<1>(transport),
+            leak_mut([[0; CONTROL_MESSAGE_LEN]; CONTROL_RECEIVE_BUFFERS]),
+            leak_mut([0; CONTROL_MESSAGE_LEN]),
+        ));
+        let queues: [&dyn Virtqueue; 2] = [port.port.receiveq, port.port.transmitq];
+        assert!(matches!(
+            transport.initialize(console, leak(queues)),
+            Ok(VirtIODeviceType::Console)
+        ));
+        port
+    }
+
+    /// The control queue device ends of a multiport console.
+    struct Control {
+        rx: sim::Device<CONTROL_RECEIVE_BUFFERS>,
+        tx: sim::Device<1>,
+    }
+
+    impl Control {
+        /// Send a control message to the driver.
+        fn send(&self, id: u32, event: u16) {
+            let chain = self.rx.pop().expect("no control receive buffer");
+            let mut message = Vec::new();
+            message.extend_from_slice(&id.to_le_bytes());
+            message.extend_from_slice(&event.to_le_bytes());
+            message.extend_from_slice(&1u16.to_le_bytes());
+            chain.buffers[0].write(&message);
+            self.rx.complete(chain, message.len());
+        }
+
+        /// Take the next control message from the driver as port id and
+        /// event.
+        fn receive(&self) -> Option<(u32, u16)> {
+            let chain = self.tx.pop()?;
+            let message = chain.buffers[0].read();
+            self.tx.complete(chain, 0);
+            Some((
+                u32::from_le_bytes(message[0..4].try_into().unwrap()),
+                u16::from_le_bytes(message[4..6].try_into().unwrap()),
+            ))
+        }
+    }
+
+    /// A console with two ports, or the initialization error.
+    fn multiport_console(
+        features: u64,
+    ) -> Result<(Control, TestPort, TestPort), VirtIOInitializationError> {
+        let transport = sim::Transport::new(features, Vec::new());
+        let port0 = port(transport);
+        let port1 = port(transport);
+        let control_receiveq = sim::queue::<CONTROL_RECEIVE_BUFFERS>(transport);
+        let control_transmitq = sim::queue::<1>(transport);
+        let console = leak(VirtIOConsole::new(
+            leak([port0.port, port1.port]),
+            control_receiveq,
+            control_transmitq,
+            leak_mut([[0; CONTROL_MESSAGE_LEN]; CONTROL_RECEIVE_BUFFERS]),
+            leak_mut([0; CONTROL_MESSAGE_LEN]),
+        ));
+        control_receiveq.set_client(console);
+        control_transmitq.set_client(console);
+        let queues: [&dyn Virtqueue; 6] = [
+            port0.port.receiveq,
+            port0.port.transmitq,
+            control_receiveq,
+            control_transmitq,
+            port1.port.receiveq,
+            port1.port.transmitq,
+        ];
+        transport.initialize(console, leak(queues))?;
+        let control = Control {
+            rx: sim::Device::new(control_receiveq),
+            tx: sim::Device::new(control_transmitq),
+        };
+        Ok((control, port0, port1))
+    }
+
+    fn leak_mut<T>(value: T) -> &'static mut T {
+        std::boxed::Box::leak(std::boxed::Box::new(value))
+    }
+
+    #[test]
+    fn transmit_completes_when_the_device_returns_the_buffer() {
+        let test = console();
+        let buffer = static_buf(8);
+        buffer[..5].copy_from_slice(b"hello");
+
+        assert!(test.port.transmit_buffer(buffer, 5).is_ok());
+        assert!(matches!(
+            test.port.transmit_buffer(static_buf(8), 1),
+            Err((ErrorCode::BUSY, _))
+        ));
+        assert_eq!(test.port.transmit_abort(), Err(ErrorCode::FAIL));
+
+        let chain = test.tx.pop().unwrap();
+        assert!(!chain.buffers[0].device_writeable);
+        assert_eq!(chain.buffers[0].read(), b"hello");
+        test.tx.complete(chain, 0);
+        assert_eq!(test.client.transmitted.get(), Some(5));
+
+        // The port is free again.
+        assert_eq!(test.port.transmit_abort(), Ok(()));
+        assert!(test.port.transmit_buffer(static_buf(8), 1).is_ok());
+    }
+
+    #[test]
+    fn partial_input_waits_for_the_remaining_bytes() {
+        let test = console();
+
+        assert!(test.port.receive_buffer(static_buf(8), 4).is_ok());
+        let chain = test.rx.pop().unwrap();
+        assert_eq!(chain.buffers[0].len, 4);
+        chain.buffers[0].write(b"ab");
+        test.rx.complete(chain, 2);
+        assert!(test.client.received.borrow().is_none());
+
+        // The rest of the buffer is provided to the device again.
+        let chain = test.rx.pop().unwrap();
+        assert_eq!(chain.buffers[0].len, 2);
+        chain.buffers[0].write(b"cd");
+        test.rx.complete(chain, 2);
+        assert_eq!(
+            test.client.received.take(),
+            Some((b"abcd".to_vec(), Ok(())))
+        );
+        assert!(test.rx.pop().is_none());
+    }
+
+    #[test]
+    fn aborted_receive_ends_with_the_next_input() {
+        let test = console();
+
+        assert!(test.port.receive_buffer(static_buf(8), 4).is_ok());
+        assert!(matches!(
+            test.port.receive_buffer(static_buf(8), 4),
+            Err((ErrorCode::BUSY, _))
+        ));
+        assert_eq!(test.port.receive_abort(), Err(ErrorCode::FAIL));
+
+        let chain = test.rx.pop().unwrap();
+        chain.buffers[0].write(b"a");
+        test.rx.complete(chain, 1);
+        assert_eq!(
+            test.client.received.take(),
+            Some((b"a".to_vec(), Err(ErrorCode::CANCEL)))
+        );
+        assert!(test.rx.pop().is_none());
+        assert_eq!(test.port.receive_abort(), Ok(()));
+    }
+
+    #[test]
+    fn multiport_requires_the_feature() {
+        assert!(matches!(
+            multiport_console(0),
+            Err(VirtIOInitializationError::FeatureNegotiationFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn added_ports_are_acknowledged_and_opened() {
+        let (control, _, _) = multiport_console(MULTIPORT).unwrap();
+
+        assert_eq!(control.receive(), Some((0, DEVICE_READY)));
+        assert_eq!(control.receive(), None);
+
+        control.send(1, DEVICE_ADD);
+        // Ports the board did not set up are ignored.
+        control.send(7, DEVICE_ADD);
+        assert_eq!(control.receive(), Some((1, PORT_READY)));
+        assert_eq!(control.receive(), Some((1, PORT_OPEN)));
+        assert_eq!(control.receive(), None);
+
+        // Every control message buffer is provided to the device again.
+        for _ in 0..CONTROL_RECEIVE_BUFFERS {
+            control.send(1, PORT_OPEN);
+        }
+        assert_eq!(control.receive(), None);
+        control.send(0, DEVICE_ADD);
+        assert_eq!(control.receive(), Some((0, PORT_READY)));
+        assert_eq!(control.receive(), Some((0, PORT_OPEN)));
+    }
+
+    #[test]
+    fn removed_port_is_not_opened() {
+        let (control, _, port1) = multiport_console(MULTIPORT).unwrap();
+        assert_eq!(control.receive(), Some((0, DEVICE_READY)));
+
+        control.send(1, DEVICE_ADD);
+        control.send(1, DEVICE_REMOVE);
+        assert_eq!(control.receive(), Some((1, PORT_READY)));
+        assert_eq!(control.receive(), None);
+
+        // The port itself still works.
+        assert!(port1.port.transmit_buffer(static_buf(8), 1).is_ok());
+        assert!(port1.tx.pop().is_some());
+    }
+}
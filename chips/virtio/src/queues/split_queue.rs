@@ -929,3 +929,201 @@ pub trait SplitVirtqueueClient<'b> {
         bytes_used: usize,
     );
 }
+
+/// The device side of split virtqueues, for testing device drivers on the
+/// host.
+///
+/// Test code plays the device: it takes the descriptor chains the driver
+/// makes available with [`sim::Device::pop`], accesses their buffers, and
+/// returns them with [`sim::Device::complete`], which runs the used buffer
+/// callbacks of the queue like an interrupt would.
+#[cfg(test)]
+pub(crate) mod sim {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::devices::{VirtIODeviceDriver, VirtIODeviceType};
+    use crate::transports::VirtIOInitializationError;
+
+    /// Fence for buffers which the simulated device accesses with plain
+    /// pointer reads and writes, which are coherent with the driver.
+    #[derive(Copy, Clone, Debug)]
+    pub struct HostFence;
+
+    // SAFETY: The simulated device runs on the same thread as the driver, so
+    // its accesses are ordered with the driver's.
+    unsafe impl DmaFence for HostFence {
+        fn release<T>(self, _buf: *mut [T]) {}
+
+        fn acquire<T>(self, _buf: *mut [T]) {}
+    }
+
+    /// A queue of `MAX_QUEUE_SIZE` descriptors, to be passed to the driver
+    /// under test and to [`Transport::initialize`].
+    pub fn queue<const MAX_QUEUE_SIZE: usize>(
+        transport: &'static Transport,
+    ) -> &'static SplitVirtqueue<'static, 'static, MAX_QUEUE_SIZE, HostFence> {
+        let queue = Box::leak(Box::new(SplitVirtqueue::new(
+            Box::leak(Box::default()),
+            Box::leak(Box::default()),
+            Box::leak(Box::default()),
+            HostFence,
+        )));
+        queue.set_transport(transport);
+        queue
+    }
+
+    /// A transport which offers `features` and exposes `config` as the
+    /// device configuration space.
+    pub struct Transport {
+        pub features: u64,
+        pub config: Vec<u32>,
+        /// Number of notifications per queue.
+        pub notifications: RefCell<Vec<usize>>,
+    }
+
+    impl Transport {
+        pub fn new(features: u64, config: Vec<u32>) -> &'static Self {
+            Box::leak(Box::new(Self {
+                features,
+                config,
+                notifications: RefCell::new(Vec::new()),
+            }))
+        }
+    }
+
+    impl VirtIOTransport for Transport {
+        fn initialize(
+            &self,
+            driver: &dyn VirtIODeviceDriver,
+            queues: &'static [&'static dyn Virtqueue],
+        ) -> Result<VirtIODeviceType, VirtIOInitializationError> {
+            let accepted = driver.negotiate_features(self.features).ok_or(
+                VirtIOInitializationError::FeatureNegotiationFailed {
+                    offered: self.features,
+                    accepted: None,
+                },
+            )?;
+            if accepted & !self.features != 0 {
+                return Err(VirtIOInitializationError::FeatureNegotiationFailed {
+                    offered: self.features,
+                    accepted: Some(accepted),
+                });
+            }
+            driver
+                .pre_device_initialization()
+                .map_err(VirtIOInitializationError::DriverPreInitializationError)?;
+            *self.notifications.borrow_mut() = std::vec![0; queues.len()];
+            for (number, queue) in queues.iter().enumerate() {
+                let elements = queue.negotiate_queue_size(usize::MAX);
+                queue.initialize(number as u32, elements);
+            }
+            driver.device_initialized().map_err(|e| {
+                VirtIOInitializationError::DriverInitializationError(driver.device_type(), e)
+            })?;
+            Ok(driver.device_type())
+        }
+
+        fn queue_notify(&self, queue_id: u32) {
+            self.notifications.borrow_mut()[queue_id as usize] += 1;
+        }
+
+        fn read_device_config(&self, offset: usize) -> Option<u32> {
+            if !offset.is_multiple_of(4) {
+                return None;
+            }
+            self.config.get(offset / 4).copied()
+        }
+    }
+
+    /// A buffer of a descriptor chain, as the device sees it.
+    pub struct Buffer {
+        address: u64,
+        pub len: usize,
+        pub device_writeable: bool,
+    }
+
+    impl Buffer {
+        pub fn read(&self) -> Vec<u8> {
+            // SAFETY: The driver shared the buffer with the device, and does
+            // not access it until the chain is returned.
+            unsafe { core::slice::from_raw_parts(self.address as *const u8, self.len) }.to_vec()
+        }
+
+        pub fn write(&self, data: &[u8]) {
+            assert!(self.device_writeable, "the buffer is read-only");
+            assert!(data.len() <= self.len, "the data does not fit the buffer");
+            // SAFETY: See `read()`.
+            unsafe {
+                core::ptr::copy_nonoverlapping(data.as_ptr(), self.address as *mut u8, data.len())
+            };
+        }
+    }
+
+    /// A descriptor chain made available by the driver.
+    pub struct Chain {
+        head: u16,
+        pub buffers: Vec<Buffer>,
+    }
+
+    /// The device end of `queue`.
+    pub struct Device<const MAX_QUEUE_SIZE: usize> {
+        queue: &'static SplitVirtqueue<'static, 'static, MAX_QUEUE_SIZE, HostFence>,
+        next_available: Cell<u16>,
+    }
+
+    impl<const MAX_QUEUE_SIZE: usize> Device<MAX_QUEUE_SIZE> {
+        pub fn new(
+            queue: &'static SplitVirtqueue<'static, 'static, MAX_QUEUE_SIZE, HostFence>,
+        ) -> Self {
+            Self {
+                queue,
+                next_available: Cell::new(0),
+            }
+        }
+
+        /// Take the next chain the driver made available, if any.
+        pub fn pop(&self) -> Option<Chain> {
+            let queue = self.queue;
+            let next = self.next_available.get();
+            if queue.available_ring.idx.get() == next {
+                return None;
+            }
+            self.next_available.set(next.wrapping_add(1));
+
+            let position = next as usize % queue.max_elements.get();
+            let head = queue.available_ring.ring[position].0.get();
+            let mut buffers = Vec::new();
+            let mut index = Some(head as usize);
+            while let Some(current) = index {
+                let descriptor = &queue.descriptors.0[current];
+                buffers.push(Buffer {
+                    address: descriptor.addr.get(),
+                    len: descriptor.len.get() as usize,
+                    device_writeable: descriptor.flags.is_set(DescriptorFlags::WriteOnly),
+                });
+                index = descriptor
+                    .flags
+                    .is_set(DescriptorFlags::Next)
+                    .then(|| descriptor.next.get() as usize);
+            }
+            Some(Chain { head, buffers })
+        }
+
+        /// Return `chain` to the driver, reporting that the device wrote
+        /// `written` bytes into it.
+        pub fn complete(&self, chain: Chain, written: usize) {
+            let queue = self.queue;
+            let idx = queue.used_ring.idx.get();
+            let element = &queue.used_ring.ring[idx as usize % queue.max_elements.get()];
+            element.id.set(chain.head as u32);
+            element.len.set(written as u32);
+            queue.used_ring.idx.set(idx.wrapping_add(1));
+            queue.used_interrupt();
+        }
+    }
+}
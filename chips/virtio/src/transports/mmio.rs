@@ -86,9 +86,9 @@ pub struct VirtIOMMIODeviceRegisters {
     /// 0x100 - 0x19C device configuration space
     ///
     /// This is individually defined per device, with a variable
-    /// size. TODO: How to address this properly? Just hand around
-    /// addresses to this?
-    config: [u32; 40],
+    /// size. Device drivers access it through
+    /// [`VirtIOTransport::read_device_config`].
+    config: [ReadOnly<u32>; 40],
 }
 
 register_bitfields![u32,
@@ -417,4 +417,11 @@ impl VirtIOTransport for VirtIOMMIODevice {
 
         self.regs.queue_notify.set(queue_id);
     }
+
+    fn read_device_config(&self, offset: usize) -> Option<u32> {
+        if !offset.is_multiple_of(4) {
+            return None;
+        }
+        self.regs.config.get(offset / 4).map(|word| word.get())
+    }
 }
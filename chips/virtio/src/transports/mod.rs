@@ -96,4 +96,13 @@ pub trait VirtIOTransport {
     /// driver, the queue can invoke this function, passing its own respective
     /// queue ID.
     fn queue_notify(&self, queue_id: u32);
+
+    /// Read a 32-bit word of the device-specific configuration space.
+    ///
+    /// `offset` is the byte offset of the word in the configuration space
+    /// and must be a multiple of 4. Returns `None` if the offset is
+    /// misaligned, lies outside of the configuration space, or the device
+    /// has no configuration space. Fields wider than 32 bits are read as
+    /// multiple words, starting with the least significant one.
+    fn read_device_config(&self, offset: usize) -> Option<u32>;
 }
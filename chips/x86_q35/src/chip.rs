@@ -11,8 +11,9 @@ use x86::registers::bits32::paging::{PD, PT};
 use x86::support;
 use x86::{Boundary, InterruptPoller};
 
+use crate::hpet::Hpet;
 use crate::keyboard::Ps2Keyboard;
-use crate::pic::PIC1_OFFSET;
+use crate::lapic::LapicTimer;
 use crate::pit::{Pit, RELOAD_1KHZ};
 use crate::ps2::Ps2Controller;
 use crate::serial::{COM1_BASE, COM2_BASE, COM3_BASE, COM4_BASE, SerialPort, SerialPortComponent};
@@ -20,20 +21,31 @@ use crate::vga_uart_driver::VgaText;
 
 /// Interrupt constants for legacy PC peripherals
 mod interrupt {
-    use crate::pic::PIC1_OFFSET;
+    /// Interrupt line used by the PIT, or by the HPET once started
+    pub(super) const TIMER: u32 = 0;
 
-    /// Interrupt number used by PIT
-    pub(super) const PIT: u32 = PIC1_OFFSET as u32;
+    /// Interrupt line shared by COM2 and COM4 serial devices
+    pub(super) const COM2_COM4: u32 = 3;
 
-    /// Interrupt number shared by COM2 and COM4 serial devices
-    pub(super) const COM2_COM4: u32 = (PIC1_OFFSET as u32) + 3;
+    /// Interrupt line shared by COM1 and COM3 serial devices
+    pub(super) const COM1_COM3: u32 = 4;
 
-    /// Interrupt number shared by COM1 and COM3 serial devices
-    pub(super) const COM1_COM3: u32 = (PIC1_OFFSET as u32) + 4;
-
-    /// Interrupt number used by the PS/2 keyboard (i8042, IRQ1).
+    /// Interrupt line used by the PS/2 keyboard (i8042, IRQ1).
     /// Raised when the controller’s output buffer has data ready (OB=1).
-    pub(super) const KEYBOARD: u32 = (PIC1_OFFSET as u32) + 1;
+    pub(super) const KEYBOARD: u32 = 1;
+}
+
+/// Interrupt controller used by the chip
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptController {
+    /// Legacy 8259 PIC
+    Pic,
+
+    /// Local APIC and I/O APIC
+    ///
+    /// This also enables the Local APIC timer. If the processor has no Local APIC, or the HPET
+    /// needed to calibrate its timer is missing, the chip falls back to the 8259 PIC.
+    Apic,
 }
 
 /// Representation of a generic PC platform.
@@ -47,18 +59,21 @@ mod interrupt {
 /// # Interrupt Handling
 ///
 /// This chip automatically handles interrupts for legacy PC devices which are known to be present
-/// on QEMU's Q35 machine type. This includes the PIT timer and four serial ports. Other devices
-/// which are conditionally present (e.g. Virtio devices specified on the QEMU command line) may be
-/// handled via a board-specific implementation of [`InterruptService`].
+/// on QEMU's Q35 machine type. This includes the PIT and HPET timers, the four serial ports and
+/// the PS/2 keyboard. Other devices which are conditionally present (e.g. Virtio devices specified
+/// on the QEMU command line) may be handled via a board-specific implementation of
+/// [`InterruptService`].
 ///
-/// This chip uses the legacy 8259 PIC to manage interrupts. This is relatively simple compared with
-/// using the Local APIC or I/O APIC and avoids needing to interact with ACPI or MP tables.
+/// This chip uses either the legacy 8259 PIC or the Local APIC and I/O APIC to manage interrupts,
+/// as selected by [`InterruptController`]. Neither requires interacting with ACPI or MP tables: the
+/// APIC configuration assumes the conventional layout of QEMU's Q35 machine type.
 ///
-/// Internally, this chip re-maps the PIC interrupt numbers to avoid conflicts with ISA-defined
+/// Internally, this chip re-maps the interrupt numbers to avoid conflicts with ISA-defined
 /// exceptions. This remapping is fully encapsulated within the chip. **N.B.** Implementors of
 /// [`InterruptService`] will be passed the physical interrupt line number, _not_ the remapped
 /// number used internally by the chip. This should match the interrupt line number reported by
-/// documentation or read from the PCI configuration space.
+/// documentation or read from the PCI configuration space. With the I/O APIC, PCI devices instead
+/// use the number of the I/O APIC input to which they are connected.
 pub struct Pc<'a, I1: InterruptService + 'a, I2: InterruptService + 'a, const PR: u16 = RELOAD_1KHZ>
 {
     /// Legacy COM1 serial port
@@ -76,6 +91,12 @@ pub struct Pc<'a, I1: InterruptService + 'a, I2: InterruptService + 'a, const PR
     /// Legacy PIT timer
     pub pit: &'a Pit<'a, PR>,
 
+    /// HPET timer
+    pub hpet: &'a Hpet<'a>,
+
+    /// Local APIC timer, only usable with [`InterruptController::Apic`]
+    pub lapic_timer: &'a LapicTimer,
+
     /// Vga
     pub vga: &'a VgaText<'a>,
 
@@ -124,6 +145,8 @@ impl<I2: InterruptService, const PR: u16> Pc<'static, PcDefaultPeripherals<PR>,
             com3: default_peripherals.com3,
             com4: default_peripherals.com4,
             pit: &default_peripherals.pit,
+            hpet: &default_peripherals.hpet,
+            lapic_timer: &default_peripherals.lapic_timer,
             vga: default_peripherals.vga,
             ps2: default_peripherals.ps2,
             keyboard: default_peripherals.keyboard,
@@ -163,22 +186,17 @@ impl<'a, I1: InterruptService, I2: InterruptService, const PR: u16> Chip for Pc<
     fn service_pending_interrupts(&self) {
         InterruptPoller::access(|poller| {
             while let Some(num) = poller.next_pending() {
-                let mut handled = true;
-                match self.default_peripherals.service_interrupt(num) {
-                    true => {}
-                    false => {
-                        // Convert back to physical interrupt line number before passing to
-                        // board-specific handler
-                        let phys_num = num - PIC1_OFFSET as u32;
-                        handled = self.board_peripherals.service_interrupt(phys_num);
-                    }
-                }
+                // Convert back to physical interrupt line number before passing to handlers
+                let handled = crate::interrupts::line(num).is_some_and(|line| {
+                    self.default_peripherals.service_interrupt(line)
+                        || self.board_peripherals.service_interrupt(line)
+                });
                 poller.clear_pending(num);
 
                 // Unmask the interrupt so it can fire again, but only if we know how to handle it
                 if handled {
                     unsafe {
-                        crate::pic::unmask(num);
+                        crate::interrupts::unmask(num);
                     }
                 } else {
                     kernel::debug!("Unhandled external interrupt {} left masked", num);
@@ -259,9 +277,12 @@ pub struct PcDefaultPeripherals<const PR: u16 = RELOAD_1KHZ> {
     pub com3: &'static SerialPort<'static>,
     pub com4: &'static SerialPort<'static>,
     pub pit: Pit<'static, PR>,
+    pub hpet: Hpet<'static>,
+    pub lapic_timer: LapicTimer,
     pub vga: &'static VgaText<'static>,
     pub ps2: &'static Ps2Controller,
     pub keyboard: &'static Ps2Keyboard<'static>,
+    interrupt_controller: InterruptController,
 }
 
 impl<const PR: u16> PcDefaultPeripherals<PR> {
@@ -269,6 +290,9 @@ impl<const PR: u16> PcDefaultPeripherals<PR> {
     ///
     /// The caller must provide statics through `x86_q35_peripherals_static!()`.
     ///
+    /// Interrupts are managed by `interrupt_controller` if available, or by the 8259 PIC otherwise.
+    /// Use [`PcDefaultPeripherals::interrupt_controller`] to find out which one is in use.
+    ///
     /// # Safety
     /// - Must be called only once per kernel lifetime.
     pub unsafe fn new(
@@ -282,12 +306,36 @@ impl<const PR: u16> PcDefaultPeripherals<PR> {
             &'static mut core::mem::MaybeUninit<Ps2Keyboard<'static>>,
         ),
         page_dir: &mut PD,
+        interrupt_controller: InterruptController,
     ) -> Self {
-        // CPU/interrupt controller/VGA baseline init
-        // SAFETY: PAGE_DIR is identity-mapped, aligned, and unique
+        let hpet = unsafe { Hpet::new() };
+        let lapic_timer = unsafe { LapicTimer::new() };
+
+        // CPU/interrupt controller baseline init
         unsafe {
             x86::init();
             crate::pic::init();
+        }
+
+        // The Local APIC timer is calibrated against the HPET main counter
+        let interrupt_controller = if interrupt_controller == InterruptController::Apic
+            && crate::lapic::is_present()
+            && hpet.enable().is_ok()
+        {
+            unsafe {
+                crate::pic::disable();
+                crate::lapic::init();
+                crate::ioapic::init();
+            }
+            lapic_timer.calibrate(&hpet);
+            InterruptController::Apic
+        } else {
+            InterruptController::Pic
+        };
+
+        // VGA baseline init
+        // SAFETY: PAGE_DIR is identity-mapped, aligned, and unique
+        unsafe {
             let pd_ref: &mut PD = &mut *core::ptr::from_mut(page_dir);
             // Enable the VGA path by building or running with the feature flag, e.g.:
             //   `cargo run -- -display none`
@@ -327,12 +375,20 @@ impl<const PR: u16> PcDefaultPeripherals<PR> {
             com3,
             com4,
             pit,
+            hpet,
+            lapic_timer,
             vga,
             ps2,
             keyboard,
+            interrupt_controller,
         }
     }
 
+    /// Returns the interrupt controller in use.
+    pub fn interrupt_controller(&self) -> InterruptController {
+        self.interrupt_controller
+    }
+
     /// Finalize deferred-call registrations and any circular deps.
     pub fn setup_circular_deps(&self) {
         kernel::deferred_call::DeferredCallClient::register(self.vga);
@@ -343,8 +399,13 @@ impl<const PR: u16> PcDefaultPeripherals<PR> {
 impl<const PR: u16> InterruptService for PcDefaultPeripherals<PR> {
     fn service_interrupt(&self, num: u32) -> bool {
         match num {
-            interrupt::PIT => {
-                self.pit.handle_interrupt();
+            interrupt::TIMER => {
+                // The HPET replaces the PIT on this line once started
+                if self.hpet.is_started() {
+                    self.hpet.handle_interrupt();
+                } else {
+                    self.pit.handle_interrupt();
+                }
                 true
             }
             interrupt::COM2_COM4 => {
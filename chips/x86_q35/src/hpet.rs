@@ -0,0 +1,350 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Support for the High Precision Event Timer (HPET).
+//!
+//! The HPET provides a free-running main counter and a set of comparators which can generate
+//! interrupts. Unlike the PIT, the main counter runs at a high frequency and can be read directly,
+//! so it is a suitable source of ticks without counting interrupts in software.
+//!
+//! This implementation runs timer 0 in 32-bit, one-shot mode and uses the "legacy replacement"
+//! routing, which connects timer 0 to ISA IRQ 0 in place of the PIT. This routing works with both
+//! the 8259 PIC and the I/O APIC, and does not require parsing the ACPI tables.
+//!
+//! In 32-bit mode, a one-shot timer also interrupts when the main counter wraps around, so an
+//! interrupt only signals the alarm once the counter has reached the comparator.
+//!
+//! The HPET is expected at its conventional address. This is always the case on QEMU's Q35 machine
+//! type, which also fixes the counter period to 10 ns.
+//!
+//! This implementation is based on guidance from the following sources:
+//!
+//! * <https://wiki.osdev.org/HPET>
+//! * IA-PC HPET (High Precision Event Timers) Specification, revision 1.0a
+
+use core::cell::Cell;
+
+use kernel::ErrorCode;
+use kernel::hil::time::{Alarm, AlarmClient, Counter, Frequency, Ticks, Ticks32, Time};
+use kernel::utilities::StaticRef;
+use tock_cells::optional_cell::OptionalCell;
+use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
+use tock_registers::registers::{ReadOnly, ReadWrite};
+use tock_registers::{register_bitfields, register_structs};
+
+/// Physical address of the HPET registers
+const HPET_BASE: usize = 0xFED0_0000;
+
+/// Period of the main counter in femtoseconds, as implemented by QEMU
+const HPET_PERIOD_FS: u32 = 10_000_000;
+
+/// Minimum distance between the current time and an alarm, so that the comparator is not written
+/// with a value the main counter has already passed (10 µs)
+const MINIMUM_DT: u32 = 1_000;
+
+/// Frequency of the HPET main counter
+pub struct HpetFreq;
+
+impl Frequency for HpetFreq {
+    fn frequency() -> u32 {
+        // Period is in femtoseconds
+        (1_000_000_000_000_000 / HPET_PERIOD_FS as u64) as u32
+    }
+}
+
+register_structs! {
+    HpetRegisters {
+        (0x000 => _reserved_capabilities),
+        (0x004 => period: ReadOnly<u32>),
+        (0x008 => _reserved0),
+        (0x010 => configuration: ReadWrite<u32, CONFIGURATION::Register>),
+        (0x014 => _reserved1),
+        (0x0F0 => main_counter: ReadWrite<u32>),
+        (0x0F4 => main_counter_high: ReadWrite<u32>),
+        (0x0F8 => _reserved2),
+        (0x100 => timer0_configuration: ReadWrite<u32, TIMER_CONFIGURATION::Register>),
+        (0x104 => _reserved_routing_capabilities),
+        (0x108 => timer0_comparator: ReadWrite<u32>),
+        (0x10C => _reserved_comparator_high),
+        (0x110 => @END),
+    }
+}
+
+register_bitfields!(u32,
+    CONFIGURATION [
+        ENABLE OFFSET(0) NUMBITS(1) [],
+        LEGACY_REPLACEMENT OFFSET(1) NUMBITS(1) [],
+    ],
+    TIMER_CONFIGURATION [
+        LEVEL_TRIGGERED OFFSET(1) NUMBITS(1) [],
+        INTERRUPT_ENABLE OFFSET(2) NUMBITS(1) [],
+        PERIODIC OFFSET(3) NUMBITS(1) [],
+        MODE_32BIT OFFSET(8) NUMBITS(1) [],
+    ]
+);
+
+/// Timer based on the HPET main counter and timer 0
+pub struct Hpet<'a> {
+    registers: StaticRef<HpetRegisters>,
+    client: OptionalCell<&'a dyn AlarmClient>,
+    /// Start of the interval ending at the comparator, while an alarm is set
+    reference: Cell<Ticks32>,
+}
+
+impl Hpet<'_> {
+    /// Creates a new HPET timer object.
+    ///
+    /// # Safety
+    ///
+    /// There must never be more than a single instance of `Hpet` alive at any given time.
+    pub unsafe fn new() -> Self {
+        Self::with_registers(unsafe { StaticRef::new(HPET_BASE as *const HpetRegisters) })
+    }
+
+    fn with_registers(registers: StaticRef<HpetRegisters>) -> Self {
+        Hpet {
+            registers,
+            client: OptionalCell::empty(),
+            reference: Cell::new(Ticks32::from(0)),
+        }
+    }
+
+    /// Starts the main counter, without routing any interrupt.
+    ///
+    /// Returns `ENODEVICE` if the HPET is missing or its counter does not run at [`HpetFreq`].
+    pub fn enable(&self) -> Result<(), ErrorCode> {
+        // A missing device reads as all zeroes or all ones
+        if self.registers.period.get() != HPET_PERIOD_FS {
+            return Err(ErrorCode::NODEVICE);
+        }
+
+        self.registers
+            .configuration
+            .modify(CONFIGURATION::ENABLE::SET);
+        Ok(())
+    }
+
+    /// Starts the main counter and routes timer 0 to ISA IRQ 0, in place of the PIT.
+    ///
+    /// Once this has been called, the PIT no longer generates interrupts.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        self.enable()?;
+
+        // Edge-triggered, one-shot, with its interrupt disabled until an alarm is set
+        self.registers
+            .timer0_configuration
+            .write(TIMER_CONFIGURATION::MODE_32BIT::SET);
+        self.registers
+            .configuration
+            .modify(CONFIGURATION::LEGACY_REPLACEMENT::SET);
+        Ok(())
+    }
+
+    /// Returns whether timer 0 has been routed to ISA IRQ 0 by [`Hpet::start`].
+    pub fn is_started(&self) -> bool {
+        self.registers
+            .configuration
+            .is_set(CONFIGURATION::LEGACY_REPLACEMENT)
+    }
+
+    /// Handler to call when an HPET interrupt occurs.
+    ///
+    /// Timer 0 only fires once for each alarm, so it is disarmed before the client is signalled.
+    /// Interrupts raised by the main counter wrapping around before the alarm expires are ignored.
+    pub fn handle_interrupt(&self) {
+        if !self.is_armed()
+            || self
+                .now()
+                .within_range(self.reference.get(), self.get_alarm())
+        {
+            return;
+        }
+
+        let _ = self.disarm();
+        self.client.map(|client| client.alarm());
+    }
+}
+
+impl Time for Hpet<'_> {
+    type Frequency = HpetFreq;
+
+    type Ticks = Ticks32;
+    fn now(&self) -> Self::Ticks {
+        self.registers.main_counter.get().into()
+    }
+}
+
+impl<'a> Counter<'a> for Hpet<'a> {
+    fn start(&self) -> Result<(), ErrorCode> {
+        self.enable()
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        self.registers
+            .configuration
+            .modify(CONFIGURATION::ENABLE::CLEAR);
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), ErrorCode> {
+        // The main counter can only be written while it is halted
+        if self.is_running() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.registers.main_counter.set(0);
+        self.registers.main_counter_high.set(0);
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.registers.configuration.is_set(CONFIGURATION::ENABLE)
+    }
+}
+
+impl<'a> Alarm<'a> for Hpet<'a> {
+    fn set_alarm_client(&self, client: &'a dyn AlarmClient) {
+        self.client.set(client);
+    }
+
+    fn set_alarm(&self, mut reference: Self::Ticks, dt: Self::Ticks) {
+        let now = self.now();
+        let mut expire = reference.wrapping_add(dt);
+
+        // The comparator only matches when the main counter reaches it, so an alarm in the past
+        // would otherwise fire after the counter wraps around
+        if !now.within_range(reference, expire) || expire.wrapping_sub(now) < self.minimum_dt() {
+            reference = now;
+            expire = now.wrapping_add(self.minimum_dt());
+        }
+
+        self.reference.set(reference);
+        self.registers.timer0_comparator.set(expire.into_u32());
+        self.registers
+            .timer0_configuration
+            .modify(TIMER_CONFIGURATION::INTERRUPT_ENABLE::SET);
+    }
+
+    fn get_alarm(&self) -> Self::Ticks {
+        self.registers.timer0_comparator.get().into()
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.registers
+            .timer0_configuration
+            .modify(TIMER_CONFIGURATION::INTERRUPT_ENABLE::CLEAR);
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.registers
+            .timer0_configuration
+            .is_set(TIMER_CONFIGURATION::INTERRUPT_ENABLE)
+    }
+
+    fn minimum_dt(&self) -> Self::Ticks {
+        MINIMUM_DT.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capsules_test_harness::{leak, static_registers};
+
+    struct Recorder {
+        fired: Cell<usize>,
+    }
+
+    impl AlarmClient for Recorder {
+        fn alarm(&self) {
+            self.fired.set(self.fired.get() + 1);
+        }
+    }
+
+    fn hpet() -> (&'static Hpet<'static>, &'static Recorder) {
+        let hpet = leak(Hpet::with_registers(static_registers()));
+        let recorder = leak(Recorder {
+            fired: Cell::new(0),
+        });
+        hpet.set_alarm_client(recorder);
+        (hpet, recorder)
+    }
+
+    fn set_counter(hpet: &Hpet, value: u32) {
+        hpet.registers.main_counter.set(value);
+    }
+
+    #[test]
+    fn counter_runs_at_100_mhz() {
+        assert_eq!(HpetFreq::frequency(), 100_000_000);
+    }
+
+    #[test]
+    fn missing_device_is_not_enabled() {
+        let (hpet, _) = hpet();
+
+        assert_eq!(hpet.enable(), Err(ErrorCode::NODEVICE));
+        assert_eq!(hpet.start(), Err(ErrorCode::NODEVICE));
+        assert!(!hpet.is_running());
+        assert!(!hpet.is_started());
+    }
+
+    #[test]
+    fn comparator_is_reference_plus_dt() {
+        let (hpet, _) = hpet();
+        set_counter(hpet, 1_500);
+
+        hpet.set_alarm(1_000.into(), 5_000.into());
+
+        assert_eq!(hpet.get_alarm(), 6_000.into());
+        assert!(hpet.is_armed());
+    }
+
+    #[test]
+    fn alarms_in_the_past_expire_after_the_minimum_dt() {
+        let (hpet, _) = hpet();
+        set_counter(hpet, 10_000);
+
+        hpet.set_alarm(0.into(), 100.into());
+        assert_eq!(hpet.get_alarm(), (10_000 + MINIMUM_DT).into());
+
+        // Too close to the current time for the comparator write to land in time
+        hpet.set_alarm(10_000.into(), (MINIMUM_DT - 1).into());
+        assert_eq!(hpet.get_alarm(), (10_000 + MINIMUM_DT).into());
+    }
+
+    #[test]
+    fn wrap_around_interrupt_does_not_fire_the_alarm() {
+        let (hpet, recorder) = hpet();
+        set_counter(hpet, 0xFFFF_F000);
+
+        hpet.set_alarm(0xFFFF_F000.into(), 0x2000.into());
+        assert_eq!(hpet.get_alarm(), 0x1000.into());
+
+        // The main counter wrapped around, but has not reached the comparator yet
+        set_counter(hpet, 0);
+        hpet.handle_interrupt();
+        assert_eq!(recorder.fired.get(), 0);
+        assert!(hpet.is_armed());
+
+        set_counter(hpet, 0x1000);
+        hpet.handle_interrupt();
+        assert_eq!(recorder.fired.get(), 1);
+        assert!(!hpet.is_armed());
+    }
+
+    #[test]
+    fn disarmed_timer_ignores_interrupts() {
+        let (hpet, recorder) = hpet();
+        set_counter(hpet, 0);
+        hpet.set_alarm(0.into(), 2_000.into());
+        hpet.disarm().unwrap();
+
+        set_counter(hpet, 5_000);
+        hpet.handle_interrupt();
+
+        assert_eq!(recorder.fired.get(), 0);
+    }
+}
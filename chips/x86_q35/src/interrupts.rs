@@ -4,7 +4,28 @@
 
 use x86::InterruptPoller;
 
-use super::pic;
+use super::{ioapic, lapic, pic};
+
+/// Returns the physical interrupt line corresponding to interrupt `num`, if it was raised by the
+/// 8259 PIC or the I/O APIC.
+pub(crate) fn line(num: u32) -> Option<u32> {
+    pic::line(num).or_else(|| ioapic::line(num))
+}
+
+/// Unmasks interrupt `num` in the interrupt controller which raised it.
+///
+/// # Safety
+///
+/// Same as [`pic::unmask`].
+pub(crate) unsafe fn unmask(num: u32) {
+    unsafe {
+        if pic::line(num).is_some() {
+            pic::unmask(num);
+        } else {
+            ioapic::unmask(num);
+        }
+    }
+}
 
 /// Handler for external interrupts.
 ///
@@ -13,6 +34,11 @@ use super::pic;
 /// interrupt, then issues an EOI message to the system interrupt controller so that subsequent
 /// interrupts can be delivered.
 ///
+/// Interrupts are told apart by their number: the 8259 PIC, the I/O APIC and the Local APIC each
+/// use a distinct range. The Local APIC timer only serves to return control to the kernel, so it is
+/// acknowledged without being marked as pending. Spurious interrupts from the Local APIC must not
+/// be acknowledged at all.
+///
 /// # Safety
 ///
 /// This function must only be called when handling an interrupt. It should _never_ be called by
@@ -24,8 +50,19 @@ use super::pic;
 #[no_mangle]
 unsafe extern "cdecl" fn handle_external_interrupt(num: u32) {
     unsafe {
-        InterruptPoller::set_pending(num);
-        pic::mask(num);
-        pic::eoi(num);
+        match num {
+            lapic::SPURIOUS_VECTOR => {}
+            lapic::TIMER_VECTOR => lapic::eoi(),
+            _ if ioapic::line(num).is_some() => {
+                InterruptPoller::set_pending(num);
+                ioapic::mask(num);
+                lapic::eoi();
+            }
+            _ => {
+                InterruptPoller::set_pending(num);
+                pic::mask(num);
+                pic::eoi(num);
+            }
+        }
     }
 }
@@ -0,0 +1,173 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Module for interacting with the I/O APIC
+//!
+//! The I/O APIC receives interrupts from devices and forwards them to the Local APIC of a processor
+//! according to its redirection table. Each input pin of the I/O APIC is identified by a Global
+//! System Interrupt (GSI) number.
+//!
+//! Inputs 0-15 of the I/O APIC are connected to the ISA interrupt lines, except for ISA IRQ 0
+//! (the timer) which is connected to GSI 2, as described by the interrupt source override found in
+//! the ACPI tables of virtually all PCs. The remaining inputs are used by PCI devices, and are
+//! level-triggered.
+//!
+//! This module re-maps the inputs of the I/O APIC to interrupt vectors such that the interrupt line
+//! number can be recovered from the vector: ISA IRQs keep their number, and PCI interrupts use
+//! their GSI number. Interrupt numbers passed to implementors of
+//! [`InterruptService`](kernel::platform::chip::InterruptService) therefore match the ISA IRQ
+//! numbers whether the 8259 PIC or the I/O APIC is in use.
+//!
+//! The I/O APIC is expected at its conventional address, and is assumed to have 24 inputs as on
+//! QEMU's Q35 machine type.
+//!
+//! This implementation is based on guidance from the following sources:
+//!
+//! * <https://wiki.osdev.org/IOAPIC>
+//! * Intel 82093AA I/O Advanced Programmable Interrupt Controller (IOAPIC) datasheet
+
+use kernel::utilities::StaticRef;
+use tock_registers::interfaces::{Readable, Writeable};
+use tock_registers::registers::ReadWrite;
+use tock_registers::{LocalRegisterCopy, register_bitfields, register_structs};
+
+use crate::lapic;
+
+/// Offset to which I/O APIC interrupts are re-mapped
+pub(crate) const IOAPIC_OFFSET: u32 = 0x50;
+
+/// Number of interrupt lines handled by the I/O APIC
+const IOAPIC_NUM_LINES: u32 = 24;
+
+/// Number of interrupt lines connected to the ISA bus
+const ISA_NUM_LINES: u32 = 16;
+
+/// Index of the first redirection table register
+const IOREDTBL: u32 = 0x10;
+
+register_structs! {
+    IoApicRegisters {
+        (0x00 => select: ReadWrite<u32>),
+        (0x04 => _reserved0),
+        (0x10 => window: ReadWrite<u32>),
+        (0x14 => @END),
+    }
+}
+
+register_bitfields!(u32,
+    REDIRECTION [
+        VECTOR OFFSET(0) NUMBITS(8) [],
+        LEVEL_TRIGGERED OFFSET(15) NUMBITS(1) [],
+        MASK OFFSET(16) NUMBITS(1) [],
+    ],
+    REDIRECTION_HIGH [
+        DESTINATION OFFSET(24) NUMBITS(8) [],
+    ]
+);
+
+const IOAPIC_BASE: StaticRef<IoApicRegisters> =
+    unsafe { StaticRef::new(0xFEC0_0000 as *const IoApicRegisters) };
+
+/// Returns the GSI to which interrupt line `line` is connected, if any.
+fn gsi(line: u32) -> Option<u32> {
+    match line {
+        // The timer is connected to GSI 2, so ISA IRQ 2 does not exist
+        0 => Some(2),
+        2 => None,
+        _ if line < IOAPIC_NUM_LINES => Some(line),
+        _ => None,
+    }
+}
+
+/// Returns the interrupt line corresponding to interrupt `num`, if it is handled by the I/O APIC.
+pub(crate) fn line(num: u32) -> Option<u32> {
+    let line = num.checked_sub(IOAPIC_OFFSET)?;
+    gsi(line).map(|_| line)
+}
+
+fn read(reg: u32) -> u32 {
+    IOAPIC_BASE.select.set(reg);
+    IOAPIC_BASE.window.get()
+}
+
+fn write(reg: u32, val: u32) {
+    IOAPIC_BASE.select.set(reg);
+    IOAPIC_BASE.window.set(val);
+}
+
+/// Updates the mask bit of the redirection table entry for interrupt `num`.
+fn set_masked(num: u32, masked: bool) {
+    let Some(gsi) = line(num).and_then(gsi) else {
+        return;
+    };
+
+    let reg = IOREDTBL + 2 * gsi;
+    let mut entry = LocalRegisterCopy::<u32, REDIRECTION::Register>::new(read(reg));
+    if masked {
+        entry.modify(REDIRECTION::MASK::SET);
+    } else {
+        entry.modify(REDIRECTION::MASK::CLEAR);
+    }
+    write(reg, entry.get());
+}
+
+/// Initializes the I/O APIC to deliver all interrupts to the current processor and unmasks them.
+///
+/// The input connected to the 8259 PIC (GSI 0) is left masked.
+///
+/// # Safety
+///
+/// Calling this function will cause interrupts to start firing. This means the IDT must already be
+/// initialized with valid handlers for all possible interrupt numbers (i.e. by a call to
+/// [`x86::init`]), and the Local APIC must be enabled.
+pub(crate) unsafe fn init() {
+    let mut destination = LocalRegisterCopy::<u32, REDIRECTION_HIGH::Register>::new(0);
+    destination.modify(REDIRECTION_HIGH::DESTINATION.val(lapic::id()));
+
+    write(IOREDTBL, REDIRECTION::MASK::SET.value);
+
+    for line in 0..IOAPIC_NUM_LINES {
+        let Some(gsi) = gsi(line) else {
+            continue;
+        };
+
+        // ISA interrupts are edge-triggered and active-high, and PCI interrupts are
+        // level-triggered. QEMU declares the latter as active-high in its ACPI tables.
+        let mut entry = LocalRegisterCopy::<u32, REDIRECTION::Register>::new(0);
+        entry.modify(REDIRECTION::VECTOR.val(IOAPIC_OFFSET + line));
+        if line >= ISA_NUM_LINES {
+            entry.modify(REDIRECTION::LEVEL_TRIGGERED::SET);
+        }
+
+        write(IOREDTBL + 2 * gsi + 1, destination.get());
+        write(IOREDTBL + 2 * gsi, entry.get());
+    }
+}
+
+/// Masks interrupt `num`, disabling its delivery to the CPU.
+///
+/// If `num` does not correspond to the I/O APIC, then no action is taken.
+///
+/// # Safety
+///
+/// Must be called with interrupts disabled or from within an interrupt handler to avoid race
+/// conditions updating the redirection table.
+pub(crate) unsafe fn mask(num: u32) {
+    set_masked(num, true);
+}
+
+/// Unmasks interrupt `num`, enabling its delivery to the CPU.
+///
+/// If `num` does not correspond to the I/O APIC, then no action is taken.
+///
+/// # Safety
+///
+/// Must be called with interrupts disabled or from within an interrupt handler to avoid race
+/// conditions updating the redirection table.
+///
+/// There must be an interrupt handler registered to properly handle `num`, as the interrupt may
+/// fire at any time once this function is called.
+pub(crate) unsafe fn unmask(num: u32) {
+    set_masked(num, false);
+}
@@ -0,0 +1,312 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Support for the Local APIC.
+//!
+//! Each processor has a Local APIC which accepts interrupts from the I/O APIC and delivers them to
+//! the CPU. It also contains a timer, which this module exposes as a [`SchedulerTimer`].
+//!
+//! The Local APIC is expected at its default address of `0xFEE00000`, where firmware leaves it. Its
+//! presence is detected with `CPUID`, since QEMU only emulates one for `-cpu 486` when the `apic`
+//! feature is requested.
+//!
+//! This implementation is based on guidance from the following sources:
+//!
+//! * <https://wiki.osdev.org/APIC>
+//! * <https://wiki.osdev.org/APIC_Timer>
+//! * Intel 64 and IA-32 Architectures Software Developer's Manual, Volume 3, Chapter 11
+
+use core::cell::Cell;
+use core::num::NonZeroU32;
+
+use kernel::hil::time::{Frequency, Ticks, Time};
+use kernel::platform::scheduler_timer::SchedulerTimer;
+use kernel::utilities::StaticRef;
+use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
+use tock_registers::registers::{ReadOnly, ReadWrite, WriteOnly};
+use tock_registers::{register_bitfields, register_structs};
+
+/// Interrupt vector used by the Local APIC timer
+pub(crate) const TIMER_VECTOR: u32 = 0x70;
+
+/// Interrupt vector used for spurious interrupts
+///
+/// The lower four bits must be set on older processors.
+pub(crate) const SPURIOUS_VECTOR: u32 = 0xFF;
+
+/// Fraction of a second during which the timer is calibrated (10 ms)
+const CALIBRATION_FRACTION: u32 = 100;
+
+register_structs! {
+    LapicRegisters {
+        (0x000 => _reserved0),
+        (0x020 => id: ReadOnly<u32, ID::Register>),
+        (0x024 => _reserved1),
+        (0x080 => task_priority: ReadWrite<u32>),
+        (0x084 => _reserved2),
+        (0x0B0 => eoi: WriteOnly<u32>),
+        (0x0B4 => _reserved3),
+        (0x0F0 => spurious_vector: ReadWrite<u32, SVR::Register>),
+        (0x0F4 => _reserved4),
+        (0x320 => lvt_timer: ReadWrite<u32, LVT::Register>),
+        (0x324 => _reserved5),
+        (0x350 => lvt_lint0: ReadWrite<u32, LVT::Register>),
+        (0x354 => _reserved6),
+        (0x370 => lvt_error: ReadWrite<u32, LVT::Register>),
+        (0x374 => _reserved7),
+        (0x380 => timer_initial_count: ReadWrite<u32>),
+        (0x384 => _reserved8),
+        (0x390 => timer_current_count: ReadOnly<u32>),
+        (0x394 => _reserved9),
+        (0x3E0 => timer_divide: ReadWrite<u32, TIMER_DIVIDE::Register>),
+        (0x3E4 => _reserved10),
+        (0x400 => @END),
+    }
+}
+
+register_bitfields!(u32,
+    ID [
+        ID OFFSET(24) NUMBITS(8) [],
+    ],
+    SVR [
+        VECTOR OFFSET(0) NUMBITS(8) [],
+        ENABLE OFFSET(8) NUMBITS(1) [],
+    ],
+    LVT [
+        VECTOR OFFSET(0) NUMBITS(8) [],
+        MASK OFFSET(16) NUMBITS(1) [],
+        TIMER_MODE OFFSET(17) NUMBITS(2) [
+            OneShot = 0b00,
+            Periodic = 0b01,
+        ],
+    ],
+    TIMER_DIVIDE [
+        VALUE OFFSET(0) NUMBITS(4) [
+            Divide16 = 0b0011,
+        ],
+    ]
+);
+
+const LAPIC_BASE: StaticRef<LapicRegisters> =
+    unsafe { StaticRef::new(0xFEE0_0000 as *const LapicRegisters) };
+
+/// Returns whether the processor has a Local APIC.
+#[cfg(target_arch = "x86")]
+pub fn is_present() -> bool {
+    use core::arch::x86::__cpuid;
+    use x86::registers::bits32::eflags;
+
+    /// EFLAGS bit which can only be toggled if the `CPUID` instruction is supported
+    const EFLAGS_ID: u32 = 1 << 21;
+
+    /// CPUID leaf 1 EDX bit indicating an on-chip APIC
+    const CPUID_APIC: u32 = 1 << 9;
+
+    // Safety: Toggling the ID flag has no side effects, and it is restored afterwards.
+    let has_cpuid = unsafe {
+        let original = eflags::read();
+        let mut toggled = original;
+        toggled.0.set(original.0.get() ^ EFLAGS_ID);
+        eflags::set(toggled);
+        let changed = eflags::read().0.get() ^ original.0.get();
+        eflags::set(original);
+        changed & EFLAGS_ID != 0
+    };
+
+    has_cpuid && __cpuid(0).eax >= 1 && __cpuid(1).edx & CPUID_APIC != 0
+}
+
+#[cfg(not(target_arch = "x86"))]
+pub fn is_present() -> bool {
+    unimplemented!()
+}
+
+/// Enables the Local APIC of the current processor.
+///
+/// The local interrupt pins and the timer are masked, so only interrupts from the I/O APIC are
+/// delivered. In particular, this disconnects the 8259 PIC, which firmware routes through LINT0.
+///
+/// # Safety
+///
+/// The Local APIC must be present (see [`is_present`]), and the IDT must already be initialized
+/// with valid handlers for all possible interrupt numbers (i.e. by a call to [`x86::init`]).
+pub(crate) unsafe fn init() {
+    LAPIC_BASE.lvt_lint0.modify(LVT::MASK::SET);
+    LAPIC_BASE.lvt_error.modify(LVT::MASK::SET);
+    LAPIC_BASE
+        .lvt_timer
+        .write(LVT::MASK::SET + LVT::VECTOR.val(TIMER_VECTOR));
+
+    // Accept interrupts of all priorities
+    LAPIC_BASE.task_priority.set(0);
+    LAPIC_BASE
+        .spurious_vector
+        .write(SVR::ENABLE::SET + SVR::VECTOR.val(SPURIOUS_VECTOR));
+}
+
+/// Returns the ID of the current processor's Local APIC.
+pub(crate) fn id() -> u32 {
+    LAPIC_BASE.id.read(ID::ID)
+}
+
+/// Sends an end-of-interrupt signal to the Local APIC.
+///
+/// # Safety
+///
+/// This function must _only_ be called from an interrupt servicing routine. Calling this function
+/// from the normal kernel loop could interfere with this crate's interrupt handling logic.
+pub(crate) unsafe fn eoi() {
+    LAPIC_BASE.eoi.set(0);
+}
+
+/// Scheduler timer based on the Local APIC timer
+///
+/// The Local APIC timer counts down at a frequency derived from the bus clock, which is not
+/// reported by the hardware. It must therefore be calibrated against a known time source with
+/// [`LapicTimer::calibrate`] before use.
+///
+/// The timer runs in one-shot mode and raises an interrupt when it expires, if armed. The
+/// interrupt is acknowledged by the chip without being dispatched to any peripheral: it only serves
+/// to return control to the kernel.
+pub struct LapicTimer {
+    /// Frequency of the timer in Hz, zero until calibrated
+    frequency: Cell<u32>,
+}
+
+impl LapicTimer {
+    /// Creates a new Local APIC timer object.
+    ///
+    /// # Safety
+    ///
+    /// There must never be more than a single instance of `LapicTimer` alive at any given time.
+    pub unsafe fn new() -> Self {
+        LapicTimer {
+            frequency: Cell::new(0),
+        }
+    }
+
+    /// Measures the frequency of the timer against `time`.
+    ///
+    /// This busy-waits for 10 ms, so `time` must advance without relying on interrupts.
+    pub fn calibrate<T: Time>(&self, time: &T) {
+        let interval = T::Ticks::from(T::Frequency::frequency() / CALIBRATION_FRACTION);
+
+        LAPIC_BASE.timer_divide.write(TIMER_DIVIDE::VALUE::Divide16);
+        LAPIC_BASE
+            .lvt_timer
+            .write(LVT::MASK::SET + LVT::TIMER_MODE::OneShot + LVT::VECTOR.val(TIMER_VECTOR));
+
+        let start = time.now();
+        LAPIC_BASE.timer_initial_count.set(u32::MAX);
+        while time.now().wrapping_sub(start) < interval {}
+        let elapsed = u32::MAX - LAPIC_BASE.timer_current_count.get();
+        LAPIC_BASE.timer_initial_count.set(0);
+
+        self.frequency.set(calibrated_frequency(elapsed));
+    }
+
+    /// Returns the frequency of the timer in Hz, or zero if it has not been calibrated.
+    ///
+    /// The bus clock frequency is this value multiplied by 16.
+    pub fn frequency(&self) -> u32 {
+        self.frequency.get()
+    }
+}
+
+/// Returns the frequency of a timer which counted `elapsed` ticks during the calibration interval.
+fn calibrated_frequency(elapsed: u32) -> u32 {
+    elapsed.saturating_mul(CALIBRATION_FRACTION)
+}
+
+/// Converts a duration to an initial count for a timer running at `frequency`.
+///
+/// The count saturates, and is at least one since a zero count stops the timer.
+fn us_to_ticks(us: NonZeroU32, frequency: u32) -> u32 {
+    let ticks = u64::from(us.get()) * u64::from(frequency) / 1_000_000;
+    u32::try_from(ticks).unwrap_or(u32::MAX).max(1)
+}
+
+/// Converts a count of a timer running at `frequency` to a duration, rounded down.
+///
+/// Returns `None` if the duration is zero or the timer has not been calibrated.
+fn ticks_to_us(ticks: u32, frequency: u32) -> Option<NonZeroU32> {
+    let us = (u64::from(ticks) * 1_000_000).checked_div(u64::from(frequency))?;
+    NonZeroU32::new(u32::try_from(us).unwrap_or(u32::MAX))
+}
+
+impl SchedulerTimer for LapicTimer {
+    fn start(&self, us: NonZeroU32) {
+        // Writing the initial count starts the timer, so configure it first
+        LAPIC_BASE
+            .lvt_timer
+            .write(LVT::MASK::SET + LVT::TIMER_MODE::OneShot + LVT::VECTOR.val(TIMER_VECTOR));
+        LAPIC_BASE
+            .timer_initial_count
+            .set(us_to_ticks(us, self.frequency.get()));
+    }
+
+    fn reset(&self) {
+        LAPIC_BASE.lvt_timer.modify(LVT::MASK::SET);
+        LAPIC_BASE.timer_initial_count.set(0);
+    }
+
+    fn arm(&self) {
+        LAPIC_BASE.lvt_timer.modify(LVT::MASK::CLEAR);
+    }
+
+    fn disarm(&self) {
+        LAPIC_BASE.lvt_timer.modify(LVT::MASK::SET);
+    }
+
+    fn get_remaining_us(&self) -> Option<NonZeroU32> {
+        ticks_to_us(LAPIC_BASE.timer_current_count.get(), self.frequency.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn us(us: u32) -> NonZeroU32 {
+        NonZeroU32::new(us).unwrap()
+    }
+
+    #[test]
+    fn calibration_scales_to_one_second() {
+        assert_eq!(calibrated_frequency(62_500), 6_250_000);
+        assert_eq!(calibrated_frequency(u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn durations_convert_to_ticks() {
+        assert_eq!(us_to_ticks(us(10_000), 6_250_000), 62_500);
+        // Rounded down
+        assert_eq!(us_to_ticks(us(3), 500_000), 1);
+    }
+
+    #[test]
+    fn initial_count_is_never_zero() {
+        assert_eq!(us_to_ticks(us(1), 100_000), 1);
+        assert_eq!(us_to_ticks(us(1), 0), 1);
+    }
+
+    #[test]
+    fn initial_count_saturates() {
+        assert_eq!(us_to_ticks(us(u32::MAX), 1_000_000_000), u32::MAX);
+    }
+
+    #[test]
+    fn remaining_ticks_convert_to_durations() {
+        assert_eq!(ticks_to_us(62_500, 6_250_000), Some(us(10_000)));
+        assert_eq!(ticks_to_us(u32::MAX, 1), Some(us(u32::MAX)));
+    }
+
+    #[test]
+    fn expired_or_uncalibrated_timer_has_no_remaining_time() {
+        assert_eq!(ticks_to_us(0, 6_250_000), None);
+        // Less than a microsecond left
+        assert_eq!(ticks_to_us(6, 6_250_000), None);
+        assert_eq!(ticks_to_us(62_500, 0), None);
+    }
+}
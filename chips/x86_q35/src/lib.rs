@@ -15,11 +15,14 @@
 #![no_std]
 
 mod chip;
-pub use chip::{Pc, PcDefaultPeripherals};
+pub use chip::{InterruptController, Pc, PcDefaultPeripherals};
 
 mod cmd_fifo;
 mod interrupts;
 
+pub mod hpet;
+pub mod ioapic;
+pub mod lapic;
 pub mod pic;
 
 pub mod pit;
@@ -91,6 +91,28 @@ pub unsafe fn init() {
     }
 }
 
+/// Masks all interrupts of both PICs.
+///
+/// This is used when interrupts are managed by the I/O APIC instead. The PICs must still be
+/// initialized first, so that any spurious interrupt they raise does not use the vector of an
+/// exception.
+///
+/// # Safety
+///
+/// Must be called with interrupts disabled to avoid race conditions updating the IMR.
+pub(crate) unsafe fn disable() {
+    unsafe {
+        io::outb(PIC1_DATA, 0xff);
+        io::outb(PIC2_DATA, 0xff);
+    }
+}
+
+/// Returns the interrupt line corresponding to interrupt `num`, if it is handled by the PICs.
+pub(crate) fn line(num: u32) -> Option<u32> {
+    let line = num.checked_sub(PIC1_OFFSET as u32)?;
+    (line < (PIC1_NUM_INTERRUPTS as u32) * 2).then_some(line)
+}
+
 /// Sends an end-of-interrupt signal to the PIC responsible for generating interrupt `num`.
 ///
 /// If `num` does not correspond to either the primary or secondary PIC, then no action is taken.
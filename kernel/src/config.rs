@@ -43,6 +43,13 @@ pub(crate) struct Config {
     /// system call or upcall parameters.
     pub(crate) trace_syscalls: bool,
 
+    /// Whether the kernel should report syscalls to a syscall tracer.
+    ///
+    /// If enabled, the kernel will pass each system call and the value returned
+    /// to the process to the `SyscallTracer` set on the kernel, if any, which
+    /// can keep structured records of them.
+    pub(crate) record_syscalls: bool,
+
     /// Whether the kernel should show debugging output when loading processes.
     ///
     /// If enabled, the kernel will show from which addresses processes are
@@ -90,6 +97,7 @@ pub(crate) struct Config {
 /// we permit `#[cfg(x)]` to be used to configure code based on Cargo features.
 pub(crate) const CONFIG: Config = Config {
     trace_syscalls: cfg!(feature = "trace_syscalls"),
+    record_syscalls: cfg!(feature = "record_syscalls"),
     debug_load_processes: cfg!(feature = "debug_load_processes"),
     debug_panics: !cfg!(feature = "no_debug_panics"),
     debug_process_credentials: cfg!(feature = "debug_process_credentials"),
@@ -109,14 +109,18 @@ use core::panic::PanicInfo;
 use core::str;
 
 use crate::capabilities::SetDebugWriterCapability;
+use crate::deferred_call::DeferredCall;
+use crate::errorcode::ErrorCode;
 use crate::hil;
 use crate::platform::chip::Chip;
+use crate::platform::chip::FaultRegisters;
 use crate::platform::chip::PanicWriter;
 use crate::platform::chip::ThreadIdProvider;
 use crate::process::ProcessPrinter;
 use crate::process::ProcessSlot;
 use crate::processbuffer::ReadableProcessSlice;
 use crate::utilities::binary_write::BinaryToWriteWrapper;
+use crate::utilities::binary_write::BinaryWrite;
 use crate::utilities::cells::MapCell;
 use crate::utilities::cells::NumericCellExt;
 use crate::utilities::io_write::IoWrite;
@@ -133,6 +137,8 @@ pub struct PanicResources<C: Chip + 'static, PP: ProcessPrinter + 'static> {
     pub chip: MapCell<&'static C>,
     /// The tool for printing process details.
     pub printer: MapCell<&'static PP>,
+    /// Persistent storage for a record of the panic.
+    pub crash_recorder: MapCell<&'static dyn CrashRecorder>,
 }
 
 impl<C: Chip, PP: ProcessPrinter> PanicResources<C, PP> {
@@ -142,10 +148,109 @@ impl<C: Chip, PP: ProcessPrinter> PanicResources<C, PP> {
             processes: MapCell::empty(),
             chip: MapCell::empty(),
             printer: MapCell::empty(),
+            crash_recorder: MapCell::empty(),
         }
     }
 }
 
+/// What caused a crash record to be written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrashKind {
+    /// The kernel panicked.
+    KernelPanic = 1,
+    /// A process faulted.
+    ProcessFault = 2,
+}
+
+/// Persistent storage for a compact record of a crash.
+///
+/// A crash recorder keeps a summary of a kernel panic or process fault (the
+/// fault registers, the process involved, a textual summary and recent debug
+/// output) in nonvolatile storage, so that it can be inspected after the
+/// system reboots.
+///
+/// The panic routines use the recorder with interrupts disabled. After
+/// `commit()` they drive the chip's interrupt handling and deferred calls
+/// until `is_busy()` returns `false`, so the recorder may complete the write
+/// asynchronously through the usual HILs.
+pub trait CrashRecorder {
+    /// Start a new crash record, discarding any record that was started but
+    /// not committed.
+    fn begin(&self, kind: CrashKind, registers: &FaultRegisters, process_name: Option<&str>);
+
+    /// Append summary text (e.g. the panic message or process summaries) to
+    /// the current record. Text that does not fit is dropped.
+    fn append(&self, text: &[u8]);
+
+    /// Append kernel debug output to the current record. If there is more
+    /// than fits, only the most recent output is kept.
+    fn append_debug(&self, text: &[u8]);
+
+    /// Start writing the current record to storage.
+    fn commit(&self) -> Result<(), ErrorCode>;
+
+    /// Whether a committed record is still being written to storage.
+    fn is_busy(&self) -> bool;
+}
+
+/// A crash record read back from storage.
+#[derive(Clone, Copy, Debug)]
+pub struct CrashRecord<'a> {
+    /// Sequence number of the record, increasing with each record written.
+    pub sequence: u32,
+    /// What caused the record to be written.
+    pub kind: CrashKind,
+    /// Processor registers at the time of the crash.
+    pub registers: FaultRegisters,
+    /// Name of the process that faulted, empty for kernel panics.
+    pub process_name: &'a [u8],
+    /// Summary text (panic message and process summaries).
+    pub text: &'a [u8],
+    /// Kernel debug output from just before the crash.
+    pub debug: &'a [u8],
+}
+
+/// Read access to the crash records kept by a [`CrashRecorder`].
+pub trait CrashLog {
+    /// Number of valid records.
+    fn record_count(&self) -> usize;
+
+    /// Get a record, where `index` 0 is the most recent record. Returns `None`
+    /// if there is no such record.
+    fn record(&self, index: usize) -> Option<CrashRecord<'_>>;
+
+    /// Start erasing all records. Records may still be returned until the
+    /// erase completes.
+    fn clear(&self) -> Result<(), ErrorCode>;
+}
+
+/// Adapter to format summary text into a [`CrashRecorder`].
+pub struct CrashRecordWriter<'a>(pub &'a dyn CrashRecorder);
+
+impl Write for CrashRecordWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.append(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl BinaryWrite for CrashRecordWriter<'_> {
+    fn write_buffer(&mut self, buffer: &[u8]) -> Result<usize, ()> {
+        self.0.append(buffer);
+        Ok(buffer.len())
+    }
+}
+
+/// Adapter to copy the debug buffer into a [`CrashRecorder`].
+struct CrashRecordDebugWriter<'a>(&'a dyn CrashRecorder);
+
+impl IoWrite for CrashRecordDebugWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> usize {
+        self.0.append_debug(buf);
+        buf.len()
+    }
+}
+
 /// Tock panic routine, without the infinite LED-blinking loop.
 ///
 /// This is useful for boards which do not feature LEDs to blink or want to
@@ -171,6 +276,7 @@ pub unsafe fn panic_print<PW: PanicWriter, C: Chip, PP: ProcessPrinter>(
         // Flush debug buffer if needed
         flush(&mut writer);
         panic_banner(&mut writer, panic_info);
+        panic_resources.map(|pr| panic_record(panic_info, pr));
 
         panic_resources.map(|pr| {
             let chip = pr.chip.take();
@@ -239,6 +345,7 @@ pub unsafe fn panic_print_old<W: Write + IoWrite, C: Chip, PP: ProcessPrinter>(
         // Flush debug buffer if needed
         flush(writer);
         panic_banner(writer, panic_info);
+        panic_resources.map(|pr| panic_record(panic_info, pr));
 
         panic_resources.map(|pr| {
             let chip = pr.chip.take();
@@ -336,6 +443,85 @@ pub unsafe fn panic_banner<W: Write>(writer: &mut W, panic_info: &PanicInfo) {
     }
 }
 
+/// Number of times [`panic_record`] services pending interrupts and deferred
+/// calls while waiting for the crash recorder before giving up on it.
+const PANIC_RECORD_SERVICE_ATTEMPTS: usize = 10000;
+
+/// Write a persistent record of the panic, if a crash recorder is configured.
+///
+/// The record holds the fault registers, the panic message, a summary of each
+/// process and the pending contents of the debug buffer. This waits for the
+/// record to be written to storage, servicing interrupts and deferred calls
+/// at most `PANIC_RECORD_SERVICE_ATTEMPTS` times.
+///
+/// # Safety
+///
+/// This must only be called from the panic handler, after `panic_begin()`,
+/// with no other code running. It reads the fault registers with
+/// `Chip::fault_registers()`, calls `Chip::service_pending_interrupts()` and
+/// services deferred calls from the panic handler's context, and prints the
+/// processes, all of which assume that the kernel loop is no longer running.
+pub unsafe fn panic_record<C: Chip, PP: ProcessPrinter>(
+    panic_info: &PanicInfo,
+    panic_resources: &PanicResources<C, PP>,
+) {
+    let Some(recorder) = panic_resources.crash_recorder.take() else {
+        return;
+    };
+    let chip = panic_resources.chip.map(|chip| *chip);
+
+    let wait = || {
+        wait_for_crash_recorder(recorder, || {
+            chip.map(|chip| chip.service_pending_interrupts());
+            DeferredCall::service_next_pending();
+        })
+    };
+
+    // Let a record that is already being written (e.g. of the process fault
+    // that caused this panic) complete first.
+    wait();
+
+    let registers = unsafe { C::fault_registers(chip) };
+    recorder.begin(CrashKind::KernelPanic, &registers, None);
+
+    let mut writer = CrashRecordWriter(recorder);
+    unsafe {
+        panic_banner(&mut writer, panic_info);
+    }
+    panic_resources.printer.map(|printer| {
+        panic_resources.processes.map(|processes| {
+            for slot in processes.iter() {
+                slot.proc.get().map(|process| {
+                    printer.print_overview(process, &mut writer, None);
+                });
+            }
+        });
+    });
+    try_get_debug_writer(|debug_writer| {
+        debug_writer.flush(&mut CrashRecordDebugWriter(recorder));
+    });
+
+    if recorder.commit().is_ok() {
+        wait();
+    }
+}
+
+/// Call `service` until `recorder` is no longer busy, at most
+/// `PANIC_RECORD_SERVICE_ATTEMPTS` times, and return whether the recorder
+/// finished.
+///
+/// This gives up eventually rather than hang the panic handler, in case the
+/// storage never completes a write.
+fn wait_for_crash_recorder(recorder: &dyn CrashRecorder, mut service: impl FnMut()) -> bool {
+    for _ in 0..PANIC_RECORD_SERVICE_ATTEMPTS {
+        if !recorder.is_busy() {
+            return true;
+        }
+        service();
+    }
+    !recorder.is_busy()
+}
+
 /// Print current machine (CPU) state.
 ///
 /// **NOTE:** The supplied `writer` must be synchronous.
@@ -756,3 +942,62 @@ fn flush<W: Write + IoWrite>(writer: &mut W) {
         None
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A recorder which stays busy for `busy_for` more services.
+    struct Recorder {
+        busy_for: Cell<usize>,
+    }
+
+    impl CrashRecorder for Recorder {
+        fn begin(&self, _: CrashKind, _: &FaultRegisters, _: Option<&str>) {}
+
+        fn append(&self, _: &[u8]) {}
+
+        fn append_debug(&self, _: &[u8]) {}
+
+        fn commit(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn is_busy(&self) -> bool {
+            self.busy_for.get() > 0
+        }
+    }
+
+    fn wait(busy_for: usize) -> (bool, usize) {
+        let recorder = Recorder {
+            busy_for: Cell::new(busy_for),
+        };
+        let mut services = 0;
+        let finished = wait_for_crash_recorder(&recorder, || {
+            services += 1;
+            recorder
+                .busy_for
+                .set(recorder.busy_for.get().saturating_sub(1));
+        });
+        (finished, services)
+    }
+
+    #[test]
+    fn crash_recorder_wait_ends_when_the_write_completes() {
+        assert_eq!(wait(0), (true, 0));
+        assert_eq!(wait(3), (true, 3));
+        assert_eq!(
+            wait(PANIC_RECORD_SERVICE_ATTEMPTS),
+            (true, PANIC_RECORD_SERVICE_ATTEMPTS)
+        );
+    }
+
+    #[test]
+    fn crash_recorder_wait_gives_up() {
+        assert_eq!(
+            wait(PANIC_RECORD_SERVICE_ATTEMPTS + 1),
+            (false, PANIC_RECORD_SERVICE_ATTEMPTS)
+        );
+        assert_eq!(wait(usize::MAX), (false, PANIC_RECORD_SERVICE_ATTEMPTS));
+    }
+}
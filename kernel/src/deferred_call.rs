@@ -195,6 +195,31 @@ pub unsafe fn initialize_deferred_call_state_unsafe<P: ThreadIdProvider>() {
     }
 }
 
+/// Forget all created deferred calls and drop any pending ones.
+///
+/// This lets host-side test harnesses reuse the deferred call state across
+/// test cases. It has no effect if the state is not bound to the running
+/// thread.
+///
+/// # Safety
+///
+/// Callers must ensure that no [`DeferredCall`] created before this call is
+/// used again, as its index will be handed out to new deferred calls. This
+/// must never be called on a running board.
+pub unsafe fn reset_deferred_call_state() {
+    if let Some(ctr) = CTR.get() {
+        ctr.set(0);
+    }
+    if let Some(bitmask) = BITMASK.get() {
+        bitmask.set(0);
+    }
+    if let Some(defcalls) = DEFCALLS.get() {
+        for defcall in defcalls.iter() {
+            defcall.clear();
+        }
+    }
+}
+
 pub struct DeferredCall {
     idx: usize,
 }
@@ -8,9 +8,11 @@
 //! during runtime without requiring the user to restart the device.
 
 use core::cell::Cell;
+use core::cmp;
 
 use crate::ErrorCode;
 use crate::Kernel;
+use crate::ProcessId;
 use crate::config;
 use crate::debug;
 use crate::deferred_call::{DeferredCall, DeferredCallClient};
@@ -21,7 +23,7 @@ use crate::process_loading::{
     PaddingRequirement, ProcessLoadError, SequentialProcessLoaderMachine,
 };
 use crate::process_standard::ProcessStandardDebug;
-use crate::utilities::cells::{OptionalCell, TakeCell};
+use crate::utilities::cells::{MapCell, OptionalCell, TakeCell};
 use crate::utilities::leasable_buffer::SubSliceMut;
 
 /// Expected buffer length for storing application binaries.
@@ -40,6 +42,42 @@ pub enum State {
     Unload(Result<(), ErrorCode>, usize),
     PaddingWrite,
     Fail,
+    /// Loading the new binary in place of the process being updated.
+    Update,
+    /// The new process is running, writing a padding header over the binary
+    /// of the process it replaced.
+    UpdateCommit,
+    /// The new binary failed to load, writing a padding header over it.
+    Rollback,
+    /// Loading the binary of the process that was being updated again.
+    RollbackLoad,
+    /// The update is finished, and the client must be notified.
+    UpdateDone,
+    /// Moving a process binary to close a gap in flash.
+    Compact(CompactStep),
+    /// Compaction is finished after moving the given number of binaries, and
+    /// the client must be notified.
+    CompactDone(Result<(), ErrorCode>, usize),
+}
+
+/// The steps of moving one process binary during compaction.
+///
+/// The steps are ordered so that the binaries in flash can be discovered
+/// after every write, and the binary is stored at either its old or its new
+/// address if the board resets.
+#[derive(Clone, Copy, PartialEq)]
+pub enum CompactStep {
+    /// Writing one padding header over the gap before the binary.
+    MergePadding,
+    /// Copying the binary to its new address, inside the merged padding.
+    Copy,
+    /// Writing a padding header over the rest of the gap and the old binary.
+    PostPadding,
+    /// Making the binary at its new address the one after the previous
+    /// binary.
+    Link,
+    /// Loading the moved process again.
+    Reload,
 }
 
 /// Addresses of where the new process will be stored.
@@ -51,6 +89,35 @@ struct ProcessLoadMetadata {
     next_app_start_addr: usize,
     padding_requirement: PaddingRequirement,
     setup_padding: bool,
+    /// Address of the binary of the process being updated.
+    old_app_start_addr: usize,
+    old_app_length: usize,
+    /// ShortId of the process being updated.
+    update_app: Option<ShortId>,
+    /// Whether the new binary was loaded into a process during an update.
+    update_loaded: bool,
+}
+
+/// A process binary being moved during compaction.
+#[derive(Clone, Copy)]
+struct CompactMetadata {
+    /// Start of the gap before the binary.
+    gap_start: usize,
+    /// Current address of the binary.
+    from: usize,
+    /// Address the binary is moved to.
+    to: usize,
+    length: usize,
+    /// How many bytes of the binary have been copied to the new address.
+    copied: usize,
+    post_padded: bool,
+    linked: bool,
+    /// Whether the binary was loaded into a process that must be loaded
+    /// again once it is moved.
+    reload: bool,
+    /// How many binaries were moved before this one.
+    moved: usize,
+    result: Result<(), ErrorCode>,
 }
 
 /// This interface supports flashing binaries at runtime.
@@ -89,11 +156,27 @@ pub trait DynamicBinaryStore {
     /// Call to abort the setup/writing process.
     fn abort(&self) -> Result<(), ErrorCode>;
 
+    /// Move stored binaries to close the gaps between them, so that the free
+    /// space can hold larger binaries.
+    ///
+    /// Processes are stopped while their binary is moved, and started again
+    /// from the new address. The binary of `caller`, the process requesting
+    /// compaction if any, is not moved, so that it keeps running and is
+    /// notified when compaction is done.
+    fn compact(&self, caller: Option<ProcessId>) -> Result<(), ErrorCode>;
+
     /// Sets a client for the SequentialDynamicBinaryStore Object
     ///
     /// When the client operation is done, it calls the `setup_done()`,
-    /// `write_done()` and `abort_done()` functions.
+    /// `write_done()`, `abort_done()` and `compact_done()` functions.
     fn set_storage_client(&self, client: &'static dyn DynamicBinaryStoreClient);
+
+    /// Sets a client other than the storage client that requests compaction,
+    /// such as the process console.
+    ///
+    /// Both clients are notified when compaction is done, and must ignore
+    /// compactions they did not request.
+    fn set_compact_client(&self, client: &'static dyn DynamicBinaryCompactClient);
 }
 
 /// The callback for dynamic binary flashing.
@@ -110,6 +193,16 @@ pub trait DynamicBinaryStoreClient {
 
     /// Canceled any setup or writing operation and freed up reserved space.
     fn abort_done(&self, result: Result<(), ErrorCode>);
+
+    /// Finished compacting stored binaries, after moving `moved` of them.
+    fn compact_done(&self, result: Result<(), ErrorCode>, moved: usize);
+}
+
+/// The callback for compaction requested by a client other than the storage
+/// client.
+pub trait DynamicBinaryCompactClient {
+    /// Finished compacting stored binaries, after moving `moved` of them.
+    fn compact_done(&self, result: Result<(), ErrorCode>, moved: usize);
 }
 
 /// This interface supports loading processes at runtime.
@@ -117,10 +210,20 @@ pub trait DynamicProcessLoad {
     /// Call to request kernel to load a new process.
     fn load(&self) -> Result<(), ErrorCode>;
 
+    /// Call to request kernel to load the new process in place of the running
+    /// process with ShortId `app`.
+    ///
+    /// This is called instead of `load()` once the new binary is finalized.
+    /// The process being updated is stopped and unloaded, and the new binary
+    /// is checked and loaded. If that fails, the new binary is discarded and
+    /// the previous binary is loaded again. Once the new process is running,
+    /// the previous binary is discarded.
+    fn update(&self, app: ShortId) -> Result<(), ErrorCode>;
+
     /// Sets a client for the SequentialDynamicProcessLoading Object
     ///
-    /// When the client operation is done, it calls the `load_done()`
-    /// function.
+    /// When the client operation is done, it calls the `load_done()` or
+    /// `update_done()` function.
     fn set_load_client(&self, client: &'static dyn DynamicProcessLoadClient);
 }
 
@@ -128,6 +231,10 @@ pub trait DynamicProcessLoad {
 pub trait DynamicProcessLoadClient {
     /// The new app has been loaded.
     fn load_done(&self, result: Result<(), ProcessLoadError>);
+
+    /// The new app has replaced the previous version, or failed to load, in
+    /// which case the previous version has been loaded again.
+    fn update_done(&self, result: Result<(), ProcessLoadError>);
 }
 
 /// This interface supports unloading processes at runtime.
@@ -163,7 +270,14 @@ pub struct SequentialDynamicBinaryStorage<
     storage_client: OptionalCell<&'static dyn DynamicBinaryStoreClient>,
     load_client: OptionalCell<&'static dyn DynamicProcessLoadClient>,
     unload_client: OptionalCell<&'static dyn DynamicProcessUnloadClient>,
+    compact_client: OptionalCell<&'static dyn DynamicBinaryCompactClient>,
     process_metadata: OptionalCell<ProcessLoadMetadata>,
+    compact_metadata: OptionalCell<CompactMetadata>,
+    /// Start of the binary of the process which requested compaction, which
+    /// is not moved.
+    compact_caller_binary: OptionalCell<usize>,
+    /// Why the new binary failed to load during an update.
+    update_error: MapCell<ProcessLoadError>,
     state: Cell<State>,
     deferred_call: DeferredCall,
 }
@@ -185,7 +299,11 @@ impl<'a, 'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: Nonvolatil
             storage_client: OptionalCell::empty(),
             load_client: OptionalCell::empty(),
             unload_client: OptionalCell::empty(),
+            compact_client: OptionalCell::empty(),
             process_metadata: OptionalCell::empty(),
+            compact_metadata: OptionalCell::empty(),
+            compact_caller_binary: OptionalCell::empty(),
+            update_error: MapCell::empty(),
             state: Cell::new(State::Idle),
             deferred_call: DeferredCall::new(),
         }
@@ -235,7 +353,13 @@ impl<'a, 'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: Nonvolatil
             // If we are going to write the padding header, we already know
             // where to write in flash, so we don't have to add the start
             // address
-            State::Setup | State::Load | State::PaddingWrite | State::Abort => Ok(offset),
+            State::Setup
+            | State::Load
+            | State::PaddingWrite
+            | State::Abort
+            | State::UpdateCommit
+            | State::Rollback
+            | State::Compact(_) => Ok(offset),
             // We aren't supposed to be able to write unless we are in one of
             // the first two write states
             _ => Err(ErrorCode::FAIL),
@@ -359,6 +483,245 @@ impl<'a, 'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: Nonvolatil
             }
         })
     }
+
+    /// The new process is running, so discard the binary of the process it
+    /// replaced.
+    fn commit_update(&self) {
+        self.update_error.take();
+        self.state.set(State::UpdateCommit);
+        if let Some(metadata) = self.process_metadata.get() {
+            if self
+                .write_padding_app(metadata.old_app_length, metadata.old_app_start_addr)
+                .is_ok()
+            {
+                return;
+            }
+        }
+        // The previous binary stays in flash, but is superseded by the new
+        // one when loading processes at boot if the new one has a higher
+        // version.
+        if config::CONFIG.debug_load_processes {
+            debug!("Update: unable to discard the previous binary");
+        }
+        self.finish_update();
+    }
+
+    /// The new binary did not load, so discard it and load the previous
+    /// binary again.
+    fn rollback_update(&self) {
+        if self.update_error.is_none() {
+            self.update_error.replace(ProcessLoadError::InternalError);
+        }
+        self.state.set(State::Rollback);
+        if let Some(metadata) = self.process_metadata.get() {
+            if self
+                .write_padding_app(metadata.new_app_length, metadata.new_app_start_addr)
+                .is_ok()
+            {
+                return;
+            }
+        }
+        self.reload_previous_binary();
+    }
+
+    fn reload_previous_binary(&self) {
+        self.state.set(State::RollbackLoad);
+        let reloading = self.process_metadata.get().is_some_and(|metadata| {
+            self.loader_driver
+                .load_new_process_binary(metadata.old_app_start_addr, metadata.old_app_length)
+                .is_ok()
+        });
+        if !reloading {
+            if config::CONFIG.debug_load_processes {
+                debug!("Update: unable to reload the previous binary");
+            }
+            self.finish_update();
+        }
+    }
+
+    /// Notify the client the update is done from a deferred call, with the
+    /// result in `update_error`.
+    fn finish_update(&self) {
+        self.state.set(State::UpdateDone);
+        self.deferred_call.set();
+    }
+
+    /// Stop and unload the process loaded from the binary at `flash_start`.
+    ///
+    /// Returns whether there was such a process.
+    fn stop_and_remove_process(&self, flash_start: usize) -> Result<bool, ErrorCode> {
+        let Some(process) = self
+            .kernel
+            .get_process_iter()
+            .find(|proc| proc.get_addresses().flash_start == flash_start)
+        else {
+            return Ok(false);
+        };
+        process.stop();
+        self.kernel
+            .remove_process_with_flash_start(flash_start)
+            .map(|()| true)
+            .or(Err(ErrorCode::BUSY))
+    }
+
+    /// Copy `length` bytes of flash from address `from` to address `to`.
+    /// `length` must be no more than the length of the buffer.
+    fn copy_flash(&self, from: usize, to: usize, length: usize) -> Result<(), ErrorCode> {
+        let flash = self.loader_driver.get_flash_bank();
+        let offset = from - flash.as_ptr() as usize;
+        let data = flash.get(offset..offset + length).ok_or(ErrorCode::FAIL)?;
+        self.buffer.take().map_or(Err(ErrorCode::BUSY), |buffer| {
+            buffer[..length].copy_from_slice(data);
+            let mut slice = SubSliceMut::new(buffer);
+            slice.slice(..length);
+            self.write_buffer(slice, to)
+        })
+    }
+
+    /// Find the next binary to move, and start moving it.
+    fn start_compaction_move(&self, moved: usize) {
+        let Some((gap_start, from, to, length)) = self
+            .loader_driver
+            .check_flash_for_compaction(self.compact_caller_binary.get())
+        else {
+            self.finish_compaction(Ok(()), moved);
+            return;
+        };
+        if config::CONFIG.debug_load_processes {
+            debug!("Compaction: moving binary at {:#x} to {:#x}", from, to);
+        }
+
+        // The process cannot run while its binary is moved.
+        let reload = match self.stop_and_remove_process(from) {
+            Ok(reload) => reload,
+            Err(e) => {
+                self.finish_compaction(Err(e), moved);
+                return;
+            }
+        };
+        let metadata = CompactMetadata {
+            gap_start,
+            from,
+            to,
+            length,
+            // If the binary is moved to the start of the gap, writing its
+            // header links it in, so the header is written last.
+            copied: if to == gap_start { 8 } else { 0 },
+            post_padded: false,
+            linked: false,
+            reload,
+            moved,
+            result: Ok(()),
+        };
+        self.compact_metadata.set(metadata);
+
+        // Replace all padding in the gap with one padding header, so that
+        // the copy cannot overwrite any of their headers.
+        self.state.set(State::Compact(CompactStep::MergePadding));
+        if let Err(e) = self.write_padding_app(from - gap_start, gap_start) {
+            self.fail_compaction_move(e);
+        }
+    }
+
+    /// Perform the next write to move the binary.
+    fn continue_compaction_move(&self, metadata: CompactMetadata) {
+        // The padding after the moved binary starts at the old binary's
+        // header if the binary moves by its length, so the moved binary must
+        // be linked in first. Otherwise the padding is written first, so the
+        // moved binary is followed by valid padding as soon as it is linked.
+        let link_first = metadata.to + metadata.length == metadata.from;
+
+        let result = if metadata.copied < metadata.length {
+            let chunk = cmp::min(
+                self.buffer.map_or(0, |buffer| buffer.len()),
+                metadata.length - metadata.copied,
+            );
+            self.compact_metadata.set(CompactMetadata {
+                copied: metadata.copied + chunk,
+                ..metadata
+            });
+            self.state.set(State::Compact(CompactStep::Copy));
+            self.copy_flash(
+                metadata.from + metadata.copied,
+                metadata.to + metadata.copied,
+                chunk,
+            )
+        } else if !metadata.post_padded && (metadata.linked || !link_first) {
+            self.state.set(State::Compact(CompactStep::PostPadding));
+            self.write_padding_app(metadata.from - metadata.to, metadata.to + metadata.length)
+        } else if !metadata.linked {
+            self.state.set(State::Compact(CompactStep::Link));
+            if metadata.to == metadata.gap_start {
+                self.copy_flash(metadata.from, metadata.to, 8)
+            } else {
+                self.write_padding_app(metadata.to - metadata.gap_start, metadata.gap_start)
+            }
+        } else {
+            self.reload_moved_binary(metadata);
+            return;
+        };
+
+        if let Err(e) = result {
+            self.fail_compaction_move(e);
+        }
+    }
+
+    /// Load the process from wherever its binary is now, if it was loaded
+    /// before the move.
+    fn reload_moved_binary(&self, metadata: CompactMetadata) {
+        if metadata.reload {
+            let address = if metadata.linked {
+                metadata.to
+            } else {
+                metadata.from
+            };
+            self.state.set(State::Compact(CompactStep::Reload));
+            match self
+                .loader_driver
+                .load_new_process_binary(address, metadata.length)
+            {
+                Ok(()) => return,
+                Err(_e) => self.compact_metadata.set(CompactMetadata {
+                    result: metadata.result.and(Err(ErrorCode::FAIL)),
+                    ..metadata
+                }),
+            }
+        }
+        self.finish_compaction_move();
+    }
+
+    /// Move on to the next binary, or stop if moving this binary failed.
+    fn finish_compaction_move(&self) {
+        match self.compact_metadata.get() {
+            Some(metadata) => match metadata.result {
+                Ok(()) => self.start_compaction_move(metadata.moved + 1),
+                Err(e) => self.finish_compaction(Err(e), metadata.moved),
+            },
+            None => self.finish_compaction(Err(ErrorCode::FAIL), 0),
+        }
+    }
+
+    /// A write to move the binary failed. Stop compacting, and start the
+    /// process again from wherever its binary is now.
+    fn fail_compaction_move(&self, error: ErrorCode) {
+        match self.compact_metadata.get() {
+            Some(metadata) => {
+                let metadata = CompactMetadata {
+                    result: Err(error),
+                    ..metadata
+                };
+                self.compact_metadata.set(metadata);
+                self.reload_moved_binary(metadata);
+            }
+            None => self.finish_compaction(Err(error), 0),
+        }
+    }
+
+    /// Notify the client compaction is done from a deferred call.
+    fn finish_compaction(&self, result: Result<(), ErrorCode>, moved: usize) {
+        self.state.set(State::CompactDone(result, moved));
+        self.deferred_call.set();
+    }
 }
 
 impl<'b, C: Chip, D: ProcessStandardDebug, F: NonvolatileStorage<'b>> DeferredCallClient
@@ -379,6 +742,26 @@ impl<'b, C: Chip, D: ProcessStandardDebug, F: NonvolatileStorage<'b>> DeferredCa
                     client.unload_done(result, app_handle);
                 });
             }
+            State::UpdateDone => {
+                self.reset_process_loading_metadata();
+                let result = self.update_error.take().map_or(Ok(()), Err);
+
+                self.load_client.map(|client| {
+                    client.update_done(result);
+                });
+            }
+            State::CompactDone(result, moved) => {
+                self.reset_process_loading_metadata();
+                self.compact_metadata.clear();
+                self.compact_caller_binary.clear();
+
+                self.storage_client.map(|client| {
+                    client.compact_done(result, moved);
+                });
+                self.compact_client.map(|client| {
+                    client.compact_done(result, moved);
+                });
+            }
             _ => {}
         }
     }
@@ -463,10 +846,32 @@ impl<'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: NonvolatileSto
                     client.abort_done(Ok(()));
                 });
             }
-            State::Unload(_, _) => {
+            State::UpdateCommit => {
                 self.buffer.replace(buffer);
+                self.finish_update();
             }
-            State::Idle => {
+            State::Rollback => {
+                self.buffer.replace(buffer);
+                self.reload_previous_binary();
+            }
+            State::Compact(step) => {
+                self.buffer.replace(buffer);
+                if let Some(mut metadata) = self.compact_metadata.get() {
+                    match step {
+                        CompactStep::PostPadding => metadata.post_padded = true,
+                        CompactStep::Link => metadata.linked = true,
+                        _ => {}
+                    }
+                    self.compact_metadata.set(metadata);
+                    self.continue_compaction_move(metadata);
+                }
+            }
+            State::Unload(_, _)
+            | State::Update
+            | State::RollbackLoad
+            | State::UpdateDone
+            | State::CompactDone(_, _)
+            | State::Idle => {
                 self.buffer.replace(buffer);
             }
         }
@@ -478,15 +883,80 @@ impl<'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: NonvolatileSto
     ProcessLoadingAsyncClient for SequentialDynamicBinaryStorage<'_, 'b, C, D, F>
 {
     fn process_loaded(&self, result: Result<(), ProcessLoadError>) {
-        self.load_client.map(|client| {
-            client.load_done(result);
-        });
+        match self.state.get() {
+            State::Update => match result {
+                Ok(()) => {
+                    if let Some(mut metadata) = self.process_metadata.get() {
+                        // The new binary must be a version of the process it
+                        // replaces, otherwise it is unloaded again and the
+                        // update rolled back.
+                        let replaces_app = self
+                            .kernel
+                            .get_process_iter()
+                            .find(|proc| {
+                                proc.get_addresses().flash_start == metadata.new_app_start_addr
+                            })
+                            .is_some_and(|proc| Some(proc.short_app_id()) == metadata.update_app);
+                        if replaces_app {
+                            metadata.update_loaded = true;
+                            self.process_metadata.set(metadata);
+                        } else {
+                            let _ = self.stop_and_remove_process(metadata.new_app_start_addr);
+                            self.update_error.replace(ProcessLoadError::ShortIdMismatch);
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.update_error.replace(e);
+                }
+            },
+            State::RollbackLoad => {
+                if config::CONFIG.debug_load_processes {
+                    debug!("Update: reloaded previous binary: {:?}", result);
+                }
+            }
+            State::Compact(_) => {
+                if let Err(e) = result {
+                    if config::CONFIG.debug_load_processes {
+                        debug!("Compaction: unable to reload moved process: {:?}", e);
+                    }
+                    self.compact_metadata.get().map(|metadata| {
+                        self.compact_metadata.set(CompactMetadata {
+                            result: metadata.result.and(Err(ErrorCode::FAIL)),
+                            ..metadata
+                        });
+                    });
+                }
+            }
+            _ => {
+                self.load_client.map(|client| {
+                    client.load_done(result);
+                });
+            }
+        }
     }
 
     fn process_loading_finished(&self) {
-        self.load_client.map(|client| {
-            client.load_done(Ok(()));
-        });
+        match self.state.get() {
+            State::Update => {
+                if self
+                    .process_metadata
+                    .get()
+                    .is_some_and(|metadata| metadata.update_loaded)
+                {
+                    self.commit_update();
+                } else {
+                    self.rollback_update();
+                }
+            }
+            State::RollbackLoad => self.finish_update(),
+            State::Compact(_) => self.finish_compaction_move(),
+            _ => {
+                self.load_client.map(|client| {
+                    client.load_done(Ok(()));
+                });
+            }
+        }
     }
 }
 
@@ -498,6 +968,10 @@ impl<'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: NonvolatileSto
         self.storage_client.set(client);
     }
 
+    fn set_compact_client(&self, client: &'static dyn DynamicBinaryCompactClient) {
+        self.compact_client.set(client);
+    }
+
     fn setup(&self, app_length: usize) -> Result<usize, ErrorCode> {
         self.process_metadata.set(ProcessLoadMetadata::default());
 
@@ -675,6 +1149,21 @@ impl<'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: NonvolatileSto
             }
         }
     }
+
+    fn compact(&self, caller: Option<ProcessId>) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Idle => {
+                let caller_binary = caller.and_then(|caller| {
+                    self.kernel
+                        .process_map_or(None, caller, |proc| Some(proc.get_addresses().flash_start))
+                });
+                self.compact_caller_binary.insert(caller_binary);
+                self.start_compaction_move(0);
+                Ok(())
+            }
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
 }
 
 /// Loading interface exposed to the app_loader capsule
@@ -711,6 +1200,46 @@ impl<'b, C: Chip + 'static, D: ProcessStandardDebug + 'static, F: NonvolatileSto
             _ => Err(ErrorCode::INVAL),
         }
     }
+
+    fn update(&self, app: ShortId) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Load {
+            return Err(ErrorCode::INVAL);
+        }
+        let Some(mut metadata) = self.process_metadata.get() else {
+            self.reset_process_loading_metadata();
+            return Err(ErrorCode::FAIL);
+        };
+
+        // Stop and unload the process being updated, keeping track of where
+        // its binary is so it can be loaded again if the update fails.
+        let mut old_app_end_addr = 0;
+        let old_app_start_addr = self
+            .kernel
+            .remove_process_from_active_processes(app, |proc| {
+                let addresses = proc.get_addresses();
+                old_app_end_addr = addresses.flash_end;
+                addresses.flash_start
+            })
+            .or(Err(ErrorCode::INVAL))?;
+        metadata.old_app_start_addr = old_app_start_addr;
+        metadata.old_app_length = old_app_end_addr - old_app_start_addr;
+        metadata.update_app = Some(app);
+        metadata.update_loaded = false;
+        self.process_metadata.set(metadata);
+        self.update_error.take();
+
+        // The new binary is checked and loaded asynchronously, and from here
+        // on any failure rolls back to the previous binary.
+        self.state.set(State::Update);
+        if let Err(e) = self
+            .loader_driver
+            .load_new_process_binary(metadata.new_app_start_addr, metadata.new_app_length)
+        {
+            self.update_error.replace(e);
+            self.rollback_update();
+        }
+        Ok(())
+    }
 }
 
 /// Loading interface exposed to the app_loader capsule
@@ -0,0 +1,94 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Bluetooth Low Energy link layer radio HIL
+//!
+//! Interface for radios that can take part in a BLE connection as the
+//! peripheral. Connections need tighter timing than advertising: after a
+//! packet ends the peer expects an answer exactly T_IFS (150 us) later, which
+//! is too short to go through a deferred callback in the kernel. This HIL
+//! therefore offers two combined operations that the radio performs back to
+//! back in hardware:
+//!
+//! - `transmit_then_receive` sends a packet and then listens on the same
+//!   channel. This is used for connectable advertising, where a central
+//!   answers an `ADV_IND` with a `CONNECT_IND`.
+//! - `receive_then_transmit` listens for a packet and then sends a response.
+//!   This is one exchange of a connection event. The response is passed in
+//!   when the operation starts: there is no time to involve software between
+//!   the two packets, so it cannot depend on the packet that is received.
+//!
+//! Both operations keep listening until a packet is received or `stop` is
+//! called. The link layer is responsible for timeouts, so `stop` hands the
+//! buffer back directly instead of through a callback.
+//!
+//! Packets are passed without access address and CRC, i.e. they start with
+//! the 2 byte PDU header. See the Bluetooth Core Specification v4.2 [Vol 6,
+//! Part B], section 2.
+//!
+//! ```text
+//! +----------------+      +---------------------+      +----------------+
+//! | Link layer     | ---> | BleConnectionDriver | ---> | Radio hardware |
+//! | (capsule)      | <--- | ConnectionClient    | <--- |                |
+//! +----------------+      +---------------------+      +----------------+
+//! ```
+
+use crate::ErrorCode;
+use crate::hil::ble_advertising::RadioChannel;
+
+/// Access address used on the advertising channels.
+///
+/// Bluetooth Core Specification v4.2 [Vol 6, Part B], section 2.1.2
+pub const ADVERTISING_ACCESS_ADDRESS: u32 = 0x8e89bed6;
+
+/// CRC initialisation value used on the advertising channels.
+pub const ADVERTISING_CRC_INIT: u32 = 0x555555;
+
+pub trait BleConnectionDriver<'a> {
+    /// Set the access address and CRC initialisation value (24 bits) used for
+    /// all following operations. Connections use the values from the
+    /// `CONNECT_IND` PDU, advertising uses `ADVERTISING_ACCESS_ADDRESS` and
+    /// `ADVERTISING_CRC_INIT`.
+    fn set_access_address(&self, access_address: u32, crc_init: u32);
+
+    /// Transmit the first `len` bytes of `buf` on `channel` and then listen on
+    /// the same channel. When a packet is received it is copied into `buf`
+    /// and `operation_done` is called with its length. Returns
+    /// `ErrorCode::FAIL` if the radio does not start.
+    fn transmit_then_receive(
+        &self,
+        buf: &'static mut [u8],
+        len: usize,
+        channel: RadioChannel,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Listen on `channel` and transmit the first `len` bytes of `buf` T_IFS
+    /// after the end of the received packet. The response is sent even if
+    /// the received packet had an invalid CRC. Once it has been sent, the
+    /// received packet is copied into `buf` and `operation_done` is called
+    /// with its length. Returns `ErrorCode::FAIL` if the radio does not start.
+    fn receive_then_transmit(
+        &self,
+        buf: &'static mut [u8],
+        len: usize,
+        channel: RadioChannel,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Abort the current operation and return its buffer. `operation_done`
+    /// is not called for a stopped operation. Returns `ErrorCode::OFF` if no
+    /// operation was in progress.
+    fn stop(&self) -> Result<&'static mut [u8], ErrorCode>;
+
+    fn set_connection_client(&self, client: &'a dyn ConnectionClient);
+}
+
+pub trait ConnectionClient {
+    /// An operation finished. `rx_len` is the length of the received packet,
+    /// which is now stored in `buf`.
+    ///
+    /// `result` is `Err(ErrorCode::FAIL)` if the received packet had an
+    /// invalid CRC, or, with an `rx_len` of 0, if the radio did not turn
+    /// around between the packets and the operation was aborted.
+    fn operation_done(&self, buf: &'static mut [u8], rx_len: usize, result: Result<(), ErrorCode>);
+}
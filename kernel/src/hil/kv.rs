@@ -34,6 +34,9 @@
 //!
 //!    hil::flash
 //! ```
+//!
+//! KV stores can also implement `KVHealth` to report how worn and how full
+//! their underlying storage is.
 
 use crate::ErrorCode;
 use crate::storage_permissions::StoragePermissions;
@@ -76,7 +79,8 @@ pub trait KVClient {
     /// - `result`: `Ok(())` on success, `Err(ErrorCode)` on error. Valid
     ///   `ErrorCode`s:
     ///   - `NOSUPPORT`: The caller does not have permission to store this key.
-    ///   - `NOMEM`: The key could not be set because the KV store is full.
+    ///   - `NOMEM`: The key could not be set because the KV store is full
+    ///     or the caller's storage quota would be exceeded.
     ///   - `SIZE`: The key could not be set because the key or value is too
     ///     many bytes.
     ///   - `FAIL`: An internal error occurred and the operation cannot be
@@ -97,7 +101,8 @@ pub trait KVClient {
     /// - `result`: `Ok(())` on success, `Err(ErrorCode)` on error. Valid
     ///   `ErrorCode`s:
     ///   - `NOSUPPORT`: The key already exists and cannot be added.
-    ///   - `NOMEM`: The key could not be added because the KV store is full.
+    ///   - `NOMEM`: The key could not be added because the KV store is full
+    ///     or the caller's storage quota would be exceeded.
     ///   - `SIZE`: The key could not be set because the key or value is too
     ///     many bytes.
     ///   - `FAIL`: An internal error occurred and the operation cannot be
@@ -119,7 +124,8 @@ pub trait KVClient {
     ///   `ErrorCode`s:
     ///   - `NOSUPPORT`: The key does not already exist and cannot be modified
     ///     or the caller does not have permission to modify this key.
-    ///   - `NOMEM`: The key could not be updated because the KV store is full.
+    ///   - `NOMEM`: The key could not be updated because the KV store is full
+    ///     or the caller's storage quota would be exceeded.
     ///   - `SIZE`: The key could not be set because the key or value is too
     ///     many bytes.
     ///   - `FAIL`: An internal error occurred and the operation cannot be
@@ -155,6 +161,20 @@ pub trait KVClient {
     ///   - `FAIL`: An internal error occurred and the operation cannot be
     ///     completed.
     fn garbage_collection_complete(&self, result: Result<(), ErrorCode>);
+
+    /// This callback is called when the list keys operation completes.
+    ///
+    /// ### Return Values
+    ///
+    /// - `result`: `Ok(count)` on success, where `count` is the number of keys
+    ///   the caller has stored, `Err(ErrorCode)` on error. Valid `ErrorCode`s:
+    ///   - `SIZE`: Not all keys fit in the `keys` buffer. The keys that fit are
+    ///     provided.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    /// - `keys`: The keys buffer, sliced to the bytes written. Each key is
+    ///   stored as a one byte length followed by the key.
+    fn list_keys_complete(&self, result: Result<usize, ErrorCode>, keys: SubSliceMut<'static, u8>);
 }
 
 /// Key-Value interface with permissions.
@@ -343,6 +363,27 @@ pub trait KVPermissions<'a> {
     ///     completed.
     fn garbage_collect(&self) -> Result<(), ErrorCode>;
 
+    /// List the keys stored with the `write_id` of `permissions`.
+    ///
+    /// ### Arguments
+    ///
+    /// - `keys`: The buffer the keys will be written to.
+    /// - `permissions`: The read/write/modify permissions for this access.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A callback will be issued.
+    /// - On error, returns the buffer and:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `INVAL`: The caller does not have write permissions.
+    ///   - `NOSUPPORT`: The store does not keep track of the keys stored with
+    ///     the `write_id`, for example because it has no storage quota.
+    fn list_keys(
+        &self,
+        keys: SubSliceMut<'static, u8>,
+        permissions: StoragePermissions,
+    ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)>;
+
     /// Returns the length of the key-value store's header in bytes.
     ///
     /// Room for this header must be accommodated in a `set`, `add`, or `update`
@@ -517,3 +558,65 @@ pub trait KV<'a> {
     ///     completed.
     fn garbage_collect(&self) -> Result<(), ErrorCode>;
 }
+
+/// Wear and capacity information about one region of a KV store.
+///
+/// A region is the unit of storage the KV store erases at once. The used,
+/// reclaimable and free bytes add up to the size of the region.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct KVRegionHealth {
+    /// The number of times the region has been erased.
+    pub erase_count: u32,
+    /// The number of bytes used by stored values and their metadata.
+    pub used: usize,
+    /// The number of bytes used by deleted values, which garbage collection
+    /// can reclaim.
+    pub reclaimable: usize,
+    /// The number of free bytes.
+    pub free: usize,
+}
+
+/// Callback trait for KV store health queries.
+///
+/// Implement this trait and use `set_health_client()` to receive callbacks.
+pub trait KVHealthClient {
+    /// This callback is called when the region health operation completes.
+    ///
+    /// ### Return Values
+    ///
+    /// - `result`: `Ok(health)` on success, `Err(ErrorCode)` on error. Valid
+    ///   `ErrorCode`s:
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    /// - `region`: The region that was read.
+    fn region_health_complete(&self, result: Result<KVRegionHealth, ErrorCode>, region: usize);
+}
+
+/// Key-Value store health interface.
+///
+/// This interface reports the erase counts and capacity of the storage
+/// underneath a KV store, one region at a time, so that flash wear-out can be
+/// predicted before it happens.
+pub trait KVHealth<'a> {
+    /// Set the health client.
+    fn set_health_client(&self, client: &'a dyn KVHealthClient);
+
+    /// Returns the number of regions in the KV store.
+    fn region_count(&self) -> usize;
+
+    /// Read the wear and capacity information of a region.
+    ///
+    /// ### Arguments
+    ///
+    /// - `region`: The region to read, less than `region_count()`.
+    ///
+    /// ### Return
+    ///
+    /// - On success returns `Ok(())`. A callback will be issued.
+    /// - On error returns:
+    ///   - `BUSY`: An operation is already in progress.
+    ///   - `INVAL`: The region does not exist.
+    ///   - `FAIL`: An internal error occurred and the operation cannot be
+    ///     completed.
+    fn region_health(&self, region: usize) -> Result<(), ErrorCode>;
+}
@@ -401,6 +401,15 @@ pub trait Timer<'a>: Time {
 // they can never be constructed, it forces them to be used purely as
 // type-markers which are guaranteed to be elided at runtime.
 
+/// 1GHz `Frequency`
+#[derive(Debug)]
+pub enum Freq1GHz {}
+impl Frequency for Freq1GHz {
+    fn frequency() -> u32 {
+        1_000_000_000
+    }
+}
+
 /// 600MHz `Frequency`
 #[derive(Debug)]
 pub enum Freq600MHz {}
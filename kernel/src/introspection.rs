@@ -147,6 +147,47 @@ impl KernelInfo {
         (used, number_of_grants)
     }
 
+    /// Returns the CPU time, in microseconds, the app has used since it was
+    /// last started. Time the app ran without a timeslice is not counted.
+    pub fn app_cpu_time_us(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> u64 {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_cpu_time_us())
+    }
+
+    /// Returns entry `index` of the per-driver syscall counts of the app, as a
+    /// tuple of (driver number, number of syscalls). Returns `None` if there is
+    /// no such entry.
+    pub fn app_driver_syscall_count(
+        &self,
+        app: ProcessId,
+        index: usize,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Option<(usize, usize)> {
+        self.kernel.process_map_or(None, app, |process| {
+            process.debug_driver_syscall_count(index)
+        })
+    }
+
+    /// Returns a tuple of the (the largest number of bytes of memory the app
+    /// has had accessible to it, the largest number of bytes of the app's
+    /// memory the kernel has used for grants) since the app was last started.
+    pub fn app_memory_high_water_marks(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> (usize, usize) {
+        self.kernel.process_map_or((0, 0), app, |process| {
+            (
+                process.debug_app_memory_high_water_mark(),
+                process.debug_grant_memory_high_water_mark(),
+            )
+        })
+    }
+
     /// Returns the total number of times all processes have exceeded
     /// their timeslices.
     pub fn timeslice_expirations(&self, _capability: &dyn ProcessManagementCapability) -> usize {
@@ -6,6 +6,10 @@
 //!
 //! This is a special syscall driver that allows userspace applications to
 //! share memory.
+//!
+//! Processes that only need to exchange messages can instead use the
+//! message-queue IPC driver in `capsules_extra::ipc_mailbox`, which copies
+//! messages between processes and does not share memory.
 
 use crate::ErrorCode;
 use crate::capabilities::MemoryAllocationCapability;
@@ -33,8 +33,9 @@ use crate::syscall::SyscallDriver;
 use crate::syscall::{ContextSwitchReason, SyscallReturn};
 use crate::syscall::{Syscall, YieldVariant};
 use crate::syscall_driver::CommandReturn;
+use crate::syscall_trace::SyscallTracer;
 use crate::upcall::{Upcall, UpcallId};
-use crate::utilities::cells::NumericCellExt;
+use crate::utilities::cells::{NumericCellExt, OptionalCell};
 
 /// Threshold in microseconds to consider a process's timeslice to be exhausted.
 /// That is, Tock will skip re-scheduling a process if its remaining timeslice
@@ -60,6 +61,10 @@ pub struct Kernel {
     /// created and the data structures for grants have already been
     /// established.
     grants_finalized: Cell<bool>,
+
+    /// Receives the system calls of processes if the `record_syscalls`
+    /// configuration option is enabled.
+    syscall_tracer: OptionalCell<&'static dyn SyscallTracer>,
 }
 
 /// Represents the different outcomes when trying to allocate a grant region
@@ -94,6 +99,7 @@ impl Kernel {
             process_identifier_max: Cell::new(0),
             grant_counter: Cell::new(0),
             grants_finalized: Cell::new(false),
+            syscall_tracer: OptionalCell::empty(),
         }
     }
 
@@ -338,6 +344,32 @@ impl Kernel {
         Err(())
     }
 
+    /// Terminate and remove the process whose binary starts at `flash_start`
+    /// from the processes array.
+    ///
+    /// The process must have been stopped first: a process which is still
+    /// running or yielded is not removed, and `Err(())` is returned.
+    pub(crate) fn remove_process_with_flash_start(&self, flash_start: usize) -> Result<(), ()> {
+        for slot in self.processes.iter() {
+            if let Some(process) = slot.get() {
+                if process.get_addresses().flash_start == flash_start {
+                    if matches!(
+                        process.get_state(),
+                        process::State::Running
+                            | process::State::Yielded
+                            | process::State::YieldedFor(_)
+                    ) {
+                        return Err(());
+                    }
+                    process.terminate(None);
+                    slot.proc.set(None);
+                    return Ok(());
+                }
+            }
+        }
+        Err(())
+    }
+
     /// Cause all apps to fault.
     ///
     /// This will call `set_fault_state()` on each app, causing the app to enter
@@ -354,6 +386,29 @@ impl Kernel {
         }
     }
 
+    /// Set the tracer that receives the system calls processes make.
+    ///
+    /// The tracer is only used if the kernel is built with the
+    /// `record_syscalls` configuration option, otherwise it is never called.
+    /// Only callers with the `ProcessManagementCapability` can set the tracer,
+    /// as it sees the arguments of every system call.
+    pub fn set_syscall_tracer(
+        &self,
+        tracer: &'static dyn SyscallTracer,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.syscall_tracer.set(tracer);
+    }
+
+    /// Report the value returned to `processid` for its last system call to
+    /// the syscall tracer, if there is one.
+    pub(crate) fn trace_syscall_return(&self, processid: ProcessId, return_value: &SyscallReturn) {
+        if config::CONFIG.record_syscalls {
+            self.syscall_tracer
+                .map(|tracer| tracer.syscall_returned(processid, return_value));
+        }
+    }
+
     /// Perform one iteration of the core Tock kernel loop.
     ///
     /// This function is responsible for three main operations:
@@ -749,6 +804,11 @@ impl Kernel {
             }
         });
 
+        // Charge the time to the process for accounting.
+        if let Some(time_executed_us) = time_executed_us {
+            process.debug_cpu_time_used(time_executed_us);
+        }
+
         // Reset the scheduler timer in case it unconditionally triggers
         // interrupts upon expiration. We do not want it to expire while the
         // chip is sleeping, for example.
@@ -772,6 +832,11 @@ impl Kernel {
         // Hook for process debugging.
         process.debug_syscall_called(syscall);
 
+        if config::CONFIG.record_syscalls {
+            self.syscall_tracer
+                .map(|tracer| tracer.syscall_called(process.processid(), &syscall));
+        }
+
         // Enforce platform-specific syscall filtering here.
         //
         // Before continuing to handle non-yield syscalls the kernel first
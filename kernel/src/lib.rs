@@ -175,6 +175,7 @@ pub mod processbuffer;
 pub mod scheduler;
 pub mod storage_permissions;
 pub mod syscall;
+pub mod syscall_trace;
 pub mod upcall;
 pub mod utilities;
 
@@ -96,6 +96,31 @@ pub trait Chip {
     /// information if it depends on runtime-accessible state in `Self`, but
     /// that reference is not provided.
     unsafe fn print_state(this: Option<&Self>, writer: &mut dyn Write);
+
+    /// Capture the processor registers describing the most recent fault, for
+    /// inclusion in a persistent crash record.
+    ///
+    /// Like `print_state()`, this may be called from a panic handler and so
+    /// accepts an `Option<&Self>` instead of `&self`. Chips that do not
+    /// support this return [`FaultRegisters::default()`], with all registers
+    /// set to zero.
+    unsafe fn fault_registers(_this: Option<&Self>) -> FaultRegisters {
+        FaultRegisters::default()
+    }
+}
+
+/// Processor registers captured at the time of a fault.
+///
+/// Registers that are unknown or that the architecture does not have are zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FaultRegisters {
+    /// The program counter of the faulting instruction.
+    pub pc: usize,
+    /// The link register (return address) at the time of the fault.
+    pub lr: usize,
+    /// Architecture-specific fault status registers. On Cortex-M these are
+    /// CFSR, HFSR, MMFAR and BFAR; on RISC-V mcause, mtval and mstatus.
+    pub status: [usize; 4],
 }
 
 /// Interface for retrieving the currently executing thread.
@@ -14,6 +14,7 @@ use crate::capabilities;
 use crate::errorcode::ErrorCode;
 use crate::ipc;
 use crate::kernel::Kernel;
+use crate::platform::chip::FaultRegisters;
 use crate::platform::mpu::{self};
 use crate::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
 use crate::storage_permissions;
@@ -212,6 +213,20 @@ impl ProcessId {
         })
     }
 
+    /// Get the TBF command permissions of the process for `driver_num`. This
+    /// lets capsules check permissions for operations that are more specific
+    /// than a single system call. Returns `None` if the process does not
+    /// exist.
+    pub fn get_command_permissions(
+        &self,
+        driver_num: usize,
+        offset: usize,
+    ) -> Option<CommandPermissions> {
+        self.kernel.process_map_or(None, *self, |process| {
+            Some(process.get_command_permissions(driver_num, offset))
+        })
+    }
+
     /// Get the storage permissions for the process. These permissions indicate
     /// what the process is allowed to read and write. Returns `None` if the
     /// process has no storage permissions.
@@ -850,10 +865,50 @@ pub trait Process {
     /// binary representation. Returns `ErrorCode::FAIL` on an internal error.
     fn get_stored_state(&self, out: &mut [u8]) -> Result<usize, ErrorCode>;
 
+    /// Write a checkpoint of this process into the `out` slice. Returns the
+    /// number of bytes written to `out` on success.
+    ///
+    /// A checkpoint captures the process-accessible RAM, the location of the
+    /// app break, and the stored architecture state (registers) of the
+    /// process, tagged with a hash of the process binary. Grant regions,
+    /// pending upcalls, and buffers shared with capsules are _not_ part of the
+    /// checkpoint.
+    ///
+    /// Returns `ErrorCode::SIZE` if `out` is too short to hold the checkpoint,
+    /// `ErrorCode::BUSY` if the process is in a state that cannot be
+    /// checkpointed (e.g. it is waiting on a specific upcall), and
+    /// `ErrorCode::FAIL` if the process is inactive.
+    fn checkpoint(&self, out: &mut [u8]) -> Result<usize, ErrorCode>;
+
+    /// Restore this process from a checkpoint previously created with
+    /// `checkpoint()`.
+    ///
+    /// The checkpoint is only applied if it was taken from the same process
+    /// binary and with the same memory layout. On success, the process's RAM
+    /// and registers are replaced with the ones from the checkpoint, any
+    /// pending tasks are discarded, and the process will resume execution
+    /// where it was checkpointed. A process that was yielded when the
+    /// checkpoint was taken resumes as if its yield returned without an
+    /// upcall.
+    ///
+    /// This must only be called on a process that has been started but has
+    /// not run yet, i.e. right after process loading and before the kernel
+    /// loop starts. Returns `ErrorCode::INVAL` if `snapshot` does not hold a
+    /// valid checkpoint for this process, and `ErrorCode::FAIL` if the process
+    /// is not in a state where it can be restored.
+    fn restore_checkpoint(&self, snapshot: &[u8]) -> Result<(), ErrorCode>;
+
     /// Print out the full state of the process: its memory map, its context,
     /// and the state of the memory protection unit (MPU).
     fn print_full_process(&self, writer: &mut dyn Write);
 
+    /// Return the fault registers of this process, taken from its stored
+    /// state, for inclusion in a crash record. Only meaningful after the
+    /// process has faulted.
+    fn fault_registers(&self) -> FaultRegisters {
+        FaultRegisters::default()
+    }
+
     // debug
 
     /// Returns how many syscalls this app has called.
@@ -875,6 +930,76 @@ pub trait Process {
     /// Return the last syscall the process called. Returns `None` if the
     /// process has not called any syscalls or the information is unknown.
     fn debug_syscall_last(&self) -> Option<Syscall>;
+
+    /// Add `time_us` microseconds to the CPU time the process has used.
+    ///
+    /// The default implementation does not record CPU time.
+    fn debug_cpu_time_used(&self, _time_us: u32) {}
+
+    /// Returns how much CPU time, in microseconds, this process has used since
+    /// it was last started. Only time the process ran with a timeslice is
+    /// counted, as the kernel does not time processes that run cooperatively.
+    ///
+    /// The default implementation returns 0.
+    fn debug_cpu_time_us(&self) -> u64 {
+        0
+    }
+
+    /// Returns entry `index` of the count of syscalls this process has made to
+    /// each driver, as a tuple of (driver number, number of syscalls). Only
+    /// syscalls with a driver number (subscribe, command and allows) are
+    /// counted. Returns `None` if there is no such entry, and entries are
+    /// contiguous from index 0.
+    ///
+    /// The default implementation has no entries.
+    fn debug_driver_syscall_count(&self, _index: usize) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Returns the largest amount of memory, in bytes, this process has had
+    /// accessible to it (from the start of its memory to its app break) since
+    /// it was last started. If the process does not record this, the current
+    /// amount is returned, as the default implementation does.
+    fn debug_app_memory_high_water_mark(&self) -> usize {
+        let addresses = self.get_addresses();
+        addresses.sram_app_brk - addresses.sram_start
+    }
+
+    /// Returns the largest amount of memory, in bytes, the kernel has used for
+    /// grants and other kernel data structures in this process's memory since
+    /// it was last started. If the process does not record this, the current
+    /// amount is returned, as the default implementation does.
+    fn debug_grant_memory_high_water_mark(&self) -> usize {
+        let addresses = self.get_addresses();
+        addresses.sram_end - addresses.sram_grant_start
+    }
+}
+
+/// Saves checkpoints of processes (see [`Process::checkpoint`]) to persistent
+/// storage.
+pub trait ProcessCheckpointer<'a> {
+    /// Checkpoint the process `processid`. On success,
+    /// [`CheckpointClient::checkpoint_done`] is called once the checkpoint
+    /// has been saved.
+    fn checkpoint(&self, processid: ProcessId) -> Result<(), ErrorCode>;
+
+    /// Checkpoint every process, one after the other. On success,
+    /// [`CheckpointClient::checkpoint_done`] is called for each process,
+    /// followed by [`CheckpointClient::checkpoint_all_done`].
+    fn checkpoint_all(&self) -> Result<(), ErrorCode>;
+
+    fn set_client(&self, client: &'a dyn CheckpointClient);
+}
+
+/// Receives the result of checkpointing processes with a
+/// [`ProcessCheckpointer`].
+pub trait CheckpointClient {
+    /// Called when the checkpoint of `processid` has been saved, or could not
+    /// be taken.
+    fn checkpoint_done(&self, processid: ProcessId, result: Result<(), ErrorCode>);
+
+    /// Called after `checkpoint_all()` has handled every process.
+    fn checkpoint_all_done(&self) {}
 }
 
 /// Opaque identifier for custom grants allocated dynamically from a process's
@@ -65,6 +65,10 @@ pub enum ProcessLoadError {
     /// Process loading failed because checking the process failed.
     CheckError(ProcessCheckError),
 
+    /// A new binary meant to update a process has a different ShortId than
+    /// the process it would replace.
+    ShortIdMismatch,
+
     /// Process loading error due (likely) to a bug in the kernel. If you get
     /// this error please open a bug report.
     InternalError,
@@ -108,6 +112,10 @@ impl fmt::Debug for ProcessLoadError {
                 write!(f, "{:?}", check_error)
             }
 
+            ProcessLoadError::ShortIdMismatch => {
+                write!(f, "ShortId does not match the process being updated")
+            }
+
             ProcessLoadError::InternalError => write!(f, "Error in kernel. Likely a bug."),
         }
     }
@@ -985,6 +993,12 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
             }
         }
 
+        // If there are no applications in flash, the new one goes first.
+        if start_count == 0 {
+            let flash_start = self.flash_bank.get().as_ptr() as usize;
+            return self.find_next_cortex_m_aligned_address(flash_start, app_size);
+        }
+
         // If there is only one application in flash:
         if start_count == 1 {
             let potential_address = self
@@ -1073,10 +1087,10 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
             }
         }
 
-        // Prepad requirement.
+        // Prepad requirement. Zeros are unused entries, not binaries.
         if let Some(previous_closest_neighbor) = process_binaries_end_addresses
             .iter()
-            .filter(|&&x| x < new_app_start_address + 1)
+            .filter(|&&x| x != 0 && x < new_app_start_address + 1)
             .max()
         {
             // We found the previous closest app in flash.
@@ -1175,6 +1189,70 @@ impl<'a, C: Chip, D: ProcessStandardDebug> SequentialProcessLoaderMachine<'a, C,
         }
     }
 
+    /// Function to find a process binary that can be moved to a lower address
+    /// in flash to close the gap before it.
+    ///
+    /// Returns the start of the gap, the current address of the binary, the
+    /// address to move it to, and its length. Binaries are never moved over
+    /// themselves, and binaries compiled for a fixed flash address, as well as
+    /// the binary starting at `pinned` if any, are not moved at all.
+    pub fn check_flash_for_compaction(
+        &self,
+        pinned: Option<usize>,
+    ) -> Option<(usize, usize, usize, usize)> {
+        const MAX_PROCS: usize = 10;
+        let mut pb_start_address: [usize; MAX_PROCS] = [0; MAX_PROCS];
+        let mut pb_end_address: [usize; MAX_PROCS] = [0; MAX_PROCS];
+        let flash = self.flash_bank.get();
+        self.scan_flash_for_process_binaries(flash, &mut pb_start_address, &mut pb_end_address)
+            .ok()?;
+
+        // The scan finds binaries in the order they are stored, so each gap is
+        // between the end of one binary and the start of the next.
+        let mut gap_start = flash.as_ptr() as usize;
+        for (&start, &end) in pb_start_address.iter().zip(pb_end_address.iter()) {
+            if start == 0 {
+                break;
+            }
+            let length = end - start;
+            let new_address = self.find_next_cortex_m_aligned_address(gap_start, length);
+            // Any space left before the new address must fit a padding
+            // header.
+            let leading_gap = new_address - gap_start;
+            if new_address + length <= start
+                && (leading_gap == 0 || leading_gap >= 16)
+                && pinned != Some(start)
+                && !self.has_fixed_flash_address(start)
+            {
+                return Some((gap_start, start, new_address, length));
+            }
+            gap_start = end;
+        }
+        None
+    }
+
+    /// Function to check if the process binary at `app_address` was compiled
+    /// for a fixed address in flash. Binaries with a header that cannot be
+    /// parsed are treated as fixed.
+    fn has_fixed_flash_address(&self, app_address: usize) -> bool {
+        let flash = self.flash_bank.get();
+        let offset = app_address - flash.as_ptr() as usize;
+        let fixed = || -> Option<bool> {
+            let lengths: &[u8; 8] = flash.get(offset..offset + 8)?.try_into().ok()?;
+            let (version, header_length, _entry_length) =
+                tock_tbf::parse::parse_tbf_header_lengths(lengths).ok()?;
+            let header = flash.get(offset..offset + header_length as usize)?;
+            let header = tock_tbf::parse::parse_tbf_header(header, version).ok()?;
+            Some(header.get_fixed_address_flash().is_some())
+        };
+        fixed().unwrap_or(true)
+    }
+
+    /// Get the flash region that process binaries are stored in.
+    pub(crate) fn get_flash_bank(&self) -> &'static [u8] {
+        self.flash_bank.get()
+    }
+
     /// Function to check if the app binary at address `app_address` is valid.
     fn check_new_binary_validity(&self, app_address: usize) -> bool {
         let flash = self.flash_bank.get();
@@ -22,7 +22,7 @@ use crate::debug;
 use crate::errorcode::ErrorCode;
 use crate::init_uninit_struct;
 use crate::kernel::Kernel;
-use crate::platform::chip::Chip;
+use crate::platform::chip::{Chip, FaultRegisters};
 use crate::platform::mpu::{self, MPU};
 use crate::process::ProcessBinary;
 use crate::process::{BinaryVersion, ReturnArguments};
@@ -40,6 +40,7 @@ use crate::syscall::{self, Syscall, SyscallReturn, UserspaceKernelBoundary};
 use crate::upcall::UpcallId;
 use crate::utilities::capability_ptr::{CapabilityPtr, CapabilityPtrPermissions};
 use crate::utilities::cells::{MapCell, NumericCellExt, OptionalCell};
+use crate::utilities::helpers::crc32_posix;
 
 use tock_tbf::types::CommandPermissions;
 
@@ -196,8 +197,59 @@ pub trait ProcessStandardDebug: Default {
     /// Reset the recorded count of the number of the process has exceeded its
     /// timeslice to 0.
     fn reset_timeslice_expiration_count(&self);
+
+    // The resource accounting methods below have default implementations
+    // that record nothing, so that existing implementations keep working.
+
+    /// Add to the recorded CPU time the process has used, in microseconds.
+    fn add_cpu_time_us(&self, _time_us: u32) {}
+    /// Get the recorded CPU time the process has used, in microseconds.
+    ///
+    /// This should return 0 if [`ProcessStandardDebug::add_cpu_time_us()`] is
+    /// never called.
+    fn get_cpu_time_us(&self) -> u64 {
+        0
+    }
+    /// Reset the recorded CPU time the process has used to 0.
+    fn reset_cpu_time(&self) {}
+
+    /// Increase the recorded count of the number of system calls the process
+    /// has made to the driver with number `driver_num`.
+    fn increment_driver_syscall_count(&self, _driver_num: usize) {}
+    /// Get entry `index` of the recorded per-driver system call counts, as a
+    /// tuple of (driver number, number of system calls). Returns `None` if
+    /// there is no such entry.
+    fn get_driver_syscall_count(&self, _index: usize) -> Option<(usize, usize)> {
+        None
+    }
+    /// Clear the recorded per-driver system call counts.
+    fn reset_driver_syscall_counts(&self) {}
+
+    /// Provide an address the app break has been at, and record the address if
+    /// it is the highest address the app break has reached.
+    fn set_new_app_break_max_pointer(&self, _ptr: *const u8) {}
+    /// Get the highest address the app break has reached, if it was recorded.
+    fn get_app_break_max_pointer(&self) -> Option<*const u8> {
+        None
+    }
+    /// Provide an address the kernel memory break has been at, and record the
+    /// address if it is the lowest address the kernel memory break has
+    /// reached.
+    fn set_new_kernel_memory_break_min_pointer(&self, _ptr: *const u8) {}
+    /// Get the lowest address the kernel memory break has reached, if it was
+    /// recorded.
+    fn get_kernel_memory_break_min_pointer(&self) -> Option<*const u8> {
+        None
+    }
+    /// Clear any record of the addresses the app break and kernel memory break
+    /// have reached.
+    fn reset_memory_break_pointers(&self) {}
 }
 
+/// Number of drivers [`ProcessStandardDebugFull`] counts system calls for.
+/// System calls to further drivers are not included in the per-driver counts.
+const DRIVER_SYSCALL_COUNT_ENTRIES: usize = 16;
+
 /// A debugging implementation for [`ProcessStandard`] that records the full
 /// debugging state.
 pub struct ProcessStandardDebugFull {
@@ -247,6 +299,19 @@ struct ProcessStandardDebugFullInner {
     /// How many times this process has been paused because it exceeded its
     /// timeslice.
     timeslice_expiration_count: usize,
+
+    /// How much time this process has spent executing, in microseconds.
+    cpu_time_us: u64,
+
+    /// How many syscalls have been made to each driver, as (driver number,
+    /// count) in the order the drivers were first called.
+    driver_syscall_counts: [Option<(usize, usize)>; DRIVER_SYSCALL_COUNT_ENTRIES],
+
+    /// How high has the app break ever been.
+    app_break_max_pointer: Option<*const u8>,
+
+    /// How low has the kernel memory break ever been.
+    kernel_memory_break_min_pointer: Option<*const u8>,
 }
 
 impl ProcessStandardDebug for ProcessStandardDebugFull {
@@ -333,6 +398,71 @@ impl ProcessStandardDebug for ProcessStandardDebugFull {
     fn reset_timeslice_expiration_count(&self) {
         self.debug.map(|d| d.timeslice_expiration_count = 0);
     }
+
+    fn add_cpu_time_us(&self, time_us: u32) {
+        self.debug.map(|d| d.cpu_time_us += u64::from(time_us));
+    }
+    fn get_cpu_time_us(&self) -> u64 {
+        self.debug.map_or(0, |d| d.cpu_time_us)
+    }
+    fn reset_cpu_time(&self) {
+        self.debug.map(|d| d.cpu_time_us = 0);
+    }
+
+    fn increment_driver_syscall_count(&self, driver_num: usize) {
+        self.debug.map(|d| {
+            // Use the entry for this driver, or the first free entry if the
+            // driver has not been called before.
+            let entry = d
+                .driver_syscall_counts
+                .iter_mut()
+                .find(|entry| entry.is_none_or(|(num, _)| num == driver_num));
+            match entry {
+                Some(Some((_, count))) => *count += 1,
+                Some(entry) => *entry = Some((driver_num, 1)),
+                None => {}
+            }
+        });
+    }
+    fn get_driver_syscall_count(&self, index: usize) -> Option<(usize, usize)> {
+        self.debug.map_or(None, |d| {
+            d.driver_syscall_counts.get(index).copied().flatten()
+        })
+    }
+    fn reset_driver_syscall_counts(&self) {
+        self.debug
+            .map(|d| d.driver_syscall_counts = [None; DRIVER_SYSCALL_COUNT_ENTRIES]);
+    }
+
+    fn set_new_app_break_max_pointer(&self, ptr: *const u8) {
+        self.debug.map(|d| {
+            if d.app_break_max_pointer.is_none_or(|max| ptr > max) {
+                d.app_break_max_pointer = Some(ptr);
+            }
+        });
+    }
+    fn get_app_break_max_pointer(&self) -> Option<*const u8> {
+        self.debug.map_or(None, |d| d.app_break_max_pointer)
+    }
+    fn set_new_kernel_memory_break_min_pointer(&self, ptr: *const u8) {
+        self.debug.map(|d| {
+            if d.kernel_memory_break_min_pointer
+                .is_none_or(|min| ptr < min)
+            {
+                d.kernel_memory_break_min_pointer = Some(ptr);
+            }
+        });
+    }
+    fn get_kernel_memory_break_min_pointer(&self) -> Option<*const u8> {
+        self.debug
+            .map_or(None, |d| d.kernel_memory_break_min_pointer)
+    }
+    fn reset_memory_break_pointers(&self) {
+        self.debug.map(|d| {
+            d.app_break_max_pointer = None;
+            d.kernel_memory_break_min_pointer = None;
+        });
+    }
 }
 
 impl Default for ProcessStandardDebugFull {
@@ -410,6 +540,42 @@ struct GrantPointerEntry {
     grant_ptr: *mut u8,
 }
 
+/// Values for encoding a process checkpoint in a binary slice.
+///
+/// A checkpoint starts with a header of `CHECKPOINT_HEADER_WORDS`
+/// little-endian `usize` words, followed by the stored architecture state
+/// (as written by `store_context()`), followed by a copy of the process
+/// memory from the start of process RAM up to the app break.
+const CHECKPOINT_VERSION: usize = 1;
+const CHECKPOINT_TAG: [u8; 4] = *b"tkcp";
+const CHECKPOINT_HEADER_WORDS: usize = 8;
+const CHECKPOINT_HEADER_LEN: usize = CHECKPOINT_HEADER_WORDS * mem::size_of::<usize>();
+
+const CHECKPOINT_TAG_IDX: usize = 0;
+const CHECKPOINT_VERSION_IDX: usize = 1;
+/// Length of the entire checkpoint, including the header.
+const CHECKPOINT_LEN_IDX: usize = 2;
+/// CRC32 of everything following the header, used to detect checkpoints
+/// that were only partially written to storage.
+const CHECKPOINT_CHECKSUM_IDX: usize = 3;
+/// CRC32 of the process binary the checkpoint was taken from.
+const CHECKPOINT_BINARY_HASH_IDX: usize = 4;
+const CHECKPOINT_MEMORY_START_IDX: usize = 5;
+const CHECKPOINT_APP_BREAK_IDX: usize = 6;
+const CHECKPOINT_CONTEXT_LEN_IDX: usize = 7;
+
+fn checkpoint_word(header: &[u8], index: usize) -> usize {
+    const USIZE_SZ: usize = mem::size_of::<usize>();
+    let mut word = [0; USIZE_SZ];
+    word.copy_from_slice(&header[index * USIZE_SZ..(index + 1) * USIZE_SZ]);
+    usize::from_le_bytes(word)
+}
+
+fn set_checkpoint_word(header: &mut [u8], index: usize, value: usize) {
+    const USIZE_SZ: usize = mem::size_of::<usize>();
+    header[index * USIZE_SZ..(index + 1) * USIZE_SZ].copy_from_slice(&value.to_le_bytes());
+}
+
 /// A type for userspace processes in Tock.
 ///
 /// As its name implies, this is the standard implementation for Tock processes
@@ -985,6 +1151,7 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
                 Err(Error::OutOfMemory)
             } else {
                 let old_break: *const u8 = self.app_break.get();
+                self.debug.set_new_app_break_max_pointer(old_break);
                 self.app_break.set(new_break);
 
                 // SAFETY: `configure_mpu` is unsafe, as invoking it with an incorrect
@@ -1439,6 +1606,9 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
     }
 
     fn set_syscall_return_value(&self, return_value: SyscallReturn) {
+        self.kernel
+            .trace_syscall_return(self.processid(), &return_value);
+
         match self.stored_state.map(|stored_state| {
             // Actually set the return value for a particular process.
             //
@@ -1592,12 +1762,49 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
     fn debug_syscall_called(&self, last_syscall: Syscall) {
         self.debug.increment_syscall_count();
         self.debug.set_last_syscall(last_syscall);
+        if let Some(driver_num) = last_syscall.driver_number() {
+            self.debug.increment_driver_syscall_count(driver_num);
+        }
     }
 
     fn debug_syscall_last(&self) -> Option<Syscall> {
         self.debug.get_last_syscall()
     }
 
+    fn debug_cpu_time_used(&self, time_us: u32) {
+        self.debug.add_cpu_time_us(time_us);
+    }
+
+    fn debug_cpu_time_us(&self) -> u64 {
+        self.debug.get_cpu_time_us()
+    }
+
+    fn debug_driver_syscall_count(&self, index: usize) -> Option<(usize, usize)> {
+        self.debug.get_driver_syscall_count(index)
+    }
+
+    fn debug_app_memory_high_water_mark(&self) -> usize {
+        // The recorded address only covers previous app breaks, so include the
+        // current one as well.
+        let app_break = self.app_break.get();
+        let max_app_break = self
+            .debug
+            .get_app_break_max_pointer()
+            .map_or(app_break, |max| cmp::max(max, app_break));
+        max_app_break.addr() - self.mem_start().addr()
+    }
+
+    fn debug_grant_memory_high_water_mark(&self) -> usize {
+        let kernel_memory_break = self.kernel_memory_break.get();
+        let min_kernel_memory_break = self
+            .debug
+            .get_kernel_memory_break_min_pointer()
+            .map_or(kernel_memory_break, |min| {
+                cmp::min(min, kernel_memory_break)
+            });
+        self.mem_end().addr() - min_kernel_memory_break.addr()
+    }
+
     fn get_addresses(&self) -> ProcessAddresses {
         ProcessAddresses {
             flash_start: self.flash_start() as usize,
@@ -1625,6 +1832,22 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
         }
     }
 
+    fn fault_registers(&self) -> FaultRegisters {
+        self.stored_state
+            .map_or(FaultRegisters::default(), |stored_state| {
+                // SAFETY: As in `print_full_process()`, the start of process
+                // memory and the current `app_break` bound memory that is
+                // accessible to the process.
+                unsafe {
+                    self.chip.userspace_kernel_boundary().fault_registers(
+                        self.mem_start(),
+                        self.app_break.get(),
+                        stored_state,
+                    )
+                }
+            })
+    }
+
     fn print_full_process(&self, writer: &mut dyn Write) {
         if !config::CONFIG.debug_panics {
             return;
@@ -1727,6 +1950,157 @@ impl<C: Chip, D: 'static + ProcessStandardDebug> Process for ProcessStandard<'_,
             })
             .unwrap_or(Err(ErrorCode::FAIL))
     }
+
+    fn checkpoint(&self, out: &mut [u8]) -> Result<usize, ErrorCode> {
+        // Only checkpoint a process whose stored state is consistent and can
+        // be resumed without the kernel handing it a specific upcall.
+        match self.state.get() {
+            State::Running
+            | State::Yielded
+            | State::Stopped(StoppedState::Running)
+            | State::Stopped(StoppedState::Yielded) => {}
+            State::YieldedFor(_) | State::Stopped(StoppedState::YieldedFor(_)) => {
+                return Err(ErrorCode::BUSY);
+            }
+            State::Faulted | State::Terminated => return Err(ErrorCode::FAIL),
+        }
+
+        if out.len() < CHECKPOINT_HEADER_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        let (header, body) = out.split_at_mut(CHECKPOINT_HEADER_LEN);
+
+        let context_len = self.get_stored_state(body)?;
+        let memory_len = self.app_break.get().addr() - self.mem_start().addr();
+        let memory_out = body
+            .get_mut(context_len..context_len + memory_len)
+            .ok_or(ErrorCode::SIZE)?;
+
+        // SAFETY: `[memory_start, app_break)` is process-accessible memory,
+        // which is always initialized (see `brk()`). The process is not
+        // executing while the kernel runs this, and we only read from it.
+        unsafe {
+            ptr::copy_nonoverlapping(self.mem_start(), memory_out.as_mut_ptr(), memory_len);
+        }
+
+        let total_len = CHECKPOINT_HEADER_LEN + context_len + memory_len;
+        let checksum = crc32_posix(&body[..context_len + memory_len]);
+
+        set_checkpoint_word(
+            header,
+            CHECKPOINT_TAG_IDX,
+            u32::from_le_bytes(CHECKPOINT_TAG) as usize,
+        );
+        set_checkpoint_word(header, CHECKPOINT_VERSION_IDX, CHECKPOINT_VERSION);
+        set_checkpoint_word(header, CHECKPOINT_LEN_IDX, total_len);
+        set_checkpoint_word(header, CHECKPOINT_CHECKSUM_IDX, checksum as usize);
+        set_checkpoint_word(
+            header,
+            CHECKPOINT_BINARY_HASH_IDX,
+            self.binary_hash() as usize,
+        );
+        set_checkpoint_word(header, CHECKPOINT_MEMORY_START_IDX, self.mem_start().addr());
+        set_checkpoint_word(
+            header,
+            CHECKPOINT_APP_BREAK_IDX,
+            self.app_break.get().addr(),
+        );
+        set_checkpoint_word(header, CHECKPOINT_CONTEXT_LEN_IDX, context_len);
+
+        Ok(total_len)
+    }
+
+    fn restore_checkpoint(&self, snapshot: &[u8]) -> Result<(), ErrorCode> {
+        // A process that has been started but not yet run is `Yielded` with
+        // only its init function queued.
+        let stopped = match self.state.get() {
+            State::Yielded => false,
+            State::Stopped(StoppedState::Yielded) => true,
+            _ => return Err(ErrorCode::FAIL),
+        };
+
+        let header = snapshot
+            .get(..CHECKPOINT_HEADER_LEN)
+            .ok_or(ErrorCode::INVAL)?;
+        if checkpoint_word(header, CHECKPOINT_TAG_IDX)
+            != u32::from_le_bytes(CHECKPOINT_TAG) as usize
+            || checkpoint_word(header, CHECKPOINT_VERSION_IDX) != CHECKPOINT_VERSION
+        {
+            return Err(ErrorCode::INVAL);
+        }
+
+        // Make sure the checkpoint was completely written, and that it belongs
+        // to this exact binary running in this exact memory region.
+        let body = snapshot
+            .get(CHECKPOINT_HEADER_LEN..checkpoint_word(header, CHECKPOINT_LEN_IDX))
+            .ok_or(ErrorCode::INVAL)?;
+        if checkpoint_word(header, CHECKPOINT_CHECKSUM_IDX) != crc32_posix(body) as usize
+            || checkpoint_word(header, CHECKPOINT_BINARY_HASH_IDX) != self.binary_hash() as usize
+            || checkpoint_word(header, CHECKPOINT_MEMORY_START_IDX) != self.mem_start().addr()
+        {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let (context, memory) = body
+            .split_at_checked(checkpoint_word(header, CHECKPOINT_CONTEXT_LEN_IDX))
+            .ok_or(ErrorCode::INVAL)?;
+        let new_break = self.mem_start().wrapping_add(memory.len());
+        if new_break.addr() != checkpoint_word(header, CHECKPOINT_APP_BREAK_IDX)
+            || new_break > self.kernel_memory_break.get()
+        {
+            return Err(ErrorCode::INVAL);
+        }
+
+        // Decode the architecture state before changing anything about the
+        // process, so that an invalid checkpoint leaves the process untouched.
+        let mut stored_state = Default::default();
+        self.chip
+            .userspace_kernel_boundary()
+            .load_context(&mut stored_state, context)
+            .or(Err(ErrorCode::INVAL))?;
+
+        self.mpu_config.map_or(Err(ErrorCode::FAIL), |config| {
+            self.chip
+                .mpu()
+                .update_app_memory_region(
+                    new_break,
+                    self.kernel_memory_break.get(),
+                    mpu::Permissions::ReadWriteOnly,
+                    config,
+                )
+                .or(Err(ErrorCode::NOMEM))
+        })?;
+        self.debug
+            .set_new_app_break_max_pointer(self.app_break.get());
+        self.app_break.set(new_break);
+
+        // SAFETY: `[memory_start, new_break)` was checked above to lie below
+        // the kernel memory break, so it is process-accessible memory that
+        // holds no kernel data structures. The process has not run yet, so no
+        // process buffers into this memory have been shared with capsules.
+        unsafe {
+            ptr::copy_nonoverlapping(memory.as_ptr(), self.mem_start().cast_mut(), memory.len());
+        }
+
+        self.stored_state.replace(stored_state);
+
+        // Nothing shared by the previous execution survives a reboot, and the
+        // init function must not run on top of the restored memory.
+        self.tasks.map(|tasks| {
+            tasks.empty();
+        });
+        self.allow_high_water_mark.set(self.mem_start());
+        self.is_yield_wait_for_ready.set(false);
+
+        // Let the process continue where it was checkpointed.
+        if stopped {
+            self.state.set(State::Stopped(StoppedState::Running));
+        } else {
+            self.state.set(State::Running);
+        }
+
+        Ok(())
+    }
 }
 
 impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C, D> {
@@ -2381,6 +2755,9 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
         self.debug.reset_syscall_count();
         self.debug.reset_dropped_upcall_count();
         self.debug.reset_timeslice_expiration_count();
+        self.debug.reset_cpu_time();
+        self.debug.reset_driver_syscall_counts();
+        self.debug.reset_memory_break_pointers();
 
         // Reset MPU region configuration.
         //
@@ -2628,6 +3005,8 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
 
                 // We always allocate down, so we must lower the
                 // kernel_memory_break.
+                self.debug
+                    .set_new_kernel_memory_break_min_pointer(self.kernel_memory_break.get());
                 self.kernel_memory_break.set(new_break);
 
                 // We need `grant_ptr` as a mutable pointer.
@@ -2694,6 +3073,14 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
         }
     }
 
+    /// Hash of the process binary (the TBF header and application, excluding
+    /// footers), used to tag checkpoints so they are only restored into the
+    /// binary they were taken from.
+    fn binary_hash(&self) -> u32 {
+        let binary_end = cmp::min(self.header.get_binary_end() as usize, self.flash.len());
+        crc32_posix(&self.flash[..binary_end])
+    }
+
     /// The start address of allocated RAM for this process.
     fn mem_start(&self) -> *const u8 {
         self.memory_start
@@ -2734,3 +3121,71 @@ impl<C: 'static + Chip, D: 'static + ProcessStandardDebug> ProcessStandard<'_, C
         self.app_break.get()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{DRIVER_SYSCALL_COUNT_ENTRIES, ProcessStandardDebug, ProcessStandardDebugFull};
+
+    #[test]
+    fn cpu_time_accumulates() {
+        let debug = ProcessStandardDebugFull::default();
+        assert_eq!(debug.get_cpu_time_us(), 0);
+        debug.add_cpu_time_us(u32::MAX);
+        debug.add_cpu_time_us(10);
+        assert_eq!(debug.get_cpu_time_us(), u64::from(u32::MAX) + 10);
+        debug.reset_cpu_time();
+        assert_eq!(debug.get_cpu_time_us(), 0);
+    }
+
+    #[test]
+    fn driver_syscalls_are_counted_in_call_order() {
+        let debug = ProcessStandardDebugFull::default();
+        debug.increment_driver_syscall_count(0x2);
+        debug.increment_driver_syscall_count(0x1);
+        debug.increment_driver_syscall_count(0x2);
+        assert_eq!(debug.get_driver_syscall_count(0), Some((0x2, 2)));
+        assert_eq!(debug.get_driver_syscall_count(1), Some((0x1, 1)));
+        assert_eq!(debug.get_driver_syscall_count(2), None);
+        debug.reset_driver_syscall_counts();
+        assert_eq!(debug.get_driver_syscall_count(0), None);
+    }
+
+    #[test]
+    fn driver_syscalls_beyond_the_entries_are_not_counted() {
+        let debug = ProcessStandardDebugFull::default();
+        for driver_num in 0..DRIVER_SYSCALL_COUNT_ENTRIES + 1 {
+            debug.increment_driver_syscall_count(driver_num);
+        }
+        debug.increment_driver_syscall_count(0);
+        assert_eq!(debug.get_driver_syscall_count(0), Some((0, 2)));
+        assert_eq!(
+            debug.get_driver_syscall_count(DRIVER_SYSCALL_COUNT_ENTRIES - 1),
+            Some((DRIVER_SYSCALL_COUNT_ENTRIES - 1, 1))
+        );
+        assert_eq!(
+            debug.get_driver_syscall_count(DRIVER_SYSCALL_COUNT_ENTRIES),
+            None
+        );
+    }
+
+    #[test]
+    fn memory_breaks_record_extremes() {
+        let debug = ProcessStandardDebugFull::default();
+        let memory = [0u8; 16];
+        let at = |offset: usize| memory.as_ptr().wrapping_add(offset);
+
+        debug.set_new_app_break_max_pointer(at(4));
+        debug.set_new_app_break_max_pointer(at(8));
+        debug.set_new_app_break_max_pointer(at(6));
+        assert_eq!(debug.get_app_break_max_pointer(), Some(at(8)));
+
+        debug.set_new_kernel_memory_break_min_pointer(at(12));
+        debug.set_new_kernel_memory_break_min_pointer(at(10));
+        debug.set_new_kernel_memory_break_min_pointer(at(14));
+        assert_eq!(debug.get_kernel_memory_break_min_pointer(), Some(at(10)));
+
+        debug.reset_memory_break_pointers();
+        assert_eq!(debug.get_app_break_max_pointer(), None);
+        assert_eq!(debug.get_kernel_memory_break_min_pointer(), None);
+    }
+}
@@ -81,6 +81,16 @@ pub trait Scheduler<C: Chip> {
     }
 }
 
+/// Per-process statistics kept by a scheduler.
+///
+/// Schedulers which track more than the kernel itself implement this trait so
+/// that tools such as the process console can show the statistics.
+pub trait SchedulerInfo {
+    /// Number of deadlines the process has missed, or `None` if the scheduler
+    /// does not give the process deadlines.
+    fn deadline_misses(&self, processid: ProcessId) -> Option<u32>;
+}
+
 /// Enum representing the actions the scheduler can request in each call to
 /// `scheduler.next()`.
 #[derive(Copy, Clone)]
@@ -21,9 +21,21 @@ use crate::capabilities::KerneluserStorageCapability;
 /// fn StoragePermissions::check_read_permission(&self, stored_id: u32) -> bool;
 /// fn StoragePermissions::check_modify_permission(&self, stored_id: u32) -> bool;
 /// fn StoragePermissions::get_write_id(&self) -> Option<u32>;
+/// fn StoragePermissions::get_quota(&self) -> Option<StorageQuota>;
 /// ```
 #[derive(Clone, Copy)]
-pub struct StoragePermissions(StoragePermissionsPrivate);
+pub struct StoragePermissions(StoragePermissionsPrivate, Option<StorageQuota>);
+
+/// Limits on how much persistent storage an application may use for the state
+/// it writes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StorageQuota {
+    /// The maximum number of bytes of keys and values the application may
+    /// store.
+    pub max_bytes: u32,
+    /// The maximum number of keys the application may store.
+    pub max_keys: u32,
+}
 
 /// Inner enum type for types of permissions.
 ///
@@ -112,7 +124,7 @@ impl StoragePermissions {
         short_id_fixed: core::num::NonZeroU32,
         _cap: &dyn ApplicationStorageCapability,
     ) -> Self {
-        Self(StoragePermissionsPrivate::SelfOnly(short_id_fixed))
+        Self(StoragePermissionsPrivate::SelfOnly(short_id_fixed), None)
     }
 
     pub fn new_fixed_size(
@@ -125,15 +137,18 @@ impl StoragePermissions {
         modify_permissions: [u32; 8],
         _cap: &dyn ApplicationStorageCapability,
     ) -> Self {
-        Self(StoragePermissionsPrivate::FixedSize(FixedSizePermissions {
-            app_id,
-            write_permission,
-            read_modify_self,
-            read_count,
-            read_permissions,
-            modify_count,
-            modify_permissions,
-        }))
+        Self(
+            StoragePermissionsPrivate::FixedSize(FixedSizePermissions {
+                app_id,
+                write_permission,
+                read_modify_self,
+                read_count,
+                read_permissions,
+                modify_count,
+                modify_permissions,
+            }),
+            None,
+        )
     }
 
     pub fn new_listed(
@@ -144,21 +159,30 @@ impl StoragePermissions {
         modify_permissions: &'static [u32],
         _cap: &dyn ApplicationStorageCapability,
     ) -> Self {
-        Self(StoragePermissionsPrivate::Listed(ListedPermissions {
-            app_id,
-            write_permission,
-            read_modify_self,
-            read_permissions,
-            modify_permissions,
-        }))
+        Self(
+            StoragePermissionsPrivate::Listed(ListedPermissions {
+                app_id,
+                write_permission,
+                read_modify_self,
+                read_permissions,
+                modify_permissions,
+            }),
+            None,
+        )
     }
 
     pub fn new_kernel(_cap: &dyn KerneluserStorageCapability) -> Self {
-        Self(StoragePermissionsPrivate::Kernel)
+        Self(StoragePermissionsPrivate::Kernel, None)
     }
 
     pub fn new_null() -> Self {
-        Self(StoragePermissionsPrivate::Null)
+        Self(StoragePermissionsPrivate::Null, None)
+    }
+
+    /// Limit the storage the application may use for the state it writes to
+    /// `quota`.
+    pub fn with_quota(self, quota: StorageQuota, _cap: &dyn ApplicationStorageCapability) -> Self {
+        Self(self.0, Some(quota))
     }
 
     /// Check if these storage permissions grant read access to the stored state
@@ -218,4 +242,10 @@ impl StoragePermissions {
             StoragePermissionsPrivate::Null => None,
         }
     }
+
+    /// Retrieve the limits on the storage the application may use for the
+    /// state it writes. Returns `None` if the storage is not limited.
+    pub fn get_quota(&self) -> Option<StorageQuota> {
+        self.1
+    }
 }
@@ -69,6 +69,7 @@
 use core::fmt::Write;
 
 use crate::errorcode::ErrorCode;
+use crate::platform::chip::FaultRegisters;
 use crate::process;
 use crate::utilities::capability_ptr::CapabilityPtr;
 use crate::utilities::machine_register::MachineRegister;
@@ -651,7 +652,37 @@ pub trait UserspaceKernelBoundary {
         writer: &mut dyn Write,
     );
 
+    /// Return the fault registers (program counter, link register and fault
+    /// status) of a process identified by the stored state for that process,
+    /// for inclusion in a crash record.
+    ///
+    /// This is only meaningful after the process has faulted. Architectures
+    /// that do not support this return [`FaultRegisters::default()`].
+    ///
+    /// # Safety
+    ///
+    /// This function guarantees that if it needs to read process memory, it
+    /// will only read memory starting at `accessible_memory_start` and before
+    /// `app_brk`. The caller is responsible for guaranteeing that those
+    /// pointers are valid for the process.
+    unsafe fn fault_registers(
+        &self,
+        _accessible_memory_start: *const u8,
+        _app_brk: *const u8,
+        _state: &Self::StoredState,
+    ) -> FaultRegisters {
+        FaultRegisters::default()
+    }
+
     /// Store architecture specific (e.g. CPU registers or status flags) data
     /// for a process. On success returns the number of elements written to out.
     fn store_context(&self, state: &Self::StoredState, out: &mut [u8]) -> Result<usize, ErrorCode>;
+
+    /// Load architecture specific (e.g. CPU registers or status flags) data
+    /// for a process from a binary blob previously produced by
+    /// `store_context()`.
+    ///
+    /// Returns `ErrorCode::FAIL` if `input` is not a valid stored state for
+    /// this architecture, in which case `state` is left unmodified.
+    fn load_context(&self, state: &mut Self::StoredState, input: &[u8]) -> Result<(), ErrorCode>;
 }
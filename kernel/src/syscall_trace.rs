@@ -0,0 +1,409 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Structured tracing of system calls.
+//!
+//! When the `record_syscalls` configuration option is enabled, the kernel
+//! reports each system call a process makes, and the value returned to the
+//! process, to the [`SyscallTracer`] set with
+//! [`Kernel::set_syscall_tracer()`](crate::Kernel::set_syscall_tracer). Unlike
+//! the `trace_syscalls` option, which prints each system call to the debug
+//! output, this lets a tracer keep records that can be inspected later. When
+//! the option is disabled the hooks compile to nothing.
+//!
+//! Traces can be exported in a binary format for host tools. A trace starts
+//! with a header, followed by the records from oldest to newest. All fields are
+//! little-endian:
+//!
+//! ```text
+//! Header:
+//! 0   magic ("TKST")
+//! 4   format version (u16)
+//! 6   record length in bytes (u16)
+//! 8   number of records (u32)
+//!
+//! Record:
+//! 0   timestamp in microseconds (u32)
+//! 4   process identifier (u32)
+//! 8   system call class (u8)
+//! 9   flags (u8), bit 0 set if the return value was recorded
+//! 10  reserved (u16)
+//! 12  four system call arguments (u32), as passed in registers
+//! 28  four return value registers (u32), encoded as in TRD104
+//! ```
+//!
+//! Timestamps wrap around with the timer the tracer uses. Arguments and return
+//! values wider than 32 bits are truncated on 64-bit platforms.
+
+use crate::ErrorCode;
+use crate::process::ProcessId;
+use crate::syscall::{Syscall, SyscallClass, SyscallReturn, YieldVariant};
+use crate::utilities::arch_helpers::{TRD104SyscallReturn, encode_syscall_return_trd104};
+use crate::utilities::binary_write::BinaryWrite;
+
+/// Marks the start of a binary trace.
+pub const TRACE_MAGIC: [u8; 4] = *b"TKST";
+
+/// Version of the binary trace format.
+pub const TRACE_VERSION: u16 = 1;
+
+/// Length of the header of a binary trace.
+pub const TRACE_HEADER_LEN: usize = 12;
+
+/// Length of each record in a binary trace.
+pub const TRACE_RECORD_LEN: usize = 44;
+
+/// A system call made by a process.
+#[derive(Copy, Clone, Debug)]
+pub struct SyscallTraceRecord {
+    /// When the system call was made, in microseconds.
+    pub timestamp_us: u32,
+    /// The process that made the system call.
+    pub processid: ProcessId,
+    /// The system call and its arguments.
+    pub syscall: Syscall,
+    /// The value returned to the process, or `None` if no value has been
+    /// returned (yet).
+    pub return_value: Option<SyscallReturn>,
+}
+
+impl SyscallTraceRecord {
+    /// The class and register arguments of the system call, as passed by the
+    /// process.
+    fn syscall_registers(&self) -> (SyscallClass, [usize; 4]) {
+        match self.syscall {
+            Syscall::Yield { yield_type } => match yield_type {
+                YieldVariant::NoWait { ptr } => (SyscallClass::Yield, [0, ptr as usize, 0, 0]),
+                YieldVariant::Wait => (SyscallClass::Yield, [1, 0, 0, 0]),
+                YieldVariant::WaitFor {
+                    driver_number,
+                    subdriver_number,
+                } => (SyscallClass::Yield, [2, driver_number, subdriver_number, 0]),
+            },
+            Syscall::Subscribe {
+                driver_number,
+                subdriver_number,
+                upcall_ptr,
+                appdata,
+            } => (
+                SyscallClass::Subscribe,
+                [
+                    driver_number,
+                    subdriver_number,
+                    upcall_ptr.addr(),
+                    appdata.as_usize(),
+                ],
+            ),
+            Syscall::Command {
+                driver_number,
+                subdriver_number,
+                arg0,
+                arg1,
+            } => (
+                SyscallClass::Command,
+                [driver_number, subdriver_number, arg0, arg1],
+            ),
+            Syscall::ReadWriteAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                SyscallClass::ReadWriteAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::UserspaceReadableAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                SyscallClass::UserspaceReadableAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::ReadOnlyAllow {
+                driver_number,
+                subdriver_number,
+                allow_address,
+                allow_size,
+            } => (
+                SyscallClass::ReadOnlyAllow,
+                [
+                    driver_number,
+                    subdriver_number,
+                    allow_address as usize,
+                    allow_size,
+                ],
+            ),
+            Syscall::Memop { operand, arg0 } => (SyscallClass::Memop, [operand, arg0, 0, 0]),
+            Syscall::Exit {
+                which,
+                completion_code,
+            } => (SyscallClass::Exit, [which, completion_code, 0, 0]),
+        }
+    }
+
+    /// Encode the record in the binary trace format.
+    pub fn encode(&self) -> [u8; TRACE_RECORD_LEN] {
+        let mut buf = [0; TRACE_RECORD_LEN];
+        let (class, arguments) = self.syscall_registers();
+        buf[0..4].copy_from_slice(&self.timestamp_us.to_le_bytes());
+        buf[4..8].copy_from_slice(&(self.processid.id() as u32).to_le_bytes());
+        buf[8] = class as u8;
+        for (i, argument) in arguments.iter().enumerate() {
+            let start = 12 + 4 * i;
+            buf[start..start + 4].copy_from_slice(&(*argument as u32).to_le_bytes());
+        }
+        if let Some(return_value) = self.return_value {
+            buf[9] = 1;
+            let (mut a0, mut a1, mut a2, mut a3) = (0, 0, 0, 0);
+            encode_syscall_return_trd104(
+                &TRD104SyscallReturn::from(return_value),
+                &mut a0,
+                &mut a1,
+                &mut a2,
+                &mut a3,
+            );
+            for (i, register) in [a0, a1, a2, a3].iter().enumerate() {
+                let start = 28 + 4 * i;
+                buf[start..start + 4].copy_from_slice(&register.to_le_bytes());
+            }
+        }
+        buf
+    }
+}
+
+/// Encode the header of a binary trace with `count` records.
+pub fn encode_trace_header(count: usize) -> [u8; TRACE_HEADER_LEN] {
+    let mut buf = [0; TRACE_HEADER_LEN];
+    buf[0..4].copy_from_slice(&TRACE_MAGIC);
+    buf[4..6].copy_from_slice(&TRACE_VERSION.to_le_bytes());
+    buf[6..8].copy_from_slice(&(TRACE_RECORD_LEN as u16).to_le_bytes());
+    buf[8..12].copy_from_slice(&(count as u32).to_le_bytes());
+    buf
+}
+
+/// Receives the system calls processes make.
+///
+/// The kernel only calls the tracer if the `record_syscalls` configuration
+/// option is enabled.
+pub trait SyscallTracer {
+    /// Called when `processid` makes `syscall`, before the kernel handles it.
+    fn syscall_called(&self, processid: ProcessId, syscall: &Syscall);
+
+    /// Called when the kernel sets the value returned to `processid` for its
+    /// most recent system call.
+    fn syscall_returned(&self, processid: ProcessId, return_value: &SyscallReturn);
+}
+
+/// A context token that the caller must pass back to
+/// [`SyscallTrace::write_binary()`] to continue writing a trace.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub struct SyscallTraceContext {
+    /// The byte of the binary trace to continue writing from.
+    pub offset: usize,
+}
+
+/// Control of and access to the records kept by a [`SyscallTracer`].
+pub trait SyscallTrace {
+    /// Start or stop recording the system calls of `processid`. Returns
+    /// `ErrorCode::NOMEM` if no more processes can be traced.
+    fn set_tracing(&self, processid: ProcessId, enabled: bool) -> Result<(), ErrorCode>;
+
+    /// Whether the system calls of `processid` are being recorded.
+    fn is_tracing(&self, processid: ProcessId) -> bool;
+
+    /// Number of records kept.
+    fn record_count(&self) -> usize;
+
+    /// Get a record, where `index` 0 is the oldest record. Returns `None` if
+    /// there is no such record.
+    fn record(&self, index: usize) -> Option<SyscallTraceRecord>;
+
+    /// Remove all records.
+    fn clear(&self);
+
+    /// Write the records in the binary trace format to `writer`.
+    ///
+    /// This works like
+    /// [`ProcessPrinter::print_overview()`](crate::process::ProcessPrinter::print_overview):
+    /// pass `None` for `context` the first time. If this returns `Some`, the
+    /// writer did not accept the whole trace, and this should be called again
+    /// with the returned context once the writer is ready for more data.
+    fn write_binary(
+        &self,
+        writer: &mut dyn BinaryWrite,
+        context: Option<SyscallTraceContext>,
+    ) -> Option<SyscallTraceContext>;
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use crate::Kernel;
+    use crate::utilities::arch_helpers::syscall_from_register_arguments_trd104;
+    use crate::utilities::capability_ptr::CapabilityPtr;
+    use crate::utilities::machine_register::MachineRegister;
+    use std::boxed::Box;
+
+    fn processid(identifier: usize) -> ProcessId {
+        ProcessId::new(Box::leak(Box::new(Kernel::new(&[]))), identifier, 0)
+    }
+
+    fn record(syscall: Syscall, return_value: Option<SyscallReturn>) -> SyscallTraceRecord {
+        SyscallTraceRecord {
+            timestamp_us: 0x1234_5678,
+            processid: processid(7),
+            syscall,
+            return_value,
+        }
+    }
+
+    fn word(buf: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Decode the system call of an encoded record.
+    fn decode_syscall(buf: &[u8; TRACE_RECORD_LEN]) -> Option<Syscall> {
+        let argument = |i: usize| word(buf, 12 + 4 * i) as usize;
+        syscall_from_register_arguments_trd104(
+            buf[8],
+            argument(0),
+            MachineRegister::from(argument(1)),
+            MachineRegister::from(argument(2)),
+            MachineRegister::from(argument(3)),
+        )
+    }
+
+    /// Decode the return value registers of an encoded record, or `None` if
+    /// no return value was recorded.
+    fn decode_return(buf: &[u8; TRACE_RECORD_LEN]) -> Option<[u32; 4]> {
+        (buf[9] & 1 == 1).then(|| [0, 1, 2, 3].map(|i: usize| word(buf, 28 + 4 * i)))
+    }
+
+    #[test]
+    fn header() {
+        let header = encode_trace_header(3);
+        assert_eq!(header[0..4], TRACE_MAGIC);
+        assert_eq!(u16::from_le_bytes([header[4], header[5]]), TRACE_VERSION);
+        assert_eq!(
+            u16::from_le_bytes([header[6], header[7]]) as usize,
+            TRACE_RECORD_LEN
+        );
+        assert_eq!(word(&header, 8), 3);
+    }
+
+    #[test]
+    fn record_fields() {
+        let syscall = Syscall::Memop {
+            operand: 1,
+            arg0: 64,
+        };
+        let buf = record(syscall, None).encode();
+        assert_eq!(word(&buf, 0), 0x1234_5678);
+        assert_eq!(word(&buf, 4), 7);
+        assert_eq!(buf[8], SyscallClass::Memop as u8);
+        assert_eq!(buf[10..12], [0, 0]);
+    }
+
+    #[test]
+    fn syscalls_round_trip() {
+        let syscalls = [
+            Syscall::Yield {
+                yield_type: YieldVariant::Wait,
+            },
+            Syscall::Yield {
+                yield_type: YieldVariant::WaitFor {
+                    driver_number: 0x1,
+                    subdriver_number: 2,
+                },
+            },
+            Syscall::Subscribe {
+                driver_number: 0x90000,
+                subdriver_number: 1,
+                upcall_ptr: CapabilityPtr::from(0x4_0100),
+                appdata: MachineRegister::from(0xaa),
+            },
+            Syscall::Command {
+                driver_number: 0x2,
+                subdriver_number: 3,
+                arg0: 0xdead_beef,
+                arg1: 4,
+            },
+            Syscall::ReadWriteAllow {
+                driver_number: 0x1,
+                subdriver_number: 0,
+                allow_address: 0x2000_0100 as *mut u8,
+                allow_size: 64,
+            },
+            Syscall::UserspaceReadableAllow {
+                driver_number: 0x1,
+                subdriver_number: 1,
+                allow_address: 0x2000_0200 as *mut u8,
+                allow_size: 32,
+            },
+            Syscall::ReadOnlyAllow {
+                driver_number: 0x1,
+                subdriver_number: 2,
+                allow_address: 0x2000_0300 as *const u8,
+                allow_size: 16,
+            },
+            Syscall::Memop {
+                operand: 1,
+                arg0: 256,
+            },
+            Syscall::Exit {
+                which: 0,
+                completion_code: 5,
+            },
+        ];
+        for syscall in syscalls {
+            let buf = record(syscall, None).encode();
+            assert_eq!(decode_syscall(&buf), Some(syscall));
+            assert_eq!(decode_return(&buf), None);
+            assert_eq!(buf[28..], [0; 16]);
+        }
+    }
+
+    #[test]
+    fn return_values_round_trip() {
+        let syscall = Syscall::Command {
+            driver_number: 0x2,
+            subdriver_number: 1,
+            arg0: 0,
+            arg1: 0,
+        };
+        let return_values = [
+            (SyscallReturn::Success, [128, 0, 0, 0]),
+            (SyscallReturn::Failure(ErrorCode::NOMEM), [0, 9, 0, 0]),
+            (SyscallReturn::SuccessU32(42), [129, 42, 0, 0]),
+            (SyscallReturn::SuccessU32U32U32(1, 2, 3), [132, 1, 2, 3]),
+            (
+                SyscallReturn::FailureU32(ErrorCode::BUSY, 0xffff_ffff),
+                [1, 2, 0xffff_ffff, 0],
+            ),
+            (
+                SyscallReturn::SuccessU64(0x1122_3344_5566_7788),
+                [131, 0x5566_7788, 0x1122_3344, 0],
+            ),
+        ];
+        for (return_value, registers) in return_values {
+            let buf = record(syscall, Some(return_value)).encode();
+            assert_eq!(decode_syscall(&buf), Some(syscall));
+            assert_eq!(decode_return(&buf), Some(registers));
+        }
+    }
+}
@@ -129,7 +129,7 @@
 use crate::error_codes::ErrorCode;
 use crate::flash_controller::FlashController;
 use crate::success_codes::SuccessCode;
-use crate::tickv::{State, TicKV};
+use crate::tickv::{KeyCursor, KeyEntry, RegionHealth, State, TicKV};
 use core::cell::Cell;
 
 /// The return type from the continue operation
@@ -149,6 +149,10 @@ pub struct AsyncTicKV<'a, C: FlashController<S>, const S: usize> {
     key: Cell<Option<u64>>,
     value: Cell<Option<&'static mut [u8]>>,
     value_length: Cell<usize>,
+    cursor: Cell<KeyCursor>,
+    key_entry: Cell<Option<KeyEntry>>,
+    region: Cell<usize>,
+    region_health: Cell<Option<RegionHealth>>,
 }
 
 impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
@@ -164,6 +168,10 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
             key: Cell::new(None),
             value: Cell::new(None),
             value_length: Cell::new(0),
+            cursor: Cell::new(KeyCursor::default()),
+            key_entry: Cell::new(None),
+            region: Cell::new(0),
+            region_health: Cell::new(None),
         }
     }
 
@@ -308,6 +316,65 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
         }
     }
 
+    /// Finds the next valid object in flash storage.
+    ///
+    /// `cursor`: The position to start looking from. Use
+    ///           `KeyCursor::default()` to start from the beginning of the
+    ///           flash storage, and the `next` cursor of the previous
+    ///           `KeyEntry` to continue from the object that was found.
+    ///
+    /// On success a `SuccessCode` will be returned. Once the operation has
+    /// completed the object that was found can be retrieved with
+    /// `take_key_entry()`.
+    /// On error a `ErrorCode` will be returned.
+    pub fn next_key(&self, cursor: KeyCursor) -> Result<SuccessCode, ErrorCode> {
+        self.key_entry.set(None);
+        match self.tickv.next_key(cursor) {
+            Ok(_code) => Err(ErrorCode::ReadFail),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_) => {
+                    self.cursor.set(cursor);
+                    Ok(SuccessCode::Queued)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Retrieve the object found by the last completed `next_key()`
+    /// operation.
+    pub fn take_key_entry(&self) -> Option<KeyEntry> {
+        self.key_entry.take()
+    }
+
+    /// Reads the wear and capacity information of a region.
+    ///
+    /// `region`: The region to read, less than `region_count()` of `tickv`.
+    ///
+    /// On success a `SuccessCode` will be returned. Once the operation has
+    /// completed the information can be retrieved with
+    /// `take_region_health()`.
+    /// On error a `ErrorCode` will be returned.
+    pub fn region_health(&self, region: usize) -> Result<SuccessCode, ErrorCode> {
+        self.region_health.set(None);
+        match self.tickv.region_health(region) {
+            Ok(_code) => Err(ErrorCode::ReadFail),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_) => {
+                    self.region.set(region);
+                    Ok(SuccessCode::Queued)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Retrieve the information read by the last completed
+    /// `region_health()` operation.
+    pub fn take_region_health(&self) -> Option<RegionHealth> {
+        self.region_health.take()
+    }
+
     /// Copy data from `read_buffer` argument to the internal read_buffer.
     /// This should be used to copy the data that the implementation wanted
     /// to read when calling `read_region` after the async operation has
@@ -362,6 +429,20 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
                 Ok(bytes_freed) => (Ok(SuccessCode::Complete), bytes_freed),
                 Err(e) => (Err(e), 0),
             },
+            State::NextKey(_) => match self.tickv.next_key(self.cursor.get()) {
+                Ok((s, entry)) => {
+                    self.key_entry.set(Some(entry));
+                    (Ok(s), entry.value_length)
+                }
+                Err(e) => (Err(e), 0),
+            },
+            State::RegionHealth(_) => match self.tickv.region_health(self.region.get()) {
+                Ok((s, health)) => {
+                    self.region_health.set(Some(health));
+                    (Ok(s), 0)
+                }
+                Err(e) => (Err(e), 0),
+            },
             _ => unreachable!(),
         };
 
@@ -373,7 +454,11 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
             Err(e) => match e {
                 ErrorCode::ReadNotReady(_) | ErrorCode::EraseNotReady(_) => (ret, None, 0),
                 ErrorCode::WriteNotReady(_) => {
-                    self.tickv.state.set(State::None);
+                    // Garbage collection continues after writing a region
+                    // header, all other operations are done.
+                    if !matches!(self.tickv.state.get(), State::GarbageCollect(_)) {
+                        self.tickv.state.set(State::None);
+                    }
                     (ret, None, 0)
                 }
                 _ => {
@@ -395,7 +480,7 @@ mod tests {
         use crate::error_codes::ErrorCode;
         use crate::flash_controller::FlashController;
         use crate::success_codes::SuccessCode;
-        use crate::tickv::{HASH_OFFSET, LEN_OFFSET, MAIN_KEY, VERSION, VERSION_OFFSET};
+        use crate::tickv::{HASH_OFFSET, KeyCursor, LEN_OFFSET, MAIN_KEY, VERSION, VERSION_OFFSET};
         use core::hash::{Hash, Hasher};
         use core::ptr::addr_of_mut;
         use std::cell::Cell;
@@ -1164,17 +1249,24 @@ mod tests {
             println!("Get non-existent key ONE");
             match unsafe { tickv.get_key(get_hashed_key(b"ONE"), &mut *addr_of_mut!(BUF)) } {
                 Ok(SuccessCode::Queued) => {
-                    flash_ctrl_callback(&tickv);
-                    assert_eq!(
-                        tickv.continue_operation().0,
-                        Err(ErrorCode::ReadNotReady(62))
-                    );
+                    // The erased region only contains its region header, so
+                    // the neighbouring regions aren't searched.
                     flash_ctrl_callback(&tickv);
                     assert_eq!(tickv.continue_operation().0, Err(ErrorCode::KeyNotFound));
                 }
                 _ => unreachable!(),
             }
 
+            println!("Read the erase counts");
+            let mut erase_count = 0;
+            for region in 0..tickv.tickv.region_count() {
+                assert_eq!(tickv.region_health(region), Ok(SuccessCode::Queued));
+                flash_ctrl_callback(&tickv);
+                assert_eq!(tickv.continue_operation().0, Ok(SuccessCode::Complete));
+                erase_count += tickv.take_region_health().unwrap().erase_count;
+            }
+            assert_eq!(erase_count, 1);
+
             println!("Add Key ONE");
             let ret =
                 unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut *addr_of_mut!(VALUE), 32) };
@@ -1187,5 +1279,77 @@ mod tests {
                 _ => unreachable!("ret: {:?}", ret),
             }
         }
+
+        #[test]
+        fn test_next_key() {
+            let mut read_buf: [u8; 1024] = [0; 1024];
+            let mut hash_function = DefaultHasher::new();
+            MAIN_KEY.hash(&mut hash_function);
+            let hash = hash_function.finish();
+
+            let tickv = AsyncTicKV::<FlashCtrl<1024>, 1024>::new(
+                FlashCtrl::new(true),
+                &mut read_buf,
+                0x10000,
+            );
+
+            let mut ret = tickv.initialise(hash);
+            while ret.is_err() {
+                flash_ctrl_callback(&tickv);
+
+                // There is no actual delay in the test, just continue now
+                let (r, _buf, _len) = tickv.continue_operation();
+                ret = r;
+            }
+
+            static mut VALUE: [u8; 32] = [0x23; 32];
+
+            println!("Add key ONE");
+            let ret =
+                unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut *addr_of_mut!(VALUE), 32) };
+            match ret {
+                Ok(SuccessCode::Queued) => {
+                    // There is no actual delay in the test, just continue now
+                    flash_ctrl_callback(&tickv);
+                    tickv.continue_operation().0.unwrap();
+                }
+                _ => unreachable!("ret: {:?}", ret),
+            }
+
+            println!("List keys");
+            let mut keys = std::vec::Vec::new();
+            let mut cursor = KeyCursor::default();
+            loop {
+                match tickv.next_key(cursor) {
+                    Ok(SuccessCode::Queued) => {}
+                    Err(ErrorCode::KeyNotFound) => break,
+                    ret => unreachable!("ret: {:?}", ret),
+                }
+
+                // Each region that is searched is read asynchronously
+                let ret = loop {
+                    flash_ctrl_callback(&tickv);
+                    match tickv.continue_operation().0 {
+                        Err(ErrorCode::ReadNotReady(_)) => {}
+                        ret => break ret,
+                    }
+                };
+
+                match ret {
+                    Ok(_) => {
+                        let entry = tickv.take_key_entry().unwrap();
+                        keys.push((entry.hashed_key, entry.value_length));
+                        cursor = entry.next;
+                    }
+                    Err(ErrorCode::KeyNotFound) => break,
+                    ret => unreachable!("ret: {:?}", ret),
+                }
+            }
+
+            keys.sort_unstable();
+            let mut expected = std::vec![(hash, 0), (get_hashed_key(b"ONE"), 32)];
+            expected.sort_unstable();
+            assert_eq!(keys, expected);
+        }
     }
 }
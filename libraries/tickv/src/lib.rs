@@ -228,6 +228,8 @@ pub use crate::flash_controller::FlashController;
 pub use crate::tickv::MAIN_KEY;
 #[doc(inline)]
 pub use crate::tickv::TicKV;
+#[doc(inline)]
+pub use crate::tickv::{KeyCursor, KeyEntry, RegionHealth};
 
 // This is used to run the tests on a host
 #[cfg(test)]
@@ -4,7 +4,10 @@
 
 use crate::error_codes::ErrorCode;
 use crate::flash_controller::FlashController;
-use crate::tickv::{HASH_OFFSET, LEN_OFFSET, MAIN_KEY, TicKV, VERSION, VERSION_OFFSET};
+use crate::tickv::{
+    HASH_OFFSET, KeyCursor, LEN_OFFSET, MAIN_KEY, REGION_HEADER_LENGTH, RegionHealth, TicKV,
+    VERSION, VERSION_OFFSET,
+};
 use core::hash::{Hash, Hasher};
 use std::cell::Cell;
 use std::cell::RefCell;
@@ -527,6 +530,116 @@ mod store_flast_ctrl {
         println!("Add Key ONE");
         tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
     }
+
+    #[test]
+    fn test_next_key() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+
+        let value: [u8; 32] = [0x23; 32];
+
+        println!("Add Key ONE and TWO");
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        tickv.append_key(get_hashed_key(b"TWO"), &value).unwrap();
+
+        let list_keys = || {
+            let mut keys = std::vec::Vec::new();
+            let mut cursor = KeyCursor::default();
+            loop {
+                match tickv.next_key(cursor) {
+                    Ok((_, entry)) => {
+                        keys.push((entry.hashed_key, entry.value_length));
+                        cursor = entry.next;
+                    }
+                    Err(ErrorCode::KeyNotFound) => break,
+                    Err(e) => panic!("Unexpected error {:?}", e),
+                }
+            }
+            keys.sort_unstable();
+            keys
+        };
+
+        println!("List keys");
+        let mut expected = std::vec![
+            (hash, 0),
+            (get_hashed_key(b"ONE"), 32),
+            (get_hashed_key(b"TWO"), 32),
+        ];
+        expected.sort_unstable();
+        assert_eq!(list_keys(), expected);
+
+        println!("Delete Key ONE");
+        tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+
+        println!("List keys without ONE");
+        expected.retain(|(key, _)| *key != get_hashed_key(b"ONE"));
+        assert_eq!(list_keys(), expected);
+    }
+
+    #[test]
+    fn test_region_health() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+
+        let value: [u8; 32] = [0x23; 32];
+        let object_length = 11 + 32 + 4;
+        let main_key_length = 11 + 4;
+
+        let total_health = || {
+            let mut total = RegionHealth::default();
+            let mut erase_counts = std::vec::Vec::new();
+            for region in 0..tickv.region_count() {
+                let (_, health) = tickv.region_health(region).unwrap();
+                assert_eq!(health.used + health.reclaimable + health.free, 1024);
+                total.used += health.used;
+                total.reclaimable += health.reclaimable;
+                total.free += health.free;
+                erase_counts.push(health.erase_count);
+            }
+            (total, erase_counts)
+        };
+
+        assert_eq!(tickv.region_health(64), Err(ErrorCode::ReadFail));
+
+        println!("Add and delete Key ONE");
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+
+        let (total, erase_counts) = total_health();
+        assert_eq!(total.used, main_key_length);
+        assert_eq!(total.reclaimable, object_length);
+        assert!(erase_counts.iter().all(|count| *count == 0));
+
+        for erase_count in 1..=2 {
+            println!("Garbage collect flash with deleted key");
+            assert_eq!(tickv.garbage_collect(), Ok(1024));
+
+            let (total, erase_counts) = total_health();
+            assert_eq!(total.used, main_key_length + REGION_HEADER_LENGTH);
+            assert_eq!(total.reclaimable, 0);
+            assert_eq!(erase_counts.iter().sum::<u32>(), erase_count);
+            assert_eq!(erase_counts.iter().max(), Some(&erase_count));
+
+            println!("Add and delete Key ONE in the erased region");
+            tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+            tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+        }
+
+        println!("The region header is not a key");
+        let (_, entry) = tickv.next_key(KeyCursor::default()).unwrap();
+        assert_eq!(entry.hashed_key, hash);
+        assert_eq!(tickv.next_key(entry.next), Err(ErrorCode::KeyNotFound));
+    }
 }
 
 mod no_check_store_flast_ctrl {
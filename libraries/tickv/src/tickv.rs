@@ -34,7 +34,10 @@ pub(crate) enum KeyState {
 #[derive(Clone, Copy, PartialEq)]
 pub(crate) enum RubbishState {
     ReadRegion(usize, usize),
-    EraseRegion(usize, usize),
+    /// Erasing a region, with the erase count to store in its region header
+    EraseRegion(usize, usize, u32),
+    /// Writing the region header of an erased region
+    WriteHeader(usize, usize),
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -55,6 +58,46 @@ pub(crate) enum State {
     ZeroiseKey(KeyState),
     /// Running garbage collection
     GarbageCollect(RubbishState),
+    /// Finding the next key
+    NextKey(KeyState),
+    /// Reading the health of a region
+    RegionHealth(KeyState),
+}
+
+/// A position in the flash storage, used to iterate over the stored objects.
+///
+/// The default cursor points to the start of the flash storage.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct KeyCursor {
+    region: usize,
+    offset: usize,
+}
+
+/// A valid object found by `next_key()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyEntry {
+    /// The hashed key of the object.
+    pub hashed_key: u64,
+    /// The length of the value stored in the object.
+    pub value_length: usize,
+    /// The cursor to pass to `next_key()` to find the following object.
+    pub next: KeyCursor,
+}
+
+/// Wear and capacity information about a region, from `region_health()`.
+///
+/// The used, reclaimable and free bytes add up to the size of the region.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct RegionHealth {
+    /// The number of times garbage collection has erased the region.
+    pub erase_count: u32,
+    /// The number of bytes used by valid objects and the region header.
+    pub used: usize,
+    /// The number of bytes used by invalidated objects. These are only freed
+    /// once every object in the region has been invalidated.
+    pub reclaimable: usize,
+    /// The number of bytes after the last object in the region.
+    pub free: usize,
 }
 
 /// The struct storing all of the TicKV information.
@@ -97,6 +140,14 @@ pub(crate) const HASH_OFFSET: usize = 3;
 pub(crate) const HEADER_LENGTH: usize = HASH_OFFSET + 8;
 pub(crate) const CHECK_SUM_LEN: usize = 4;
 
+/// The hashed key of the region header.
+///
+/// The region header is the object at the start of a region that stores how
+/// many times the region has been erased. `get_region()` doesn't accept this
+/// hash, so it can't be used by a stored key.
+pub(crate) const REGION_HEADER_KEY: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+pub(crate) const REGION_HEADER_LENGTH: usize = HEADER_LENGTH + 4 + CHECK_SUM_LEN;
+
 /// The main key. A hashed version of this should be passed to
 /// `initialise()`.
 pub const MAIN_KEY: &[u8; 15] = b"tickv-super-key";
@@ -258,6 +309,88 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         None
     }
 
+    /// Find the region header at the start of some loaded region data.
+    ///
+    /// Returns the length of the region header, or 0 if the region doesn't
+    /// start with one, and the erase count stored in it. A region header
+    /// with an invalid check sum is skipped, but has an erase count of 0.
+    fn region_header(&self, region_data: &[u8]) -> (usize, u32) {
+        let header: &[u8; REGION_HEADER_LENGTH] = match region_data
+            .get(..REGION_HEADER_LENGTH)
+            .and_then(|header| header.try_into().ok())
+        {
+            Some(header) => header,
+            None => return (0, 0),
+        };
+
+        let total_length =
+            ((header[LEN_OFFSET] as u16) & !0xF0) << 8 | header[LEN_OFFSET + 1] as u16;
+        if header[VERSION_OFFSET] != VERSION
+            || header[LEN_OFFSET] & 0x80 != 0x80
+            || total_length as usize != REGION_HEADER_LENGTH
+            || header[HASH_OFFSET..HEADER_LENGTH] != REGION_HEADER_KEY.to_be_bytes()
+        {
+            return (0, 0);
+        }
+
+        let check_sum = crc32::Crc32::new();
+        check_sum.update(&header[..HEADER_LENGTH + 4]);
+        if header[HEADER_LENGTH + 4..] != check_sum.finalise().to_ne_bytes() {
+            return (REGION_HEADER_LENGTH, 0);
+        }
+
+        let mut erase_count = [0; 4];
+        erase_count.copy_from_slice(&header[HEADER_LENGTH..HEADER_LENGTH + 4]);
+        (REGION_HEADER_LENGTH, u32::from_le_bytes(erase_count))
+    }
+
+    /// Write the region header of a region that has just been erased.
+    ///
+    /// `region`: The region to write the header to.
+    /// `flash_freed`: The number of bytes freed by the garbage collection so
+    ///                far, including this region.
+    /// `erase_count`: The number of times the region has been erased.
+    ///
+    /// The whole region is written, so that flash controllers which write
+    /// full pages don't write back the data from before the erase.
+    fn write_region_header(
+        &self,
+        region: usize,
+        flash_freed: usize,
+        erase_count: u32,
+    ) -> Result<(), ErrorCode> {
+        let region_data = self.read_buffer.take().unwrap();
+        region_data.fill(0xFF);
+
+        let header = ObjectHeader::new(REGION_HEADER_KEY, REGION_HEADER_LENGTH as u16);
+        region_data[VERSION_OFFSET] = header.version;
+        region_data[LEN_OFFSET] = (header.len >> 8) as u8 & 0x0F | (header.flags << 4) & 0xF0;
+        region_data[LEN_OFFSET + 1] = (header.len & 0xFF) as u8;
+        region_data[HASH_OFFSET..HEADER_LENGTH].copy_from_slice(&header.hashed_key.to_be_bytes());
+        region_data[HEADER_LENGTH..HEADER_LENGTH + 4].copy_from_slice(&erase_count.to_le_bytes());
+
+        let check_sum = crc32::Crc32::new();
+        check_sum.update(&region_data[..HEADER_LENGTH + 4]);
+        region_data[HEADER_LENGTH + 4..REGION_HEADER_LENGTH]
+            .copy_from_slice(&check_sum.finalise().to_ne_bytes());
+
+        let ret = self.controller.write(S * region, region_data);
+        self.read_buffer.replace(Some(region_data));
+
+        if let Err(e) = ret {
+            if let ErrorCode::WriteNotReady(_) = e {
+                self.state
+                    .set(State::GarbageCollect(RubbishState::WriteHeader(
+                        region,
+                        flash_freed,
+                    )));
+            }
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
     /// Find a key in some loaded region data.
     ///
     /// On success return the offset in the region_data where the key is and the
@@ -274,7 +407,8 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         // Split the hash
         let hash = hash.to_ne_bytes();
 
-        let mut offset: usize = 0;
+        // The region header doesn't make a region non-empty
+        let (mut offset, _) = self.region_header(region_data);
         let mut empty: bool = true;
 
         loop {
@@ -967,6 +1101,231 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         }
     }
 
+    /// Find the first valid object at or after `offset` in some loaded region
+    /// data.
+    ///
+    /// On success return the offset of the object, its hashed key and its
+    /// total length, or `None` if there are no more valid objects in the
+    /// region.
+    fn find_next_object(
+        &self,
+        region_data: &[u8],
+        mut offset: usize,
+    ) -> Result<Option<(usize, u64, u16)>, ErrorCode> {
+        // The region header isn't an object
+        let (header_length, _) = self.region_header(region_data);
+        offset = offset.max(header_length);
+
+        loop {
+            if offset + HEADER_LENGTH >= S {
+                // We have reached the end of the region
+                return Ok(None);
+            }
+
+            // Check to see if we have data
+            let version = *region_data
+                .get(offset + VERSION_OFFSET)
+                .ok_or(ErrorCode::CorruptData)?;
+            if version == 0xFF {
+                // We hit the end.
+                return Ok(None);
+            }
+
+            // We found a version, check that we support it
+            if version != VERSION {
+                return Err(ErrorCode::UnsupportedVersion);
+            }
+
+            // Find this entries length
+            let len_flags = *region_data
+                .get(offset + LEN_OFFSET)
+                .ok_or(ErrorCode::CorruptData)?;
+            let total_length = ((len_flags as u16) & !0xF0) << 8
+                | *region_data
+                    .get(offset + LEN_OFFSET + 1)
+                    .ok_or(ErrorCode::CorruptData)? as u16;
+
+            // Check to see if all fields are just 0
+            if total_length == 0 {
+                return Ok(None);
+            }
+
+            // Check to see if the entry has been deleted
+            if len_flags & 0x80 != 0x80 {
+                // Increment our offset by the length and repeat the loop
+                offset += total_length as usize;
+                continue;
+            }
+
+            // We have found a valid entry, the hash is stored most significant
+            // byte first.
+            let hashed_key = region_data
+                .get(offset + HASH_OFFSET..offset + HEADER_LENGTH)
+                .and_then(|hash| hash.try_into().ok())
+                .map(u64::from_be_bytes)
+                .ok_or(ErrorCode::CorruptData)?;
+
+            return Ok(Some((offset, hashed_key, total_length)));
+        }
+    }
+
+    /// Finds the next valid object in flash storage.
+    ///
+    /// `cursor`: The position to start looking from. Use
+    ///           `KeyCursor::default()` to start from the beginning of the
+    ///           flash storage, and the `next` cursor of the returned
+    ///           `KeyEntry` to continue from the object that was found.
+    ///
+    /// On success a `SuccessCode` will be returned and the `KeyEntry` with
+    /// the hashed key and value length of the object.
+    /// On error a `ErrorCode` will be returned. `KeyNotFound` is returned
+    /// when there are no more valid objects.
+    ///
+    /// Objects are found in the order they are stored in flash, which is not
+    /// related to their keys. This includes the object of the main key.
+    /// Objects that are added or invalidated during an iteration may or may
+    /// not be found, and a garbage collection does not change the positions of
+    /// the objects that remain.
+    pub fn next_key(&self, cursor: KeyCursor) -> Result<(SuccessCode, KeyEntry), ErrorCode> {
+        let num_region = self.flash_size / S;
+
+        let (mut region, mut offset) = match self.state.get() {
+            State::None => (cursor.region, cursor.offset),
+            State::NextKey(key_state) => match key_state {
+                KeyState::ReadRegion(reg) if reg == cursor.region => (reg, cursor.offset),
+                // We already moved past the region of the cursor
+                KeyState::ReadRegion(reg) => (reg, 0),
+            },
+            _ => unreachable!(),
+        };
+
+        loop {
+            if region >= num_region {
+                // We have looked through all of the regions
+                return Err(ErrorCode::KeyNotFound);
+            }
+
+            // Get the data from that region
+            let region_data = self.read_buffer.take().unwrap();
+            if self.state.get() != State::NextKey(KeyState::ReadRegion(region)) {
+                if let Err(e) = self.controller.read_region(region, region_data) {
+                    self.read_buffer.replace(Some(region_data));
+                    if let ErrorCode::ReadNotReady(reg) = e {
+                        self.state.set(State::NextKey(KeyState::ReadRegion(reg)));
+                    }
+                    return Err(e);
+                }
+            }
+
+            let object = self.find_next_object(region_data, offset);
+            self.read_buffer.replace(Some(region_data));
+
+            match object? {
+                Some((object_offset, hashed_key, total_length)) => {
+                    let entry = KeyEntry {
+                        hashed_key,
+                        value_length: (total_length as usize)
+                            .saturating_sub(HEADER_LENGTH + CHECK_SUM_LEN),
+                        next: KeyCursor {
+                            region,
+                            offset: object_offset + total_length as usize,
+                        },
+                    };
+                    return Ok((SuccessCode::Complete, entry));
+                }
+                None => {
+                    // Try the next region
+                    region += 1;
+                    offset = 0;
+                    self.state.set(State::None);
+                }
+            }
+        }
+    }
+
+    /// Returns the number of regions used by TicKV.
+    pub fn region_count(&self) -> usize {
+        self.flash_size / S
+    }
+
+    /// Count the bytes used by the objects in some loaded region data.
+    fn region_usage(&self, region_data: &[u8]) -> Result<RegionHealth, ErrorCode> {
+        let (mut offset, erase_count) = self.region_header(region_data);
+        let mut health = RegionHealth {
+            erase_count,
+            used: offset,
+            ..RegionHealth::default()
+        };
+
+        while offset + HEADER_LENGTH < S {
+            let version = *region_data
+                .get(offset + VERSION_OFFSET)
+                .ok_or(ErrorCode::CorruptData)?;
+            if version == 0xFF {
+                // We hit the end.
+                break;
+            }
+            if version != VERSION {
+                return Err(ErrorCode::UnsupportedVersion);
+            }
+
+            let len_flags = *region_data
+                .get(offset + LEN_OFFSET)
+                .ok_or(ErrorCode::CorruptData)?;
+            let total_length = ((len_flags as u16) & !0xF0) << 8
+                | *region_data
+                    .get(offset + LEN_OFFSET + 1)
+                    .ok_or(ErrorCode::CorruptData)? as u16;
+            if total_length == 0 {
+                // Nothing after this can be used
+                break;
+            }
+
+            let total_length = (total_length as usize).min(S - offset);
+            if len_flags & 0x80 == 0x80 {
+                health.used += total_length;
+            } else {
+                health.reclaimable += total_length;
+            }
+            offset += total_length;
+        }
+
+        health.free = S - offset.min(S);
+        Ok(health)
+    }
+
+    /// Reads the wear and capacity information of a region.
+    ///
+    /// `region`: The region to read, less than `region_count()`.
+    ///
+    /// On success a `SuccessCode` will be returned and the `RegionHealth`
+    /// of the region.
+    /// On error a `ErrorCode` will be returned. `ReadFail` is returned if
+    /// the region doesn't exist.
+    pub fn region_health(&self, region: usize) -> Result<(SuccessCode, RegionHealth), ErrorCode> {
+        if region >= self.region_count() {
+            return Err(ErrorCode::ReadFail);
+        }
+
+        // Get the data from that region
+        let region_data = self.read_buffer.take().unwrap();
+        if self.state.get() != State::RegionHealth(KeyState::ReadRegion(region)) {
+            if let Err(e) = self.controller.read_region(region, region_data) {
+                self.read_buffer.replace(Some(region_data));
+                if let ErrorCode::ReadNotReady(reg) = e {
+                    self.state
+                        .set(State::RegionHealth(KeyState::ReadRegion(reg)));
+                }
+                return Err(e);
+            }
+        }
+
+        let health = self.region_usage(region_data);
+        self.read_buffer.replace(Some(region_data));
+
+        health.map(|health| (SuccessCode::Complete, health))
+    }
+
     fn garbage_collect_region(
         &self,
         region: usize,
@@ -990,7 +1349,9 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         }
 
         let mut entry_found = false;
-        let mut offset: usize = 0;
+        // The region header isn't an entry, but keep the erase count
+        let (mut offset, erase_count) = self.region_header(region_data);
+        let erase_count = erase_count.saturating_add(1);
 
         loop {
             if offset >= S {
@@ -1070,16 +1431,22 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                     .set(State::GarbageCollect(RubbishState::EraseRegion(
                         reg,
                         flash_freed + S,
+                        erase_count,
                     )));
             }
             return Err(e);
         }
 
+        self.write_region_header(region, flash_freed + S, erase_count)?;
+
         Ok(S)
     }
 
     /// Perform a garbage collection on TicKV
     ///
+    /// Each region that is erased starts with a region header afterwards,
+    /// which counts how many times the region has been erased.
+    ///
     /// On success the number of bytes freed will be returned.
     /// On error a `ErrorCode` will be returned.
     pub fn garbage_collect(&self) -> Result<usize, ErrorCode> {
@@ -1092,8 +1459,14 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                     flash_freed += ff;
                     reg
                 }
-                // We already erased region reg, so move to the next one
-                RubbishState::EraseRegion(reg, ff) => {
+                // We already erased region reg, so write its region header
+                // and move to the next one
+                RubbishState::EraseRegion(reg, ff, erase_count) => {
+                    flash_freed += ff;
+                    self.write_region_header(reg, ff, erase_count)?;
+                    reg + 1
+                }
+                RubbishState::WriteHeader(reg, ff) => {
                     flash_freed += ff;
                     reg + 1
                 }
@@ -3,11 +3,23 @@
 // Copyright Tock Contributors 2022.
 
 //! Tock Binary Format (TBF) header parsing library.
+//!
+//! With the `std` feature, this also includes code to write TBF headers, for
+//! host tools that create or modify TBFs.
 
 // Parsing the headers does not require any unsafe operations.
 #![forbid(unsafe_code)]
 #![no_std]
 
+// Writing headers needs an allocator, so is only available on a host.
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
 pub mod parse;
 #[allow(dead_code)] // Some fields not read on device, but read when creating headers
 pub mod types;
+#[cfg(any(feature = "std", test))]
+pub mod write;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,215 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Round-trip tests between the TBF writer and parser.
+
+use std::num::NonZeroU32;
+use std::string::String;
+use std::vec;
+use std::vec::Vec;
+
+use crate::parse;
+use crate::types::{CommandPermissions, TbfFooterV2CredentialsType, TbfHeader, TbfParseError};
+use crate::write::{DriverPermission, Tbf, TbfFooterEntry, TbfHeaderEntry};
+
+/// A TBF with one of every header entry the writer supports.
+fn every_header() -> Tbf {
+    Tbf::new(
+        vec![
+            TbfHeaderEntry::Program {
+                init_fn_offset: 0x40,
+                protected_trailer_size: 0x20,
+                minimum_ram_size: 4096,
+                binary_end_offset: 0,
+                version: 7,
+            },
+            TbfHeaderEntry::PackageName(String::from("blink")),
+            TbfHeaderEntry::WriteableFlashRegions(vec![(0x100, 0x200)]),
+            TbfHeaderEntry::FixedAddresses {
+                start_process_ram: 0x20004000,
+                start_process_flash: 0xFFFFFFFF,
+            },
+            TbfHeaderEntry::Permissions(vec![
+                DriverPermission {
+                    driver_number: 0x1,
+                    offset: 0,
+                    allowed_commands: 0b111,
+                },
+                DriverPermission {
+                    driver_number: 0x1,
+                    offset: 1,
+                    allowed_commands: 0b1,
+                },
+            ]),
+            TbfHeaderEntry::StoragePermissions {
+                write_id: NonZeroU32::new(0x1234),
+                read_ids: vec![0x1234, 0x5678],
+                modify_ids: vec![0x1234],
+            },
+            TbfHeaderEntry::KernelVersion { major: 2, minor: 1 },
+            TbfHeaderEntry::ShortId(NonZeroU32::new(0xabcd)),
+        ],
+        vec![0xa5; 256],
+    )
+}
+
+/// Parse the header of a serialized TBF with the kernel's parser.
+fn parse_header(bytes: &[u8]) -> Result<TbfHeader<'_>, TbfParseError> {
+    let (version, header_len, total_len) =
+        parse::parse_tbf_header_lengths(bytes[0..8].try_into().unwrap())
+            .unwrap_or_else(|_| panic!("bad TBF lengths"));
+    assert_eq!(total_len as usize, bytes.len());
+    parse::parse_tbf_header(&bytes[..header_len as usize], version)
+}
+
+#[test]
+fn written_header_parses() {
+    let tbf = every_header();
+    let bytes = tbf.to_bytes();
+    let header = parse_header(&bytes).unwrap();
+
+    assert!(header.is_app());
+    assert!(header.enabled());
+    assert_eq!(header.length() as usize, tbf.header_len());
+    assert_eq!(header.get_package_name(), Some("blink"));
+    assert_eq!(header.get_minimum_app_ram_size(), 4096);
+    assert_eq!(header.get_protected_size(), header.length() as u32 + 0x20);
+    assert_eq!(
+        header.get_init_function_offset(),
+        header.length() as u32 + 0x40
+    );
+    assert_eq!(
+        header.get_binary_end() as usize,
+        header.length() as usize + 256
+    );
+    assert_eq!(header.get_binary_version(), 7);
+    assert_eq!(header.number_writeable_flash_regions(), 1);
+    assert_eq!(header.get_writeable_flash_region(0), (0x100, 0x200));
+    assert_eq!(header.get_fixed_address_ram(), Some(0x20004000));
+    assert_eq!(header.get_fixed_address_flash(), None);
+    assert!(matches!(
+        header.get_command_permissions(0x1, 1),
+        CommandPermissions::Mask(0b1)
+    ));
+    assert!(matches!(
+        header.get_command_permissions(0x2, 0),
+        CommandPermissions::NoPermsThisDriver
+    ));
+    assert_eq!(header.get_storage_write_id(), NonZeroU32::new(0x1234));
+    let (read_len, read_ids) = header.get_storage_read_ids().unwrap();
+    assert_eq!(&read_ids[..read_len], &[0x1234, 0x5678]);
+    let (modify_len, modify_ids) = header.get_storage_modify_ids().unwrap();
+    assert_eq!(&modify_ids[..modify_len], &[0x1234]);
+    assert_eq!(header.get_kernel_version(), Some((2, 1)));
+    assert_eq!(header.get_fixed_short_id(), NonZeroU32::new(0xabcd));
+}
+
+#[test]
+fn main_header_parses() {
+    let mut tbf = Tbf::new(
+        vec![TbfHeaderEntry::Main {
+            init_fn_offset: 0x10,
+            protected_trailer_size: 0,
+            minimum_ram_size: 1024,
+        }],
+        vec![0; 64],
+    );
+    tbf.set_enabled(false);
+    let bytes = tbf.to_bytes();
+    let header = parse_header(&bytes).unwrap();
+
+    assert!(!header.enabled());
+    assert_eq!(header.get_minimum_app_ram_size(), 1024);
+    assert_eq!(header.get_binary_end() as usize, bytes.len());
+    assert_eq!(header.get_fixed_short_id(), None);
+}
+
+#[test]
+fn round_trip() {
+    let mut tbf = every_header();
+    tbf.headers.push(TbfHeaderEntry::Unknown {
+        tipe: 0x77,
+        data: vec![1, 2, 3],
+    });
+    tbf.footers.push(TbfFooterEntry::Credentials {
+        format: TbfFooterV2CredentialsType::SHA256,
+        data: vec![0x11; 32],
+    });
+    assert!(tbf.pad_to(1024));
+    let bytes = tbf.to_bytes();
+    assert_eq!(bytes.len(), 1024);
+
+    let parsed = Tbf::parse(&bytes).unwrap();
+    assert_eq!(parsed.to_bytes(), bytes);
+    // Only the Program header's binary end offset is filled in on write.
+    assert_eq!(parsed.headers[1..], tbf.headers[1..]);
+    assert_eq!(parsed.binary, tbf.binary);
+    assert_eq!(parsed.footers, tbf.footers);
+}
+
+#[test]
+fn footers_parse() {
+    let mut tbf = every_header();
+    tbf.footers.push(TbfFooterEntry::Credentials {
+        format: TbfFooterV2CredentialsType::EcdsaNistP256,
+        data: vec![0x22; 64],
+    });
+    assert!(tbf.pad_to(tbf.total_len() + 100));
+    let bytes = tbf.to_bytes();
+    let binary_end = tbf.header_len() + tbf.binary.len();
+
+    // The kernel's footer parser needs a static slice.
+    let footers: &'static [u8] = Vec::leak(bytes[binary_end..].to_vec());
+    let (credentials, len) = parse::parse_tbf_footer(footers).unwrap();
+    assert_eq!(
+        credentials.format(),
+        TbfFooterV2CredentialsType::EcdsaNistP256
+    );
+    assert_eq!(credentials.data(), &[0x22; 64]);
+
+    let (padding, _) = parse::parse_tbf_footer(&footers[len as usize + 4..]).unwrap();
+    assert_eq!(padding.format(), TbfFooterV2CredentialsType::Reserved);
+}
+
+#[test]
+fn header_edit_keeps_binary() {
+    let tbf = every_header();
+    let mut edited = Tbf::parse(&tbf.to_bytes()).unwrap();
+    for entry in edited.headers.iter_mut() {
+        if let TbfHeaderEntry::PackageName(name) = entry {
+            *name = String::from("a much longer package name");
+        }
+    }
+    let bytes = edited.to_bytes();
+    let header = parse_header(&bytes).unwrap();
+
+    assert_eq!(
+        header.get_package_name(),
+        Some("a much longer package name")
+    );
+    let binary_start = header.length() as usize;
+    let binary_end = header.get_binary_end() as usize;
+    assert_eq!(bytes[binary_start..binary_end], tbf.binary[..]);
+}
+
+#[test]
+fn corrupted_checksum() {
+    let mut bytes = every_header().to_bytes();
+    // Flip a bit in the Program header's init function offset.
+    bytes[16 + 4 + 1] ^= 0x1;
+    assert!(matches!(
+        Tbf::parse(&bytes),
+        Err(TbfParseError::ChecksumMismatch(_, _))
+    ));
+}
+
+#[test]
+fn pad_too_small() {
+    let mut tbf = every_header();
+    let len = tbf.total_len();
+    assert!(!tbf.pad_to(len - 1));
+    assert!(!tbf.pad_to(len + 4));
+    assert!(tbf.pad_to(len));
+    assert!(tbf.footers.is_empty());
+}
@@ -588,6 +588,25 @@ impl core::convert::TryFrom<&[u8]> for TbfHeaderV2ShortId {
     }
 }
 
+impl core::convert::TryFrom<u32> for TbfFooterV2CredentialsType {
+    type Error = TbfParseError;
+
+    fn try_from(format: u32) -> Result<TbfFooterV2CredentialsType, Self::Error> {
+        match format {
+            0 => Ok(TbfFooterV2CredentialsType::Reserved),
+            1 => Ok(TbfFooterV2CredentialsType::Rsa3072Key),
+            2 => Ok(TbfFooterV2CredentialsType::Rsa4096Key),
+            3 => Ok(TbfFooterV2CredentialsType::SHA256),
+            4 => Ok(TbfFooterV2CredentialsType::SHA384),
+            5 => Ok(TbfFooterV2CredentialsType::SHA512),
+            6 => Ok(TbfFooterV2CredentialsType::EcdsaNistP256),
+            _ => Err(TbfParseError::BadTlvEntry(
+                TbfHeaderTypes::TbfFooterCredentials as usize,
+            )),
+        }
+    }
+}
+
 impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
     type Error = TbfParseError;
 
@@ -597,20 +616,7 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
                 .ok_or(TbfParseError::InternalError)?
                 .try_into()?,
         );
-        let ftype = match format {
-            0 => TbfFooterV2CredentialsType::Reserved,
-            1 => TbfFooterV2CredentialsType::Rsa3072Key,
-            2 => TbfFooterV2CredentialsType::Rsa4096Key,
-            3 => TbfFooterV2CredentialsType::SHA256,
-            4 => TbfFooterV2CredentialsType::SHA384,
-            5 => TbfFooterV2CredentialsType::SHA512,
-            6 => TbfFooterV2CredentialsType::EcdsaNistP256,
-            _ => {
-                return Err(TbfParseError::BadTlvEntry(
-                    TbfHeaderTypes::TbfFooterCredentials as usize,
-                ));
-            }
-        };
+        let ftype: TbfFooterV2CredentialsType = format.try_into()?;
         let length = match ftype {
             TbfFooterV2CredentialsType::Reserved => 0,
             TbfFooterV2CredentialsType::Rsa3072Key => 768,
@@ -0,0 +1,578 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Tock Binary Format writing code.
+//!
+//! This is the inverse of [`parse`](crate::parse): it serializes TBF headers
+//! and footers from an owned description of their entries. A [`Tbf`] can also
+//! be created from an existing TBF, modified, and written back out, which is
+//! what host tools need to inspect, edit and sign TBFs.
+//!
+//! This module is only available with the `std` feature.
+
+use std::num::NonZeroU32;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::parse;
+use crate::types::{TbfFooterV2CredentialsType, TbfHeaderTypes, TbfParseError};
+
+/// Length of the fields every v2 header starts with: version, header size,
+/// total size, flags and checksum.
+const BASE_LEN: usize = 16;
+
+/// Length of the type and length fields of a TLV entry.
+const TLV_LEN: usize = 4;
+
+/// The header version this module writes.
+const VERSION: u16 = 2;
+
+/// Permissions for a range of 64 commands of one driver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DriverPermission {
+    pub driver_number: u32,
+    /// Which range of 64 commands `allowed_commands` covers: offset 1 covers
+    /// commands 64 to 127.
+    pub offset: u32,
+    /// Bitmask of the allowed commands.
+    pub allowed_commands: u64,
+}
+
+/// A TLV entry in a TBF header.
+///
+/// The fields mirror the corresponding types in [`types`](crate::types).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TbfHeaderEntry {
+    Main {
+        init_fn_offset: u32,
+        protected_trailer_size: u32,
+        minimum_ram_size: u32,
+    },
+    /// A Program header. When the TBF is written with [`Tbf::to_bytes()`],
+    /// `binary_end_offset` is set to the end of [`Tbf::binary`].
+    Program {
+        init_fn_offset: u32,
+        protected_trailer_size: u32,
+        minimum_ram_size: u32,
+        binary_end_offset: u32,
+        version: u32,
+    },
+    /// Writeable flash regions as `(offset, size)` pairs.
+    WriteableFlashRegions(Vec<(u32, u32)>),
+    PackageName(String),
+    /// Fixed addresses, where `0xFFFFFFFF` means no fixed address.
+    FixedAddresses {
+        start_process_ram: u32,
+        start_process_flash: u32,
+    },
+    Permissions(Vec<DriverPermission>),
+    StoragePermissions {
+        write_id: Option<NonZeroU32>,
+        read_ids: Vec<u32>,
+        modify_ids: Vec<u32>,
+    },
+    KernelVersion {
+        major: u16,
+        minor: u16,
+    },
+    ShortId(Option<NonZeroU32>),
+    /// An entry of a type this library does not understand, kept as is.
+    Unknown {
+        tipe: u16,
+        data: Vec<u8>,
+    },
+}
+
+/// A TLV entry in the footers of a TBF.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TbfFooterEntry {
+    /// A credential over the integrity region of the TBF. `Reserved`
+    /// credentials are padding, and their data is ignored.
+    Credentials {
+        format: TbfFooterV2CredentialsType,
+        data: Vec<u8>,
+    },
+    /// An entry of a type this library does not understand, kept as is.
+    Unknown { tipe: u16, data: Vec<u8> },
+}
+
+fn read_u16(b: &[u8], pos: usize) -> Result<u16, TbfParseError> {
+    Ok(u16::from_le_bytes(
+        b.get(pos..pos + 2)
+            .ok_or(TbfParseError::NotEnoughFlash)?
+            .try_into()?,
+    ))
+}
+
+fn read_u32(b: &[u8], pos: usize) -> Result<u32, TbfParseError> {
+    Ok(u32::from_le_bytes(
+        b.get(pos..pos + 4)
+            .ok_or(TbfParseError::NotEnoughFlash)?
+            .try_into()?,
+    ))
+}
+
+fn read_u64(b: &[u8], pos: usize) -> Result<u64, TbfParseError> {
+    Ok(u64::from_le_bytes(
+        b.get(pos..pos + 8)
+            .ok_or(TbfParseError::NotEnoughFlash)?
+            .try_into()?,
+    ))
+}
+
+/// Read a list of `u32`s prefixed by a `u16` count, returning the list and
+/// the position after it.
+fn read_u32_list(b: &[u8], pos: usize) -> Result<(Vec<u32>, usize), TbfParseError> {
+    let count = read_u16(b, pos)? as usize;
+    let list = (0..count)
+        .map(|i| read_u32(b, pos + 2 + 4 * i))
+        .collect::<Result<Vec<u32>, TbfParseError>>()?;
+    Ok((list, pos + 2 + 4 * count))
+}
+
+/// Write a TLV entry, padding the value to a multiple of four bytes if `pad`
+/// is set.
+fn write_tlv(out: &mut Vec<u8>, tipe: u16, value: &[u8], pad: bool) {
+    out.extend_from_slice(&tipe.to_le_bytes());
+    out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    out.extend_from_slice(value);
+    if pad {
+        out.resize(
+            out.len() + (value.len().next_multiple_of(4) - value.len()),
+            0,
+        );
+    }
+}
+
+impl TbfHeaderEntry {
+    /// The TLV type of this entry.
+    pub fn tipe(&self) -> u16 {
+        let tipe = match self {
+            TbfHeaderEntry::Main { .. } => TbfHeaderTypes::TbfHeaderMain,
+            TbfHeaderEntry::Program { .. } => TbfHeaderTypes::TbfHeaderProgram,
+            TbfHeaderEntry::WriteableFlashRegions(_) => {
+                TbfHeaderTypes::TbfHeaderWriteableFlashRegions
+            }
+            TbfHeaderEntry::PackageName(_) => TbfHeaderTypes::TbfHeaderPackageName,
+            TbfHeaderEntry::FixedAddresses { .. } => TbfHeaderTypes::TbfHeaderFixedAddresses,
+            TbfHeaderEntry::Permissions(_) => TbfHeaderTypes::TbfHeaderPermissions,
+            TbfHeaderEntry::StoragePermissions { .. } => {
+                TbfHeaderTypes::TbfHeaderStoragePermissions
+            }
+            TbfHeaderEntry::KernelVersion { .. } => TbfHeaderTypes::TbfHeaderKernelVersion,
+            TbfHeaderEntry::ShortId(_) => TbfHeaderTypes::TbfHeaderShortId,
+            TbfHeaderEntry::Unknown { tipe, .. } => return *tipe,
+        };
+        tipe as u16
+    }
+
+    /// The value of this entry, without the TLV type and length.
+    fn value(&self) -> Vec<u8> {
+        let mut value = Vec::new();
+        match self {
+            TbfHeaderEntry::Main {
+                init_fn_offset,
+                protected_trailer_size,
+                minimum_ram_size,
+            } => {
+                value.extend_from_slice(&init_fn_offset.to_le_bytes());
+                value.extend_from_slice(&protected_trailer_size.to_le_bytes());
+                value.extend_from_slice(&minimum_ram_size.to_le_bytes());
+            }
+            TbfHeaderEntry::Program {
+                init_fn_offset,
+                protected_trailer_size,
+                minimum_ram_size,
+                binary_end_offset,
+                version,
+            } => {
+                value.extend_from_slice(&init_fn_offset.to_le_bytes());
+                value.extend_from_slice(&protected_trailer_size.to_le_bytes());
+                value.extend_from_slice(&minimum_ram_size.to_le_bytes());
+                value.extend_from_slice(&binary_end_offset.to_le_bytes());
+                value.extend_from_slice(&version.to_le_bytes());
+            }
+            TbfHeaderEntry::WriteableFlashRegions(regions) => {
+                for (offset, size) in regions {
+                    value.extend_from_slice(&offset.to_le_bytes());
+                    value.extend_from_slice(&size.to_le_bytes());
+                }
+            }
+            TbfHeaderEntry::PackageName(name) => value.extend_from_slice(name.as_bytes()),
+            TbfHeaderEntry::FixedAddresses {
+                start_process_ram,
+                start_process_flash,
+            } => {
+                value.extend_from_slice(&start_process_ram.to_le_bytes());
+                value.extend_from_slice(&start_process_flash.to_le_bytes());
+            }
+            TbfHeaderEntry::Permissions(perms) => {
+                value.extend_from_slice(&(perms.len() as u16).to_le_bytes());
+                for perm in perms {
+                    value.extend_from_slice(&perm.driver_number.to_le_bytes());
+                    value.extend_from_slice(&perm.offset.to_le_bytes());
+                    value.extend_from_slice(&perm.allowed_commands.to_le_bytes());
+                }
+            }
+            TbfHeaderEntry::StoragePermissions {
+                write_id,
+                read_ids,
+                modify_ids,
+            } => {
+                value.extend_from_slice(&write_id.map_or(0, |id| id.get()).to_le_bytes());
+                for ids in [read_ids, modify_ids] {
+                    value.extend_from_slice(&(ids.len() as u16).to_le_bytes());
+                    for id in ids {
+                        value.extend_from_slice(&id.to_le_bytes());
+                    }
+                }
+            }
+            TbfHeaderEntry::KernelVersion { major, minor } => {
+                value.extend_from_slice(&major.to_le_bytes());
+                value.extend_from_slice(&minor.to_le_bytes());
+            }
+            TbfHeaderEntry::ShortId(short_id) => {
+                value.extend_from_slice(&short_id.map_or(0, |id| id.get()).to_le_bytes());
+            }
+            TbfHeaderEntry::Unknown { data, .. } => value.extend_from_slice(data),
+        }
+        value
+    }
+
+    /// Append this entry as a TLV, padded to a multiple of four bytes.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        write_tlv(out, self.tipe(), &self.value(), true);
+    }
+
+    /// Decode the entry of type `tipe` from its value `b`.
+    fn parse(tipe: u16, b: &[u8]) -> Result<TbfHeaderEntry, TbfParseError> {
+        let entry = match TbfHeaderTypes::try_from(tipe)? {
+            TbfHeaderTypes::TbfHeaderMain => TbfHeaderEntry::Main {
+                init_fn_offset: read_u32(b, 0)?,
+                protected_trailer_size: read_u32(b, 4)?,
+                minimum_ram_size: read_u32(b, 8)?,
+            },
+            TbfHeaderTypes::TbfHeaderProgram => TbfHeaderEntry::Program {
+                init_fn_offset: read_u32(b, 0)?,
+                protected_trailer_size: read_u32(b, 4)?,
+                minimum_ram_size: read_u32(b, 8)?,
+                binary_end_offset: read_u32(b, 12)?,
+                version: read_u32(b, 16)?,
+            },
+            TbfHeaderTypes::TbfHeaderWriteableFlashRegions => {
+                TbfHeaderEntry::WriteableFlashRegions(
+                    (0..b.len() / 8)
+                        .map(|i| Ok((read_u32(b, 8 * i)?, read_u32(b, 8 * i + 4)?)))
+                        .collect::<Result<Vec<(u32, u32)>, TbfParseError>>()?,
+                )
+            }
+            TbfHeaderTypes::TbfHeaderPackageName => TbfHeaderEntry::PackageName(String::from(
+                core::str::from_utf8(b).or(Err(TbfParseError::BadProcessName))?,
+            )),
+            TbfHeaderTypes::TbfHeaderFixedAddresses => TbfHeaderEntry::FixedAddresses {
+                start_process_ram: read_u32(b, 0)?,
+                start_process_flash: read_u32(b, 4)?,
+            },
+            TbfHeaderTypes::TbfHeaderPermissions => {
+                let count = read_u16(b, 0)? as usize;
+                TbfHeaderEntry::Permissions(
+                    (0..count)
+                        .map(|i| {
+                            let pos = 2 + 16 * i;
+                            Ok(DriverPermission {
+                                driver_number: read_u32(b, pos)?,
+                                offset: read_u32(b, pos + 4)?,
+                                allowed_commands: read_u64(b, pos + 8)?,
+                            })
+                        })
+                        .collect::<Result<Vec<DriverPermission>, TbfParseError>>()?,
+                )
+            }
+            TbfHeaderTypes::TbfHeaderStoragePermissions => {
+                let (read_ids, pos) = read_u32_list(b, 4)?;
+                let (modify_ids, _) = read_u32_list(b, pos)?;
+                TbfHeaderEntry::StoragePermissions {
+                    write_id: NonZeroU32::new(read_u32(b, 0)?),
+                    read_ids,
+                    modify_ids,
+                }
+            }
+            TbfHeaderTypes::TbfHeaderKernelVersion => TbfHeaderEntry::KernelVersion {
+                major: read_u16(b, 0)?,
+                minor: read_u16(b, 2)?,
+            },
+            TbfHeaderTypes::TbfHeaderShortId => {
+                TbfHeaderEntry::ShortId(NonZeroU32::new(read_u32(b, 0)?))
+            }
+            TbfHeaderTypes::TbfFooterCredentials | TbfHeaderTypes::Unknown => {
+                TbfHeaderEntry::Unknown {
+                    tipe,
+                    data: b.to_vec(),
+                }
+            }
+        };
+        Ok(entry)
+    }
+}
+
+impl TbfFooterEntry {
+    /// Create a `Reserved` credentials footer of `len` bytes in total,
+    /// including its TLV header. `len` must be at least 8.
+    pub fn reserved(len: usize) -> TbfFooterEntry {
+        TbfFooterEntry::Credentials {
+            format: TbfFooterV2CredentialsType::Reserved,
+            data: std::vec![0; len - TLV_LEN - 4],
+        }
+    }
+
+    /// The length of this footer in bytes, including its TLV header.
+    pub fn len(&self) -> usize {
+        match self {
+            TbfFooterEntry::Credentials { data, .. } => TLV_LEN + 4 + data.len(),
+            TbfFooterEntry::Unknown { data, .. } => TLV_LEN + data.len(),
+        }
+    }
+
+    /// Append this footer as a TLV. Unlike header entries, footers are not
+    /// padded.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            TbfFooterEntry::Credentials { format, data } => {
+                let mut value = (*format as u32).to_le_bytes().to_vec();
+                value.extend_from_slice(data);
+                write_tlv(
+                    out,
+                    TbfHeaderTypes::TbfFooterCredentials as u16,
+                    &value,
+                    false,
+                );
+            }
+            TbfFooterEntry::Unknown { tipe, data } => write_tlv(out, *tipe, data, false),
+        }
+    }
+
+    fn parse(tipe: u16, b: &[u8]) -> Result<TbfFooterEntry, TbfParseError> {
+        match TbfHeaderTypes::try_from(tipe)? {
+            TbfHeaderTypes::TbfFooterCredentials => Ok(TbfFooterEntry::Credentials {
+                format: read_u32(b, 0)?.try_into()?,
+                data: b[4..].to_vec(),
+            }),
+            _ => Ok(TbfFooterEntry::Unknown {
+                tipe,
+                data: b.to_vec(),
+            }),
+        }
+    }
+}
+
+/// Split `b` into the TLV entries it holds, as `(type, value)` pairs.
+fn split_tlvs(mut b: &[u8], pad: bool) -> Result<Vec<(u16, &[u8])>, TbfParseError> {
+    let mut entries = Vec::new();
+    while !b.is_empty() {
+        let tipe = read_u16(b, 0)?;
+        let length = read_u16(b, 2)? as usize;
+        let value = b
+            .get(TLV_LEN..TLV_LEN + length)
+            .ok_or(TbfParseError::NotEnoughFlash)?;
+        entries.push((tipe, value));
+        let skip = if pad {
+            length.next_multiple_of(4)
+        } else {
+            length
+        };
+        b = b.get(TLV_LEN + skip..).unwrap_or(&[]);
+    }
+    Ok(entries)
+}
+
+/// A complete TBF object: the header entries, the binary they describe, and
+/// the footers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tbf {
+    /// Header flags. Bit 0 is set if the process is enabled.
+    pub flags: u32,
+    /// The header entries, in the order they are written.
+    pub headers: Vec<TbfHeaderEntry>,
+    /// Everything between the header and the end of the binary, including any
+    /// protected trailer.
+    pub binary: Vec<u8>,
+    /// The footers, in the order they are written. Footers are only allowed
+    /// if there is a Program header.
+    pub footers: Vec<TbfFooterEntry>,
+}
+
+impl Tbf {
+    /// Create an enabled TBF with the given header entries and binary, and no
+    /// footers.
+    pub fn new(headers: Vec<TbfHeaderEntry>, binary: Vec<u8>) -> Tbf {
+        Tbf {
+            flags: 1,
+            headers,
+            binary,
+            footers: Vec::new(),
+        }
+    }
+
+    /// Parse a TBF object, which may be followed by other data.
+    ///
+    /// The header is checked with [`parse::parse_tbf_header()`], so this fails
+    /// if the kernel would not accept the header.
+    pub fn parse(tbf: &[u8]) -> Result<Tbf, TbfParseError> {
+        let version = read_u16(tbf, 0)?;
+        if version != VERSION {
+            return Err(TbfParseError::UnsupportedVersion(version));
+        }
+        let lengths: &[u8; 8] = tbf
+            .get(0..8)
+            .ok_or(TbfParseError::NotEnoughFlash)?
+            .try_into()?;
+        let (_, header_size, total_size) =
+            parse::parse_tbf_header_lengths(lengths).or(Err(TbfParseError::NotEnoughFlash))?;
+        let header_size = header_size as usize;
+        let total_size = total_size as usize;
+
+        let tbf = tbf.get(..total_size).ok_or(TbfParseError::NotEnoughFlash)?;
+        let header = &tbf[..header_size];
+        let parsed = parse::parse_tbf_header(header, version)?;
+
+        let headers = split_tlvs(&header[BASE_LEN..], true)?
+            .into_iter()
+            .map(|(tipe, value)| TbfHeaderEntry::parse(tipe, value))
+            .collect::<Result<Vec<TbfHeaderEntry>, TbfParseError>>()?;
+
+        let binary_end = if parsed.is_app() {
+            parsed.get_binary_end() as usize
+        } else {
+            total_size
+        };
+        let binary = tbf
+            .get(header_size..binary_end)
+            .ok_or(TbfParseError::NotEnoughFlash)?;
+
+        let footers = split_tlvs(&tbf[binary_end..], false)?
+            .into_iter()
+            .map(|(tipe, value)| TbfFooterEntry::parse(tipe, value))
+            .collect::<Result<Vec<TbfFooterEntry>, TbfParseError>>()?;
+
+        Ok(Tbf {
+            flags: read_u32(tbf, 8)?,
+            headers,
+            binary: binary.to_vec(),
+            footers,
+        })
+    }
+
+    /// Whether the process is enabled, i.e. the kernel should start it.
+    pub fn enabled(&self) -> bool {
+        self.flags & 0x00000001 == 1
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.flags = (self.flags & !0x00000001) | u32::from(enabled);
+    }
+
+    /// The length of the header in bytes.
+    pub fn header_len(&self) -> usize {
+        BASE_LEN
+            + self
+                .headers
+                .iter()
+                .map(|entry| TLV_LEN + entry.value().len().next_multiple_of(4))
+                .sum::<usize>()
+    }
+
+    /// The length of the footers in bytes.
+    pub fn footers_len(&self) -> usize {
+        self.footers.iter().map(|footer| footer.len()).sum()
+    }
+
+    /// The length of the whole TBF in bytes.
+    pub fn total_len(&self) -> usize {
+        self.header_len() + self.binary.len() + self.footers_len()
+    }
+
+    /// Serialize the header, with the sizes, Program header binary end and
+    /// checksum set to match the rest of the TBF.
+    pub fn header_bytes(&self) -> Vec<u8> {
+        let binary_end = (self.header_len() + self.binary.len()) as u32;
+
+        let mut out = Vec::with_capacity(self.header_len());
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.header_len() as u16).to_le_bytes());
+        out.extend_from_slice(&(self.total_len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.flags.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        for entry in &self.headers {
+            match entry {
+                TbfHeaderEntry::Program {
+                    init_fn_offset,
+                    protected_trailer_size,
+                    minimum_ram_size,
+                    version,
+                    ..
+                } => TbfHeaderEntry::Program {
+                    init_fn_offset: *init_fn_offset,
+                    protected_trailer_size: *protected_trailer_size,
+                    minimum_ram_size: *minimum_ram_size,
+                    binary_end_offset: binary_end,
+                    version: *version,
+                }
+                .write(&mut out),
+                _ => entry.write(&mut out),
+            }
+        }
+
+        // The checksum is the XOR of each 4 byte word in the header, other
+        // than the checksum itself, which is still zero here.
+        let (chunks, _) = out.as_chunks::<4>();
+        let checksum = chunks
+            .iter()
+            .fold(0, |checksum, chunk| checksum ^ u32::from_le_bytes(*chunk));
+        out[12..16].copy_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    /// The header and binary, which credentials in the footers cover.
+    pub fn integrity_region(&self) -> Vec<u8> {
+        let mut out = self.header_bytes();
+        out.extend_from_slice(&self.binary);
+        out
+    }
+
+    /// Serialize the whole TBF.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.integrity_region();
+        for footer in &self.footers {
+            footer.write(&mut out);
+        }
+        out
+    }
+
+    /// Add `Reserved` credentials footers so that the TBF is `total_len` bytes
+    /// long, as is done to leave room for credentials added later.
+    ///
+    /// Returns `false` and leaves the footers unchanged if this is not
+    /// possible, because the TBF is already longer or the difference is less
+    /// than the smallest footer.
+    pub fn pad_to(&mut self, total_len: usize) -> bool {
+        let min_footer = TLV_LEN + 4;
+        let max_footer = TLV_LEN + u16::MAX as usize;
+
+        let mut remaining = match total_len.checked_sub(self.total_len()) {
+            Some(0) => return true,
+            Some(remaining) if remaining >= min_footer => remaining,
+            _ => return false,
+        };
+        while remaining > 0 {
+            // Don't leave less than a footer for the next iteration.
+            let mut len = core::cmp::min(remaining, max_footer);
+            if remaining - len > 0 && remaining - len < min_footer {
+                len -= min_footer;
+            }
+            self.footers.push(TbfFooterEntry::reserved(len));
+            remaining -= len;
+        }
+        true
+    }
+}
@@ -71,6 +71,32 @@ fn earlgrey_cw310() -> Result<(), Error> {
     Ok(())
 }
 
+fn qemu_rv32_virt_test_storage() -> Result<(), Error> {
+    // First, build the board if needed
+    // n.b. rexpect's `exp_eof` does not actually block main thread, so use
+    // the standard Rust process library mechanism instead.
+    let mut build = Command::new("make")
+        .arg("-C")
+        .arg("../../../boards/configurations/qemu_rv32_virt/qemu_rv32_virt-test-storage")
+        .spawn()
+        .expect("failed to spawn build");
+    assert!(build.wait().unwrap().success());
+
+    let mut p = spawn(
+        "make run -C ../../../boards/configurations/qemu_rv32_virt/qemu_rv32_virt-test-storage",
+        Some(10_000),
+    )?;
+
+    p.exp_string("initialization complete.")?;
+    p.exp_string("Finished loading processes.")?;
+
+    // Test completed, kill QEMU
+    kill_qemu(&mut p)?;
+
+    p.exp_string("QEMU: Terminated")?;
+    Ok(())
+}
+
 fn main() {
     println!("Tock qemu-runner starting...");
     println!("");
@@ -81,4 +107,9 @@ fn main() {
     println!("Running earlgrey_cw310 tests...");
     earlgrey_cw310().unwrap_or_else(|e| panic!("earlgrey_cw310 job failed with {}", e));
     println!("earlgrey_cw310 SUCCESS.");
+    println!("");
+    println!("Running qemu_rv32_virt-test-storage tests...");
+    qemu_rv32_virt_test_storage()
+        .unwrap_or_else(|e| panic!("qemu_rv32_virt-test-storage job failed with {}", e));
+    println!("qemu_rv32_virt-test-storage SUCCESS.");
 }
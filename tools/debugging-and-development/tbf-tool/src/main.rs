@@ -0,0 +1,365 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2026.
+
+//! Inspect, edit and sign Tock Binary Format objects.
+//!
+//! ```text
+//! tbf-tool dump <tbf>
+//! tbf-tool edit <tbf> [-o <out>] [options]
+//! tbf-tool sign <tbf> [-o <out>] (--sha256 | --sha384 | --sha512 | --ecdsa-p256 <key>)
+//! ```
+//!
+//! `edit` and `sign` overwrite the input TBF unless `-o` is given. Editing a
+//! header invalidates any credentials, so sign the TBF again afterwards.
+
+use std::fmt::Write;
+use std::num::NonZeroU32;
+use std::process::ExitCode;
+
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::pkcs8::DecodePrivateKey;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use tock_tbf::types::TbfFooterV2CredentialsType;
+use tock_tbf::write::{Tbf, TbfFooterEntry, TbfHeaderEntry};
+
+const USAGE: &str = "\
+usage: tbf-tool dump <tbf>
+       tbf-tool edit <tbf> [-o <out>] [options]
+       tbf-tool sign <tbf> [-o <out>] <credential>
+
+edit options:
+  --name <name>              set the package name
+  --enable, --disable        set whether the kernel starts the process
+  --min-ram <bytes>          set the minimum RAM size
+  --short-id <id>            set a fixed ShortId
+  --no-short-id              remove the fixed ShortId
+  --kernel-version <M.m>     set the required kernel version
+
+sign credentials, which replace any existing credentials:
+  --sha256, --sha384, --sha512
+  --ecdsa-p256 <key>         sign with a raw 32 byte or PKCS#8 DER P-256 key";
+
+/// Parse a decimal or `0x` prefixed hexadecimal number.
+fn parse_number(s: &str) -> Result<u32, String> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number '{}'", s))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{:02x}", byte);
+        out
+    })
+}
+
+fn read_tbf(path: &str) -> Result<Tbf, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    Tbf::parse(&bytes).map_err(|e| format!("{}: {:?}", path, e))
+}
+
+fn write_tbf(path: &str, tbf: &Tbf) -> Result<(), String> {
+    std::fs::write(path, tbf.to_bytes()).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Describe a header entry on one line.
+fn describe_header(entry: &TbfHeaderEntry) -> String {
+    match entry {
+        TbfHeaderEntry::Main {
+            init_fn_offset,
+            protected_trailer_size,
+            minimum_ram_size,
+        } => format!(
+            "Main: init_fn_offset={:#x} protected_trailer_size={:#x} minimum_ram_size={}",
+            init_fn_offset, protected_trailer_size, minimum_ram_size
+        ),
+        TbfHeaderEntry::Program {
+            init_fn_offset,
+            protected_trailer_size,
+            minimum_ram_size,
+            binary_end_offset,
+            version,
+        } => format!(
+            "Program: init_fn_offset={:#x} protected_trailer_size={:#x} minimum_ram_size={} binary_end_offset={:#x} version={}",
+            init_fn_offset, protected_trailer_size, minimum_ram_size, binary_end_offset, version
+        ),
+        TbfHeaderEntry::WriteableFlashRegions(regions) => {
+            let mut out = String::from("WriteableFlashRegions:");
+            for (offset, size) in regions {
+                let _ = write!(out, " {:#x}+{:#x}", offset, size);
+            }
+            out
+        }
+        TbfHeaderEntry::PackageName(name) => format!("PackageName: {}", name),
+        TbfHeaderEntry::FixedAddresses {
+            start_process_ram,
+            start_process_flash,
+        } => format!(
+            "FixedAddresses: ram={:#x} flash={:#x}",
+            start_process_ram, start_process_flash
+        ),
+        TbfHeaderEntry::Permissions(perms) => {
+            let mut out = String::from("Permissions:");
+            for perm in perms {
+                let _ = write!(
+                    out,
+                    " {:#x}[{}]={:#x}",
+                    perm.driver_number, perm.offset, perm.allowed_commands
+                );
+            }
+            out
+        }
+        TbfHeaderEntry::StoragePermissions {
+            write_id,
+            read_ids,
+            modify_ids,
+        } => format!(
+            "StoragePermissions: write_id={} read_ids={:x?} modify_ids={:x?}",
+            write_id.map_or(String::from("none"), |id| format!("{:#x}", id)),
+            read_ids,
+            modify_ids
+        ),
+        TbfHeaderEntry::KernelVersion { major, minor } => {
+            format!("KernelVersion: ^{}.{}", major, minor)
+        }
+        TbfHeaderEntry::ShortId(short_id) => format!(
+            "ShortId: {}",
+            short_id.map_or(String::from("none"), |id| format!("{:#x}", id))
+        ),
+        TbfHeaderEntry::Unknown { tipe, data } => {
+            format!("Unknown type {}: {}", tipe, hex(data))
+        }
+    }
+}
+
+/// The hash of the integrity region for hash credentials.
+fn digest(format: TbfFooterV2CredentialsType, region: &[u8]) -> Option<Vec<u8>> {
+    match format {
+        TbfFooterV2CredentialsType::SHA256 => Some(Sha256::digest(region).to_vec()),
+        TbfFooterV2CredentialsType::SHA384 => Some(Sha384::digest(region).to_vec()),
+        TbfFooterV2CredentialsType::SHA512 => Some(Sha512::digest(region).to_vec()),
+        _ => None,
+    }
+}
+
+fn dump(args: &[String]) -> Result<(), String> {
+    let [path] = args else {
+        return Err(String::from(USAGE));
+    };
+    let tbf = read_tbf(path)?;
+
+    println!("header length: {}", tbf.header_len());
+    println!("total length:  {}", tbf.total_len());
+    println!("enabled:       {}", tbf.enabled());
+    println!("headers:");
+    for entry in &tbf.headers {
+        println!("  {}", describe_header(entry));
+    }
+    println!("binary length: {}", tbf.binary.len());
+
+    let region = tbf.integrity_region();
+    if !tbf.footers.is_empty() {
+        println!("footers:");
+    }
+    for footer in &tbf.footers {
+        match footer {
+            TbfFooterEntry::Credentials {
+                format: TbfFooterV2CredentialsType::Reserved,
+                data,
+            } => println!("  Reserved: {} bytes", data.len() + 4),
+            TbfFooterEntry::Credentials { format, data } => {
+                let check = match digest(*format, &region) {
+                    Some(hash) if hash == *data => " (valid)",
+                    Some(_) => " (INVALID)",
+                    None => "",
+                };
+                println!("  {:?}: {}{}", format, hex(data), check);
+            }
+            TbfFooterEntry::Unknown { tipe, data } => {
+                println!("  Unknown type {}: {}", tipe, hex(data))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Split the `-o <out>` option from the arguments, returning the input path,
+/// the output path and the remaining options.
+fn paths(args: &[String]) -> Result<(&str, &str, Vec<&str>), String> {
+    let (input, rest) = args.split_first().ok_or(String::from(USAGE))?;
+    let mut output = input.as_str();
+    let mut options = Vec::new();
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        if arg == "-o" {
+            output = rest.next().ok_or("-o requires a path")?.as_str();
+        } else {
+            options.push(arg.as_str());
+        }
+    }
+    Ok((input.as_str(), output, options))
+}
+
+fn edit(args: &[String]) -> Result<(), String> {
+    let (input, output, options) = paths(args)?;
+    let mut tbf = read_tbf(input)?;
+
+    let mut options = options.into_iter();
+    while let Some(option) = options.next() {
+        let mut value = || options.next().ok_or(format!("{} requires a value", option));
+        match option {
+            "--name" => {
+                let name = String::from(value()?);
+                tbf.headers
+                    .retain(|entry| !matches!(entry, TbfHeaderEntry::PackageName(_)));
+                tbf.headers.push(TbfHeaderEntry::PackageName(name));
+            }
+            "--enable" => tbf.set_enabled(true),
+            "--disable" => tbf.set_enabled(false),
+            "--min-ram" => {
+                let min_ram = parse_number(value()?)?;
+                let mut found = false;
+                for entry in tbf.headers.iter_mut() {
+                    match entry {
+                        TbfHeaderEntry::Main {
+                            minimum_ram_size, ..
+                        }
+                        | TbfHeaderEntry::Program {
+                            minimum_ram_size, ..
+                        } => {
+                            *minimum_ram_size = min_ram;
+                            found = true;
+                        }
+                        _ => {}
+                    }
+                }
+                if !found {
+                    return Err(String::from("TBF has no Main or Program header"));
+                }
+            }
+            "--short-id" | "--no-short-id" => {
+                let short_id = if option == "--short-id" {
+                    Some(
+                        NonZeroU32::new(parse_number(value()?)?)
+                            .ok_or("the ShortId cannot be 0")?,
+                    )
+                } else {
+                    None
+                };
+                tbf.headers
+                    .retain(|entry| !matches!(entry, TbfHeaderEntry::ShortId(_)));
+                if short_id.is_some() {
+                    tbf.headers.push(TbfHeaderEntry::ShortId(short_id));
+                }
+            }
+            "--kernel-version" => {
+                let version = value()?;
+                let (major, minor) = version
+                    .split_once('.')
+                    .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)))
+                    .ok_or(format!("invalid kernel version '{}'", version))?;
+                tbf.headers
+                    .retain(|entry| !matches!(entry, TbfHeaderEntry::KernelVersion { .. }));
+                tbf.headers
+                    .push(TbfHeaderEntry::KernelVersion { major, minor });
+            }
+            _ => return Err(format!("unknown option '{}'\n{}", option, USAGE)),
+        }
+    }
+
+    let signed = tbf.footers.iter().any(|footer| {
+        matches!(footer, TbfFooterEntry::Credentials { format, .. }
+            if *format != TbfFooterV2CredentialsType::Reserved)
+    });
+    if signed {
+        eprintln!("warning: existing credentials are no longer valid, sign the TBF again");
+    }
+    write_tbf(output, &tbf)
+}
+
+/// Load a P-256 private key, either as the raw 32 byte scalar or PKCS#8 DER.
+fn signing_key(path: &str) -> Result<SigningKey, String> {
+    let key = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let parsed = if key.len() == 32 {
+        SigningKey::from_slice(&key).map_err(|e| e.to_string())
+    } else {
+        SigningKey::from_pkcs8_der(&key).map_err(|e| e.to_string())
+    };
+    parsed.map_err(|e| format!("{}: invalid P-256 key: {}", path, e))
+}
+
+fn sign(args: &[String]) -> Result<(), String> {
+    let (input, output, options) = paths(args)?;
+    let mut tbf = read_tbf(input)?;
+
+    let (format, key) = match options[..] {
+        ["--sha256"] => (TbfFooterV2CredentialsType::SHA256, None),
+        ["--sha384"] => (TbfFooterV2CredentialsType::SHA384, None),
+        ["--sha512"] => (TbfFooterV2CredentialsType::SHA512, None),
+        ["--ecdsa-p256", key] => (
+            TbfFooterV2CredentialsType::EcdsaNistP256,
+            Some(signing_key(key)?),
+        ),
+        _ => return Err(String::from(USAGE)),
+    };
+    if !tbf
+        .headers
+        .iter()
+        .any(|entry| matches!(entry, TbfHeaderEntry::Program { .. }))
+    {
+        return Err(String::from("footers require a Program header"));
+    }
+
+    // The integrity region includes the total size of the TBF, so the footers
+    // must be laid out before the credential is computed. Reuse the space
+    // the old credentials and padding took up where possible.
+    let total_len = tbf.total_len();
+    let length = match format {
+        TbfFooterV2CredentialsType::SHA384 => 48,
+        TbfFooterV2CredentialsType::SHA512 | TbfFooterV2CredentialsType::EcdsaNistP256 => 64,
+        _ => 32,
+    };
+    tbf.footers
+        .retain(|footer| !matches!(footer, TbfFooterEntry::Credentials { .. }));
+    tbf.footers.insert(
+        0,
+        TbfFooterEntry::Credentials {
+            format,
+            data: vec![0; length],
+        },
+    );
+    tbf.pad_to(total_len);
+
+    let region = tbf.integrity_region();
+    let data = match key {
+        Some(key) => {
+            let signature: Signature = key.sign(&region);
+            signature.to_bytes().to_vec()
+        }
+        None => digest(format, &region).unwrap_or_default(),
+    };
+    tbf.footers[0] = TbfFooterEntry::Credentials { format, data };
+    write_tbf(output, &tbf)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.split_first() {
+        Some((command, args)) if command == "dump" => dump(args),
+        Some((command, args)) if command == "edit" => edit(args),
+        Some((command, args)) if command == "sign" => sign(args),
+        _ => Err(String::from(USAGE)),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}